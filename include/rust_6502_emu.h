@@ -0,0 +1,69 @@
+/* C interface of rust-6502-emu, built with:
+ *   cargo rustc --release --lib --no-default-features --features ffi --crate-type cdylib
+ * and linked with target/release/librust_6502_emu.so (.dylib, .dll). See src/ffi.rs.
+ *
+ * A handle is used by one thread at a time. No function panics or unwinds into the caller: NULL handles are ignored,
+ * and functions return false, 0 or NULL for them. */
+
+#ifndef RUST_6502_EMU_H
+#define RUST_6502_EMU_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct Emu6502 Emu6502;
+
+typedef struct Emu6502Registers {
+    uint16_t pc;
+    uint8_t ac;
+    uint8_t x;
+    uint8_t y;
+    uint8_t sr;
+    uint8_t sp;
+    uint64_t cycles;
+} Emu6502Registers;
+
+/* called before each instruction with its address; returning true stops before it, and resuming executes it */
+typedef bool (*Emu6502InstructionCallback)(void *user_data, uint16_t pc);
+
+/* called once per CPU cycle with its number */
+typedef void (*Emu6502ClockCallback)(void *user_data, uint64_t cycle);
+
+/* cleared memory, PC at the reset vector; free with emu6502_destroy */
+Emu6502 *emu6502_create(void);
+void emu6502_destroy(Emu6502 *emu);
+
+/* restarts at the reset vector keeping the memory, e.g. the loaded program */
+void emu6502_reset(Emu6502 *emu);
+void emu6502_load(Emu6502 *emu, uint16_t addr, const uint8_t *bytes, size_t len);
+
+/* execute an instruction (or enter an interrupt), or at least the given number of cycles; whether execution
+ * stopped, e.g. at an undefined opcode or should the emulator fail internally */
+bool emu6502_step(Emu6502 *emu);
+bool emu6502_run(Emu6502 *emu, uint64_t cycles);
+
+/* why the last step or run stopped, or NULL; valid until the next call on the handle */
+const char *emu6502_stop_reason(const Emu6502 *emu);
+
+/* reads are without side effects on devices */
+uint8_t emu6502_read(const Emu6502 *emu, uint16_t addr);
+void emu6502_read_memory(const Emu6502 *emu, uint16_t addr, uint8_t *buffer, size_t len);
+void emu6502_write(Emu6502 *emu, uint16_t addr, uint8_t value);
+
+Emu6502Registers emu6502_get_registers(const Emu6502 *emu);
+void emu6502_set_registers(Emu6502 *emu, const Emu6502Registers *registers);
+
+/* replace the callback; NULL removes it. user_data is passed to the callback as is */
+void emu6502_set_instruction_callback(Emu6502 *emu, Emu6502InstructionCallback callback, void *user_data);
+void emu6502_set_clock_callback(Emu6502 *emu, Emu6502ClockCallback callback, void *user_data);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,435 @@
+// MOS 6551 Asynchronous Communications Interface Adapter
+//
+// Register layout (offset from base address):
+//   +0  data          read: received byte, clears RDRF; write: byte to transmit
+//   +1  status        read: IRQ (bit 7), TDRE (bit 4), RDRF (bit 3), overrun (bit 2), clears IRQ; write: programmed reset
+//   +2  command       DTR (bit 0, enables receiver and interrupts), receiver IRQ disabled (bit 1),
+//                     transmitter control (bits 3-2, 01 = IRQ enabled)
+//   +3  control       baud rate, word length and stop bits (stored only)
+//
+// Bytes are exchanged with a backend at the speed of the emulation; the baud rate is not emulated, so the transmitter
+// is always empty again right after a write.
+
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::ops::RangeInclusive;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::device::Device;
+use crate::state;
+
+pub const ACIA_BASE_DEFAULT: u16 = 0x5000;
+pub const ACIA_REGISTERS: u16 = 4;
+pub const ACIA_TCP_PORT_DEFAULT: u16 = 6502;
+
+const TCP_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+pub const REG_DATA: u16 = 0;
+pub const REG_STATUS: u16 = 1;
+pub const REG_COMMAND: u16 = 2;
+pub const REG_CONTROL: u16 = 3;
+
+pub const STATUS_OVERRUN: u8 = 0b00000100;
+pub const STATUS_RDRF: u8 = 0b00001000;
+pub const STATUS_TDRE: u8 = 0b00010000;
+pub const STATUS_IRQ: u8 = 0b10000000;
+
+pub const COMMAND_DTR: u8 = 0b00000001;
+pub const COMMAND_RX_IRQ_DISABLED: u8 = 0b00000010;
+pub const COMMAND_TX_CONTROL: u8 = 0b00001100;
+pub const COMMAND_TX_IRQ: u8 = 0b00000100;
+
+// the other end of the serial line
+pub trait SerialBackend {
+    // a received byte if one is available; must not block
+    fn receive(&mut self) -> Option<u8>;
+
+    fn transmit(&mut self, byte: u8);
+}
+
+// where a serial line is connected, e.g. on the command line
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SerialLine {
+    Stdio,
+    Tcp(u16),
+}
+
+impl SerialLine {
+    // `device` names the device using the line in messages
+    pub fn open(self, device: &str) -> Result<Box<dyn SerialBackend>, String> {
+        match self {
+            SerialLine::Stdio => Ok(Box::new(StdioSerial::create())),
+            SerialLine::Tcp(port) => {
+                let serial = TcpSerial::listen(port).map_err(|error| format!("{device} port {port}: {error}"))?;
+                log::info!("{device} serial line listening on localhost:{port}");
+                Ok(Box::new(serial))
+            },
+        }
+    }
+}
+
+// host terminal: bytes typed are received (line-buffered by the terminal, LF sent as CR as serial consoles expect),
+// transmitted bytes are written to stdout
+pub struct StdioSerial {
+    input: Receiver<u8>,
+}
+
+impl StdioSerial {
+    pub fn create() -> Self {
+        let (sender, input) = mpsc::channel();
+        thread::spawn(move || {
+            for byte in io::stdin().lock().bytes() {
+                let Ok(byte) = byte else {
+                    break;
+                };
+                if sender.send(if byte == b'\n' { b'\r' } else { byte }).is_err() {
+                    break;
+                }
+            }
+        });
+        Self { input }
+    }
+}
+
+impl SerialBackend for StdioSerial {
+    fn receive(&mut self) -> Option<u8> {
+        self.input.try_recv().ok()
+    }
+
+    fn transmit(&mut self, byte: u8) {
+        let mut stdout = io::stdout().lock();
+        let _ = stdout.write_all(&[byte]).and_then(|_| stdout.flush());
+    }
+}
+
+// a client connected to a port on localhost, e.g. with 'telnet localhost 6502' or 'nc localhost 6502'; one client at a
+// time, others can connect after it disconnected. CR LF and LF are received as CR, there is no telnet negotiation.
+pub struct TcpSerial {
+    listener: TcpListener,
+    client: Option<TcpStream>,
+    received: VecDeque<u8>,
+    last_received: u8,
+    polled: Option<Instant>,
+}
+
+impl TcpSerial {
+    pub fn listen(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        listener.set_nonblocking(true)?;
+        Ok(Self { listener, client: None, received: VecDeque::new(), last_received: 0, polled: None })
+    }
+
+    pub fn port(&self) -> io::Result<u16> {
+        Ok(self.listener.local_addr()?.port())
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.client.is_some()
+    }
+
+    fn client(&mut self) -> Option<&mut TcpStream> {
+        if self.client.is_none() {
+            if let Ok((stream, _)) = self.listener.accept() {
+                self.client = stream.set_nonblocking(true).and_then(|_| stream.set_nodelay(true)).ok().map(|_| stream);
+            }
+        }
+        self.client.as_mut()
+    }
+
+    fn fill(&mut self) {
+        let mut buffer = [0; 256];
+        let Some(client) = self.client() else {
+            return;
+        };
+        let len = match client.read(&mut buffer) {
+            Ok(0) => {
+                self.client = None;     // disconnected
+                return;
+            },
+            Ok(len) => len,
+            Err(error) if error.kind() == ErrorKind::WouldBlock => return,
+            Err(_) => {
+                self.client = None;
+                return;
+            },
+        };
+        for &byte in &buffer[..len] {
+            let previous = std::mem::replace(&mut self.last_received, byte);
+            match byte {
+                b'\n' if previous == b'\r' => {},
+                b'\n' => self.received.push_back(b'\r'),
+                byte => self.received.push_back(byte),
+            }
+        }
+    }
+}
+
+impl SerialBackend for TcpSerial {
+    // the socket is polled at most every millisecond, devices may ask every instruction
+    fn receive(&mut self) -> Option<u8> {
+        if self.received.is_empty() && self.polled.is_none_or(|polled| polled.elapsed() >= TCP_POLL_INTERVAL) {
+            self.polled = Some(Instant::now());
+            self.fill();
+        }
+        self.received.pop_front()
+    }
+
+    // bytes transmitted without a client are lost
+    fn transmit(&mut self, byte: u8) {
+        if let Some(client) = self.client() {
+            if client.write_all(&[byte]).is_err() {
+                self.client = None;
+            }
+        }
+    }
+}
+
+pub struct Acia {
+    name: String,
+    pub base: u16,
+    backend: Box<dyn SerialBackend>,
+    rx: u8,
+    status: u8,
+    command: u8,
+    control: u8,
+}
+
+impl Acia {
+    pub fn create(name: &str, base: u16, backend: Box<dyn SerialBackend>) -> Self {
+        Self {
+            name: String::from(name),
+            base,
+            backend,
+            rx: 0,
+            status: STATUS_TDRE,
+            command: 0,
+            control: 0,
+        }
+    }
+
+    fn reg(&self, addr: u16) -> u16 {
+        addr.wrapping_sub(self.base) % ACIA_REGISTERS
+    }
+
+    fn enabled(&self) -> bool {
+        self.command & COMMAND_DTR != 0
+    }
+
+    fn rx_irq_enabled(&self) -> bool {
+        self.enabled() && self.command & COMMAND_RX_IRQ_DISABLED == 0
+    }
+
+    fn tx_irq_enabled(&self) -> bool {
+        self.enabled() && self.command & COMMAND_TX_CONTROL == COMMAND_TX_IRQ
+    }
+}
+
+impl Device for Acia {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        self.base..=self.base + (ACIA_REGISTERS - 1)
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        let value = self.peek(addr);
+        match self.reg(addr) {
+            REG_DATA => self.status &= !(STATUS_RDRF | STATUS_OVERRUN),
+            REG_STATUS => self.status &= !STATUS_IRQ,
+            _ => {},
+        }
+        value
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match self.reg(addr) {
+            REG_DATA => self.rx,
+            REG_STATUS => self.status,
+            REG_COMMAND => self.command,
+            _ => self.control,
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match self.reg(addr) {
+            REG_DATA => {
+                self.backend.transmit(value);
+                if self.tx_irq_enabled() {
+                    self.status |= STATUS_IRQ;
+                }
+            },
+            REG_STATUS => {
+                self.command &= 0b11100000;
+                self.status &= !STATUS_OVERRUN;
+            },
+            REG_COMMAND => {
+                self.command = value;
+                if self.tx_irq_enabled() {
+                    self.status |= STATUS_IRQ;     // the transmitter is empty
+                }
+            },
+            _ => self.control = value,
+        }
+    }
+
+    // takes a byte from the backend once the previous one has been read
+    fn tick(&mut self, _cycles: u64) {
+        if !self.enabled() || self.status & STATUS_RDRF != 0 {
+            return;
+        }
+        if let Some(byte) = self.backend.receive() {
+            self.rx = byte;
+            self.status |= STATUS_RDRF;
+            if self.rx_irq_enabled() {
+                self.status |= STATUS_IRQ;
+            }
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.status & STATUS_IRQ != 0
+    }
+
+    fn reset(&mut self) {
+        self.status = STATUS_TDRE;
+        self.command = 0;
+        self.control = 0;
+    }
+
+    // the registers; bytes buffered by the backend stay with the backend
+    fn save_state(&self, writer: &mut dyn Write) -> io::Result<()> {
+        for value in [self.rx, self.status, self.command, self.control] {
+            state::write_u8(writer, value)?;
+        }
+        Ok(())
+    }
+
+    fn load_state(&mut self, reader: &mut dyn Read) -> io::Result<()> {
+        for value in [&mut self.rx, &mut self.status, &mut self.command, &mut self.control] {
+            *value = state::read_u8(reader)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+
+    use super::*;
+
+    const BASE: u16 = ACIA_BASE_DEFAULT;
+
+    #[derive(Clone, Default)]
+    struct Line {
+        input: Rc<RefCell<VecDeque<u8>>>,
+        output: Rc<RefCell<Vec<u8>>>,
+    }
+
+    impl SerialBackend for Line {
+        fn receive(&mut self) -> Option<u8> {
+            self.input.borrow_mut().pop_front()
+        }
+
+        fn transmit(&mut self, byte: u8) {
+            self.output.borrow_mut().push(byte);
+        }
+    }
+
+    #[test]
+    fn transfer() {
+        let line = Line::default();
+        line.input.borrow_mut().extend(b"OK");
+        let mut acia = Acia::create("acia", BASE, Box::new(line.clone()));
+
+        // nothing is received while DTR is off
+        acia.tick(1);
+        assert_eq!(acia.peek(BASE + REG_STATUS), STATUS_TDRE);
+
+        acia.write(BASE + REG_COMMAND, COMMAND_DTR | COMMAND_RX_IRQ_DISABLED);
+        acia.tick(1);
+        acia.tick(1);
+        assert_eq!((acia.read(BASE + REG_STATUS), acia.irq()), (STATUS_TDRE | STATUS_RDRF, false));
+        assert_eq!(acia.read(BASE + REG_DATA), b'O');
+        assert_eq!(acia.peek(BASE + REG_STATUS) & STATUS_RDRF, 0);
+        acia.tick(1);
+        assert_eq!(acia.read(BASE + REG_DATA), b'K');
+
+        acia.write(BASE + REG_DATA, b'!');
+        assert_eq!(line.output.borrow().as_slice(), b"!");
+    }
+
+    #[test]
+    fn interrupts() {
+        let line = Line::default();
+        let mut acia = Acia::create("acia", BASE, Box::new(line.clone()));
+        acia.write(BASE + REG_COMMAND, COMMAND_DTR);
+        acia.tick(1);
+        assert!(!acia.irq());
+
+        // receiving a byte interrupts until the status is read
+        line.input.borrow_mut().push_back(b'A');
+        acia.tick(1);
+        assert!(acia.irq());
+        assert_eq!(acia.read(BASE + REG_STATUS), STATUS_IRQ | STATUS_TDRE | STATUS_RDRF);
+        assert!(!acia.irq());
+
+        // the transmitter interrupts when enabled and after each byte
+        acia.write(BASE + REG_COMMAND, COMMAND_DTR | COMMAND_RX_IRQ_DISABLED | COMMAND_TX_IRQ);
+        assert!(acia.irq());
+        acia.read(BASE + REG_STATUS);
+        acia.write(BASE + REG_DATA, b'B');
+        assert!(acia.irq());
+
+        // programmed reset disables interrupts
+        acia.read(BASE + REG_STATUS);
+        acia.write(BASE + REG_STATUS, 0);
+        acia.write(BASE + REG_DATA, b'C');
+        assert_eq!((acia.irq(), acia.peek(BASE + REG_COMMAND)), (false, 0));
+        assert_eq!(line.output.borrow().as_slice(), b"BC");
+    }
+
+    // polls for up to a second
+    fn wait(mut done: impl FnMut() -> bool) {
+        for _ in 0..1000 {
+            if done() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        panic!("timed out");
+    }
+
+    #[test]
+    fn tcp() {
+        let mut serial = TcpSerial::listen(0).unwrap();     // any free port
+        assert_eq!((serial.receive(), serial.is_connected()), (None, false));
+
+        let mut client = TcpStream::connect((Ipv4Addr::LOCALHOST, serial.port().unwrap())).unwrap();
+        client.write_all(b"AB\r\nC\n").unwrap();
+        let mut received = Vec::new();
+        wait(|| {
+            received.extend(serial.receive());
+            received.len() == 5
+        });
+        assert_eq!(received, b"AB\rC\r");
+
+        serial.transmit(b'!');
+        let mut byte = [0];
+        client.read_exact(&mut byte).unwrap();
+        assert_eq!(&byte, b"!");
+
+        // a new client can connect after the first one disconnected
+        drop(client);
+        wait(|| serial.receive().is_none() && !serial.is_connected());
+        let _client = TcpStream::connect((Ipv4Addr::LOCALHOST, serial.port().unwrap())).unwrap();
+        wait(|| serial.receive().is_none() && serial.is_connected());
+    }
+}
@@ -0,0 +1,231 @@
+// the emulator as run from the command line: the machine built from the options, run as given by `Config`
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::ops::RangeInclusive;
+
+use crate::{cpu, mem, monitor};
+use crate::coverage::Coverage;
+use crate::disasm::{Disassembler, Format};
+use crate::emulator::Emulator;
+#[cfg(feature = "window")]
+use crate::framebuffer::Framebuffer;
+use crate::lcd::Lcd;
+use crate::monitor::Monitor;
+use crate::screen::Redraw;
+use crate::segments::SevenSegment;
+#[cfg(feature = "window")]
+use crate::window::Window;
+
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug)]
+pub enum Verbosity {
+    Normal = 0,
+    Verbose = 1,
+    VeryVerbose = 2,
+}
+
+// what the command line does with the machine, which it sets up with `EmulatorBuilder` (including the screen, the
+// instrumentation and the traces)
+pub struct Config {
+    pub verbosity: Verbosity,
+    pub cycles_to_execute: Option<u64>,
+    pub load_file: Option<String>,          // reloaded by the monitor's hard reset
+    pub interactive: bool,
+    pub monitor_script: Option<String>,
+    pub checkpoint_interval: Option<u64>,
+    pub screen_refresh: u64,
+    pub screen_redraw: Redraw,
+    #[cfg(feature = "window")]
+    pub framebuffer: Option<(u16, u16, u16)>,
+    #[cfg(feature = "window")]
+    pub framebuffer_bpp: u8,
+    #[cfg(feature = "window")]
+    pub palette_file: Option<String>,
+    #[cfg(feature = "window")]
+    pub framebuffer_refresh: u64,
+    #[cfg(feature = "window")]
+    pub window_scale: u8,
+    pub heatmap: bool,
+    pub heatmap_csv: Option<String>,
+    pub profile: bool,
+    pub stats: bool,
+    pub coverage_file: Option<String>,
+    pub record_input_file: Option<String>,
+    pub disassemble: Option<RangeInclusive<u16>>,
+    pub disassemble_listing: bool,
+    pub disassemble_code_file: Option<String>,
+    pub disassemble_format: Format,
+    pub output_file: Option<String>,
+}
+
+
+pub fn run(mut emulator: Emulator, config: Config) -> Result<(), Box<dyn Error>> {
+    // disassemble without executing anything
+    if let Some(range) = config.disassemble {
+        let mem = emulator.mem();
+        let variant = emulator.cpu().variant();
+        let mut writer: Box<dyn Write> = match config.output_file {
+            Some(filename) => Box::new(BufWriter::new(File::create(filename)?)),
+            None => Box::new(io::stdout().lock()),
+        };
+        let coverage = match &config.disassemble_code_file {
+            Some(filename) => {
+                let mut disassembler = Disassembler::create();
+                disassembler.set_variant(variant);
+                let len = |addr| disassembler.line_at(mem, addr).bytes.len() as u8;
+                Some(Coverage::read_addresses(BufReader::new(File::open(filename)?), len).map_err(|error| format!("{filename}: {error}"))?)
+            },
+            None => None,
+        };
+        let mut disassembler = Disassembler::create();
+        disassembler.set_coverage(coverage.as_ref());
+        disassembler.set_symbols(Some(mem.symbols()));
+        disassembler.set_auto_labels(true);
+        disassembler.set_format(config.disassemble_format);
+        disassembler.set_variant(variant);
+        for line in disassembler.disassemble_range(mem, range) {
+            match config.disassemble_listing {
+                true => writeln!(writer, "{}", line.listing())?,
+                false => writeln!(writer, "{line}")?,
+            }
+        }
+        writer.flush()?;
+        return Ok(());
+    }
+
+    if config.verbosity >= Verbosity::Verbose {
+        let (cpu, mem) = (emulator.cpu(), emulator.mem());
+        log::info!("Reset vector: {}", mem.dump(cpu::VECTOR_RES, 2));
+        log::info!("Data at reset vector address: {}", mem.dump(mem::ADDR_RESET_VECTOR, 16));
+        log::debug!("After reset: {:#?}", cpu);
+    }
+
+    emulator.cpu().dump_state(emulator.mem());
+
+    let mut screen = emulator.take_screen();
+    #[cfg(feature = "window")]
+    let mut window = match config.framebuffer {
+        Some((base, width, height)) => {
+            let palette = config.palette_file.as_deref().map(Framebuffer::load_palette).transpose()?;
+            let framebuffer = Framebuffer::create(base, width, height, config.framebuffer_bpp, palette)?;
+            Some(Window::create("rust-6502-emu", framebuffer, config.window_scale)?)
+        },
+        None => None,
+    };
+    #[cfg(not(feature = "window"))]
+    let window = None::<()>;
+    let lcd_rows = emulator.mem().device::<Lcd>("lcd").map(|lcd| lcd.rows);
+    let segments = emulator.mem().device::<SevenSegment>("segments").is_some();
+    if config.interactive || config.monitor_script.is_some() {
+        let (cpu, mem) = emulator.parts_mut();
+        let mut monitor = Monitor::create();
+        if let Some(filename) = &config.load_file {
+            monitor.set_program_file(filename);
+        }
+        if let Some(interval) = config.checkpoint_interval {
+            monitor.enable_checkpoints(interval);
+        }
+        if let Some(screen) = screen {
+            monitor.set_screen(screen);
+        }
+        let mut running = match config.monitor_script {
+            Some(filename) => monitor.source(cpu, mem, &filename)?,
+            None => true,
+        };
+        while running {
+            let Ok(user_input) = monitor::get_user_input(&monitor) else {
+                break;
+            };
+            if user_input.is_empty() {
+                // probably ^D
+                break;
+            }
+            let user_input = user_input.trim();
+            running = monitor.process_user_input(cpu, mem, user_input);
+            #[cfg(feature = "window")]
+            if let Some(window) = window.as_mut().filter(|window| window.is_open()) {
+                window.render(mem)?;
+            }
+        }
+    } else if screen.is_some() || lcd_rows.is_some() || segments || window.is_some() {
+        // runs in slices up to the next refresh of the screen (with the LCD and LED digits below it) or window, rendering
+        // the due ones after each
+        let mut remaining = config.cycles_to_execute.unwrap_or(u64::MAX);
+        let mut screen_due = emulator.cpu().cycles + config.screen_refresh;
+        let terminal = screen.is_some() || lcd_rows.is_some() || segments;
+        let lcd_top = screen.as_ref().map_or(1, |screen| screen.rows + 2);
+        let segments_top = lcd_top + lcd_rows.map_or(0, |rows| rows + 3);
+        #[cfg(feature = "window")]
+        let mut window_due = emulator.cpu().cycles + config.framebuffer_refresh;
+        while remaining > 0 {
+            let due = if terminal { screen_due } else { u64::MAX };
+            #[cfg(feature = "window")]
+            let due = if window.is_some() { due.min(window_due) } else { due };
+            let start = emulator.cpu().cycles;
+            let stopped = emulator.run(remaining.min(due.saturating_sub(start).max(1))).is_some();
+            let cycles = emulator.cpu().cycles;
+            remaining = remaining.saturating_sub(cycles - start);
+            if stopped || cycles >= screen_due {
+                if let Some(screen) = &mut screen {
+                    screen.render(emulator.mem(), &mut io::stdout(), config.screen_redraw)?;
+                }
+                if let Some(lcd) = emulator.mem_mut().device_mut::<Lcd>("lcd") {
+                    lcd.render(&mut io::stdout(), lcd_top, config.screen_redraw)?;
+                }
+                if let Some(segments) = emulator.mem_mut().device_mut::<SevenSegment>("segments") {
+                    segments.render(&mut io::stdout(), segments_top, config.screen_redraw)?;
+                }
+                screen_due = cycles + config.screen_refresh;
+            }
+            #[cfg(feature = "window")]
+            if let Some(window) = &mut window {
+                if stopped || cycles >= window_due {
+                    window.render(emulator.mem())?;
+                    window_due = cycles + config.framebuffer_refresh;
+                }
+                if !window.is_open() {
+                    break;
+                }
+            }
+            if stopped {
+                break;
+            }
+        }
+    } else if let Some(cycles_to_execute) = config.cycles_to_execute {
+        emulator.run(cycles_to_execute);
+    } else {
+        emulator.run_until_stop();
+    }
+
+    if let Some(comparison) = emulator.stop_traces().filter(|comparison| comparison.divergence().is_none()) {
+        println!("{} instructions match the reference trace", comparison.matched());
+    }
+    let (cpu, mem) = (emulator.cpu(), emulator.mem());
+    if config.heatmap {
+        monitor::print_heatmap(mem, monitor::HEATMAP_REPORT_ENTRIES);
+    }
+    if config.profile {
+        monitor::print_profile(cpu, mem, monitor::PROFILE_REPORT_ENTRIES);
+    }
+    if config.stats {
+        monitor::print_stats(cpu);
+    }
+    if let Some(filename) = config.coverage_file {
+        if let Some(coverage) = cpu.coverage() {
+            coverage.write_addresses(BufWriter::new(File::create(filename)?))?;
+        }
+    }
+    if let Some(filename) = config.record_input_file {
+        if let Some(recording) = cpu.input_recording() {
+            recording.write_to(&mut BufWriter::new(File::create(filename)?))?;
+        }
+    }
+    if let Some(filename) = config.heatmap_csv {
+        if let Some(heatmap) = mem.heatmap() {
+            heatmap.write_csv(BufWriter::new(File::create(filename)?))?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,212 @@
+// Apple II (+): 48K RAM, the system ROM with Applesoft BASIC and the monitor at $D000-$FFFF, and the I/O page:
+//
+//  $C000-$C00F  keyboard: code of the last key, bit 7 set until the strobe is cleared
+//  $C010-$C01F  any access clears the keyboard strobe
+//  $C030-$C03F  any access toggles the speaker
+//  $C050-$C057  softswitches, set by any access: graphics/text, full screen/mixed, page 1/2, lo-res/hi-res
+//
+// The 40-column text page 1 at $0400 is shown in the terminal, whatever the softswitches select; graphics are not.
+
+use std::ops::RangeInclusive;
+
+use crate::acia::SerialBackend;
+use crate::device::Device;
+use crate::keyboard::{self, Keyboard, Keymap};
+use crate::machine::{Machine, Profile, RomSlot, Wiring};
+use crate::screen::{Charset, TextScreen};
+
+pub const IO_BASE: u16 = 0xC000;
+pub const TEXT_PAGE: u16 = 0x0400;
+
+const KBD: u16 = 0x00;
+const KBDSTRB: u16 = 0x10;
+const SPKR: u16 = 0x30;
+const TXTCLR: u16 = 0x50;
+const TXTSET: u16 = 0x51;
+const MIXCLR: u16 = 0x52;
+const MIXSET: u16 = 0x53;
+const LOWSCR: u16 = 0x54;
+const HISCR: u16 = 0x55;
+const LORES: u16 = 0x56;
+const HIRES: u16 = 0x57;
+
+pub const APPLE_II: Profile = Profile {
+    name: "apple2",
+    description: "Apple II+: 48K RAM, system ROM at $D000 (12K image as ROM 'rom'), keyboard at $C000, 40-column text page at $0400",
+    clock_hz: 1_023_000,
+    frame_cycles: 17030,        // 262 lines of 65 cycles at 60 Hz
+    roms: &[RomSlot { name: "rom", addr: 0xD000, size: 0x3000 }],
+    keyboard: true,
+    wire,
+};
+
+fn wire(machine: &mut Machine, wiring: &mut Wiring) -> Result<(), String> {
+    let keymap = wiring.keymap.take().unwrap_or_else(keymap);
+    machine.attach(Box::new(AppleIo::create(wiring.keyboard.take(), keymap)));
+    machine.screen = Some(text_screen()?);
+    Ok(())
+}
+
+// upper case only, delete as the left arrow
+pub fn keymap() -> Keymap {
+    let mut keymap = Keymap::default();
+    for host in b'a'..=b'z' {
+        keymap.insert(host, host.to_ascii_uppercase());
+    }
+    keymap.insert(0x7F, 0x08);
+    keymap
+}
+
+// the rows of a page are interleaved: three groups of 8 rows, each row of a group 128 bytes after the previous one
+pub fn text_screen() -> Result<TextScreen, String> {
+    let row_addrs = (0..24).map(|row| TEXT_PAGE + (row % 8) * 0x80 + (row / 8) * 40).collect();
+    TextScreen::with_row_addrs(row_addrs, 40, Charset::AppleII)
+}
+
+pub struct AppleIo {
+    keyboard: Keyboard,     // queues keys until the program cleared the strobe
+    key: u8,
+    strobe: bool,
+    speaker: bool,
+    pub text: bool,
+    pub mixed: bool,
+    pub page2: bool,
+    pub hires: bool,
+}
+
+impl AppleIo {
+    pub fn create(backend: Option<Box<dyn SerialBackend>>, keymap: Keymap) -> Self {
+        Self {
+            keyboard: Keyboard::create("keyboard", 0, backend, keymap),
+            key: 0,
+            strobe: false,
+            speaker: false,
+            text: true,
+            mixed: false,
+            page2: false,
+            hires: false,
+        }
+    }
+
+    pub fn press(&mut self, host: u8) {
+        self.keyboard.press(host);
+        self.latch_key();
+    }
+
+    pub fn speaker(&self) -> bool {
+        self.speaker
+    }
+
+    fn latch_key(&mut self) {
+        if !self.strobe && self.keyboard.waiting() > 0 {
+            self.key = self.keyboard.read(keyboard::REG_DATA) & 0x7F;
+            self.strobe = true;
+        }
+    }
+
+    fn access(&mut self, addr: u16) {
+        match addr - IO_BASE {
+            offset if offset & 0xF0 == KBDSTRB => self.strobe = false,
+            offset if offset & 0xF0 == SPKR => self.speaker = !self.speaker,
+            TXTCLR => self.text = false,
+            TXTSET => self.text = true,
+            MIXCLR => self.mixed = false,
+            MIXSET => self.mixed = true,
+            LOWSCR => self.page2 = false,
+            HISCR => self.page2 = true,
+            LORES => self.hires = false,
+            HIRES => self.hires = true,
+            _ => {},
+        }
+    }
+}
+
+impl Device for AppleIo {
+    fn name(&self) -> &str {
+        "apple2-io"
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        IO_BASE..=IO_BASE + 0xFF
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        let value = self.peek(addr);
+        self.access(addr);
+        value
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match (addr - IO_BASE) & 0xF0 {
+            KBD => self.key | if self.strobe { 0x80 } else { 0 },
+            KBDSTRB => self.key,
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, addr: u16, _value: u8) {
+        self.access(addr);
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.keyboard.tick(cycles);
+        self.latch_key();
+    }
+
+    fn reset(&mut self) {
+        self.strobe = false;
+        (self.text, self.mixed, self.page2, self.hires) = (true, false, false, false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::asm;
+
+    use super::*;
+
+    #[test]
+    fn keyboard() {
+        // waits for a key, clears the strobe and shows the key at the start of the third row
+        let program = asm::assemble("
+            .org $0300
+    wait:   LDA $C000
+            BPL wait
+            STA $C010
+            STA $0500
+            BRK
+        ").unwrap();
+        let mut machine = Machine::create(&APPLE_II, Wiring::default()).unwrap();
+        program.write_to(&mut machine.mem);
+        machine.cpu.pc = 0x0300;
+        machine.run(100);
+        assert_eq!(machine.mem.peek(0x0500), 0x00);
+
+        let io = machine.mem.device_mut::<AppleIo>("apple2-io").unwrap();
+        io.press(b'a');
+        io.press(b'b');
+        machine.run(20);
+        let screen = machine.screen.as_ref().unwrap();
+        assert_eq!(screen.lines(&machine.mem)[2], format!("A{}", "@".repeat(39)));
+
+        // the next key is latched once the strobe is cleared
+        assert_eq!(machine.mem.peek(0xC000), 0xC2);
+    }
+
+    #[test]
+    fn softswitches() {
+        let mut io = AppleIo::create(None, keymap());
+        for addr in [0xC050, 0xC053, 0xC055, 0xC057] {
+            io.read(addr);
+        }
+        assert_eq!((io.text, io.mixed, io.page2, io.hires), (false, true, true, true));
+        io.write(0xC051, 0);
+        io.write(0xC030, 0);
+        assert!(io.text && io.speaker());
+        io.reset();
+        assert_eq!((io.text, io.mixed, io.page2, io.hires), (true, false, false, false));
+
+        let screen = text_screen().unwrap();
+        assert_eq!((screen.range(), screen.rows), (0x0400..=0x07F7, 24));
+    }
+}
@@ -0,0 +1,524 @@
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+use crate::disasm::{self, Disassembler, Format};
+use crate::expr::{self, Env};
+use crate::instruction::{AddressingMode, Instruction, Mnemonic, Opcode};
+use crate::mem::{Memory, ADDR_RESET_VECTOR};
+use crate::symbols::SymbolTable;
+
+// bytes assembled to consecutive addresses starting at `addr`
+#[derive(Clone, PartialEq, Debug)]
+pub struct Segment {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+}
+
+// a source line with the bytes assembled from it
+#[derive(Clone, PartialEq, Debug)]
+pub struct ListingLine {
+    pub addr: Option<u16>,          // None for empty and comment lines
+    pub bytes: Vec<u8>,
+    pub instruction: bool,
+    pub source: String,
+}
+
+pub struct Program {
+    pub segments: Vec<Segment>,     // in source order, one per .org
+    pub symbols: SymbolTable,       // labels
+    pub listing: Vec<ListingLine>,  // one per source line
+}
+
+impl Program {
+    // address of the first assembled byte
+    pub fn origin(&self) -> Option<u16> {
+        self.segments.first().map(|segment| segment.addr)
+    }
+
+    // contiguous image from the lowest to the highest assembled address; gaps between segments are zero
+    pub fn image(&self) -> Option<(u16, Vec<u8>)> {
+        let start = self.segments.iter().map(|segment| segment.addr).min()?;
+        let end = self.segments.iter().map(|segment| segment.addr as usize + segment.bytes.len()).max()?;
+        let mut image = vec![0; end - start as usize];
+        for segment in &self.segments {
+            let offset = (segment.addr - start) as usize;
+            image[offset..offset + segment.bytes.len()].copy_from_slice(&segment.bytes);
+        }
+        Some((start, image))
+    }
+
+    // whether any segment covers the address
+    pub fn contains(&self, addr: u16) -> bool {
+        self.segments.iter().any(|segment| (segment.addr as usize..segment.addr as usize + segment.bytes.len()).contains(&(addr as usize)))
+    }
+
+    // listing with address, bytes, cycles of instructions and the source line
+    pub fn write_listing(&self, writer: &mut impl Write) -> io::Result<()> {
+        for line in &self.listing {
+            // the assembler only knows the instructions of the NMOS 6502
+            let cycles = match Opcode::decode(line.bytes.first().copied().unwrap_or_default(), false).and_then(|opcode| Instruction::from_opcode(opcode).ok()) {
+                Some(ins) if line.instruction => Some((ins.cycles, disasm::cycle_note(&ins, line.addr.unwrap_or_default(), &line.bytes))),
+                _ => None,
+            };
+            writeln!(writer, "{}", disasm::format_listing(&Format::default(), line.addr, &line.bytes, cycles, &line.source))?;
+        }
+        writer.flush()
+    }
+
+    pub fn write_to(&self, mem: &mut Memory) {
+        for segment in &self.segments {
+            for (addr, byte) in (segment.addr..=0xFFFF).zip(&segment.bytes) {
+                mem.write_u8(addr, *byte);
+            }
+        }
+    }
+}
+
+// a source line split into its parts; all of them are optional
+struct SourceLine<'a> {
+    label: Option<&'a str>,
+    directive: Option<(&'a str, &'a str)>,     // name without '.' and arguments
+    instruction: Option<&'a str>,
+}
+
+fn is_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// "label: LDA #$10 ; comment", "label: .byte 1, 2", ".org $E000"
+fn parse_source_line(line: &str) -> Result<SourceLine<'_>, String> {
+    let line = strip_comment(line).trim();
+    let (label, rest) = match line.split_once(':') {
+        Some((label, rest)) if !label.contains('"') => {
+            let label = label.trim();
+            if !is_identifier(label) {
+                return Err(format!("Invalid label '{label}'"));
+            }
+            (Some(label), rest.trim())
+        },
+        _ => (None, line),
+    };
+
+    let (directive, instruction) = match rest.strip_prefix('.') {
+        Some(directive) => (Some(directive.split_once(char::is_whitespace).map_or((directive, ""), |(name, args)| (name, args.trim()))), None),
+        None if rest.is_empty() => (None, None),
+        None => (None, Some(rest)),
+    };
+
+    Ok(SourceLine { label, directive, instruction })
+}
+
+// the part before ';' outside of string literals
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..i],
+            _ => {},
+        }
+    }
+    line
+}
+
+// comma-separated directive arguments; commas within string literals do not separate
+fn split_args(args: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    let (mut start, mut quoted) = (0, false);
+    for (i, c) in args.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                result.push(args[start..i].trim());
+                start = i + 1;
+            },
+            _ => {},
+        }
+    }
+    result.push(args[start..].trim());
+    result.retain(|arg| !arg.is_empty());
+    result
+}
+
+// bytes of a .byte or .word directive; strings are only allowed for .byte.
+// With `forward` values may refer to symbols not defined yet, which are assembled as 0.
+fn data_bytes(directive: &str, args: &str, env: &Env, forward: bool) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    for arg in split_args(args) {
+        if let Some(text) = arg.strip_prefix('"') {
+            let text = text.strip_suffix('"').ok_or_else(|| format!("Unterminated string {arg}"))?;
+            if directive != "byte" || !text.is_ascii() {
+                return Err(format!("Invalid string {arg}"));
+            }
+            bytes.extend(text.bytes());
+            continue;
+        }
+        let value = match expr::eval(arg, env) {
+            Err(_) if forward => 0,
+            value => value?,
+        };
+        match directive {
+            "byte" => bytes.push(u8::try_from(value).map_err(|_| format!("Value ${value:04X} does not fit in a byte"))?),
+            _ => bytes.extend(value.to_le_bytes()),
+        }
+    }
+    if bytes.is_empty() {
+        return Err(format!("Missing values for .{directive}"));
+    }
+    Ok(bytes)
+}
+
+// Two-pass assembler for a program consisting of labels, instructions and the directives .org, .byte and .word.
+// The first pass determines the address of every label; operands referring to labels defined later are assembled
+// with absolute addressing. Assembly starts at $E000 unless the program begins with .org.
+pub fn assemble(source: &str) -> Result<Program, String> {
+    let mut symbols = SymbolTable::default();
+    let mut forward = Vec::new();       // line numbers of instructions referencing labels defined later
+    let mut listing = Vec::new();
+
+    for pass in 1..=2 {
+        let mut segments = vec![Segment { addr: ADDR_RESET_VECTOR, bytes: Vec::new() }];
+        let mut addr = ADDR_RESET_VECTOR as u32;
+
+        for (number, text) in source.lines().enumerate() {
+            let error = |error: String| format!("Line {}: {error}", number + 1);
+            let line = parse_source_line(text).map_err(error)?;
+
+            if let Some(label) = line.label.filter(|_| pass == 1) {
+                if symbols.lookup(label).is_some() {
+                    return Err(error(format!("Label '{label}' defined twice")));
+                }
+                symbols.add(label, u16::try_from(addr).map_err(|_| error(String::from("Address beyond $FFFF")))?);
+            }
+
+            let env = Env { symbols: Some(&symbols), ..Env::default() };
+            let bytes = match (line.directive, line.instruction) {
+                (Some(("org", args)), _) => {
+                    addr = expr::eval(args, &env).map_err(error)? as u32;
+                    segments.push(Segment { addr: addr as u16, bytes: Vec::new() });
+                    Vec::new()
+                },
+                (Some((directive @ ("byte" | "word"), args)), _) => data_bytes(directive, args, &env, pass == 1).map_err(error)?,
+                (Some((directive, _)), _) => return Err(error(format!("Unknown directive .{directive}"))),
+                (None, Some(instruction)) if pass == 1 => {
+                    let pc = addr as u16;
+                    assemble_instruction(pc, instruction, &env, false, false).or_else(|_| {
+                        forward.push(number);
+                        assemble_instruction(pc, instruction, &env, true, true)
+                    }).map_err(error)?
+                },
+                (None, Some(instruction)) => assemble_instruction(addr as u16, instruction, &env, forward.contains(&number), false).map_err(error)?,
+                (None, None) => Vec::new(),
+            };
+
+            if pass == 2 {
+                let used = line.label.is_some() || line.directive.is_some() || line.instruction.is_some();
+                listing.push(ListingLine {
+                    addr: used.then_some(addr as u16),
+                    bytes: bytes.clone(),
+                    instruction: line.instruction.is_some(),
+                    source: String::from(text.trim_end()),
+                });
+            }
+
+            addr += bytes.len() as u32;
+            if addr > 0x10000 {
+                return Err(error(String::from("Address beyond $FFFF")));
+            }
+            segments.last_mut().unwrap().bytes.extend(bytes);
+        }
+
+        if pass == 2 {
+            segments.retain(|segment| !segment.bytes.is_empty());
+            return Ok(Program { segments, symbols, listing });
+        }
+    }
+    unreachable!()
+}
+
+// Disassembles `range` and assembles the result again, which must give the same bytes; keeps the assembler, the
+// disassembler and the opcode table consistent with each other. Returns the number of lines verified.
+pub fn verify_round_trip(mem: &Memory, range: RangeInclusive<u16>) -> Result<usize, String> {
+    let lines = Disassembler::create().disassemble_range(mem, range.clone());
+    let mut source = vec![format!(".org ${:04X}", range.start())];
+    source.extend(lines.iter().map(|line| line.text()));
+
+    let program = assemble(&source.join("\n")).map_err(|error| {
+        // "Line N: ..." refers to the generated source
+        let number: Option<usize> = error.strip_prefix("Line ").and_then(|rest| rest.split(':').next()?.parse().ok());
+        match number.and_then(|number| source.get(number - 1)) {
+            Some(text) => format!("{error} (in '{text}')"),
+            None => error,
+        }
+    })?;
+
+    for (line, assembled) in lines.iter().zip(&program.listing[1..]) {
+        if assembled.bytes != line.bytes {
+            let hex = |bytes: &[u8]| bytes.iter().map(|byte| format!("{byte:02X}")).collect::<Vec<String>>().join(" ");
+            return Err(format!("${:04X}: '{}' assembles to {} instead of {}", line.addr, line.text(), hex(&assembled.bytes), hex(&line.bytes)));
+        }
+    }
+    Ok(lines.len())
+}
+
+// hex literals with more than two digits (e.g. $0010) force absolute addressing
+fn is_wide(arg: &str) -> bool {
+    match arg.strip_prefix('$').or_else(|| arg.strip_prefix("0x")) {
+        Some(hex) => hex.len() > 2,
+        None => false,
+    }
+}
+
+// all instructions (one per addressing mode) for the given mnemonic
+fn instructions(mnemonic: Mnemonic) -> Vec<Instruction> {
+    Instruction::all()
+        .filter(|ins| ins.mnemonic == mnemonic)
+        .collect()
+}
+
+// assembles a single line like "LDA #$10" or "STA start+2,X" to be placed at `addr`; operands are expressions
+pub fn assemble_line(addr: u16, line: &str, env: &Env) -> Result<Vec<u8>, String> {
+    assemble_instruction(addr, line, env, false, false)
+}
+
+// `absolute` rules out zero page addressing; with `forward` an operand referring to symbols not defined yet is
+// assembled as `addr` (first assembler pass, where the size must not depend on the value)
+fn assemble_instruction(addr: u16, line: &str, env: &Env, absolute: bool, forward: bool) -> Result<Vec<u8>, String> {
+    let line = line.split(';').next().unwrap_or("").trim();
+    let (mnemonic, operand) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let operand: String = operand.chars().filter(|c| !c.is_whitespace()).collect();
+    let upper = operand.to_ascii_uppercase();
+    let len = operand.len();
+
+    if mnemonic.is_empty() {
+        return Err(String::from("Missing mnemonic"));
+    }
+    let mnemonic = mnemonic.parse::<Mnemonic>()?;
+    let instructions = instructions(mnemonic);
+
+    // candidate addressing modes in order of preference, with the operand expression
+    let (modes, value, wide): (Vec<AddressingMode>, Option<&str>, bool) = if operand.is_empty() {
+        (vec![AddressingMode::IMP, AddressingMode::ACC], None, false)
+    } else if upper == "A" {
+        (vec![AddressingMode::ACC], None, false)
+    } else if let Some(value) = operand.strip_prefix('#') {
+        (vec![AddressingMode::IMM], Some(value), false)
+    } else if upper.starts_with('(') && upper.ends_with(",X)") {
+        (vec![AddressingMode::IDX], Some(&operand[1..len - 3]), false)
+    } else if upper.starts_with('(') && upper.ends_with("),Y") {
+        (vec![AddressingMode::IDY], Some(&operand[1..len - 3]), false)
+    } else if upper.starts_with('(') && upper.ends_with(')') {
+        (vec![AddressingMode::IND], Some(&operand[1..len - 1]), false)
+    } else if upper.ends_with(",X") {
+        (vec![AddressingMode::ZPX, AddressingMode::ABX], Some(&operand[..len - 2]), is_wide(&operand[..len - 2]))
+    } else if upper.ends_with(",Y") {
+        (vec![AddressingMode::ZPY, AddressingMode::ABY], Some(&operand[..len - 2]), is_wide(&operand[..len - 2]))
+    } else {
+        (vec![AddressingMode::REL, AddressingMode::ZPG, AddressingMode::ABS], Some(operand.as_str()), is_wide(&operand))
+    };
+    let value = match value.map(|value| expr::eval(value, env)).transpose() {
+        Err(_) if forward => Some(addr),
+        value => value?,
+    };
+    let wide = wide || absolute;
+
+    for addr_mode in modes {
+        let zero_page = matches!(addr_mode, AddressingMode::ZPG | AddressingMode::ZPX | AddressingMode::ZPY);
+        if zero_page && (wide || value.is_some_and(|value| value > 0xFF)) {
+            continue;
+        }
+        let Some(ins) = instructions.iter().find(|ins| ins.addr_mode == addr_mode) else {
+            continue;
+        };
+
+        let mut bytes = vec![ins.opcode.into()];
+        match (ins.bytes(), value) {
+            (1, _) => {},
+            (2, Some(value)) if ins.addr_mode == AddressingMode::REL => {
+                let offset = value as i32 - (addr as i32 + 2);
+                if !(-128..=127).contains(&offset) {
+                    return Err(format!("Branch target ${value:04X} out of range"));
+                }
+                bytes.push(offset as u8);
+            },
+            (2, Some(value)) => {
+                bytes.push(u8::try_from(value).map_err(|_| format!("Operand ${value:04X} does not fit in a byte"))?);
+            },
+            (_, Some(value)) => {
+                bytes.push((value & 0x00FF) as u8);         // LB
+                bytes.push(((value & 0xFF00) >> 8) as u8);  // HB
+            },
+            _ => return Err(String::from("Missing operand")),
+        }
+        return Ok(bytes);
+    }
+
+    Err(format!("Invalid addressing mode for {mnemonic}: '{operand}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instruction::Opcode::*;
+
+    use super::*;
+
+    #[test]
+    fn addressing_modes() {
+        assert_eq!(assemble_line(0xE000, "NOP", &Env::default()), Ok(vec![NOP.into()]));
+        assert_eq!(assemble_line(0xE000, "asl", &Env::default()), Ok(vec![ASL_ACC.into()]));
+        assert_eq!(assemble_line(0xE000, "ROL A", &Env::default()), Ok(vec![ROL_ACC.into()]));
+        assert_eq!(assemble_line(0xE000, "LDA #$10", &Env::default()), Ok(vec![LDA_IMM.into(), 0x10]));
+        assert_eq!(assemble_line(0xE000, "LDA #%101", &Env::default()), Ok(vec![LDA_IMM.into(), 0x05]));
+        assert_eq!(assemble_line(0xE000, "LDA $10", &Env::default()), Ok(vec![LDA_ZPG.into(), 0x10]));
+        assert_eq!(assemble_line(0xE000, "LDA $0010", &Env::default()), Ok(vec![LDA_ABS.into(), 0x10, 0x00]));
+        assert_eq!(assemble_line(0xE000, "LDA $10,X", &Env::default()), Ok(vec![LDA_ZPX.into(), 0x10]));
+        assert_eq!(assemble_line(0xE000, "STA $0200,X", &Env::default()), Ok(vec![STA_ABX.into(), 0x00, 0x02]));
+        assert_eq!(assemble_line(0xE000, "LDX $10, Y", &Env::default()), Ok(vec![LDX_ZPY.into(), 0x10]));
+        assert_eq!(assemble_line(0xE000, "LDA $10,Y", &Env::default()), Ok(vec![LDA_ABY.into(), 0x10, 0x00]));
+        assert_eq!(assemble_line(0xE000, "LDA ($10,X)", &Env::default()), Ok(vec![LDA_IDX.into(), 0x10]));
+        assert_eq!(assemble_line(0xE000, "LDA ($10),Y", &Env::default()), Ok(vec![LDA_IDY.into(), 0x10]));
+        assert_eq!(assemble_line(0xE000, "JMP ($FFFC)", &Env::default()), Ok(vec![JMP_IND.into(), 0xFC, 0xFF]));
+        assert_eq!(assemble_line(0xE000, "JSR 57360 ; comment", &Env::default()), Ok(vec![JSR_ABS.into(), 0x10, 0xE0]));
+    }
+
+    #[test]
+    fn branches() {
+        assert_eq!(assemble_line(0xE000, "BNE $E000", &Env::default()), Ok(vec![BNE_REL.into(), 0xFE]));
+        assert_eq!(assemble_line(0xE000, "BEQ $E081", &Env::default()), Ok(vec![BEQ_REL.into(), 0x7F]));
+        assert!(assemble_line(0xE000, "BEQ $E082", &Env::default()).is_err());
+    }
+
+    #[test]
+    fn expressions() {
+        let mut symbols = crate::symbols::SymbolTable::default();
+        symbols.add("counter", 0x0010);
+        symbols.add("table", 0x0300);
+        let env = Env { symbols: Some(&symbols), ..Env::default() };
+
+        assert_eq!(assemble_line(0xE000, "INC counter", &env), Ok(vec![INC_ZPG.into(), 0x10]));
+        assert_eq!(assemble_line(0xE000, "LDA table+2,x", &env), Ok(vec![LDA_ABX.into(), 0x02, 0x03]));
+        assert_eq!(assemble_line(0xE000, "LDA #>table", &env), Ok(vec![LDA_IMM.into(), 0x03]));
+        assert_eq!(assemble_line(0xE000, "STA (counter),y", &env), Ok(vec![STA_IDY.into(), 0x10]));
+        assert!(assemble_line(0xE000, "LDA unknown", &env).is_err());
+    }
+
+    #[test]
+    fn errors() {
+        assert!(assemble_line(0xE000, "", &Env::default()).is_err());
+        assert!(assemble_line(0xE000, "XYZ", &Env::default()).is_err());
+        assert!(assemble_line(0xE000, "LDA", &Env::default()).is_err());
+        assert!(assemble_line(0xE000, "LDA #$100", &Env::default()).is_err());
+        assert!(assemble_line(0xE000, "STA #$10", &Env::default()).is_err());
+        assert!(assemble_line(0xE000, "LDA $zz", &Env::default()).is_err());
+    }
+
+    #[test]
+    fn program() {
+        let source = "
+            ; counts X up to 10 and stores it
+                    .org $E000
+            start:  LDX #0
+            loop:   INX
+                    STX counter
+                    CPX #10
+                    BNE loop
+                    JSR done        ; forward reference
+                    JMP start
+            done:   RTS
+            table:  .byte 1, $02, \"A;,\", >table
+                    .word start, table+1
+                    .org $10
+            counter:
+        ";
+        let program = assemble(source).unwrap();
+        assert_eq!(program.origin(), Some(0xE000));
+        assert_eq!(program.symbols.lookup("loop"), Some(0xE002));
+        assert_eq!(program.symbols.lookup("done"), Some(0xE010));
+        assert_eq!(program.symbols.lookup("counter"), Some(0x0010));
+        assert_eq!(program.segments, vec![Segment { addr: 0xE000, bytes: vec![
+            LDX_IMM.into(), 0x00,
+            INX.into(),
+            STX_ABS.into(), 0x10, 0x00,     // defined later, so not zero page
+            CPX_IMM.into(), 10,
+            BNE_REL.into(), 0xF8,
+            JSR_ABS.into(), 0x10, 0xE0,
+            JMP_ABS.into(), 0x00, 0xE0,
+            RTS.into(),
+            0x01, 0x02, b'A', b';', b',', 0xE0,
+            0x00, 0xE0, 0x12, 0xE0,
+        ] }]);
+
+        let mut mem = Memory::create();
+        program.write_to(&mut mem);
+        assert_eq!((mem.peek(0xE000), mem.peek(0xE01A)), (LDX_IMM.into(), 0xE0));
+
+        // labels defined earlier in zero page are accessed with zero page addressing
+        let program = assemble(".org $FB\nptr: .word 0\n.org $E000\nLDA ptr\nINC ptr+1\n").unwrap();
+        assert_eq!(program.origin(), Some(0x00FB));
+        assert_eq!(program.segments[1].bytes, vec![LDA_ZPG.into(), 0xFB, INC_ZPG.into(), 0xFC]);
+        assert!(program.contains(0x00FC) && !program.contains(0x00FD) && program.contains(0xE003));
+        assert_eq!(assemble("NOP").unwrap().segments, vec![Segment { addr: 0xE000, bytes: vec![NOP.into()] }]);
+
+        let program = assemble(".org $0202\n.byte 3\n.org $0200\n.byte 1\n").unwrap();
+        assert_eq!(program.image(), Some((0x0200, vec![1, 0, 3])));
+        assert_eq!(assemble("; empty").unwrap().image(), None);
+
+        // a vector table ends at $FFFF
+        let program = assemble(".org $FFFA\n.word $0300, $0300, $0301\n").unwrap();
+        program.write_to(&mut mem);
+        assert_eq!((mem.read_u16(0xFFFA), mem.read_u16(0xFFFE)), (0x0300, 0x0301));
+    }
+
+    #[test]
+    fn listing() {
+        let program = assemble("; copy\n        .org $E0FC\nloop:   LDA $0200,X\n        BNE loop\n        .byte 1, 2, 3, 4\n").unwrap();
+        assert_eq!(program.listing[0], ListingLine { addr: None, bytes: vec![], instruction: false, source: String::from("; copy") });
+
+        let mut listing = Vec::new();
+        program.write_listing(&mut listing).unwrap();
+        assert_eq!(String::from_utf8(listing).unwrap(), [
+            "                            ; copy",
+            "E0FC                                .org $E0FC",
+            "E0FC  BD 00 02  4 +1 page   loop:   LDA $0200,X",
+            "E0FF  D0 FB     2 +2 taken          BNE loop",
+            "E101  01 02 03                      .byte 1, 2, 3, 4",
+            "E104  04",
+            "",
+        ].join("\n"));
+    }
+
+    #[test]
+    fn program_errors() {
+        assert_eq!(assemble("NOP\nLDA unknown").err(), Some(String::from("Line 2: Unknown symbol 'unknown'")));
+        assert_eq!(assemble("a: NOP\na: NOP").err(), Some(String::from("Line 2: Label 'a' defined twice")));
+        assert!(assemble("1abc: NOP").is_err());
+        assert!(assemble(".fill 10").is_err());
+        assert!(assemble(".byte 256").is_err());
+        assert!(assemble(".byte").is_err());
+        assert!(assemble(".word \"AB\"").is_err());
+        assert!(assemble(".org later\nlater: NOP").is_err());
+        assert!(assemble(".org $FFFF\nJMP $E000").is_err());
+        assert!(assemble("BNE far\n.org $E100\nfar: NOP").is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut mem = Memory::create();
+
+        // every documented instruction with operand bytes that keep zero page and absolute modes apart
+        let mut addr = 0x1000u16;
+        for ins in Instruction::all() {
+            mem.write_u8(addr, ins.opcode.into());
+            for i in 1..ins.bytes() as u16 {
+                mem.write_u8(addr + i, 0x10 + i as u8);
+            }
+            addr += ins.bytes() as u16;
+        }
+        // undocumented opcodes and unused bytes are data
+        mem.write_u8(addr, 0xA7);
+
+        assert_eq!(verify_round_trip(&mem, 0x1000..=addr), Ok(Instruction::all().count() + 1));
+        assert!(verify_round_trip(&mem, 0xFFF0..=0xFFFF).is_ok());
+    }
+}
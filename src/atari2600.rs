@@ -0,0 +1,312 @@
+// Atari 2600 (NTSC): the 6507, a 6502 with 13 address lines and no interrupt inputs, so everything is mirrored every
+// 8K. Within them, by A12, A7 and A9:
+//
+//  $0000-$007F  TIA (A12 = 0, A7 = 0): 64 write registers ($00-$3F) and 16 read registers ($00-$0F)
+//  $0080-$00FF  RIOT RAM, 128 bytes (A7 = 1, A9 = 0), also the stack at $0180-$01FF
+//  $0280-$029F  RIOT ports and timer (A7 = 1, A9 = 1): SWCHA joysticks (none pressed), SWCHB console switches
+//               (released, color, difficulty B), INTIM, TIMINT, TIM1T-T1024T at $0294-$0297
+//  $1000-$1FFF  cartridge (A12 = 1), given as ROM 'cart': 2K mirrored or 4K, without bank switching
+//
+// The TIA is a register-level stub for tracing kernels: writes are stored, the beam runs 228 color clocks (76 cycles)
+// per scanline and a frame starts when VSYNC is turned on. WSYNC halts the CPU until the end of the scanline and
+// RSYNC restarts it. Nothing is drawn, collisions never happen and the fire buttons (INPT4, INPT5) are released.
+
+use std::ops::RangeInclusive;
+
+use crate::device::Device;
+use crate::machine::{Machine, Profile, RomSlot, Wiring};
+use crate::riot::Riot;
+use crate::rom::RomImage;
+
+pub const ADDR_MASK: u16 = 0x1FFF;
+pub const RIOT_BASE: u16 = 0x0280;
+pub const CART_BASE: u16 = 0xF000;
+
+pub const REG_VSYNC: u16 = 0x00;
+pub const REG_VBLANK: u16 = 0x01;
+pub const REG_WSYNC: u16 = 0x02;
+pub const REG_RSYNC: u16 = 0x03;
+pub const REG_COLUBK: u16 = 0x09;
+pub const REG_INPT4: u16 = 0x0C;
+pub const REG_INPT5: u16 = 0x0D;
+
+pub const VSYNC_ON: u8 = 0x02;
+
+const SWCHB_DEFAULT: u8 = 0x0B;     // reset and select released, color, both difficulties B
+const LINE_CLOCKS: u64 = 228;
+const CLOCKS_PER_CYCLE: u64 = 3;
+const LINE_CYCLES: u64 = LINE_CLOCKS / CLOCKS_PER_CYCLE;
+const FRAME_LINES: u64 = 262;
+const CART_SIZES: [usize; 2] = [0x0800, 0x1000];
+
+pub const ATARI_2600: Profile = Profile {
+    name: "atari2600",
+    description: "Atari 2600 (NTSC): 6507 CPU (8K address space), TIA stub with WSYNC at $00, 128 bytes RAM at $80, RIOT ports and timer at $280, 2K or 4K cartridge as ROM 'cart' at $F000",
+    clock_hz: 1_193_182,
+    frame_cycles: FRAME_LINES * LINE_CYCLES,        // 60 Hz
+    roms: &[RomSlot { name: "cart", addr: CART_BASE, size: 0 }],
+    keyboard: false,
+    wire,
+};
+
+fn wire(machine: &mut Machine, wiring: &mut Wiring) -> Result<(), String> {
+    let cart = wiring.take_rom("cart");
+    if let Some(image) = cart.as_ref().filter(|image| !CART_SIZES.contains(&image.len())) {
+        return Err(format!("cart: 2K or 4K image expected instead of {} bytes, bank switching is not supported", image.len()));
+    }
+    machine.attach(Box::new(Atari2600Bus::create(cart)));
+    Ok(())
+}
+
+// TIA: registers stored as written, but for the beam position and WSYNC
+pub struct Tia {
+    regs: [u8; 0x40],
+    clock: u64,             // color clock into the scanline
+    scanline: u64,          // into the frame
+    frames: u64,
+    frame_lines: u64,       // scanlines of the last complete frame
+    wsync: bool,
+}
+
+impl Tia {
+    fn create() -> Self {
+        Self { regs: [0; 0x40], clock: 0, scanline: 0, frames: 0, frame_lines: 0, wsync: false }
+    }
+
+    // the value last written
+    pub fn register(&self, reg: u16) -> u8 {
+        self.regs[(reg & 0x3F) as usize]
+    }
+
+    pub fn color_clock(&self) -> u64 {
+        self.clock
+    }
+
+    pub fn scanline(&self) -> u64 {
+        self.scanline
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn frame_lines(&self) -> u64 {
+        self.frame_lines
+    }
+
+    fn peek(&self, reg: u16) -> u8 {
+        match reg & 0x0F {
+            REG_INPT4 | REG_INPT5 => 0x80,
+            _ => 0x00,
+        }
+    }
+
+    fn write(&mut self, reg: u16, value: u8) {
+        let reg = reg & 0x3F;
+        match reg {
+            REG_VSYNC if value & VSYNC_ON != 0 && self.regs[reg as usize] & VSYNC_ON == 0 => {
+                self.frames += 1;
+                self.frame_lines = self.scanline;
+                self.scanline = 0;
+            },
+            REG_WSYNC => self.wsync = true,
+            REG_RSYNC => self.clock = 0,
+            _ => {},
+        }
+        self.regs[reg as usize] = value;
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.clock += cycles * CLOCKS_PER_CYCLE;
+        self.scanline += self.clock / LINE_CLOCKS;
+        self.clock %= LINE_CLOCKS;
+    }
+
+    fn halt(&mut self) -> u64 {
+        match std::mem::take(&mut self.wsync) {
+            true if self.clock > 0 => (LINE_CLOCKS - self.clock).div_ceil(CLOCKS_PER_CYCLE),
+            _ => 0,
+        }
+    }
+}
+
+// the whole address space, decoded from the 13 address lines
+pub struct Atari2600Bus {
+    tia: Tia,
+    ram: [u8; 0x80],
+    riot: Riot,
+    cart: Option<RomImage>,
+}
+
+impl Atari2600Bus {
+    pub fn create(cart: Option<RomImage>) -> Self {
+        let mut riot = Riot::create("riot", RIOT_BASE);
+        riot.set_port_b(SWCHB_DEFAULT);
+        Self { tia: Tia::create(), ram: [0; 0x80], riot, cart }
+    }
+
+    pub fn tia(&self) -> &Tia {
+        &self.tia
+    }
+
+    pub fn riot(&self) -> &Riot {
+        &self.riot
+    }
+
+    pub fn riot_mut(&mut self) -> &mut Riot {
+        &mut self.riot
+    }
+}
+
+impl Device for Atari2600Bus {
+    fn name(&self) -> &str {
+        "atari2600-bus"
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        0x0000..=0xFFFF
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr & ADDR_MASK {
+            addr if addr & 0x1280 == 0x0280 => self.riot.read(addr),
+            _ => self.peek(addr),
+        }
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match addr & ADDR_MASK {
+            addr if addr & 0x1000 != 0 => self.cart.as_ref().map_or(0, |cart| cart.read((addr & 0x0FFF) as usize % cart.len())),
+            addr if addr & 0x0080 == 0 => self.tia.peek(addr),
+            addr if addr & 0x0200 == 0 => self.ram[(addr & 0x7F) as usize],
+            addr => self.riot.peek(addr),
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr & ADDR_MASK {
+            addr if addr & 0x1000 != 0 => {},
+            addr if addr & 0x0080 == 0 => self.tia.write(addr, value),
+            addr if addr & 0x0200 == 0 => self.ram[(addr & 0x7F) as usize] = value,
+            addr => self.riot.write(addr, value),
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.tia.tick(cycles);
+        self.riot.tick(cycles);
+    }
+
+    fn halt(&mut self) -> u64 {
+        self.tia.halt()
+    }
+
+    // the RIOT is reset, the TIA (not wired to the reset line) and RAM are kept
+    fn reset(&mut self) {
+        self.riot.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::asm;
+    use crate::riot::{FLAG_TIMER, REG_TIM64T};
+
+    use super::*;
+
+    fn cart(image: &[u8]) -> Result<Machine, String> {
+        let filename = std::env::temp_dir().join(format!("rust-6502-emu-atari2600-{}-{}.bin", image.len(), std::process::id()));
+        std::fs::write(&filename, image).unwrap();
+        let machine = Machine::create(&ATARI_2600, Wiring::with_roms(vec![(String::from("cart"), String::from(filename.to_str().unwrap()))]));
+        std::fs::remove_file(&filename).unwrap();
+        machine
+    }
+
+    #[test]
+    fn memory() {
+        // 2K cartridge, its reset vector pointing to $F123
+        let mut image = vec![0xEA; 0x0800];
+        image[0x07FC..].copy_from_slice(&[0x23, 0xF1, 0x00, 0x00]);
+        let mut machine = cart(&image).unwrap();
+        assert_eq!(machine.cpu.pc, 0xF123);
+
+        // RAM at $80, mirrored at $180 (the stack) and every 8K
+        let mem = &mut machine.mem;
+        mem.write_u8(0x00FF, 0x42);
+        mem.write_u8(0xF000, 0x00);
+        assert_eq!((mem.peek(0x01FF), mem.peek(0x20FF), mem.peek(0xF000), mem.peek(0x1800)), (0x42, 0x42, 0xEA, 0xEA));
+
+        // TIA reads: no collisions, fire buttons released; RIOT ports and timer mirrored
+        mem.write_u8(REG_COLUBK, 0x1E);
+        assert_eq!((mem.peek(REG_COLUBK), mem.peek(0x003C), mem.peek(0x004D)), (0x00, 0x80, 0x80));
+        mem.write_u8(RIOT_BASE + REG_TIM64T + 0x2000, 2);
+        mem.tick_devices(1 + 2 * 64);
+        assert_eq!((mem.peek(0x0280), mem.peek(0x0282), mem.peek(0x0284), mem.peek(0x0285)), (0xFF, 0x0B, 0xFF, FLAG_TIMER));
+        let bus = mem.device::<Atari2600Bus>("atari2600-bus").unwrap();
+        assert_eq!(bus.tia().register(REG_COLUBK), 0x1E);
+
+        assert!(cart(&[0xEA; 0x0800 * 3]).err().unwrap().starts_with("cart: 2K or 4K image expected"));
+        assert!(Machine::create(&ATARI_2600, Wiring::default()).is_ok());
+    }
+
+    #[test]
+    fn kernel() {
+        // 3 lines of VSYNC, VBLANK timed by the RIOT, 192 visible lines, 30 lines of overscan
+        let program = asm::assemble("
+                .org $F000
+        start:  SEI
+                CLD
+                LDX #$FF
+                TXS
+        frame:  LDA #2
+                STA $00
+                STA $02
+                STA $02
+                STA $02
+                LDA #0
+                STA $00
+                LDA #44
+                STA $0296
+        vblank: LDA $0284
+                BNE vblank
+                STA $02
+                STA $01
+                LDX #192
+        line:   STX $09
+                STA $02
+                DEX
+                BNE line
+                LDA #2
+                STA $01
+                LDX #30
+        over:   STA $02
+                DEX
+                BNE over
+                JMP frame
+                .org $FFFC
+                .word start, start
+        ").unwrap();
+        let (addr, image) = program.image().unwrap();
+        assert_eq!((addr, image.len()), (CART_BASE, 0x1000));
+        let mut machine = cart(&image).unwrap();
+
+        // frames start at VSYNC, a few cycles into a scanline, and have 262 lines
+        let mut run_to_frame = |frame: u64| {
+            while machine.mem.device::<Atari2600Bus>("atari2600-bus").unwrap().tia().frames() < frame {
+                machine.step();
+            }
+            machine.cpu.cycles
+        };
+        let cycles = run_to_frame(2);
+        assert_eq!(run_to_frame(3) - cycles, FRAME_LINES * LINE_CYCLES);
+        let bus = machine.mem.device::<Atari2600Bus>("atari2600-bus").unwrap();
+        assert_eq!((bus.tia().frame_lines(), bus.tia().scanline(), bus.tia().register(REG_COLUBK)), (262, 0, 1));
+
+        // WSYNC halts until the end of the scanline
+        let mut tia = Tia::create();
+        tia.tick(10);
+        tia.write(REG_WSYNC, 0);
+        assert_eq!((tia.halt(), tia.halt()), (LINE_CYCLES - 10, 0));
+        assert_eq!(tia.peek(REG_INPT4 + 0x10) & 0x80, 0x80);
+    }
+}
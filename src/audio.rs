@@ -0,0 +1,66 @@
+// Host sound output for the beeper (feature "audio"). Samples are queued for the output stream; as the emulation is
+// not paced to real time, samples beyond a quarter of a second in the queue are dropped.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat, SizedSample, Stream, StreamConfig};
+
+use crate::beeper::AudioSink;
+
+pub struct HostAudio {
+    _stream: Stream,            // plays while alive
+    sample_rate: u32,
+    queue: Arc<Mutex<VecDeque<f32>>>,
+}
+
+impl HostAudio {
+    pub fn open() -> Result<Self, String> {
+        let device = cpal::default_host().default_output_device().ok_or("No audio output device")?;
+        let config = device.default_output_config().map_err(|error| format!("Audio output: {error}"))?;
+        let queue = Arc::new(Mutex::new(VecDeque::new()));
+        let stream = match config.sample_format() {
+            SampleFormat::F32 => Self::stream::<f32>(&device, &config.config(), queue.clone()),
+            SampleFormat::I16 => Self::stream::<i16>(&device, &config.config(), queue.clone()),
+            SampleFormat::U16 => Self::stream::<u16>(&device, &config.config(), queue.clone()),
+            format => return Err(format!("Unsupported audio sample format {format}")),
+        }?;
+        stream.play().map_err(|error| format!("Audio output: {error}"))?;
+        Ok(Self { _stream: stream, sample_rate: config.sample_rate().0, queue })
+    }
+
+    fn stream<T>(device: &cpal::Device, config: &StreamConfig, queue: Arc<Mutex<VecDeque<f32>>>) -> Result<Stream, String>
+    where
+        T: SizedSample + FromSample<f32>,
+    {
+        let channels = config.channels as usize;
+        let mut last = 0.0;
+        device.build_output_stream(
+            config,
+            move |data: &mut [T], _| {
+                let mut queue = queue.lock().unwrap();
+                for frame in data.chunks_mut(channels) {
+                    // holds the level when running out of samples
+                    last = queue.pop_front().unwrap_or(last);
+                    frame.fill(T::from_sample(last));
+                }
+            },
+            |error| log::error!("Audio output: {error}"),
+            None,
+        ).map_err(|error| format!("Audio output: {error}"))
+    }
+}
+
+impl AudioSink for HostAudio {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn play(&mut self, samples: &[f32]) {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.len() < self.sample_rate as usize / 4 {
+            queue.extend(samples);
+        }
+    }
+}
@@ -0,0 +1,214 @@
+// Beeper: a one-bit speaker toggled by the program on each access to an address as on the Apple II, or by a timer
+// playing a square wave. The speaker level is sampled over the emulated cycles and the samples are played by an
+// audio sink (the host's sound output with feature "audio").
+//
+//  +0  speaker     any read or write toggles the speaker
+//  +1  period LB   half period of the tone in cycles
+//  +2  period HB
+//  +3  control     bit 0: tone on, the timer toggling the speaker each half period
+
+use std::ops::RangeInclusive;
+
+use crate::device::Device;
+
+pub const BEEPER_BASE_DEFAULT: u16 = 0xDF30;
+pub const BEEPER_CLOCK_HZ_DEFAULT: u64 = 1_000_000;
+
+pub const REG_SPEAKER: u16 = 0;
+pub const REG_PERIOD_LB: u16 = 1;
+pub const REG_PERIOD_HB: u16 = 2;
+pub const REG_CONTROL: u16 = 3;
+
+const CONTROL_TONE: u8 = 0x01;
+
+const AMPLITUDE: f32 = 0.25;
+const SAMPLES_BUFFERED: usize = 256;    // played together
+
+pub trait AudioSink {
+    fn sample_rate(&self) -> u32;
+
+    // mono samples from -1.0 to 1.0
+    fn play(&mut self, samples: &[f32]);
+}
+
+pub struct Beeper {
+    name: String,
+    pub base: u16,
+    hz: u64,                    // emulated clock rate
+    sink: Box<dyn AudioSink>,
+    level: bool,
+    period: u16,
+    tone: bool,
+    countdown: u64,             // cycles until the timer toggles the speaker
+    fill: u64,                  // cycles of the current sample, scaled by the sample rate
+    high: u64,                  // of which the speaker was high
+    samples: Vec<f32>,
+}
+
+impl Beeper {
+    pub fn create(name: &str, base: u16, hz: u64, sink: Box<dyn AudioSink>) -> Self {
+        Self {
+            name: String::from(name),
+            base,
+            hz,
+            sink,
+            level: false,
+            period: 0,
+            tone: false,
+            countdown: 0,
+            fill: 0,
+            high: 0,
+            samples: Vec::with_capacity(SAMPLES_BUFFERED),
+        }
+    }
+
+    pub fn level(&self) -> bool {
+        self.level
+    }
+
+    // samples are averaged over their cycles
+    fn sample(&mut self, cycles: u64) {
+        let mut remaining = cycles * self.sink.sample_rate() as u64;
+        while remaining > 0 {
+            let step = remaining.min(self.hz - self.fill);
+            if self.level {
+                self.high += step;
+            }
+            self.fill += step;
+            remaining -= step;
+            if self.fill == self.hz {
+                self.samples.push(AMPLITUDE * (2.0 * self.high as f32 / self.hz as f32 - 1.0));
+                (self.fill, self.high) = (0, 0);
+                if self.samples.len() == SAMPLES_BUFFERED {
+                    self.sink.play(&self.samples);
+                    self.samples.clear();
+                }
+            }
+        }
+    }
+}
+
+impl Device for Beeper {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        self.base..=self.base + REG_CONTROL
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        if addr.wrapping_sub(self.base) == REG_SPEAKER {
+            self.level = !self.level;
+        }
+        self.peek(addr)
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match addr.wrapping_sub(self.base) {
+            REG_PERIOD_LB => self.period as u8,
+            REG_PERIOD_HB => (self.period >> 8) as u8,
+            REG_CONTROL if self.tone => CONTROL_TONE,
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr.wrapping_sub(self.base) {
+            REG_SPEAKER => self.level = !self.level,
+            REG_PERIOD_LB => self.period = (self.period & 0xFF00) | value as u16,
+            REG_PERIOD_HB => self.period = (self.period & 0x00FF) | (value as u16) << 8,
+            _ => {
+                self.tone = value & CONTROL_TONE != 0;
+                self.countdown = self.period as u64;
+            },
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        if !self.tone || self.period == 0 {
+            self.sample(cycles);
+            return;
+        }
+        let mut remaining = cycles;
+        while remaining > 0 {
+            let step = remaining.min(self.countdown.max(1));
+            self.sample(step);
+            remaining -= step;
+            self.countdown = self.countdown.saturating_sub(step);
+            if self.countdown == 0 {
+                self.level = !self.level;
+                self.countdown = self.period as u64;
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.level = false;
+        self.period = 0;
+        self.tone = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Recorder {
+        samples: Rc<RefCell<Vec<f32>>>,
+    }
+
+    impl AudioSink for Recorder {
+        fn sample_rate(&self) -> u32 {
+            10000
+        }
+
+        fn play(&mut self, samples: &[f32]) {
+            self.samples.borrow_mut().extend(samples);
+        }
+    }
+
+    #[test]
+    fn toggle() {
+        let recorder = Recorder::default();
+        let mut beeper = Beeper::create("beeper", BEEPER_BASE_DEFAULT, BEEPER_CLOCK_HZ_DEFAULT, Box::new(recorder.clone()));
+        assert_eq!(beeper.peek(0xDF30), 0);
+        assert!(!beeper.level());
+        beeper.read(0xDF30);
+        assert!(beeper.level());
+        beeper.tick(150);
+        beeper.write(0xDF30, 0);
+        beeper.tick(100 * SAMPLES_BUFFERED as u64 - 150);
+
+        // 100 cycles per sample, the first one half high
+        let samples = recorder.samples.borrow();
+        assert_eq!(samples.len(), SAMPLES_BUFFERED);
+        assert_eq!(samples[..3], [AMPLITUDE, 0.0, -AMPLITUDE]);
+    }
+
+    #[test]
+    fn tone() {
+        let recorder = Recorder::default();
+        let mut beeper = Beeper::create("beeper", BEEPER_BASE_DEFAULT, BEEPER_CLOCK_HZ_DEFAULT, Box::new(recorder.clone()));
+        beeper.write(0xDF31, 0x2C);
+        beeper.write(0xDF32, 0x01);
+        beeper.write(0xDF33, 0x01);
+        assert_eq!((beeper.peek(0xDF31), beeper.peek(0xDF32), beeper.peek(0xDF33)), (0x2C, 0x01, 0x01));
+
+        // toggled every 300 cycles, i.e. every 3 samples
+        beeper.tick(299);
+        assert!(!beeper.level());
+        beeper.tick(1);
+        assert!(beeper.level());
+        beeper.tick(100 * SAMPLES_BUFFERED as u64 - 300);
+        let samples = recorder.samples.borrow();
+        assert_eq!(samples[..7], [-AMPLITUDE, -AMPLITUDE, -AMPLITUDE, AMPLITUDE, AMPLITUDE, AMPLITUDE, -AMPLITUDE]);
+
+        beeper.reset();
+        assert_eq!(beeper.peek(0xDF33), 0);
+    }
+}
@@ -0,0 +1,572 @@
+// Sets up a machine step by step and checks the combination when built, e.g.
+//
+//   let emulator = EmulatorBuilder::create().model(Variant::Cmos65C02).load_hex("program.hex")?.map_rom("banks.rom")?
+//       .breakpoint(0xE010).build()?;
+//
+// Files are read (and output files created) when given, so that the method naming them fails; devices, memory contents,
+// the ROM window and the saved state are put into the machine by `build`, in this order, with the contents in the order
+// given, and the instrumentation and traces last, so that they only see execution.
+
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::ops::RangeInclusive;
+
+use crate::acia::{self, Acia, SerialBackend, SerialLine};
+use crate::asm::{Program, Segment};
+#[cfg(feature = "audio")]
+use crate::audio::HostAudio;
+#[cfg(feature = "audio")]
+use crate::beeper::{self, Beeper};
+use crate::bus;
+use crate::chario::{self, CharIo};
+use crate::cpu::{self, Variant};
+use crate::crt::{self, Cartridge};
+use crate::device::Device;
+use crate::dma::{self, Dma};
+use crate::emulator::Emulator;
+use crate::gpio::{self, Gpio, GpioScript};
+use crate::ihex::IntelHex;
+use crate::input::InputLog;
+use crate::kernal::Kernal;
+use crate::keyboard::{self, Keyboard, Keymap};
+use crate::lcd::{self, Lcd, LcdConnection};
+use crate::machine::{self, Machine, Profile, Wiring};
+use crate::mem::UninitPolicy;
+use crate::pia::{self, Pia};
+use crate::rng::{self, Rng};
+use crate::rom::{self, BankedRom, RomImage};
+use crate::rtc::{self, Rtc, RtcClock};
+use crate::screen::{Charset, TextScreen};
+use crate::segments::{self, SevenSegment};
+use crate::state;
+use crate::storage::{self, Storage};
+use crate::symbols::SymbolTable;
+use crate::tape::{self, Tape, TapeRate};
+use crate::trace::{TraceComparison, TraceFormat, TraceWriter};
+use crate::via::{self, Via};
+use crate::woz::WozImage;
+
+// memory contents, starting execution at `origin` (by the reset vector) unless they set the reset vector themselves
+struct Contents {
+    segments: Vec<Segment>,
+    origin: Option<u16>,
+}
+
+pub struct EmulatorBuilder {
+    profile: &'static Profile,
+    roms: Vec<(String, String)>,
+    model: Option<Variant>,
+    dma: bool,
+    devices: Vec<Box<dyn Device>>,                              // attached in the order given
+    keyboard: Option<Box<dyn SerialBackend>>,                   // the machine's own keyboard takes it, if it has one
+    keymap: Option<Keymap>,
+    stdio_devices: Vec<&'static str>,
+    uninit_policy: UninitPolicy,
+    stack_check: bool,
+    kernal_dir: Option<String>,
+    contents: Vec<Contents>,
+    symbols: SymbolTable,
+    banked_rom: Option<BankedRom>,
+    resume_file: Option<String>,
+    demo: bool,
+    breakpoints: BTreeSet<u16>,
+    screen: Option<TextScreen>,                                 // instead of the machine's
+    heatmap: bool,
+    profiling: bool,
+    stats: bool,
+    coverage: bool,
+    dump: Option<(Box<dyn Write>, bool)>,
+    trace: Option<TraceWriter>,
+    bus_trace: Option<BufWriter<File>>,
+    trace_comparison: Option<TraceComparison>,
+    input_replay: Option<InputLog>,
+    input_recording: bool,
+}
+
+impl EmulatorBuilder {
+    // the generic machine without devices
+    pub fn create() -> Self {
+        Self {
+            profile: &machine::GENERIC,
+            roms: Vec::new(),
+            model: None,
+            dma: false,
+            devices: Vec::new(),
+            keyboard: None,
+            keymap: None,
+            stdio_devices: Vec::new(),
+            uninit_policy: UninitPolicy::Ignore,
+            stack_check: false,
+            kernal_dir: None,
+            contents: Vec::new(),
+            symbols: SymbolTable::default(),
+            banked_rom: None,
+            resume_file: None,
+            demo: false,
+            breakpoints: BTreeSet::new(),
+            screen: None,
+            heatmap: false,
+            profiling: false,
+            stats: false,
+            coverage: false,
+            dump: None,
+            trace: None,
+            bus_trace: None,
+            trace_comparison: None,
+            input_replay: None,
+            input_recording: false,
+        }
+    }
+
+    pub fn machine(mut self, profile: &'static Profile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    // image for a ROM slot of the machine, checked when built
+    pub fn machine_rom(mut self, name: &str, filename: &str) -> Self {
+        self.roms.push((String::from(name), String::from(filename)));
+        self
+    }
+
+    // the CPU variant instead of the machine's, e.g. `Variant::Cmos65C02`
+    pub fn model(mut self, variant: Variant) -> Self {
+        self.model = Some(variant);
+        self
+    }
+
+    pub fn dma(mut self) -> Self {
+        self.dma = true;
+        self
+    }
+
+    pub fn device(mut self, device: Box<dyn Device>) -> Self {
+        self.devices.push(device);
+        self
+    }
+
+    pub fn via(self) -> Self {
+        self.device(Box::new(Via::create("via", via::VIA_BASE_DEFAULT)))
+    }
+
+    pub fn pia(self) -> Self {
+        self.device(Box::new(Pia::create("pia", pia::PIA_BASE_DEFAULT)))
+    }
+
+    pub fn rng(self, seed: u64) -> Self {
+        self.device(Box::new(Rng::create("rng", rng::RNG_ADDR_DEFAULT, seed)))
+    }
+
+    pub fn rtc(self, clock: RtcClock) -> Self {
+        self.device(Box::new(Rtc::create("rtc", rtc::RTC_BASE_DEFAULT, clock)))
+    }
+
+    pub fn storage(self, filename: &str) -> Result<Self, String> {
+        Ok(self.device(Box::new(Storage::open("storage", storage::STORAGE_BASE_DEFAULT, filename)?)))
+    }
+
+    pub fn tape(self, filename: &str, rate: TapeRate) -> Result<Self, String> {
+        Ok(self.device(Box::new(Tape::load("tape", tape::TAPE_BASE_DEFAULT, filename, rate)?)))
+    }
+
+    #[cfg(feature = "audio")]
+    pub fn beeper(self) -> Result<Self, String> {
+        let sink = Box::new(HostAudio::open()?);
+        Ok(self.device(Box::new(Beeper::create("beeper", beeper::BEEPER_BASE_DEFAULT, beeper::BEEPER_CLOCK_HZ_DEFAULT, sink))))
+    }
+
+    // on the VIA's ports the LCD comes with its own VIA, which takes the place of `via`
+    pub fn lcd(self, columns: u16, rows: u16, connection: LcdConnection) -> Self {
+        let lcd = match connection {
+            LcdConnection::Mapped => Lcd::create("lcd", lcd::LCD_BASE_DEFAULT, columns, rows),
+            LcdConnection::Via => Lcd::with_via("lcd", Via::create("via", via::VIA_BASE_DEFAULT), columns, rows),
+        };
+        self.device(Box::new(lcd))
+    }
+
+    pub fn segments(self, digits: usize) -> Self {
+        self.device(Box::new(SevenSegment::create("segments", segments::SEGMENTS_BASE_DEFAULT, digits)))
+    }
+
+    pub fn gpio(self, script: GpioScript) -> Self {
+        self.device(Box::new(Gpio::with_script("gpio", gpio::GPIO_BASE_DEFAULT, script)))
+    }
+
+    pub fn acia(mut self, line: SerialLine) -> Result<Self, String> {
+        self.serial_line(line, "ACIA");
+        Ok(self.device(Box::new(Acia::create("acia", acia::ACIA_BASE_DEFAULT, line.open("ACIA")?))))
+    }
+
+    pub fn char_io(mut self, line: SerialLine) -> Result<Self, String> {
+        self.serial_line(line, "character I/O");
+        Ok(self.device(Box::new(CharIo::create("chario", chario::CHARIO_BASE_DEFAULT, line.open("Character I/O")?))))
+    }
+
+    pub fn keyboard(mut self, line: SerialLine) -> Result<Self, String> {
+        self.serial_line(line, "keyboard");
+        self.keyboard = Some(line.open("Keyboard")?);
+        Ok(self)
+    }
+
+    // of the keyboard, or the machine's own keyboard instead of its default
+    pub fn keymap(mut self, keymap: Keymap) -> Self {
+        self.keymap = Some(keymap);
+        self
+    }
+
+    fn serial_line(&mut self, line: SerialLine, device: &'static str) {
+        if line == SerialLine::Stdio {
+            self.stdio_devices.push(device);
+        }
+    }
+
+    pub fn uninit_policy(mut self, policy: UninitPolicy) -> Self {
+        self.uninit_policy = policy;
+        self
+    }
+
+    pub fn stack_check(mut self, enabled: bool) -> Self {
+        self.stack_check = enabled;
+        self
+    }
+
+    // KERNAL semihosting on the console, loading files from `dir`
+    pub fn kernal_traps(mut self, dir: &str) -> Self {
+        self.kernal_dir = Some(String::from(dir));
+        self
+    }
+
+    pub fn load(mut self, addr: u16, bytes: &[u8]) -> Result<Self, String> {
+        if addr as usize + bytes.len() > 0x10000 {
+            return Err(format!("{} bytes at ${addr:04X} reach beyond $FFFF", bytes.len()));
+        }
+        self.contents.push(Contents { segments: vec![Segment { addr, bytes: bytes.to_vec() }], origin: None });
+        Ok(self)
+    }
+
+    // binary image
+    pub fn load_file(self, addr: u16, filename: &str) -> Result<Self, String> {
+        let bytes = fs::read(filename).map_err(|error| format!("{filename}: {error}"))?;
+        self.load(addr, &bytes).map_err(|error| format!("{filename}: {error}"))
+    }
+
+    pub fn load_hex(self, filename: &str) -> Result<Self, String> {
+        Ok(self.hex(IntelHex::load_from_file(filename)?))
+    }
+
+    pub fn hex(mut self, image: IntelHex) -> Self {
+        self.contents.push(Contents { origin: image.origin(), segments: image.segments });
+        self
+    }
+
+    pub fn load_woz(self, filename: &str) -> Result<Self, String> {
+        Ok(self.woz(WozImage::load_from_file(filename)?))
+    }
+
+    pub fn woz(mut self, image: WozImage) -> Self {
+        self.contents.push(Contents { origin: image.origin(), segments: image.segments });
+        self
+    }
+
+    // an assembled program with its labels
+    pub fn program(mut self, program: &Program) -> Self {
+        for (addr, name) in program.symbols.iter() {
+            self.symbols.add(name, addr);
+        }
+        self.contents.push(Contents { segments: program.segments.clone(), origin: program.origin() });
+        self
+    }
+
+    pub fn symbols(mut self, symbols: &SymbolTable) -> Self {
+        for (addr, name) in symbols.iter() {
+            self.symbols.add(name, addr);
+        }
+        self
+    }
+
+    // banked ROM window at its default address
+    pub fn map_rom(mut self, filename: &str) -> Result<Self, String> {
+        let image = RomImage::open(filename).map_err(|error| format!("Error mapping ROM image {filename}: {error}"))?;
+        self.banked_rom = Some(BankedRom::create(image, rom::ROM_BASE_DEFAULT, rom::ROM_BANK_SIZE_DEFAULT, rom::ROM_BANK_SELECT_DEFAULT));
+        Ok(self)
+    }
+
+    pub fn cartridge(mut self, cartridge: &Cartridge) -> Result<Self, String> {
+        self.banked_rom = Some(cartridge.banked_rom(crt::CRT_BANK_SELECT)?);
+        Ok(self)
+    }
+
+    // a saved machine replacing the reset state, restored last
+    pub fn resume(mut self, filename: &str) -> Self {
+        self.resume_file = Some(String::from(filename));
+        self
+    }
+
+    pub fn demo(mut self) -> Self {
+        self.demo = true;
+        self
+    }
+
+    // see `Emulator::add_breakpoint`
+    pub fn breakpoint(mut self, addr: u16) -> Self {
+        self.breakpoints.insert(addr);
+        self
+    }
+
+    // text display of the memory at `base`, taken by `Emulator::take_screen`
+    pub fn screen(mut self, base: u16, columns: u16, rows: u16, charset: Charset) -> Result<Self, String> {
+        self.screen = Some(TextScreen::create(base, columns, rows, charset)?);
+        Ok(self)
+    }
+
+    // counts of the accesses per address, see `Memory::heatmap`
+    pub fn heatmap(mut self) -> Self {
+        self.heatmap = true;
+        self
+    }
+
+    // see `Cpu::profile`
+    pub fn profile(mut self) -> Self {
+        self.profiling = true;
+        self
+    }
+
+    // see `Cpu::stats`
+    pub fn stats(mut self) -> Self {
+        self.stats = true;
+        self
+    }
+
+    // see `Cpu::coverage`
+    pub fn coverage(mut self) -> Self {
+        self.coverage = true;
+        self
+    }
+
+    // the executed instructions and register dumps, written to the file without colors instead of being logged
+    pub fn dump_file(mut self, filename: &str) -> Result<Self, String> {
+        self.dump = Some((Box::new(BufWriter::new(File::create(filename).map_err(|error| format!("{filename}: {error}"))?)), false));
+        Ok(self)
+    }
+
+    // the executed instructions and register dumps, written to `output` instead of being logged, see `Cpu::set_dump_output`
+    pub fn dump_output(mut self, output: Box<dyn Write>, color: bool) -> Self {
+        self.dump = Some((output, color));
+        self
+    }
+
+    // a line per instruction in `format`, only within `ranges` unless empty; written until `Emulator::stop_traces`
+    pub fn trace_file(mut self, filename: &str, format: TraceFormat, ranges: Vec<RangeInclusive<u16>>) -> Result<Self, String> {
+        let file = File::create(filename).map_err(|error| format!("{filename}: {error}"))?;
+        let mut trace = TraceWriter::create(Box::new(BufWriter::new(file)), format);
+        trace.set_ranges(ranges);
+        self.trace = Some(trace);
+        Ok(self)
+    }
+
+    // a line per bus cycle, see `bus::HEADER`; written until `Emulator::stop_traces`
+    pub fn bus_trace_file(mut self, filename: &str) -> Result<Self, String> {
+        let mut writer = BufWriter::new(File::create(filename).map_err(|error| format!("{filename}: {error}"))?);
+        writeln!(writer, "{}", bus::HEADER).map_err(|error| format!("{filename}: {error}"))?;
+        self.bus_trace = Some(writer);
+        Ok(self)
+    }
+
+    // execution stops before the first instruction not matching its line in the reference trace
+    pub fn compare_trace(mut self, filename: &str) -> Result<Self, String> {
+        let file = File::open(filename).map_err(|error| format!("{filename}: {error}"))?;
+        self.trace_comparison = Some(TraceComparison::create(Box::new(BufReader::new(file))));
+        Ok(self)
+    }
+
+    // IRQ/NMI inputs recorded by an earlier run, applied at their cycles
+    pub fn replay_input(mut self, filename: &str) -> Result<Self, String> {
+        self.input_replay = Some(InputLog::load_from_file(filename)?);
+        Ok(self)
+    }
+
+    // see `Cpu::input_recording`
+    pub fn record_input(mut self) -> Self {
+        self.input_recording = true;
+        self
+    }
+
+    pub fn build(mut self) -> Result<Emulator, String> {
+        if self.stdio_devices.len() > 1 {
+            return Err(format!("Only one device can use stdio, not the {}; use a TCP port instead", self.stdio_devices.join(" and the ")));
+        }
+
+        let mut wiring = Wiring::with_roms(self.roms);
+        if self.profile.keyboard {
+            (wiring.keyboard, wiring.keymap) = (self.keyboard.take(), self.keymap.take());
+        }
+        if let Some(backend) = self.keyboard {
+            let keymap = self.keymap.unwrap_or_default();
+            self.devices.push(Box::new(Keyboard::create("keyboard", keyboard::KEYBOARD_BASE_DEFAULT, Some(backend), keymap)));
+        }
+        if self.dma {
+            self.devices.push(Box::new(Dma::create("dma", dma::DMA_BASE_DEFAULT)?));
+        }
+        let ranges = self.devices.iter().map(|device| (device.name(), device.range())).collect::<Vec<_>>();
+        for (i, (name, range)) in ranges.iter().enumerate() {
+            if let Some((other, _)) = ranges[i + 1..].iter().find(|(_, other)| other.start() <= range.end() && range.start() <= other.end()) {
+                return Err(format!("Devices {name} and {other} overlap at ${:04X}-${:04X}", range.start(), range.end()));
+            }
+        }
+
+        let mut machine = Machine::create(self.profile, wiring)?;
+        let Machine { cpu, mem, .. } = &mut machine;
+        for device in self.devices {
+            mem.attach_device(device);
+        }
+        if let Some(variant) = self.model {
+            cpu.set_variant(variant);
+        }
+        cpu.warm_reset(mem);
+        mem.set_uninit_policy(self.uninit_policy);
+        cpu.set_stack_check(self.stack_check);
+        if let Some(dir) = &self.kernal_dir {
+            Kernal::create(Box::new(io::stdout()), Box::new(BufReader::new(io::stdin())), dir).install(cpu);
+        }
+
+        for contents in &self.contents {
+            for segment in &contents.segments {
+                for (addr, byte) in (segment.addr..=0xFFFF).zip(&segment.bytes) {
+                    mem.write_u8(addr, *byte);
+                }
+            }
+            let sets_reset_vector = contents.segments.iter()
+                .any(|segment| (segment.addr as usize..segment.addr as usize + segment.bytes.len()).contains(&(cpu::VECTOR_RES as usize)));
+            if let Some(origin) = contents.origin.filter(|_| !sets_reset_vector) {
+                mem.write_u16(cpu::VECTOR_RES, origin);
+            }
+            if contents.origin.is_some() {
+                cpu.pc = mem.read_u16(cpu::VECTOR_RES);
+            }
+        }
+        for (addr, name) in self.symbols.iter() {
+            mem.symbols_mut().add(name, addr);
+        }
+        if let Some(rom) = self.banked_rom {
+            mem.map_banked_rom(rom);
+        }
+        // after mapping the ROM as only the selected bank is saved
+        if let Some(filename) = &self.resume_file {
+            state::load_from_file(cpu, mem, filename).map_err(|error| format!("{filename}: {error}"))?;
+        }
+        if self.demo {
+            mem.demo();
+        }
+
+        if self.heatmap {
+            mem.enable_heatmap();
+        }
+        if self.profiling {
+            cpu.enable_profile();
+        }
+        if self.stats {
+            cpu.enable_stats();
+        }
+        if self.coverage {
+            cpu.enable_coverage();
+        }
+        if let Some((output, color)) = self.dump {
+            cpu.set_dump_output(output, color);
+        }
+        if let Some(trace) = self.trace {
+            cpu.start_trace(trace);
+        }
+        if let Some(mut writer) = self.bus_trace {
+            cpu.on_bus_cycle(move |cycle| if let Err(error) = writeln!(writer, "{cycle}") {
+                log::error!("Error writing bus trace: {error}");
+            });
+        }
+        if let Some(comparison) = self.trace_comparison {
+            cpu.start_trace_comparison(comparison);
+        }
+        if let Some(log) = self.input_replay {
+            cpu.replay_inputs(log);
+        }
+        if self.input_recording {
+            cpu.enable_input_recording();
+        }
+        if self.screen.is_some() {
+            machine.screen = self.screen;
+        }
+
+        let mut emulator = Emulator::create(machine);
+        for addr in self.breakpoints {
+            emulator.add_breakpoint(addr);
+        }
+        Ok(emulator)
+    }
+}
+
+impl Default for EmulatorBuilder {
+    fn default() -> Self {
+        Self::create()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::asm;
+    use crate::cpu::StopReason;
+    use crate::mem::ADDR_RESET_VECTOR;
+
+    use super::*;
+
+    #[test]
+    fn build() {
+        // E000 LDX #$03, E002 DEX, E003 BNE $E002, E005 BRA $E009, E007 STX $10, E009 STZ $10
+        let mut emulator = EmulatorBuilder::create()
+            .model(Variant::Cmos65C02)
+            .via()
+            .load(ADDR_RESET_VECTOR, &[0xA2, 0x03, 0xCA, 0xD0, 0xFD, 0x80, 0x02, 0x86, 0x10, 0x64, 0x10]).unwrap()
+            .breakpoint(0xE009)
+            .build()
+            .unwrap();
+        assert_eq!((emulator.cpu().pc, emulator.cpu().variant()), (ADDR_RESET_VECTOR, Variant::Cmos65C02));
+        assert!(emulator.mem().device::<Via>("via").is_some());
+        assert_eq!(emulator.run(1000), Some(StopReason::Breakpoint { id: 1, pc: 0xE009 }));
+        assert_eq!(emulator.cpu().x, 0x00);
+
+        // a program starts at its origin, with its labels
+        let program = asm::assemble(".org $0300\nstart: JMP start\n").unwrap();
+        let emulator = EmulatorBuilder::create().program(&program).build().unwrap();
+        assert_eq!((emulator.cpu().pc, emulator.mem().symbols().lookup("start")), (0x0300, Some(0x0300)));
+
+        let overlapping = EmulatorBuilder::create().via().lcd(16, 2, LcdConnection::Via).build();
+        assert_eq!(overlapping.err(), Some(String::from("Devices via and lcd overlap at $6000-$600F")));
+        assert!(EmulatorBuilder::create().load(0xFFFF, &[0x01, 0x02]).is_err());
+        let emulator = EmulatorBuilder::create().load(0xFFFC, &[0x00, 0x03, 0x01, 0x03]).unwrap().build().unwrap();
+        assert_eq!((emulator.mem().read_u16(0xFFFC), emulator.mem().read_u16(0xFFFE)), (0x0300, 0x0301));
+    }
+
+    #[test]
+    fn instrumentation() {
+        // E000 LDA #$42, E002 STA $10
+        let filename = std::env::temp_dir().join(format!("rust-6502-emu-builder-{}.trace", std::process::id()));
+        let mut emulator = EmulatorBuilder::create()
+            .load(ADDR_RESET_VECTOR, &[0xA9, 0x42, 0x85, 0x10]).unwrap()
+            .screen(0x0400, 40, 25, Charset::Ascii).unwrap()
+            .heatmap()
+            .stats()
+            .trace_file(filename.to_str().unwrap(), TraceFormat::Plain, Vec::new()).unwrap()
+            .build()
+            .unwrap();
+        emulator.run(5);
+        assert!(emulator.stop_traces().is_none());
+        let trace = fs::read_to_string(&filename).unwrap();
+        fs::remove_file(&filename).unwrap();
+        assert_eq!(trace.lines().count(), 2);
+        assert!(trace.starts_with("E000  A9 42     LDA #$42"));
+        assert_eq!(emulator.mem().heatmap().map(|heatmap| heatmap.entry(0x0010).writes), Some(1));
+        assert_eq!(emulator.cpu().stats().map(|stats| stats.instructions()), Some(2));
+        assert_eq!(emulator.take_screen().map(|screen| screen.rows), Some(25));
+
+        let missing = EmulatorBuilder::create().compare_trace("/nonexistent/reference.trace");
+        assert!(missing.is_err_and(|error| error.starts_with("/nonexistent/reference.trace: ")));
+    }
+}
@@ -0,0 +1,416 @@
+// Bus activity cycle by cycle, as a logic analyzer on a real 6502 would show it: the address and data bus, the R/W
+// line and SYNC (high while the opcode is fetched)
+//
+// The CPU executes whole instructions, so the cycles are reconstructed from the state before an instruction or
+// interrupt entry following the NMOS sequences, including the dummy reads and writes. Memory is peeked, so reads
+// return what they would return without their side effects. The cycles match those the emulator counts: indexed
+// reads crossing a page, which take an extra cycle on the real chip, are shown without it. The instructions of the 65C02 follow the same pattern; its additional cycles of
+// JMP (ind), of ADC and SBC in decimal mode and of the NOP $5C are shown as dummy reads.
+//
+//    CYCLE  ADDR  DATA  R/W  SYNC
+//        7  E000  20    R    SYNC  opcode
+//        8  E001  00    R          address low
+
+use core::fmt;
+
+use crate::cpu::{Cpu, Interrupt, StatusFlags, Variant, STACK_BASE, VECTOR_IRQ, VECTOR_NMI, ZERO_PAGE_BASE};
+use crate::instruction::{AddressingMode, Instruction, Mnemonic};
+use crate::mem::Memory;
+use crate::prelude::*;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BusCycle {
+    pub cycle: u64,
+    pub addr: u16,
+    pub data: u8,
+    pub write: bool,        // R/W low
+    pub sync: bool,
+    pub activity: &'static str,     // what the cycle is for, e.g. "operand" or "dummy read"
+}
+
+impl fmt::Display for BusCycle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:>9}  {:04X}  {:02X}    {}    {}  {}", self.cycle, self.addr, self.data, if self.write { 'W' } else { 'R' }, if self.sync { "SYNC" } else { "    " }, self.activity)
+    }
+}
+
+// receives the cycles while tracing, see `Cpu::on_bus_cycle`
+pub type BusCallback = Box<dyn FnMut(&BusCycle)>;
+
+pub const HEADER: &str = "    CYCLE  ADDR  DATA  R/W  SYNC";
+
+// collects the cycles of one instruction or interrupt entry
+struct Cycles<'a> {
+    mem: &'a Memory,
+    cycle: u64,
+    cycles: Vec<BusCycle>,
+}
+
+impl<'a> Cycles<'a> {
+    fn create(mem: &'a Memory, cycle: u64) -> Self {
+        Self { mem, cycle, cycles: Vec::new() }
+    }
+
+    fn push(&mut self, addr: u16, data: u8, write: bool, activity: &'static str) {
+        let sync = self.cycles.is_empty();
+        self.cycles.push(BusCycle { cycle: self.cycle + self.cycles.len() as u64, addr, data, write, sync, activity });
+    }
+
+    fn read(&mut self, addr: u16, activity: &'static str) -> u8 {
+        let data = self.mem.peek(addr);
+        self.push(addr, data, false, activity);
+        data
+    }
+
+    fn write(&mut self, addr: u16, data: u8, activity: &'static str) {
+        self.push(addr, data, true, activity);
+    }
+}
+
+// cycles of the instruction at PC
+pub fn instruction_cycles(cpu: &Cpu, mem: &Memory) -> Vec<BusCycle> {
+    let mut bus = Cycles::create(mem, cpu.cycles);
+    let pc = cpu.pc;
+    let cmos = cpu.variant() == Variant::Cmos65C02;
+    let Some(ins) = Instruction::decode(bus.read(pc, "opcode"), cmos) else {
+        return bus.cycles;
+    };
+    if ins.cycles == 1 {
+        return bus.cycles;     // NOP of the 65C02 for an unassigned opcode
+    }
+    let next = pc.wrapping_add(1);
+    let stack = |offset: u8| STACK_BASE | cpu.sp.wrapping_add(offset) as u16;
+    let sr = cpu.sr.union(StatusFlags::RESERVED);
+
+    // stack and flow control
+    match ins.mnemonic {
+        Mnemonic::BRK => {
+            let ret = pc.wrapping_add(2);
+            bus.read(next, "dummy read");
+            bus.write(stack(0), (ret >> 8) as u8, "push PC high");
+            bus.write(stack(0xFF), ret as u8, "push PC low");
+            bus.write(stack(0xFE), cpu.sr.union(StatusFlags::B).bits(), "push status");
+            bus.read(VECTOR_IRQ, "vector low");
+            bus.read(VECTOR_IRQ + 1, "vector high");
+            return bus.cycles;
+        },
+        Mnemonic::JSR => {
+            let ret = pc.wrapping_add(2);
+            bus.read(next, "address low");
+            bus.read(stack(0), "dummy read");
+            bus.write(stack(0), (ret >> 8) as u8, "push PC high");
+            bus.write(stack(0xFF), ret as u8, "push PC low");
+            bus.read(ret, "address high");
+            return bus.cycles;
+        },
+        Mnemonic::RTS => {
+            bus.read(next, "dummy read");
+            bus.read(stack(0), "dummy read");
+            let low = bus.read(stack(1), "pull PC low");
+            let high = bus.read(stack(2), "pull PC high");
+            bus.read(u16::from_le_bytes([low, high]), "dummy read");
+            return bus.cycles;
+        },
+        Mnemonic::RTI => {
+            bus.read(next, "dummy read");
+            bus.read(stack(0), "dummy read");
+            bus.read(stack(1), "pull status");
+            bus.read(stack(2), "pull PC low");
+            bus.read(stack(3), "pull PC high");
+            return bus.cycles;
+        },
+        Mnemonic::PHA | Mnemonic::PHP | Mnemonic::PHX | Mnemonic::PHY => {
+            bus.read(next, "dummy read");
+            let value = match ins.mnemonic {
+                Mnemonic::PHA => cpu.ac,
+                Mnemonic::PHX => cpu.x,
+                Mnemonic::PHY => cpu.y,
+                _ => sr.union(StatusFlags::B).bits(),
+            };
+            bus.write(stack(0), value, "push");
+            return bus.cycles;
+        },
+        Mnemonic::PLA | Mnemonic::PLP | Mnemonic::PLX | Mnemonic::PLY => {
+            bus.read(next, "dummy read");
+            bus.read(stack(0), "dummy read");
+            bus.read(stack(1), "pull");
+            return bus.cycles;
+        },
+        _ => {},
+    }
+
+    let write = matches!(ins.mnemonic, Mnemonic::STA | Mnemonic::STX | Mnemonic::STY | Mnemonic::STZ);
+    let modify = matches!(ins.mnemonic, Mnemonic::ASL | Mnemonic::LSR | Mnemonic::ROL | Mnemonic::ROR | Mnemonic::INC | Mnemonic::DEC
+        | Mnemonic::TRB | Mnemonic::TSB);
+    let decimal = cmos && cpu.sr.contains(StatusFlags::D) && matches!(ins.mnemonic, Mnemonic::ADC | Mnemonic::SBC);
+
+    // effective address, after the cycles computing it
+    let addr = match ins.addr_mode {
+        AddressingMode::IMP | AddressingMode::ACC => {
+            bus.read(next, "dummy read");
+            return bus.cycles;
+        },
+        AddressingMode::IMM => {
+            bus.read(next, "operand");
+            if decimal {
+                bus.read(next, "dummy read");
+            }
+            return bus.cycles;
+        },
+        AddressingMode::REL => {
+            let offset = bus.read(next, "operand") as i8;
+            let taken = match ins.mnemonic {
+                Mnemonic::BCC => !cpu.sr.contains(StatusFlags::C),
+                Mnemonic::BCS => cpu.sr.contains(StatusFlags::C),
+                Mnemonic::BNE => !cpu.sr.contains(StatusFlags::Z),
+                Mnemonic::BEQ => cpu.sr.contains(StatusFlags::Z),
+                Mnemonic::BPL => !cpu.sr.contains(StatusFlags::N),
+                Mnemonic::BMI => cpu.sr.contains(StatusFlags::N),
+                Mnemonic::BVC => !cpu.sr.contains(StatusFlags::V),
+                Mnemonic::BVS => cpu.sr.contains(StatusFlags::V),
+                _ => true,
+            };
+            if taken {
+                let from = pc.wrapping_add(2);
+                let target = from.wrapping_add(offset as u16);
+                bus.read(from, "dummy read");
+                if target >> 8 != from >> 8 {
+                    bus.read((from & 0xFF00) | (target & 0x00FF), "dummy read");
+                }
+            }
+            return bus.cycles;
+        },
+        AddressingMode::ZPG => ZERO_PAGE_BASE | bus.read(next, "address") as u16,
+        AddressingMode::ZPX | AddressingMode::ZPY => {
+            let base = bus.read(next, "address");
+            bus.read(ZERO_PAGE_BASE | base as u16, "dummy read");
+            let index = if ins.addr_mode == AddressingMode::ZPX { cpu.x } else { cpu.y };
+            ZERO_PAGE_BASE | base.wrapping_add(index) as u16
+        },
+        AddressingMode::ABS | AddressingMode::ABX | AddressingMode::ABY | AddressingMode::IND | AddressingMode::IAX => {
+            let low = bus.read(next, "address low");
+            let high = bus.read(pc.wrapping_add(2), "address high");
+            let base = u16::from_le_bytes([low, high]);
+            match ins.addr_mode {
+                AddressingMode::ABS if ins.mnemonic == Mnemonic::JMP => return bus.cycles,
+                AddressingMode::ABS => base,
+                AddressingMode::IND | AddressingMode::IAX => {
+                    let pointer = if ins.addr_mode == AddressingMode::IAX { base.wrapping_add(cpu.x as u16) } else { base };
+                    if cmos {
+                        bus.read(pc.wrapping_add(2), "dummy read");
+                    }
+                    // JMP ($xxFF) of the NMOS 6502 reads its high byte from $xx00
+                    let pointer_high = match ins.addr_mode == AddressingMode::IAX || cmos {
+                        true => pointer.wrapping_add(1),
+                        false => (pointer & 0xFF00) | (pointer.wrapping_add(1) & 0x00FF),
+                    };
+                    bus.read(pointer, "pointer low");
+                    bus.read(pointer_high, "pointer high");
+                    return bus.cycles;
+                },
+                _ => {
+                    let index = if ins.addr_mode == AddressingMode::ABX { cpu.x } else { cpu.y };
+                    let addr = base.wrapping_add(index as u16);
+                    if write || modify {
+                        bus.read((base & 0xFF00) | (addr & 0x00FF), "dummy read");
+                    }
+                    addr
+                },
+            }
+        },
+        AddressingMode::IDX => {
+            let base = bus.read(next, "pointer");
+            bus.read(ZERO_PAGE_BASE | base as u16, "dummy read");
+            let pointer = base.wrapping_add(cpu.x);
+            let low = bus.read(ZERO_PAGE_BASE | pointer as u16, "address low");
+            let high = bus.read(ZERO_PAGE_BASE | pointer.wrapping_add(1) as u16, "address high");
+            u16::from_le_bytes([low, high])
+        },
+        AddressingMode::IDY => {
+            let pointer = bus.read(next, "pointer");
+            let low = bus.read(ZERO_PAGE_BASE | pointer as u16, "address low");
+            let high = bus.read(ZERO_PAGE_BASE | pointer.wrapping_add(1) as u16, "address high");
+            let base = u16::from_le_bytes([low, high]);
+            let addr = base.wrapping_add(cpu.y as u16);
+            if write {
+                bus.read((base & 0xFF00) | (addr & 0x00FF), "dummy read");
+            }
+            addr
+        },
+        AddressingMode::ZPI => {
+            let pointer = bus.read(next, "pointer");
+            let low = bus.read(ZERO_PAGE_BASE | pointer as u16, "address low");
+            let high = bus.read(ZERO_PAGE_BASE | pointer.wrapping_add(1) as u16, "address high");
+            u16::from_le_bytes([low, high])
+        },
+    };
+
+    if write {
+        let value = match ins.mnemonic {
+            Mnemonic::STA => cpu.ac,
+            Mnemonic::STX => cpu.x,
+            Mnemonic::STY => cpu.y,
+            _ => 0,
+        };
+        bus.write(addr, value, "data");
+    } else if modify {
+        // the unmodified value is written back while the new one is computed
+        let value = bus.read(addr, "data");
+        let carry = cpu.sr.contains(StatusFlags::C) as u8;
+        let result = match ins.mnemonic {
+            Mnemonic::ASL => value << 1,
+            Mnemonic::LSR => value >> 1,
+            Mnemonic::ROL => value << 1 | carry,
+            Mnemonic::ROR => value >> 1 | carry << 7,
+            Mnemonic::INC => value.wrapping_add(1),
+            Mnemonic::DEC => value.wrapping_sub(1),
+            Mnemonic::TRB => value & !cpu.ac,
+            _ => value | cpu.ac,
+        };
+        bus.write(addr, value, "dummy write");
+        bus.write(addr, result, "data");
+    } else {
+        bus.read(addr, "data");
+        if decimal {
+            bus.read(addr, "dummy read");
+        }
+    }
+
+    // the 65C02 takes 8 cycles for the NOP $5C
+    while ins.mnemonic == Mnemonic::NOP && bus.cycles.len() < ins.cycles as usize {
+        bus.read(addr, "dummy read");
+    }
+    bus.cycles
+}
+
+// cycles of entering the handler of an IRQ or NMI instead of the instruction at PC
+pub fn interrupt_cycles(cpu: &Cpu, mem: &Memory, interrupt: Interrupt) -> Vec<BusCycle> {
+    let mut bus = Cycles::create(mem, cpu.cycles);
+    let stack = |offset: u8| STACK_BASE | cpu.sp.wrapping_add(offset) as u16;
+    let vector = if interrupt == Interrupt::Nmi { VECTOR_NMI } else { VECTOR_IRQ };
+    bus.read(cpu.pc, "opcode, ignored");
+    bus.read(cpu.pc, "dummy read");
+    bus.write(stack(0), (cpu.pc >> 8) as u8, "push PC high");
+    bus.write(stack(0xFF), cpu.pc as u8, "push PC low");
+    bus.write(stack(0xFE), cpu.sr.difference(StatusFlags::B).union(StatusFlags::RESERVED).bits(), "push status");
+    bus.read(vector, "vector low");
+    bus.read(vector + 1, "vector high");
+    bus.cycles
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::asm;
+    use crate::instruction::Opcode;
+    use crate::mem::ADDR_RESET_VECTOR;
+
+    use super::*;
+
+    fn setup(source: &str) -> (Cpu, Memory) {
+        let (cpu, mut mem) = crate::cpu::tests::setup();
+        asm::assemble(source).unwrap().write_to(&mut mem);
+        (cpu, mem)
+    }
+
+    #[test]
+    fn sequences() {
+        let (mut cpu, mut mem) = setup("
+                .org $E000
+                JSR sub
+                INC $10FF,X
+                BNE $E000
+        sub:    RTS
+        ");
+        cpu.x = 1;
+        mem.write_u8(0x1100, 0x41);
+
+        let lines = |cycles: Vec<BusCycle>| cycles.iter().map(|cycle| cycle.to_string()).collect::<Vec<String>>();
+        assert_eq!(lines(instruction_cycles(&cpu, &mem)), vec![
+            "        7  E000  20    R    SYNC  opcode",
+            "        8  E001  08    R          address low",
+            "        9  01FD  00    R          dummy read",
+            "       10  01FD  E0    W          push PC high",
+            "       11  01FC  02    W          push PC low",
+            "       12  E002  E0    R          address high",
+        ]);
+        cpu.exec(&mut mem, 1);
+        assert_eq!(instruction_cycles(&cpu, &mem).iter().map(|cycle| (cycle.addr, cycle.data, cycle.write)).collect::<Vec<_>>(), vec![
+            (0xE008, 0x60, false), (0xE009, 0x00, false), (0x01FB, 0x00, false), (0x01FC, 0x02, false), (0x01FD, 0xE0, false), (0xE002, 0xE0, false),
+        ]);
+        cpu.exec(&mut mem, 1);
+
+        // the dummy read of the absolute,X read-modify-write before the page is fixed up, then the value written twice
+        assert_eq!(lines(instruction_cycles(&cpu, &mem))[3..], [
+            "       22  1000  00    R          dummy read",
+            "       23  1100  41    R          data",
+            "       24  1100  41    W          dummy write",
+            "       25  1100  42    W          data",
+        ]);
+        cpu.exec(&mut mem, 1);
+
+        // a branch taken, reading the next opcode while adding the offset
+        assert_eq!(instruction_cycles(&cpu, &mem).iter().map(|cycle| cycle.addr).collect::<Vec<u16>>(), vec![0xE006, 0xE007, 0xE008]);
+        let entry = interrupt_cycles(&cpu, &mem, Interrupt::Nmi);
+        assert_eq!((entry[0].sync, entry[4].data, entry[6].addr), (true, 0x20, VECTOR_NMI + 1));
+    }
+
+    #[test]
+    fn cmos() {
+        // E000 PHX, E001 STZ $10 on the 65C02
+        let (mut cpu, mut mem) = setup(".org $E000\n.byte $DA, $64, $10");
+        cpu.set_variant(Variant::Cmos65C02);
+        cpu.x = 0x42;
+
+        let cycles = |cpu: &Cpu, mem: &Memory| instruction_cycles(cpu, mem).iter().map(|cycle| (cycle.addr, cycle.data, cycle.write)).collect::<Vec<_>>();
+        assert_eq!(cycles(&cpu, &mem), vec![(0xE000, 0xDA, false), (0xE001, 0x64, false), (0x01FD, 0x42, true)]);
+        cpu.exec(&mut mem, 1);
+        assert_eq!(cycles(&cpu, &mem), vec![(0xE001, 0x64, false), (0xE002, 0x10, false), (0x0010, 0x00, true)]);
+    }
+
+    #[test]
+    fn jmp_indirect() {
+        // the pointer's high byte does not carry into its page, except on the 65C02 taking a cycle more
+        let (mut cpu, mem) = setup(".org $E000\nJMP ($02FF)");
+        let addrs = |cpu: &Cpu| instruction_cycles(cpu, &mem).iter().map(|cycle| cycle.addr).collect::<Vec<u16>>();
+        assert_eq!(addrs(&cpu), vec![0xE000, 0xE001, 0xE002, 0x02FF, 0x0200]);
+        cpu.set_variant(Variant::Cmos65C02);
+        assert_eq!(addrs(&cpu), vec![0xE000, 0xE001, 0xE002, 0xE002, 0x02FF, 0x0300]);
+    }
+
+    #[test]
+    fn cycle_counts() {
+        // as many cycles as the emulator counts for every instruction, with branches taken or not; on the 65C02 also
+        // in decimal mode
+        let variants = [(Variant::Nmos6502, StatusFlags::all().difference(StatusFlags::D)), (Variant::Cmos65C02, StatusFlags::all())];
+        for (variant, flags) in variants {
+            for opcode in (0..=0xFF).filter_map(|byte| Opcode::decode(byte, variant == Variant::Cmos65C02)) {
+                for sr in [StatusFlags::empty(), flags] {
+                    let mut mem = Memory::create();
+                    let mut cpu = Cpu::create();
+                    cpu.set_variant(variant);
+                    cpu.reset(&mut mem);
+                    (cpu.sr, cpu.ac) = (sr, 0xFF);
+                    mem.write_u8(ADDR_RESET_VECTOR, opcode.into());
+                    mem.write_u16(None, 0x80F0);
+                    let cycles = instruction_cycles(&cpu, &mem);
+                    let before = cpu.cycles;
+                    cpu.exec(&mut mem, 1);
+                    assert_eq!(cycles.len() as u64, cpu.cycles - before, "{variant:?} {opcode:?}");
+                }
+            }
+        }
+
+        // cycles of the first instruction when tracing the bus
+        let (mut cpu, mut mem) = setup(".org $E000\nLDA #1");
+        let traced = Rc::new(RefCell::new(Vec::new()));
+        let cycles = Rc::clone(&traced);
+        cpu.on_bus_cycle(move |cycle| cycles.borrow_mut().push(*cycle));
+        cpu.exec(&mut mem, 1);
+        cpu.stop_bus_trace();
+        cpu.exec(&mut mem, 1);
+        assert_eq!(traced.borrow().iter().map(|cycle| (cycle.addr, cycle.sync)).collect::<Vec<_>>(), vec![(0xE000, true), (0xE001, false)]);
+    }
+}
@@ -0,0 +1,459 @@
+// Commodore 64 (PAL): 64K RAM with the BASIC, KERNAL and character ROMs and the I/O chips banked in above $A000 by
+// the processor port at $0000 (DDR) and $0001 (bit 0 LORAM, bit 1 HIRAM, bit 2 CHAREN; inputs are pulled up):
+//
+//  $A000-$BFFF  BASIC if LORAM and HIRAM are set, RAM otherwise
+//  $D000-$DFFF  I/O if CHAREN is set, the character ROM otherwise; RAM if neither LORAM nor HIRAM is set
+//  $E000-$FFFF  KERNAL if HIRAM is set, RAM otherwise
+//
+// Writes go to the RAM below a ROM; a ROM not given reads as that RAM. The I/O chips are stubbed as far as the
+// KERNAL needs them to boot to BASIC:
+//
+//  $D000-$D3FF  VIC-II registers (mirrored every 64 bytes); only the raster counter ($D011 bit 7, $D012) and its
+//               compare interrupt ($D019, $D01A) work, 312 lines of 63 cycles
+//  $D400-$D7FF  SID, writes are ignored
+//  $D800-$DBFF  color RAM (4 bits)
+//  $DC00-$DCFF  CIA 1: keyboard matrix (columns selected on port A, rows read on port B), IRQ
+//  $DD00-$DDFF  CIA 2: NMI
+//
+// The screen RAM at $0400 is shown in the terminal. Host keys are typed on the keyboard matrix as PETSCII.
+
+use std::cell::Cell;
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+
+use crate::acia::SerialBackend;
+use crate::cia::Cia;
+use crate::commodore::{self, Matrix, MatrixKeyboard};
+use crate::device::Device;
+use crate::keyboard::Keymap;
+use crate::machine::{Machine, Profile, RomSlot, Wiring};
+use crate::rom::RomImage;
+use crate::screen::{Charset, TextScreen};
+
+pub const SCREEN_RAM: u16 = 0x0400;
+pub const BANKED_BASE: u16 = 0xA000;
+pub const CIA1_BASE: u16 = 0xDC00;
+pub const CIA2_BASE: u16 = 0xDD00;
+
+pub const PORT_LORAM: u8 = 0b001;
+pub const PORT_HIRAM: u8 = 0b010;
+pub const PORT_CHAREN: u8 = 0b100;
+
+const PORT_INPUTS: u8 = 0x17;       // banking lines pulled up, cassette button not pressed
+
+const RASTER_LINES: u16 = 312;
+const LINE_CYCLES: u64 = 63;
+
+// columns on port A, rows on port B
+const MATRIX: Matrix = [
+    [0x14, 0x0D, 0x1D, 0x88, 0x85, 0x86, 0x87, 0x11],     // DEL RETURN CRSR-RIGHT F7 F1 F3 F5 CRSR-DOWN
+    [b'3', b'W', b'A', b'4', b'Z', b'S', b'E', 0],          // ... LEFT-SHIFT
+    [b'5', b'R', b'D', b'6', b'C', b'F', b'T', b'X'],
+    [b'7', b'Y', b'G', b'8', b'B', b'H', b'U', b'V'],
+    [b'9', b'I', b'J', b'0', b'M', b'K', b'O', b'N'],
+    [b'+', b'P', b'L', b'-', b'.', b':', b'@', b','],
+    [0x5C, b'*', b';', 0x13, 0, b'=', 0x5E, b'/'],          // POUND ... HOME RIGHT-SHIFT ... UP-ARROW
+    [b'1', 0x5F, 0, b'2', b' ', 0, b'Q', 0x03],             // ... LEFT-ARROW CTRL ... C= ... RUN/STOP
+];
+const LEFT_SHIFT: (u8, u8) = (1, 7);
+
+pub const C64: Profile = Profile {
+    name: "c64",
+    description: "Commodore 64 (PAL): 64K RAM, ROMs 'basic' (8K), 'kernal' (8K) and 'char' (4K) banked by $01, CIAs at $DC00/$DD00, screen at $0400",
+    clock_hz: 985_248,
+    frame_cycles: RASTER_LINES as u64 * LINE_CYCLES,     // 50 Hz
+    roms: &[
+        RomSlot { name: "basic", addr: 0xA000, size: 0x2000 },
+        RomSlot { name: "kernal", addr: 0xE000, size: 0x2000 },
+        RomSlot { name: "char", addr: 0xD000, size: 0x1000 },
+    ],
+    keyboard: true,
+    wire,
+};
+
+fn wire(machine: &mut Machine, wiring: &mut Wiring) -> Result<(), String> {
+    let lines = Rc::new(Cell::new(PORT_LORAM | PORT_HIRAM | PORT_CHAREN));
+    let keymap = wiring.keymap.take().unwrap_or_else(commodore::keymap);
+    let mut bus = C64Bus::create(lines.clone(), wiring.keyboard.take(), keymap);
+    (bus.basic, bus.kernal, bus.character) = (wiring.take_rom("basic"), wiring.take_rom("kernal"), wiring.take_rom("char"));
+    machine.attach(Box::new(CpuPort::create(lines)));
+    machine.attach(Box::new(bus));
+    machine.screen = Some(TextScreen::create(SCREEN_RAM, 40, 25, Charset::Petscii)?);
+    Ok(())
+}
+
+// the processor port, whose banking lines select what the bus maps above $A000
+pub struct CpuPort {
+    ddr: u8,
+    port: u8,
+    lines: Rc<Cell<u8>>,
+}
+
+impl CpuPort {
+    pub fn create(lines: Rc<Cell<u8>>) -> Self {
+        Self { ddr: 0, port: 0, lines }
+    }
+
+    fn update(&mut self) {
+        self.lines.set((self.port | !self.ddr) & (PORT_LORAM | PORT_HIRAM | PORT_CHAREN));
+    }
+}
+
+impl Device for CpuPort {
+    fn name(&self) -> &str {
+        "c64-port"
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        0x0000..=0x0001
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        self.peek(addr)
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0 => self.ddr,
+            _ => (self.port & self.ddr) | (PORT_INPUTS & !self.ddr),
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0 => self.ddr = value,
+            _ => self.port = value,
+        }
+        self.update();
+    }
+
+    fn reset(&mut self) {
+        (self.ddr, self.port) = (0, 0);
+        self.update();
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Layer {
+    Ram,
+    Basic,
+    Kernal,
+    Character,
+    Io,
+}
+
+// VIC-II: registers stored as written, but for the raster counter and its interrupt
+struct Vic {
+    regs: [u8; 64],
+    raster: u16,
+    cycles: u64,            // into the current line
+    flags: u8,
+}
+
+impl Vic {
+    fn create() -> Self {
+        Self { regs: [0; 64], raster: 0, cycles: 0, flags: 0 }
+    }
+
+    fn compare(&self) -> u16 {
+        (self.regs[0x11] as u16 & 0x80) << 1 | self.regs[0x12] as u16
+    }
+
+    fn irq(&self) -> bool {
+        self.flags & self.regs[0x1A] & 0x0F != 0
+    }
+
+    fn peek(&self, reg: usize) -> u8 {
+        match reg {
+            0x11 => (self.regs[0x11] & 0x7F) | ((self.raster >> 1) & 0x80) as u8,
+            0x12 => self.raster as u8,
+            0x19 => self.flags | 0x70 | if self.irq() { 0x80 } else { 0 },
+            0x1A => self.regs[0x1A] | 0xF0,
+            0x2F.. => 0xFF,
+            _ => self.regs[reg],
+        }
+    }
+
+    fn write(&mut self, reg: usize, value: u8) {
+        match reg {
+            0x19 => self.flags &= !value,
+            _ => self.regs[reg] = value,
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.cycles += cycles;
+        while self.cycles >= LINE_CYCLES {
+            self.cycles -= LINE_CYCLES;
+            self.raster = (self.raster + 1) % RASTER_LINES;
+            if self.raster == self.compare() {
+                self.flags |= 0x01;
+            }
+        }
+    }
+}
+
+// everything mapped from $A000 up: the RAM there, the ROMs and the I/O chips
+pub struct C64Bus {
+    lines: Rc<Cell<u8>>,
+    ram: Vec<u8>,
+    pub basic: Option<RomImage>,
+    pub kernal: Option<RomImage>,
+    pub character: Option<RomImage>,
+    vic: Vic,
+    color: [u8; 0x400],
+    cia1: Cia,
+    cia2: Cia,
+    keyboard: MatrixKeyboard,
+}
+
+impl C64Bus {
+    pub fn create(lines: Rc<Cell<u8>>, backend: Option<Box<dyn SerialBackend>>, keymap: Keymap) -> Self {
+        Self {
+            lines,
+            ram: vec![0; 0x10000 - BANKED_BASE as usize],
+            basic: None,
+            kernal: None,
+            character: None,
+            vic: Vic::create(),
+            color: [0; 0x400],
+            cia1: Cia::create("cia1", CIA1_BASE),
+            cia2: Cia::create("cia2", CIA2_BASE),
+            keyboard: MatrixKeyboard::create(backend, keymap, &MATRIX, LEFT_SHIFT),
+        }
+    }
+
+    pub fn press(&mut self, host: u8) {
+        self.keyboard.press(host);
+    }
+
+    pub fn cia1(&self) -> &Cia {
+        &self.cia1
+    }
+
+    pub fn cia2(&self) -> &Cia {
+        &self.cia2
+    }
+
+    fn layer(&self, addr: u16) -> Layer {
+        let lines = self.lines.get();
+        let (loram, hiram) = (lines & PORT_LORAM != 0, lines & PORT_HIRAM != 0);
+        match addr {
+            0xA000..=0xBFFF if loram && hiram && self.basic.is_some() => Layer::Basic,
+            0xD000..=0xDFFF if lines & PORT_CHAREN != 0 && (loram || hiram) => Layer::Io,
+            0xD000..=0xDFFF if (loram || hiram) && self.character.is_some() => Layer::Character,
+            0xE000..=0xFFFF if hiram && self.kernal.is_some() => Layer::Kernal,
+            _ => Layer::Ram,
+        }
+    }
+
+    fn peek_io(&self, addr: u16) -> u8 {
+        match addr {
+            0xD000..=0xD3FF => self.vic.peek((addr % 64) as usize),
+            0xD800..=0xDBFF => self.color[(addr - 0xD800) as usize],
+            0xDC00..=0xDCFF => self.cia1.peek(addr),
+            0xDD00..=0xDDFF => self.cia2.peek(addr),
+            _ => 0,
+        }
+    }
+
+    fn scan(&mut self) {
+        let rows = self.keyboard.rows(self.cia1.port_a());
+        self.cia1.set_port_b(rows);
+    }
+}
+
+impl Device for C64Bus {
+    fn name(&self) -> &str {
+        "c64-bus"
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        BANKED_BASE..=0xFFFF
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        match (self.layer(addr), addr) {
+            (Layer::Io, 0xDC00..=0xDCFF) => self.cia1.read(addr),
+            (Layer::Io, 0xDD00..=0xDDFF) => self.cia2.read(addr),
+            _ => self.peek(addr),
+        }
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        let rom = |image: &Option<RomImage>, base: u16| image.as_ref().map_or(0, |image| image.read((addr - base) as usize));
+        match self.layer(addr) {
+            Layer::Ram => self.ram[(addr - BANKED_BASE) as usize],
+            Layer::Basic => rom(&self.basic, 0xA000),
+            Layer::Kernal => rom(&self.kernal, 0xE000),
+            Layer::Character => rom(&self.character, 0xD000),
+            Layer::Io => self.peek_io(addr),
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        if self.layer(addr) != Layer::Io {
+            self.ram[(addr - BANKED_BASE) as usize] = value;
+            return;
+        }
+        match addr {
+            0xD000..=0xD3FF => self.vic.write((addr % 64) as usize, value),
+            0xD800..=0xDBFF => self.color[(addr - 0xD800) as usize] = value & 0x0F,
+            0xDC00..=0xDCFF => {
+                self.cia1.write(addr, value);
+                self.scan();
+            },
+            0xDD00..=0xDDFF => self.cia2.write(addr, value),
+            _ => {},
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.vic.tick(cycles);
+        self.cia1.tick(cycles);
+        self.cia2.tick(cycles);
+        self.keyboard.tick(cycles);
+        self.scan();
+    }
+
+    fn irq(&self) -> bool {
+        self.vic.irq() || self.cia1.irq()
+    }
+
+    fn nmi(&self) -> bool {
+        self.cia2.irq()
+    }
+
+    // the CIAs are reset, RAM, the ROMs and the VIC-II (not wired to the reset line) are kept
+    fn reset(&mut self) {
+        self.cia1.reset();
+        self.cia2.reset();
+        self.keyboard.reset();
+        self.scan();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::asm;
+
+    use super::*;
+
+    fn rom_file(name: &str, size: usize, fill: u8) -> String {
+        let filename = std::env::temp_dir().join(format!("rust-6502-emu-c64-{name}-{}.rom", std::process::id()));
+        let mut image = vec![fill; size];
+        if name == "kernal" {
+            image[0x1FFC..].copy_from_slice(&[0x00, 0xE0, 0x00, 0x00]);      // reset to $E000
+        }
+        std::fs::write(&filename, &image).unwrap();
+        String::from(filename.to_str().unwrap())
+    }
+
+    #[test]
+    fn banking() {
+        let roms: Vec<_> = [("basic", 0x2000, 0xBA), ("kernal", 0x2000, 0xEA), ("char", 0x1000, 0xCC)].iter()
+            .map(|&(name, size, fill)| (String::from(name), rom_file(name, size, fill)))
+            .collect();
+        let mut machine = Machine::create(&C64, Wiring::with_roms(roms.clone())).unwrap();
+        for (_, filename) in roms {
+            std::fs::remove_file(filename).unwrap();
+        }
+        assert_eq!(machine.cpu.pc, 0xE000);
+
+        // writes go to the RAM below the ROMs
+        let mem = &mut machine.mem;
+        mem.write_u8(0xA000, 0x11);
+        mem.write_u8(0xE000, 0x22);
+        mem.write_u8(0xD020, 0x0E);
+        mem.write_u8(0xD800, 0xF1);
+        assert_eq!((mem.peek(0x0001), mem.peek(0xA000), mem.peek(0xE000)), (0x17, 0xBA, 0xEA));
+        assert_eq!((mem.peek(0xD020), mem.peek(0xD800)), (0x0E, 0x01));
+
+        // BASIC out, then the character ROM in, then all RAM
+        mem.write_u8(0x0000, 0x2F);
+        mem.write_u8(0x0001, 0x36);
+        assert_eq!((mem.peek(0x0001), mem.peek(0xA000), mem.peek(0xE000)), (0x36, 0x11, 0xEA));
+        mem.write_u8(0x0001, 0x32);
+        assert_eq!((mem.peek(0xA000), mem.peek(0xD020)), (0x11, 0xCC));
+        mem.write_u8(0x0001, 0x30);
+        assert_eq!((mem.peek(0xD020), mem.peek(0xE000)), (0x00, 0x22));
+
+        // the KERNAL's ROM is back after a reset
+        machine.reset();
+        assert_eq!((machine.mem.peek(0xA000), machine.mem.peek(0xD020)), (0xBA, 0x0E));
+        assert!(Machine::create(&C64, Wiring::with_roms(vec![(String::from("rom"), String::new())])).is_err());
+    }
+
+    #[test]
+    fn keyboard() {
+        let mut machine = Machine::create(&C64, Wiring::default()).unwrap();
+        let screen = machine.screen.as_ref().unwrap();
+        assert_eq!((screen.range(), screen.charset), (0x0400..=0x07E7, Charset::Petscii));
+
+        // column 1 selected, the row of A and of the left shift
+        let mem = &mut machine.mem;
+        mem.write_u8(0xDC02, 0xFF);
+        mem.write_u8(0xDC00, 0xFD);
+        mem.device_mut::<C64Bus>("c64-bus").unwrap().press(b'a');
+        assert_eq!(mem.peek(0xDC01), 0xFF);
+        mem.tick_devices(1);
+        assert_eq!(mem.peek(0xDC01), 0xFB);
+        mem.write_u8(0xDC00, 0xFB);
+        assert_eq!(mem.peek(0xDC01), 0xFF);
+
+        // released, then the next key
+        mem.device_mut::<C64Bus>("c64-bus").unwrap().press(b'"');
+        mem.tick_devices(commodore::KEY_CYCLES);
+        mem.write_u8(0xDC00, 0x7D);
+        assert_eq!(mem.peek(0xDC01), 0xFF);
+        mem.tick_devices(commodore::KEY_CYCLES);
+        assert_eq!(mem.peek(0xDC01), 0x77);
+    }
+
+    #[test]
+    fn interrupts() {
+        // RAM instead of the KERNAL; counts CIA 1 timer interrupts and VIC raster interrupts
+        let program = asm::assemble("
+                .org $C000
+        irq:    INC $02
+                LDA $DC0D
+                RTI
+        start:  LDA #$2F
+                STA $00
+                LDA #$35
+                STA $01
+                LDA #$00
+                STA $FFFE
+                LDA #$C0
+                STA $FFFF
+                LDA #99
+                STA $DC04
+                LDA #0
+                STA $DC05
+                LDA #$81
+                STA $DC0D
+                LDA #$01
+                STA $DC0E
+                CLI
+        loop:   JMP loop
+        ").unwrap();
+        let mut machine = Machine::create(&C64, Wiring::default()).unwrap();
+        program.write_to(&mut machine.mem);
+        machine.cpu.pc = 0xC006;
+        machine.run(1050);
+        assert_eq!(machine.mem.peek(0x02), 9);
+
+        // a raster interrupt at line 0, once per frame
+        let bus = machine.mem.device_mut::<C64Bus>("c64-bus").unwrap();
+        bus.write(0xDC0D, 0x01);
+        assert!(!bus.irq());
+        bus.write(0xD012, 0x00);
+        bus.write(0xD01A, 0x01);
+        let line = bus.peek(0xD012);
+        bus.tick(LINE_CYCLES * RASTER_LINES as u64);
+        assert!(bus.irq());
+        assert_eq!((bus.peek(0xD019), bus.peek(0xD012)), (0xF1, line));
+        bus.write(0xD019, 0x01);
+        assert!(!bus.irq() && !bus.nmi());
+    }
+}
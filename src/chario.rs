@@ -0,0 +1,124 @@
+// Character I/O as in simulators such as py65 and Kowalski's 6502 simulator: writing $F001 outputs a character,
+// reading $F004 returns the next input character or 0 if there is none. The other addresses read 0.
+
+use std::ops::RangeInclusive;
+
+use crate::acia::SerialBackend;
+use crate::device::Device;
+
+pub const CHARIO_BASE_DEFAULT: u16 = 0xF000;
+
+pub const REG_PUTC: u16 = 1;
+pub const REG_GETC: u16 = 4;
+
+pub struct CharIo {
+    name: String,
+    pub base: u16,
+    backend: Box<dyn SerialBackend>,
+    input: Option<u8>,      // taken from the backend while ticking, so it can be peeked
+}
+
+impl CharIo {
+    pub fn create(name: &str, base: u16, backend: Box<dyn SerialBackend>) -> Self {
+        Self {
+            name: String::from(name),
+            base,
+            backend,
+            input: None,
+        }
+    }
+}
+
+impl Device for CharIo {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        self.base + REG_PUTC..=self.base + REG_GETC
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr.wrapping_sub(self.base) {
+            REG_GETC => self.input.take().unwrap_or(0),
+            _ => 0,
+        }
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match addr.wrapping_sub(self.base) {
+            REG_GETC => self.input.unwrap_or(0),
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        if addr.wrapping_sub(self.base) == REG_PUTC {
+            self.backend.transmit(value);
+        }
+    }
+
+    fn tick(&mut self, _cycles: u64) {
+        if self.input.is_none() {
+            self.input = self.backend.receive();
+        }
+    }
+
+    fn reset(&mut self) {
+        self.input = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::asm;
+    use crate::cpu::Cpu;
+    use crate::mem::Memory;
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Console {
+        input: Rc<RefCell<Vec<u8>>>,
+        output: Rc<RefCell<Vec<u8>>>,
+    }
+
+    impl SerialBackend for Console {
+        fn receive(&mut self) -> Option<u8> {
+            self.input.borrow_mut().pop()
+        }
+
+        fn transmit(&mut self, byte: u8) {
+            self.output.borrow_mut().push(byte);
+        }
+    }
+
+    #[test]
+    fn echo() {
+        // echoes characters in upper case until a '.'
+        let program = asm::assemble("
+            .org $E000
+    loop:   LDA $F004
+            BEQ loop
+            AND #$DF
+            STA $F001
+            CMP #$0E
+            BNE loop
+            BRK
+        ").unwrap();
+        let console = Console::default();
+        console.input.borrow_mut().extend(b".ih");     // taken from the end
+
+        let mut mem = Memory::create();
+        let mut cpu = Cpu::create();
+        mem.attach_device(Box::new(CharIo::create("chario", CHARIO_BASE_DEFAULT, Box::new(console.clone()))));
+        cpu.reset(&mut mem);
+        program.write_to(&mut mem);
+        cpu.exec(&mut mem, 200);
+        assert_eq!(console.output.borrow().as_slice(), b"HI\x0E");
+        assert_eq!((mem.peek(0xF001), mem.peek(0xF004)), (0, 0));
+    }
+}
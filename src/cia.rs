@@ -0,0 +1,346 @@
+// MOS 6526 Complex Interface Adapter
+//
+// Register layout (offset from base address):
+//   +0  PRA           port A; reads output register bits where DDRA is 1 and the input pins where it is 0
+//   +1  PRB           port B
+//   +2  DDRA          data direction of port A (1 = output)
+//   +3  DDRB
+//   +4  TA LO         write: timer A latch LB; read: counter LB
+//   +5  TA HI         write: latch HB, loads the counter from the latch while timer A is stopped; read: counter HB
+//   +6  TB LO         timer B, likewise
+//   +7  TB HI
+//   +8  TOD 10THS     time of day: tenths, seconds, minutes, hours (BCD)
+//   +9  TOD SEC
+//   +A  TOD MIN
+//   +B  TOD HR
+//   +C  SDR           serial data
+//   +D  ICR           interrupt control; writes set (bit 7 = 1) or clear the given mask bits, reads return the flags
+//                     with bit 7 set while any enabled flag is and clear them
+//   +E  CRA           control A: bit 0 start, bit 3 one-shot, bit 4 (write only) loads the counter from the latch
+//   +F  CRB           control B, likewise; bits 6-5 select counting cycles (00) or timer A underflows (1x)
+//
+// Timers count down once per cycle (timer B optionally per timer A underflow), set their flag when passing zero and
+// reload from the latch, a period of N+1; in one-shot mode they stop at the underflow. The time of day clock keeps the
+// values written without running, the serial port only stores SDR and the FLAG input is not emulated.
+
+use std::io::{self, Read, Write};
+use std::ops::RangeInclusive;
+
+use crate::device::Device;
+use crate::state;
+
+pub const CIA_REGISTERS: u16 = 16;
+
+pub const REG_PRA: u16 = 0x0;
+pub const REG_PRB: u16 = 0x1;
+pub const REG_DDRA: u16 = 0x2;
+pub const REG_DDRB: u16 = 0x3;
+pub const REG_TA_LO: u16 = 0x4;
+pub const REG_TA_HI: u16 = 0x5;
+pub const REG_TB_LO: u16 = 0x6;
+pub const REG_TB_HI: u16 = 0x7;
+pub const REG_TOD_10THS: u16 = 0x8;
+pub const REG_TOD_HR: u16 = 0xB;
+pub const REG_SDR: u16 = 0xC;
+pub const REG_ICR: u16 = 0xD;
+pub const REG_CRA: u16 = 0xE;
+pub const REG_CRB: u16 = 0xF;
+
+// interrupt flags (ICR)
+pub const INT_TA: u8 = 0b00000001;
+pub const INT_TB: u8 = 0b00000010;
+pub const INT_ANY: u8 = 0b10000000;
+
+pub const CR_START: u8 = 0b00000001;
+pub const CR_ONE_SHOT: u8 = 0b00001000;
+pub const CR_LOAD: u8 = 0b00010000;
+pub const CRB_COUNT_TA: u8 = 0b01000000;
+
+#[derive(Clone, Debug, Default)]
+struct Timer {
+    counter: u16,
+    latch: u16,
+    control: u8,
+}
+
+impl Timer {
+    fn write_control(&mut self, value: u8) {
+        if value & CR_LOAD != 0 {
+            self.counter = self.latch;
+        }
+        self.control = value & !CR_LOAD;
+    }
+
+    fn write_hi(&mut self, value: u8) {
+        self.latch = (self.latch & 0x00FF) | (value as u16) << 8;
+        if self.control & CR_START == 0 {
+            self.counter = self.latch;
+        }
+    }
+
+    // counts `steps` down, returning the underflows
+    fn count(&mut self, steps: u64) -> u64 {
+        if self.control & CR_START == 0 || steps == 0 {
+            return 0;
+        }
+        let remaining = self.counter as u64 + 1;     // steps until passing zero
+        if steps < remaining {
+            self.counter -= steps as u16;
+            return 0;
+        }
+        if self.control & CR_ONE_SHOT != 0 {
+            self.counter = self.latch;
+            self.control &= !CR_START;
+            return 1;
+        }
+        let period = self.latch as u64 + 1;
+        let after = steps - remaining;
+        self.counter = self.latch - (after % period) as u16;
+        1 + after / period
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Cia {
+    name: String,
+    pub base: u16,
+    pra: u8,
+    prb: u8,
+    ddra: u8,
+    ddrb: u8,
+    pins_a: u8,             // levels applied to the port pins from outside
+    pins_b: u8,
+    ta: Timer,
+    tb: Timer,
+    tod: [u8; 4],
+    sdr: u8,
+    icr: u8,                // flags
+    mask: u8,
+}
+
+impl Cia {
+    pub fn create(name: &str, base: u16) -> Self {
+        Self {
+            name: String::from(name),
+            base,
+            pra: 0,
+            prb: 0,
+            ddra: 0,
+            ddrb: 0,
+            pins_a: 0xFF,       // inputs are pulled up
+            pins_b: 0xFF,
+            ta: Timer::default(),
+            tb: Timer::default(),
+            tod: [0; 4],
+            sdr: 0,
+            icr: 0,
+            mask: 0,
+        }
+    }
+
+    // levels on the port pins: driven by the output register where configured as output, from outside otherwise
+    pub fn port_a(&self) -> u8 {
+        (self.pra & self.ddra) | (self.pins_a & !self.ddra)
+    }
+
+    pub fn port_b(&self) -> u8 {
+        (self.prb & self.ddrb) | (self.pins_b & !self.ddrb)
+    }
+
+    // levels applied to the input pins
+    pub fn set_port_a(&mut self, pins: u8) {
+        self.pins_a = pins;
+    }
+
+    pub fn set_port_b(&mut self, pins: u8) {
+        self.pins_b = pins;
+    }
+
+    fn reg(&self, addr: u16) -> u16 {
+        addr.wrapping_sub(self.base) % CIA_REGISTERS
+    }
+
+    fn icr(&self) -> u8 {
+        match self.icr & self.mask != 0 {
+            true => self.icr | INT_ANY,
+            false => self.icr,
+        }
+    }
+}
+
+impl Device for Cia {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        self.base..=self.base + (CIA_REGISTERS - 1)
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        let value = self.peek(addr);
+        if self.reg(addr) == REG_ICR {
+            self.icr = 0;
+        }
+        value
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match self.reg(addr) {
+            REG_PRA => self.port_a(),
+            REG_PRB => self.port_b(),
+            REG_DDRA => self.ddra,
+            REG_DDRB => self.ddrb,
+            REG_TA_LO => self.ta.counter as u8,
+            REG_TA_HI => (self.ta.counter >> 8) as u8,
+            REG_TB_LO => self.tb.counter as u8,
+            REG_TB_HI => (self.tb.counter >> 8) as u8,
+            reg @ REG_TOD_10THS..=REG_TOD_HR => self.tod[(reg - REG_TOD_10THS) as usize],
+            REG_SDR => self.sdr,
+            REG_ICR => self.icr(),
+            REG_CRA => self.ta.control,
+            _ => self.tb.control,
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match self.reg(addr) {
+            REG_PRA => self.pra = value,
+            REG_PRB => self.prb = value,
+            REG_DDRA => self.ddra = value,
+            REG_DDRB => self.ddrb = value,
+            REG_TA_LO => self.ta.latch = (self.ta.latch & 0xFF00) | value as u16,
+            REG_TA_HI => self.ta.write_hi(value),
+            REG_TB_LO => self.tb.latch = (self.tb.latch & 0xFF00) | value as u16,
+            REG_TB_HI => self.tb.write_hi(value),
+            reg @ REG_TOD_10THS..=REG_TOD_HR => self.tod[(reg - REG_TOD_10THS) as usize] = value,
+            REG_SDR => self.sdr = value,
+            REG_ICR => match value & INT_ANY != 0 {
+                true => self.mask |= value & !INT_ANY,
+                false => self.mask &= !value,
+            },
+            REG_CRA => self.ta.write_control(value),
+            _ => self.tb.write_control(value),
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        let underflows = self.ta.count(cycles);
+        if underflows > 0 {
+            self.icr |= INT_TA;
+        }
+        let steps = match self.tb.control & CRB_COUNT_TA != 0 {
+            true => underflows,
+            false => cycles,
+        };
+        if self.tb.count(steps) > 0 {
+            self.icr |= INT_TB;
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.icr & self.mask != 0
+    }
+
+    // registers are cleared and the timers stopped, the latches set to $FFFF; pins keep their levels
+    fn reset(&mut self) {
+        *self = Self {
+            pins_a: self.pins_a,
+            pins_b: self.pins_b,
+            ta: Timer { counter: 0xFFFF, latch: 0xFFFF, control: 0 },
+            tb: Timer { counter: 0xFFFF, latch: 0xFFFF, control: 0 },
+            ..Self::create(&self.name, self.base)
+        };
+    }
+
+    fn save_state(&self, writer: &mut dyn Write) -> io::Result<()> {
+        for value in [self.pra, self.prb, self.ddra, self.ddrb, self.pins_a, self.pins_b] {
+            state::write_u8(writer, value)?;
+        }
+        for timer in [&self.ta, &self.tb] {
+            state::write_u16(writer, timer.counter)?;
+            state::write_u16(writer, timer.latch)?;
+            state::write_u8(writer, timer.control)?;
+        }
+        writer.write_all(&self.tod)?;
+        for value in [self.sdr, self.icr, self.mask] {
+            state::write_u8(writer, value)?;
+        }
+        Ok(())
+    }
+
+    fn load_state(&mut self, reader: &mut dyn Read) -> io::Result<()> {
+        for value in [&mut self.pra, &mut self.prb, &mut self.ddra, &mut self.ddrb, &mut self.pins_a, &mut self.pins_b] {
+            *value = state::read_u8(reader)?;
+        }
+        for timer in [&mut self.ta, &mut self.tb] {
+            timer.counter = state::read_u16(reader)?;
+            timer.latch = state::read_u16(reader)?;
+            timer.control = state::read_u8(reader)?;
+        }
+        reader.read_exact(&mut self.tod)?;
+        for value in [&mut self.sdr, &mut self.icr, &mut self.mask] {
+            *value = state::read_u8(reader)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: u16 = 0xDC00;
+
+    #[test]
+    fn ports() {
+        let mut cia = Cia::create("cia", BASE);
+        cia.write(BASE + REG_DDRA, 0xFF);
+        cia.write(BASE + REG_PRA, 0xFE);
+        cia.set_port_b(0xF7);
+        assert_eq!((cia.port_a(), cia.read(BASE + REG_PRB)), (0xFE, 0xF7));
+        cia.write(BASE + REG_DDRB, 0x0F);
+        cia.write(BASE + REG_PRB, 0x00);
+        assert_eq!(cia.peek(BASE + REG_PRB + CIA_REGISTERS), 0xF0);
+        cia.write(BASE + REG_TOD_HR, 0x12);
+        assert_eq!(cia.peek(BASE + REG_TOD_HR), 0x12);
+    }
+
+    #[test]
+    fn timers() {
+        let mut cia = Cia::create("cia", BASE);
+        cia.write(BASE + REG_ICR, INT_ANY | INT_TA | INT_TB);
+        cia.write(BASE + REG_TA_LO, 9);
+        cia.write(BASE + REG_TA_HI, 0);
+        cia.write(BASE + REG_CRA, CR_START);
+
+        // a period of 10 cycles
+        cia.tick(9);
+        assert_eq!((cia.peek(BASE + REG_TA_LO), cia.irq()), (0, false));
+        cia.tick(1);
+        assert_eq!((cia.peek(BASE + REG_TA_LO), cia.irq()), (9, true));
+        assert_eq!(cia.read(BASE + REG_ICR), INT_ANY | INT_TA);
+        assert!(!cia.irq());
+        cia.tick(25);
+        assert_eq!((cia.peek(BASE + REG_TA_LO), cia.peek(BASE + REG_ICR)), (4, INT_ANY | INT_TA));
+
+        // timer B counts underflows of timer A, once
+        cia.read(BASE + REG_ICR);
+        cia.write(BASE + REG_TB_LO, 1);
+        cia.write(BASE + REG_TB_HI, 0);
+        cia.write(BASE + REG_CRB, CR_START | CR_ONE_SHOT | CRB_COUNT_TA);
+        cia.tick(10);
+        assert_eq!((cia.peek(BASE + REG_TB_LO), cia.peek(BASE + REG_ICR)), (0, INT_ANY | INT_TA));
+        cia.tick(10);
+        assert_eq!((cia.peek(BASE + REG_TB_LO), cia.peek(BASE + REG_CRB) & CR_START), (1, 0));
+        assert_eq!(cia.read(BASE + REG_ICR), INT_ANY | INT_TB | INT_TA);
+
+        // flags are set while masked, without an interrupt
+        cia.write(BASE + REG_ICR, INT_TA);
+        cia.tick(10);
+        assert_eq!((cia.peek(BASE + REG_ICR), cia.irq()), (INT_TA, false));
+
+        cia.reset();
+        assert_eq!((cia.peek(BASE + REG_TA_HI), cia.peek(BASE + REG_CRA), cia.peek(BASE + REG_ICR)), (0xFF, 0, 0));
+    }
+}
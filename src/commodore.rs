@@ -0,0 +1,138 @@
+// Shared by the Commodore machines: the PETSCII keymap and typing host keys on a keyboard matrix, each key held down
+// long enough for the KERNAL's scan and then released. The matrix is scanned by driving columns low on one port and
+// reading the rows pulled low by keys held down on another.
+
+use crate::acia::SerialBackend;
+use crate::device::Device;
+use crate::keyboard::{self, Keyboard, Keymap};
+
+pub const KEY_CYCLES: u64 = 20000;      // longer than the 1/60 s between scans
+
+// PETSCII code of the key at each column and row; 0 for the modifiers
+pub type Matrix = [[u8; 8]; 8];
+
+// codes typed with shift and the key's unshifted code
+const SHIFTED: [(u8, u8); 20] = [
+    (b'!', b'1'), (b'"', b'2'), (b'#', b'3'), (b'$', b'4'), (b'%', b'5'), (b'&', b'6'), (b'\'', b'7'), (b'(', b'8'),
+    (b')', b'9'), (b'[', b':'), (b']', b';'), (b'<', b','), (b'>', b'.'), (b'?', b'/'),
+    (0x9D, 0x1D), (0x91, 0x11), (0x93, 0x13), (0x94, 0x14), (0x89, 0x85), (0x8A, 0x86),
+];
+
+// host to PETSCII: letters as upper case (unshifted), line feed as RETURN, delete and backspace as DEL, escape as
+// RUN/STOP
+pub fn keymap() -> Keymap {
+    let mut keymap = Keymap::default();
+    for host in b'a'..=b'z' {
+        keymap.insert(host, host.to_ascii_uppercase());
+    }
+    keymap.insert(b'\n', 0x0D);
+    keymap.insert(0x7F, 0x14);
+    keymap.insert(0x08, 0x14);
+    keymap.insert(0x1B, 0x03);
+    keymap
+}
+
+pub struct MatrixKeyboard {
+    keyboard: Keyboard,     // queues keys until the previous one was typed
+    matrix: &'static Matrix,
+    shift: (u8, u8),        // column and row of the left shift key
+    key: Option<u8>,        // held down
+    countdown: u64,         // cycles until the key is released or the next one pressed
+}
+
+impl MatrixKeyboard {
+    pub fn create(backend: Option<Box<dyn SerialBackend>>, keymap: Keymap, matrix: &'static Matrix, shift: (u8, u8)) -> Self {
+        Self {
+            keyboard: Keyboard::create("keyboard", 0, backend, keymap),
+            matrix,
+            shift,
+            key: None,
+            countdown: 0,
+        }
+    }
+
+    pub fn press(&mut self, host: u8) {
+        self.keyboard.press(host);
+    }
+
+    // positions (column, row) held down for a PETSCII code
+    pub fn keys(&self, code: u8) -> Vec<(u8, u8)> {
+        let find = |code: u8| (0..64).map(|index| (index / 8, index % 8)).find(|&(column, row)| self.matrix[column as usize][row as usize] == code);
+        if code == 0 {
+            return vec![];
+        }
+        if let Some(key) = find(code) {
+            return vec![key];
+        }
+        match SHIFTED.iter().find(|(shifted, _)| *shifted == code).and_then(|&(_, key)| find(key)) {
+            Some(key) => vec![self.shift, key],
+            None => vec![],
+        }
+    }
+
+    // rows pulled low by the keys held down in the columns driven low
+    pub fn rows(&self, columns: u8) -> u8 {
+        let mut rows = 0xFF;
+        for (column, row) in self.key.map(|code| self.keys(code)).unwrap_or_default() {
+            if columns & (1 << column) == 0 {
+                rows &= !(1 << row);
+            }
+        }
+        rows
+    }
+
+    // releases the key held down or presses the next one when due
+    pub fn tick(&mut self, cycles: u64) {
+        self.keyboard.tick(cycles);
+        self.countdown = self.countdown.saturating_sub(cycles);
+        if self.countdown > 0 {
+            return;
+        }
+        if self.key.take().is_some() {
+            self.countdown = KEY_CYCLES;
+        } else if self.keyboard.waiting() > 0 {
+            self.key = Some(self.keyboard.read(keyboard::REG_DATA));
+            self.countdown = KEY_CYCLES;
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.key = None;
+        self.countdown = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // digits along the first row, letters along the second
+    const MATRIX: Matrix = [
+        [b'1', b'A', 0, 0, 0, 0, 0, 0],
+        [b'2', b'B', 0, 0, 0, 0, 0, 0],
+        [0; 8], [0; 8], [0; 8], [0; 8], [0; 8],
+        [0, 0, 0, 0, 0, 0, 0, 0x14],
+    ];
+
+    #[test]
+    fn typing() {
+        let mut keyboard = MatrixKeyboard::create(None, keymap(), &MATRIX, (7, 0));
+        assert_eq!(keyboard.keys(b'B'), vec![(1, 1)]);
+        assert_eq!(keyboard.keys(b'"'), vec![(7, 0), (1, 0)]);
+        assert_eq!(keyboard.keys(b'{'), vec![]);
+
+        keyboard.press(b'b');
+        keyboard.press(0x7F);
+        assert_eq!(keyboard.rows(0x00), 0xFF);
+        keyboard.tick(1);
+        assert_eq!((keyboard.rows(0xFD), keyboard.rows(0xFE)), (0xFD, 0xFF));
+
+        // released, then the next key
+        keyboard.tick(KEY_CYCLES);
+        assert_eq!(keyboard.rows(0x00), 0xFF);
+        keyboard.tick(KEY_CYCLES);
+        assert_eq!(keyboard.rows(0x7F), 0x7F);
+        keyboard.reset();
+        assert_eq!(keyboard.rows(0x00), 0xFF);
+    }
+}
@@ -0,0 +1,159 @@
+use core::ops::RangeInclusive;
+#[cfg(feature = "std")]
+use std::io::{self, BufRead, Write};
+
+use crate::symbols::SymbolTable;
+use crate::prelude::*;
+
+const ADDRESSES: usize = 0x10000;
+
+fn bit(addr: u16) -> (usize, u64) {
+    (addr as usize / 64, 1 << (addr % 64))
+}
+
+// executed addresses: instruction starts (opcodes) and all bytes belonging to executed instructions
+pub struct Coverage {
+    opcodes: [u64; ADDRESSES / 64],
+    bytes: [u64; ADDRESSES / 64],
+}
+
+impl Coverage {
+    pub fn create() -> Self {
+        Self {
+            opcodes: [0; ADDRESSES / 64],
+            bytes: [0; ADDRESSES / 64],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.opcodes = [0; ADDRESSES / 64];
+        self.bytes = [0; ADDRESSES / 64];
+    }
+
+    pub fn record(&mut self, pc: u16, len: u8) {
+        let (index, mask) = bit(pc);
+        self.opcodes[index] |= mask;
+        for i in 0..len as u16 {
+            let (index, mask) = bit(pc.wrapping_add(i));
+            self.bytes[index] |= mask;
+        }
+    }
+
+    // an instruction was executed starting at `addr`
+    pub fn is_executed(&self, addr: u16) -> bool {
+        let (index, mask) = bit(addr);
+        self.opcodes[index] & mask != 0
+    }
+
+    // `addr` is part of an executed instruction (opcode or operand)
+    pub fn is_covered(&self, addr: u16) -> bool {
+        let (index, mask) = bit(addr);
+        self.bytes[index] & mask != 0
+    }
+
+    // addresses where an instruction was executed, in ascending order
+    pub fn executed(&self) -> impl Iterator<Item = u16> + '_ {
+        (0..=0xFFFF).filter(|addr| self.is_executed(*addr))
+    }
+
+    pub fn covered_count(&self, range: RangeInclusive<u16>) -> usize {
+        range.filter(|addr| self.is_covered(*addr)).count()
+    }
+
+    // maximal ranges within `range` not part of any executed instruction
+    pub fn uncovered(&self, range: RangeInclusive<u16>) -> Vec<RangeInclusive<u16>> {
+        let mut ranges = Vec::new();
+        let mut start = None;
+        for addr in range.clone() {
+            match (self.is_covered(addr), start) {
+                (false, None) => start = Some(addr),
+                (true, Some(from)) => {
+                    ranges.push(from..=addr - 1);
+                    start = None;
+                },
+                _ => {},
+            }
+        }
+        if let Some(from) = start {
+            ranges.push(from..=*range.end());
+        }
+        ranges
+    }
+
+    // symbols whose address was never executed, e.g. routines not reached by a test run
+    pub fn unreached_symbols<'a>(&self, symbols: &'a SymbolTable) -> Vec<(u16, &'a str)> {
+        symbols.iter().filter(|(addr, _)| !self.is_executed(*addr)).collect()
+    }
+
+    // one executed address per line as 4-digit hex
+    #[cfg(feature = "std")]
+    pub fn write_addresses<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for addr in self.executed() {
+            writeln!(writer, "{:04X}", addr)?;
+        }
+        Ok(())
+    }
+
+    // reads addresses as written by `write_addresses`, or instruction traces (address first on each line, also in
+    // the VICE layout ".C:e000");
+    // `len` gives the length of the instruction at an address
+    #[cfg(feature = "std")]
+    pub fn read_addresses<R: BufRead>(reader: R, len: impl Fn(u16) -> u8) -> io::Result<Self> {
+        let mut coverage = Self::create();
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            let Some(addr) = line.split_whitespace().next().map(|addr| addr.strip_prefix(".C:").unwrap_or(addr)) else {
+                continue;
+            };
+            match u16::from_str_radix(addr, 16) {
+                Ok(addr) => coverage.record(addr, len(addr)),
+                Err(_) => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Line {}: invalid address '{addr}'", number + 1))),
+            }
+        }
+        Ok(coverage)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record() {
+        let mut coverage = Coverage::create();
+        coverage.record(0xE000, 2);
+        coverage.record(0xE002, 3);
+        coverage.record(0xE008, 1);
+
+        assert!(coverage.is_executed(0xE002));
+        assert!(!coverage.is_executed(0xE003));
+        assert!(coverage.is_covered(0xE004));
+        assert_eq!(coverage.covered_count(0xE000..=0xE00F), 6);
+        assert_eq!(coverage.uncovered(0xE000..=0xE00F), vec![0xE005..=0xE007, 0xE009..=0xE00F]);
+        assert!(coverage.uncovered(0xE000..=0xE004).is_empty());
+
+        let mut symbols = SymbolTable::default();
+        symbols.add("main", 0xE000);
+        symbols.add("unused", 0xE005);
+        assert_eq!(coverage.unreached_symbols(&symbols), vec![(0xE005, "unused")]);
+
+        #[cfg(feature = "std")]
+        {
+            let mut out = Vec::new();
+            coverage.write_addresses(&mut out).unwrap();
+            assert_eq!(String::from_utf8(out.clone()).unwrap(), "E000\nE002\nE008\n");
+
+            let read = Coverage::read_addresses(&out[..], |addr| if addr == 0xE002 { 3 } else { 1 }).unwrap();
+            assert_eq!(read.executed().collect::<Vec<_>>(), vec![0xE000, 0xE002, 0xE008]);
+            assert!(read.is_covered(0xE004) && !read.is_covered(0xE001));
+            let trace = "E000  A9 05     LDA #$05    A:00 X:00\n\nE002  EA        NOP\n";
+            assert_eq!(Coverage::read_addresses(trace.as_bytes(), |_| 1).unwrap().executed().count(), 2);
+            let trace = ".C:e000  a9 05     LDA #$05        - A:00 X:00 Y:00 SP:fd ..-.....          7\n";
+            assert!(Coverage::read_addresses(trace.as_bytes(), |_| 2).unwrap().is_executed(0xE000));
+            assert!(Coverage::read_addresses(&b"E000\nxyz\n"[..], |_| 1).is_err());
+        }
+
+        coverage.clear();
+        assert_eq!(coverage.executed().count(), 0);
+    }
+}
@@ -1,9 +1,33 @@
-use std::cmp::Ordering;
-use std::{fmt,cmp};
+use core::cell::Cell;
+#[cfg(feature = "std")]
+use core::cell::RefCell;
+use core::cmp::Ordering;
+use alloc::collections::{BTreeMap, VecDeque};
+use core::{fmt,cmp};
+#[cfg(feature = "std")]
+use std::io::{self, Read, Write};
 use bitflags::bitflags;
+#[cfg(feature = "cli")]
 use colored::Colorize;
-use crate::instruction::{Opcode::*,Mnemonic,AddressingMode,Instruction};
-use crate::mem::Memory;
+#[cfg(not(feature = "cli"))]
+use crate::plain::Colorize;
+use log::Level;
+use crate::instruction::{Opcode,Opcode::*,Mnemonic,AddressingMode,Instruction};
+use crate::bus::{self, BusCallback, BusCycle};
+use crate::coverage::Coverage;
+use crate::debugger::InstructionBreak;
+use crate::disasm::format_operand_bytes;
+use crate::events::Event;
+use crate::input::{Input, InputLog};
+use crate::mem::{Memory, UninitPolicy};
+use crate::profile::Profile;
+#[cfg(feature = "std")]
+use crate::state;
+use crate::stats::Stats;
+use crate::watch::WatchHit;
+#[cfg(feature = "std")]
+use crate::trace::{TraceComparison, TraceEntry, TraceFormat, TraceWriter};
+use crate::prelude::*;
 
 pub const VECTOR_NMI: u16 = 0xFFFA;                     // 0xFFFA LB, 0xFFFB HB NMI vector
 pub const VECTOR_RES: u16 = 0xFFFC;                     // 0xFFFC LB, 0xFFFD HB holding reset vector address
@@ -13,9 +37,19 @@ pub const STACK_BASE: u16 = 0x0100;                     // 0x0100 to 0x01FF
 pub const ZERO_PAGE_BASE: u16 = 0x0000;                 // 0x0000 to 0x00FF
 pub const INITIAL_STACK_POINTER: u8 = 0xFD;             // [0x0100 - 0x01FF] in memory; CPU starts with SP=0 and decrements 3x which is 0xFD
 pub const CYCLES_AFTER_RESET: u64 = 7;                  // after reset 7 cycles already happend
+pub const INTERRUPT_CYCLES: u64 = 7;                    // IRQ/NMI entry sequence
+pub const HISTORY_SIZE_DEFAULT: usize = 64;             // executed instructions kept for post-mortem inspection
+
+// log targets of the instructions executed and the register dump after each; other messages use the module path
+pub const LOG_TARGET_INSTRUCTIONS: &str = "rust_6502_emu::cpu::ins";
+pub const LOG_TARGET_STATE: &str = "rust_6502_emu::cpu::state";
+
+#[cfg(feature = "std")]
+const CALL_TRACE_OPCODES: [Opcode; 6] = [JSR_ABS, RTS, JMP_ABS, JMP_IND, BRK, RTI];     // traced in call traces, besides interrupts
 
 bitflags! {
     #[derive(Clone, Copy, PartialEq, Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct StatusFlags: u8 {
         const C = 0b00000001;          // [0] Carry Flag
         const Z = 0b00000010;          // [1] Zero Flag
@@ -37,6 +71,269 @@ impl Default for StatusFlags {
     }
 }
 
+// NV-BDIZC as letters, '.' if clear; the reserved bit is always '-'
+impl fmt::Display for StatusFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        "NV-BDIZC".chars().enumerate()
+            .map(|(i, flag)| match (flag, self.bits() & (0x80 >> i) != 0) {
+                ('-', _) => '-',
+                (flag, true) => flag,
+                (_, false) => '.',
+            })
+            .try_for_each(|c| write!(f, "{c}"))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum StopReason {
+    UninitializedRead { pc: u16, addr: u16 },
+    IrqVectorUninitialized { pc: u16 },
+    IrqHandlerBrk { pc: u16 },
+    StackOverflow { pc: u16 },
+    StackUnderflow { pc: u16 },
+    ReturnAddressOverwritten { pc: u16, addr: u16 },
+    TraceDivergence { pc: u16, line: usize },
+    Hook { pc: u16 },
+    Breakpoint { id: usize, pc: u16 },
+    Watchpoint { pc: u16, hit: WatchHit },
+    InstructionBreak { pc: u16, on: InstructionBreak },
+    InterruptBreak { interrupt: Interrupt, handler: u16 },
+    CycleBreak { cycle: u64, reached: u64, pc: u16 },
+    UndefinedOpcode { pc: u16, opcode: u8 },
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UninitializedRead { pc, addr } => write!(f, "Read of uninitialized memory at ${:04X} by instruction at ${:04X}", addr, pc),
+            Self::IrqVectorUninitialized { pc } => write!(f, "BRK at ${:04X} without IRQ handler: IRQ vector is $0000 (uninitialized)", pc),
+            Self::IrqHandlerBrk { pc } => write!(f, "BRK at ${:04X} would loop forever: IRQ handler starts with BRK", pc),
+            Self::StackOverflow { pc } => write!(f, "Stack overflow (SP wrapped from $00 to $FF) by instruction at ${:04X}", pc),
+            Self::StackUnderflow { pc } => write!(f, "Stack underflow (SP wrapped from $FF to $00) by instruction at ${:04X}", pc),
+            Self::ReturnAddressOverwritten { pc, addr } => write!(f, "Push to ${:04X} overwrote a return address in use by instruction at ${:04X}", addr, pc),
+            Self::TraceDivergence { pc, line } => write!(f, "Execution diverges from line {} of the reference trace before instruction at ${:04X}", line, pc),
+            Self::Hook { pc } => write!(f, "Stopped by hook at instruction at ${:04X}", pc),
+            Self::Breakpoint { id, pc } => write!(f, "Breakpoint #{} hit at ${:04X}", id, pc),
+            Self::Watchpoint { pc, hit } => write!(f, "{} by instruction at ${:04X}", hit, pc),
+            Self::InstructionBreak { pc, on } => write!(f, "Break on {} at ${:04X}", on, pc),
+            Self::InterruptBreak { interrupt, handler } => write!(f, "Break on {} entry, handler at ${:04X}", interrupt, handler),
+            Self::CycleBreak { cycle, reached, pc } => write!(f, "Cycle break at {} reached at cycle {} (${:04X})", cycle, reached, pc),
+            Self::UndefinedOpcode { pc, opcode } => write!(f, "Undefined opcode ${:02X} at ${:04X}", opcode, pc),
+        }
+    }
+}
+
+// members of the family differing in behavior
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Variant {
+    #[default]
+    Nmos6502,
+    Ricoh2A03,      // NES: without decimal mode, the D flag is kept but ignored
+    // CMOS: the instructions and addressing modes added by the 65C02 (not the Rockwell/WDC bit instructions, their
+    // opcodes and the other unassigned ones are NOPs), D cleared on interrupts, valid N and Z in decimal mode and
+    // JMP (ind) taking 6 cycles
+    Cmos65C02,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Interrupt {
+    Brk,
+    Irq,
+    Nmi,
+}
+
+impl fmt::Display for Interrupt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Brk => write!(f, "BRK"),
+            Self::Irq => write!(f, "IRQ"),
+            Self::Nmi => write!(f, "NMI"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StackFrame {
+    Subroutine { call: u16, ret: u16 },             // pushed by JSR at `call`, RTS continues at `ret`
+    Interrupt { brk: u16, ret: u16 },               // pushed by BRK at `brk`, RTI continues at `ret`
+    Hardware { interrupt: Interrupt, ret: u16 },    // pushed when taking an IRQ or NMI, RTI continues at `ret`
+    Injected { ret: u16 },                          // pushed by `call` without a JSR, RTS continues at `ret`
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct StackEntry {
+    pub addr: u16,
+    pub value: u8,
+    pub frame: Option<StackFrame>,                  // set on the low byte of what looks like a return address
+}
+
+// an active subroutine call or interrupt, tracked while executing
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CallFrame {
+    pub frame: StackFrame,
+    pub target: u16,                                // entry point of the subroutine or handler
+    pub sp: u8,                                     // SP before the return address was pushed
+}
+
+// an executed instruction with the registers before its execution
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HistoryEntry {
+    pub pc: u16,
+    pub bytes: [u8; 3],                             // opcode and operands; unused trailing bytes are zero
+    pub ac: u8,
+    pub x: u8,
+    pub y: u8,
+    pub sr: StatusFlags,
+    pub sp: u8,
+    pub cycles: u64,
+}
+
+// a step executed by `Cpu::iter` with the registers after it
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct StepInfo {
+    pub addr: u16,                                  // of the instruction, or PC when an interrupt was entered instead
+    pub opcode: Option<Opcode>,                     // None if an IRQ or NMI was entered
+    pub interrupt: Option<Interrupt>,               // BRK executed or IRQ/NMI entered
+    pub cycles: u64,                                // taken by the step
+    pub pc: u16,
+    pub ac: u8,
+    pub x: u8,
+    pub y: u8,
+    pub sr: StatusFlags,
+    pub sp: u8,
+}
+
+// an instruction formatted for display, see `Cpu::format_instruction`
+#[derive(Clone, PartialEq, Debug)]
+pub struct InstructionLine {
+    pub addr: u16,
+    pub label: Option<String>,                      // symbol at `addr`
+    pub opcode: String,
+    pub operand_bytes: String,
+    pub mnemonic: String,
+    pub operands: String,
+    pub info: String,                               // comment with effective address, index register and addressing mode
+}
+
+// same layout as printed while executing, without the label
+impl fmt::Display for InstructionLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04X}  {} {}   {} {:<10}  {}", self.addr, self.opcode, self.operand_bytes, self.mnemonic, self.operands, self.info)
+    }
+}
+
+// same layout as trace lines
+impl fmt::Display for HistoryEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ins = Instruction::from_opcode(self.bytes[0].into()).map_err(|_| fmt::Error)?;
+        let (oper_bytestr, operands) = format_operand_bytes(&ins, [self.bytes[1], self.bytes[2]], |_| None);
+        write!(f, "{:04X}  {:02X} {}  {:?} {:<10}  A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+            self.pc, ins.opcode, oper_bytestr, ins.mnemonic, operands,
+            self.ac, self.x, self.y, self.sr, self.sp, self.cycles)
+    }
+}
+
+impl CallFrame {
+    // the stack pointer value addresses a byte of the return address (and status for interrupts)
+    fn contains(&self, sp: u8) -> bool {
+        let len = match self.frame {
+            StackFrame::Subroutine { .. } | StackFrame::Injected { .. } => 2,
+            StackFrame::Interrupt { .. } | StackFrame::Hardware { .. } => 3,
+        };
+        (0..len).any(|i| self.sp.wrapping_sub(i) == sp)
+    }
+}
+
+// register values at one point, e.g. for front-ends and the bindings (as `Registers` in JavaScript and
+// `Emu6502Registers` in C); see `Cpu::registers`
+#[repr(C)]
+#[cfg_attr(feature = "wasm", wasm_bindgen::prelude::wasm_bindgen)]
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Registers {
+    pub pc: u16,
+    pub ac: u8,
+    pub x: u8,
+    pub y: u8,
+    pub sr: u8,         // bits of the `StatusFlags`
+    pub sp: u8,
+    pub cycles: u64,
+}
+
+impl Registers {
+    pub fn flags(&self) -> StatusFlags {
+        StatusFlags::from_bits_retain(self.sr)
+    }
+}
+
+impl fmt::Display for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PC={:04X} A={:02X} X={:02X} Y={:02X} P={} SP={:02X} CYC={}", self.pc, self.ac, self.x, self.y, self.flags(), self.sp, self.cycles)
+    }
+}
+
+// registers and debugging state, e.g. to step backwards
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Snapshot {
+    pc: u16,
+    ac: u8,
+    x: u8,
+    y: u8,
+    sr: StatusFlags,
+    sp: u8,
+    cycles: u64,
+    call_stack: Vec<CallFrame>,
+    irq: bool,
+    nmi_pending: bool,
+}
+
+// like `Cpu`
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let registers = Registers { pc: self.pc, ac: self.ac, x: self.x, y: self.y, sr: self.sr.bits(), sp: self.sp, cycles: self.cycles };
+        write!(f, "{registers}")
+    }
+}
+
+// host code run in place of a subroutine, e.g. a ROM entry point; see `Cpu::set_trap`
+pub type TrapHandler = Box<dyn FnMut(&mut Cpu, &mut Memory)>;
+
+// host code run once per CPU cycle with its number, after the devices advanced by it; see `Cpu::on_clock`
+pub type ClockHook = Box<dyn FnMut(u64, &mut Memory)>;
+
+// host code run before or after every instruction, e.g. a tracer, profiler, breakpoint or cheat; see `Cpu::add_hook`
+pub type InstructionHook = Box<dyn FnMut(&mut Cpu, &mut Memory, &Instruction) -> HookAction>;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum HookPoint {
+    Before,     // the instruction at PC, which is not executed if a hook stops
+    After,      // the instruction just executed, with PC at the next one
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum HookAction {
+    Continue,
+    Stop,       // `exec` returns `StopReason::Hook`
+}
+
+struct Hook {
+    id: usize,
+    point: HookPoint,
+    hook: InstructionHook,
+}
+
+// executes step by step, see `Cpu::iter`
+pub struct Steps<'a> {
+    cpu: &'a mut Cpu,
+    mem: &'a mut Memory,
+    stop_reason: Option<StopReason>,
+    ended: bool,
+}
+
 pub struct Cpu {
     pub pc: u16,
     pub ac: u8,
@@ -44,9 +341,51 @@ pub struct Cpu {
     pub y: u8,
     pub sr: StatusFlags,
     pub sp: u8,
+    variant: Variant,
 
     // for debugging
     pub cycles: u64,
+    call_stack: Vec<CallFrame>,
+    #[cfg(feature = "std")]
+    trace: Option<TraceWriter>,
+    #[cfg(feature = "std")]
+    trace_comparison: Option<TraceComparison>,
+    bus_trace: Option<BusCallback>,
+    traps: BTreeMap<u16, TrapHandler>,
+    hooks: Vec<Hook>,
+    next_hook_id: usize,
+    hook_stopped: Option<u64>,                      // cycle at which a hook stopped before the instruction at PC
+    clock_hook: Option<ClockHook>,
+    history: VecDeque<HistoryEntry>,
+    history_size: usize,
+    profile: Option<Profile>,
+    stats: Option<Stats>,
+    coverage: Option<Box<Coverage>>,
+    stack_check: bool,                              // stop on SP wrap-around and overwritten return addresses
+    stack_fault: Option<StackFault>,                // detected during the current instruction
+
+    // interrupt lines; IRQ is level-triggered, NMI edge-triggered
+    irq: bool,
+    nmi_pending: bool,
+    device_irq: bool,                               // outputs of the attached devices, polled before each step
+    device_nmi: bool,
+    entered_interrupt: Option<Interrupt>,           // interrupt entered by the last step
+    input_recording: Option<InputLog>,              // external inputs applied so far
+    input_replay: Option<InputLog>,                 // external inputs still to apply
+
+    displayed: Cell<Option<Registers>>,             // changes since are highlighted
+    show_changes: bool,                             // additionally list changes as old→new
+    #[cfg(feature = "std")]
+    dump_output: RefCell<Option<Box<dyn Write>>>,   // of the dumps instead of logging them
+    #[cfg(feature = "std")]
+    dump_color: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum StackFault {
+    Overflow,
+    Underflow,
+    ReturnAddressOverwritten(u16),
 }
 
 impl Cpu {
@@ -59,9 +398,41 @@ impl Cpu {
             y: 0,
             sr: StatusFlags::empty(),
             sp: 0,
+            variant: Variant::default(),
 
             // debug
             cycles: 0,
+            call_stack: Vec::new(),
+            #[cfg(feature = "std")]
+            trace: None,
+            #[cfg(feature = "std")]
+            trace_comparison: None,
+            bus_trace: None,
+            traps: BTreeMap::new(),
+            hooks: Vec::new(),
+            next_hook_id: 0,
+            hook_stopped: None,
+            clock_hook: None,
+            history: VecDeque::new(),
+            history_size: HISTORY_SIZE_DEFAULT,
+            profile: None,
+            stats: None,
+            coverage: None,
+            stack_check: false,
+            stack_fault: None,
+            irq: false,
+            nmi_pending: false,
+            device_irq: false,
+            device_nmi: false,
+            entered_interrupt: None,
+            input_recording: None,
+            input_replay: None,
+            displayed: Cell::new(None),
+            show_changes: false,
+            #[cfg(feature = "std")]
+            dump_output: RefCell::new(None),
+            #[cfg(feature = "std")]
+            dump_color: true,
         }
     }
 
@@ -83,6 +454,12 @@ impl Cpu {
 
     pub fn reset(&mut self, mem: &mut Memory) {
         mem.reset();
+        self.warm_reset(mem);
+    }
+
+    // reset without clearing memory, e.g. to restart the loaded program
+    pub fn warm_reset(&mut self, mem: &mut Memory) {
+        mem.reset_devices();
 
         // AC, X and Y
         self.ac = 0;
@@ -100,30 +477,97 @@ impl Cpu {
 
         // [debug]
         self.cycles = CYCLES_AFTER_RESET;
+        self.call_stack.clear();
+        self.history.clear();
+        self.irq = false;
+        self.nmi_pending = false;
+        self.device_irq = false;
+        self.device_nmi = false;
+        self.entered_interrupt = None;
+        self.displayed.set(None);
     }
 
-    pub fn exec(&mut self, mem: &mut Memory, max_cycles: u64) {
+    pub fn exec(&mut self, mem: &mut Memory, max_cycles: u64) -> Option<StopReason> {
         let mut cycles_to_execute = max_cycles;
         let mut opcode_byte: u8;
         let mut cur_addr: u16;
+        let mut ins_addr: u16;
 
         while cycles_to_execute > 0 {
-            // load instruction from mem at PC
-            opcode_byte = mem.read_u8(self.pc);
+            self.entered_interrupt = None;
+            self.apply_replayed_inputs();
+            self.poll_devices(mem);
+
+            // a pending interrupt is taken instead of the next instruction
+            if let Some(interrupt) = self.pending_interrupt() {
+                let cycles_consumed = self.enter_interrupt(mem, interrupt);
+                cycles_to_execute = cycles_to_execute.saturating_sub(cycles_consumed);
+                self.cycles = self.cycles.saturating_add(cycles_consumed);
+                self.clock(mem, cycles_consumed);
+                continue;
+            }
+
+            // hooks before the instruction; resuming after they stopped executes it without running them again
+            if !self.hooks.is_empty() && self.hook_stopped.take() != Some(self.cycles) {
+                let ins = match self.traps.contains_key(&self.pc) {
+                    true => Instruction::from_opcode(RTS).ok(),
+                    false => Instruction::decode(mem.peek(self.pc), self.variant == Variant::Cmos65C02),
+                };
+                if let Some(ins) = ins {
+                    if self.run_hooks(HookPoint::Before, mem, &ins) {
+                        self.hook_stopped = Some(self.cycles);
+                        return Some(StopReason::Hook { pc: self.pc });
+                    }
+                }
+            }
+
+            ins_addr = self.pc;
+
+            // load instruction from mem at PC; a trapped address runs its handler and returns to the caller like RTS
+            opcode_byte = match self.traps.remove(&self.pc) {
+                Some(mut handler) => {
+                    handler(self, mem);
+                    self.traps.entry(ins_addr).or_insert(handler);
+                    RTS.into()
+                },
+                None => mem.fetch(self.pc),
+            };
 
             // advance read address by 1 read opcode byte
-            cur_addr = self.pc + 1;
+            cur_addr = self.pc.wrapping_add(1);
 
-            let result = Instruction::from_opcode(opcode_byte.into());
+            let result = match self.decode(opcode_byte) {
+                Some(opcode) => Instruction::from_opcode(opcode),
+                None => match Instruction::unassigned_65c02(opcode_byte).filter(|_| self.variant == Variant::Cmos65C02) {
+                    Some(ins) => Ok(ins),
+                    None => return Some(self.jam(mem, opcode_byte)),
+                },
+            };
             match result {
                 Ok(ins) => {
+                    #[cfg(feature = "std")]
+                    let call_trace_entry = match self.trace_instruction(mem, &ins) {
+                        Ok(entry) => entry,
+                        Err(reason) => return Some(reason),
+                    };
+                    self.record_history(mem, &ins);
                     self.dump_ins(mem, &ins);
+                    if self.bus_trace.is_some() {
+                        let cycles = bus::instruction_cycles(self, mem);
+                        self.write_bus_trace(&cycles);
+                    }
             
                     // advance PC by instruction bytes
-                    self.pc += ins.bytes() as u16;
+                    self.pc = self.pc.wrapping_add(ins.bytes() as u16);
 
                     // handle the opcode
+                    let sp = self.sp;
                     let cycles_additional = self.handle_opcode(mem, &ins, cur_addr);
+                    self.track_call(&ins, ins_addr, sp);
+                    #[cfg(feature = "std")]
+                    if let Some((entry, depth)) = call_trace_entry {
+                        self.write_call_trace(&entry, depth);
+                    }
                     let cycles_consumed = ins.cycles + cycles_additional;
         
                     // decrease remaining cycle counter 
@@ -132,75 +576,806 @@ impl Cpu {
                     // [debug] increase global cycles counter
                     self.cycles = self.cycles.saturating_add(cycles_consumed as u64);
 
+                    // a DMA transfer triggered by this instruction takes over the bus
+                    let cycles_stalled = mem.service_dma();
+                    if cycles_stalled > 0 {
+                        self.stall(cycles_stalled);
+                        cycles_to_execute = cycles_to_execute.saturating_sub(cycles_stalled);
+                    }
+                    self.clock(mem, (cycles_consumed as u64).saturating_add(cycles_stalled));
+
+                    // a device holding RDY low halts the CPU once the instruction is done
+                    let cycles_halted = mem.device_halt();
+                    if cycles_halted > 0 {
+                        self.stall(cycles_halted);
+                        cycles_to_execute = cycles_to_execute.saturating_sub(cycles_halted);
+                        self.clock(mem, cycles_halted);
+                    }
+                    let cycles_stalled = cycles_stalled.saturating_add(cycles_halted);
+
+                    if let Some(profile) = &mut self.profile {
+                        profile.record(ins_addr, (cycles_consumed as u64).saturating_add(cycles_stalled));
+                    }
+                    if let Some(stats) = &mut self.stats {
+                        stats.record(ins.opcode, (cycles_consumed as u64).saturating_add(cycles_stalled));
+                    }
+                    if let Some(coverage) = &mut self.coverage {
+                        coverage.record(ins_addr, ins.bytes());
+                    }
+                    if !mem.events().is_empty() {
+                        mem.events().emit(&Event::InstructionExecuted {
+                            addr: ins_addr, opcode: ins.opcode, cycles: (cycles_consumed as u64).saturating_add(cycles_stalled),
+                        });
+                    }
+
                     self.dump_state(mem);
+                    let hook_stop = !self.hooks.is_empty() && self.run_hooks(HookPoint::After, mem, &ins);
+
+                    if let Some(addr) = mem.take_uninit_reads().first() {
+                        let reason = StopReason::UninitializedRead { pc: ins_addr, addr: *addr };
+                        log::warn!("{reason}");
+                        if mem.uninit_policy() == UninitPolicy::Break {
+                            return Some(reason);
+                        }
+                    }
+
+                    if let Some(fault) = self.stack_fault.take() {
+                        let reason = match fault {
+                            StackFault::Overflow => StopReason::StackOverflow { pc: ins_addr },
+                            StackFault::Underflow => StopReason::StackUnderflow { pc: ins_addr },
+                            StackFault::ReturnAddressOverwritten(addr) => StopReason::ReturnAddressOverwritten { pc: ins_addr, addr },
+                        };
+                        log::warn!("{reason}");
+                        return Some(reason);
+                    }
+
+                    // a BRK without a usable interrupt handler ends the program
+                    if ins.opcode == BRK {
+                        let reason = if self.pc == 0x0000 {
+                            Some(StopReason::IrqVectorUninitialized { pc: ins_addr })
+                        } else if mem.peek(self.pc) == u8::from(BRK) {
+                            Some(StopReason::IrqHandlerBrk { pc: ins_addr })
+                        } else {
+                            None
+                        };
+                        if let Some(reason) = reason {
+                            log::warn!("{reason}");
+                            return Some(reason);
+                        }
+                    }
+
+                    if hook_stop {
+                        return Some(StopReason::Hook { pc: ins_addr });
+                    }
+                },
+                Err(cause) => {
+                    log::warn!("Cannot convert opcode {:02X} @ {:04X} into instruction: {}", opcode_byte, self.pc, cause);
+                    return Some(self.jam(mem, opcode_byte));
                 },
-                Err(cause) => panic!("Cannot convert opcode {:02X} @ {:04X} into instruction: {}", opcode_byte, self.pc, cause),
             }
         }
+
+        None
     }
 
-    fn dump_ins(&self, mem: &Memory, ins: &Instruction) {
-        let addr_operand = self.pc.wrapping_add(1);
+    // an undefined opcode stops execution at it, so executing again stops again
+    fn jam(&self, mem: &Memory, opcode: u8) -> StopReason {
+        if !mem.events().is_empty() {
+            mem.events().emit(&Event::Jammed { pc: self.pc, opcode });
+        }
+        let reason = StopReason::UndefinedOpcode { pc: self.pc, opcode };
+        log::warn!("{reason}");
+        self.print_history();
+        reason
+    }
+
+    // executes an instruction (or enters an interrupt) per item, e.g. `cpu.iter(&mut mem).take_while(..).find(..)`;
+    // ends when execution stops, see `Steps::stop_reason`
+    pub fn iter<'a>(&'a mut self, mem: &'a mut Memory) -> Steps<'a> {
+        Steps { cpu: self, mem, stop_reason: None, ended: false }
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            pc: self.pc,
+            ac: self.ac,
+            x: self.x,
+            y: self.y,
+            sr: self.sr,
+            sp: self.sp,
+            cycles: self.cycles,
+            call_stack: self.call_stack.clone(),
+            irq: self.irq,
+            nmi_pending: self.nmi_pending,
+        }
+    }
 
-        let oper_bytestr = match ins.bytes() {
-            2 => format!("{:02X}   ", mem.read_u8(addr_operand)),
-            3 => format!("{:02X} {:02X}", mem.read_u8(addr_operand), mem.read_u8(addr_operand.wrapping_add(1))),
-            _ => String::from("     "),
-        };
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        self.pc = snapshot.pc;
+        self.ac = snapshot.ac;
+        self.x = snapshot.x;
+        self.y = snapshot.y;
+        self.sr = snapshot.sr;
+        self.sp = snapshot.sp;
+        self.cycles = snapshot.cycles;
+        self.call_stack = snapshot.call_stack;
+        self.irq = snapshot.irq;
+        self.nmi_pending = snapshot.nmi_pending;
+
+        // instructions executed after the snapshot are no longer part of the history
+        while self.history.back().is_some_and(|entry| entry.cycles >= snapshot.cycles) {
+            self.history.pop_back();
+        }
+    }
 
-        let opcode = format!("{:02X}", ins.opcode);
-        
-        let oper = match ins.bytes() {
-            1 => if ins.addr_mode == AddressingMode::ACC { "A".to_owned() } else { String::new() },
-            2 => format!("${:02X}", mem.read_u8(addr_operand)),
-            3 => format!("${:04X}", mem.read_u16(addr_operand)),
-            _ => panic!("Unexpected number of bytes {} for instruction", ins.bytes()),
+    #[cfg(feature = "std")]
+    pub fn save_state(&self, writer: &mut impl Write) -> io::Result<()> {
+        state::write_u16(writer, self.pc)?;
+        state::write_u8(writer, self.ac)?;
+        state::write_u8(writer, self.x)?;
+        state::write_u8(writer, self.y)?;
+        state::write_u8(writer, self.sr.bits())?;
+        state::write_u8(writer, self.sp)?;
+        state::write_u64(writer, self.cycles)?;
+        state::write_bool(writer, self.irq)?;
+        state::write_bool(writer, self.nmi_pending)
+    }
+
+    // the tracked call stack is not part of the state and starts empty
+    #[cfg(feature = "std")]
+    pub fn load_state(&mut self, reader: &mut impl Read) -> io::Result<()> {
+        self.pc = state::read_u16(reader)?;
+        self.ac = state::read_u8(reader)?;
+        self.x = state::read_u8(reader)?;
+        self.y = state::read_u8(reader)?;
+        self.sr = StatusFlags::from_bits_truncate(state::read_u8(reader)?);
+        self.sp = state::read_u8(reader)?;
+        self.cycles = state::read_u64(reader)?;
+        self.irq = state::read_bool(reader)?;
+        self.nmi_pending = state::read_bool(reader)?;
+        self.call_stack.clear();
+        Ok(())
+    }
+
+    // active calls, outermost first
+    pub fn call_stack(&self) -> &[CallFrame] {
+        &self.call_stack
+    }
+
+    // `sp` is the stack pointer before the instruction at `ins_addr` was executed
+    fn track_call(&mut self, ins: &Instruction, ins_addr: u16, sp: u8) {
+        let frame = match ins.opcode {
+            JSR_ABS => StackFrame::Subroutine { call: ins_addr, ret: ins_addr.wrapping_add(3) },
+            BRK => StackFrame::Interrupt { brk: ins_addr, ret: ins_addr.wrapping_add(2) },
+            _ => return,
         };
 
-        let operands = ins.addr_mode.operands().replace("oper", &oper);
+        // frames at or below the new one were abandoned (e.g. return address pulled or SP reloaded)
+        self.call_stack.retain(|frame| frame.sp > sp);
+        self.call_stack.push(CallFrame { frame, target: self.pc, sp });
+    }
+
+    // runs `handler` instead of the subroutine at `addr` when it is reached, e.g. to provide ROM routines on the host;
+    // the registers it leaves are returned to the caller
+    pub fn set_trap(&mut self, addr: u16, handler: impl FnMut(&mut Cpu, &mut Memory) + 'static) {
+        self.traps.insert(addr, Box::new(handler));
+    }
+
+    pub fn remove_trap(&mut self, addr: u16) -> bool {
+        self.traps.remove(&addr).is_some()
+    }
+
+    pub fn traps(&self) -> impl Iterator<Item = u16> + '_ {
+        self.traps.keys().copied()
+    }
+
+    // registers a hook run before or after every instruction, returning its id for `remove_hook`; hooks run in the
+    // order added and execution stops if any of them asks to
+    pub fn add_hook(&mut self, point: HookPoint, hook: impl FnMut(&mut Cpu, &mut Memory, &Instruction) -> HookAction + 'static) -> usize {
+        self.next_hook_id += 1;
+        self.hooks.push(Hook { id: self.next_hook_id, point, hook: Box::new(hook) });
+        self.next_hook_id
+    }
+
+    pub fn remove_hook(&mut self, id: usize) -> bool {
+        let count = self.hooks.len();
+        self.hooks.retain(|hook| hook.id != id);
+        self.hooks.len() != count
+    }
+
+    // whether any hook asked to stop; hooks added meanwhile run from the next instruction on
+    fn run_hooks(&mut self, point: HookPoint, mem: &mut Memory, ins: &Instruction) -> bool {
+        let mut hooks = core::mem::take(&mut self.hooks);
+        let mut stop = false;
+        for hook in hooks.iter_mut().filter(|hook| hook.point == point) {
+            stop |= (hook.hook)(self, mem, ins) == HookAction::Stop;
+        }
+        hooks.append(&mut self.hooks);
+        self.hooks = hooks;
+        stop
+    }
+
+    // calls the subroutine at `target` as if JSR were executed at the current PC, so its RTS continues there
+    pub fn call(&mut self, mem: &mut Memory, target: u16) {
+        let (ret, sp) = (self.pc, self.sp);
+        self.stack_push_u16(mem, ret.wrapping_sub(1));
+        self.call_stack.retain(|frame| frame.sp > sp);
+        self.call_stack.push(CallFrame { frame: StackFrame::Injected { ret }, target, sp });
+        self.pc = target;
+    }
+
+    // most recently executed instructions, oldest first; recorded independently of tracing
+    pub fn history(&self) -> impl Iterator<Item = &HistoryEntry> {
+        self.history.iter()
+    }
+
+    pub fn set_history_size(&mut self, size: usize) {
+        self.history_size = size;
+        while self.history.len() > size {
+            self.history.pop_front();
+        }
+    }
+
+    pub fn print_history(&self) {
+        log::info!("Last {} executed instructions:", self.history.len());
+        for entry in &self.history {
+            log::info!("{entry}");
+        }
+    }
+
+    fn record_history(&mut self, mem: &Memory, ins: &Instruction) {
+        if self.history_size == 0 {
+            return;
+        }
+        if self.history.len() == self.history_size {
+            self.history.pop_front();
+        }
+        let mut bytes = [0; 3];
+        for (i, byte) in bytes.iter_mut().enumerate().take(ins.bytes() as usize) {
+            *byte = mem.peek(self.pc.wrapping_add(i as u16));
+        }
+        self.history.push_back(HistoryEntry { pc: self.pc, bytes, ac: self.ac, x: self.x, y: self.y, sr: self.sr, sp: self.sp, cycles: self.cycles });
+    }
+
+    pub fn enable_profile(&mut self) {
+        if self.profile.is_none() {
+            self.profile = Some(Profile::create());
+        }
+    }
+
+    pub fn disable_profile(&mut self) {
+        self.profile = None;
+    }
+
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    pub fn clear_profile(&mut self) {
+        if let Some(profile) = &mut self.profile {
+            profile.clear();
+        }
+    }
+
+    pub fn enable_stats(&mut self) {
+        if self.stats.is_none() {
+            self.stats = Some(Stats::create());
+        }
+    }
+
+    pub fn disable_stats(&mut self) {
+        self.stats = None;
+    }
+
+    pub fn stats(&self) -> Option<&Stats> {
+        self.stats.as_ref()
+    }
+
+    pub fn clear_stats(&mut self) {
+        if let Some(stats) = &mut self.stats {
+            stats.clear();
+        }
+    }
+
+    pub fn enable_coverage(&mut self) {
+        if self.coverage.is_none() {
+            self.coverage = Some(Box::new(Coverage::create()));
+        }
+    }
+
+    pub fn disable_coverage(&mut self) {
+        self.coverage = None;
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_deref()
+    }
+
+    pub fn clear_coverage(&mut self) {
+        if let Some(coverage) = &mut self.coverage {
+            coverage.clear();
+        }
+    }
+
+    // records IRQ/NMI inputs with the cycle at which they are applied, for a later replay
+    pub fn enable_input_recording(&mut self) {
+        if self.input_recording.is_none() {
+            self.input_recording = Some(InputLog::create());
+        }
+    }
+
+    pub fn input_recording(&self) -> Option<&InputLog> {
+        self.input_recording.as_ref()
+    }
+
+    // applies the inputs of `log` when the cycle counter reaches their cycle, before the next instruction
+    pub fn replay_inputs(&mut self, log: InputLog) {
+        self.input_replay = Some(log);
+    }
+
+    fn apply_replayed_inputs(&mut self) {
+        while let Some(input) = self.input_replay.as_mut().and_then(|log| log.next_due(self.cycles)) {
+            match input {
+                Input::Irq(asserted) => self.set_irq(asserted),
+                Input::Nmi => self.nmi(),
+            }
+        }
+    }
+
+    fn record_input(&mut self, input: Input) {
+        if let Some(log) = &mut self.input_recording {
+            log.record(self.cycles, input);
+        }
+    }
+
+    // IRQ line level; while asserted, an IRQ is taken whenever the flag I is clear
+    pub fn set_irq(&mut self, asserted: bool) {
+        self.record_input(Input::Irq(asserted));
+        self.irq = asserted;
+    }
+
+    pub fn irq(&self) -> bool {
+        self.irq
+    }
+
+    // NMI edge; taken before the next instruction regardless of the flag I
+    pub fn nmi(&mut self) {
+        self.record_input(Input::Nmi);
+        self.nmi_pending = true;
+    }
 
-        let calculated = match ins.addr_mode {
-            AddressingMode::IMP => String::new(),
-            AddressingMode::ACC => format!("${:02X}", self.ac),
-            AddressingMode::IMM => format!("${:02X}", mem.read_u8(addr_operand)),
-            _ => {
-                let addr = self.fetch_addr(mem, ins, addr_operand);
-                format!("${:04X}", addr)
+    // BRK executed or IRQ/NMI taken by the last step; PC then points to the first instruction of the handler
+    pub fn entered_interrupt(&self) -> Option<Interrupt> {
+        self.entered_interrupt
+    }
+
+    // device IRQ outputs are wired-OR with the IRQ line; a rising device NMI output is an NMI edge
+    fn poll_devices(&mut self, mem: &Memory) {
+        let nmi = mem.device_nmi();
+        if nmi && !self.device_nmi {
+            self.nmi_pending = true;
+        }
+        self.device_nmi = nmi;
+        self.device_irq = mem.device_irq();
+    }
+
+    fn pending_interrupt(&self) -> Option<Interrupt> {
+        if self.nmi_pending {
+            Some(Interrupt::Nmi)
+        } else if (self.irq || self.device_irq) && !self.sr.contains(StatusFlags::I) {
+            Some(Interrupt::Irq)
+        } else {
+            None
+        }
+    }
+
+    // pushes PC and SR (with B clear), sets I and continues at the vector; returns the cycles consumed
+    fn enter_interrupt(&mut self, mem: &mut Memory, interrupt: Interrupt) -> u64 {
+        let vector = match interrupt {
+            Interrupt::Nmi => {
+                self.nmi_pending = false;
+                VECTOR_NMI
             },
+            _ => VECTOR_IRQ,
         };
 
-        let reg_info = match ins.addr_mode {
-            AddressingMode::ACC => format!("A=${:02X}", self.ac),
-            AddressingMode::ZPX | AddressingMode::ABX | AddressingMode::IDX => format!("X=${:02X}", self.x),
-            AddressingMode::ZPY | AddressingMode::ABY | AddressingMode::IDY => format!("Y=${:02X}", self.y),
-            _ => String::new(),
+        let sp = self.sp;
+        let ret = self.pc;
+        #[cfg(feature = "std")]
+        let call_trace_entry = match self.trace.as_ref() {
+            Some(trace) if trace.format().is_call_trace() && trace.includes(ret) => Some(TraceEntry {
+                pc: ret, bytes: Vec::new(), mnemonic: interrupt.to_string(), operands: String::new(),
+                ac: self.ac, x: self.x, y: self.y, sr: self.sr.bits(), sp, cycles: self.cycles,
+            }),
+            _ => None,
         };
+        #[cfg(feature = "std")]
+        let depth = self.call_stack.len();
+        if self.bus_trace.is_some() {
+            let cycles = bus::interrupt_cycles(self, mem, interrupt);
+            self.write_bus_trace(&cycles);
+        }
+        self.stack_push_u16(mem, ret);
+        self.stack_push_u8(mem, self.sr.difference(StatusFlags::B).union(StatusFlags::RESERVED).bits());
+        self.sr.set(StatusFlags::I, true);
+        if self.variant == Variant::Cmos65C02 {
+            self.sr.remove(StatusFlags::D);
+        }
+        self.pc = mem.read_u16(vector);
+        if !mem.events().is_empty() {
+            mem.events().emit(&Event::InterruptTaken { interrupt, handler: self.pc });
+        }
+
+        self.call_stack.retain(|frame| frame.sp > sp);
+        self.call_stack.push(CallFrame { frame: StackFrame::Hardware { interrupt, ret }, target: self.pc, sp });
+        self.entered_interrupt = Some(interrupt);
+        if let Some(stats) = &mut self.stats {
+            stats.record_interrupt(interrupt, INTERRUPT_CYCLES);
+        }
+        #[cfg(feature = "std")]
+        if let Some(entry) = call_trace_entry {
+            self.write_call_trace(&entry, depth);
+        }
+
+        log::info!("{} taken at ${:04X}, handler at ${:04X}", interrupt, ret, self.pc);
+        INTERRUPT_CYCLES
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    pub fn set_variant(&mut self, variant: Variant) {
+        self.variant = variant;
+    }
+
+    // the opcode of `byte` on this variant, None if undefined
+    pub fn decode(&self, byte: u8) -> Option<Opcode> {
+        Opcode::decode(byte, self.variant == Variant::Cmos65C02)
+    }
+
+    fn decimal_mode(&self) -> bool {
+        self.sr.contains(StatusFlags::D) && self.variant != Variant::Ricoh2A03
+    }
+
+    pub fn stack_check(&self) -> bool {
+        self.stack_check
+    }
+
+    pub fn set_stack_check(&mut self, enabled: bool) {
+        self.stack_check = enabled;
+        self.stack_fault = None;
+    }
+
+    // RDY pulled low: the CPU is halted for the given number of cycles
+    pub fn stall(&mut self, cycles: u64) {
+        self.cycles = self.cycles.saturating_add(cycles);
+    }
+
+    // called for every cycle consumed from now on, including interrupt entries and stalls, so host models (timers,
+    // video, audio) advance in lockstep with the CPU; the devices are then ticked a cycle at a time as well
+    pub fn on_clock(&mut self, hook: impl FnMut(u64, &mut Memory) + 'static) {
+        self.clock_hook = Some(Box::new(hook));
+    }
+
+    pub fn remove_clock_hook(&mut self) {
+        self.clock_hook = None;
+    }
+
+    // advances the devices by the last `cycles` counted, at once unless a clock hook is run for each of them
+    fn clock(&mut self, mem: &mut Memory, cycles: u64) {
+        match &mut self.clock_hook {
+            None => mem.tick_devices(cycles),
+            Some(hook) => for cycle in self.cycles.saturating_sub(cycles)..self.cycles {
+                mem.tick_devices(1);
+                hook(cycle, mem);
+            },
+        }
+    }
+
+    // streams a line per instruction (state before execution), independent of console output
+    #[cfg(feature = "std")]
+    pub fn start_trace(&mut self, trace: TraceWriter) {
+        self.stop_trace();
+        self.trace = Some(trace);
+    }
+
+    #[cfg(feature = "std")]
+    pub fn stop_trace(&mut self) {
+        if let Some(mut writer) = self.trace.take() {
+            if let Err(error) = writer.flush() {
+                log::error!("Error writing trace: {error}");
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub fn is_tracing(&self) -> bool {
+        self.trace.is_some()
+    }
+
+    // called for every bus cycle of the instructions and interrupt entries executed from now on
+    pub fn on_bus_cycle(&mut self, callback: impl FnMut(&BusCycle) + 'static) {
+        self.bus_trace = Some(Box::new(callback));
+    }
+
+    pub fn stop_bus_trace(&mut self) {
+        self.bus_trace = None;
+    }
+
+    fn write_bus_trace(&mut self, cycles: &[BusCycle]) {
+        if let Some(callback) = &mut self.bus_trace {
+            cycles.iter().for_each(callback);
+        }
+    }
+
+    // stops before the first instruction not matching its line in the reference trace
+    #[cfg(feature = "std")]
+    pub fn start_trace_comparison(&mut self, comparison: TraceComparison) {
+        self.trace_comparison = Some(comparison);
+    }
+
+    #[cfg(feature = "std")]
+    pub fn stop_trace_comparison(&mut self) -> Option<TraceComparison> {
+        self.trace_comparison.take()
+    }
+
+    #[cfg(feature = "std")]
+    pub fn trace_comparison(&self) -> Option<&TraceComparison> {
+        self.trace_comparison.as_ref()
+    }
+
+    #[cfg(feature = "std")]
+    pub fn trace_format(&self) -> Option<TraceFormat> {
+        self.trace.as_ref().map(|trace| trace.format())
+    }
+
+    #[cfg(feature = "std")]
+    pub fn trace(&self) -> Option<&TraceWriter> {
+        self.trace.as_ref()
+    }
+
+    #[cfg(feature = "std")]
+    pub fn trace_mut(&mut self) -> Option<&mut TraceWriter> {
+        self.trace.as_mut()
+    }
 
-        let mut addr_mode_info = String::from(ins.addr_mode.abbr());
-        if ins.addr_mode != AddressingMode::IMP {
-            addr_mode_info.push(' ');
-            addr_mode_info.push_str(ins.addr_mode.operands());
+    #[cfg(feature = "std")]
+    fn write_trace(&mut self, entry: &TraceEntry) {
+        if let Some(trace) = self.trace.as_mut() {
+            if let Err(error) = trace.write(entry) {
+                log::error!("Error writing trace, tracing stopped: {error}");
+                self.trace = None;
+            }
         }
+    }
 
-        let mnemonic = format!("{:?}", ins.mnemonic);
+    // for call traces; continues at the current PC
+    #[cfg(feature = "std")]
+    fn write_call_trace(&mut self, entry: &TraceEntry, depth: usize) {
+        if let Some(trace) = self.trace.as_mut() {
+            if let Err(error) = trace.write_call(entry, self.pc, depth) {
+                log::error!("Error writing trace, tracing stopped: {error}");
+                self.trace = None;
+            }
+        }
+    }
 
-        let info = format!("; {:<5} {:<5}  ({})", calculated, reg_info, addr_mode_info);
+    // traces the instruction at PC and compares it with the reference trace; returns the entry of a call trace, written
+    // once the target is known, with the call depth
+    #[cfg(feature = "std")]
+    fn trace_instruction(&mut self, mem: &Memory, ins: &Instruction) -> Result<Option<(TraceEntry, usize)>, StopReason> {
+        let tracing = self.trace.as_ref().is_some_and(|trace| trace.includes(self.pc));
+        let comparing = self.trace_comparison.as_ref().is_some_and(|comparison| comparison.is_active());
+        if !tracing && !comparing {
+            return Ok(None);
+        }
+        let entry = mem.untracked(|| self.trace_entry(mem, ins));
+        if let Some(Err(divergence)) = self.trace_comparison.as_mut().map(|comparison| comparison.check(&entry)) {
+            let reason = StopReason::TraceDivergence { pc: self.pc, line: divergence.line };
+            log::warn!("{reason}\n{divergence}");
+            return Err(reason);
+        }
+        match self.trace_format() {
+            Some(format) if format.is_call_trace() => if tracing && CALL_TRACE_OPCODES.contains(&ins.opcode) {
+                return Ok(Some((entry, self.call_stack.len())));
+            },
+            _ => if tracing {
+                self.write_trace(&entry);
+            },
+        }
+        Ok(None)
+    }
+
+    #[cfg(feature = "std")]
+    fn trace_entry(&self, mem: &Memory, ins: &Instruction) -> TraceEntry {
+        let (_, operands) = self.format_operands(mem, ins);
+        TraceEntry {
+            pc: self.pc,
+            bytes: (0..ins.bytes() as u16).map(|i| mem.peek(self.pc.wrapping_add(i))).collect(),
+            mnemonic: ins.mnemonic.to_string(),
+            operands,
+            ac: self.ac,
+            x: self.x,
+            y: self.y,
+            sr: self.sr.bits(),
+            sp: self.sp,
+            cycles: self.cycles,
+        }
+    }
+
+    // operand bytes as hex and operands in assembler syntax for the instruction at PC; operand addresses with a symbol are shown by name
+    fn format_operands(&self, mem: &Memory, ins: &Instruction) -> (String, String) {
+        let addr_operand = self.pc.wrapping_add(1);
+        let operand = [mem.peek(addr_operand), mem.peek(addr_operand.wrapping_add(1))];
+        format_operand_bytes(ins, operand, |addr| mem.symbols().name_at(addr).map(String::from))
+    }
+
+    // the instruction at PC as shown while executing, with the effective address and names of its target;
+    // memory is accessed without side effects
+    pub fn format_instruction(&self, mem: &Memory, ins: &Instruction) -> InstructionLine {
+        mem.untracked(|| {
+            let addr_operand = self.pc.wrapping_add(1);
+            let (operand_bytes, operands) = self.format_operands(mem, ins);
+
+            let calculated = match ins.addr_mode {
+                AddressingMode::IMP => String::new(),
+                AddressingMode::ACC => format!("${:02X}", self.ac),
+                AddressingMode::IMM => format!("${:02X}", mem.read_u8(addr_operand)),
+                _ => {
+                    let addr = self.fetch_addr(mem, ins, addr_operand);
+                    format!("${:04X}", addr)
+                },
+            };
+
+            let reg_info = match ins.addr_mode {
+                AddressingMode::ACC => format!("A=${:02X}", self.ac),
+                AddressingMode::ZPX | AddressingMode::ABX | AddressingMode::IDX | AddressingMode::IAX => format!("X=${:02X}", self.x),
+                AddressingMode::ZPY | AddressingMode::ABY | AddressingMode::IDY => format!("Y=${:02X}", self.y),
+                _ => String::new(),
+            };
+
+            let mut addr_mode_info = String::from(ins.addr_mode.abbr());
+            if ins.addr_mode != AddressingMode::IMP {
+                addr_mode_info.push(' ');
+                addr_mode_info.push_str(ins.addr_mode.operands());
+            }
 
-        println!("{} {:04X}  {} {}   {} {:<10}  {}",
-            "»»»".black().on_yellow().bold(), self.pc,
-            opcode.bold(), oper_bytestr,
-            mnemonic.bold(), operands.bright_blue(),
-            info.bright_black());
+            let target = match ins.addr_mode {
+                AddressingMode::IMP | AddressingMode::ACC | AddressingMode::IMM => None,
+                _ => Some(self.fetch_addr(mem, ins, addr_operand)),
+            };
+            let region_info = target
+                .map(|target| [mem.symbols().name_at(target), mem.region_at(target).map(|region| region.name.as_str())])
+                .map(|names| names.into_iter().flatten().collect::<Vec<&str>>().join(" "))
+                .unwrap_or_default();
+
+            InstructionLine {
+                addr: self.pc,
+                label: mem.symbols().name_at(self.pc).map(String::from),
+                opcode: format!("{:02X}", ins.opcode),
+                operand_bytes,
+                mnemonic: format!("{:?}", ins.mnemonic),
+                operands,
+                info: format!("; {:<5} {:<5}  {:<18} {}", calculated, reg_info, format!("({})", addr_mode_info), region_info).trim_end().to_owned(),
+            }
+        })
+    }
+
+    // the executed instructions and register dumps are written to `output` instead of being logged, with or without
+    // colors (ANSI escape sequences)
+    #[cfg(feature = "std")]
+    pub fn set_dump_output(&mut self, output: Box<dyn Write>, color: bool) {
+        self.remove_dump_output();
+        *self.dump_output.get_mut() = Some(output);
+        self.dump_color = color;
+    }
+
+    // flushed, logging dumps again
+    #[cfg(feature = "std")]
+    pub fn remove_dump_output(&mut self) -> Option<Box<dyn Write>> {
+        let mut output = self.dump_output.take()?;
+        if let Err(error) = output.flush() {
+            log::error!("Error writing dump: {error}");
+        }
+        Some(output)
     }
 
+    #[cfg(feature = "std")]
+    fn is_dumping(&self, target: &str) -> bool {
+        self.dump_output.borrow().is_some() || log::log_enabled!(target: target, Level::Debug)
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn is_dumping(&self, target: &str) -> bool {
+        log::log_enabled!(target: target, Level::Debug)
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn dump(&self, target: &str, line: fmt::Arguments) {
+        log::debug!(target: target, "{line}");
+    }
+
+    // to the dump output, logged at debug level with the target otherwise
+    #[cfg(feature = "std")]
+    fn dump(&self, target: &str, line: fmt::Arguments) {
+        let mut dump_output = self.dump_output.borrow_mut();
+        let Some(output) = dump_output.as_mut() else {
+            log::debug!(target: target, "{}", strip_styles(&line.to_string()));
+            return;
+        };
+        let written = match self.dump_color {
+            true => writeln!(output, "{line}"),
+            false => writeln!(output, "{}", strip_styles(&line.to_string())),
+        };
+        if let Err(error) = written {
+            log::error!("Error writing dump, dumping stopped: {error}");
+            *dump_output = None;
+        }
+    }
+
+    // logged with target `LOG_TARGET_INSTRUCTIONS` unless written to the dump output
+    fn dump_ins(&self, mem: &Memory, ins: &Instruction) {
+        if !self.is_dumping(LOG_TARGET_INSTRUCTIONS) {
+            return;
+        }
+        let line = self.format_instruction(mem, ins);
+        if let Some(label) = &line.label {
+            self.dump(LOG_TARGET_INSTRUCTIONS, format_args!("{} {}:", "»»»".black().on_yellow().bold(), label.bold()));
+        }
+        self.dump(LOG_TARGET_INSTRUCTIONS, format_args!("{} {:04X}  {} {}   {} {:<10}  {}",
+            "»»»".black().on_yellow().bold(), line.addr,
+            line.opcode.bold(), line.operand_bytes,
+            line.mnemonic.bold(), line.operands.bright_blue(),
+            line.info.bright_black()));
+    }
+
+    pub fn set_show_changes(&mut self, enabled: bool) {
+        self.show_changes = enabled;
+    }
+
+    pub fn show_changes(&self) -> bool {
+        self.show_changes
+    }
+
+    pub fn registers(&self) -> Registers {
+        Registers { pc: self.pc, ac: self.ac, x: self.x, y: self.y, sr: self.sr.bits(), sp: self.sp, cycles: self.cycles }
+    }
+
+    // registers and flags which changed since the last `dump_state` as "A:00→42", "Z:0→1"; PC is not included
+    fn changes(previous: &Registers, current: &Registers) -> Vec<String> {
+        let mut changes = Vec::new();
+        for (name, old, new) in [("A", previous.ac, current.ac), ("X", previous.x, current.x), ("Y", previous.y, current.y), ("SP", previous.sp, current.sp)] {
+            if old != new {
+                changes.push(format!("{name}:{old:02X}→{new:02X}"));
+            }
+        }
+        for (name, flag) in [("N", StatusFlags::N), ("V", StatusFlags::V), ("B", StatusFlags::B), ("D", StatusFlags::D), ("I", StatusFlags::I), ("Z", StatusFlags::Z), ("C", StatusFlags::C)] {
+            if previous.flags().contains(flag) != current.flags().contains(flag) {
+                changes.push(format!("{name}:{}→{}", previous.flags().contains(flag) as u8, current.flags().contains(flag) as u8));
+            }
+        }
+        changes
+    }
+
+    // values which changed since the previous call are highlighted; logged with target `LOG_TARGET_STATE` unless written
+    // to the dump output
     pub fn dump_state(&self, mem: &Memory) {
-        let srf_n = if self.sr.contains(StatusFlags::N) { 1 } else { 0 };
-        let srf_v = if self.sr.contains(StatusFlags::V) { 1 } else { 0 };
-        let srf_b = if self.sr.contains(StatusFlags::B) { 1 } else { 0 };
-        let srf_d = if self.sr.contains(StatusFlags::D) { 1 } else { 0 };
-        let srf_i = if self.sr.contains(StatusFlags::I) { 1 } else { 0 };
-        let srf_z = if self.sr.contains(StatusFlags::Z) { 1 } else { 0 };
-        let srf_c = if self.sr.contains(StatusFlags::C) { 1 } else { 0 };
+        if !self.is_dumping(LOG_TARGET_STATE) {
+            return;
+        }
+        let current = self.registers();
+        let previous = self.displayed.replace(Some(current)).unwrap_or(current);
+        let highlight = |text: String, changed: bool| if changed { text.yellow().bold().to_string() } else { text };
+
+        let srf = |flag: StatusFlags| highlight((self.sr.contains(flag) as u8).to_string(), previous.flags().contains(flag) != self.sr.contains(flag));
+        let srf_n = srf(StatusFlags::N);
+        let srf_v = srf(StatusFlags::V);
+        let srf_b = srf(StatusFlags::B);
+        let srf_d = srf(StatusFlags::D);
+        let srf_i = srf(StatusFlags::I);
+        let srf_z = srf(StatusFlags::Z);
+        let srf_c = srf(StatusFlags::C);
 
         let sp_maxbytes = 8;
         let sp_bytes = cmp::min(0xFF - self.sp, sp_maxbytes);
@@ -209,15 +1384,52 @@ impl Cpu {
         for spp in 0..sp_bytes {
             let sp = self.sp.wrapping_add(spp).wrapping_add(1);
             sp_headers.push(format!("{:02X}", sp));
-            sp_values.push(format!("{:02X}", mem.read_u8(self.addr_stack(sp))));
+            sp_values.push(format!("{:02X}", mem.peek(self.addr_stack(sp))));
         }
         let sp_width: usize = (sp_maxbytes * 2 + sp_maxbytes - 1) as usize;
 
-        println!("    ░  {}  ░ {} ░ {} ░ {} ░ {} [NV-BDIZC] ░ {}  [{:>sp_width$}] ░",
-            "PC".bold(), "AC".bold(), " X".bold(), " Y".bold(), "SR".bold(), "SP".bold(), sp_headers.join(" "));
+        self.dump(LOG_TARGET_STATE, format_args!("    ░  {}  ░ {} ░ {} ░ {} ░ {} [NV-BDIZC] ░ {}  [{:>sp_width$}] ░",
+            "PC".bold(), "AC".bold(), " X".bold(), " Y".bold(), "SR".bold(), "SP".bold(), sp_headers.join(" ")));
+
+        self.dump(LOG_TARGET_STATE, format_args!("    ░ {:04X} ░ {} ░ {} ░ {} ░ {}  {srf_n}{srf_v}1{srf_b}{srf_d}{srf_i}{srf_z}{srf_c}  ░ {}  [{:>sp_width$}] ░",
+            self.pc,
+            highlight(format!("{:02X}", self.ac), previous.ac != self.ac),
+            highlight(format!("{:02X}", self.x), previous.x != self.x),
+            highlight(format!("{:02X}", self.y), previous.y != self.y),
+            highlight(format!("{:02X}", self.sr), previous.sr != self.sr.bits()),
+            highlight(format!("{:02X}", self.sp), previous.sp != self.sp),
+            sp_values.join(" ")));
+
+        if self.show_changes {
+            let changes = Self::changes(&previous, &current);
+            if !changes.is_empty() {
+                self.dump(LOG_TARGET_STATE, format_args!("    {}", changes.join(" ").yellow()));
+            }
+        }
+    }
 
-        println!("    ░ {:04X} ░ {:02X} ░ {:02X} ░ {:02X} ░ {:02X}  {srf_n}{srf_v}1{srf_b}{srf_d}{srf_i}{srf_z}{srf_c}  ░ {:02X}  [{:>sp_width$}] ░",
-            self.pc, self.ac, self.x, self.y, self.sr, self.sp, sp_values.join(" "));
+    // stack contents from SP+1 (top) to $01FF, annotating entries which look like return addresses
+    pub fn stack(&self, mem: &Memory) -> Vec<StackEntry> {
+        let mut entries: Vec<StackEntry> = (self.sp as u16 + 1..=0xFF)
+            .map(|sp| self.addr_stack(sp as u8))
+            .map(|addr| StackEntry { addr, value: mem.peek(addr), frame: None })
+            .collect();
+
+        let mut i = 1;
+        while i < entries.len() {
+            let value = (entries[i].value as u16) << 8 | entries[i - 1].value as u16;
+            // JSR pushes the address of its last byte; BRK the address following its break mark (after SR with B set)
+            let call = value.wrapping_sub(2);
+            let pushed_sr = i >= 2 && entries[i - 2].value & (StatusFlags::B | StatusFlags::RESERVED).bits() == (StatusFlags::B | StatusFlags::RESERVED).bits();
+            entries[i - 1].frame = match mem.peek(call) {
+                _ if !mem.is_initialized(call) => None,
+                opcode if opcode == u8::from(JSR_ABS) => Some(StackFrame::Subroutine { call, ret: value.wrapping_add(1) }),
+                opcode if opcode == u8::from(BRK) && pushed_sr => Some(StackFrame::Interrupt { brk: call, ret: value }),
+                _ => None,
+            };
+            i += if entries[i - 1].frame.is_some() { 2 } else { 1 };
+        }
+        entries
     }
 
     fn addr_stack(&self, addr: u8) -> u16 {
@@ -225,23 +1437,38 @@ impl Cpu {
     }
 
     fn stack_push_u8(&mut self, mem: &mut Memory, value: u8) {
+        if self.stack_check && self.stack_fault.is_none() {
+            if self.call_stack.iter().any(|frame| frame.contains(self.sp)) {
+                self.stack_fault = Some(StackFault::ReturnAddressOverwritten(self.addr_stack(self.sp)));
+            } else if self.sp == 0x00 {
+                self.stack_fault = Some(StackFault::Overflow);
+            }
+        }
         mem.write_u8(self.addr_stack(self.sp), value);
         self.sp = self.sp.wrapping_sub(1);
     }
 
     fn stack_push_u16(&mut self, mem: &mut Memory, value: u16) {
-        mem.write_u16(self.addr_stack(self.sp), value);
-        self.sp = self.sp.wrapping_sub(2);
+        self.stack_push_u8(mem, ((value & 0xFF00) >> 8) as u8);    // HB
+        self.stack_push_u8(mem, (value & 0x00FF) as u8);           // LB
     }
 
     fn stack_pop_u8(&mut self, mem: &mut Memory) -> u8 {
+        if self.stack_check && self.stack_fault.is_none() && self.sp == 0xFF {
+            self.stack_fault = Some(StackFault::Underflow);
+        }
         self.sp = self.sp.wrapping_add(1);
+
+        // frames whose return address is no longer (completely) on the stack are gone
+        self.call_stack.retain(|frame| frame.sp > self.sp);
+
         mem.read_u8(self.addr_stack(self.sp))
     }
 
     fn stack_pop_u16(&mut self, mem: &mut Memory) -> u16 {
-        self.sp = self.sp.wrapping_add(2);
-        mem.read_u16(self.addr_stack(self.sp))
+        let lb = self.stack_pop_u8(mem) as u16;
+        let hb = self.stack_pop_u8(mem) as u16;
+        (hb << 8) | lb
     }
 
     fn addr_zpg(&self, addr: u8) -> u16 {
@@ -292,8 +1519,13 @@ impl Cpu {
         self.addr_aby(mem.read_u16(addr))
     }
 
+    // the NMOS 6502 does not carry into the high byte of the pointer: JMP ($xxFF) reads its high byte from $xx00
     fn addr_ind(&self, mem: &Memory, addr: u16) -> u16 {
-        mem.read_u16(addr)
+        let addr_high = match self.variant {
+            Variant::Cmos65C02 => addr.wrapping_add(1),
+            _ => (addr & 0xFF00) | (addr.wrapping_add(1) & 0x00FF),
+        };
+        mem.read_u8(addr) as u16 /* LB */ | (mem.read_u8(addr_high) as u16) << 8 /* HB */
     }
 
     fn fetch_addr_ind(&self, mem: &Memory, addr: u16) -> u16 {
@@ -316,6 +1548,22 @@ impl Cpu {
         self.addr_idy(mem, mem.read_u8(addr))
     }
 
+    fn addr_zpi(&self, mem: &Memory, addr: u8) -> u16 {
+        mem.read_u16(ZERO_PAGE_BASE | addr as u16)
+    }
+
+    fn fetch_addr_zpi(&self, mem: &Memory, addr: u16) -> u16 {
+        self.addr_zpi(mem, mem.read_u8(addr))
+    }
+
+    fn addr_iax(&self, mem: &Memory, addr: u16) -> u16 {
+        mem.read_u16(addr.wrapping_add(self.x as u16))
+    }
+
+    fn fetch_addr_iax(&self, mem: &Memory, addr: u16) -> u16 {
+        self.addr_iax(mem, mem.read_u16(addr))
+    }
+
     fn addr_rel(&self, rel: i8) -> u16 {
         self.pc.wrapping_add(rel as u16)     // add/sub relative address
     }
@@ -336,6 +1584,8 @@ impl Cpu {
             AddressingMode::IND => self.fetch_addr_ind(mem, addr),
             AddressingMode::IDX => self.fetch_addr_idx(mem, addr),
             AddressingMode::IDY => self.fetch_addr_idy(mem, addr),
+            AddressingMode::ZPI => self.fetch_addr_zpi(mem, addr),
+            AddressingMode::IAX => self.fetch_addr_iax(mem, addr),
             _ => panic!("Unhandled address mode {}", ins.addr_mode),
         }
     }
@@ -347,15 +1597,10 @@ impl Cpu {
         match opcode {
             NOP => {},
 
-            ADC_IMM | ADC_ZPG | ADC_ZPX | ADC_ABS | ADC_ABX | ADC_ABY | ADC_IDX | ADC_IDY
-            | SBC_IMM | SBC_ZPG | SBC_ZPX | SBC_ABS | SBC_ABX | SBC_ABY | SBC_IDX | SBC_IDY => {
+            ADC_IMM | ADC_ZPG | ADC_ZPX | ADC_ABS | ADC_ABX | ADC_ABY | ADC_IDX | ADC_IDY | ADC_ZPI
+            | SBC_IMM | SBC_ZPG | SBC_ZPX | SBC_ABS | SBC_ABX | SBC_ABY | SBC_IDX | SBC_IDY | SBC_ZPI => {
                 // TODO: possible page crossing additional cycle for ZPX, ABX and ABY?
 
-                // TODO: BCD mode
-                if self.sr.contains(StatusFlags::D) {
-                    panic!("BCD mode not yet implemented");
-                }
-
                 let value = if ins.addr_mode == AddressingMode::IMM {
                     mem.read_u8(cur_addr)
                 } else {
@@ -364,15 +1609,17 @@ impl Cpu {
                 };
                 // println!("oper: 0x{:02X}", value);
 
+                let (ac, value16) = (self.ac as u16, value as u16);
+                let carry = if self.sr.contains(StatusFlags::C) { 1u16 } else { 0u16 };
                 let result: u8;
                 if ins.mnemonic == Mnemonic::ADC {
-                    let sum = (self.ac as u16) + value as u16 + if self.sr.contains(StatusFlags::C) { 1u16 } else { 0u16 };
+                    let sum = ac + value16 + carry;
                     result = (sum & 0xFF) as u8;
                     
                     self.sr.set(StatusFlags::C, sum > 255);
                     self.sr.set(StatusFlags::V, (!(self.ac ^ value) & (self.ac ^ result) & 0x80) != 0);
                 } else {
-                    let difference = (self.ac as u16) - value as u16 - if self.sr.contains(StatusFlags::C) { 0 } else { 1 };
+                    let difference = ac.wrapping_sub(value16).wrapping_sub(1 - carry);
                     result = (difference & 0xFF) as u8;
 
                     self.sr.set(StatusFlags::C, difference < 256);      // acts as borrow flag
@@ -383,18 +1630,51 @@ impl Cpu {
                 self.sr.set(StatusFlags::N, result & 0b10000000 != 0);
                 self.sr.set(StatusFlags::Z, result == 0);
                 self.ac = result;
+
+                // BCD as on the NMOS 6502: the digits are adjusted one after the other; Z (and for SBC all flags) are
+                // those of the binary operation, N and V of ADC those before adjusting the high digit
+                if self.decimal_mode() {
+                    if ins.mnemonic == Mnemonic::ADC {
+                        let mut low = (ac & 0x0F) + (value16 & 0x0F) + carry;
+                        if low > 0x09 {
+                            low += 0x06;
+                        }
+                        let mut sum = (ac & 0xF0) + (value16 & 0xF0) + (low & 0x0F) + if low > 0x0F { 0x10 } else { 0x00 };
+                        self.sr.set(StatusFlags::N, sum & 0x80 != 0);
+                        self.sr.set(StatusFlags::V, (!(ac ^ value16) & (ac ^ sum) & 0x80) != 0);
+                        if sum & 0x1F0 > 0x90 {
+                            sum += 0x60;
+                        }
+                        self.sr.set(StatusFlags::C, sum & 0xFF0 > 0xF0);
+                        self.ac = (sum & 0xFF) as u8;
+                    } else {
+                        let low = (ac & 0x0F).wrapping_sub(value16 & 0x0F).wrapping_sub(1 - carry);
+                        let high = (ac & 0xF0).wrapping_sub(value16 & 0xF0);
+                        let mut difference = if low & 0x10 != 0 {
+                            (low.wrapping_sub(0x06) & 0x0F) | high.wrapping_sub(0x10)
+                        } else {
+                            (low & 0x0F) | high
+                        };
+                        if difference & 0x100 != 0 {
+                            difference = difference.wrapping_sub(0x60);
+                        }
+                        self.ac = (difference & 0xFF) as u8;
+                    }
+
+                    // the 65C02 takes a cycle more to set N and Z from the adjusted result
+                    if self.variant == Variant::Cmos65C02 {
+                        self.sr.set(StatusFlags::N, self.ac & 0b10000000 != 0);
+                        self.sr.set(StatusFlags::Z, self.ac == 0);
+                        cycles_additional += 1;
+                    }
+                }
             },
 
-            CMP_IMM | CMP_ZPG | CMP_ZPX | CMP_ABS | CMP_ABX | CMP_ABY | CMP_IDX | CMP_IDY
+            CMP_IMM | CMP_ZPG | CMP_ZPX | CMP_ABS | CMP_ABX | CMP_ABY | CMP_IDX | CMP_IDY | CMP_ZPI
             | CPX_IMM | CPX_ZPG | CPX_ABS
             | CPY_IMM | CPY_ZPG | CPY_ABS => {
                 // TODO: possible page crossing additional cycle for ZPX, ABX and ABY?
 
-                // TODO: BCD mode also for CMP/CPX/CPY?
-                if self.sr.contains(StatusFlags::D) {
-                    panic!("BCD mode not yet implemented");
-                }
-
                 let value = if ins.addr_mode == AddressingMode::IMM {
                     mem.read_u8(cur_addr)
                 } else {
@@ -429,32 +1709,32 @@ impl Cpu {
                 }
             },
 
-            JMP_ABS | JMP_IND => self.pc = self.fetch_addr(mem, ins, cur_addr),
+            JMP_ABS | JMP_IND | JMP_IAX => {
+                self.pc = self.fetch_addr(mem, ins, cur_addr);
+                if opcode == JMP_IND && self.variant == Variant::Cmos65C02 {
+                    cycles_additional += 1;
+                }
+            },
 
             JSR_ABS => {
-                self.stack_push_u16(mem, self.pc - ins.bytes() as u16 + 2);      // previous PC + 2
+                self.stack_push_u16(mem, self.pc.wrapping_sub(ins.bytes() as u16).wrapping_add(2));      // previous PC + 2
                 self.pc = self.fetch_addr_abs(mem, cur_addr);
             },
 
             RTS => {
                 let addr = self.stack_pop_u16(mem);
-                self.pc = addr + 1;
+                self.pc = addr.wrapping_add(1);
             },
 
             BRK => {
-                self.stack_push_u16(mem, self.pc - ins.bytes() as u16 + 2);      // previous PC + 2
+                self.stack_push_u16(mem, self.pc.wrapping_sub(ins.bytes() as u16).wrapping_add(2));      // previous PC + 2
                 self.stack_push_u8(mem, self.sr.union(StatusFlags::B).bits());
                 self.sr.set(StatusFlags::I, true);
-                self.pc = mem.read_u16(VECTOR_IRQ);
-
-                if self.pc == 0x0000 {
-                    self.dump_state(mem);
-                    panic!("Reset vector points to $0000 (uninitialized) and I'm guessing we're done. Exiting.");
-                }
-                if mem.read_u8(self.pc) == BRK.into() {
-                    self.dump_state(mem);
-                    panic!("Instruction pointed to by reset vector is BRK ($00), which in fact is an infinite loop. Exiting.");
+                if self.variant == Variant::Cmos65C02 {
+                    self.sr.remove(StatusFlags::D);
                 }
+                self.pc = mem.read_u16(VECTOR_IRQ);
+                self.entered_interrupt = Some(Interrupt::Brk);
             },
 
             RTI => {
@@ -469,7 +1749,12 @@ impl Cpu {
                 self.pc = spc;
             },
 
-            BIT_ZPG | BIT_ABS => {
+            BIT_IMM => {
+                let value = mem.read_u8(cur_addr);
+                self.sr.set(StatusFlags::Z, value & self.ac == 0);                  // N and V are kept
+            },
+
+            BIT_ZPG | BIT_ABS | BIT_ZPX | BIT_ABX => {
                 let addr = self.fetch_addr(mem, ins, cur_addr);
                 let value = mem.read_u8(addr);
                 // println!("addr: {:04X} value: {:02X} result: {:02X}", addr, value, value & self.ac);
@@ -528,9 +1813,9 @@ impl Cpu {
                 }
             },
 
-            AND_IMM | AND_ZPG | AND_ZPX | AND_ABS | AND_ABX | AND_ABY | AND_IDX | AND_IDY
-            | EOR_IMM | EOR_ZPG | EOR_ZPX | EOR_ABS | EOR_ABX | EOR_ABY | EOR_IDX | EOR_IDY
-            | ORA_IMM | ORA_ZPG | ORA_ZPX | ORA_ABS | ORA_ABX | ORA_ABY | ORA_IDX | ORA_IDY => {
+            AND_IMM | AND_ZPG | AND_ZPX | AND_ABS | AND_ABX | AND_ABY | AND_IDX | AND_IDY | AND_ZPI
+            | EOR_IMM | EOR_ZPG | EOR_ZPX | EOR_ABS | EOR_ABX | EOR_ABY | EOR_IDX | EOR_IDY | EOR_ZPI
+            | ORA_IMM | ORA_ZPG | ORA_ZPX | ORA_ABS | ORA_ABX | ORA_ABY | ORA_IDX | ORA_IDY | ORA_ZPI => {
                 // TODO: additional cycles if page crossed
                 let value = if ins.addr_mode == AddressingMode::IMM {
                     mem.read_u8(cur_addr)
@@ -559,7 +1844,7 @@ impl Cpu {
             SED => self.sr.insert(StatusFlags::D),
             SEI => self.sr.insert(StatusFlags::I),
 
-            BCC_REL | BCS_REL | BEQ_REL | BNE_REL | BPL_REL | BMI_REL | BVC_REL | BVS_REL => {
+            BCC_REL | BCS_REL | BEQ_REL | BNE_REL | BPL_REL | BMI_REL | BVC_REL | BVS_REL | BRA_REL => {
                 let jmp = match opcode {
                     BCC_REL => !self.sr.contains(StatusFlags::C),
                     BCS_REL => self.sr.contains(StatusFlags::C),
@@ -569,6 +1854,7 @@ impl Cpu {
                     BMI_REL => self.sr.contains(StatusFlags::N),
                     BVC_REL => !self.sr.contains(StatusFlags::V),
                     BVS_REL => self.sr.contains(StatusFlags::V),
+                    BRA_REL => true,
                     _ => panic!("Unhandled branch opcode {:02X}", opcode),
                 };
                 // println!("jmp: {}", jmp);
@@ -581,13 +1867,20 @@ impl Cpu {
                 }
             }
 
-            INC_ZPG | INC_ZPX | INC_ABS | INC_ABX | DEC_ZPG | DEC_ZPX | DEC_ABS | DEC_ABX => {
+            INC_ZPG | INC_ZPX | INC_ABS | INC_ABX | DEC_ZPG | DEC_ZPX | DEC_ABS | DEC_ABX | INC_ACC | DEC_ACC => {
                 // TODO: possible page crossing additional cycle for ZPX and ABX?
-                let addr = self.fetch_addr(mem, ins, cur_addr);
-                let mut value: u8 = mem.read_u8(addr);
+                let addr;
+                let mut value;
+                if ins.addr_mode == AddressingMode::ACC {
+                    addr = cur_addr;    // unused
+                    value = self.ac;
+                } else {
+                    addr = self.fetch_addr(mem, ins, cur_addr);
+                    value = mem.read_u8(addr);
+                }
 
                 if ins.mnemonic == Mnemonic::INC { value = value.wrapping_add(1) } else { value = value.wrapping_sub(1) }
-                mem.write_u8(addr, value);
+                if ins.addr_mode == AddressingMode::ACC { self.ac = value } else { mem.write_u8(addr, value) }
                 self.sr.set(StatusFlags::Z, value == 0);
                 self.sr.set(StatusFlags::N, value & 0b10000000 != 0);
             },
@@ -606,7 +1899,7 @@ impl Cpu {
                 self.sr.set(StatusFlags::N, value & 0b10000000 != 0);
             },
 
-            LDA_IMM | LDA_ZPG | LDA_ZPX | LDA_ABS | LDA_ABX | LDA_ABY | LDA_IDX | LDA_IDY
+            LDA_IMM | LDA_ZPG | LDA_ZPX | LDA_ABS | LDA_ABX | LDA_ABY | LDA_IDX | LDA_IDY | LDA_ZPI
             | LDX_IMM | LDX_ZPG | LDX_ZPY | LDX_ABS | LDX_ABY
             | LDY_IMM | LDY_ZPG | LDY_ZPY | LDY_ABS | LDY_ABY => {
                 // TODO: possible page crossing additional cycle for LDA: ABX, ABY and IDX  and LDX/LDY: ABX?
@@ -629,19 +1922,28 @@ impl Cpu {
                 self.sr.set(StatusFlags::N, value & 0b10000000 != 0);
             },
 
-            STA_ZPG | STA_ZPX | STA_ABS | STA_ABX | STA_ABY | STA_IDX | STA_IDY
+            STA_ZPG | STA_ZPX | STA_ABS | STA_ABX | STA_ABY | STA_IDX | STA_IDY | STA_ZPI
              | STX_ZPG | STX_ZPY | STX_ABS
-             | STY_ZPG | STY_ZPX | STY_ABS => {
+             | STY_ZPG | STY_ZPX | STY_ABS
+             | STZ_ZPG | STZ_ZPX | STZ_ABS | STZ_ABX => {
                 let addr = self.fetch_addr(mem, ins, cur_addr);
                 let value = match ins.mnemonic {
                     Mnemonic::STA => self.ac,
                     Mnemonic::STX => self.x,
                     Mnemonic::STY => self.y,
+                    Mnemonic::STZ => 0,
                     _ => panic!("Unhandled ST* opcode {:02X}", opcode),
                 };
                 mem.write_u8(addr, value);
             },
 
+            TRB_ZPG | TRB_ABS | TSB_ZPG | TSB_ABS => {
+                let addr = self.fetch_addr(mem, ins, cur_addr);
+                let value = mem.read_u8(addr);
+                self.sr.set(StatusFlags::Z, value & self.ac == 0);
+                mem.write_u8(addr, if ins.mnemonic == Mnemonic::TSB { value | self.ac } else { value & !self.ac });
+            },
+
             TAX | TAY | TSX | TXA | TXS | TYA => {
                 let value = match ins.opcode {
                     TAY | TAX => self.ac,
@@ -665,21 +1967,27 @@ impl Cpu {
                 }
             },
 
-            PHA | PHP => {
+            PHA | PHP | PHX | PHY => {
                 let value = match opcode {
                     PHA => self.ac,
+                    PHX => self.x,
+                    PHY => self.y,
                     PHP => self.sr.union(StatusFlags::RESERVED | StatusFlags::B).bits(),    // SR will be pushed with the B flag and bit 5 set to 1
                     _ => panic!("Unhandled PH* opcode {:02X}", opcode),
                 };
                 self.stack_push_u8(mem, value);
             },
 
-            PLA => {
+            PLA | PLX | PLY => {
                 let value = self.stack_pop_u8(mem);
-                self.ac = value;
+                match opcode {
+                    PLX => self.x = value,
+                    PLY => self.y = value,
+                    _ => self.ac = value,
+                }
 
-                self.sr.set(StatusFlags::Z, self.ac == 0);
-                self.sr.set(StatusFlags::N, self.ac & 0b10000000 != 0);
+                self.sr.set(StatusFlags::Z, value == 0);
+                self.sr.set(StatusFlags::N, value & 0b10000000 != 0);
             },
 
             PLP => {
@@ -696,6 +2004,59 @@ impl Cpu {
     }
 }
 
+impl Steps<'_> {
+    // why the steps ended, unless not yet
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        self.stop_reason
+    }
+}
+
+impl Iterator for Steps<'_> {
+    type Item = StepInfo;
+
+    fn next(&mut self) -> Option<StepInfo> {
+        if self.ended {
+            return None;
+        }
+        let (addr, cycles) = (self.cpu.pc, self.cpu.cycles);
+        let opcode = if self.cpu.traps.contains_key(&addr) { Some(RTS) } else { self.cpu.decode(self.mem.peek(addr)) };
+        if let Some(reason) = self.cpu.exec(self.mem, 1) {
+            self.stop_reason = Some(reason);
+            self.ended = true;
+            return None;
+        }
+        let cpu = &self.cpu;
+        let interrupt = cpu.entered_interrupt;
+        Some(StepInfo {
+            addr,
+            opcode: if matches!(interrupt, Some(Interrupt::Irq | Interrupt::Nmi)) { None } else { opcode },
+            interrupt,
+            cycles: cpu.cycles - cycles,
+            pc: cpu.pc,
+            ac: cpu.ac,
+            x: cpu.x,
+            y: cpu.y,
+            sr: cpu.sr,
+            sp: cpu.sp,
+        })
+    }
+}
+
+// text without the ANSI escape sequences setting colors and styles
+#[cfg(feature = "std")]
+fn strip_styles(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(char) = chars.next() {
+        if char == '\x1b' {
+            chars.by_ref().find(|&char| char == 'm');
+        } else {
+            plain.push(char);
+        }
+    }
+    plain
+}
+
 impl fmt::Debug for Cpu {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let f_n = if self.sr.contains(StatusFlags::N) {"N"} else {"-"};
@@ -717,13 +2078,24 @@ impl fmt::Debug for Cpu {
     }
 }
 
+// registers on one line for logs and traces, e.g. "PC=E000 A=00 X=00 Y=00 P=..-..I.. SP=FD CYC=7"
+impl fmt::Display for Cpu {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.registers())
+    }
+}
+
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
+    #[cfg(not(feature = "std"))]
+    use std::println;
+
     use crate::mem::ADDR_RESET_VECTOR;
 
     use super::*;
 
-    fn setup() -> (Cpu, Memory) {
+    // a CPU after reset with empty memory, shared by the tests of other modules
+    pub(crate) fn setup() -> (Cpu, Memory) {
         let mut mem = Memory::create();
         let mut cpu = Cpu::create();
         cpu.reset(&mut mem);
@@ -759,6 +2131,16 @@ mod tests {
         assert_eq!(cpu.cycles, CYCLES_AFTER_RESET);
     }
 
+    #[test]
+    fn display() {
+        let (mut cpu, _) = setup();
+        assert_eq!(cpu.to_string(), "PC=E000 A=00 X=00 Y=00 P=..-..... SP=FD CYC=7");
+
+        (cpu.ac, cpu.sr) = (0x80, StatusFlags::ALL | StatusFlags::RESERVED);
+        assert_eq!(cpu.snapshot().to_string(), "PC=E000 A=80 X=00 Y=00 P=NV-BDIZC SP=FD CYC=7");
+        assert_eq!((StatusFlags::N | StatusFlags::I | StatusFlags::C).to_string(), "N.-..I.C");
+    }
+
     #[test]
     fn addr_stack() {
         let (cpu, _) = setup();
@@ -775,6 +2157,631 @@ mod tests {
 
         cpu.stack_push_u16(&mut mem, 0xABCD);
         assert_eq!(cpu.stack_pop_u16(&mut mem), 0xABCD);
+
+        // pushes below the previous top of stack, high byte first
+        let sp = cpu.sp;
+        cpu.stack_push_u8(&mut mem, 0xAA);
+        cpu.stack_push_u16(&mut mem, 0xABCD);
+        assert_eq!(cpu.sp, sp.wrapping_sub(3));
+        assert_eq!((mem.peek(cpu.addr_stack(sp)), mem.peek(cpu.addr_stack(sp - 1)), mem.peek(cpu.addr_stack(sp - 2))), (0xAA, 0xAB, 0xCD));
+        assert_eq!(cpu.stack_pop_u16(&mut mem), 0xABCD);
+        assert_eq!(cpu.stack_pop_u8(&mut mem), 0xAA);
+    }
+
+    #[test]
+    fn call_stack() {
+        let (mut cpu, mut mem) = setup();
+
+        // E000 JSR $E010, E003 NOP; E010 JSR $E020, E013 RTS; E020 BRK, E021 (break mark); IRQ handler at E030: RTI
+        mem.write_u16(VECTOR_IRQ, 0xE030);
+        mem.write_u8(ADDR_RESET_VECTOR, JSR_ABS.into());
+        mem.write_u16(None, 0xE010);
+        mem.write_u8(None, NOP.into());
+        mem.write_u8(0xE010, JSR_ABS.into());
+        mem.write_u16(None, 0xE020);
+        mem.write_u8(None, RTS.into());
+        mem.write_u8(0xE020, BRK.into());
+        mem.write_u8(None, 0xAA);
+        mem.write_u8(None, RTS.into());
+        mem.write_u8(0xE030, RTI.into());
+
+        cpu.exec(&mut mem, 1);
+        cpu.exec(&mut mem, 1);
+        cpu.exec(&mut mem, 1);
+        assert_eq!(cpu.pc, 0xE030);
+        assert_eq!(cpu.call_stack(), &[
+            CallFrame { frame: StackFrame::Subroutine { call: 0xE000, ret: 0xE003 }, target: 0xE010, sp: 0xFD },
+            CallFrame { frame: StackFrame::Subroutine { call: 0xE010, ret: 0xE013 }, target: 0xE020, sp: 0xFB },
+            CallFrame { frame: StackFrame::Interrupt { brk: 0xE020, ret: 0xE022 }, target: 0xE030, sp: 0xF9 },
+        ]);
+
+        // RTI, RTS, RTS
+        cpu.exec(&mut mem, 1);
+        assert_eq!(cpu.call_stack().len(), 2);
+        cpu.exec(&mut mem, 1);
+        cpu.exec(&mut mem, 1);
+        assert_eq!(cpu.pc, 0xE003);
+        assert!(cpu.call_stack().is_empty());
+
+        // a subroutine discarding its return address leaves no stale frame behind
+        cpu.reset(&mut mem);
+        mem.write_u8(ADDR_RESET_VECTOR, JSR_ABS.into());
+        mem.write_u16(None, 0xE010);
+        mem.write_u8(0xE010, PLA.into());
+        mem.write_u8(None, PLA.into());
+        mem.write_u8(None, JSR_ABS.into());
+        mem.write_u16(None, 0xE020);
+        for _ in 0..4 {
+            cpu.exec(&mut mem, 1);
+        }
+        assert_eq!(cpu.call_stack(), &[
+            CallFrame { frame: StackFrame::Subroutine { call: 0xE012, ret: 0xE015 }, target: 0xE020, sp: 0xFD },
+        ]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn trace() {
+        #[derive(Clone, Default)]
+        struct Buffer(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+        impl Write for Buffer {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.borrow_mut().write(buf)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let (mut cpu, mut mem) = setup();
+        mem.write_u8(ADDR_RESET_VECTOR, LDA_IMM.into());
+        mem.write_u8(None, 0x42);
+        mem.write_u8(None, STA_ABX.into());
+        mem.write_u16(None, 0x0200);
+        mem.write_u8(None, NOP.into());
+
+        let buffer = Buffer::default();
+        cpu.start_trace(TraceWriter::create(Box::new(buffer.clone()), TraceFormat::Plain));
+        assert!(cpu.is_tracing());
+        cpu.exec(&mut mem, 1);
+        cpu.exec(&mut mem, 1);
+        cpu.stop_trace();
+        cpu.exec(&mut mem, 1);
+        assert!(!cpu.is_tracing());
+
+        assert_eq!(String::from_utf8(buffer.0.borrow().clone()).unwrap(), concat!(
+            "E000  A9 42     LDA #$42        A:00 X:00 Y:00 P:20 SP:FD CYC:7\n",
+            "E002  9D 00 02  STA $0200,X     A:42 X:00 Y:00 P:20 SP:FD CYC:9\n",
+        ));
+
+        // only instructions in the ranges are traced
+        let buffer = Buffer::default();
+        cpu.pc = ADDR_RESET_VECTOR;
+        cpu.start_trace(TraceWriter::create(Box::new(buffer.clone()), TraceFormat::Plain));
+        cpu.trace_mut().unwrap().set_ranges(vec![0xD000..=0xDFFF, 0xE002..=0xE002]);
+        for _ in 0..3 {
+            cpu.exec(&mut mem, 1);
+        }
+        assert_eq!(cpu.pc, ADDR_RESET_VECTOR + 6);
+        assert_eq!(buffer.0.borrow().iter().filter(|&&byte| byte == b'\n').count(), 1);
+        assert!(buffer.0.borrow().starts_with(b"E002"));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn dump_output() {
+        #[derive(Clone, Default)]
+        struct Buffer(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+        impl Write for Buffer {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.borrow_mut().write(buf)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let (mut cpu, mut mem) = setup();
+        mem.write_u8(ADDR_RESET_VECTOR, LDA_IMM.into());
+        mem.write_u8(None, 0x42);
+        mem.write_u8(None, NOP.into());
+
+        let buffer = Buffer::default();
+        cpu.set_dump_output(Box::new(buffer.clone()), false);
+        cpu.exec(&mut mem, 1);
+        assert!(cpu.remove_dump_output().is_some());
+        cpu.exec(&mut mem, 1);
+
+        let dump = String::from_utf8(buffer.0.take()).unwrap();
+        let lines = dump.lines().map(str::trim_end).collect::<Vec<&str>>();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("»»» E000  A9 42      LDA #$42"), "{}", lines[0]);
+        assert_eq!(lines[2], "    ░ E002 ░ 42 ░ 00 ░ 00 ░ 20  00100000  ░ FD  [                  00 00] ░");
+        assert_eq!(strip_styles("\x1b[1;33mA:00→42\x1b[0m Z"), "A:00→42 Z");
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn call_trace() {
+        #[derive(Clone, Default)]
+        struct Buffer(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+        impl Write for Buffer {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.borrow_mut().write(buf)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        // E000 JSR $E006, E003 JMP $E003, E006 CLI, E007 RTS; IRQ handler F000 RTI
+        let (mut cpu, mut mem) = setup();
+        mem.write_u8(ADDR_RESET_VECTOR, JSR_ABS.into());
+        mem.write_u16(None, 0xE006);
+        mem.write_u8(None, JMP_ABS.into());
+        mem.write_u16(None, 0xE003);
+        mem.write_u8(None, CLI.into());
+        mem.write_u8(None, RTS.into());
+        mem.write_u16(VECTOR_IRQ, 0xF000);
+        mem.write_u8(0xF000, RTI.into());
+
+        let buffer = Buffer::default();
+        cpu.start_trace(TraceWriter::create(Box::new(buffer.clone()), TraceFormat::Calls));
+        for _ in 0..4 {
+            cpu.exec(&mut mem, 1);
+        }
+        cpu.set_irq(true);
+        cpu.exec(&mut mem, 1);
+        cpu.set_irq(false);
+        cpu.exec(&mut mem, 1);
+        cpu.stop_trace();
+
+        assert_eq!(String::from_utf8(buffer.0.borrow().clone()).unwrap(), concat!(
+            "         7  E000  JSR $E006       -> E006\n",
+            "        15    E007  RTS             -> E003\n",
+            "        21  E003  JMP $E003       -> E003\n",
+            "        24  E003  IRQ             -> F000\n",
+            "        31    F000  RTI             -> E003\n",
+        ));
+    }
+
+    #[test]
+    fn history() {
+        let (mut cpu, mut mem) = setup();
+        mem.write_u8(ADDR_RESET_VECTOR, LDA_IMM.into());
+        mem.write_u8(None, 0x42);
+        mem.write_u8(None, STA_ABX.into());
+        mem.write_u16(None, 0x0200);
+        mem.write_u8(None, ASL_ACC.into());
+
+        cpu.set_history_size(2);
+        cpu.exec(&mut mem, 1);
+        let snapshot = cpu.snapshot();
+        cpu.exec(&mut mem, 1);
+        cpu.exec(&mut mem, 1);
+
+        let history: Vec<String> = cpu.history().map(|entry| entry.to_string()).collect();
+        assert_eq!(history, vec![
+            "E002  9D 00 02  STA $0200,X     A:42 X:00 Y:00 P:20 SP:FD CYC:9",
+            "E005  0A        ASL A           A:42 X:00 Y:00 P:20 SP:FD CYC:14",
+        ]);
+
+        // restoring an earlier state drops the instructions executed since
+        cpu.restore(snapshot);
+        assert_eq!(cpu.history().count(), 0);
+
+        cpu.set_history_size(0);
+        cpu.exec(&mut mem, 1);
+        assert_eq!(cpu.history().count(), 0);
+    }
+
+    #[test]
+    fn profile() {
+        let (mut cpu, mut mem) = setup();
+        mem.write_u8(ADDR_RESET_VECTOR, LDA_IMM.into());
+        mem.write_u8(None, 0x42);
+        mem.write_u8(None, JMP_ABS.into());
+        mem.write_u16(None, ADDR_RESET_VECTOR);
+
+        cpu.exec(&mut mem, 1);
+        assert!(cpu.profile().is_none());
+
+        cpu.enable_profile();
+        cpu.exec(&mut mem, 10);
+        let hottest = cpu.profile().unwrap().hottest(2);
+        assert_eq!((hottest[0].addr, hottest[0].cycles, hottest[0].executions), (0xE002, 6, 2));
+        assert_eq!((hottest[1].addr, hottest[1].cycles, hottest[1].executions), (0xE000, 4, 2));
+
+        cpu.clear_profile();
+        assert_eq!(cpu.profile().unwrap().total_cycles(), 0);
+        cpu.disable_profile();
+        assert!(cpu.profile().is_none());
+    }
+
+    #[test]
+    fn stats() {
+        let (mut cpu, mut mem) = setup();
+        mem.write_u8(ADDR_RESET_VECTOR, CLI.into());
+        mem.write_u8(None, JMP_ABS.into());
+        mem.write_u16(None, ADDR_RESET_VECTOR + 1);
+        mem.write_u16(VECTOR_IRQ, 0xF000);
+        mem.write_u8(0xF000, RTI.into());
+
+        cpu.enable_stats();
+        cpu.exec(&mut mem, 8);
+        cpu.set_irq(true);
+        cpu.exec(&mut mem, 1);
+        cpu.set_irq(false);
+        cpu.exec(&mut mem, 1);
+
+        // CLI, 2x JMP, IRQ entry, RTI
+        let stats = cpu.stats().unwrap();
+        assert_eq!((stats.instructions(), stats.cycles(), stats.interrupts(Interrupt::Irq)), (4, 2 + 2 * 3 + 7 + 6, 1));
+        assert_eq!(stats.by_mnemonic(), vec![(Mnemonic::JMP, 2), (Mnemonic::RTI, 1), (Mnemonic::CLI, 1)]);
+
+        cpu.clear_stats();
+        assert_eq!(cpu.stats().unwrap().instructions(), 0);
+        cpu.disable_stats();
+        assert!(cpu.stats().is_none());
+    }
+
+    #[test]
+    fn coverage() {
+        let (mut cpu, mut mem) = setup();
+        mem.write_u8(ADDR_RESET_VECTOR, LDA_IMM.into());
+        mem.write_u8(None, 0x42);
+        mem.write_u8(None, JMP_ABS.into());
+        mem.write_u16(None, ADDR_RESET_VECTOR);
+
+        cpu.enable_coverage();
+        cpu.exec(&mut mem, 10);
+        let coverage = cpu.coverage().unwrap();
+        assert_eq!(coverage.executed().collect::<Vec<u16>>(), vec![0xE000, 0xE002]);
+        assert_eq!(coverage.covered_count(0xE000..=0xE00F), 5);
+
+        cpu.clear_coverage();
+        assert_eq!(cpu.coverage().unwrap().executed().count(), 0);
+        cpu.disable_coverage();
+        assert!(cpu.coverage().is_none());
+    }
+
+    #[test]
+    fn stack_check() {
+        // E000 JSR $E010; E010 LDX #$FF, E012 TXS, E013 PHA, E014 PHA, E015 PHA (overwrites HB of return address at $01FD)
+        let program = |mem: &mut Memory| {
+            mem.write_u8(ADDR_RESET_VECTOR, JSR_ABS.into());
+            mem.write_u16(None, 0xE010);
+            mem.write_u8(0xE010, LDX_IMM.into());
+            mem.write_u8(None, 0xFF);
+            mem.write_u8(None, TXS.into());
+            mem.write_u8(None, PHA.into());
+            mem.write_u8(None, PHA.into());
+            mem.write_u8(None, PHA.into());
+        };
+
+        let (mut cpu, mut mem) = setup();
+        program(&mut mem);
+        for _ in 0..6 {
+            assert_eq!(cpu.exec(&mut mem, 1), None);
+        }
+
+        cpu.reset(&mut mem);
+        program(&mut mem);
+        cpu.set_stack_check(true);
+        for _ in 0..5 {
+            assert_eq!(cpu.exec(&mut mem, 1), None);
+        }
+        assert_eq!(cpu.exec(&mut mem, 1), Some(StopReason::ReturnAddressOverwritten { pc: 0xE015, addr: 0x01FD }));
+
+        // SP wrapping in either direction
+        cpu.reset(&mut mem);
+        mem.write_u8(ADDR_RESET_VECTOR, PHA.into());
+        mem.write_u8(None, PLA.into());
+        mem.write_u8(None, PLA.into());
+        cpu.sp = 0x00;
+        assert_eq!(cpu.exec(&mut mem, 1), Some(StopReason::StackOverflow { pc: 0xE000 }));
+        assert_eq!(cpu.exec(&mut mem, 1), Some(StopReason::StackUnderflow { pc: 0xE001 }));
+        assert_eq!(cpu.exec(&mut mem, 1), None);
+    }
+
+    #[test]
+    fn variant() {
+        // E000 SED, E001 LDA #$09, E003 ADC #$01, E005 CMP #$0A
+        let (mut cpu, mut mem) = setup();
+        mem.write_u8(ADDR_RESET_VECTOR, SED.into());
+        for byte in [LDA_IMM.into(), 0x09, ADC_IMM.into(), 0x01, CMP_IMM.into(), 0x0A] {
+            mem.write_u8(None, byte);
+        }
+        cpu.set_variant(Variant::Ricoh2A03);
+        assert_eq!(cpu.variant(), Variant::Ricoh2A03);
+        cpu.exec(&mut mem, 10);
+        assert!(cpu.sr.contains(StatusFlags::D | StatusFlags::Z));
+        assert_eq!(cpu.ac, 0x0A);
+    }
+
+    #[test]
+    fn decimal_mode() {
+        // operation, A, operand, carry in; A, C, N, V, Z after
+        let cases = [
+            (ADC_IMM, 0x12, 0x34, false, (0x46, false, false, false, false)),
+            (ADC_IMM, 0x58, 0x46, true, (0x05, true, true, true, false)),      // N and V of $A5
+            (ADC_IMM, 0x81, 0x92, false, (0x73, true, false, true, false)),
+            (ADC_IMM, 0x99, 0x01, false, (0x00, true, true, false, false)),     // N of $A0 before adjusting, Z of $9A
+            (SBC_IMM, 0x46, 0x12, true, (0x34, true, false, false, false)),
+            (SBC_IMM, 0x40, 0x13, true, (0x27, true, false, false, false)),
+            (SBC_IMM, 0x12, 0x21, true, (0x91, false, true, false, false)),
+            (SBC_IMM, 0x00, 0x00, false, (0x99, false, true, false, false)),
+        ];
+        for (opcode, ac, value, carry, expected) in cases {
+            // E000 SED, E001 SEC/CLC, E002 LDA #ac, E004 ADC/SBC #value
+            let (mut cpu, mut mem) = setup();
+            mem.write_u8(ADDR_RESET_VECTOR, SED.into());
+            for byte in [if carry { SEC } else { CLC }.into(), LDA_IMM.into(), ac, opcode.into(), value] {
+                mem.write_u8(None, byte);
+            }
+            assert_eq!(cpu.exec(&mut mem, 8), None);
+            let flags = (cpu.sr.contains(StatusFlags::C), cpu.sr.contains(StatusFlags::N), cpu.sr.contains(StatusFlags::V), cpu.sr.contains(StatusFlags::Z));
+            assert_eq!((cpu.ac, flags), (expected.0, (expected.1, expected.2, expected.3, expected.4)), "{:?} ${:02X}, ${:02X}", opcode, ac, value);
+        }
+    }
+
+    #[test]
+    fn cmos() {
+        // E000 LDX #$10, PHX, PLY, STZ $20, LDA #$0F, TSB $21, INC A, LDA ($30), BRA $E010, E00F NOP,
+        // E010 JMP ($0300,X) to E020; E020 unassigned $02, $03 and $5C
+        let program = [
+            LDX_IMM.into(), 0x10, PHX.into(), PLY.into(), STZ_ZPG.into(), 0x20, LDA_IMM.into(), 0x0F, TSB_ZPG.into(), 0x21,
+            INC_ACC.into(), LDA_ZPI.into(), 0x30, BRA_REL.into(), 0x01, NOP.into(), JMP_IAX.into(), 0x00, 0x03,
+        ];
+        let (mut cpu, mut mem) = setup();
+        mem.write_u8(ADDR_RESET_VECTOR, program[0]);
+        for byte in &program[1..] {
+            mem.write_u8(None, *byte);
+        }
+        mem.write_u8(0x0020, 0xFF);
+        mem.write_u8(0x0021, 0xF0);
+        mem.write_u16(0x0030, 0x0200);
+        mem.write_u8(0x0200, 0x42);
+        mem.write_u16(0x0310, 0xE020);
+        mem.write_u8(0xE020, 0x02);
+        for byte in [0xFF, 0x03, 0x5C, 0x34, 0x12] {
+            mem.write_u8(None, byte);
+        }
+
+        // undefined on the NMOS 6502
+        assert_eq!(cpu.exec(&mut mem, 100), Some(StopReason::UndefinedOpcode { pc: 0xE002, opcode: PHX.into() }));
+
+        let (mut cpu, _) = setup();
+        cpu.set_variant(Variant::Cmos65C02);
+        let cycles = cpu.cycles;
+        assert_eq!(cpu.exec(&mut mem, 2 + 3 + 4 + 3 + 2 + 5 + 2 + 5 + 3 + 6), None);
+        assert_eq!((cpu.pc, cpu.ac, cpu.y, mem.read_u8(0x0020), mem.read_u8(0x0021)), (0xE020, 0x42, 0x10, 0x00, 0xFF));
+
+        // the opcodes it leaves unassigned are NOPs of 1 to 3 bytes
+        assert_eq!(cpu.exec(&mut mem, 2 + 1 + 8), None);
+        assert_eq!((cpu.pc, cpu.ac, cpu.cycles - cycles), (0xE026, 0x42, 35 + 2 + 1 + 8));
+
+        // N and Z of the BCD result, a cycle more; BRK clears D
+        let (mut cpu, mut mem) = setup();
+        cpu.set_variant(Variant::Cmos65C02);
+        mem.write_u16(VECTOR_IRQ, 0xE010);
+        mem.write_u8(ADDR_RESET_VECTOR, SED.into());
+        for byte in [CLC.into(), LDA_IMM.into(), 0x99, ADC_IMM.into(), 0x01, BRK.into(), 0x00] {
+            mem.write_u8(None, byte);
+        }
+        let cycles = cpu.cycles;
+        assert_eq!(cpu.exec(&mut mem, 9), None);
+        assert_eq!((cpu.ac, cpu.sr.contains(StatusFlags::N), cpu.sr.contains(StatusFlags::Z), cpu.cycles - cycles), (0x00, false, true, 9));
+        cpu.exec(&mut mem, 1);
+        assert_eq!((cpu.pc, cpu.sr.contains(StatusFlags::D)), (0xE010, false));
+    }
+
+    #[test]
+    fn interrupts() {
+        let (mut cpu, mut mem) = setup();
+
+        // E000 CLI, E001 NOP, E002 NOP; IRQ handler E010 RTI, NMI handler E020 RTI
+        mem.write_u16(VECTOR_IRQ, 0xE010);
+        mem.write_u16(VECTOR_NMI, 0xE020);
+        mem.write_u8(ADDR_RESET_VECTOR, CLI.into());
+        mem.write_u8(None, NOP.into());
+        mem.write_u8(None, NOP.into());
+        mem.write_u8(0xE010, RTI.into());
+        mem.write_u8(0xE020, RTI.into());
+        cpu.sr.set(StatusFlags::I, true);
+
+        // masked while I is set
+        cpu.set_irq(true);
+        cpu.exec(&mut mem, 1);
+        assert_eq!((cpu.pc, cpu.entered_interrupt()), (0xE001, None));
+
+        let cycles = cpu.cycles;
+        cpu.exec(&mut mem, 1);
+        assert_eq!((cpu.pc, cpu.entered_interrupt()), (0xE010, Some(Interrupt::Irq)));
+        assert_eq!(cpu.cycles, cycles + INTERRUPT_CYCLES);
+        assert!(cpu.sr.contains(StatusFlags::I));
+        assert_eq!(mem.peek(0x01FB) & StatusFlags::B.bits(), 0);
+        assert_eq!(cpu.call_stack()[0].frame, StackFrame::Hardware { interrupt: Interrupt::Irq, ret: 0xE001 });
+
+        // NMI is taken although I is set; RTI restores I and the still asserted IRQ is taken again
+        cpu.nmi();
+        cpu.exec(&mut mem, 1);
+        assert_eq!((cpu.pc, cpu.entered_interrupt()), (0xE020, Some(Interrupt::Nmi)));
+        cpu.exec(&mut mem, 1);
+        assert_eq!((cpu.pc, cpu.entered_interrupt()), (0xE010, None));
+        cpu.set_irq(false);
+        cpu.exec(&mut mem, 1);
+        cpu.exec(&mut mem, 1);
+        assert_eq!(cpu.pc, 0xE002);
+        assert!(cpu.call_stack().is_empty());
+    }
+
+    #[test]
+    fn input_replay() {
+        // E000 CLI, E001 INX, E002 JMP $E001; IRQ handler E010 INY, E011 RTI; NMI handler E020 RTI
+        let load = |mem: &mut Memory| {
+            mem.write_u16(VECTOR_IRQ, 0xE010);
+            mem.write_u16(VECTOR_NMI, 0xE020);
+            mem.write_u8(ADDR_RESET_VECTOR, CLI.into());
+            mem.write_u8(None, INX.into());
+            mem.write_u8(None, JMP_ABS.into());
+            mem.write_u16(None, 0xE001);
+            mem.write_u8(0xE010, INY.into());
+            mem.write_u8(None, RTI.into());
+            mem.write_u8(0xE020, RTI.into());
+        };
+        let (mut cpu, mut mem) = setup();
+        load(&mut mem);
+
+        cpu.enable_input_recording();
+        cpu.exec(&mut mem, 10);
+        cpu.set_irq(true);
+        cpu.exec(&mut mem, 1);
+        cpu.set_irq(false);
+        cpu.exec(&mut mem, 20);
+        cpu.nmi();
+        cpu.exec(&mut mem, 30);
+        let recording = cpu.input_recording().unwrap().clone();
+        assert_eq!(recording.events().count(), 3);
+        let state = (cpu.pc, cpu.x, cpu.y, cpu.cycles);
+
+        // the same inputs at the same cycles give the same run
+        cpu.reset(&mut mem);
+        load(&mut mem);
+        cpu.replay_inputs(recording);
+        while cpu.cycles < state.3 {
+            cpu.exec(&mut mem, 1);
+        }
+        assert_eq!((cpu.pc, cpu.x, cpu.y, cpu.cycles), state);
+        assert_eq!(cpu.y, 1);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let (mut cpu, mut mem) = setup();
+        mem.write_u8(ADDR_RESET_VECTOR, LDA_IMM.into());
+        mem.write_u8(None, 0x80);
+        mem.write_u8(None, JSR_ABS.into());
+        mem.write_u16(None, 0xF000);
+        cpu.exec(&mut mem, 2);
+
+        let snapshot = cpu.snapshot();
+        let json = serde_json::to_string(&snapshot).unwrap();
+        let mut restored = Cpu::create();
+        restored.restore(serde_json::from_str(&json).unwrap());
+        assert_eq!((restored.pc, restored.ac, restored.sr, restored.sp, restored.cycles), (cpu.pc, cpu.ac, cpu.sr, cpu.sp, cpu.cycles));
+        assert_eq!(restored.call_stack, cpu.call_stack);
+
+        assert_eq!(serde_json::to_string(&(StatusFlags::N | StatusFlags::C)).unwrap(), "\"C | N\"");
+        let ins = Instruction::from_opcode(LDA_IMM).unwrap();
+        let json = serde_json::to_string(&ins).unwrap();
+        assert_eq!(json, r#"{"opcode":"LDA_IMM","mnemonic":"LDA","addr_mode":"IMM","cycles":2}"#);
+        let ins: Instruction = serde_json::from_str(&json).unwrap();
+        assert_eq!((ins.opcode, ins.mnemonic, ins.addr_mode), (LDA_IMM, Mnemonic::LDA, AddressingMode::IMM));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn trace_comparison() {
+        let (mut cpu, mut mem) = setup();
+        mem.write_u8(ADDR_RESET_VECTOR, LDA_IMM.into());
+        mem.write_u8(None, 0x42);
+        mem.write_u8(None, TAX.into());
+        mem.write_u8(None, INX.into());
+        mem.write_u8(None, NOP.into());
+
+        let reference = concat!(
+            "E000  A9 42     LDA #$42                        A:00 X:00 Y:00 P:20 SP:FD PPU:  0, 21 CYC:7\n",
+            "E002  AA        TAX                             A:42 X:00 Y:00 P:20 SP:FD PPU:  0, 27 CYC:9\n",
+            "E003  E8        INX                             A:42 X:42 Y:00 P:20 SP:FD PPU:  0, 33 CYC:11\n",
+            "E004  EA        NOP                             A:42 X:42 Y:00 P:20 SP:FD PPU:  0, 39 CYC:13\n",
+        );
+        cpu.start_trace_comparison(TraceComparison::create(Box::new(reference.as_bytes())));
+        assert_eq!(cpu.exec(&mut mem, 5), None);
+        assert_eq!(cpu.exec(&mut mem, 1), Some(StopReason::TraceDivergence { pc: 0xE004, line: 4 }));
+        assert_eq!(cpu.pc, 0xE004);
+
+        let comparison = cpu.stop_trace_comparison().unwrap();
+        assert_eq!(comparison.matched(), 3);
+        assert_eq!(comparison.divergence().unwrap().differences, vec![("X", String::from("$42"), String::from("$43"))]);
+    }
+
+    #[test]
+    fn changes() {
+        let (mut cpu, _) = setup();
+        let previous = cpu.registers();
+        cpu.ac = 0x42;
+        cpu.sp = 0xFB;
+        cpu.sr.set(StatusFlags::Z, true);
+        cpu.sr.set(StatusFlags::I, true);
+        assert_eq!(Cpu::changes(&previous, &cpu.registers()), vec!["A:00→42", "SP:FD→FB", "I:0→1", "Z:0→1"]);
+        assert!(Cpu::changes(&previous, &previous).is_empty());
+    }
+
+    #[test]
+    fn symbolic_operands() {
+        let (mut cpu, mut mem) = setup();
+        mem.symbols_mut().add("counter", 0x0010);
+        mem.symbols_mut().add("print", 0xE100);
+        mem.write_u8(ADDR_RESET_VECTOR, JSR_ABS.into());
+        mem.write_u16(None, 0xE100);
+        mem.write_u8(0xE100, LDA_IMM.into());
+        mem.write_u8(None, 0x10);
+        mem.write_u8(0xE200, INC_ZPX.into());
+        mem.write_u8(None, 0x10);
+
+        let operands = |cpu: &Cpu, mem: &Memory| cpu.format_operands(mem, &Instruction::from_opcode(mem.peek(cpu.pc).into()).unwrap()).1;
+        assert_eq!(operands(&cpu, &mem), "print");
+        cpu.pc = 0xE100;
+        assert_eq!(operands(&cpu, &mem), "#$10");
+        cpu.pc = 0xE200;
+        assert_eq!(operands(&cpu, &mem), "counter,X");
+    }
+
+    #[test]
+    fn format_instruction() {
+        let (mut cpu, mut mem) = setup();
+        mem.symbols_mut().add("main", ADDR_RESET_VECTOR);
+        mem.symbols_mut().add("table", 0x0300);
+        mem.set_uninit_policy(UninitPolicy::Warn);
+        mem.write_u8(ADDR_RESET_VECTOR, LDA_ABX.into());
+        mem.write_u16(None, 0x02FF);
+        cpu.x = 1;
+
+        let line = cpu.format_instruction(&mem, &Instruction::from_opcode(LDA_ABX).unwrap());
+        assert_eq!(line.label.as_deref(), Some("main"));
+        assert_eq!((line.opcode.as_str(), line.mnemonic.as_str(), line.operands.as_str()), ("BD", "LDA", "$02FF,X"));
+        assert_eq!(line.to_string(), "E000  BD FF 02   LDA $02FF,X     ; $0300 X=$01  (ABX oper,X)       table");
+        assert!(mem.take_uninit_reads().is_empty());
+    }
+
+    #[test]
+    fn stack_frames() {
+        let (mut cpu, mut mem) = setup();
+
+        // E000 JSR $E010; E010 PHA, E011 BRK, E012 (break mark); IRQ handler at E030
+        mem.write_u16(VECTOR_IRQ, 0xE030);
+        mem.write_u8(ADDR_RESET_VECTOR, JSR_ABS.into());
+        mem.write_u16(None, 0xE010);
+        mem.write_u8(0xE010, PHA.into());
+        mem.write_u8(None, BRK.into());
+        mem.write_u8(None, 0xAA);
+        mem.write_u8(0xE030, NOP.into());
+        cpu.ac = 0x42;
+
+        cpu.exec(&mut mem, 1);
+        cpu.exec(&mut mem, 1);
+        cpu.exec(&mut mem, 1);
+        assert_eq!(cpu.pc, 0xE030);
+
+        let stack = cpu.stack(&mem);
+        assert_eq!(stack.len(), (0xFF - cpu.sp) as usize);
+        assert_eq!(stack[0].addr, STACK_BASE | (cpu.sp as u16 + 1));
+        assert_eq!(stack[1].frame, Some(StackFrame::Interrupt { brk: 0xE011, ret: 0xE013 }));
+        assert_eq!(stack[3].value, 0x42);
+        assert_eq!(stack[4].frame, Some(StackFrame::Subroutine { call: 0xE000, ret: 0xE003 }));
+        assert_eq!(stack.iter().filter(|entry| entry.frame.is_some()).count(), 2);
     }
 
     #[test]
@@ -956,6 +2963,160 @@ mod tests {
         assert_eq!(cpu.cycles, CYCLES_AFTER_RESET + Instruction::from_opcode(NOP).unwrap().cycles as u64);
     }
 
+    #[test]
+    fn uninit_read_break() {
+        let (mut cpu, mut mem) = setup();
+        mem.set_uninit_policy(UninitPolicy::Break);
+
+        mem.write_u8(ADDR_RESET_VECTOR, LDA_IMM.into());
+        mem.write_u8(None, 0x01);
+        mem.write_u8(None, LDA_ABS.into());
+        mem.write_u16(None, 0x0200);
+        mem.write_u8(None, NOP.into());
+
+        assert_eq!(cpu.exec(&mut mem, 2), None);
+        assert_eq!(cpu.exec(&mut mem, 100), Some(StopReason::UninitializedRead { pc: ADDR_RESET_VECTOR + 2, addr: 0x0200 }));
+        assert_eq!(cpu.pc, ADDR_RESET_VECTOR + 5);      // stopped after the offending instruction
+    }
+
+    #[test]
+    fn hooks() {
+        // E000 LDA #$01, E002 STA $10, E004 JMP $E000
+        let (mut cpu, mut mem) = setup();
+        mem.write_u8(ADDR_RESET_VECTOR, LDA_IMM.into());
+        mem.write_u8(None, 0x01);
+        mem.write_u8(None, STA_ZPG.into());
+        mem.write_u8(None, 0x10);
+        mem.write_u8(None, JMP_ABS.into());
+        mem.write_u16(None, ADDR_RESET_VECTOR);
+
+        // counting executed instructions, a cheat changing what is stored and a breakpoint
+        let executed = std::rc::Rc::new(Cell::new(0));
+        let counter = std::rc::Rc::clone(&executed);
+        cpu.add_hook(HookPoint::After, move |_, _, _| {
+            counter.set(counter.get() + 1);
+            HookAction::Continue
+        });
+        cpu.add_hook(HookPoint::Before, |cpu, _, ins| {
+            if ins.mnemonic == Mnemonic::STA {
+                cpu.ac = 0x42;
+            }
+            HookAction::Continue
+        });
+        let breakpoint = cpu.add_hook(HookPoint::Before, |cpu, _, _| if cpu.pc == 0xE004 { HookAction::Stop } else { HookAction::Continue });
+
+        assert_eq!(cpu.exec(&mut mem, 100), Some(StopReason::Hook { pc: 0xE004 }));
+        assert_eq!((cpu.pc, executed.get(), mem.peek(0x10)), (0xE004, 2, 0x42));
+        assert_eq!(cpu.exec(&mut mem, 100), Some(StopReason::Hook { pc: 0xE004 }), "resumes past the breakpoint");
+        assert_eq!(executed.get(), 5);
+
+        // stopping after an instruction
+        assert!(cpu.remove_hook(breakpoint) && !cpu.remove_hook(breakpoint));
+        cpu.add_hook(HookPoint::After, |_, _, ins| if ins.opcode == JMP_ABS { HookAction::Stop } else { HookAction::Continue });
+        assert_eq!(cpu.exec(&mut mem, 100), Some(StopReason::Hook { pc: 0xE004 }));
+        assert_eq!((cpu.pc, executed.get()), (ADDR_RESET_VECTOR, 6));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn clock() {
+        use crate::via::{Via, REG_T1C_H, REG_T1C_L, VIA_BASE_DEFAULT};
+
+        // E000 NOP, E001 LDA $10, with VIA T1 counting down from $FFFF
+        let (mut cpu, mut mem) = setup();
+        mem.attach_device(Box::new(Via::create("via", VIA_BASE_DEFAULT)));
+        mem.write_u8(VIA_BASE_DEFAULT + REG_T1C_L, 0xFF);
+        mem.write_u8(VIA_BASE_DEFAULT + REG_T1C_H, 0xFF);
+        mem.write_u8(ADDR_RESET_VECTOR, NOP.into());
+        mem.write_u8(None, LDA_ZPG.into());
+        mem.write_u8(None, 0x10);
+
+        // the timer is seen advancing by one in every cycle
+        let start = cpu.cycles;
+        let clocked = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let samples = std::rc::Rc::clone(&clocked);
+        cpu.on_clock(move |cycle, mem| samples.borrow_mut().push((cycle - start, mem.peek(VIA_BASE_DEFAULT + REG_T1C_L))));
+        cpu.exec(&mut mem, 5);
+        assert_eq!(*clocked.borrow(), vec![(0, 0xFE), (1, 0xFD), (2, 0xFC), (3, 0xFB), (4, 0xFA)]);
+
+        cpu.remove_clock_hook();
+        cpu.exec(&mut mem, 1);
+        assert_eq!(clocked.borrow().len(), 5);
+    }
+
+    #[test]
+    fn iter() {
+        // E000 LDX #$03, E002 DEX, E003 BNE $E002, E005 STX $10, E007 .byte $02
+        let (mut cpu, mut mem) = setup();
+        mem.write_u8(ADDR_RESET_VECTOR, LDX_IMM.into());
+        mem.write_u8(None, 0x03);
+        mem.write_u8(None, DEX.into());
+        mem.write_u8(None, BNE_REL.into());
+        mem.write_u8(None, 0xFD);
+        mem.write_u8(None, STX_ZPG.into());
+        mem.write_u8(None, 0x10);
+        mem.write_u8(None, 0x02);
+
+        let first = cpu.iter(&mut mem).next().unwrap();
+        assert_eq!((first.addr, first.opcode, first.cycles, first.pc, first.x), (ADDR_RESET_VECTOR, Some(LDX_IMM), 2, 0xE002, 0x03));
+        let taken = cpu.iter(&mut mem).take_while(|step| step.addr != 0xE005).filter(|step| step.opcode == Some(BNE_REL) && step.cycles == 3).count();
+        assert_eq!((taken, cpu.pc, mem.peek(0x10)), (2, 0xE007, 0x00), "the step ending take_while is executed");
+
+        // ends when stopped, e.g. at the undefined opcode
+        let mut steps = cpu.iter(&mut mem);
+        assert_eq!((steps.next(), steps.stop_reason()), (None, Some(StopReason::UndefinedOpcode { pc: 0xE007, opcode: 0x02 })));
+        cpu.pc = ADDR_RESET_VECTOR;
+        cpu.add_hook(HookPoint::Before, |cpu, _, _| if cpu.pc == 0xE003 { HookAction::Stop } else { HookAction::Continue });
+        let mut steps = cpu.iter(&mut mem);
+        assert_eq!(steps.by_ref().count(), 2);
+        assert_eq!(steps.stop_reason(), Some(StopReason::Hook { pc: 0xE003 }));
+
+        // an interrupt entered instead of an instruction
+        cpu.nmi();
+        let step = cpu.iter(&mut mem).next().unwrap();
+        assert_eq!((step.addr, step.opcode, step.interrupt, step.cycles), (0xE003, None, Some(Interrupt::Nmi), INTERRUPT_CYCLES));
+    }
+
+    #[test]
+    fn brk_halt() {
+        let (mut cpu, mut mem) = setup();
+
+        mem.write_u8(ADDR_RESET_VECTOR, NOP.into());
+        mem.write_u8(None, BRK.into());
+        assert_eq!(cpu.exec(&mut mem, 100), Some(StopReason::IrqVectorUninitialized { pc: ADDR_RESET_VECTOR + 1 }));
+
+        cpu.reset(&mut mem);
+        mem.write_u16(VECTOR_IRQ, 0xA000);
+        mem.write_u8(ADDR_RESET_VECTOR, BRK.into());
+        assert_eq!(cpu.exec(&mut mem, 100), Some(StopReason::IrqHandlerBrk { pc: ADDR_RESET_VECTOR }));
+    }
+
+    #[test]
+    fn dma_stall() {
+        let (mut cpu, mut mem) = setup();
+        let base = crate::dma::DMA_BASE_DEFAULT;
+        mem.attach_device(Box::new(crate::dma::Dma::create("dma", base).unwrap()));
+
+        mem.write_u8(0x0200, 0xAA);
+        mem.write_u16(base, 0x0200);            // source
+        mem.write_u16(None, 0x0300);            // destination
+        mem.write_u16(None, 0x0001);            // length
+
+        mem.write_u8(ADDR_RESET_VECTOR, LDA_IMM.into());
+        mem.write_u8(None, crate::dma::CTRL_START);
+        mem.write_u8(None, STA_ABS.into());
+        mem.write_u16(None, base + crate::dma::REG_CTRL);
+
+        cpu.exec(&mut mem, 1);
+        cpu.exec(&mut mem, 1);
+
+        assert_eq!(mem.read_u8(0x0300), 0xAA);
+        assert_eq!(cpu.cycles, CYCLES_AFTER_RESET
+            + Instruction::from_opcode(LDA_IMM).unwrap().cycles as u64
+            + Instruction::from_opcode(STA_ABS).unwrap().cycles as u64
+            + crate::dma::DMA_CYCLES_PER_BYTE);
+    }
+
     #[test]
     fn ins_adcsbc() {
         let (mut cpu, mut mem) = setup();
@@ -1032,6 +3193,19 @@ mod tests {
         mem.write_u16(None, target_addr);
         cpu.exec(&mut mem, 1);
         assert_eq!(cpu.pc, target_addr_ind);
+
+        // JMP ($xxFF) reads the high byte from the start of the same page, except on the 65C02
+        for variant in [Variant::Nmos6502, Variant::Ricoh2A03, Variant::Cmos65C02] {
+            cpu.set_variant(variant);
+            cpu.reset(&mut mem);
+            mem.write_u8(0x02FF, 0x34);
+            mem.write_u8(0x0200, 0x12);
+            mem.write_u8(0x0300, 0xE0);
+            mem.write_u8(ADDR_RESET_VECTOR, JMP_IND.into());
+            mem.write_u16(None, 0x02FF);
+            cpu.exec(&mut mem, 1);
+            assert_eq!(cpu.pc, if variant == Variant::Cmos65C02 { 0xE034 } else { 0x1234 }, "{variant:?}");
+        }
     }
 
     #[test]
@@ -1622,7 +3796,7 @@ mod tests {
 
         assert_eq!(cpu.pc, addr);
         assert_eq!(cpu.sp, sp_orig - 2 /* return addr */);
-        assert_eq!(mem.read_u16(cpu.addr_stack(cpu.sp + 2)), ADDR_RESET_VECTOR + 2);
+        assert_eq!(mem.read_u16(cpu.addr_stack(cpu.sp + 1)), ADDR_RESET_VECTOR + 2);
 
 
         let sp_orig = cpu.sp;
@@ -1634,6 +3808,32 @@ mod tests {
         assert_eq!(cpu.sp, sp_orig + 2 /* return addr */);
     }
 
+    #[test]
+    fn pc_wrap() {
+        let (mut cpu, mut mem) = setup();
+
+        // an instruction at $FFFF continues at $0000
+        mem.write_u8(0xFFFF, NOP.into());
+        cpu.pc = 0xFFFF;
+        cpu.exec(&mut mem, 1);
+        assert_eq!(cpu.pc, 0x0000);
+
+        // RTS to $FFFF pops $FFFE and adds 1
+        mem.write_u8(ADDR_RESET_VECTOR, RTS.into());
+        cpu.pc = ADDR_RESET_VECTOR;
+        cpu.stack_push_u16(&mut mem, 0xFFFE);
+        cpu.exec(&mut mem, 1);
+        assert_eq!(cpu.pc, 0xFFFF);
+
+        // JSR ending at $FFFF pushes $FFFF as its return address - 1
+        mem.write_u8(0xFFFD, JSR_ABS.into());
+        mem.write_u16(0xFFFE, ADDR_RESET_VECTOR);
+        cpu.pc = 0xFFFD;
+        cpu.exec(&mut mem, 1);
+        assert_eq!(cpu.pc, ADDR_RESET_VECTOR);
+        assert_eq!(mem.read_u16(cpu.addr_stack(cpu.sp.wrapping_add(1))), 0xFFFF);
+    }
+
     #[test]
     fn ins_brkrti() {
         let (mut cpu, mut mem) = setup();
@@ -1657,7 +3857,7 @@ mod tests {
         assert_eq!(cpu.pc, addr);
         assert_eq!(cpu.sp, sp_orig - 3 /* SR and return address */);
         assert_eq!(StatusFlags::from_bits_truncate(mem.read_u8(cpu.addr_stack(cpu.sp + 1))), StatusFlags::RESERVED | StatusFlags::B);
-        assert_eq!(mem.read_u16(cpu.addr_stack(cpu.sp + 3)), ADDR_RESET_VECTOR + 2);
+        assert_eq!(mem.read_u16(cpu.addr_stack(cpu.sp + 2)), ADDR_RESET_VECTOR + 2);
 
 
         let sp_orig = cpu.sp;
@@ -0,0 +1,177 @@
+// Cartridge images in the CRT container format of VICE: a header followed by CHIP packets, each holding the contents
+// of a ROM, RAM or flash chip with the bank and address it appears at. All values are big-endian.
+//
+//   header  signature (16 bytes, e.g. "C64 CARTRIDGE   "), header length u32, version u16, hardware type u16,
+//           EXROM u8, GAME u8, 6 reserved bytes, name (32 bytes, zero-padded)
+//   CHIP    "CHIP", packet length u32 including this 16-byte header, chip type u16 (0 ROM, 1 RAM, 2 flash),
+//           bank u16, load address u16, size u16, contents
+
+use std::fs;
+
+use crate::rom::{BankedRom, RomImage, OPEN_BUS};
+
+pub const CRT_BANK_SELECT: u16 = 0xDE00;    // I/O 1, where bank-switching cartridges (e.g. Ocean, Magic Desk) take the bank
+
+const SIGNATURES: [(&[u8; 16], &str); 5] = [
+    (b"C64 CARTRIDGE   ", "C64"), (b"C128 CARTRIDGE  ", "C128"), (b"CBM2 CARTRIDGE  ", "CBM-II"),
+    (b"VIC20 CARTRIDGE ", "VIC-20"), (b"PLUS4 CARTRIDGE ", "Plus/4"),
+];
+const HEADER_LEN: usize = 0x40;
+const CHIP_HEADER_LEN: usize = 0x10;
+const WINDOW_SIZE_MAX: usize = 0x4000;      // bank window of the banked ROM
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ChipType {
+    Rom,
+    Ram,
+    Flash,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Chip {
+    pub chip_type: ChipType,
+    pub bank: u16,
+    pub addr: u16,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Cartridge {
+    pub machine: &'static str,
+    pub version: u16,
+    pub hardware_type: u16,     // 0 for a plain cartridge, otherwise the bank-switching scheme
+    pub exrom: bool,
+    pub game: bool,
+    pub name: String,
+    pub chips: Vec<Chip>,       // in file order
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+impl Cartridge {
+    pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+        let machine = SIGNATURES.iter()
+            .find(|(signature, _)| bytes.starts_with(*signature))
+            .map(|(_, machine)| *machine)
+            .ok_or("Not a CRT cartridge image")?;
+        if bytes.len() < HEADER_LEN {
+            return Err(String::from("Truncated CRT header"));
+        }
+        let header_len = (u32_at(bytes, 0x10) as usize).max(HEADER_LEN);
+        let name = &bytes[0x20..0x40];
+        let mut cartridge = Self {
+            machine,
+            version: u16_at(bytes, 0x14),
+            hardware_type: u16_at(bytes, 0x16),
+            exrom: bytes[0x18] != 0,
+            game: bytes[0x19] != 0,
+            name: String::from_utf8_lossy(&name[..name.iter().position(|&byte| byte == 0).unwrap_or(name.len())]).trim_end().to_owned(),
+            chips: Vec::new(),
+        };
+
+        let mut offset = header_len;
+        while offset < bytes.len() {
+            let error = |message: &str| format!("{message} in CHIP packet at offset ${offset:X}");
+            let packet = &bytes[offset..];
+            if packet.len() < CHIP_HEADER_LEN || &packet[..4] != b"CHIP" {
+                return Err(error("Missing CHIP signature or truncated header"));
+            }
+            let chip_type = match u16_at(packet, 0x08) {
+                0 => ChipType::Rom,
+                1 => ChipType::Ram,
+                2 => ChipType::Flash,
+                chip_type => return Err(error(&format!("Unknown chip type {chip_type}"))),
+            };
+            let size = u16_at(packet, 0x0E) as usize;
+            let Some(data) = packet.get(CHIP_HEADER_LEN..CHIP_HEADER_LEN + size) else {
+                return Err(error("Truncated contents"));
+            };
+            cartridge.chips.push(Chip { chip_type, bank: u16_at(packet, 0x0A), addr: u16_at(packet, 0x0C), data: data.to_vec() });
+
+            // the packet length usually equals header plus contents, but is authoritative if larger
+            offset += (u32_at(packet, 0x04) as usize).max(CHIP_HEADER_LEN + size);
+        }
+        Ok(cartridge)
+    }
+
+    pub fn load_from_file(filename: &str) -> Result<Self, String> {
+        let bytes = fs::read(filename).map_err(|error| format!("{filename}: {error}"))?;
+        Self::parse(&bytes).map_err(|error| format!("{filename}: {error}"))
+    }
+
+    // address and size of the smallest window covering all chips, e.g. $8000 and 16K for two 8K chips per bank
+    pub fn window(&self) -> Result<(u16, u16), String> {
+        let start = self.chips.iter().map(|chip| chip.addr as usize).min().ok_or("Cartridge contains no chips")?;
+        let end = self.chips.iter().map(|chip| chip.addr as usize + chip.data.len()).max().unwrap_or(start);
+        match end - start {
+            0 => Err(String::from("Cartridge chips are empty")),
+            size if size <= WINDOW_SIZE_MAX && end <= 0x10000 => Ok((start as u16, size as u16)),
+            _ => Err(format!("Cartridge chips span ${:04X}-${:04X}, more than a {}K bank window", start, end - 1, WINDOW_SIZE_MAX / 1024)),
+        }
+    }
+
+    // banked ROM with each chip at its bank and address in the window; uncovered parts read as open bus.
+    // RAM chips are mapped with their initial contents and are not writable.
+    pub fn banked_rom(&self, bank_select: u16) -> Result<BankedRom, String> {
+        let (base, bank_size) = self.window()?;
+        let banks = self.chips.iter().map(|chip| chip.bank as usize + 1).max().unwrap_or(1);
+        let mut image = vec![OPEN_BUS; banks * bank_size as usize];
+        for chip in &self.chips {
+            let offset = chip.bank as usize * bank_size as usize + (chip.addr - base) as usize;
+            image[offset..offset + chip.data.len()].copy_from_slice(&chip.data);
+        }
+        Ok(BankedRom::create(RomImage::from_bytes(image), base, bank_size, bank_select))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn crt(hardware_type: u16, chips: &[(u16, u16, &[u8])]) -> Vec<u8> {
+        let mut bytes = b"C64 CARTRIDGE   ".to_vec();
+        bytes.extend(0x40u32.to_be_bytes());
+        bytes.extend(0x0100u16.to_be_bytes());
+        bytes.extend(hardware_type.to_be_bytes());
+        bytes.extend([0, 1, 0, 0, 0, 0, 0, 0]);
+        bytes.extend(b"TEST\0".iter().chain([0; 27].iter()));
+        for (bank, addr, data) in chips {
+            bytes.extend(b"CHIP");
+            bytes.extend((0x10 + data.len() as u32).to_be_bytes());
+            bytes.extend(0u16.to_be_bytes());
+            bytes.extend(bank.to_be_bytes());
+            bytes.extend(addr.to_be_bytes());
+            bytes.extend((data.len() as u16).to_be_bytes());
+            bytes.extend(*data);
+        }
+        bytes
+    }
+
+    #[test]
+    fn parse() {
+        // two banks of 16 bytes: bank 0 in two chips, bank 1 with only its upper half
+        let bytes = crt(5, &[(0, 0x8000, &[1; 8]), (0, 0x8008, &[2; 8]), (1, 0x8008, &[3; 8])]);
+        let cartridge = Cartridge::parse(&bytes).unwrap();
+        assert_eq!((cartridge.machine, cartridge.name.as_str(), cartridge.hardware_type), ("C64", "TEST", 5));
+        assert_eq!((cartridge.exrom, cartridge.game, cartridge.version), (false, true, 0x0100));
+        assert_eq!(cartridge.chips.len(), 3);
+        assert_eq!(cartridge.chips[2], Chip { chip_type: ChipType::Rom, bank: 1, addr: 0x8008, data: vec![3; 8] });
+        assert_eq!(cartridge.window(), Ok((0x8000, 16)));
+
+        let mut rom = cartridge.banked_rom(CRT_BANK_SELECT).unwrap();
+        assert_eq!((rom.banks(), rom.read(0x8000), rom.read(0x800F)), (2, 1, 2));
+        rom.select_bank(1);
+        assert_eq!((rom.read(0x8000), rom.read(0x800F)), (OPEN_BUS, 3));
+
+        assert_eq!(Cartridge::parse(b"NES\x1a").unwrap_err(), "Not a CRT cartridge image");
+        assert_eq!(Cartridge::parse(&bytes[..bytes.len() - 1]).unwrap_err(), "Truncated contents in CHIP packet at offset $70");
+        let spread = Cartridge::parse(&crt(0, &[(0, 0x8000, &[0; 8]), (0, 0xE000, &[0; 8])])).unwrap();
+        assert_eq!(spread.window().unwrap_err(), "Cartridge chips span $8000-$E007, more than a 16K bank window");
+    }
+}
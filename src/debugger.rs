@@ -0,0 +1,251 @@
+// Breakpoints and the other conditions stopping execution, for library users as for front-ends like the monitor, e.g.
+//
+//   let mut debugger = Debugger::default();
+//   debugger.add_breakpoint(0xE010);
+//   mem.watchpoints_mut().add(0x0200..=0x02FF, false, true);
+//   if let Some(reason) = debugger.run(&mut cpu, &mut mem, 1_000_000) { ... }
+//
+// They are checked between instructions: execution stops before the instruction at a breakpoint and after one accessing
+// a watched address. The watchpoints are kept by the memory, which sees the accesses. The instruction at PC is always
+// executed, so running again continues where execution stopped.
+
+use core::fmt;
+
+use crate::cpu::{Cpu, Interrupt, StopReason};
+use crate::events::Event;
+use crate::instruction::{Instruction, Mnemonic, Opcode};
+use crate::mem::Memory;
+use crate::prelude::*;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Breakpoint {
+    pub id: usize,
+    pub addr: u16,
+    pub enabled: bool,
+}
+
+// stops before an instruction with the given mnemonic or opcode executes, regardless of address
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum InstructionBreak {
+    Mnemonic(Mnemonic),
+    Opcode(Opcode),
+}
+
+impl InstructionBreak {
+    pub(crate) fn matches(&self, opcode: Opcode) -> bool {
+        match self {
+            Self::Mnemonic(mnemonic) => Instruction::from_opcode(opcode).is_ok_and(|ins| ins.mnemonic == *mnemonic),
+            Self::Opcode(op) => *op == opcode,
+        }
+    }
+}
+
+impl fmt::Display for InstructionBreak {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Mnemonic(mnemonic) => write!(f, "{mnemonic}"),
+            Self::Opcode(opcode) => write!(f, "${:02X}", opcode),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    next_breakpoint_id: usize,
+    instruction_breaks: Vec<InstructionBreak>,
+    interrupt_breaks: Vec<Interrupt>,   // stop on entry into the handler
+    break_cycle: Option<u64>,           // one-shot; cleared once reached
+}
+
+impl Debugger {
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u16) -> usize {
+        self.next_breakpoint_id += 1;
+        self.breakpoints.push(Breakpoint { id: self.next_breakpoint_id, addr, enabled: true });
+        self.next_breakpoint_id
+    }
+
+    pub fn delete_breakpoint(&mut self, id: usize) -> bool {
+        let count = self.breakpoints.len();
+        self.breakpoints.retain(|bp| bp.id != id);
+        self.breakpoints.len() != count
+    }
+
+    pub fn enable_breakpoint(&mut self, id: usize, enabled: bool) -> bool {
+        match self.breakpoints.iter_mut().find(|bp| bp.id == id) {
+            Some(bp) => {
+                bp.enabled = enabled;
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn instruction_breaks(&self) -> &[InstructionBreak] {
+        &self.instruction_breaks
+    }
+
+    pub fn add_instruction_break(&mut self, instruction_break: InstructionBreak) {
+        if !self.instruction_breaks.contains(&instruction_break) {
+            self.instruction_breaks.push(instruction_break);
+        }
+    }
+
+    pub fn remove_instruction_break(&mut self, instruction_break: InstructionBreak) -> bool {
+        let count = self.instruction_breaks.len();
+        self.instruction_breaks.retain(|ib| *ib != instruction_break);
+        self.instruction_breaks.len() != count
+    }
+
+    pub fn clear_instruction_breaks(&mut self) {
+        self.instruction_breaks.clear();
+    }
+
+    pub fn interrupt_breaks(&self) -> &[Interrupt] {
+        &self.interrupt_breaks
+    }
+
+    pub fn set_interrupt_break(&mut self, interrupt: Interrupt, enabled: bool) {
+        self.interrupt_breaks.retain(|ib| *ib != interrupt);
+        if enabled {
+            self.interrupt_breaks.push(interrupt);
+        }
+    }
+
+    pub fn clear_interrupt_breaks(&mut self) {
+        self.interrupt_breaks.clear();
+    }
+
+    pub fn break_cycle(&self) -> Option<u64> {
+        self.break_cycle
+    }
+
+    pub fn set_break_cycle(&mut self, cycle: Option<u64>) {
+        self.break_cycle = cycle;
+    }
+
+    // nothing to check between instructions, apart from watchpoints
+    fn is_empty(&self) -> bool {
+        self.breakpoints.is_empty() && self.instruction_breaks.is_empty() && self.interrupt_breaks.is_empty() && self.break_cycle.is_none()
+    }
+
+    // executes one instruction (or enters an interrupt), stopping like `Cpu::exec` or by a watchpoint or break condition
+    pub fn step(&mut self, cpu: &mut Cpu, mem: &mut Memory) -> Option<StopReason> {
+        let pc = cpu.pc;
+        let reason = cpu.exec(mem, 1);
+        let watch_hit = self.watch_hits(pc, mem).into_iter().next();
+        reason.or(watch_hit).or_else(|| self.check(cpu, mem))
+    }
+
+    // executes at least `max_cycles` cycles unless stopped earlier
+    pub fn run(&mut self, cpu: &mut Cpu, mem: &mut Memory, max_cycles: u64) -> Option<StopReason> {
+        if self.is_empty() && mem.watchpoints().is_empty() {
+            return cpu.exec(mem, max_cycles);
+        }
+        let end = cpu.cycles.saturating_add(max_cycles);
+        while cpu.cycles < end {
+            if let Some(reason) = self.step(cpu, mem) {
+                return Some(reason);
+            }
+        }
+        None
+    }
+
+    // accesses of watched addresses since the last call, made by the instruction at `pc`
+    pub fn watch_hits(&self, pc: u16, mem: &Memory) -> Vec<StopReason> {
+        mem.watchpoints().take_hits().into_iter().map(|hit| StopReason::Watchpoint { pc, hit }).collect()
+    }
+
+    // break conditions before the instruction at PC; a reached cycle break is cleared
+    pub fn check(&mut self, cpu: &Cpu, mem: &Memory) -> Option<StopReason> {
+        if let Some(bp) = self.breakpoints.iter().find(|bp| bp.enabled && bp.addr == cpu.pc) {
+            mem.events().emit(&Event::BreakpointHit { id: bp.id, pc: cpu.pc });
+            return Some(StopReason::Breakpoint { id: bp.id, pc: cpu.pc });
+        }
+        if let Some(interrupt) = cpu.entered_interrupt().filter(|interrupt| self.interrupt_breaks.contains(interrupt)) {
+            return Some(StopReason::InterruptBreak { interrupt, handler: cpu.pc });
+        }
+        if let Some(ib) = cpu.decode(mem.peek(cpu.pc)).and_then(|opcode| self.instruction_breaks.iter().find(|ib| ib.matches(opcode))) {
+            return Some(StopReason::InstructionBreak { pc: cpu.pc, on: *ib });
+        }
+        // instructions are not interrupted, so this stops at the first instruction boundary at or after the cycle
+        if let Some(cycle) = self.break_cycle.filter(|&cycle| cpu.cycles >= cycle) {
+            self.break_cycle = None;
+            return Some(StopReason::CycleBreak { cycle, reached: cpu.cycles, pc: cpu.pc });
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::tests::setup;
+    use crate::cpu::{Variant, VECTOR_NMI};
+    use crate::instruction::Opcode::*;
+    use crate::mem::{Access, ADDR_RESET_VECTOR};
+    use crate::watch::WatchHit;
+
+    use super::*;
+
+    #[test]
+    fn run() {
+        // E000 LDX #$03, E002 DEX, E003 BNE $E002, E005 STX $10, E007 INC $10, E009 JMP $E000
+        let (mut cpu, mut mem) = setup();
+        mem.write_u8(ADDR_RESET_VECTOR, LDX_IMM.into());
+        mem.write_u8(None, 0x03);
+        mem.write_u8(None, DEX.into());
+        mem.write_u8(None, BNE_REL.into());
+        mem.write_u8(None, 0xFD);
+        mem.write_u8(None, STX_ZPG.into());
+        mem.write_u8(None, 0x10);
+        mem.write_u8(None, INC_ZPG.into());
+        mem.write_u8(None, 0x10);
+        mem.write_u8(None, JMP_ABS.into());
+        mem.write_u16(None, ADDR_RESET_VECTOR);
+
+        let mut debugger = Debugger::default();
+        let id = debugger.add_breakpoint(0xE005);
+        assert_eq!(debugger.run(&mut cpu, &mut mem, 1000), Some(StopReason::Breakpoint { id, pc: 0xE005 }));
+        assert_eq!(cpu.x, 0x00);
+
+        // continuing executes the instruction at the breakpoint
+        mem.watchpoints_mut().add(0x0010..=0x0010, false, true);
+        let hit = WatchHit { id: 1, addr: 0x0010, access: Access::Write, old: 0x00, new: 0x00 };
+        assert_eq!(debugger.run(&mut cpu, &mut mem, 1000), Some(StopReason::Watchpoint { pc: 0xE005, hit }));
+        assert_eq!(debugger.watch_hits(0xE007, &mem), vec![]);
+
+        debugger.add_instruction_break(InstructionBreak::Mnemonic(Mnemonic::JMP));
+        let hit = WatchHit { id: 1, addr: 0x0010, access: Access::Write, old: 0x00, new: 0x01 };
+        assert_eq!(debugger.step(&mut cpu, &mut mem), Some(StopReason::Watchpoint { pc: 0xE007, hit }));
+        assert_eq!(debugger.check(&cpu, &mem), Some(StopReason::InstructionBreak { pc: 0xE009, on: InstructionBreak::Mnemonic(Mnemonic::JMP) }));
+        debugger.clear_instruction_breaks();
+
+        assert!(debugger.enable_breakpoint(id, false));
+        debugger.set_break_cycle(Some(cpu.cycles + 10));
+        let Some(StopReason::CycleBreak { cycle, reached, .. }) = debugger.run(&mut cpu, &mut mem, 1000) else { panic!("no cycle break") };
+        assert!(reached >= cycle && debugger.break_cycle().is_none());
+
+        cpu.nmi();
+        mem.write_u16(VECTOR_NMI, 0xE100);
+        debugger.set_interrupt_break(Interrupt::Nmi, true);
+        assert_eq!(debugger.step(&mut cpu, &mut mem), Some(StopReason::InterruptBreak { interrupt: Interrupt::Nmi, handler: 0xE100 }));
+        assert!(debugger.delete_breakpoint(id) && !debugger.delete_breakpoint(id));
+    }
+
+    #[test]
+    fn instruction_break_variant() {
+        // E000 PHX on the 65C02, undefined on the NMOS 6502
+        let (mut cpu, mut mem) = setup();
+        mem.write_u8(ADDR_RESET_VECTOR, PHX.into());
+
+        let mut debugger = Debugger::default();
+        debugger.add_instruction_break(InstructionBreak::Opcode(PHX));
+        assert_eq!(debugger.check(&cpu, &mem), None);
+        cpu.set_variant(Variant::Cmos65C02);
+        assert_eq!(debugger.check(&cpu, &mem), Some(StopReason::InstructionBreak { pc: 0xE000, on: InstructionBreak::Opcode(PHX) }));
+    }
+}
@@ -0,0 +1,192 @@
+// Memory-mapped peripherals on the bus
+//
+// Devices are attached with `Memory::attach_device` and take precedence over RAM in their address range (a banked ROM
+// takes precedence over devices). After every instruction and interrupt entry the CPU ticks them with the cycles
+// spent. Their interrupt outputs are combined with the CPU's IRQ line (wired-OR); NMI is taken on the rising edge of
+// any device's NMI output. A device may halt the CPU after an instruction by holding RDY low or request a DMA transfer.
+
+use core::any::Any;
+use core::ops::RangeInclusive;
+#[cfg(feature = "std")]
+use std::io::{self, Read, Write};
+
+use crate::dma::DmaTransfer;
+
+// `Any` allows getting the concrete device back from the bus, e.g. to drive its input pins
+pub trait Device: Any {
+    // shown as memory region and in the monitor; unique among attached devices
+    fn name(&self) -> &str;
+
+    // addresses of the registers
+    fn range(&self) -> RangeInclusive<u16>;
+
+    // a read by the CPU, which may have side effects such as acknowledging an interrupt
+    fn read(&mut self, addr: u16) -> u8;
+
+    // what `read` would return, without side effects (e.g. for the monitor and traces)
+    fn peek(&self, addr: u16) -> u8;
+
+    fn write(&mut self, addr: u16, value: u8);
+
+    // `cycles` CPU cycles have passed
+    fn tick(&mut self, _cycles: u64) {}
+
+    // interrupt outputs, as line levels
+    fn irq(&self) -> bool {
+        false
+    }
+
+    fn nmi(&self) -> bool {
+        false
+    }
+
+    // cycles the CPU is halted (RDY held low) after the instruction just executed, e.g. until the end of a scanline
+    fn halt(&mut self) -> u64 {
+        0
+    }
+
+    // a block copy requested as bus master (see `dma`), performed by the bus after the instruction with the CPU
+    // stalled; `transfer_done` then acknowledges it
+    fn pending_transfer(&self) -> Option<DmaTransfer> {
+        None
+    }
+
+    fn transfer_done(&mut self) {}
+
+    // back to the power-on state
+    fn reset(&mut self) {}
+
+    // registers, timers and interrupt state for machine states, see `state`; restored into the attached device with
+    // the same name. Devices without such state (or not supporting it yet) save nothing
+    #[cfg(feature = "std")]
+    fn save_state(&self, _writer: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+
+    #[cfg(feature = "std")]
+    fn load_state(&mut self, _reader: &mut dyn Read) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::{Cpu, Interrupt, VECTOR_IRQ};
+    use crate::instruction::Opcode::*;
+    use crate::mem::{Memory, ADDR_RESET_VECTOR};
+    use crate::prelude::*;
+
+    use super::*;
+
+    // interval timer: writing the period to $D000 starts it, reading $D001 acknowledges the IRQ
+    #[derive(Default)]
+    struct Timer {
+        period: u64,
+        elapsed: u64,
+        expired: bool,
+    }
+
+    impl Device for Timer {
+        fn name(&self) -> &str {
+            "timer"
+        }
+
+        fn range(&self) -> RangeInclusive<u16> {
+            0xD000..=0xD001
+        }
+
+        fn read(&mut self, addr: u16) -> u8 {
+            let value = self.peek(addr);
+            if addr == 0xD001 {
+                self.expired = false;
+            }
+            value
+        }
+
+        fn peek(&self, addr: u16) -> u8 {
+            match addr {
+                0xD000 => self.period as u8,
+                _ => self.expired as u8,
+            }
+        }
+
+        fn write(&mut self, addr: u16, value: u8) {
+            if addr == 0xD000 {
+                (self.period, self.elapsed) = (value as u64, 0);
+            }
+        }
+
+        fn tick(&mut self, cycles: u64) {
+            self.elapsed += cycles;
+            if self.period > 0 && self.elapsed >= self.period {
+                self.elapsed %= self.period;
+                self.expired = true;
+            }
+        }
+
+        fn irq(&self) -> bool {
+            self.expired
+        }
+
+        fn reset(&mut self) {
+            *self = Self::default();
+        }
+    }
+
+    #[test]
+    fn dispatch() {
+        let mut mem = Memory::create();
+        mem.attach_device(Box::<Timer>::default());
+        assert_eq!(mem.regions().last().map(|region| (region.name.as_str(), region.range.clone())), Some(("timer", 0xD000..=0xD001)));
+
+        mem.write_u8(0xD000, 10);
+        mem.tick_devices(12);
+        assert!(mem.device_irq() && !mem.device_nmi());
+        assert_eq!((mem.read_u8(0xD000), mem.peek(0xD001)), (10, 1));
+        assert!(mem.device_irq(), "peeking has no side effects");
+        assert_eq!(mem.read_u8(0xD001), 1);
+        assert!(!mem.device_irq());
+
+        mem.tick_devices(20);
+        mem.reset_devices();
+        assert_eq!((mem.peek(0xD000), mem.device_irq()), (0, false));
+
+        assert!(mem.detach_device("timer").is_some() && mem.detach_device("timer").is_none());
+        assert_eq!((mem.devices().count(), mem.peek(0xD000)), (0, 0));
+    }
+
+    #[test]
+    fn irq() {
+        // E000 LDA #20, E002 STA $D000, E005 CLI, E006 JMP $E006; IRQ handler F000 LDA $D001, F003 RTI
+        let mut mem = Memory::create();
+        let mut cpu = Cpu::create();
+        mem.attach_device(Box::<Timer>::default());
+        cpu.reset(&mut mem);
+        mem.write_u8(ADDR_RESET_VECTOR, LDA_IMM.into());
+        mem.write_u8(None, 20);
+        mem.write_u8(None, STA_ABS.into());
+        mem.write_u16(None, 0xD000);
+        mem.write_u8(None, CLI.into());
+        mem.write_u8(None, JMP_ABS.into());
+        mem.write_u16(None, 0xE006);
+        mem.write_u16(VECTOR_IRQ, 0xF000);
+        mem.write_u8(0xF000, LDA_ABS.into());
+        mem.write_u16(None, 0xD001);
+        mem.write_u8(None, RTI.into());
+
+        let mut taken = Vec::new();
+        while taken.len() < 2 {
+            cpu.exec(&mut mem, 1);
+            if cpu.entered_interrupt() == Some(Interrupt::Irq) {
+                taken.push(cpu.cycles);
+            }
+        }
+
+        // the timer started by STA (ticked with its cycles, from cycle 9) expires during the JMP ending at cycle 30, so the
+        // IRQ entry ends at cycle 37; the handler acknowledges it and the next expiry is taken after the JMP ending at 50
+        assert_eq!(taken, vec![37, 57]);
+        assert!(mem.device_irq());
+        cpu.exec(&mut mem, 1);
+        assert!(!mem.device_irq());
+    }
+}
@@ -0,0 +1,542 @@
+// Disassembly of memory into structured lines with the instruction set of the NMOS 6502 or, if set, of the 65C02;
+// memory is only peeked, nothing is executed
+
+use alloc::collections::BTreeMap;
+use core::fmt;
+use core::ops::RangeInclusive;
+
+use crate::coverage::Coverage;
+use crate::cpu::Variant;
+use crate::expr::Radix;
+use crate::instruction::{AddressingMode, Instruction, Mnemonic, Opcode};
+use crate::mem::Memory;
+use crate::symbols::SymbolTable;
+use crate::prelude::*;
+
+const LISTING_BYTES_PER_ROW: usize = 3;
+const DATA_BYTES_PER_LINE: usize = 8;
+
+// how undocumented opcodes of the NMOS 6502 are shown
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum IllegalOpcodes {
+    #[default]
+    Data,       // .byte $A7
+    Plain,      // LAX $12
+    Marked,     // *LAX $12
+}
+
+// conventions of the text output; different tools expect different ones
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Format {
+    pub bytes: bool,                    // raw byte column
+    pub lowercase: bool,                // mnemonics in lowercase
+    pub illegal: IllegalOpcodes,
+    pub radix: Radix,                   // addresses and operands
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Self { bytes: true, lowercase: false, illegal: IllegalOpcodes::Data, radix: Radix::Hex }
+    }
+}
+
+impl Format {
+    // "$0A" and "$E000" for hex with `digits` digits, "10" and "57344" for decimal
+    pub fn number(&self, value: u16, digits: usize) -> String {
+        match self.radix {
+            Radix::Hex => format!("${value:0digits$X}"),
+            Radix::Decimal => value.to_string(),
+        }
+    }
+
+    fn addr(&self, addr: u16) -> String {
+        match self.radix {
+            Radix::Hex => format!("{addr:04X}"),
+            Radix::Decimal => format!("{addr:05}"),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Line {
+    pub addr: u16,
+    pub bytes: Vec<u8>,                 // opcode and operands
+    pub mnemonic: Option<Mnemonic>,     // None for data (.byte), e.g. if the byte is not a known opcode
+    pub illegal: Option<&'static str>,  // name of an undocumented opcode, unless shown as data
+    pub operands: String,               // assembler syntax; branch targets as absolute address
+    pub cycles: u8,                     // base cycles, without page crossing or branch penalties
+    pub label: Option<String>,          // symbol or generated label at `addr`
+    pub format: Format,
+    pub cmos: bool,                     // decoded with the instructions added by the 65C02
+}
+
+impl Line {
+    // address of the following instruction
+    pub fn next_addr(&self) -> u16 {
+        self.addr.wrapping_add(self.bytes.len() as u16)
+    }
+
+    // additional cycles on top of `cycles`, e.g. "+1 page"
+    pub fn cycle_note(&self) -> &'static str {
+        match Instruction::decode(self.bytes[0], self.cmos) {
+            Some(ins) => cycle_note(&ins, self.addr, &self.bytes),
+            None => "",
+        }
+    }
+
+    // mnemonic and operands, e.g. "LDA #$05"
+    pub fn text(&self) -> String {
+        let mnemonic = match (self.mnemonic, self.illegal) {
+            (Some(mnemonic), _) => mnemonic.to_string(),
+            (None, Some(name)) if self.format.illegal == IllegalOpcodes::Marked => format!("*{name}"),
+            (None, Some(name)) => name.to_owned(),
+            (None, None) => return format!(".byte {}", self.operands).trim_end().to_owned(),
+        };
+        let mnemonic = if self.format.lowercase { mnemonic.to_lowercase() } else { mnemonic };
+        format!("{mnemonic} {}", self.operands).trim_end().to_owned()
+    }
+
+    // listing line with cycles: "E005  D0 F9     2 +1 taken  BNE $E000"
+    pub fn listing(&self) -> String {
+        let cycles = (self.mnemonic.is_some() || self.illegal.is_some()).then(|| (self.cycles, self.cycle_note()));
+        let bytes: &[u8] = if self.format.bytes { &self.bytes } else { &[] };
+        let listing = format_listing(&self.format, Some(self.addr), bytes, cycles, &self.text());
+        match &self.label {
+            Some(label) => format!("{}\n{listing}", format_listing(&self.format, None, &[], None, &format!("{label}:"))),
+            None => listing,
+        }
+    }
+}
+
+// "E000  A9 05     LDA #$05", unknown opcodes as ".byte $xx"; the byte column shows at most three bytes.
+// A label is shown on a row of its own before.
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes = match self.format.bytes {
+            true => {
+                let bytes: Vec<String> = self.bytes.iter().take(LISTING_BYTES_PER_ROW).map(|byte| format!("{byte:02X}")).collect();
+                format!("{:<8}  ", bytes.join(" "))
+            },
+            false => String::new(),
+        };
+        let addr = self.format.addr(self.addr);
+        if let Some(label) = &self.label {
+            writeln!(f, "{:width$}{label}:", "", width = addr.len() + 2 + bytes.len())?;
+        }
+        write!(f, "{addr}  {bytes}{}", self.text())
+    }
+}
+
+#[derive(Default)]
+pub struct Disassembler<'a> {
+    coverage: Option<&'a Coverage>,     // only executed addresses are disassembled as code
+    symbols: Option<&'a SymbolTable>,   // names for addresses in operands and labels
+    auto_labels: bool,                  // name jump and branch targets without symbol within a range
+    format: Format,
+    variant: Variant,                   // instruction set
+}
+
+impl<'a> Disassembler<'a> {
+    pub fn create() -> Self {
+        Self { coverage: None, symbols: None, auto_labels: false, format: Format::default(), variant: Variant::default() }
+    }
+
+    // decodes the instructions of `variant`, usually `cpu.variant()`
+    pub fn set_variant(&mut self, variant: Variant) {
+        self.variant = variant;
+    }
+
+    pub fn set_format(&mut self, format: Format) {
+        self.format = format;
+    }
+
+    pub fn set_symbols(&mut self, symbols: Option<&'a SymbolTable>) {
+        self.symbols = symbols;
+    }
+
+    // labels like "L_E010" for targets of JMP, JSR and branches within the disassembled range
+    pub fn set_auto_labels(&mut self, enabled: bool) {
+        self.auto_labels = enabled;
+    }
+
+    // separates code from data: bytes not executed according to `coverage` become .byte lines
+    pub fn set_coverage(&mut self, coverage: Option<&'a Coverage>) {
+        self.coverage = coverage;
+    }
+
+    // `count` consecutive instructions starting at `addr`
+    pub fn disassemble(&self, mem: &Memory, addr: u16, count: usize) -> Vec<Line> {
+        let mut lines = Vec::with_capacity(count);
+        let mut addr = addr;
+        for _ in 0..count {
+            let line = self.line_at(mem, addr);
+            addr = line.next_addr();
+            lines.push(line);
+        }
+        lines
+    }
+
+    // instructions starting within `range`; the last one may extend past its end
+    pub fn disassemble_range(&self, mem: &Memory, range: RangeInclusive<u16>) -> Vec<Line> {
+        let lines = self.decode_range(mem, &range, &BTreeMap::new());
+        if !self.auto_labels {
+            return lines;
+        }
+
+        // a target in the middle of an instruction keeps its address
+        let starts: Vec<u16> = lines.iter().map(|line| line.addr).collect();
+        let labels = lines.iter()
+            .filter_map(jump_target)
+            .filter(|target| starts.binary_search(target).is_ok() && self.symbol(*target).is_none())
+            .map(|target| (target, format!("L_{target:04X}")))
+            .collect();
+        self.decode_range(mem, &range, &labels)
+    }
+
+    pub fn line_at(&self, mem: &Memory, addr: u16) -> Line {
+        self.decode(mem, addr, &BTreeMap::new())
+    }
+
+    fn decode_range(&self, mem: &Memory, range: &RangeInclusive<u16>, labels: &BTreeMap<u16, String>) -> Vec<Line> {
+        let mut lines = Vec::new();
+        let mut addr = *range.start();
+        while range.contains(&addr) {
+            let line = self.decode(mem, addr, labels);
+            let next_addr = line.next_addr();
+            lines.push(line);
+            if next_addr < addr {
+                break;  // wrapped around at $FFFF
+            }
+            addr = next_addr;
+        }
+        lines
+    }
+
+    fn symbol(&self, addr: u16) -> Option<&str> {
+        self.symbols.and_then(|symbols| symbols.name_at(addr))
+    }
+
+    fn name(&self, addr: u16, labels: &BTreeMap<u16, String>) -> Option<String> {
+        self.symbol(addr).map(String::from).or_else(|| labels.get(&addr).cloned())
+    }
+
+    fn decode(&self, mem: &Memory, addr: u16, labels: &BTreeMap<u16, String>) -> Line {
+        let label = self.name(addr, labels);
+        let cmos = self.variant == Variant::Cmos65C02;
+
+        if let Some(coverage) = self.coverage.filter(|coverage| !coverage.is_executed(addr)) {
+            // data up to the next executed instruction or label
+            let bytes: Vec<u8> = (0..DATA_BYTES_PER_LINE as u16)
+                .map(|i| addr.wrapping_add(i))
+                .take_while(|&data_addr| data_addr == addr || (data_addr > addr && !coverage.is_executed(data_addr) && self.name(data_addr, labels).is_none()))
+                .map(|data_addr| mem.peek(data_addr))
+                .collect();
+            let operands = bytes.iter().map(|byte| self.format.number(*byte as u16, 2)).collect::<Vec<String>>().join(",");
+            return Line { addr, bytes, mnemonic: None, illegal: None, operands, cycles: 0, label, format: self.format, cmos };
+        }
+
+        let opcode = mem.peek(addr);
+        let (mnemonic, illegal, addr_mode, cycles) = match Instruction::decode(opcode, cmos) {
+            Some(ins) => (Some(ins.mnemonic), None, ins.addr_mode, ins.cycles),
+            None => match illegal_opcode(opcode).filter(|_| self.format.illegal != IllegalOpcodes::Data) {
+                Some((name, addr_mode, cycles)) => (None, Some(name), addr_mode, cycles),
+                None => {
+                    let operands = self.format.number(opcode as u16, 2);
+                    return Line { addr, bytes: vec![opcode], mnemonic: None, illegal: None, operands, cycles: 0, label, format: self.format, cmos };
+                },
+            },
+        };
+
+        let bytes: Vec<u8> = (0..addr_mode.instruction_bytes() as u16).map(|i| mem.peek(addr.wrapping_add(i))).collect();
+        let operands = match addr_mode {
+            AddressingMode::REL => {
+                let target = branch_target(addr, bytes[1]);
+                self.name(target, labels).unwrap_or_else(|| self.format.number(target, 4))
+            },
+            _ => format_operands(addr_mode, [mem.peek(addr.wrapping_add(1)), mem.peek(addr.wrapping_add(2))], |addr| self.name(addr, labels), &self.format),
+        };
+
+        Line { addr, bytes, mnemonic, illegal, operands, cycles, label, format: self.format, cmos }
+    }
+}
+
+// destination of a JMP (absolute), JSR or branch
+fn jump_target(line: &Line) -> Option<u16> {
+    let ins = Instruction::decode(line.bytes[0], line.cmos)?;
+    match (ins.opcode, ins.addr_mode) {
+        (Opcode::JMP_ABS | Opcode::JSR_ABS, _) => Some(u16::from_le_bytes([line.bytes[1], line.bytes[2]])),
+        (_, AddressingMode::REL) => Some(branch_target(line.addr, line.bytes[1])),
+        _ => None,
+    }
+}
+
+// additional cycles of the instruction at `addr` with the given bytes: branches taken, indexing across pages
+pub fn cycle_note(ins: &Instruction, addr: u16, bytes: &[u8]) -> &'static str {
+    match ins.addr_mode {
+        AddressingMode::REL if addr.wrapping_add(2) >> 8 != branch_target(addr, bytes[1]) >> 8 => "+2 taken",
+        AddressingMode::REL => "+1 taken",
+        _ if ins.page_cross_penalty() => "+1 page",
+        _ => "",
+    }
+}
+
+// listing row(s) with address (in the radix of `format`), bytes, cycles and note, and text; bytes exceeding the first
+// row continue on further rows
+pub fn format_listing(format: &Format, addr: Option<u16>, bytes: &[u8], cycles: Option<(u8, &str)>, text: &str) -> String {
+    let hex = |bytes: &[u8]| bytes.iter().map(|byte| format!("{byte:02X}")).collect::<Vec<String>>().join(" ");
+    let addr_column = |addr: Option<u16>| addr.map_or(" ".repeat(format.addr(0).len()), |addr| format.addr(addr));
+    let cycles = cycles.map_or(String::new(), |(cycles, note)| format!("{cycles} {note}"));
+
+    let mut rows = bytes.chunks(LISTING_BYTES_PER_ROW);
+    let first = rows.next().unwrap_or_default();
+    let mut listing = format!("{}  {:<8}  {:<10}  {}", addr_column(addr), hex(first), cycles, text).trim_end().to_owned();
+    for (i, row) in rows.enumerate() {
+        let addr = addr.map(|addr| addr.wrapping_add(((i + 1) * LISTING_BYTES_PER_ROW) as u16));
+        listing.push_str(&format!("\n{}  {}", addr_column(addr), hex(row)));
+    }
+    listing
+}
+
+// destination of the branch instruction at `addr`
+pub fn branch_target(addr: u16, offset: u8) -> u16 {
+    addr.wrapping_add(2).wrapping_add(offset as i8 as u16)
+}
+
+// operand bytes as hex and operands in assembler syntax; `symbol` names operand addresses
+pub(crate) fn format_operand_bytes(ins: &Instruction, operand: [u8; 2], symbol: impl Fn(u16) -> Option<String>) -> (String, String) {
+    let oper_bytestr = match ins.bytes() {
+        2 => format!("{:02X}   ", operand[0]),
+        3 => format!("{:02X} {:02X}", operand[0], operand[1]),
+        _ => String::from("     "),
+    };
+
+    (oper_bytestr, format_operands(ins.addr_mode, operand, symbol, &Format::default()))
+}
+
+fn format_operands(addr_mode: AddressingMode, operand: [u8; 2], symbol: impl Fn(u16) -> Option<String>, format: &Format) -> String {
+    let symbol = |addr: u16| match addr_mode {
+        AddressingMode::IMM | AddressingMode::REL => None,
+        _ => symbol(addr),
+    };
+    let oper = match addr_mode.instruction_bytes() {
+        1 => if addr_mode == AddressingMode::ACC { "A".to_owned() } else { String::new() },
+        2 => symbol(operand[0] as u16).unwrap_or_else(|| format.number(operand[0] as u16, 2)),
+        3 => {
+            let value = operand[0] as u16 | (operand[1] as u16) << 8;
+            symbol(value).unwrap_or_else(|| format.number(value, 4))
+        },
+        bytes => panic!("Unexpected number of bytes {bytes} for instruction"),
+    };
+
+    addr_mode.operands().replace("oper", &oper)
+}
+
+// undocumented opcodes of the NMOS 6502 with name, addressing mode and cycles (without page crossing)
+fn illegal_opcode(opcode: u8) -> Option<(&'static str, AddressingMode, u8)> {
+    use AddressingMode::*;
+
+    // combined read-modify-write and ALU operations in the column of opcodes ending in 3, 7, B and F
+    let combined = ["SLO", "RLA", "SRE", "RRA", "SAX", "LAX", "DCP", "ISC"];
+    let info = match opcode {
+        0x02 | 0x12 | 0x22 | 0x32 | 0x42 | 0x52 | 0x62 | 0x72 | 0x92 | 0xB2 | 0xD2 | 0xF2 => ("JAM", IMP, 0),
+        0x1A | 0x3A | 0x5A | 0x7A | 0xDA | 0xFA => ("NOP", IMP, 2),
+        0x80 | 0x82 | 0x89 | 0xC2 | 0xE2 => ("NOP", IMM, 2),
+        0x04 | 0x44 | 0x64 => ("NOP", ZPG, 3),
+        0x14 | 0x34 | 0x54 | 0x74 | 0xD4 | 0xF4 => ("NOP", ZPX, 4),
+        0x0C => ("NOP", ABS, 4),
+        0x1C | 0x3C | 0x5C | 0x7C | 0xDC | 0xFC => ("NOP", ABX, 4),
+        0x0B | 0x2B => ("ANC", IMM, 2),
+        0x4B => ("ALR", IMM, 2),
+        0x6B => ("ARR", IMM, 2),
+        0x8B => ("ANE", IMM, 2),
+        0xAB => ("LXA", IMM, 2),
+        0xCB => ("SBX", IMM, 2),
+        0xEB => ("SBC", IMM, 2),
+        0x93 => ("SHA", IDY, 6),
+        0x9F => ("SHA", ABY, 5),
+        0x9B => ("TAS", ABY, 5),
+        0x9C => ("SHY", ABX, 5),
+        0x9E => ("SHX", ABY, 5),
+        0xBB => ("LAS", ABY, 4),
+        0x97 => ("SAX", ZPY, 4),
+        0xB7 => ("LAX", ZPY, 4),
+        0xBF => ("LAX", ABY, 4),
+        _ if opcode & 0x03 == 0x03 => {
+            let name = combined[opcode as usize >> 5];
+            let read_only = matches!(name, "SAX" | "LAX");
+            match (opcode >> 2) & 0x07 {
+                0 => (name, IDX, if read_only { 6 } else { 8 }),
+                1 => (name, ZPG, if read_only { 3 } else { 5 }),
+                3 => (name, ABS, if read_only { 4 } else { 6 }),
+                4 => (name, IDY, if read_only { 5 } else { 8 }),
+                5 => (name, ZPX, 6),
+                6 => (name, ABY, 7),
+                _ => (name, ABX, 7),
+            }
+        },
+        _ => return None,
+    };
+    Some(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coverage::Coverage;
+    use crate::instruction::Opcode::*;
+    use crate::mem::ADDR_RESET_VECTOR;
+
+    use super::*;
+
+    #[test]
+    fn disassemble() {
+        let mut mem = Memory::create();
+
+        // E000 LDA #$05, E002 STA $0200,X, E005 BNE $E000, E007 ASL A, E008 (unknown), E009 JMP ($FFFC)
+        mem.write_u8(ADDR_RESET_VECTOR, LDA_IMM.into());
+        mem.write_u8(None, 0x05);
+        mem.write_u8(None, STA_ABX.into());
+        mem.write_u16(None, 0x0200);
+        mem.write_u8(None, BNE_REL.into());
+        mem.write_u8(None, 0xF9);
+        mem.write_u8(None, ASL_ACC.into());
+        mem.write_u8(None, 0x0B);
+        mem.write_u8(None, JMP_IND.into());
+        mem.write_u16(None, 0xFFFC);
+
+        let lines = Disassembler::create().disassemble(&mem, ADDR_RESET_VECTOR, 6);
+        assert_eq!(lines[0], Line { addr: 0xE000, bytes: vec![0xA9, 0x05], mnemonic: Some(Mnemonic::LDA), illegal: None, operands: String::from("#$05"), cycles: 2, label: None, format: Format::default(), cmos: false });
+        assert_eq!(lines[1].operands, "$0200,X");
+        assert_eq!((lines[2].operands.as_str(), lines[2].next_addr()), ("$E000", 0xE007));
+        assert_eq!(lines[3].operands, "A");
+        assert_eq!((lines[4].mnemonic, lines[4].operands.as_str(), lines[4].cycles), (None, "$0B", 0));
+        assert_eq!(lines[5].operands, "($FFFC)");
+
+        let text: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+        assert_eq!(text[1], "E002  9D 00 02  STA $0200,X");
+        assert_eq!(text[3], "E007  0A        ASL A");
+        assert_eq!(text[4], "E008  0B        .byte $0B");
+
+        assert_eq!(lines[0].listing(), "E000  A9 05     2           LDA #$05");
+        assert_eq!(lines[2].listing(), "E005  D0 F9     2 +1 taken  BNE $E000");
+        assert_eq!(lines[4].listing(), "E008  0B                    .byte $0B");
+        assert_eq!(format_listing(&Format::default(), Some(0xE0FE), &[1, 2, 3, 4], None, ".byte 1, 2, 3, 4"), "E0FE  01 02 03              .byte 1, 2, 3, 4\nE101  04");
+
+        let lines = Disassembler::create().disassemble_range(&mem, 0xE002..=0xE005);
+        assert_eq!(lines.iter().map(|line| line.addr).collect::<Vec<_>>(), vec![0xE002, 0xE005]);
+        assert_eq!(Disassembler::create().disassemble_range(&mem, 0xFFFE..=0xFFFF).len(), 2);
+    }
+
+    #[test]
+    fn coverage() {
+        let mut mem = Memory::create();
+
+        // E000 JMP $E00C, E003 table (9 bytes), E00C RTS
+        mem.write_u8(ADDR_RESET_VECTOR, JMP_ABS.into());
+        mem.write_u16(None, 0xE00C);
+        for i in 0..9 {
+            mem.write_u8(None, 0x60 + i);
+        }
+        mem.write_u8(None, RTS.into());
+
+        let mut coverage = Coverage::create();
+        coverage.record(0xE000, 3);
+        coverage.record(0xE00C, 1);
+        let mut disassembler = Disassembler::create();
+        disassembler.set_coverage(Some(&coverage));
+
+        let text: Vec<String> = disassembler.disassemble_range(&mem, 0xE000..=0xE00C).iter().map(|line| line.to_string()).collect();
+        assert_eq!(text, vec![
+            "E000  4C 0C E0  JMP $E00C",
+            "E003  60 61 62  .byte $60,$61,$62,$63,$64,$65,$66,$67",
+            "E00B  68        .byte $68",
+            "E00C  60        RTS",
+        ]);
+    }
+
+    #[test]
+    fn labels() {
+        let mut mem = Memory::create();
+
+        // E000 JSR $E008, E003 LDX #$03, E005 DEX, E006 BNE $E005, E008 RTS
+        mem.write_u8(ADDR_RESET_VECTOR, JSR_ABS.into());
+        mem.write_u16(None, 0xE008);
+        for byte in [LDX_IMM.into(), 0x03, DEX.into(), BNE_REL.into(), 0xFD, RTS.into()] {
+            mem.write_u8(None, byte);
+        }
+        mem.symbols_mut().add("done", 0xE008);
+
+        let mut disassembler = Disassembler::create();
+        disassembler.set_symbols(Some(mem.symbols()));
+        assert_eq!(disassembler.line_at(&mem, 0xE000).operands, "done");
+        assert_eq!(disassembler.line_at(&mem, 0xE006).operands, "$E005");
+
+        disassembler.set_auto_labels(true);
+        let lines = disassembler.disassemble_range(&mem, 0xE000..=0xE008);
+        assert_eq!(lines[2].label.as_deref(), Some("L_E005"));
+        assert_eq!(lines[4].label.as_deref(), Some("done"));
+        let text: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+        assert_eq!(text.join("\n"), [
+            "E000  20 08 E0  JSR done",
+            "E003  A2 03     LDX #$03",
+            "                L_E005:",
+            "E005  CA        DEX",
+            "E006  D0 FD     BNE L_E005",
+            "                done:",
+            "E008  60        RTS",
+        ].join("\n"));
+
+        // targets outside the range are not labelled
+        assert!(disassembler.disassemble_range(&mem, 0xE006..=0xE007)[0].operands == "$E005");
+    }
+
+    #[test]
+    fn format() {
+        let mut mem = Memory::create();
+
+        // E000 LDA $0200,X, E003 LAX ($12),Y (undocumented), E005 BNE $E000
+        mem.write_u8(ADDR_RESET_VECTOR, LDA_ABX.into());
+        mem.write_u16(None, 0x0200);
+        for byte in [0xB3, 0x12, BNE_REL.into(), 0xF9] {
+            mem.write_u8(None, byte);
+        }
+
+        let text = |disassembler: &Disassembler| disassembler.disassemble(&mem, 0xE000, 3).iter().map(|line| line.to_string()).collect::<Vec<String>>();
+        let mut disassembler = Disassembler::create();
+        assert_eq!(text(&disassembler)[1], "E003  B3        .byte $B3");
+
+        disassembler.set_format(Format { illegal: IllegalOpcodes::Plain, ..Format::default() });
+        assert_eq!(text(&disassembler)[1..], ["E003  B3 12     LAX ($12),Y", "E005  D0 F9     BNE $E000"]);
+
+        disassembler.set_format(Format { bytes: false, lowercase: true, illegal: IllegalOpcodes::Marked, radix: Radix::Decimal });
+        assert_eq!(text(&disassembler), ["57344  lda 512,X", "57347  *lax (18),Y", "57349  bne 57344"]);
+        let line = disassembler.line_at(&mem, 0xE003);
+        assert_eq!((line.cycles, line.listing().as_str()), (5, "57347            5           *lax (18),Y"));
+
+        // every opcode is either documented or undocumented
+        assert!((0..=0xFF).all(|opcode| Opcode::decode(opcode, false).is_some() != illegal_opcode(opcode).is_some()));
+    }
+
+    #[test]
+    fn cmos() {
+        let mut mem = Memory::create();
+
+        // E000 PHX, E001 STZ $10, E003 BRA $E000, E005 LAX ($12),Y on the NMOS 6502
+        mem.write_u8(ADDR_RESET_VECTOR, PHX.into());
+        for byte in [STZ_ZPG.into(), 0x10, BRA_REL.into(), 0xFB, 0xB3, 0x12] {
+            mem.write_u8(None, byte);
+        }
+
+        let mut disassembler = Disassembler::create();
+        disassembler.set_format(Format { illegal: IllegalOpcodes::Plain, ..Format::default() });
+        let text = |disassembler: &Disassembler| disassembler.disassemble(&mem, 0xE000, 3).iter().map(|line| line.text()).collect::<Vec<String>>();
+        assert_eq!(text(&disassembler), ["NOP", "NOP $10", "NOP #$FB"]);
+
+        disassembler.set_variant(Variant::Cmos65C02);
+        assert_eq!(text(&disassembler), ["PHX", "STZ $10", "BRA $E000"]);
+        let lines = disassembler.disassemble_range(&mem, 0xE003..=0xE005);
+        assert_eq!((lines[0].listing().as_str(), lines[1].text().as_str(), lines[1].next_addr()), ("E003  80 FB     2 +1 taken  BRA $E000", "NOP", 0xE006));
+
+        // the opcodes the 65C02 leaves unassigned are NOPs
+        assert!((0..=0xFF).all(|opcode| Instruction::decode(opcode, true).is_some()));
+    }
+}
@@ -0,0 +1,158 @@
+// Simple DMA block-copy controller
+//
+// Register layout (offset from base address):
+//   +0  source address LB
+//   +1  source address HB
+//   +2  destination address LB
+//   +3  destination address HB
+//   +4  length LB
+//   +5  length HB
+//   +6  control/status: write bit 0 to start transfer; reads back bit 7 if the last transfer completed
+//
+// The transfer is performed by the bus after the instruction which triggered it and the CPU is stalled (RDY low)
+// for the duration of the transfer.
+
+use core::ops::RangeInclusive;
+#[cfg(feature = "std")]
+use std::io::{self, Read, Write};
+
+use crate::device::Device;
+#[cfg(feature = "std")]
+use crate::state;
+use crate::prelude::*;
+
+pub const DMA_BASE_DEFAULT: u16 = 0xDF00;
+pub const DMA_REGISTERS: u16 = 7;
+pub const DMA_CYCLES_PER_BYTE: u64 = 2;                 // one read and one write cycle per transferred byte
+
+pub const REG_SRC_LB: u16 = 0;
+pub const REG_SRC_HB: u16 = 1;
+pub const REG_DST_LB: u16 = 2;
+pub const REG_DST_HB: u16 = 3;
+pub const REG_LEN_LB: u16 = 4;
+pub const REG_LEN_HB: u16 = 5;
+pub const REG_CTRL: u16 = 6;
+
+pub const CTRL_START: u8 = 0b00000001;
+pub const STATUS_DONE: u8 = 0b10000000;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DmaTransfer {
+    pub src: u16,
+    pub dst: u16,
+    pub len: u16,
+}
+
+impl DmaTransfer {
+    pub fn cycles(&self) -> u64 {
+        self.len as u64 * DMA_CYCLES_PER_BYTE
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Dma {
+    name: String,
+    pub base: u16,
+    src: u16,
+    dst: u16,
+    len: u16,
+    done: bool,
+    pending: bool,
+}
+
+impl Dma {
+    pub fn create(name: &str, base: u16) -> Result<Self, String> {
+        if base.checked_add(DMA_REGISTERS - 1).is_none() {
+            return Err(format!("DMA controller '{name}' does not fit at ${base:04X}"));
+        }
+        Ok(Self {
+            name: String::from(name),
+            base,
+            src: 0,
+            dst: 0,
+            len: 0,
+            done: false,
+            pending: false,
+        })
+    }
+}
+
+impl Device for Dma {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        self.base..=self.base + (DMA_REGISTERS - 1)
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        self.peek(addr)
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match addr - self.base {
+            REG_SRC_LB => (self.src & 0x00FF) as u8,
+            REG_SRC_HB => (self.src >> 8) as u8,
+            REG_DST_LB => (self.dst & 0x00FF) as u8,
+            REG_DST_HB => (self.dst >> 8) as u8,
+            REG_LEN_LB => (self.len & 0x00FF) as u8,
+            REG_LEN_HB => (self.len >> 8) as u8,
+            REG_CTRL if self.done => STATUS_DONE,
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr - self.base {
+            REG_SRC_LB => self.src = (self.src & 0xFF00) | value as u16,
+            REG_SRC_HB => self.src = (self.src & 0x00FF) | ((value as u16) << 8),
+            REG_DST_LB => self.dst = (self.dst & 0xFF00) | value as u16,
+            REG_DST_HB => self.dst = (self.dst & 0x00FF) | ((value as u16) << 8),
+            REG_LEN_LB => self.len = (self.len & 0xFF00) | value as u16,
+            REG_LEN_HB => self.len = (self.len & 0x00FF) | ((value as u16) << 8),
+            REG_CTRL if value & CTRL_START != 0 => {
+                self.pending = true;
+                self.done = false;
+            },
+            _ => {},
+        }
+    }
+
+    fn pending_transfer(&self) -> Option<DmaTransfer> {
+        self.pending.then_some(DmaTransfer { src: self.src, dst: self.dst, len: self.len })
+    }
+
+    fn transfer_done(&mut self) {
+        self.pending = false;
+        self.done = true;
+    }
+
+    fn reset(&mut self) {
+        (self.src, self.dst, self.len, self.done, self.pending) = (0, 0, 0, false, false);
+    }
+
+    #[cfg(feature = "std")]
+    fn save_state(&self, writer: &mut dyn Write) -> io::Result<()> {
+        state::write_u16(writer, self.base)?;
+        state::write_u16(writer, self.src)?;
+        state::write_u16(writer, self.dst)?;
+        state::write_u16(writer, self.len)?;
+        state::write_bool(writer, self.done)?;
+        state::write_bool(writer, self.pending)
+    }
+
+    #[cfg(feature = "std")]
+    fn load_state(&mut self, reader: &mut dyn Read) -> io::Result<()> {
+        let base = state::read_u16(reader)?;
+        if base != self.base {
+            return Err(state::invalid_data(&format!("DMA controller '{}' saved at ${base:04X}, attached at ${:04X}", self.name, self.base)));
+        }
+        self.src = state::read_u16(reader)?;
+        self.dst = state::read_u16(reader)?;
+        self.len = state::read_u16(reader)?;
+        self.done = state::read_bool(reader)?;
+        self.pending = state::read_bool(reader)?;
+        Ok(())
+    }
+}
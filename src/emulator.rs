@@ -0,0 +1,213 @@
+// The emulator as a whole for library users: the machine (CPU, memory map and devices) with the debugger state, set
+// up by `EmulatorBuilder`, e.g.
+//
+//   let mut emulator = EmulatorBuilder::create().load_hex("program.hex")?.build()?;
+//   emulator.add_breakpoint(0xE010);
+//   if let Some(reason) = emulator.run(1_000_000) {
+//       println!("{reason}\n{}", emulator.disassemble(emulator.cpu().pc, 1)[0]);
+//   }
+
+use std::ops::RangeInclusive;
+
+use crate::cpu::{Cpu, StopReason};
+use crate::debugger::Debugger;
+use crate::disasm::{Disassembler, Line};
+use crate::events::Event;
+use crate::machine::Machine;
+use crate::mem::Memory;
+use crate::screen::TextScreen;
+use crate::state;
+use crate::trace::TraceComparison;
+
+pub struct Emulator {
+    machine: Machine,
+    debugger: Debugger,
+}
+
+impl Emulator {
+    pub fn create(machine: Machine) -> Self {
+        Self { machine, debugger: Debugger::default() }
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        &self.machine.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut Cpu {
+        &mut self.machine.cpu
+    }
+
+    pub fn mem(&self) -> &Memory {
+        &self.machine.mem
+    }
+
+    pub fn mem_mut(&mut self) -> &mut Memory {
+        &mut self.machine.mem
+    }
+
+    // breakpoints and other break conditions; watchpoints are kept by the memory
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    pub fn debugger_mut(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
+    // listener for what happens while executing; returns its id for `unsubscribe`
+    pub fn subscribe(&mut self, listener: impl FnMut(&Event) + 'static) -> usize {
+        self.machine.mem.events_mut().subscribe(listener)
+    }
+
+    pub fn unsubscribe(&mut self, id: usize) -> bool {
+        self.machine.mem.events_mut().unsubscribe(id)
+    }
+
+    // both at once, e.g. for the monitor
+    pub fn parts_mut(&mut self) -> (&mut Cpu, &mut Memory) {
+        (&mut self.machine.cpu, &mut self.machine.mem)
+    }
+
+    // the text display of the machine, for a front-end to render
+    pub fn take_screen(&mut self) -> Option<TextScreen> {
+        self.machine.screen.take()
+    }
+
+    // ends the trace, the bus trace and the dump output, flushing their files; returns the trace comparison, whose
+    // `divergence` is none if execution matched the reference trace throughout
+    pub fn stop_traces(&mut self) -> Option<TraceComparison> {
+        let cpu = &mut self.machine.cpu;
+        cpu.stop_trace();
+        cpu.stop_bus_trace();
+        cpu.remove_dump_output();
+        cpu.stop_trace_comparison()
+    }
+
+    pub fn load(&mut self, addr: u16, bytes: &[u8]) -> Result<(), String> {
+        if addr as usize + bytes.len() > 0x10000 {
+            return Err(format!("{} bytes at ${addr:04X} reach beyond $FFFF", bytes.len()));
+        }
+        for (addr, byte) in (addr..=0xFFFF).zip(bytes) {
+            self.machine.mem.write_u8(addr, *byte);
+        }
+        Ok(())
+    }
+
+    // the reset button: CPU and devices restart, memory is kept
+    pub fn reset(&mut self) {
+        self.machine.reset();
+    }
+
+    // one instruction, or the entry into a pending interrupt
+    pub fn step(&mut self) -> Option<StopReason> {
+        self.debugger.step(&mut self.machine.cpu, &mut self.machine.mem)
+    }
+
+    // the instruction at PC is executed even if there is a breakpoint, so that running again continues
+    pub fn run(&mut self, cycles: u64) -> Option<StopReason> {
+        self.debugger.run(&mut self.machine.cpu, &mut self.machine.mem, cycles)
+    }
+
+    pub fn run_until_stop(&mut self) -> StopReason {
+        loop {
+            if let Some(reason) = self.step() {
+                return reason;
+            }
+        }
+    }
+
+    // without side effects on devices
+    pub fn read(&self, addr: u16) -> u8 {
+        self.machine.mem.peek(addr)
+    }
+
+    pub fn read_memory(&self, range: RangeInclusive<u16>) -> Vec<u8> {
+        range.map(|addr| self.machine.mem.peek(addr)).collect()
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        self.machine.mem.write_u8(addr, value);
+    }
+
+    // `count` instructions from `addr`, with the symbols of the memory
+    pub fn disassemble(&self, addr: u16, count: usize) -> Vec<Line> {
+        let mut disassembler = Disassembler::create();
+        disassembler.set_symbols(Some(self.machine.mem.symbols()));
+        disassembler.set_variant(self.machine.cpu.variant());
+        disassembler.disassemble(&self.machine.mem, addr, count)
+    }
+
+    // execution stops (`StopReason::Breakpoint`) before the instruction at `addr`; false if there is one already
+    pub fn add_breakpoint(&mut self, addr: u16) -> bool {
+        if self.debugger.breakpoints().iter().any(|bp| bp.addr == addr) {
+            return false;
+        }
+        self.debugger.add_breakpoint(addr);
+        true
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) -> bool {
+        match self.debugger.breakpoints().iter().find(|bp| bp.addr == addr) {
+            Some(bp) => self.debugger.delete_breakpoint(bp.id),
+            None => false,
+        }
+    }
+
+    // in the order added
+    pub fn breakpoints(&self) -> Vec<u16> {
+        self.debugger.breakpoints().iter().map(|bp| bp.addr).collect()
+    }
+
+    pub fn save_state(&self, filename: &str) -> Result<(), String> {
+        state::save_to_file(&self.machine.cpu, &self.machine.mem, filename).map_err(|error| format!("{filename}: {error}"))
+    }
+
+    pub fn load_state(&mut self, filename: &str) -> Result<(), String> {
+        state::load_from_file(&mut self.machine.cpu, &mut self.machine.mem, filename).map_err(|error| format!("{filename}: {error}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use crate::builder::EmulatorBuilder;
+    use crate::mem::ADDR_RESET_VECTOR;
+
+    use super::*;
+
+    #[test]
+    fn run() {
+        // E000 LDX #$03, E002 DEX, E003 BNE $E002, E005 STX $10, E007 JMP $E007
+        let mut emulator = EmulatorBuilder::create().build().unwrap();
+        emulator.load(ADDR_RESET_VECTOR, &[0xA2, 0x03, 0xCA, 0xD0, 0xFD, 0x86, 0x10, 0x4C, 0x07, 0xE0]).unwrap();
+        assert_eq!(emulator.step(), None);
+        assert_eq!(emulator.cpu().x, 0x03);
+
+        let executed = Rc::new(Cell::new(0));
+        let counter = Rc::clone(&executed);
+        let listener = emulator.subscribe(move |event| if let Event::InstructionExecuted { .. } = event { counter.set(counter.get() + 1) });
+
+        assert!(emulator.add_breakpoint(0xE005) && emulator.add_breakpoint(0xE007) && !emulator.add_breakpoint(0xE005));
+        assert_eq!(emulator.run_until_stop(), StopReason::Breakpoint { id: 1, pc: 0xE005 });
+        assert!(emulator.unsubscribe(listener));
+        assert_eq!(executed.get(), 6);
+        assert!(emulator.remove_breakpoint(0xE005) && !emulator.remove_breakpoint(0xE005));
+        assert_eq!(emulator.run(100), Some(StopReason::Breakpoint { id: 2, pc: 0xE007 }));
+        assert_eq!((emulator.breakpoints(), emulator.read(0x10)), (vec![0xE007], 0x00));
+        assert_eq!(emulator.read_memory(0xE005..=0xE006), vec![0x86, 0x10]);
+        assert_eq!(emulator.disassemble(0xE005, 2).iter().map(|line| line.text()).collect::<Vec<_>>(), vec!["STX $10", "JMP $E007"]);
+
+        emulator.write(0x10, 0x42);
+        emulator.reset();
+        assert_eq!((emulator.cpu().pc, emulator.read(0x10)), (ADDR_RESET_VECTOR, 0x42));
+        assert!(emulator.load(0xFFFF, &[0x01, 0x02]).is_err());
+        emulator.load(0xFFFE, &[0x01, 0x02]).unwrap();
+        assert_eq!(emulator.read_memory(0xFFFE..=0xFFFF), vec![0x01, 0x02]);
+    }
+}
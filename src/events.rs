@@ -0,0 +1,112 @@
+// What happens while executing, sent to listeners subscribed to the memory's event bus, so that front-ends (monitor,
+// tracer, TUI, GUI) follow execution without being part of the execution loop, e.g.
+//
+//   mem.events_mut().subscribe(|event| if let Event::MemoryWrite { addr, new, .. } = event { ... });
+//
+// The bus is kept by the memory, which the CPU, the debugger and the front-ends all have at hand. Listeners are called
+// in the order subscribed, while execution waits.
+
+use core::cell::RefCell;
+
+use crate::cpu::Interrupt;
+use crate::instruction::Opcode;
+use crate::prelude::*;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Event {
+    InstructionExecuted { addr: u16, opcode: Opcode, cycles: u64 },     // cycles including DMA and RDY stalls
+    MemoryWrite { addr: u16, old: u8, new: u8 },                        // by the CPU, not edits by the monitor
+    InterruptTaken { interrupt: Interrupt, handler: u16 },              // hardware interrupt entered; BRK is an instruction
+    BreakpointHit { id: usize, pc: u16 },                               // checked by the debugger
+    Jammed { pc: u16, opcode: u8 },                                     // undefined opcode, which `Cpu::exec` stops at
+}
+
+pub type EventListener = Box<dyn FnMut(&Event)>;
+
+#[derive(Default)]
+pub struct EventBus {
+    listeners: RefCell<Vec<(usize, EventListener)>>,    // emitted through &self, like the memory accesses
+    next_id: usize,
+}
+
+impl EventBus {
+    // returns the listener's id for `unsubscribe`
+    pub fn subscribe(&mut self, listener: impl FnMut(&Event) + 'static) -> usize {
+        self.next_id += 1;
+        self.listeners.get_mut().push((self.next_id, Box::new(listener)));
+        self.next_id
+    }
+
+    pub fn unsubscribe(&mut self, id: usize) -> bool {
+        let listeners = self.listeners.get_mut();
+        let count = listeners.len();
+        listeners.retain(|(listener_id, _)| *listener_id != id);
+        listeners.len() != count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.listeners.borrow().is_empty()
+    }
+
+    pub fn emit(&self, event: &Event) {
+        for (_, listener) in self.listeners.borrow_mut().iter_mut() {
+            listener(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::cpu::tests::setup;
+    use crate::cpu::{StopReason, VECTOR_NMI};
+    use crate::debugger::Debugger;
+    use crate::instruction::Opcode::*;
+    use crate::mem::ADDR_RESET_VECTOR;
+
+    use super::*;
+
+    #[test]
+    fn emit() {
+        // E000 LDA #$01, E002 STA $10, E004 .byte $02; NMI handler at E100
+        let (mut cpu, mut mem) = setup();
+        mem.write_u8(ADDR_RESET_VECTOR, LDA_IMM.into());
+        mem.write_u8(None, 0x01);
+        mem.write_u8(None, STA_ZPG.into());
+        mem.write_u8(None, 0x10);
+        mem.write_u8(None, 0x02);
+        mem.write_u16(VECTOR_NMI, 0xE100);
+
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let received = Rc::clone(&events);
+        let id = mem.events_mut().subscribe(move |event| received.borrow_mut().push(*event));
+
+        cpu.exec(&mut mem, 1);
+        assert_eq!(events.take(), vec![Event::InstructionExecuted { addr: 0xE000, opcode: LDA_IMM, cycles: 2 }]);
+        cpu.exec(&mut mem, 1);
+        assert_eq!(events.take(), vec![
+            Event::MemoryWrite { addr: 0x0010, old: 0x00, new: 0x01 },
+            Event::InstructionExecuted { addr: 0xE002, opcode: STA_ZPG, cycles: 3 },
+        ]);
+
+        let mut debugger = Debugger::default();
+        let bp = debugger.add_breakpoint(0xE100);
+        cpu.nmi();
+        assert_eq!(debugger.step(&mut cpu, &mut mem), Some(StopReason::Breakpoint { id: bp, pc: 0xE100 }));
+        assert_eq!(events.take(), vec![
+            Event::MemoryWrite { addr: 0x01FD, old: 0x00, new: 0xE0 },
+            Event::MemoryWrite { addr: 0x01FC, old: 0x00, new: 0x04 },
+            Event::MemoryWrite { addr: 0x01FB, old: 0x00, new: 0x20 },
+            Event::InterruptTaken { interrupt: Interrupt::Nmi, handler: 0xE100 },
+            Event::BreakpointHit { id: bp, pc: 0xE100 },
+        ]);
+
+        cpu.pc = 0xE004;
+        assert_eq!(cpu.exec(&mut mem, 1), Some(StopReason::UndefinedOpcode { pc: 0xE004, opcode: 0x02 }));
+        assert_eq!(events.take(), vec![Event::Jammed { pc: 0xE004, opcode: 0x02 }]);
+
+        assert!(mem.events_mut().unsubscribe(id) && !mem.events_mut().unsubscribe(id));
+        assert!(mem.events().is_empty());
+    }
+}
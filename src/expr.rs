@@ -0,0 +1,322 @@
+use crate::cpu::Cpu;
+use crate::mem::Memory;
+use crate::symbols::SymbolTable;
+use crate::prelude::*;
+
+// base of numbers without prefix
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Radix {
+    #[default]
+    Decimal,
+    Hex,
+}
+
+// what identifiers and memory references in an expression resolve against; all parts are optional
+#[derive(Clone, Copy, Default)]
+pub struct Env<'a> {
+    pub symbols: Option<&'a SymbolTable>,
+    pub cpu: Option<&'a Cpu>,
+    pub mem: Option<&'a Memory>,
+    pub radix: Radix,
+}
+
+impl<'a> Env<'a> {
+    pub fn create(cpu: &'a Cpu, mem: &'a Memory) -> Self {
+        Self { symbols: Some(mem.symbols()), cpu: Some(cpu), mem: Some(mem), radix: Radix::Decimal }
+    }
+
+    fn identifier(&self, name: &str) -> Result<u16, String> {
+        if let Some(addr) = self.symbols.and_then(|symbols| symbols.lookup(name)) {
+            return Ok(addr);
+        }
+        if let Some(cpu) = self.cpu {
+            match name.to_ascii_uppercase().as_str() {
+                "A" => return Ok(cpu.ac as u16),
+                "X" => return Ok(cpu.x as u16),
+                "Y" => return Ok(cpu.y as u16),
+                "SP" => return Ok(cpu.sp as u16),
+                "PC" => return Ok(cpu.pc),
+                "P" => return Ok(cpu.sr.bits() as u16),
+                _ => {},
+            }
+        }
+        // with hex as default base, "FF" is a number unless it is a symbol
+        if self.radix == Radix::Hex {
+            if let Ok(value) = parse_number(name, Radix::Hex).and_then(to_u16(name)) {
+                return Ok(value);
+            }
+        }
+        Err(format!("Unknown symbol '{name}'"))
+    }
+
+    fn peek(&self, addr: u16) -> Result<u8, String> {
+        self.mem.map(|mem| mem.peek(addr)).ok_or_else(|| String::from("Memory references are not available here"))
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    Number(u16),
+    Identifier(String),
+    Operator(&'static str),
+}
+
+const OPERATORS: [&str; 13] = ["<<", ">>", "+", "-", "*", "/", "&", "|", "^", "(", ")", "[", "]"];
+const UNARY_OPERATORS: [&str; 3] = ["-", "<", ">"];
+
+// number with optional base prefix: $ or 0x hex, % binary, + decimal; otherwise in `radix`
+pub fn parse_number(text: &str, radix: Radix) -> Result<u64, String> {
+    let (digits, base) = if let Some(hex) = text.strip_prefix('$').or_else(|| text.strip_prefix("0x")) {
+        (hex, 16)
+    } else if let Some(bin) = text.strip_prefix('%') {
+        (bin, 2)
+    } else if let Some(dec) = text.strip_prefix('+') {
+        (dec, 10)
+    } else {
+        (text, if radix == Radix::Hex { 16 } else { 10 })
+    };
+    if digits.starts_with('+') {
+        return Err(format!("Invalid number '{text}'"));
+    }
+    u64::from_str_radix(digits, base).map_err(|_| format!("Invalid number '{text}'"))
+}
+
+fn to_u16(text: &str) -> impl Fn(u64) -> Result<u16, String> + '_ {
+    move |value| u16::try_from(value).map_err(|_| format!("Invalid number '{text}'"))
+}
+
+fn tokenize(expr: &str, radix: Radix) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = expr.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        // "+" directly before a digit where an operand is expected marks a decimal number
+        let operand_expected = !matches!(tokens.last(), Some(Token::Number(_) | Token::Identifier(_) | Token::Operator(")" | "]")));
+        let decimal = c == '+' && operand_expected && rest[1..].starts_with(|c: char| c.is_ascii_digit());
+
+        let len = if c == '$' || rest.starts_with("0x") || c == '%' || decimal || c.is_ascii_digit() {
+            let prefix = if rest.starts_with("0x") { 2 } else if c.is_ascii_digit() { 0 } else { 1 };
+            let hex = radix == Radix::Hex || c == '$' || rest.starts_with("0x");
+            let digits = rest[prefix..].find(|c: char| !(c.is_ascii_digit() || (hex && !decimal && c.is_ascii_hexdigit()))).unwrap_or(rest.len() - prefix);
+            let literal = &rest[..prefix + digits];
+            tokens.push(Token::Number(parse_number(literal, radix).and_then(to_u16(literal))?));
+            prefix + digits
+        } else if c.is_ascii_alphabetic() || c == '_' || c == '.' {
+            let len = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.')).unwrap_or(rest.len());
+            tokens.push(Token::Identifier(rest[..len].trim_start_matches('.').to_owned()));
+            len
+        } else if let Some(op) = OPERATORS.iter().chain(UNARY_OPERATORS.iter()).find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Operator(op));
+            op.len()
+        } else {
+            return Err(format!("Unexpected '{c}' in expression '{expr}'"));
+        };
+        rest = rest[len..].trim_start();
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    env: &'a Env<'a>,
+}
+
+impl Parser<'_> {
+    fn peek_operator(&self, ops: &[&str]) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Operator(op)) if ops.contains(op) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        match self.peek_operator(&[op]) {
+            Some(_) => {
+                self.pos += 1;
+                Ok(())
+            },
+            None => Err(format!("Expected '{op}'")),
+        }
+    }
+
+    // binary operators in order of increasing precedence
+    fn binary(&mut self, level: usize) -> Result<u16, String> {
+        const LEVELS: [&[&str]; 6] = [&["|"], &["^"], &["&"], &["<<", ">>"], &["+", "-"], &["*", "/"]];
+
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut value = self.binary(level + 1)?;
+        while let Some(op) = self.peek_operator(LEVELS[level]) {
+            self.pos += 1;
+            let rhs = self.binary(level + 1)?;
+            value = match op {
+                "|" => value | rhs,
+                "^" => value ^ rhs,
+                "&" => value & rhs,
+                "<<" => value.checked_shl(rhs as u32).unwrap_or(0),
+                ">>" => value.checked_shr(rhs as u32).unwrap_or(0),
+                "+" => value.wrapping_add(rhs),
+                "-" => value.wrapping_sub(rhs),
+                "*" => value.wrapping_mul(rhs),
+                "/" => value.checked_div(rhs).ok_or("Division by zero")?,
+                _ => unreachable!(),
+            };
+        }
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> Result<u16, String> {
+        match self.peek_operator(&UNARY_OPERATORS) {
+            Some(op) => {
+                self.pos += 1;
+                let value = self.unary()?;
+                Ok(match op {
+                    "-" => value.wrapping_neg(),
+                    "<" => value & 0x00FF,          // low byte
+                    _ => value >> 8,                // high byte
+                })
+            },
+            None => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<u16, String> {
+        let token = self.tokens.get(self.pos).cloned().ok_or("Unexpected end of expression")?;
+        self.pos += 1;
+
+        match token {
+            Token::Number(value) => Ok(value),
+            Token::Operator("(") => {
+                let value = self.binary(0)?;
+                self.expect(")")?;
+                Ok(value)
+            },
+            // [addr]: little-endian word at addr
+            Token::Operator("[") => {
+                let addr = self.binary(0)?;
+                self.expect("]")?;
+                self.word_at(addr)
+            },
+            // "byte at addr" / "word at addr"
+            Token::Identifier(name) if (name == "byte" || name == "word") && self.tokens.get(self.pos) == Some(&Token::Identifier(String::from("at"))) => {
+                self.pos += 1;
+                let addr = self.unary()?;
+                match name.as_str() {
+                    "byte" => self.env.peek(addr).map(|value| value as u16),
+                    _ => self.word_at(addr),
+                }
+            },
+            Token::Identifier(name) if self.peek_operator(&["("]).is_some() => {
+                self.pos += 1;
+                let addr = self.binary(0)?;
+                self.expect(")")?;
+                match name.as_str() {
+                    "byte" => self.env.peek(addr).map(|value| value as u16),
+                    "word" => self.word_at(addr),
+                    _ => Err(format!("Unknown function '{name}', expected byte() or word()")),
+                }
+            },
+            Token::Identifier(name) => self.env.identifier(&name),
+            Token::Operator(op) => Err(format!("Unexpected '{op}'")),
+        }
+    }
+
+    fn word_at(&self, addr: u16) -> Result<u16, String> {
+        Ok(self.env.peek(addr)? as u16 | (self.env.peek(addr.wrapping_add(1))? as u16) << 8)
+    }
+}
+
+// evaluates expressions like "start+5", "$E000+X", "[$FFFC]", "word($FFFC)+%101" or "byte at $FB"; arithmetic wraps at 16 bits
+pub fn eval(expr: &str, env: &Env) -> Result<u16, String> {
+    let tokens = tokenize(expr, env.radix)?;
+    if tokens.is_empty() {
+        return Err(String::from("Empty expression"));
+    }
+
+    let mut parser = Parser { tokens, pos: 0, env };
+    let value = parser.binary(0)?;
+    match parser.tokens.get(parser.pos) {
+        None => Ok(value),
+        Some(_) => Err(format!("Unexpected trailing input in expression '{expr}'")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn literals_and_operators() {
+        let env = Env::default();
+        assert_eq!(eval("$E000", &env), Ok(0xE000));
+        assert_eq!(eval("0xe000", &env), Ok(0xE000));
+        assert_eq!(eval("%1010", &env), Ok(10));
+        assert_eq!(eval("512", &env), Ok(512));
+        assert_eq!(eval("2+3*4", &env), Ok(14));
+        assert_eq!(eval("(2+3)*4", &env), Ok(20));
+        assert_eq!(eval("$1234 & $FF | 1 << 8", &env), Ok(0x0134));
+        assert_eq!(eval("<$1234", &env), Ok(0x34));
+        assert_eq!(eval(">$1234", &env), Ok(0x12));
+        assert_eq!(eval("0-1", &env), Ok(0xFFFF));
+        assert_eq!(eval("-1", &env), Ok(0xFFFF));
+
+        assert!(eval("", &env).is_err());
+        assert!(eval("$10000", &env).is_err());
+        assert!(eval("1/0", &env).is_err());
+        assert!(eval("(1+2", &env).is_err());
+        assert!(eval("1 2", &env).is_err());
+        assert!(eval("start", &env).is_err());
+        assert!(eval("[$FFFC]", &env).is_err());
+    }
+
+    #[test]
+    fn radix() {
+        let env = Env { radix: Radix::Hex, ..Env::default() };
+        assert_eq!(eval("10", &env), Ok(0x10));
+        assert_eq!(eval("E000", &env), Ok(0xE000));
+        assert_eq!(eval("0E000+ff", &env), Ok(0xE0FF));
+        assert_eq!(eval("+10", &env), Ok(10));
+        assert_eq!(eval("1++10", &env), Ok(0x0B));
+        assert_eq!(eval("%101+$10", &env), Ok(0x15));
+        assert!(eval("1FFFF", &env).is_err());
+        assert!(eval("GG", &env).is_err());
+
+        assert_eq!(eval("+10", &Env::default()), Ok(10));
+        assert_eq!(eval("1+10", &Env::default()), Ok(11));
+        assert!(eval("E000", &Env::default()).is_err());
+
+        assert_eq!(parse_number("1000000", Radix::Decimal), Ok(1_000_000));
+        assert_eq!(parse_number("$10", Radix::Decimal), Ok(16));
+        assert_eq!(parse_number("10", Radix::Hex), Ok(16));
+        assert_eq!(parse_number("+10", Radix::Hex), Ok(10));
+        assert!(parse_number("++10", Radix::Hex).is_err());
+        assert!(parse_number("", Radix::Hex).is_err());
+    }
+
+    #[test]
+    fn symbols_registers_and_memory() {
+        let mut mem = Memory::create();
+        let mut cpu = Cpu::create();
+        cpu.reset(&mut mem);
+        cpu.x = 0x10;
+        mem.symbols_mut().add("start", 0xE000);
+        mem.symbols_mut().add("x", 0x0300);         // symbols take precedence over registers
+        mem.write_u8(0x0010, 0xAB);
+
+        let env = Env::create(&cpu, &mem);
+        assert_eq!(eval("start+5", &env), Ok(0xE005));
+        assert_eq!(eval(".start", &env), Ok(0xE000));
+        assert_eq!(eval("$E000+X", &env), Ok(0xE010));
+        assert_eq!(eval("$E000+x", &env), Ok(0xE300));
+        assert_eq!(eval("PC+sp", &env), Ok(0xE0FD));
+        assert_eq!(eval("[$FFFC]", &env), Ok(0xE000));
+        assert_eq!(eval("word($FFFC)+1", &env), Ok(0xE001));
+        assert_eq!(eval("byte(X)", &env), Ok(0xAB));
+        assert_eq!(eval("word at $FFFC + 1", &env), Ok(0xE001));
+        assert_eq!(eval("byte at $0010", &env), Ok(0xAB));
+        assert!(eval("dword(0)", &env).is_err());
+    }
+}
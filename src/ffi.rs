@@ -0,0 +1,336 @@
+// C interface for embedding the emulator in C/C++ front-ends and other languages (feature "ffi"), declared in
+// include/rust_6502_emu.h:
+//
+//   Emu6502 *emu = emu6502_create();
+//   emu6502_load(emu, 0x0600, program, sizeof program);
+//   Emu6502Registers registers = emu6502_get_registers(emu);
+//   registers.pc = 0x0600;
+//   emu6502_set_registers(emu, &registers);
+//   if (emu6502_run(emu, 10000)) puts(emu6502_stop_reason(emu));
+//   emu6502_destroy(emu);
+//
+// A handle is used by one thread at a time. Nothing unwinds into C: an undefined opcode stops execution like any other
+// `StopReason`, and should the emulator panic nonetheless, a step or run stops with the panic message as reason and
+// other functions return their default (false, 0, NULL). NULL handles are ignored the same way.
+
+use alloc::ffi::CString;
+use core::ffi::{c_char, c_void};
+use core::ptr;
+use core::slice;
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::cpu::{Cpu, HookAction, HookPoint, Registers, StatusFlags};
+use crate::mem::Memory;
+use crate::prelude::*;
+
+pub struct Emu6502 {
+    cpu: Cpu,
+    mem: Memory,
+    stop_reason: Option<CString>,       // of the last step or run, if it stopped
+    instruction_hook: Option<usize>,    // id of the hook calling the instruction callback
+}
+
+// `Registers`, laid out as declared in the header
+pub type Emu6502Registers = Registers;
+
+// called before each instruction with its address; returning true stops before it, and resuming executes it
+pub type Emu6502InstructionCallback = extern "C" fn(user_data: *mut c_void, pc: u16) -> bool;
+
+// called once per CPU cycle with its number
+pub type Emu6502ClockCallback = extern "C" fn(user_data: *mut c_void, cycle: u64);
+
+impl Emu6502 {
+    fn step(&mut self) -> Option<String> {
+        self.cpu.exec(&mut self.mem, 1).map(|reason| reason.to_string())
+    }
+
+    // runs `execute`, keeping the reason for `emu6502_stop_reason`; whether execution stopped
+    fn stopped(&mut self, execute: impl FnOnce(&mut Self) -> Option<String>) -> bool {
+        let reason = panic::catch_unwind(AssertUnwindSafe(|| execute(self))).unwrap_or_else(|payload| Some(panic_message(payload)));
+        self.stop_reason = reason.map(|reason| CString::new(reason).unwrap_or_default());
+        self.stop_reason.is_some()
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    let message = payload.downcast_ref::<&str>().copied().or_else(|| payload.downcast_ref::<String>().map(String::as_str));
+    format!("Emulator panicked: {}", message.unwrap_or("unknown cause"))
+}
+
+// `f` on the handle, or `default` if it is NULL or `f` panics, as unwinding into C is undefined behavior
+unsafe fn with_emu<T>(emu: *mut Emu6502, default: T, f: impl FnOnce(&mut Emu6502) -> T) -> T {
+    match emu.as_mut() {
+        Some(emu) => panic::catch_unwind(AssertUnwindSafe(|| f(emu))).unwrap_or(default),
+        None => default,
+    }
+}
+
+unsafe fn with_emu_ref<T>(emu: *const Emu6502, default: T, f: impl FnOnce(&Emu6502) -> T) -> T {
+    match emu.as_ref() {
+        Some(emu) => panic::catch_unwind(AssertUnwindSafe(|| f(emu))).unwrap_or(default),
+        None => default,
+    }
+}
+
+// cleared memory, PC at the reset vector; free with `emu6502_destroy`
+#[no_mangle]
+pub extern "C" fn emu6502_create() -> *mut Emu6502 {
+    panic::catch_unwind(|| {
+        let mut mem = Memory::create();
+        let mut cpu = Cpu::create();
+        cpu.reset(&mut mem);
+        Box::into_raw(Box::new(Emu6502 { cpu, mem, stop_reason: None, instruction_hook: None }))
+    })
+    .unwrap_or(ptr::null_mut())
+}
+
+/// # Safety
+///
+/// `emu` must be NULL or a handle from `emu6502_create` not yet destroyed.
+#[no_mangle]
+pub unsafe extern "C" fn emu6502_destroy(emu: *mut Emu6502) {
+    if !emu.is_null() {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(emu))));
+    }
+}
+
+// restarts at the reset vector keeping the memory, e.g. the loaded program
+/// # Safety
+///
+/// `emu` must be NULL or a handle from `emu6502_create` not yet destroyed.
+#[no_mangle]
+pub unsafe extern "C" fn emu6502_reset(emu: *mut Emu6502) {
+    with_emu(emu, (), |emu| emu.cpu.warm_reset(&mut emu.mem));
+}
+
+/// # Safety
+///
+/// `emu` must be NULL or a handle from `emu6502_create` not yet destroyed, and `bytes` must point to `len` readable
+/// bytes.
+#[no_mangle]
+pub unsafe extern "C" fn emu6502_load(emu: *mut Emu6502, addr: u16, bytes: *const u8, len: usize) {
+    if bytes.is_null() {
+        return;
+    }
+    with_emu(emu, (), |emu| {
+        for (offset, &byte) in slice::from_raw_parts(bytes, len).iter().enumerate() {
+            emu.mem.write_u8(addr.wrapping_add(offset as u16), byte);
+        }
+    });
+}
+
+// executes an instruction or enters an interrupt; whether execution stopped, see `emu6502_stop_reason`
+/// # Safety
+///
+/// `emu` must be NULL or a handle from `emu6502_create` not yet destroyed.
+#[no_mangle]
+pub unsafe extern "C" fn emu6502_step(emu: *mut Emu6502) -> bool {
+    with_emu(emu, false, |emu| emu.stopped(Emu6502::step))
+}
+
+// executes at least the given number of cycles unless stopped before
+/// # Safety
+///
+/// `emu` must be NULL or a handle from `emu6502_create` not yet destroyed.
+#[no_mangle]
+pub unsafe extern "C" fn emu6502_run(emu: *mut Emu6502, cycles: u64) -> bool {
+    with_emu(emu, false, |emu| emu.stopped(|emu| {
+        let end = emu.cpu.cycles.saturating_add(cycles);
+        let mut reason = None;
+        while reason.is_none() && emu.cpu.cycles < end {
+            reason = emu.step();
+        }
+        reason
+    }))
+}
+
+// why the last step or run stopped, or NULL; valid until the next call on the handle
+/// # Safety
+///
+/// `emu` must be NULL or a handle from `emu6502_create` not yet destroyed.
+#[no_mangle]
+pub unsafe extern "C" fn emu6502_stop_reason(emu: *const Emu6502) -> *const c_char {
+    with_emu_ref(emu, ptr::null(), |emu| emu.stop_reason.as_ref().map_or(ptr::null(), |reason| reason.as_ptr()))
+}
+
+// without side effects on devices
+/// # Safety
+///
+/// `emu` must be NULL or a handle from `emu6502_create` not yet destroyed.
+#[no_mangle]
+pub unsafe extern "C" fn emu6502_read(emu: *const Emu6502, addr: u16) -> u8 {
+    with_emu_ref(emu, 0, |emu| emu.mem.peek(addr))
+}
+
+/// # Safety
+///
+/// `emu` must be NULL or a handle from `emu6502_create` not yet destroyed, and `buffer` must point to `len` writable
+/// bytes.
+#[no_mangle]
+pub unsafe extern "C" fn emu6502_read_memory(emu: *const Emu6502, addr: u16, buffer: *mut u8, len: usize) {
+    if buffer.is_null() {
+        return;
+    }
+    with_emu_ref(emu, (), |emu| {
+        for (offset, byte) in slice::from_raw_parts_mut(buffer, len).iter_mut().enumerate() {
+            *byte = emu.mem.peek(addr.wrapping_add(offset as u16));
+        }
+    });
+}
+
+/// # Safety
+///
+/// `emu` must be NULL or a handle from `emu6502_create` not yet destroyed.
+#[no_mangle]
+pub unsafe extern "C" fn emu6502_write(emu: *mut Emu6502, addr: u16, value: u8) {
+    with_emu(emu, (), |emu| emu.mem.write_u8(addr, value));
+}
+
+/// # Safety
+///
+/// `emu` must be NULL or a handle from `emu6502_create` not yet destroyed.
+#[no_mangle]
+pub unsafe extern "C" fn emu6502_get_registers(emu: *const Emu6502) -> Emu6502Registers {
+    with_emu_ref(emu, Emu6502Registers::default(), |emu| emu.cpu.registers())
+}
+
+/// # Safety
+///
+/// `emu` must be NULL or a handle from `emu6502_create` not yet destroyed, and `registers` NULL or readable.
+#[no_mangle]
+pub unsafe extern "C" fn emu6502_set_registers(emu: *mut Emu6502, registers: *const Emu6502Registers) {
+    let Some(registers) = registers.as_ref() else { return };
+    with_emu(emu, (), |emu| {
+        emu.cpu.pc = registers.pc;
+        emu.cpu.ac = registers.ac;
+        emu.cpu.x = registers.x;
+        emu.cpu.y = registers.y;
+        emu.cpu.sr = StatusFlags::from_bits_retain(registers.sr) | StatusFlags::RESERVED;
+        emu.cpu.sp = registers.sp;
+        emu.cpu.cycles = registers.cycles;
+    });
+}
+
+// replaces the instruction callback; NULL removes it. `user_data` is passed to the callback as is
+/// # Safety
+///
+/// `emu` must be NULL or a handle from `emu6502_create` not yet destroyed.
+#[no_mangle]
+pub unsafe extern "C" fn emu6502_set_instruction_callback(emu: *mut Emu6502, callback: Option<Emu6502InstructionCallback>, user_data: *mut c_void) {
+    with_emu(emu, (), |emu| {
+        if let Some(id) = emu.instruction_hook.take() {
+            emu.cpu.remove_hook(id);
+        }
+        if let Some(callback) = callback {
+            let id = emu.cpu.add_hook(HookPoint::Before, move |cpu, _, _| {
+                if callback(user_data, cpu.pc) { HookAction::Stop } else { HookAction::Continue }
+            });
+            emu.instruction_hook = Some(id);
+        }
+    });
+}
+
+// replaces the clock callback; NULL removes it. `user_data` is passed to the callback as is
+/// # Safety
+///
+/// `emu` must be NULL or a handle from `emu6502_create` not yet destroyed.
+#[no_mangle]
+pub unsafe extern "C" fn emu6502_set_clock_callback(emu: *mut Emu6502, callback: Option<Emu6502ClockCallback>, user_data: *mut c_void) {
+    with_emu(emu, (), |emu| match callback {
+        Some(callback) => emu.cpu.on_clock(move |cycle, _| callback(user_data, cycle)),
+        None => emu.cpu.remove_clock_hook(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use core::ffi::CStr;
+
+    use super::*;
+
+    extern "C" fn stop_at(user_data: *mut c_void, pc: u16) -> bool {
+        pc == unsafe { *(user_data as *const u16) }
+    }
+
+    extern "C" fn count(user_data: *mut c_void, _cycle: u64) {
+        unsafe { *(user_data as *mut u64) += 1 };
+    }
+
+    #[test]
+    fn run() {
+        // 0600 LDX #$03, 0602 DEX, 0603 BNE $0602, 0605 STX $10, 0607 .byte $02
+        let emu = emu6502_create();
+        let program = [0xA2, 0x03, 0xCA, 0xD0, 0xFD, 0x86, 0x10, 0x02];
+        unsafe {
+            emu6502_load(emu, 0x0600, program.as_ptr(), program.len());
+            let mut registers = emu6502_get_registers(emu);
+            registers.pc = 0x0600;
+            emu6502_set_registers(emu, &registers);
+
+            let mut cycles = 0u64;
+            let mut stop = 0x0605u16;
+            emu6502_set_clock_callback(emu, Some(count), &mut cycles as *mut u64 as *mut c_void);
+            emu6502_set_instruction_callback(emu, Some(stop_at), &mut stop as *mut u16 as *mut c_void);
+            assert!(emu6502_run(emu, 1000));
+            assert_eq!((emu6502_get_registers(emu).pc, emu6502_get_registers(emu).x), (0x0605, 0x00));
+            assert_eq!(CStr::from_ptr(emu6502_stop_reason(emu)).to_str().unwrap(), "Stopped by hook at instruction at $0605");
+
+            emu6502_set_instruction_callback(emu, None, ptr::null_mut());
+            emu6502_set_clock_callback(emu, None, ptr::null_mut());
+            assert!(emu6502_run(emu, 1000));
+            assert_eq!(CStr::from_ptr(emu6502_stop_reason(emu)).to_str().unwrap(), "Undefined opcode $02 at $0607");
+            assert_eq!((cycles, emu6502_get_registers(emu).cycles), (2 + 3 * 2 + 2 * 3 + 2, 7 + 2 + 3 * 2 + 2 * 3 + 2 + 3));
+
+            let mut buffer = [0; 3];
+            emu6502_read_memory(emu, 0x0604, buffer.as_mut_ptr(), buffer.len());
+            assert_eq!(buffer, [0xFD, 0x86, 0x10]);
+            emu6502_write(emu, 0x10, 0x42);
+            assert_eq!(emu6502_read(emu, 0x10), 0x42);
+            emu6502_reset(emu);
+            assert_eq!(emu6502_get_registers(emu).pc, u16::from_le_bytes([emu6502_read(emu, 0xFFFC), emu6502_read(emu, 0xFFFD)]));
+            emu6502_destroy(emu);
+        }
+    }
+
+    #[test]
+    fn step_wraps() {
+        // FFFF NOP, 0000 .byte $02
+        let emu = emu6502_create();
+        let program = [0xEA, 0x02];
+        unsafe {
+            emu6502_load(emu, 0xFFFF, program.as_ptr(), program.len());
+            let mut registers = emu6502_get_registers(emu);
+            registers.pc = 0xFFFF;
+            emu6502_set_registers(emu, &registers);
+
+            assert!(!emu6502_step(emu));
+            assert_eq!(emu6502_get_registers(emu).pc, 0x0000);
+            assert!(emu6502_step(emu));
+            assert_eq!(CStr::from_ptr(emu6502_stop_reason(emu)).to_str().unwrap(), "Undefined opcode $02 at $0000");
+            emu6502_destroy(emu);
+        }
+    }
+
+    #[test]
+    fn null_handle() {
+        unsafe {
+            assert!(!emu6502_step(ptr::null_mut()) && !emu6502_run(ptr::null_mut(), 1000));
+            assert!(emu6502_stop_reason(ptr::null()).is_null());
+            assert_eq!((emu6502_read(ptr::null(), 0xFFFC), emu6502_get_registers(ptr::null())), (0, Emu6502Registers::default()));
+            emu6502_write(ptr::null_mut(), 0x10, 0x42);
+            emu6502_set_registers(ptr::null_mut(), ptr::null());
+            emu6502_destroy(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn panic_stops() {
+        let emu = emu6502_create();
+        unsafe {
+            assert!((*emu).stopped(|_| panic!("broken")));
+            assert_eq!(CStr::from_ptr(emu6502_stop_reason(emu)).to_str().unwrap(), "Emulator panicked: broken");
+            emu6502_destroy(emu);
+        }
+    }
+}
@@ -0,0 +1,156 @@
+// Bitmap framebuffer: a memory region holding the pixels row by row with 1, 2, 4 or 8 bits per pixel, leftmost pixel
+// in the most significant bits of a byte; each row starts on a byte boundary. Pixel values are palette indexes, mapped
+// to 0RGB colors for display in a window (feature "window").
+
+use std::fs;
+use std::ops::RangeInclusive;
+
+use crate::mem::Memory;
+
+pub const FRAMEBUFFER_REFRESH_CYCLES_DEFAULT: u64 = 20000;  // 50 times per second at 1 MHz
+
+// Pepto's C64 colors
+const PALETTE_C64: [u32; 16] = [
+    0x000000, 0xFFFFFF, 0x68372B, 0x70A4B2, 0x6F3D86, 0x588D43, 0x352879, 0xB8C76F,
+    0x6F4F25, 0x433900, 0x9A6759, 0x444444, 0x6C6C6C, 0x9AD284, 0x6C5EB5, 0x959595,
+];
+
+#[derive(Clone, Debug)]
+pub struct Framebuffer {
+    pub base: u16,
+    pub width: u16,
+    pub height: u16,
+    pub bpp: u8,
+    pub palette: Vec<u32>,
+}
+
+impl Framebuffer {
+    // without palette the default one for the bits per pixel
+    pub fn create(base: u16, width: u16, height: u16, bpp: u8, palette: Option<Vec<u32>>) -> Result<Self, String> {
+        if ![1, 2, 4, 8].contains(&bpp) {
+            return Err(format!("Unsupported {bpp} bits per pixel; expected 1, 2, 4 or 8"));
+        }
+        if width == 0 || height == 0 {
+            return Err(String::from("Framebuffer must have at least one pixel"));
+        }
+        let palette = palette.unwrap_or_else(|| Self::default_palette(bpp));
+        if palette.len() < 1 << bpp {
+            return Err(format!("Palette has {} colors, {} needed for {bpp} bits per pixel", palette.len(), 1 << bpp));
+        }
+        let framebuffer = Self { base, width, height, bpp, palette };
+        if base as usize + framebuffer.row_bytes() * height as usize > 0x10000 {
+            return Err(format!("Framebuffer of {width}x{height} with {bpp} bits per pixel at ${base:04X} extends beyond $FFFF"));
+        }
+        Ok(framebuffer)
+    }
+
+    // black and white, grey levels, the C64 colors or RRRGGGBB
+    pub fn default_palette(bpp: u8) -> Vec<u32> {
+        match bpp {
+            1 => vec![0x000000, 0xFFFFFF],
+            2 => vec![0x000000, 0x555555, 0xAAAAAA, 0xFFFFFF],
+            4 => PALETTE_C64.to_vec(),
+            _ => (0..=255u32).map(|index| {
+                let (red, green, blue) = (index >> 5, (index >> 2) & 0x07, index & 0x03);
+                ((red * 255 / 7) << 16) | ((green * 255 / 7) << 8) | (blue * 255 / 3)
+            }).collect(),
+        }
+    }
+
+    // "ADDR:WIDTHxHEIGHT" with a hex address, e.g. "2000:64x64"
+    pub fn parse_geometry(text: &str) -> Result<(u16, u16, u16), String> {
+        let error = || format!("Expected ADDR:WIDTHxHEIGHT, e.g. 2000:64x64, instead of '{text}'");
+        let (addr, size) = text.split_once(':').ok_or_else(error)?;
+        let (width, height) = size.split_once('x').ok_or_else(error)?;
+        let addr = u16::from_str_radix(addr.trim_start_matches('$'), 16).map_err(|_| error())?;
+        Ok((addr, width.parse().map_err(|_| error())?, height.parse().map_err(|_| error())?))
+    }
+
+    // colors as RRGGBB hex values (optionally prefixed with '#' or '$') separated by whitespace or commas
+    pub fn parse_palette(text: &str) -> Result<Vec<u32>, String> {
+        text.split(|char: char| char.is_whitespace() || char == ',')
+            .filter(|color| !color.is_empty())
+            .map(|color| {
+                let hex = color.trim_start_matches(['#', '$']);
+                match hex.len() {
+                    6 => u32::from_str_radix(hex, 16).map_err(|_| format!("Invalid color '{color}'")),
+                    _ => Err(format!("Expected color as RRGGBB instead of '{color}'")),
+                }
+            })
+            .collect()
+    }
+
+    pub fn load_palette(filename: &str) -> Result<Vec<u32>, String> {
+        let text = fs::read_to_string(filename).map_err(|error| format!("Cannot read palette file '{filename}': {error}"))?;
+        Self::parse_palette(&text).map_err(|error| format!("{filename}: {error}"))
+    }
+
+    pub fn row_bytes(&self) -> usize {
+        (self.width as usize * self.bpp as usize).div_ceil(8)
+    }
+
+    pub fn range(&self) -> RangeInclusive<u16> {
+        self.base..=(self.base as usize + self.row_bytes() * self.height as usize - 1) as u16
+    }
+
+    // palette index of each pixel, row by row
+    pub fn indexes(&self, mem: &Memory) -> Vec<u8> {
+        let per_byte = 8 / self.bpp as usize;
+        let mask = ((1u16 << self.bpp) - 1) as u8;
+        let mut indexes = Vec::with_capacity(self.width as usize * self.height as usize);
+        for row in 0..self.height as usize {
+            let start = self.base as usize + row * self.row_bytes();
+            for column in 0..self.width as usize {
+                let byte = mem.peek((start + column / per_byte) as u16);
+                let shift = 8 - self.bpp as usize * (column % per_byte + 1);
+                indexes.push((byte >> shift) & mask);
+            }
+        }
+        indexes
+    }
+
+    // 0RGB color of each pixel, row by row
+    pub fn pixels(&self, mem: &Memory) -> Vec<u32> {
+        self.indexes(mem).into_iter().map(|index| self.palette[index as usize]).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixels() {
+        let mut mem = Memory::create();
+        let framebuffer = Framebuffer::create(0x2000, 10, 2, 1, None).unwrap();
+        assert_eq!(framebuffer.row_bytes(), 2);
+        assert_eq!(framebuffer.range(), 0x2000..=0x2003);
+        for (addr, byte) in (0x2000..).zip([0b1000_0001, 0b0100_0000, 0b0000_0000, 0b1111_1111]) {
+            mem.write_u8(addr, byte);
+        }
+        assert_eq!(framebuffer.indexes(&mem), [1, 0, 0, 0, 0, 0, 0, 1, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1]);
+        assert_eq!(framebuffer.pixels(&mem)[..2], [0xFFFFFF, 0x000000]);
+
+        let framebuffer = Framebuffer::create(0x2000, 3, 1, 4, None).unwrap();
+        assert_eq!(framebuffer.indexes(&mem), [0x8, 0x1, 0x4]);
+        assert_eq!(framebuffer.pixels(&mem), [0x6F4F25, 0xFFFFFF, 0x6F3D86]);
+
+        let framebuffer = Framebuffer::create(0x2000, 4, 1, 2, Some(vec![0x000000, 0x0000FF, 0x00FF00, 0xFF0000])).unwrap();
+        assert_eq!(framebuffer.pixels(&mem), [0x00FF00, 0x000000, 0x000000, 0x0000FF]);
+
+        let framebuffer = Framebuffer::create(0x2003, 1, 1, 8, None).unwrap();
+        assert_eq!(framebuffer.pixels(&mem), [0xFFFFFF]);
+    }
+
+    #[test]
+    fn configuration() {
+        assert_eq!(Framebuffer::parse_geometry("2000:64x64"), Ok((0x2000, 64, 64)));
+        assert!(Framebuffer::parse_geometry("2000:64").is_err());
+        assert_eq!(Framebuffer::parse_palette("#000000, $FF8000\n0000ff"), Ok(vec![0x000000, 0xFF8000, 0x0000FF]));
+        assert!(Framebuffer::parse_palette("FFF").is_err());
+        assert!(Framebuffer::create(0x2000, 8, 8, 3, None).is_err());
+        assert!(Framebuffer::create(0x2000, 8, 8, 2, Some(vec![0, 0xFFFFFF])).is_err());
+        assert!(Framebuffer::create(0xF000, 256, 32, 8, None).is_err());
+        assert_eq!(Framebuffer::default_palette(8)[0xFF], 0xFFFFFF);
+    }
+}
@@ -0,0 +1,263 @@
+// General-purpose I/O port: 8 pins for custom hardware on the board (buttons, LEDs, sensors), modelled on the host.
+// Rust code drives the input pins with `set_pins` or a callback sampling them when the CPU reads the port, and is
+// called back when the levels of the output pins change; a script of timed pin levels drives them from the CLI.
+//
+// Register layout (offset from base address):
+//   +0  DATA          read: levels of the pins (output register where DDR is 1, input pins where it is 0); write:
+//                     output register
+//   +1  DDR           data direction (1 = output)
+//   +2  IER           interrupt enable: input pins whose changes raise IRQ
+//   +3  IFR           input pins that changed since their flags were cleared; writing 1 clears a flag
+//
+// Script format, one change per line: "<cycle> <levels>", the levels of the input pins from that cycle on in hex or
+// with prefix ($, %, +); '#' starts a comment. Cycles count from attaching the port.
+
+use std::collections::VecDeque;
+use std::fs;
+use std::ops::RangeInclusive;
+
+use crate::device::Device;
+use crate::expr::{self, Radix};
+
+pub const GPIO_BASE_DEFAULT: u16 = 0xDF80;
+pub const GPIO_REGISTERS: u16 = 4;
+
+pub const REG_DATA: u16 = 0x0;
+pub const REG_DDR: u16 = 0x1;
+pub const REG_IER: u16 = 0x2;
+pub const REG_IFR: u16 = 0x3;
+
+// levels applied to the input pins from a cycle on, in order
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct GpioScript {
+    changes: VecDeque<(u64, u8)>,
+}
+
+impl GpioScript {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut script = Self::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: &str| format!("Line {}: {message} '{line}'", number + 1);
+            let [cycle, levels] = line.split_whitespace().collect::<Vec<&str>>()[..] else {
+                return Err(error("Expected cycle and levels in"));
+            };
+            let cycle = cycle.parse().map_err(|_| error("Invalid cycle in"))?;
+            let levels = expr::parse_number(levels, Radix::Hex).ok().and_then(|levels| u8::try_from(levels).ok()).ok_or_else(|| error("Invalid levels in"))?;
+            if script.changes.back().is_some_and(|&(last, _)| last > cycle) {
+                return Err(error("Cycles out of order in"));
+            }
+            script.changes.push_back((cycle, levels));
+        }
+        Ok(script)
+    }
+
+    pub fn load_from_file(filename: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(filename).map_err(|error| format!("{filename}: {error}"))?;
+        Self::parse(&text).map_err(|error| format!("{filename}: {error}"))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    // removes the next change if it is due at `cycle`
+    fn next_due(&mut self, cycle: u64) -> Option<u8> {
+        match self.changes.front() {
+            Some(&(at, levels)) if at <= cycle => {
+                self.changes.pop_front();
+                Some(levels)
+            },
+            _ => None,
+        }
+    }
+}
+
+pub struct Gpio {
+    name: String,
+    pub base: u16,
+    data: u8,
+    ddr: u8,
+    pins: u8,               // levels applied to the port pins from outside
+    ier: u8,
+    ifr: u8,
+    cycles: u64,            // ticked since attaching
+    script: GpioScript,
+    on_input: Option<Box<dyn FnMut(u64) -> u8>>,
+    on_output: Option<Box<dyn FnMut(u64, u8)>>,
+}
+
+impl Gpio {
+    pub fn create(name: &str, base: u16) -> Self {
+        Self {
+            name: String::from(name),
+            base,
+            data: 0,
+            ddr: 0,
+            pins: 0xFF,         // inputs are pulled up
+            ier: 0,
+            ifr: 0,
+            cycles: 0,
+            script: GpioScript::default(),
+            on_input: None,
+            on_output: None,
+        }
+    }
+
+    pub fn with_script(name: &str, base: u16, script: GpioScript) -> Self {
+        Self { script, ..Self::create(name, base) }
+    }
+
+    // called with the cycle when the CPU reads the port, returning the levels of the input pins
+    pub fn on_input(&mut self, callback: impl FnMut(u64) -> u8 + 'static) {
+        self.on_input = Some(Box::new(callback));
+    }
+
+    // called with the cycle and the levels of the pins when the CPU changes the levels of the output pins
+    pub fn on_output(&mut self, callback: impl FnMut(u64, u8) + 'static) {
+        self.on_output = Some(Box::new(callback));
+    }
+
+    // levels on the pins: driven by the output register where configured as output, from outside otherwise
+    pub fn port(&self) -> u8 {
+        (self.data & self.ddr) | (self.pins & !self.ddr)
+    }
+
+    // levels driven by the port; the others float
+    pub fn outputs(&self) -> u8 {
+        self.data & self.ddr
+    }
+
+    pub fn ddr(&self) -> u8 {
+        self.ddr
+    }
+
+    // levels applied to the input pins; changes of those configured as input set their interrupt flags
+    pub fn set_pins(&mut self, pins: u8) {
+        self.ifr |= (self.pins ^ pins) & !self.ddr;
+        self.pins = pins;
+    }
+
+    fn reg(&self, addr: u16) -> u16 {
+        addr.wrapping_sub(self.base) % GPIO_REGISTERS
+    }
+}
+
+impl Device for Gpio {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        self.base..=self.base + (GPIO_REGISTERS - 1)
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        if self.reg(addr) == REG_DATA {
+            if let Some(callback) = &mut self.on_input {
+                let pins = callback(self.cycles);
+                self.set_pins(pins);
+            }
+        }
+        self.peek(addr)
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match self.reg(addr) {
+            REG_DATA => self.port(),
+            REG_DDR => self.ddr,
+            REG_IER => self.ier,
+            _ => self.ifr,
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        let (outputs, ddr) = (self.outputs(), self.ddr);
+        match self.reg(addr) {
+            REG_DATA => self.data = value,
+            REG_DDR => self.ddr = value,
+            REG_IER => self.ier = value,
+            _ => self.ifr &= !value,
+        }
+        if (self.outputs(), self.ddr) != (outputs, ddr) {
+            let port = self.port();
+            if let Some(callback) = &mut self.on_output {
+                callback(self.cycles, port);
+            }
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.cycles += cycles;
+        while let Some(pins) = self.script.next_due(self.cycles) {
+            self.set_pins(pins);
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.ifr & self.ier != 0
+    }
+
+    // registers are cleared, making all pins inputs; the pins keep their levels
+    fn reset(&mut self) {
+        (self.data, self.ddr, self.ier, self.ifr) = (0, 0, 0, 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    const BASE: u16 = GPIO_BASE_DEFAULT;
+
+    #[test]
+    fn callbacks() {
+        // LEDs on the low nibble, a button on bit 7 pressed from cycle 100 on (active low)
+        let mut gpio = Gpio::create("gpio", BASE);
+        let leds = Rc::new(RefCell::new(Vec::new()));
+        let changes = Rc::clone(&leds);
+        gpio.on_output(move |cycle, port| changes.borrow_mut().push((cycle, port & 0x0F)));
+        gpio.on_input(|cycle| if cycle >= 100 { 0x7F } else { 0xFF });
+
+        gpio.write(BASE + REG_DDR, 0x0F);
+        gpio.tick(10);
+        gpio.write(BASE + REG_DATA, 0x05);
+        gpio.write(BASE + REG_DATA, 0x05);
+        gpio.write(BASE + REG_IER, 0x80);
+        assert_eq!((gpio.read(BASE + REG_DATA), gpio.irq()), (0xF5, false));
+        gpio.tick(90);
+        assert_eq!(gpio.peek(BASE + REG_DATA), 0xF5, "peeking does not sample the pins");
+        assert_eq!((gpio.read(BASE + REG_DATA), gpio.peek(BASE + REG_IFR), gpio.irq()), (0x75, 0x80, true));
+        gpio.write(BASE + REG_IFR, 0x80);
+        assert!(!gpio.irq());
+        assert_eq!(*leds.borrow(), vec![(0, 0x00), (10, 0x05)]);
+
+        gpio.reset();
+        assert_eq!((gpio.outputs(), gpio.port()), (0x00, 0x7F));
+    }
+
+    #[test]
+    fn script() {
+        let script = GpioScript::parse("# button\n100 FE\n\n150 $FF  # released\n150 %11111100\n").unwrap();
+        let mut gpio = Gpio::with_script("gpio", BASE, script);
+        gpio.write(BASE + REG_IER, 0x01);
+        gpio.tick(99);
+        assert_eq!((gpio.port(), gpio.irq()), (0xFF, false));
+        gpio.tick(1);
+        assert_eq!((gpio.port(), gpio.irq()), (0xFE, true));
+        gpio.write(BASE + REG_IFR, 0xFF);
+        gpio.tick(100);
+        assert_eq!((gpio.port(), gpio.peek(BASE + REG_IFR)), (0xFC, 0x03));
+        assert!(gpio.script.is_empty());
+
+        assert_eq!(GpioScript::parse("10\n").unwrap_err(), "Line 1: Expected cycle and levels in '10'");
+        assert!(GpioScript::parse("10 100\n").is_err());
+        assert!(GpioScript::parse("20 0\n10 1\n").is_err());
+    }
+}
@@ -0,0 +1,120 @@
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+
+use crate::mem::Access;
+use crate::prelude::*;
+
+const ADDRESSES: usize = 0x10000;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct HeatmapEntry {
+    pub addr: u16,
+    pub reads: u64,
+    pub writes: u64,
+    pub executes: u64,
+}
+
+impl HeatmapEntry {
+    pub fn total(&self) -> u64 {
+        self.reads.saturating_add(self.writes).saturating_add(self.executes)
+    }
+}
+
+// per-address read/write/execute counters
+pub struct Heatmap {
+    reads: Vec<u64>,
+    writes: Vec<u64>,
+    executes: Vec<u64>,
+}
+
+impl Heatmap {
+    pub fn create() -> Self {
+        Self {
+            reads: vec![0; ADDRESSES],
+            writes: vec![0; ADDRESSES],
+            executes: vec![0; ADDRESSES],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.reads.fill(0);
+        self.writes.fill(0);
+        self.executes.fill(0);
+    }
+
+    pub fn record(&mut self, addr: u16, access: Access) {
+        let counter = match access {
+            Access::Read => &mut self.reads[addr as usize],
+            Access::Write => &mut self.writes[addr as usize],
+            Access::Execute => &mut self.executes[addr as usize],
+        };
+        *counter = counter.saturating_add(1);
+    }
+
+    pub fn entry(&self, addr: u16) -> HeatmapEntry {
+        HeatmapEntry {
+            addr,
+            reads: self.reads[addr as usize],
+            writes: self.writes[addr as usize],
+            executes: self.executes[addr as usize],
+        }
+    }
+
+    // all accessed addresses in ascending order
+    pub fn entries(&self) -> impl Iterator<Item = HeatmapEntry> + '_ {
+        (0..=0xFFFF).map(|addr| self.entry(addr)).filter(|entry| entry.total() > 0)
+    }
+
+    // the `count` most accessed addresses, hottest first
+    pub fn hottest(&self, count: usize) -> Vec<HeatmapEntry> {
+        let mut entries: Vec<HeatmapEntry> = self.entries().collect();
+        entries.sort_by(|a, b| b.total().cmp(&a.total()).then(a.addr.cmp(&b.addr)));
+        entries.truncate(count);
+        entries
+    }
+
+    #[cfg(feature = "std")]
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "addr,reads,writes,executes")?;
+        for entry in self.entries() {
+            writeln!(writer, "{:04X},{},{},{}", entry.addr, entry.reads, entry.writes, entry.executes)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hottest() {
+        let mut heatmap = Heatmap::create();
+        heatmap.record(0x0200, Access::Read);
+        heatmap.record(0xE000, Access::Execute);
+        heatmap.record(0xE000, Access::Execute);
+        heatmap.record(0x0300, Access::Write);
+        heatmap.record(0x0300, Access::Read);
+        heatmap.record(0x0300, Access::Write);
+
+        let hottest = heatmap.hottest(2);
+        assert_eq!(hottest.len(), 2);
+        assert_eq!(hottest[0], HeatmapEntry { addr: 0x0300, reads: 1, writes: 2, executes: 0 });
+        assert_eq!(hottest[1], HeatmapEntry { addr: 0xE000, reads: 0, writes: 0, executes: 2 });
+
+        heatmap.clear();
+        assert!(heatmap.hottest(2).is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn write_csv() {
+        let mut heatmap = Heatmap::create();
+        heatmap.record(0x0200, Access::Read);
+        heatmap.record(0xE000, Access::Execute);
+
+        let mut csv = Vec::new();
+        heatmap.write_csv(&mut csv).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap(), "addr,reads,writes,executes\n0200,1,0,0\nE000,0,0,1\n");
+    }
+}
@@ -0,0 +1,139 @@
+// Memory contents in the Intel HEX format, as written by many assemblers and EPROM programmers, e.g.
+//
+//   :05030000A9018D0002BF      5 data bytes at $0300
+//   :0400000500000300F4        start address $0300
+//   :00000001FF                end of file
+//
+// Each record is ':', the byte count, the 16-bit address, the record type, the data and a checksum making the bytes
+// sum to zero, all hex. Extended segment and linear addresses must keep the data within the 64K address space.
+
+use std::fs;
+
+use crate::asm::Segment;
+use crate::mem::Memory;
+
+const RECORD_DATA: u8 = 0x00;
+const RECORD_END_OF_FILE: u8 = 0x01;
+const RECORD_EXTENDED_SEGMENT_ADDRESS: u8 = 0x02;
+const RECORD_START_SEGMENT_ADDRESS: u8 = 0x03;
+const RECORD_EXTENDED_LINEAR_ADDRESS: u8 = 0x04;
+const RECORD_START_LINEAR_ADDRESS: u8 = 0x05;
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct IntelHex {
+    pub segments: Vec<Segment>,     // in file order; records continuing at the next address are merged
+    pub start: Option<u16>,         // start address record
+}
+
+impl IntelHex {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut image = Self::default();
+        let mut base = 0usize;          // of the extended segment or linear address
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: &str| format!("Line {}: {message} '{line}'", number + 1);
+
+            let Some(digits) = line.strip_prefix(':') else {
+                return Err(error("Expected ':' starting"));
+            };
+            let record = (0..digits.len() / 2)
+                .map(|i| digits.get(i * 2..i * 2 + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+                .collect::<Option<Vec<u8>>>()
+                .filter(|record| digits.len() % 2 == 0 && record.len() >= 5 && record.len() == record[0] as usize + 5)
+                .ok_or_else(|| error("Invalid record"))?;
+            if record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+                return Err(error("Checksum mismatch in"));
+            }
+            let (addr, data) = (u16::from_be_bytes([record[1], record[2]]), &record[4..record.len() - 1]);
+            let value = || data.iter().fold(0usize, |value, byte| value << 8 | *byte as usize);
+
+            match record[3] {
+                RECORD_DATA => {
+                    let addr = base + addr as usize;
+                    if addr + data.len() > 0x10000 {
+                        return Err(error("Bytes beyond $FFFF in"));
+                    }
+                    match image.segments.last_mut() {
+                        Some(segment) if segment.addr as usize + segment.bytes.len() == addr => segment.bytes.extend(data),
+                        _ => image.segments.push(Segment { addr: addr as u16, bytes: data.to_vec() }),
+                    }
+                },
+                RECORD_END_OF_FILE => break,
+                RECORD_EXTENDED_SEGMENT_ADDRESS if data.len() == 2 => base = value() << 4,
+                RECORD_EXTENDED_LINEAR_ADDRESS if data.len() == 2 => base = value() << 16,
+                RECORD_START_SEGMENT_ADDRESS | RECORD_START_LINEAR_ADDRESS if data.len() == 4 => {
+                    // CS:IP or a linear address
+                    let start = match record[3] {
+                        RECORD_START_SEGMENT_ADDRESS => (value() >> 16 << 4) + (value() & 0xFFFF),
+                        _ => value(),
+                    };
+                    image.start = Some(u16::try_from(start).map_err(|_| error("Start address beyond $FFFF in"))?);
+                },
+                _ => return Err(error("Unsupported record")),
+            }
+        }
+        Ok(image)
+    }
+
+    pub fn load_from_file(filename: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(filename).map_err(|error| format!("{filename}: {error}"))?;
+        Self::parse(&text).map_err(|error| format!("{filename}: {error}"))
+    }
+
+    // where execution starts: the start address, otherwise the first byte
+    pub fn origin(&self) -> Option<u16> {
+        self.start.or_else(|| self.segments.first().map(|segment| segment.addr))
+    }
+
+    pub fn contains(&self, addr: u16) -> bool {
+        self.segments.iter().any(|segment| (segment.addr as usize..segment.addr as usize + segment.bytes.len()).contains(&(addr as usize)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.segments.iter().map(|segment| segment.bytes.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn write_to(&self, mem: &mut Memory) {
+        for segment in &self.segments {
+            for (addr, byte) in (segment.addr..=0xFFFF).zip(&segment.bytes) {
+                mem.write_u8(addr, *byte);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        // data at $0300 and, with the extended segment address $0F00, at $FFFC; nothing after the end of file
+        let image = IntelHex::parse(":05030000A9018D0002BF\n:010305006097\n\n:020000020F00ED\n:020FFC000003F0\n:0400000500000300F4\n:00000001FF\n:0100000001FE\n").unwrap();
+        assert_eq!(image.segments, vec![
+            Segment { addr: 0x0300, bytes: vec![0xA9, 0x01, 0x8D, 0x00, 0x02, 0x60] },
+            Segment { addr: 0xFFFC, bytes: vec![0x00, 0x03] },
+        ]);
+        assert_eq!((image.start, image.origin(), image.len()), (Some(0x0300), Some(0x0300), 8));
+        assert!(image.contains(0x0305) && !image.contains(0x0306));
+
+        let mut mem = Memory::create();
+        image.write_to(&mut mem);
+        assert_eq!((mem.peek(0x0305), mem.read_u16(0xFFFC)), (0x60, 0x0300));
+        IntelHex::parse(":02FFFE000103FD\n").unwrap().write_to(&mut mem);
+        assert_eq!(mem.read_u16(0xFFFE), 0x0301);
+
+        assert_eq!(IntelHex::parse("0300: A9\n").unwrap_err(), "Line 1: Expected ':' starting '0300: A9'");
+        assert_eq!(IntelHex::parse(":05030000A9018D0002C0\n").unwrap_err(), "Line 1: Checksum mismatch in ':05030000A9018D0002C0'");
+        assert_eq!(IntelHex::parse(":0603000060\n").unwrap_err(), "Line 1: Invalid record ':0603000060'");
+        assert!(IntelHex::parse(":02FFFF000102FD\n").is_err());
+        assert!(IntelHex::parse(":020000040001F9\n:0100000001FE\n").is_err());
+    }
+}
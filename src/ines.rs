@@ -0,0 +1,111 @@
+// NES cartridge images in the iNES format: a 16-byte header, an optional 512-byte trainer, then the PRG ROM (program,
+// in 16K units) and the CHR ROM (pattern tables, in 8K units).
+//
+//   header  "NES" $1A, PRG ROM size u8, CHR ROM size u8, flags 6, flags 7, 8 bytes mostly unused
+//   flags 6 bit 0 vertical mirroring (horizontal if clear), bit 1 battery-backed PRG RAM, bit 2 trainer present,
+//           bit 3 four-screen VRAM, bits 7-4 mapper LN
+//   flags 7 bits 3-2 %10 for NES 2.0, bits 7-4 mapper HN
+
+use std::fs;
+
+const SIGNATURE: &[u8; 4] = b"NES\x1A";
+const HEADER_LEN: usize = 16;
+const TRAINER_LEN: usize = 512;
+pub const PRG_UNIT: usize = 0x4000;
+pub const CHR_UNIT: usize = 0x2000;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    FourScreen,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Ines {
+    pub mapper: u8,
+    pub mirroring: Mirroring,
+    pub battery: bool,
+    pub nes2: bool,             // NES 2.0 header; its extensions are not interpreted
+    pub trainer: Option<Vec<u8>>,
+    pub prg: Vec<u8>,
+    pub chr: Vec<u8>,           // empty for CHR RAM
+}
+
+impl Ines {
+    pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < HEADER_LEN || !bytes.starts_with(SIGNATURE) {
+            return Err(String::from("Not an iNES image"));
+        }
+        let (flags6, flags7) = (bytes[6], bytes[7]);
+        let nes2 = flags7 & 0x0C == 0x08;
+        let mut offset = HEADER_LEN;
+        let mut take = |len: usize, what: &str| match bytes.get(offset..offset + len) {
+            Some(data) => {
+                offset += len;
+                Ok(data.to_vec())
+            },
+            None => Err(format!("Image ends within the {what} ({} bytes expected at offset {offset})", len)),
+        };
+        let trainer = match flags6 & 0x04 != 0 {
+            true => Some(take(TRAINER_LEN, "trainer")?),
+            false => None,
+        };
+        let prg = take(bytes[4] as usize * PRG_UNIT, "PRG ROM")?;
+        let chr = take(bytes[5] as usize * CHR_UNIT, "CHR ROM")?;
+        if prg.is_empty() {
+            return Err(String::from("Image contains no PRG ROM"));
+        }
+        Ok(Self {
+            mapper: (flags7 & 0xF0) | (flags6 >> 4),
+            mirroring: match flags6 & 0x09 {
+                0x00 => Mirroring::Horizontal,
+                0x01 => Mirroring::Vertical,
+                _ => Mirroring::FourScreen,
+            },
+            battery: flags6 & 0x02 != 0,
+            nes2,
+            trainer,
+            prg,
+            chr,
+        })
+    }
+
+    pub fn load_from_file(filename: &str) -> Result<Self, String> {
+        let bytes = fs::read(filename).map_err(|error| format!("{filename}: {error}"))?;
+        Self::parse(&bytes).map_err(|error| format!("{filename}: {error}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(flags6: u8, flags7: u8, prg_units: u8, chr_units: u8) -> Vec<u8> {
+        let mut bytes = SIGNATURE.to_vec();
+        bytes.extend([prg_units, chr_units, flags6, flags7, 0, 0, 0, 0, 0, 0, 0, 0]);
+        if flags6 & 0x04 != 0 {
+            bytes.extend([0xEE; TRAINER_LEN]);
+        }
+        bytes.extend((0..prg_units as usize * PRG_UNIT).map(|index| (index / PRG_UNIT) as u8 + 1));
+        bytes.extend(vec![0xCC; chr_units as usize * CHR_UNIT]);
+        bytes
+    }
+
+    #[test]
+    fn parse() {
+        let ines = Ines::parse(&image(0x01, 0x00, 2, 1)).unwrap();
+        assert_eq!((ines.mapper, ines.mirroring, ines.battery, ines.nes2), (0, Mirroring::Vertical, false, false));
+        assert_eq!((ines.prg.len(), ines.prg[PRG_UNIT], ines.chr.len(), ines.trainer), (0x8000, 2, 0x2000, None));
+
+        // mapper 1 (MMC1) with trainer and battery in a NES 2.0 header, CHR RAM
+        let ines = Ines::parse(&image(0x16, 0x08, 1, 0)).unwrap();
+        assert_eq!((ines.mapper, ines.mirroring, ines.battery, ines.nes2), (1, Mirroring::Horizontal, true, true));
+        assert_eq!((ines.trainer.map(|trainer| trainer.len()), ines.chr.len()), (Some(TRAINER_LEN), 0));
+
+        assert_eq!(Ines::parse(b"C64 CARTRIDGE   ").unwrap_err(), "Not an iNES image");
+        assert_eq!(Ines::parse(&image(0x00, 0x00, 0, 1)).unwrap_err(), "Image contains no PRG ROM");
+        let truncated = &image(0x00, 0x00, 1, 1)[..HEADER_LEN + PRG_UNIT + 10];
+        assert!(Ines::parse(truncated).unwrap_err().starts_with("Image ends within the CHR ROM"));
+    }
+}
@@ -0,0 +1,134 @@
+// External inputs with the cycle at which they occurred, for reproducing a run exactly
+//
+// Text format, one event per line: "<cycle> irq on", "<cycle> irq off" or "<cycle> nmi"; '#' starts a comment.
+
+use alloc::collections::VecDeque;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::fs;
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+
+use crate::prelude::*;
+
+// anything reaching the machine from outside the program
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Input {
+    Irq(bool),      // IRQ line asserted or released
+    Nmi,
+}
+
+impl fmt::Display for Input {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Irq(true) => write!(f, "irq on"),
+            Self::Irq(false) => write!(f, "irq off"),
+            Self::Nmi => write!(f, "nmi"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InputEvent {
+    pub cycle: u64,     // CPU cycle counter when the input was applied, i.e. before the next instruction
+    pub input: Input,
+}
+
+// events in the order they occurred
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct InputLog {
+    events: VecDeque<InputEvent>,
+}
+
+impl InputLog {
+    pub fn create() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, cycle: u64, input: Input) {
+        self.events.push_back(InputEvent { cycle, input });
+    }
+
+    pub fn events(&self) -> impl Iterator<Item = &InputEvent> + '_ {
+        self.events.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    // removes the next event if it is due at `cycle`
+    pub fn next_due(&mut self, cycle: u64) -> Option<Input> {
+        match self.events.front() {
+            Some(event) if event.cycle <= cycle => self.events.pop_front().map(|event| event.input),
+            _ => None,
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut log = Self::create();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: &str| format!("Line {}: {message} '{line}'", number + 1);
+            let (cycle, input) = line.split_once(char::is_whitespace).ok_or_else(|| error("Expected cycle and input"))?;
+            let cycle = cycle.parse().map_err(|_| error("Invalid cycle in"))?;
+            let input = match input.split_whitespace().collect::<Vec<&str>>()[..] {
+                ["irq", "on"] => Input::Irq(true),
+                ["irq", "off"] => Input::Irq(false),
+                ["nmi"] => Input::Nmi,
+                _ => return Err(error("Unknown input in")),
+            };
+            if log.events.back().is_some_and(|event| event.cycle > cycle) {
+                return Err(error("Cycles out of order in"));
+            }
+            log.record(cycle, input);
+        }
+        Ok(log)
+    }
+
+    #[cfg(feature = "std")]
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        for event in &self.events {
+            writeln!(writer, "{} {}", event.cycle, event.input)?;
+        }
+        writer.flush()
+    }
+
+    #[cfg(feature = "std")]
+    pub fn load_from_file(filename: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(filename).map_err(|error| format!("{filename}: {error}"))?;
+        Self::parse(&text).map_err(|error| format!("{filename}: {error}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_write() {
+        let mut log = InputLog::parse("# recorded\n100 irq on\n\n150 irq off  # handled\n150 nmi\n").unwrap();
+        #[cfg(feature = "std")]
+        {
+            let mut out = Vec::new();
+            log.write_to(&mut out).unwrap();
+            assert_eq!(String::from_utf8(out).unwrap(), "100 irq on\n150 irq off\n150 nmi\n");
+        }
+
+        assert_eq!(log.next_due(99), None);
+        assert_eq!(log.next_due(120), Some(Input::Irq(true)));
+        assert_eq!(log.next_due(120), None);
+        assert_eq!(log.next_due(150), Some(Input::Irq(false)));
+        assert_eq!(log.next_due(150), Some(Input::Nmi));
+        assert!(log.is_empty());
+
+        assert_eq!(InputLog::parse("10 irq\n").unwrap_err(), "Line 1: Unknown input in '10 irq'");
+        assert!(InputLog::parse("x nmi\n").is_err());
+        assert!(InputLog::parse("20 nmi\n10 nmi\n").is_err());
+    }
+}
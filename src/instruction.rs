@@ -1,13 +1,17 @@
-use std::fmt;
+use core::fmt;
+use core::str::FromStr;
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
 
 use AddressingMode::*;
 use Opcode::*;
 
+use crate::prelude::*;
+
 #[allow(non_camel_case_types)]
 #[derive(Debug, FromPrimitive, PartialEq, Copy, Clone)]
 #[repr(u8)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Opcode {
     // ADC - Add with Carry
     ADC_IMM = 0x69,
@@ -222,6 +226,48 @@ pub enum Opcode {
 
     // RTI - Return from Interrupt
     RTI = 0x40,
+
+    // added by the CMOS 65C02, undefined on the NMOS 6502
+    BRA_REL = 0x80,
+    PHX = 0xDA,
+    PHY = 0x5A,
+    PLX = 0xFA,
+    PLY = 0x7A,
+    STZ_ZPG = 0x64,
+    STZ_ZPX = 0x74,
+    STZ_ABS = 0x9C,
+    STZ_ABX = 0x9E,
+    TRB_ZPG = 0x14,
+    TRB_ABS = 0x1C,
+    TSB_ZPG = 0x04,
+    TSB_ABS = 0x0C,
+    INC_ACC = 0x1A,
+    DEC_ACC = 0x3A,
+    BIT_IMM = 0x89,
+    BIT_ZPX = 0x34,
+    BIT_ABX = 0x3C,
+    JMP_IAX = 0x7C,
+    ADC_ZPI = 0x72,
+    AND_ZPI = 0x32,
+    CMP_ZPI = 0xD2,
+    EOR_ZPI = 0x52,
+    LDA_ZPI = 0xB2,
+    ORA_ZPI = 0x12,
+    SBC_ZPI = 0xF2,
+    STA_ZPI = 0x92,
+}
+
+impl Opcode {
+    // the opcode of `byte`; those added by the 65C02 are undefined unless `cmos`
+    pub fn decode(byte: u8, cmos: bool) -> Option<Self> {
+        Self::from_u8(byte).filter(|opcode| cmos || !opcode.is_cmos())
+    }
+
+    pub fn is_cmos(&self) -> bool {
+        matches!(self, BRA_REL | PHX | PHY | PLX | PLY | STZ_ZPG | STZ_ZPX | STZ_ABS | STZ_ABX
+            | TRB_ZPG | TRB_ABS | TSB_ZPG | TSB_ABS | INC_ACC | DEC_ACC | BIT_IMM | BIT_ZPX | BIT_ABX | JMP_IAX
+            | ADC_ZPI | AND_ZPI | CMP_ZPI | EOR_ZPI | LDA_ZPI | ORA_ZPI | SBC_ZPI | STA_ZPI)
+    }
 }
 
 impl fmt::UpperHex for Opcode {
@@ -248,6 +294,7 @@ impl From<Opcode> for u8 {
     }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Instruction {
     pub opcode: Opcode,
     pub mnemonic: Mnemonic,
@@ -439,12 +486,77 @@ impl Instruction {
 
             BRK     => Ok(Self { opcode, mnemonic: Mnemonic::BRK, addr_mode: IMP, cycles: 7 }),
             RTI     => Ok(Self { opcode, mnemonic: Mnemonic::RTI, addr_mode: IMP, cycles: 6 }),
+
+            BRA_REL => Ok(Self { opcode, mnemonic: Mnemonic::BRA, addr_mode: REL, cycles: 2 /* +1 taken, +2 on a different page */ }),
+            PHX     => Ok(Self { opcode, mnemonic: Mnemonic::PHX, addr_mode: IMP, cycles: 3 }),
+            PHY     => Ok(Self { opcode, mnemonic: Mnemonic::PHY, addr_mode: IMP, cycles: 3 }),
+            PLX     => Ok(Self { opcode, mnemonic: Mnemonic::PLX, addr_mode: IMP, cycles: 4 }),
+            PLY     => Ok(Self { opcode, mnemonic: Mnemonic::PLY, addr_mode: IMP, cycles: 4 }),
+            STZ_ZPG => Ok(Self { opcode, mnemonic: Mnemonic::STZ, addr_mode: ZPG, cycles: 3 }),
+            STZ_ZPX => Ok(Self { opcode, mnemonic: Mnemonic::STZ, addr_mode: ZPX, cycles: 4 }),
+            STZ_ABS => Ok(Self { opcode, mnemonic: Mnemonic::STZ, addr_mode: ABS, cycles: 4 }),
+            STZ_ABX => Ok(Self { opcode, mnemonic: Mnemonic::STZ, addr_mode: ABX, cycles: 5 }),
+            TRB_ZPG => Ok(Self { opcode, mnemonic: Mnemonic::TRB, addr_mode: ZPG, cycles: 5 }),
+            TRB_ABS => Ok(Self { opcode, mnemonic: Mnemonic::TRB, addr_mode: ABS, cycles: 6 }),
+            TSB_ZPG => Ok(Self { opcode, mnemonic: Mnemonic::TSB, addr_mode: ZPG, cycles: 5 }),
+            TSB_ABS => Ok(Self { opcode, mnemonic: Mnemonic::TSB, addr_mode: ABS, cycles: 6 }),
+            INC_ACC => Ok(Self { opcode, mnemonic: Mnemonic::INC, addr_mode: ACC, cycles: 2 }),
+            DEC_ACC => Ok(Self { opcode, mnemonic: Mnemonic::DEC, addr_mode: ACC, cycles: 2 }),
+            BIT_IMM => Ok(Self { opcode, mnemonic: Mnemonic::BIT, addr_mode: IMM, cycles: 2 }),
+            BIT_ZPX => Ok(Self { opcode, mnemonic: Mnemonic::BIT, addr_mode: ZPX, cycles: 4 }),
+            BIT_ABX => Ok(Self { opcode, mnemonic: Mnemonic::BIT, addr_mode: ABX, cycles: 4 /* +1 if page crossed */ }),
+            JMP_IAX => Ok(Self { opcode, mnemonic: Mnemonic::JMP, addr_mode: IAX, cycles: 6 }),
+            ADC_ZPI => Ok(Self { opcode, mnemonic: Mnemonic::ADC, addr_mode: ZPI, cycles: 5 }),
+            AND_ZPI => Ok(Self { opcode, mnemonic: Mnemonic::AND, addr_mode: ZPI, cycles: 5 }),
+            CMP_ZPI => Ok(Self { opcode, mnemonic: Mnemonic::CMP, addr_mode: ZPI, cycles: 5 }),
+            EOR_ZPI => Ok(Self { opcode, mnemonic: Mnemonic::EOR, addr_mode: ZPI, cycles: 5 }),
+            LDA_ZPI => Ok(Self { opcode, mnemonic: Mnemonic::LDA, addr_mode: ZPI, cycles: 5 }),
+            ORA_ZPI => Ok(Self { opcode, mnemonic: Mnemonic::ORA, addr_mode: ZPI, cycles: 5 }),
+            SBC_ZPI => Ok(Self { opcode, mnemonic: Mnemonic::SBC, addr_mode: ZPI, cycles: 5 }),
+            STA_ZPI => Ok(Self { opcode, mnemonic: Mnemonic::STA, addr_mode: ZPI, cycles: 5 }),
+        }
+    }
+
+    // the instruction executed for `byte`; the 65C02 (`cmos`) executes the opcodes it leaves unassigned as NOPs
+    pub fn decode(byte: u8, cmos: bool) -> Option<Self> {
+        match Opcode::decode(byte, cmos) {
+            Some(opcode) => Self::from_opcode(opcode).ok(),
+            None if cmos => Self::unassigned_65c02(byte),
+            None => None,
         }
     }
 
+    // NOP of the length and cycles the 65C02 takes for an opcode it leaves unassigned (without the Rockwell and WDC
+    // bit instructions in the columns ending in 7 and F)
+    pub fn unassigned_65c02(byte: u8) -> Option<Self> {
+        let (addr_mode, cycles) = match byte {
+            0x02 | 0x22 | 0x42 | 0x62 | 0x82 | 0xC2 | 0xE2 => (IMM, 2),
+            0x44 => (ZPG, 3),
+            0x54 | 0xD4 | 0xF4 => (ZPX, 4),
+            0x5C => (ABS, 8),
+            0xDC | 0xFC => (ABS, 4),
+            _ if byte & 0x03 == 0x03 => (IMP, 1),
+            _ => return None,
+        };
+        Some(Self { opcode: NOP, mnemonic: Mnemonic::NOP, addr_mode, cycles })
+    }
+
+    // every opcode defined on the NMOS 6502, in ascending order
+    pub fn all() -> impl Iterator<Item = Instruction> {
+        (0..=0xFF)
+            .filter_map(|byte| Opcode::decode(byte, false))
+            .filter_map(|opcode| Self::from_opcode(opcode).ok())
+    }
+
     pub fn bytes(&self) -> u8 {
         self.addr_mode.instruction_bytes()
     }
+
+    // reads take one cycle more if indexing crosses a page boundary; stores and read-modify-write instructions always take the extra cycle
+    pub fn page_cross_penalty(&self) -> bool {
+        use Mnemonic::*;
+        matches!(self.addr_mode, ABX | ABY | IDY) && matches!(self.mnemonic, ADC | AND | BIT | CMP | EOR | LDA | LDX | LDY | ORA | SBC)
+    }
 }
 
 impl fmt::Debug for Instruction {
@@ -460,7 +572,8 @@ impl fmt::Debug for Instruction {
 }
 
 #[allow(non_camel_case_types)]
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Mnemonic {
     ADC,    // Add with Carry
     AND,    // Logical AND
@@ -472,6 +585,7 @@ pub enum Mnemonic {
     BMI,    // Branch if Minus
     BNE,    // Branch if Not Equal
     BPL,    // Branch if Positive
+    BRA,    // Branch Always
     BRK,    // Break
     BVC,    // Branch if Overflow Clear
     BVS,    // Branch if Overflow Set
@@ -499,8 +613,12 @@ pub enum Mnemonic {
     ORA,    // Logical OR
     PHA,    // Push Accumulator
     PHP,    // Push Processor Status
+    PHX,    // Push X Register
+    PHY,    // Push Y Register
     PLA,    // Pull Accumulator
     PLP,    // Pull Processor Status
+    PLX,    // Pull X Register
+    PLY,    // Pull Y Register
     ROL,    // Rotate Left
     ROR,    // Rotate Right
     RTI,    // Return from Interrupt
@@ -512,16 +630,38 @@ pub enum Mnemonic {
     STA,    // Store Accumulator
     STX,    // Store X Register
     STY,    // Store Y Register
+    STZ,    // Store Zero
     TAX,    // Transfer Accumulator to X
     TAY,    // Transfer Accumulator to Y
+    TRB,    // Test and Reset Bits
+    TSB,    // Test and Set Bits
     TSX,    // Transfer Stack Pointer to X
     TXA,    // Transfer X to Accumulator
     TXS,    // Transfer X to Stack Pointer
     TYA,    // Transfer Y to Accumulator
 }
 
+impl FromStr for Mnemonic {
+    type Err = String;
+
+    // case-insensitive, e.g. "lda"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Instruction::all()
+            .map(|ins| ins.mnemonic)
+            .find(|mnemonic| format!("{:?}", mnemonic).eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("Unknown mnemonic '{s}'"))
+    }
+}
+
+impl fmt::Display for Mnemonic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
 #[allow(non_camel_case_types)]
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum AddressingMode {
     IMP,    // Implied
     ACC,    // Accumulator
@@ -536,6 +676,8 @@ pub enum AddressingMode {
     IND,    // Indirect
     IDX,    // Indexed Indirect
     IDY,    // Indirect Indexed
+    ZPI,    // Zero Page Indirect (65C02)
+    IAX,    // Absolute Indexed Indirect (65C02)
 }
 
 impl AddressingMode {
@@ -556,27 +698,29 @@ impl AddressingMode {
 
     fn info(&self) -> (&'static str, &'static str, &'static str) {
         match self {
-            Self::IMP => ("IMP", "Implied",          ""),
-            Self::ACC => ("ACC", "Accumulator",      "A"),
-            Self::IMM => ("IMM", "Immediate",        "#oper"),
-            Self::ZPG => ("ZPG", "Zero Page",        "oper"),
-            Self::ZPX => ("ZPX", "Zero Page,X",      "oper,X"),
-            Self::ZPY => ("ZPY", "Zero Page,Y",      "oper,Y"),
-            Self::REL => ("REL", "Relative",         "oper"),
-            Self::ABS => ("ABS", "Absolute",         "oper"),
-            Self::ABX => ("ABX", "Absolute,X",       "oper,X"),
-            Self::ABY => ("ABY", "Absolute,Y",       "oper,Y"),
-            Self::IND => ("IND", "Indirect",         "(oper)"),
-            Self::IDX => ("IDX", "Indexed Indirect", "(oper,X)"),
-            Self::IDY => ("IDY", "Indirect Indexed", "(oper),Y"),
+            Self::IMP => ("IMP", "Implied",                   ""),
+            Self::ACC => ("ACC", "Accumulator",               "A"),
+            Self::IMM => ("IMM", "Immediate",                 "#oper"),
+            Self::ZPG => ("ZPG", "Zero Page",                 "oper"),
+            Self::ZPX => ("ZPX", "Zero Page,X",               "oper,X"),
+            Self::ZPY => ("ZPY", "Zero Page,Y",               "oper,Y"),
+            Self::REL => ("REL", "Relative",                  "oper"),
+            Self::ABS => ("ABS", "Absolute",                  "oper"),
+            Self::ABX => ("ABX", "Absolute,X",                "oper,X"),
+            Self::ABY => ("ABY", "Absolute,Y",                "oper,Y"),
+            Self::IND => ("IND", "Indirect",                  "(oper)"),
+            Self::IDX => ("IDX", "Indexed Indirect",          "(oper,X)"),
+            Self::IDY => ("IDY", "Indirect Indexed",          "(oper),Y"),
+            Self::ZPI => ("ZPI", "Zero Page Indirect",        "(oper)"),
+            Self::IAX => ("IAX", "Absolute Indexed Indirect", "(oper,X)"),
         }
     }
 
     pub fn instruction_bytes(&self) -> u8 {
         match self {
             Self::IMP | Self::ACC => 1,
-            Self::IMM | Self::ZPG | Self::ZPX | Self::ZPY | Self::REL | Self::IDX | Self::IDY => 2,
-            Self::ABS | Self::ABX | Self::ABY | Self::IND => 3,
+            Self::IMM | Self::ZPG | Self::ZPX | Self::ZPY | Self::REL | Self::IDX | Self::IDY | Self::ZPI => 2,
+            Self::ABS | Self::ABX | Self::ABY | Self::IND | Self::IAX => 3,
         }
     }
 }
@@ -586,3 +730,17 @@ impl fmt::Display for AddressingMode {
         write!(f, "{}", self.abbr())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn all() {
+        let all: Vec<Instruction> = Instruction::all().collect();
+        assert_eq!(all.len(), 151);
+        assert_eq!((all[0].opcode, all[0].mnemonic, all[0].bytes(), all[0].cycles), (BRK, Mnemonic::BRK, 1, 7));
+        assert!(all.windows(2).all(|pair| (pair[0].opcode as u8) < (pair[1].opcode as u8)));
+        assert_eq!(all.iter().filter(|ins| ins.mnemonic == Mnemonic::LDA).count(), 8);
+    }
+}
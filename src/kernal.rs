@@ -0,0 +1,297 @@
+// Commodore KERNAL entry points handled on the host (semihosting), so programs written for the C64 and its relatives
+// run without ROM images: their JSRs to the jump table are trapped and return like the ROM routines would.
+//
+//  $FFBA  SETLFS   logical file (A), device (X) and secondary address (Y), stored at $B8, $BA and $B9
+//  $FFBD  SETNAM   length of the file name (A) and its address (X/Y), stored at $B7 and $BB/$BC
+//  $FFCF  CHRIN    next character of the line typed on the host's console, ending with RETURN ($0D)
+//  $FFD2  CHROUT   character (A) printed on the host's console
+//  $FFD5  LOAD     load (A = 0) or verify (A = 1) the PRG file named by SETNAM from the host's directory; secondary
+//                  address 0 loads to X/Y, otherwise to the address in the file. Returns the end address in X/Y
+//                  (and $AE/$AF) with carry clear, or carry set and error 4 (file not found) or 8 (missing file name)
+//                  in A. A verify error sets bit 4 of the status at $90.
+//
+// Characters are PETSCII. Printing $0E switches to the lowercase character set, where $41-$5A are lowercase and
+// $C1-$DA uppercase letters, $8E back; in the uppercase set shifted characters are graphics and not printed, as are
+// colors and cursor controls. Only the console is supported as input and output channel.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use crate::cpu::{Cpu, StatusFlags};
+use crate::mem::Memory;
+
+pub const ADDR_SETLFS: u16 = 0xFFBA;
+pub const ADDR_SETNAM: u16 = 0xFFBD;
+pub const ADDR_CHRIN: u16 = 0xFFCF;
+pub const ADDR_CHROUT: u16 = 0xFFD2;
+pub const ADDR_LOAD: u16 = 0xFFD5;
+
+// zero page variables of the KERNAL
+pub const ZP_STATUS: u16 = 0x90;
+pub const ZP_END: u16 = 0xAE;
+pub const ZP_FILE_NAME_LENGTH: u16 = 0xB7;
+pub const ZP_LOGICAL_FILE: u16 = 0xB8;
+pub const ZP_SECONDARY_ADDR: u16 = 0xB9;
+pub const ZP_DEVICE: u16 = 0xBA;
+pub const ZP_FILE_NAME: u16 = 0xBB;
+
+pub const ERROR_FILE_NOT_FOUND: u8 = 4;
+pub const ERROR_MISSING_FILE_NAME: u8 = 8;
+
+const STATUS_VERIFY: u8 = 0x10;
+const RETURN: u8 = 0x0D;
+
+pub struct Kernal {
+    output: Box<dyn Write>,
+    input: Box<dyn BufRead>,
+    dir: PathBuf,               // of the files to load
+    line: VecDeque<u8>,         // typed, not yet returned by CHRIN
+    lowercase: bool,
+}
+
+impl Kernal {
+    pub fn create(output: Box<dyn Write>, input: Box<dyn BufRead>, dir: &str) -> Self {
+        Self { output, input, dir: PathBuf::from(dir), line: VecDeque::new(), lowercase: false }
+    }
+
+    // traps the entry points of the jump table
+    pub fn install(self, cpu: &mut Cpu) {
+        let kernal = Rc::new(RefCell::new(self));
+        let handler = |routine: fn(&mut Self, &mut Cpu, &mut Memory)| {
+            let kernal = Rc::clone(&kernal);
+            move |cpu: &mut Cpu, mem: &mut Memory| routine(&mut kernal.borrow_mut(), cpu, mem)
+        };
+        cpu.set_trap(ADDR_SETLFS, handler(Self::setlfs));
+        cpu.set_trap(ADDR_SETNAM, handler(Self::setnam));
+        cpu.set_trap(ADDR_CHRIN, handler(Self::chrin));
+        cpu.set_trap(ADDR_CHROUT, handler(Self::chrout));
+        cpu.set_trap(ADDR_LOAD, handler(Self::load));
+    }
+
+    fn setlfs(&mut self, cpu: &mut Cpu, mem: &mut Memory) {
+        mem.write_u8(ZP_LOGICAL_FILE, cpu.ac);
+        mem.write_u8(ZP_DEVICE, cpu.x);
+        mem.write_u8(ZP_SECONDARY_ADDR, cpu.y);
+    }
+
+    fn setnam(&mut self, cpu: &mut Cpu, mem: &mut Memory) {
+        mem.write_u8(ZP_FILE_NAME_LENGTH, cpu.ac);
+        mem.write_u8(ZP_FILE_NAME, cpu.x);
+        mem.write_u8(ZP_FILE_NAME + 1, cpu.y);
+    }
+
+    fn chrin(&mut self, cpu: &mut Cpu, _mem: &mut Memory) {
+        if self.line.is_empty() {
+            let mut line = String::new();
+            // a closed input ends every line at once
+            if self.input.read_line(&mut line).is_ok() {
+                let line = line.trim_end_matches(['\r', '\n']);
+                let typed = line.chars().filter_map(|char| self.petscii(char)).collect::<Vec<u8>>();
+                self.line.extend(typed);
+            }
+            self.line.push_back(RETURN);
+        }
+        cpu.ac = self.line.pop_front().unwrap_or(RETURN);
+        cpu.sr.remove(StatusFlags::C);
+    }
+
+    fn chrout(&mut self, cpu: &mut Cpu, _mem: &mut Memory) {
+        match cpu.ac {
+            0x0E => self.lowercase = true,
+            0x8E => self.lowercase = false,
+            code => if let Some(char) = self.ascii(code) {
+                let mut buffer = [0; 4];
+                let written = self.output.write_all(char.encode_utf8(&mut buffer).as_bytes()).and_then(|_| self.output.flush());
+                if let Err(error) = written {
+                    log::error!("Error writing KERNAL output: {error}");
+                }
+            },
+        }
+        cpu.sr.remove(StatusFlags::C);
+    }
+
+    fn load(&mut self, cpu: &mut Cpu, mem: &mut Memory) {
+        let length = mem.peek(ZP_FILE_NAME_LENGTH);
+        let addr = u16::from_le_bytes([mem.peek(ZP_FILE_NAME), mem.peek(ZP_FILE_NAME + 1)]);
+        let name = (0..length as u16)
+            .filter_map(|offset| self.ascii(mem.peek(addr.wrapping_add(offset))))
+            .collect::<String>()
+            .to_lowercase();
+        let image = match name.as_str() {
+            "" => Err(ERROR_MISSING_FILE_NAME),
+            name => fs::read(self.dir.join(name))
+                .or_else(|_| fs::read(self.dir.join(format!("{name}.prg"))))
+                .ok()
+                .filter(|image| image.len() >= 2)
+                .ok_or(ERROR_FILE_NOT_FOUND),
+        };
+        let image = match image {
+            Ok(image) => image,
+            Err(error) => {
+                cpu.ac = error;
+                cpu.sr.insert(StatusFlags::C);
+                return;
+            },
+        };
+
+        // the secondary address selects the load address given in X/Y or the one in the file
+        let start = match mem.peek(ZP_SECONDARY_ADDR) {
+            0 => u16::from_le_bytes([cpu.x, cpu.y]),
+            _ => u16::from_le_bytes([image[0], image[1]]),
+        };
+        let mut status = 0;
+        for (offset, &byte) in image[2..].iter().enumerate() {
+            let addr = start.wrapping_add(offset as u16);
+            if cpu.ac == 0 {
+                mem.write_u8(addr, byte);
+            } else if mem.peek(addr) != byte {
+                status = STATUS_VERIFY;
+            }
+        }
+        let end = start.wrapping_add((image.len() - 2) as u16);
+        mem.write_u8(ZP_STATUS, status);
+        mem.write_u16(ZP_END, end);
+        [cpu.x, cpu.y] = end.to_le_bytes();
+        cpu.sr.remove(StatusFlags::C);
+    }
+
+    // PETSCII to the host's characters; None for controls and graphics
+    fn ascii(&self, code: u8) -> Option<char> {
+        match code {
+            RETURN | 0x8D => Some('\n'),
+            0x20..=0x40 | 0x5B | 0x5D => Some(code as char),
+            0x5C => Some('£'),
+            0x5E => Some('↑'),
+            0x5F => Some('←'),
+            0x41..=0x5A if self.lowercase => Some(code.to_ascii_lowercase() as char),
+            0x41..=0x5A => Some(code as char),
+            0x61..=0x7A | 0xC1..=0xDA if self.lowercase => Some((code & 0x1F | 0x40) as char),
+            _ => None,
+        }
+    }
+
+    // the host's characters to PETSCII, as typed in the current character set
+    fn petscii(&self, char: char) -> Option<u8> {
+        match char {
+            'a'..='z' => Some(char.to_ascii_uppercase() as u8),
+            'A'..='Z' if self.lowercase => Some(char as u8 | 0x80),
+            ' '..='@' | 'A'..='Z' | '[' | ']' => Some(char as u8),
+            '£' => Some(0x5C),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::asm;
+    use crate::mem::ADDR_RESET_VECTOR;
+
+    use super::*;
+
+    // output shared with the test
+    #[derive(Clone, Default)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn run(source: &str, input: &str, dir: &str) -> (Cpu, Memory, String) {
+        let mut mem = Memory::create();
+        let mut cpu = Cpu::create();
+        cpu.reset(&mut mem);
+        asm::assemble(source).unwrap().write_to(&mut mem);
+        let output = Output::default();
+        Kernal::create(Box::new(output.clone()), Box::new(io::Cursor::new(String::from(input))), dir).install(&mut cpu);
+        while cpu.pc != ADDR_RESET_VECTOR + 0x100 {
+            cpu.exec(&mut mem, 1);
+        }
+        let output = String::from_utf8(output.0.take()).unwrap();
+        (cpu, mem, output)
+    }
+
+    #[test]
+    fn console() {
+        // echoes the typed line, in the lowercase character set after the first line
+        let (_, _, output) = run("
+                .org $E000
+                LDX #0
+        print:  LDA text,X
+                BEQ input
+                JSR $FFD2
+                INX
+                BNE print
+        input:  JSR $FFCF
+                JSR $FFD2
+                CMP #$0D
+                BNE input
+                LDA #$0E
+                JSR $FFD2
+                JSR $FFCF
+                JSR $FFD2
+                JSR $FFCF
+                JSR $FFD2
+                JMP $E100
+        text:   .byte $48, $C9, $21, $0D, $00
+        ", "Go 2!\nxY\n", ".");
+        assert_eq!(output, "H!\nGO 2!\nxY");
+    }
+
+    #[test]
+    fn load() {
+        let dir = std::env::temp_dir().join(format!("rust-6502-emu-kernal-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("data.prg"), [0x00, 0x30, 0x11, 0x22, 0x33]).unwrap();
+        let dir = dir.to_str().unwrap();
+
+        // from the address in the file, then to X/Y, then a file not found
+        let (cpu, mem, _) = run("
+                .org $E000
+                LDA #1
+                LDX #8
+                LDY #1
+                JSR $FFBA
+                LDA #4
+                LDX #$00
+                LDY #$E2
+                JSR $FFBD
+                LDA #0
+                JSR $FFD5
+                STX $10
+                STY $11
+                LDA #1
+                LDX #8
+                LDY #0
+                JSR $FFBA
+                LDA #0
+                LDX #$00
+                LDY #$40
+                JSR $FFD5
+                LDA #3
+                LDX #$00
+                LDY #$E2
+                JSR $FFBD
+                JSR $FFD5
+                JMP $E100
+                .org $E200
+                .byte $44, $41, $54, $41
+        ", "", dir);
+        fs::remove_dir_all(dir).unwrap();
+        assert_eq!((mem.peek(0x3000), mem.peek(0x3002), mem.peek(0x10), mem.peek(0x11)), (0x11, 0x33, 0x03, 0x30));
+        assert_eq!((mem.peek(0x4001), mem.peek(ZP_END), mem.peek(ZP_DEVICE), mem.peek(ZP_STATUS)), (0x22, 0x03, 8, 0));
+        assert_eq!((cpu.ac, cpu.sr.contains(StatusFlags::C)), (ERROR_FILE_NOT_FOUND, true));
+    }
+}
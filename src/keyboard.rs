@@ -0,0 +1,190 @@
+// Keyboard: host key presses from the terminal or a TCP connection (or given with `press`), translated by a keymap and
+// queued until the program reads them.
+//
+//  +0  data    read: code of the next waiting key, taking it; the last taken one if none is waiting
+//  +1  status  read: bit 7 set while a key is waiting; bit 0 (r/w) enables an interrupt while a key is waiting
+
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::ops::RangeInclusive;
+
+use crate::acia::SerialBackend;
+use crate::device::Device;
+
+pub const KEYBOARD_BASE_DEFAULT: u16 = 0xDF20;
+
+pub const REG_DATA: u16 = 0;
+pub const REG_STATUS: u16 = 1;
+
+const STATUS_READY: u8 = 0x80;
+const STATUS_IRQ_ENABLE: u8 = 0x01;
+
+// host byte to key code; unmapped bytes are passed unchanged
+#[derive(Clone, PartialEq, Default, Debug)]
+pub struct Keymap {
+    codes: HashMap<u8, u8>,
+}
+
+impl Keymap {
+    pub fn insert(&mut self, host: u8, code: u8) {
+        self.codes.insert(host, code);
+    }
+
+    pub fn map(&self, host: u8) -> u8 {
+        self.codes.get(&host).copied().unwrap_or(host)
+    }
+
+    // a line per key: host byte and key code, each a character or a hex byte ($0A); ';' starts a comment
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let byte = |field: &str| match field.strip_prefix('$') {
+            Some(hex) if !hex.is_empty() => u8::from_str_radix(hex, 16).ok(),
+            _ if field.len() == 1 => Some(field.as_bytes()[0]),
+            _ => None,
+        };
+        let mut keymap = Self::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or_default();
+            match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [] => {},
+                [host, code] => match (byte(host), byte(code)) {
+                    (Some(host), Some(code)) => keymap.insert(host, code),
+                    _ => return Err(format!("Line {}: expected characters or hex bytes ($0A)", number + 1)),
+                },
+                _ => return Err(format!("Line {}: expected host byte and key code", number + 1)),
+            }
+        }
+        Ok(keymap)
+    }
+
+    pub fn load(filename: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(filename).map_err(|error| format!("Cannot read keymap file '{filename}': {error}"))?;
+        Self::parse(&text).map_err(|error| format!("{filename}: {error}"))
+    }
+}
+
+pub struct Keyboard {
+    name: String,
+    pub base: u16,
+    backend: Option<Box<dyn SerialBackend>>,
+    pub keymap: Keymap,
+    keys: VecDeque<u8>,     // waiting key codes
+    data: u8,               // last taken key code
+    irq_enable: bool,
+}
+
+impl Keyboard {
+    // without backend keys are only given with `press`
+    pub fn create(name: &str, base: u16, backend: Option<Box<dyn SerialBackend>>, keymap: Keymap) -> Self {
+        Self {
+            name: String::from(name),
+            base,
+            backend,
+            keymap,
+            keys: VecDeque::new(),
+            data: 0,
+            irq_enable: false,
+        }
+    }
+
+    // queues the key code mapped from a host byte
+    pub fn press(&mut self, host: u8) {
+        self.keys.push_back(self.keymap.map(host));
+    }
+
+    pub fn waiting(&self) -> usize {
+        self.keys.len()
+    }
+
+    fn status(&self) -> u8 {
+        (if self.keys.is_empty() { 0 } else { STATUS_READY }) | (if self.irq_enable { STATUS_IRQ_ENABLE } else { 0 })
+    }
+}
+
+impl Device for Keyboard {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        self.base..=self.base + REG_STATUS
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr.wrapping_sub(self.base) {
+            REG_DATA => {
+                if let Some(code) = self.keys.pop_front() {
+                    self.data = code;
+                }
+                self.data
+            },
+            _ => self.status(),
+        }
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match addr.wrapping_sub(self.base) {
+            REG_DATA => self.keys.front().copied().unwrap_or(self.data),
+            _ => self.status(),
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        if addr.wrapping_sub(self.base) == REG_STATUS {
+            self.irq_enable = value & STATUS_IRQ_ENABLE != 0;
+        }
+    }
+
+    fn tick(&mut self, _cycles: u64) {
+        while let Some(host) = self.backend.as_mut().and_then(|backend| backend.receive()) {
+            self.press(host);
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_enable && !self.keys.is_empty()
+    }
+
+    fn reset(&mut self) {
+        self.keys.clear();
+        self.data = 0;
+        self.irq_enable = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys() {
+        let keymap = Keymap::parse("
+            ; lower case to upper case
+            a A
+            b B
+            $7F $08     ; delete as backspace
+        ").unwrap();
+        let mut keyboard = Keyboard::create("keyboard", KEYBOARD_BASE_DEFAULT, None, keymap);
+        assert_eq!(keyboard.read(0xDF21), 0x00);
+        for host in b"ab!\x7F" {
+            keyboard.press(*host);
+        }
+        assert_eq!((keyboard.peek(0xDF21), keyboard.peek(0xDF20)), (0x80, b'A'));
+        assert!(!keyboard.irq());
+        keyboard.write(0xDF21, 0x01);
+        assert!(keyboard.irq());
+
+        let codes: Vec<u8> = (0..4).map(|_| keyboard.read(0xDF20)).collect();
+        assert_eq!(codes, b"AB!\x08");
+        assert_eq!((keyboard.read(0xDF21), keyboard.read(0xDF20)), (0x01, 0x08));
+        assert!(!keyboard.irq());
+    }
+
+    #[test]
+    fn keymap() {
+        let keymap = Keymap::parse("$0A $0D\n$3B :").unwrap();
+        assert_eq!((keymap.map(0x0A), keymap.map(b';'), keymap.map(b'x')), (0x0D, b':', b'x'));
+        assert!(Keymap::parse("ab A").is_err());
+        assert!(Keymap::parse("a").is_err());
+        assert!(Keymap::parse("$XY A").is_err());
+    }
+}
@@ -0,0 +1,520 @@
+// HD44780 character LCD controller with a 16x2, 20x4 or similar display, mapped directly or connected to the ports of
+// a 6522 VIA as on Ben Eater's breadboard computer.
+//
+// Mapped register layout (offset from base address):
+//   +0  IR/SR         write: instruction; read: status, bit 7 busy and the address counter
+//   +1  DR            data to or from DDRAM or CGRAM at the address counter, which then moves on
+//
+// On the VIA the data bus is port B and the control lines are on port A: bit 7 E, bit 6 R/W, bit 5 RS. A write is
+// taken on the falling edge of E, a read is driven on port B while E is high.
+//
+// Instructions: $01 clear display, $02 return home, $04 entry mode (bit 1 increment, bit 0 shift the display),
+// $08 display control (bit 2 display on, bit 1 cursor, bit 0 blink), $10 cursor or display shift (bit 3 display,
+// bit 2 right), $20 function set (bit 4 8-bit interface, bit 3 two lines), $40 set CGRAM address, $80 set DDRAM
+// address. After power-on the interface is 8 bits wide; in 4-bit mode each byte is transferred as two nibbles on
+// D7-D4, the high one first. Instructions keep the controller busy for 37 cycles (clear and home for 1520), timed at
+// 1 MHz; writes while busy are not rejected.
+//
+// In two-line mode the lines are at DDRAM $00-$27 and $40-$67, rows 3 and 4 of a 4-row display continue rows 1 and 2
+// (e.g. at $14 and $54 on 20x4). Characters are shown as the A00 ROM (ASCII but for $5C yen and $7E/$7F arrows);
+// custom characters from CGRAM as a shaded block. The cursor is not shown.
+
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+use crate::device::Device;
+use crate::screen::Redraw;
+use crate::via::Via;
+
+pub const LCD_BASE_DEFAULT: u16 = 0xDF60;
+pub const LCD_REGISTERS: u16 = 2;
+
+pub const REG_INSTRUCTION: u16 = 0x0;
+pub const REG_DATA: u16 = 0x1;
+
+pub const STATUS_BUSY: u8 = 0b10000000;
+
+// control lines on the VIA's port A
+pub const VIA_E: u8 = 0b10000000;
+pub const VIA_RW: u8 = 0b01000000;
+pub const VIA_RS: u8 = 0b00100000;
+
+const BUSY_CYCLES: u64 = 37;
+const BUSY_CYCLES_HOME: u64 = 1520;
+const LINE_LEN: usize = 40;            // in two-line mode
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LcdConnection {
+    Mapped,
+    Via,
+}
+
+#[derive(Clone, Debug)]
+pub struct Hd44780 {
+    ddram: [u8; 0x80],
+    cgram: [u8; 0x40],
+    addr: u8,               // address counter
+    cgram_selected: bool,
+    increment: bool,
+    shift_on_write: bool,
+    display_on: bool,
+    cursor: bool,
+    blink: bool,
+    shift: i32,             // display shifted left by this many characters
+    eight_bit: bool,
+    two_lines: bool,
+    low_nibble: bool,       // in 4-bit mode, the high nibble has been transferred
+    high: u8,               // written high nibble
+    busy: u64,              // cycles
+}
+
+impl Default for Hd44780 {
+    fn default() -> Self {
+        Self {
+            ddram: [b' '; 0x80],
+            cgram: [0; 0x40],
+            addr: 0,
+            cgram_selected: false,
+            increment: true,
+            shift_on_write: false,
+            display_on: false,
+            cursor: false,
+            blink: false,
+            shift: 0,
+            eight_bit: true,
+            two_lines: false,
+            low_nibble: false,
+            high: 0,
+            busy: 0,
+        }
+    }
+}
+
+impl Hd44780 {
+    pub fn busy(&self) -> bool {
+        self.busy > 0
+    }
+
+    pub fn addr(&self) -> u8 {
+        self.addr
+    }
+
+    // display on, cursor shown, cursor blinking
+    pub fn display_control(&self) -> (bool, bool, bool) {
+        (self.display_on, self.cursor, self.blink)
+    }
+
+    pub fn eight_bit(&self) -> bool {
+        self.eight_bit
+    }
+
+    // a transfer from the CPU: a byte, or in 4-bit mode a nibble on D7-D4
+    pub fn write(&mut self, rs: bool, value: u8) {
+        if self.eight_bit {
+            self.execute(rs, value);
+        } else if self.low_nibble {
+            self.low_nibble = false;
+            self.execute(rs, self.high | value >> 4);
+        } else {
+            self.low_nibble = true;
+            self.high = value & 0xF0;
+        }
+    }
+
+    // what a transfer to the CPU would return
+    pub fn peek(&self, rs: bool) -> u8 {
+        let value = match rs {
+            false => (if self.busy() { STATUS_BUSY } else { 0 }) | self.addr,
+            true if self.cgram_selected => self.cgram[self.addr as usize % 0x40],
+            true => self.ddram[self.addr as usize],
+        };
+        match (self.eight_bit, self.low_nibble) {
+            (true, _) => value,
+            (false, false) => value & 0xF0,
+            (false, true) => value << 4,
+        }
+    }
+
+    // a transfer to the CPU; reading data moves the address counter on
+    pub fn read(&mut self, rs: bool) -> u8 {
+        let value = self.peek(rs);
+        if !self.eight_bit {
+            self.low_nibble = !self.low_nibble;
+            if self.low_nibble {
+                return value;
+            }
+        }
+        if rs {
+            self.step(self.increment);
+        }
+        value
+    }
+
+    pub fn tick(&mut self, cycles: u64) {
+        self.busy = self.busy.saturating_sub(cycles);
+    }
+
+    fn execute(&mut self, rs: bool, value: u8) {
+        self.busy = BUSY_CYCLES;
+        if rs {
+            match self.cgram_selected {
+                true => self.cgram[self.addr as usize % 0x40] = value,
+                false => self.ddram[self.addr as usize] = value,
+            }
+            self.step(self.increment);
+            if self.shift_on_write && !self.cgram_selected {
+                self.shift += if self.increment { 1 } else { -1 };
+            }
+            return;
+        }
+        match value {
+            0x80.. => (self.addr, self.cgram_selected) = (value & 0x7F, false),
+            0x40.. => (self.addr, self.cgram_selected) = (value & 0x3F, true),
+            0x20.. => {
+                self.eight_bit = value & 0x10 != 0;
+                self.two_lines = value & 0x08 != 0;
+                self.low_nibble = false;
+            },
+            0x10.. => match value & 0x08 != 0 {
+                true => self.shift += if value & 0x04 != 0 { -1 } else { 1 },
+                false => self.step(value & 0x04 != 0),
+            },
+            0x08.. => (self.display_on, self.cursor, self.blink) = (value & 0x04 != 0, value & 0x02 != 0, value & 0x01 != 0),
+            0x04.. => (self.increment, self.shift_on_write) = (value & 0x02 != 0, value & 0x01 != 0),
+            0x01 => {
+                self.ddram = [b' '; 0x80];
+                (self.addr, self.cgram_selected, self.shift, self.increment) = (0, false, 0, true);
+                self.busy = BUSY_CYCLES_HOME;
+            },
+            0x02 | 0x03 => {
+                (self.addr, self.cgram_selected, self.shift) = (0, false, 0);
+                self.busy = BUSY_CYCLES_HOME;
+            },
+            _ => {},
+        }
+    }
+
+    // moves the address counter, within the lines of DDRAM
+    fn step(&mut self, forward: bool) {
+        self.addr = match (self.cgram_selected, self.two_lines, forward) {
+            (true, _, true) => (self.addr + 1) & 0x3F,
+            (true, _, false) => self.addr.wrapping_sub(1) & 0x3F,
+            (false, false, true) => (self.addr + 1) % 80,
+            (false, false, false) => self.addr.checked_sub(1).unwrap_or(79),
+            (false, true, true) => match self.addr {
+                0x27 => 0x40,
+                0x67 => 0x00,
+                addr => (addr + 1) & 0x7F,
+            },
+            (false, true, false) => match self.addr {
+                0x00 => 0x67,
+                0x40 => 0x27,
+                addr => addr - 1,
+            },
+        };
+    }
+
+    // DDRAM address shown at a row and column of the display, if any
+    fn ddram_addr(&self, columns: u16, row: u16, column: u16) -> Option<u8> {
+        match self.two_lines {
+            true => {
+                let offset = (row / 2 * columns + column) as i32 + self.shift;
+                Some((row % 2) as u8 * 0x40 + offset.rem_euclid(LINE_LEN as i32) as u8)
+            },
+            false if row == 0 => Some((column as i32 + self.shift).rem_euclid(2 * LINE_LEN as i32) as u8),
+            false => None,
+        }
+    }
+}
+
+// character of the A00 (Japanese) ROM, approximated
+pub fn char(code: u8) -> char {
+    match code {
+        0x00..=0x0F => '▒',
+        0x5C => '¥',
+        0x7E => '→',
+        0x7F => '←',
+        0x20..=0x7D => code as char,
+        0xDF => '°',
+        0xFF => '█',
+        _ => ' ',
+    }
+}
+
+pub struct Lcd {
+    name: String,
+    pub base: u16,
+    pub columns: u16,
+    pub rows: u16,
+    controller: Hd44780,
+    via: Option<Via>,
+    control: u8,            // E, R/W and RS as last seen on the VIA
+    drawn: Option<Vec<String>>,
+}
+
+impl Lcd {
+    pub fn create(name: &str, base: u16, columns: u16, rows: u16) -> Self {
+        Self {
+            name: String::from(name),
+            base,
+            columns,
+            rows,
+            controller: Hd44780::default(),
+            via: None,
+            control: 0,
+            drawn: None,
+        }
+    }
+
+    // connected to the VIA's ports, mapped at its registers
+    pub fn with_via(name: &str, via: Via, columns: u16, rows: u16) -> Self {
+        let base = via.base;
+        Self { via: Some(via), ..Self::create(name, base, columns, rows) }
+    }
+
+    // "COLUMNSxROWS", e.g. "16x2"; up to 80 characters on up to 4 rows
+    pub fn parse_geometry(text: &str) -> Result<(u16, u16), String> {
+        let error = || format!("Expected COLUMNSxROWS with up to 80 characters on 1, 2 or 4 rows, e.g. 16x2, instead of '{text}'");
+        let (columns, rows) = text.split_once('x').ok_or_else(error)?;
+        let (columns, rows): (u16, u16) = (columns.parse().map_err(|_| error())?, rows.parse().map_err(|_| error())?);
+        match [1, 2, 4].contains(&rows) && columns > 0 && columns as u32 * rows as u32 <= 80 {
+            true => Ok((columns, rows)),
+            false => Err(error()),
+        }
+    }
+
+    pub fn controller(&self) -> &Hd44780 {
+        &self.controller
+    }
+
+    pub fn via(&self) -> Option<&Via> {
+        self.via.as_ref()
+    }
+
+    // the rows as shown, blank while the display is off
+    pub fn lines(&self) -> Vec<String> {
+        (0..self.rows)
+            .map(|row| (0..self.columns)
+                .map(|column| match self.controller.ddram_addr(self.columns, row, column) {
+                    Some(addr) if self.controller.display_on => char(self.controller.ddram[addr as usize]),
+                    _ => ' ',
+                })
+                .collect())
+            .collect()
+    }
+
+    // draws the display framed from a row of the terminal on (1 is the top, then cleared first) if its contents
+    // changed or always for a full redraw, and leaves the cursor below; returns whether anything was drawn
+    pub fn render(&mut self, out: &mut impl Write, top: u16, redraw: Redraw) -> io::Result<bool> {
+        let lines = self.lines();
+        if redraw == Redraw::Diff && self.drawn.as_ref() == Some(&lines) {
+            return Ok(false);
+        }
+        if top == 1 && self.drawn.is_none() {
+            write!(out, "\x1b[H\x1b[2J")?;
+        }
+        let border = "─".repeat(self.columns as usize);
+        write!(out, "\x1b[{top};1H┌{border}┐")?;
+        for (row, line) in (top + 1..).zip(&lines) {
+            write!(out, "\x1b[{row};1H│{line}│")?;
+        }
+        write!(out, "\x1b[{};1H└{border}┘\x1b[{};1H", top + self.rows + 1, top + self.rows + 2)?;
+        out.flush()?;
+        self.drawn = Some(lines);
+        Ok(true)
+    }
+
+    // follows the control lines after the CPU changed a port: data is taken when E falls and driven while E is high
+    fn update_bus(&mut self) {
+        let Some(via) = &mut self.via else {
+            return;
+        };
+        let control = via.port_a() & (VIA_E | VIA_RW | VIA_RS);
+        let (rs, rw) = (control & VIA_RS != 0, control & VIA_RW != 0);
+        match (self.control & VIA_E != 0, control & VIA_E != 0) {
+            (false, true) if rw => via.set_port_b(self.controller.peek(rs)),
+            (true, false) if rw => {
+                self.controller.read(rs);
+                via.set_port_b(0xFF);
+            },
+            (true, false) => self.controller.write(rs, via.port_b()),
+            _ => {},
+        }
+        self.control = control;
+    }
+}
+
+impl Device for Lcd {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        match &self.via {
+            Some(via) => via.range(),
+            None => self.base..=self.base + (LCD_REGISTERS - 1),
+        }
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        match &mut self.via {
+            Some(via) => via.read(addr),
+            None => self.controller.read(addr.wrapping_sub(self.base) % LCD_REGISTERS == REG_DATA),
+        }
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match &self.via {
+            Some(via) => via.peek(addr),
+            None => self.controller.peek(addr.wrapping_sub(self.base) % LCD_REGISTERS == REG_DATA),
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match &mut self.via {
+            Some(via) => {
+                via.write(addr, value);
+                self.update_bus();
+            },
+            None => self.controller.write(addr.wrapping_sub(self.base) % LCD_REGISTERS == REG_DATA, value),
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.controller.tick(cycles);
+        if let Some(via) = &mut self.via {
+            via.tick(cycles);
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.via.as_ref().is_some_and(|via| via.irq())
+    }
+
+    // the VIA is reset, the controller has no reset input and keeps its state
+    fn reset(&mut self) {
+        if let Some(via) = &mut self.via {
+            via.reset();
+            via.set_port_b(0xFF);
+            self.control = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::via::{REG_DDRA, REG_DDRB, REG_ORA, REG_ORB, VIA_BASE_DEFAULT};
+
+    use super::*;
+
+    const BASE: u16 = LCD_BASE_DEFAULT;
+
+    fn puts(lcd: &mut Lcd, text: &str) {
+        for byte in text.bytes() {
+            lcd.write(BASE + REG_DATA, byte);
+        }
+    }
+
+    #[test]
+    fn commands() {
+        let mut lcd = Lcd::create("lcd", BASE, 16, 2);
+        assert_eq!(lcd.lines(), vec![" ".repeat(16); 2]);
+
+        // 8 bits, two lines, display on, clear
+        for instruction in [0x38, 0x0E, 0x06, 0x01] {
+            lcd.write(BASE + REG_INSTRUCTION, instruction);
+        }
+        assert_eq!(lcd.read(BASE + REG_INSTRUCTION), STATUS_BUSY);
+        lcd.tick(BUSY_CYCLES_HOME);
+        assert_eq!((lcd.peek(BASE + REG_INSTRUCTION), lcd.controller().display_control()), (0x00, (true, true, false)));
+        puts(&mut lcd, "Hello,");
+        lcd.write(BASE + REG_INSTRUCTION, 0xC2);
+        puts(&mut lcd, "world\\~");
+        assert_eq!(lcd.lines(), vec!["Hello,          ", "  world¥→       "]);
+
+        // wrapping from the end of the first line into the second, reading back
+        lcd.write(BASE + REG_INSTRUCTION, 0x80 | 0x26);
+        puts(&mut lcd, "ab");
+        assert_eq!(lcd.controller().addr(), 0x40);
+        lcd.write(BASE + REG_INSTRUCTION, 0x80);
+        assert_eq!((lcd.read(BASE + REG_DATA), lcd.read(BASE + REG_DATA), lcd.controller().addr()), (b'H', b'e', 0x02));
+
+        // the display shifted left by one, the cursor moved back, then off
+        lcd.write(BASE + REG_INSTRUCTION, 0x18);
+        lcd.write(BASE + REG_INSTRUCTION, 0x10);
+        assert_eq!(lcd.controller().addr(), 0x01);
+        assert_eq!(lcd.lines(), vec!["ello,           ", " world¥→        "]);
+        lcd.write(BASE + REG_INSTRUCTION, 0x08);
+        assert_eq!(lcd.lines(), vec![" ".repeat(16); 2]);
+
+        // custom characters, and rows 3 and 4 of a 20x4 display
+        let mut lcd = Lcd::create("lcd", BASE, 20, 4);
+        for instruction in [0x38, 0x0C, 0x40] {
+            lcd.write(BASE + REG_INSTRUCTION, instruction);
+        }
+        puts(&mut lcd, "\x1F\x11");
+        lcd.write(BASE + REG_INSTRUCTION, 0x80 | 0x14);
+        puts(&mut lcd, "\x00 row 3");
+        lcd.write(BASE + REG_INSTRUCTION, 0x80 | 0x54);
+        puts(&mut lcd, "row 4");
+        assert_eq!(lcd.lines()[2..], ["▒ row 3", "row 4"].map(|line| format!("{line:<20}")));
+        assert_eq!(Lcd::parse_geometry("20x4"), Ok((20, 4)));
+        assert!(Lcd::parse_geometry("40x4").is_err() && Lcd::parse_geometry("16x3").is_err());
+    }
+
+    #[test]
+    fn via() {
+        // Ben Eater's wiring: data on port B, E, R/W and RS on port A, 4-bit mode on D7-D4
+        let mut lcd = Lcd::with_via("lcd", Via::create("via", VIA_BASE_DEFAULT), 16, 2);
+        assert_eq!(lcd.range(), VIA_BASE_DEFAULT..=VIA_BASE_DEFAULT + 15);
+        lcd.write(VIA_BASE_DEFAULT + REG_DDRA, 0xE0);
+        lcd.write(VIA_BASE_DEFAULT + REG_DDRB, 0xFF);
+        let send = |lcd: &mut Lcd, rs: u8, value: u8| {
+            lcd.write(VIA_BASE_DEFAULT + REG_ORB, value);
+            lcd.write(VIA_BASE_DEFAULT + REG_ORA, rs);
+            lcd.write(VIA_BASE_DEFAULT + REG_ORA, rs | VIA_E);
+            lcd.write(VIA_BASE_DEFAULT + REG_ORA, rs);
+        };
+        send(&mut lcd, 0, 0x20);
+        assert!(!lcd.controller().eight_bit());
+        for instruction in [0x28, 0x0C, 0x06] {
+            send(&mut lcd, 0, instruction & 0xF0);
+            send(&mut lcd, 0, instruction << 4);
+        }
+        for byte in *b"Hi" {
+            send(&mut lcd, VIA_RS, byte & 0xF0);
+            send(&mut lcd, VIA_RS, byte << 4);
+        }
+        assert_eq!(lcd.lines(), vec!["Hi              ", "                "]);
+
+        // polling the busy flag and address counter: high nibble, then low nibble
+        lcd.write(VIA_BASE_DEFAULT + REG_DDRB, 0x00);
+        lcd.write(VIA_BASE_DEFAULT + REG_ORA, VIA_RW);
+        lcd.write(VIA_BASE_DEFAULT + REG_ORA, VIA_RW | VIA_E);
+        assert_eq!(lcd.read(VIA_BASE_DEFAULT + REG_ORB), STATUS_BUSY);
+        lcd.write(VIA_BASE_DEFAULT + REG_ORA, VIA_RW);
+        lcd.tick(BUSY_CYCLES);
+        lcd.write(VIA_BASE_DEFAULT + REG_ORA, VIA_RW | VIA_E);
+        assert_eq!(lcd.read(VIA_BASE_DEFAULT + REG_ORB), 0x20);
+        lcd.write(VIA_BASE_DEFAULT + REG_ORA, VIA_RW);
+        assert_eq!(lcd.read(VIA_BASE_DEFAULT + REG_ORB), 0xFF);
+    }
+
+    #[test]
+    fn render() {
+        let mut lcd = Lcd::create("lcd", BASE, 4, 1);
+        for instruction in [0x30, 0x0C] {
+            lcd.write(BASE + REG_INSTRUCTION, instruction);
+        }
+        puts(&mut lcd, "OK");
+        let mut out = Vec::new();
+        assert!(lcd.render(&mut out, 1, Redraw::Diff).unwrap());
+        assert_eq!(String::from_utf8(out).unwrap(), "\x1b[H\x1b[2J\x1b[1;1H┌────┐\x1b[2;1H│OK  │\x1b[3;1H└────┘\x1b[4;1H");
+
+        // only when changed, unless redrawn in full
+        let mut out = Vec::new();
+        assert!(!lcd.render(&mut out, 1, Redraw::Diff).unwrap());
+        assert!(lcd.render(&mut out, 5, Redraw::Full).unwrap());
+        assert!(String::from_utf8(out).unwrap().starts_with("\x1b[5;1H┌"));
+    }
+}
@@ -1,114 +1,122 @@
-use std::error::Error;
-use std::io::{self, Write};
-
-use colored::Colorize;
-
-use crate::cpu::Cpu;
-use crate::mem::Memory;
-
+// Without the feature "std" the crate is `no_std` (with `alloc`): the CPU, memory, instruction set, device interface
+// and the debugging aids built into them remain; files, traces, saved states, the machines and devices need std. The
+// default feature "cli" adds the binary's parts on top: the command line, the interactive monitor and colored output.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+// the tests run on the host, so std is there for them also when the crate is built without it
+#[cfg(all(test, not(feature = "std")))]
+extern crate std;
+
+#[cfg(feature = "std")]
+pub mod acia;
+#[cfg(feature = "cli")]
+mod app;
+#[cfg(feature = "std")]
+pub mod apple2;
+#[cfg(feature = "std")]
+pub mod asm;
+#[cfg(feature = "std")]
+pub mod atari2600;
+#[cfg(feature = "audio")]
+pub mod audio;
+#[cfg(feature = "std")]
+pub mod beeper;
+pub mod bus;
+#[cfg(feature = "std")]
+pub mod builder;
+#[cfg(feature = "std")]
+pub mod c64;
+#[cfg(feature = "std")]
+pub mod chario;
+#[cfg(feature = "std")]
+pub mod cia;
+#[cfg(feature = "std")]
+pub mod commodore;
+pub mod coverage;
 pub mod cpu;
+#[cfg(feature = "std")]
+pub mod crt;
+pub mod debugger;
+pub mod device;
+pub mod disasm;
+pub mod dma;
+#[cfg(feature = "std")]
+pub mod emulator;
+pub mod events;
+pub mod expr;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "std")]
+pub mod framebuffer;
+#[cfg(feature = "std")]
+pub mod gpio;
+pub mod heatmap;
+#[cfg(feature = "std")]
+pub mod ihex;
+#[cfg(feature = "std")]
+pub mod ines;
+pub mod input;
 pub mod instruction;
+#[cfg(feature = "std")]
+pub mod kernal;
+#[cfg(feature = "std")]
+pub mod keyboard;
+#[cfg(feature = "std")]
+pub mod lcd;
+#[cfg(feature = "std")]
+pub mod machine;
 pub mod mem;
-
-#[derive(Copy, Clone, PartialEq, PartialOrd, Debug)]
-pub enum Verbosity {
-    Normal = 0,
-    Verbose = 1,
-    VeryVerbose = 2,
-}
-
-pub struct Config {
-    pub verbosity: Verbosity,
-    pub cycles_to_execute: Option<u64>,
-    pub load_demo: bool,
-    pub load_file: Option<String>,
-    pub interactive: bool,
-}
-
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    println!("rust-6502-emu");
-    if config.verbosity > Verbosity::Normal {
-        println!("Being verbose... {:?} [{}]", config.verbosity, config.verbosity as u8);
-    }
-
-    let mut mem = Memory::create();
-    let mut cpu = Cpu::create();
-    cpu.reset(&mut mem);
-
-    if let Some(filename) = config.load_file {
-        if let Err(error) = mem.load_from_file(mem::ADDR_RESET_VECTOR, &filename) {
-            panic!("Error reading file into memory: {error}");
-        }
-    }
-
-    if config.load_demo {
-        mem.demo();
-    }
-
-    if config.verbosity >= Verbosity::Verbose {
-        print!("Reset vector: ");
-        
-        mem.dump(cpu::VECTOR_RES, 2);
-        print!("Data at reset vector address: ");
-        mem.dump(mem::ADDR_RESET_VECTOR, 16);
-
-        println!("After reset: {:#?}", cpu);
-    }
-
-    cpu.dump_state(&mem);
-
-    if config.interactive {
-        while let Ok(user_input) = get_user_input() {
-            if user_input.is_empty() {
-                // probably ^D
-                break;
-            }
-            let user_input = user_input.trim();
-            if ! process_user_input(&mut cpu, &mut mem, user_input) {
-                break;
-            }
-        }
-    } else if let Some(cycles_to_execute) = config.cycles_to_execute {
-        cpu.exec(&mut mem, cycles_to_execute);
-    } else {
-        loop {
-            cpu.exec(&mut mem, 1);
-        }
-    }
-
-    Ok(())
-}
-
-
-fn get_user_input() -> Result<String, Box<dyn Error>> {
-    let mut user_input = String::new();
-    let stdin = io::stdin();
-    print!("{} ", "?".on_blue().white().bold());
-    _ = std::io::stdout().flush();
-    stdin.read_line(&mut user_input)?;
-    Ok(user_input)
-}
-
-fn process_user_input(cpu: &mut Cpu, mem: &mut Memory, user_input: &str) -> bool {
-    let (command, _args) = user_input.split_once(' ').unwrap_or((user_input, ""));
-
-    match command {
-        "" => {},
-        "h" | "?" => {
-            println!("{}", "Help".bold());
-            println!("{} - Quit", "q".yellow().bold());
-            println!("{} - Single step", "s".yellow().bold());
-            println!("{} - Run continuously", "r".yellow().bold());
-        },
-        "q" => return false,
-        "s" => cpu.exec(mem, 1),
-        "r" => {
-            loop {
-                cpu.exec(mem, 1);
-            }
-        },
-        _ => println!("Unknown command '{command}'. Try 'h' or '?'  for help."),
-    }
-
-    true
-}
+#[cfg(feature = "cli")]
+pub mod monitor;
+#[cfg(feature = "std")]
+pub mod nes;
+#[cfg(feature = "std")]
+pub mod pia;
+#[cfg(not(feature = "cli"))]
+mod plain;
+mod prelude;
+pub mod profile;
+#[cfg(feature = "std")]
+pub mod rewind;
+#[cfg(feature = "std")]
+pub mod riot;
+#[cfg(feature = "std")]
+pub mod rng;
+pub mod rom;
+#[cfg(feature = "std")]
+pub mod rtc;
+#[cfg(feature = "std")]
+pub mod runner;
+#[cfg(feature = "std")]
+pub mod screen;
+#[cfg(feature = "std")]
+pub mod segments;
+#[cfg(feature = "std")]
+pub mod state;
+pub mod stats;
+#[cfg(feature = "std")]
+pub mod storage;
+pub mod symbols;
+#[cfg(feature = "std")]
+pub mod tape;
+#[cfg(feature = "std")]
+pub mod trace;
+#[cfg(feature = "std")]
+pub mod via;
+#[cfg(feature = "std")]
+pub mod vic20;
+#[cfg(feature = "window")]
+pub mod window;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod watch;
+#[cfg(feature = "std")]
+pub mod woz;
+
+#[cfg(feature = "cli")]
+pub use crate::app::{run, Config, Verbosity};
+#[cfg(feature = "std")]
+pub use crate::builder::EmulatorBuilder;
+#[cfg(feature = "std")]
+pub use crate::emulator::Emulator;
@@ -0,0 +1,213 @@
+// Machine: the CPU with its memory map and attached devices, run in frames of a fixed number of cycles. A profile
+// describes a specific system as data (clock, ROM slots) plus a function wiring its devices into a fresh machine.
+
+use crate::acia::SerialBackend;
+use crate::apple2;
+use crate::atari2600;
+use crate::c64;
+use crate::cpu::{Cpu, StopReason};
+use crate::device::Device;
+use crate::keyboard::Keymap;
+use crate::mem::Memory;
+use crate::nes;
+use crate::rom::{Rom, RomImage};
+use crate::screen::TextScreen;
+use crate::vic20;
+
+pub const CLOCK_HZ_DEFAULT: u64 = 1_000_000;
+pub const FRAME_CYCLES_DEFAULT: u64 = 20000;    // 50 frames per second at 1 MHz
+
+// ROM image expected at an address, given by name on the command line
+pub struct RomSlot {
+    pub name: &'static str,
+    pub addr: u16,
+    pub size: usize,            // 0 for a container the profile checks itself (e.g. a cartridge image)
+}
+
+pub struct Profile {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub clock_hz: u64,
+    pub frame_cycles: u64,
+    pub roms: &'static [RomSlot],
+    pub keyboard: bool,         // has its own keyboard, taking the keyboard line
+    pub wire: fn(&mut Machine, &mut Wiring) -> Result<(), String>,
+}
+
+// what the command line hands to a profile
+#[derive(Default)]
+pub struct Wiring {
+    pub roms: Vec<(String, String)>,    // slot name and image file
+    pub keyboard: Option<Box<dyn SerialBackend>>,
+    pub keymap: Option<Keymap>,
+    images: Vec<(&'static RomSlot, RomImage)>,     // loaded, not yet taken by the profile
+}
+
+impl Wiring {
+    pub fn with_roms(roms: Vec<(String, String)>) -> Self {
+        Self { roms, ..Self::default() }
+    }
+
+    // a loaded ROM the profile maps itself (e.g. banked); the others are mapped at their slot's address
+    pub fn take_rom(&mut self, name: &str) -> Option<RomImage> {
+        let index = self.images.iter().position(|(slot, _)| slot.name == name)?;
+        Some(self.images.remove(index).1)
+    }
+}
+
+pub const GENERIC: Profile = Profile {
+    name: "generic",
+    description: "64K RAM, reset vector pointing to $E000; devices as configured",
+    clock_hz: CLOCK_HZ_DEFAULT,
+    frame_cycles: FRAME_CYCLES_DEFAULT,
+    roms: &[],
+    keyboard: false,
+    wire: |_, _| Ok(()),
+};
+
+pub const PROFILES: &[&Profile] = &[&GENERIC, &apple2::APPLE_II, &c64::C64, &vic20::VIC20, &nes::NES, &atari2600::ATARI_2600];
+
+pub fn profile(name: &str) -> Option<&'static Profile> {
+    PROFILES.iter().copied().find(|profile| profile.name.eq_ignore_ascii_case(name))
+}
+
+pub struct Machine {
+    pub name: &'static str,
+    pub cpu: Cpu,
+    pub mem: Memory,
+    pub clock_hz: u64,
+    pub frame_cycles: u64,
+    pub screen: Option<TextScreen>,     // text display of the system
+    frames: u64,
+}
+
+impl Machine {
+    // powered on: memory cleared, the profile's ROMs and devices wired, then reset
+    pub fn create(profile: &'static Profile, mut wiring: Wiring) -> Result<Self, String> {
+        let mut machine = Self {
+            name: profile.name,
+            cpu: Cpu::create(),
+            mem: Memory::create(),
+            clock_hz: profile.clock_hz,
+            frame_cycles: profile.frame_cycles,
+            screen: None,
+            frames: 0,
+        };
+        machine.cpu.reset(&mut machine.mem);
+        for (name, filename) in std::mem::take(&mut wiring.roms) {
+            let Some(slot) = profile.roms.iter().find(|slot| slot.name == name) else {
+                let names: Vec<_> = profile.roms.iter().map(|slot| slot.name).collect();
+                return Err(format!("Machine {} has no ROM '{name}' (ROMs: {})", profile.name, names.join(", ")));
+            };
+            let image = RomImage::open(&filename).map_err(|error| format!("{filename}: {error}"))?;
+            if slot.size != 0 && image.len() != slot.size {
+                return Err(format!("{filename}: {} ROM must have {} bytes instead of {}", slot.name, slot.size, image.len()));
+            }
+            wiring.images.push((slot, image));
+        }
+        (profile.wire)(&mut machine, &mut wiring)?;
+        for (slot, image) in wiring.images {
+            machine.attach(Box::new(Rom::create(slot.name, slot.addr, image)?));
+        }
+        machine.reset();
+        Ok(machine)
+    }
+
+    pub fn attach(&mut self, device: Box<dyn Device>) {
+        self.mem.attach_device(device);
+    }
+
+    // the reset button: CPU and devices restart, memory is kept
+    pub fn reset(&mut self) {
+        self.cpu.warm_reset(&mut self.mem);
+    }
+
+    // one instruction, or the entry into a pending interrupt
+    pub fn step(&mut self) -> Option<StopReason> {
+        self.cpu.exec(&mut self.mem, 1)
+    }
+
+    pub fn run(&mut self, cycles: u64) -> Option<StopReason> {
+        self.cpu.exec(&mut self.mem, cycles)
+    }
+
+    pub fn run_frame(&mut self) -> Option<StopReason> {
+        self.frames += 1;
+        self.run(self.frame_cycles)
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rng::{Rng, RNG_ADDR_DEFAULT};
+
+    use super::*;
+
+    #[test]
+    fn frames() {
+        // counts in a loop at $0300, started by its reset vector
+        const COUNTER: Profile = Profile {
+            name: "counter",
+            description: "",
+            clock_hz: CLOCK_HZ_DEFAULT,
+            frame_cycles: 100,
+            roms: &[],
+            keyboard: false,
+            wire: |machine, _| {
+                for (addr, byte) in (0x0300..).zip([0xE6, 0x10, 0x4C, 0x00, 0x03]) {    // INC $10; JMP $0300
+                    machine.mem.write_u8(addr, byte);
+                }
+                machine.mem.write_u16(0xFFFC, 0x0300);
+                machine.attach(Box::new(Rng::create("rng", RNG_ADDR_DEFAULT, 1)));
+                Ok(())
+            },
+        };
+        let mut machine = Machine::create(&COUNTER, Wiring::default()).unwrap();
+        assert_eq!(machine.cpu.pc, 0x0300);
+        assert!(machine.mem.device::<Rng>("rng").is_some());
+
+        assert_eq!(machine.step(), None);
+        assert_eq!((machine.cpu.pc, machine.mem.peek(0x10)), (0x0302, 1));
+
+        // 8 cycles per iteration
+        machine.run_frame();
+        assert_eq!((machine.frames(), machine.mem.peek(0x10)), (1, 14));
+
+        machine.reset();
+        assert_eq!((machine.cpu.pc, machine.mem.peek(0x10)), (0x0300, 14));
+        assert_eq!(profile("GENERIC").map(|profile| profile.name), Some("generic"));
+        assert!(profile("unknown").is_none());
+    }
+
+    #[test]
+    fn roms() {
+        const SYSTEM: Profile = Profile {
+            name: "system",
+            description: "",
+            clock_hz: CLOCK_HZ_DEFAULT,
+            frame_cycles: FRAME_CYCLES_DEFAULT,
+            roms: &[RomSlot { name: "rom", addr: 0xF000, size: 0x1000 }],
+            keyboard: false,
+            wire: |_, _| Ok(()),
+        };
+        let filename = std::env::temp_dir().join(format!("rust-6502-emu-system-{}.rom", std::process::id()));
+        let mut image = vec![0xEA; 0x1000];
+        image[0xFFC..].copy_from_slice(&[0x34, 0xF2, 0x00, 0x00]);
+        std::fs::write(&filename, &image).unwrap();
+        let rom = |name: &str| Wiring::with_roms(vec![(String::from(name), String::from(filename.to_str().unwrap()))]);
+
+        // the reset vector is read from the ROM, which ignores writes
+        let mut machine = Machine::create(&SYSTEM, rom("rom")).unwrap();
+        assert_eq!(machine.cpu.pc, 0xF234);
+        machine.mem.write_u8(0xF000, 0x00);
+        assert_eq!(machine.mem.peek(0xF000), 0xEA);
+        assert!(Machine::create(&SYSTEM, rom("basic")).is_err());
+        std::fs::write(&filename, &image[..0x800]).unwrap();
+        assert!(Machine::create(&SYSTEM, rom("rom")).is_err());
+        std::fs::remove_file(&filename).unwrap();
+    }
+}
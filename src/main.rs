@@ -1,6 +1,82 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
 use std::process;
-use clap::Parser;
-use rust_6502_emu::{Config, Verbosity};
+use clap::builder::PossibleValuesParser;
+use clap::{Parser, ValueEnum};
+use colored::Colorize;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use rust_6502_emu::{Config, Emulator, EmulatorBuilder, Verbosity};
+use rust_6502_emu::acia::{SerialLine, ACIA_TCP_PORT_DEFAULT};
+use rust_6502_emu::asm;
+use rust_6502_emu::crt::Cartridge;
+use rust_6502_emu::disasm::{Format, IllegalOpcodes};
+use rust_6502_emu::expr::{self, Radix};
+#[cfg(feature = "window")]
+use rust_6502_emu::framebuffer::{Framebuffer, FRAMEBUFFER_REFRESH_CYCLES_DEFAULT};
+use rust_6502_emu::gpio::GpioScript;
+use rust_6502_emu::keyboard::Keymap;
+use rust_6502_emu::lcd::{Lcd, LcdConnection};
+use rust_6502_emu::machine;
+use rust_6502_emu::mem::{UninitPolicy, ADDR_RESET_VECTOR};
+use rust_6502_emu::rng::Rng;
+use rust_6502_emu::rtc::{RtcClock, RTC_CLOCK_HZ_DEFAULT};
+use rust_6502_emu::screen::{Charset, Redraw, TextScreen, SCREEN_REFRESH_CYCLES_DEFAULT};
+use rust_6502_emu::tape::{Tape, TapeRate};
+use rust_6502_emu::symbols::SymbolTable;
+use rust_6502_emu::trace::TraceFormat;
+#[cfg(feature = "window")]
+use rust_6502_emu::window::WINDOW_SCALE_DEFAULT;
+use rust_6502_emu::woz::WozImage;
+
+#[derive(Copy, Clone, PartialEq, Debug, ValueEnum)]
+enum Uninit {
+    Ignore,
+    Warn,
+    Break,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, ValueEnum)]
+enum Clock {
+    Host,
+    Cycles,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, ValueEnum)]
+enum Chars {
+    Ascii,
+    Petscii,
+    Apple2,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, ValueEnum)]
+enum LcdBus {
+    Mapped,
+    Via,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, ValueEnum)]
+enum Trace {
+    Plain,
+    Vice,
+    Nestest,
+    Json,
+    Calls,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, ValueEnum)]
+enum Illegal {
+    Data,
+    Plain,
+    Marked,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, ValueEnum)]
+enum Base {
+    Hex,
+    Dec,
+}
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -17,17 +93,564 @@ struct Cli {
     #[arg(short, long)]
     file: Option<String>,
 
+    /// Load Woz monitor text (0300: A9 01 8D ...) at the stated addresses; starts at its run address (300R) or first byte
+    #[arg(long, value_name = "FILE")]
+    woz: Option<String>,
+
+    /// Assemble source file into memory at its .org address; labels become symbols
+    #[arg(long, value_name = "FILE")]
+    asm: Option<String>,
+
+    /// Write the binary assembled with --asm to file
+    #[arg(long, value_name = "FILE", requires = "asm")]
+    asm_bin: Option<String>,
+
+    /// Write a listing with addresses, bytes and cycles of the source assembled with --asm to file
+    #[arg(long, value_name = "FILE", requires = "asm")]
+    asm_listing: Option<String>,
+
+    /// Resume from machine state saved with the monitor's save command instead of starting after reset
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["demo", "file", "woz", "asm"])]
+    resume: Option<String>,
+
+    /// Memory-map banked ROM image at $8000 (16K banks, bank select at $DFFE)
+    #[arg(long)]
+    rom: Option<String>,
+
+    /// Map banks of a CRT cartridge image (C64 .crt and other VICE containers) at their load address; bank select at $DE00
+    #[arg(long, value_name = "FILE", conflicts_with = "rom")]
+    crt: Option<String>,
+
+    /// Load VICE label file (al C:e000 .start) for symbolic disassembly and monitor addresses
+    #[arg(short, long, value_name = "FILE")]
+    labels: Option<String>,
+
     /// Interactive mode
     #[arg(short, long)]
     interactive: bool,
 
-    /// Verbosity; can be specified multiple times
+    /// Execute monitor commands from file at startup; implies interactive mode
+    #[arg(short = 'x', long = "exec", value_name = "FILE")]
+    monitor_script: Option<String>,
+
+    /// Keep the machine state every n cycles in the monitor to go back with 'rewind'
+    #[arg(long, value_name = "CYCLES", value_parser = clap::value_parser!(u64).range(1..))]
+    checkpoints: Option<u64>,
+
+    /// System to emulate: its memory map, ROMs and devices, to which the devices given by options are added
+    #[arg(long, value_name = "MACHINE", default_value = machine::GENERIC.name, value_parser = PossibleValuesParser::new(machine::PROFILES.iter().map(|profile| profile.name)))]
+    machine: String,
+
+    /// Load ROM image of the machine into its slot, e.g. rom=apple2plus.rom; can be specified multiple times
+    #[arg(long, value_name = "NAME=FILE", value_parser = parse_machine_rom)]
+    machine_rom: Vec<(String, String)>,
+
+    /// Attach DMA block-copy controller at $DF00
+    #[arg(long)]
+    dma: bool,
+
+    /// Attach 6522 VIA at $6000
+    #[arg(long)]
+    via: bool,
+
+    /// Attach 6821 PIA at $D010
+    #[arg(long)]
+    pia: bool,
+
+    /// Attach 6551 ACIA at $5000 with its serial line on the terminal (stdio, not with the monitor) or a TCP port on localhost (tcp[:PORT], default 6502)
+    #[arg(long, value_name = "LINE", value_parser = parse_serial_line)]
+    acia: Option<SerialLine>,
+
+    /// Attach character I/O (output at $F001, input at $F004) on the terminal (stdio) or a TCP port (tcp[:PORT])
+    #[arg(long, value_name = "LINE", value_parser = parse_serial_line)]
+    char_io: Option<SerialLine>,
+
+    /// Attach keyboard at $DF20 (data, status with bit 7 set while a key is waiting) fed from the terminal (stdio) or a TCP port (tcp[:PORT])
+    #[arg(long, value_name = "LINE", value_parser = parse_serial_line)]
+    keyboard: Option<SerialLine>,
+
+    /// Load keymap translating host characters to key codes from file, a line per key: host and code, each a character or hex byte ($0A)
+    #[arg(long, value_name = "FILE", requires = "keyboard")]
+    keymap: Option<String>,
+
+    /// Attach beeper at $DF30 playing on the host's sound output: any access to $DF30 toggles the speaker, or a tone with half period at $DF31/$DF32 is turned on with bit 0 of $DF33
+    #[cfg(feature = "audio")]
+    #[arg(long)]
+    beeper: bool,
+
+    /// Attach random number generator at $FE, each read returning a new random byte
+    #[arg(long)]
+    rng: bool,
+
+    /// Seed of the random number generator for reproducible runs; by default it differs from run to run
+    #[arg(long, value_name = "SEED", requires = "rng")]
+    rng_seed: Option<u64>,
+
+    /// Attach real-time clock at $DF10 following the host's clock or the emulated cycles (reproducible)
+    #[arg(long, value_enum, value_name = "CLOCK")]
+    rtc: Option<Clock>,
+
+    /// Attach block storage at $DF40 backed by image file, accessed in 512-byte sectors by LBA through a data register
+    #[arg(long, value_name = "FILE")]
+    storage: Option<String>,
+
+    /// Attach tape at $DF50 reading file as byte stream (data, status with bit 7 set while a byte is ready) while the motor is on (bit 0 of $DF51)
+    #[arg(long, value_name = "FILE")]
+    tape: Option<String>,
+
+    /// Rate at which bytes arrive from the tape: bytes per second at 1 MHz or instant
+    #[arg(long, value_name = "RATE", default_value = "instant", value_parser = Tape::parse_rate, requires = "tape")]
+    tape_rate: TapeRate,
+
+    /// Attach HD44780 character LCD of COLUMNSxROWS, e.g. 16x2 or 20x4, shown in the terminal while running
+    #[arg(long, value_name = "COLUMNSxROWS", value_parser = Lcd::parse_geometry)]
+    lcd: Option<(u16, u16)>,
+
+    /// Connection of the LCD: mapped with instruction/status at $DF60 and data at $DF61, or on the ports of the VIA at $6000 as on Ben Eater's computer (data on port B; E, R/W and RS on PA7-PA5)
+    #[arg(long, value_enum, default_value_t = LcdBus::Mapped, requires = "lcd")]
+    lcd_bus: LcdBus,
+
+    /// Attach multiplexed seven-segment display of the given number of digits (1 to 16), with the segment port at $DF70 and the digit select port at $DF71, shown in the terminal while running
+    #[arg(long, value_name = "DIGITS", value_parser = clap::value_parser!(u8).range(1..=16))]
+    segments: Option<u8>,
+
+    /// Attach GPIO port at $DF80 (data, direction, interrupt enable and flags for changes of the input pins), whose pins are shown and driven by the monitor's gpio command
+    #[arg(long)]
+    gpio: bool,
+
+    /// Drive the input pins of the GPIO port from a script file, a line per change: cycle and levels in hex, e.g. "100000 FE"
+    #[arg(long, value_name = "FILE", requires = "gpio")]
+    gpio_script: Option<String>,
+
+    /// Render memory as text screen in the terminal while running, e.g. 0400:40x25 for 40 columns and 25 rows at $0400
+    #[arg(long, value_name = "ADDR:COLUMNSxROWS", value_parser = TextScreen::parse_geometry)]
+    screen: Option<(u16, u16, u16)>,
+
+    /// Character set of the screen; petscii maps C64 screen codes, apple2 Apple II characters
+    #[arg(long, value_enum, default_value_t = Chars::Ascii, requires = "screen")]
+    charset: Chars,
+
+    /// Render the screen every n cycles
+    #[arg(long, value_name = "CYCLES", default_value_t = SCREEN_REFRESH_CYCLES_DEFAULT, value_parser = clap::value_parser!(u64).range(1..), requires = "screen")]
+    screen_refresh: u64,
+
+    /// Redraw the whole screen each time instead of only changed characters
+    #[arg(long, requires = "screen")]
+    full_redraw: bool,
+
+    /// Show memory as bitmap in a window while running, e.g. 2000:64x64 for 64x64 pixels at $2000
+    #[cfg(feature = "window")]
+    #[arg(long, value_name = "ADDR:WIDTHxHEIGHT", value_parser = Framebuffer::parse_geometry)]
+    framebuffer: Option<(u16, u16, u16)>,
+
+    /// Bits per pixel of the framebuffer (1, 2, 4 or 8), leftmost pixel in the most significant bits
+    #[cfg(feature = "window")]
+    #[arg(long, default_value_t = 8, requires = "framebuffer")]
+    bpp: u8,
+
+    /// Load framebuffer colors as RRGGBB hex values from file; by default black and white, grey levels, C64 colors or RRRGGGBB
+    #[cfg(feature = "window")]
+    #[arg(long, value_name = "FILE", requires = "framebuffer")]
+    palette: Option<String>,
+
+    /// Render the framebuffer every n cycles
+    #[cfg(feature = "window")]
+    #[arg(long, value_name = "CYCLES", default_value_t = FRAMEBUFFER_REFRESH_CYCLES_DEFAULT, value_parser = clap::value_parser!(u64).range(1..), requires = "framebuffer")]
+    framebuffer_refresh: u64,
+
+    /// Factor by which the framebuffer window is scaled up (1, 2, 4, 8, 16 or 32)
+    #[cfg(feature = "window")]
+    #[arg(long, default_value_t = WINDOW_SCALE_DEFAULT, requires = "framebuffer")]
+    scale: u8,
+
+    /// Detection of reads of uninitialized memory
+    #[arg(long, value_enum, default_value_t = Uninit::Ignore)]
+    uninit: Uninit,
+
+    /// Stop when SP wraps around or a push overwrites a return address in use
+    #[arg(long)]
+    stack_check: bool,
+
+    /// Handle the Commodore KERNAL routines CHROUT ($FFD2), CHRIN ($FFCF), LOAD ($FFD5), SETNAM and SETLFS on the host: console output and input, PRG files loaded from the current directory
+    #[arg(long)]
+    kernal_traps: bool,
+
+    /// Directory of the files loaded by the trapped KERNAL LOAD
+    #[arg(long, value_name = "DIR", requires = "kernal_traps")]
+    kernal_dir: Option<String>,
+
+    /// Print memory access heatmap report after the run
+    #[arg(long)]
+    heatmap: bool,
+
+    /// Write memory access heatmap as CSV after the run
+    #[arg(long, value_name = "FILE")]
+    heatmap_csv: Option<String>,
+
+    /// Print cycles spent per address and routine after the run
+    #[arg(long)]
+    profile: bool,
+
+    /// Print instruction counts by mnemonic and addressing mode, cycles and interrupts after the run
+    #[arg(long)]
+    stats: bool,
+
+    /// Write executed instruction addresses (code coverage) to file after the run
+    #[arg(long, value_name = "FILE")]
+    coverage: Option<String>,
+
+    /// Write the executed instructions and register dumps to file without colors instead of the console
+    #[arg(long, value_name = "FILE")]
+    dump: Option<String>,
+
+    /// Write a trace line per executed instruction to file
+    #[arg(long, value_name = "FILE")]
+    trace: Option<String>,
+
+    /// Layout of trace lines; calls traces only jumps, calls, returns and interrupts with their targets
+    #[arg(long, value_enum, default_value_t = Trace::Plain, requires = "trace")]
+    trace_format: Trace,
+
+    /// Trace only instructions in address range (e.g. $E000..=$EFFF); can be specified multiple times
+    #[arg(long, value_name = "RANGE", value_parser = parse_range, requires = "trace")]
+    trace_range: Vec<RangeInclusive<u16>>,
+
+    /// Write the address bus, data bus, R/W line and SYNC of every cycle to file, as a logic analyzer would show them
+    #[arg(long, value_name = "FILE")]
+    bus_trace: Option<String>,
+
+    /// Compare each executed instruction with a reference trace (e.g. nestest.log) and stop at the first difference
+    #[arg(long, value_name = "FILE")]
+    compare_trace: Option<String>,
+
+    /// Record external inputs (IRQ, NMI) with their cycle to file after the run
+    #[arg(long, value_name = "FILE")]
+    record_input: Option<String>,
+
+    /// Replay external inputs recorded with --record-input at their cycles
+    #[arg(long, value_name = "FILE")]
+    replay_input: Option<String>,
+
+    /// Disassemble address range (e.g. $E000..$E100, or ..= to include the end) and exit without executing
+    #[arg(long, value_name = "RANGE", value_parser = parse_range)]
+    disassemble: Option<RangeInclusive<u16>>,
+
+    /// Disassemble as listing with cycles per instruction and notes on additional cycles
+    #[arg(long, requires = "disassemble")]
+    listing: bool,
+
+    /// Disassemble only executed addresses as code, others as data; file written by --coverage or an instruction trace
+    #[arg(long, value_name = "FILE", requires = "disassemble")]
+    code_from: Option<String>,
+
+    /// Omit the raw byte column from the disassembly
+    #[arg(long, requires = "disassemble")]
+    no_bytes: bool,
+
+    /// Disassemble with lowercase mnemonics
+    #[arg(long, requires = "disassemble")]
+    lowercase: bool,
+
+    /// Disassemble undocumented opcodes as data (.byte $A7), by name (LAX) or by marked name (*LAX)
+    #[arg(long, value_enum, default_value_t = Illegal::Data, requires = "disassemble")]
+    illegal: Illegal,
+
+    /// Base of addresses and operands in the disassembly
+    #[arg(long, value_enum, default_value_t = Base::Hex, requires = "disassemble")]
+    radix: Base,
+
+    /// Write disassembly to file instead of stdout
+    #[arg(short, long, value_name = "FILE", requires = "disassemble")]
+    output: Option<String>,
+
+    /// Verbosity: debug messages with -v, also those of the libraries with -vv
     #[arg(short, long, action = clap::ArgAction::Count, default_value_t = 0)]
     verbose: u8,
 }
 
+// "FROM..TO" (end excluded) or "FROM..=TO"; addresses are hex with optional $ prefix
+fn parse_range(text: &str) -> Result<RangeInclusive<u16>, String> {
+    let (from, to, inclusive) = match text.split_once("..") {
+        Some((from, to)) => match to.strip_prefix('=') {
+            Some(to) => (from, to, true),
+            None => (from, to, false),
+        },
+        None => return Err(String::from("expected FROM..TO")),
+    };
+    let parse = |addr: &str| expr::parse_number(addr, Radix::Hex)
+        .and_then(|value| u16::try_from(value).map_err(|_| format!("address out of range: {addr}")));
+    let (from, to) = (parse(from)?, parse(to)?);
+    match inclusive {
+        true if from <= to => Ok(from..=to),
+        false if from < to => Ok(from..=to - 1),
+        _ => Err(String::from("empty range")),
+    }
+}
+
+fn parse_machine_rom(text: &str) -> Result<(String, String), String> {
+    match text.split_once('=') {
+        Some((name, filename)) if !name.is_empty() && !filename.is_empty() => Ok((String::from(name), String::from(filename))),
+        _ => Err(String::from("expected NAME=FILE")),
+    }
+}
+
+fn parse_serial_line(text: &str) -> Result<SerialLine, String> {
+    match text.split_once(':') {
+        None if text == "stdio" => Ok(SerialLine::Stdio),
+        None if text == "tcp" => Ok(SerialLine::Tcp(ACIA_TCP_PORT_DEFAULT)),
+        Some(("tcp", port)) => port.parse().map(SerialLine::Tcp).map_err(|_| format!("invalid port: {port}")),
+        _ => Err(String::from("expected stdio or tcp[:PORT]")),
+    }
+}
+
+// prints the messages of the emulator up to `level` (of all crates with `all_targets`) without level or target,
+// warnings and errors marked
+struct ConsoleLogger {
+    level: LevelFilter,
+    all_targets: bool,
+}
+
+impl ConsoleLogger {
+    // informational messages by default, debug messages with -v and those of the dependencies at trace level with -vv
+    fn create(verbose: u8) -> Self {
+        match verbose {
+            0 => Self { level: LevelFilter::Info, all_targets: false },
+            1 => Self { level: LevelFilter::Debug, all_targets: false },
+            _ => Self { level: LevelFilter::Trace, all_targets: true },
+        }
+    }
+}
+
+impl Log for ConsoleLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level && (self.all_targets || metadata.target().starts_with(env!("CARGO_CRATE_NAME")))
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        match record.level() {
+            Level::Error | Level::Warn => println!("{} {}", "!!!".white().on_red().bold(), record.args()),
+            _ => println!("{}", record.args()),
+        }
+    }
+
+    fn flush(&self) {
+        let _ = io::stdout().flush();
+    }
+}
+
+// the machine as given by the options, before anything is executed
+fn build_emulator(args: &Cli, verbosity: Verbosity) -> Result<Emulator, Box<dyn Error>> {
+    // the monitor reads stdin, as does each device on stdio
+    let monitor = args.interactive || args.monitor_script.is_some();
+    let stdio_devices = [args.acia, args.char_io, args.keyboard].iter().filter(|line| **line == Some(SerialLine::Stdio)).count();
+    if stdio_devices + monitor as usize > 1 {
+        return Err("Only one of the ACIA, the character I/O, the keyboard and the monitor can use stdio, use a TCP port instead".into());
+    }
+    let mut builder = EmulatorBuilder::create()
+        .machine(machine::profile(&args.machine).expect("machine is one of the profiles"))
+        .uninit_policy(match args.uninit {
+            Uninit::Ignore => UninitPolicy::Ignore,
+            Uninit::Warn => UninitPolicy::Warn,
+            Uninit::Break => UninitPolicy::Break,
+        })
+        .stack_check(args.stack_check);
+    for (name, filename) in &args.machine_rom {
+        builder = builder.machine_rom(name, filename);
+    }
+    if args.dma {
+        builder = builder.dma();
+    }
+    // an LCD on the VIA's ports comes with the VIA
+    let lcd_via = args.lcd.is_some() && args.lcd_bus == LcdBus::Via;
+    if args.via && !lcd_via {
+        builder = builder.via();
+    }
+    if args.pia {
+        builder = builder.pia();
+    }
+    if args.rng {
+        let seed = args.rng_seed.unwrap_or_else(Rng::seed_from_time);
+        builder = builder.rng(seed);
+        if verbosity >= Verbosity::Verbose {
+            log::info!("Random number generator seed: {seed}");
+        }
+    }
+    if let Some(clock) = args.rtc {
+        builder = builder.rtc(match clock {
+            Clock::Host => RtcClock::Host,
+            Clock::Cycles => RtcClock::Cycles { hz: RTC_CLOCK_HZ_DEFAULT },
+        });
+    }
+    if let Some(filename) = &args.storage {
+        builder = builder.storage(filename)?;
+    }
+    if let Some(filename) = &args.tape {
+        builder = builder.tape(filename, args.tape_rate)?;
+    }
+    #[cfg(feature = "audio")]
+    if args.beeper {
+        builder = builder.beeper()?;
+    }
+    if let Some((columns, rows)) = args.lcd {
+        builder = builder.lcd(columns, rows, match args.lcd_bus {
+            LcdBus::Mapped => LcdConnection::Mapped,
+            LcdBus::Via => LcdConnection::Via,
+        });
+    }
+    if let Some(digits) = args.segments {
+        builder = builder.segments(usize::from(digits));
+    }
+    if args.gpio {
+        let script = args.gpio_script.as_deref().map(GpioScript::load_from_file).transpose()?.unwrap_or_default();
+        builder = builder.gpio(script);
+    }
+    if let Some(line) = args.acia {
+        builder = builder.acia(line)?;
+    }
+    if let Some(line) = args.char_io {
+        builder = builder.char_io(line)?;
+    }
+    if let Some(line) = args.keyboard {
+        builder = builder.keyboard(line)?;
+    }
+    if let Some(filename) = &args.keymap {
+        builder = builder.keymap(Keymap::load(filename)?);
+    }
+    if args.kernal_traps {
+        builder = builder.kernal_traps(args.kernal_dir.as_deref().unwrap_or("."));
+    }
+
+    if let Some(filename) = &args.file {
+        builder = builder.load_file(ADDR_RESET_VECTOR, filename).map_err(|error| format!("Error reading file into memory: {error}"))?;
+    }
+
+    if let Some(filename) = &args.asm {
+        let source = fs::read_to_string(filename).map_err(|error| format!("{filename}: {error}"))?;
+        let program = asm::assemble(&source).map_err(|error| format!("{filename}: {error}"))?;
+        builder = builder.program(&program);
+
+        if let Some(filename) = &args.asm_listing {
+            program.write_listing(&mut BufWriter::new(File::create(filename)?))?;
+        }
+        if let (Some(filename), Some((addr, image))) = (&args.asm_bin, program.image()) {
+            fs::write(filename, image)?;
+            if verbosity >= Verbosity::Verbose {
+                log::info!("Wrote assembled binary for ${addr:04X} to {filename}");
+            }
+        }
+    }
+
+    if let Some(filename) = &args.woz {
+        let image = WozImage::load_from_file(filename)?;
+        if verbosity >= Verbosity::Verbose {
+            log::info!("Loaded {} bytes from {filename}", image.len());
+        }
+        builder = builder.woz(image);
+    }
+
+    if let Some(filename) = &args.rom {
+        builder = builder.map_rom(filename)?;
+    }
+
+    if let Some(filename) = &args.crt {
+        let cartridge = Cartridge::load_from_file(filename)?;
+        builder = builder.cartridge(&cartridge).map_err(|error| format!("{filename}: {error}"))?;
+        if verbosity >= Verbosity::Verbose {
+            log::info!("Mapped {} cartridge '{}' (hardware type {}) with {} chips", cartridge.machine, cartridge.name, cartridge.hardware_type, cartridge.chips.len());
+        }
+    }
+
+    if let Some(filename) = &args.resume {
+        builder = builder.resume(filename);
+    }
+
+    if let Some(filename) = &args.labels {
+        let mut symbols = SymbolTable::default();
+        let count = symbols.load_vice(filename)?;
+        builder = builder.symbols(&symbols);
+        if verbosity >= Verbosity::Verbose {
+            log::info!("Loaded {count} labels from {filename}");
+        }
+    }
+
+    if args.demo {
+        builder = builder.demo();
+    }
+
+    if let Some((base, columns, rows)) = args.screen {
+        builder = builder.screen(base, columns, rows, match args.charset {
+            Chars::Ascii => Charset::Ascii,
+            Chars::Petscii => Charset::Petscii,
+            Chars::Apple2 => Charset::AppleII,
+        })?;
+    }
+
+    // the disassembly executes nothing, so there is nothing to instrument or trace
+    if args.disassemble.is_none() {
+        builder = instrument(builder, args)?;
+    }
+
+    let emulator = builder.build()?;
+    if let Some(filename) = &args.resume {
+        if verbosity >= Verbosity::Verbose {
+            log::info!("Resumed from {filename} at cycle {}", emulator.cpu().cycles);
+        }
+    }
+    Ok(emulator)
+}
+
+fn instrument(mut builder: EmulatorBuilder, args: &Cli) -> Result<EmulatorBuilder, String> {
+    if args.heatmap || args.heatmap_csv.is_some() {
+        builder = builder.heatmap();
+    }
+    if args.profile {
+        builder = builder.profile();
+    }
+    if args.stats {
+        builder = builder.stats();
+    }
+    if args.coverage.is_some() {
+        builder = builder.coverage();
+    }
+    builder = match &args.dump {
+        Some(filename) => builder.dump_file(filename)?,
+        None => builder.dump_output(Box::new(io::stdout()), true),
+    };
+    if let Some(filename) = &args.trace {
+        let format = match args.trace_format {
+            Trace::Plain => TraceFormat::Plain,
+            Trace::Vice => TraceFormat::Vice,
+            Trace::Nestest => TraceFormat::Nestest,
+            Trace::Json => TraceFormat::Json,
+            Trace::Calls => TraceFormat::Calls,
+        };
+        builder = builder.trace_file(filename, format, args.trace_range.clone())?;
+    }
+    if let Some(filename) = &args.bus_trace {
+        builder = builder.bus_trace_file(filename)?;
+    }
+    if let Some(filename) = &args.compare_trace {
+        builder = builder.compare_trace(filename)?;
+    }
+    if let Some(filename) = &args.replay_input {
+        builder = builder.replay_input(filename)?;
+    }
+    if args.record_input.is_some() {
+        builder = builder.record_input();
+    }
+    Ok(builder)
+}
+
 fn main() {
     let args = Cli::parse();
+    let logger = ConsoleLogger::create(args.verbose);
+    let level = logger.level;
+    if log::set_logger(Box::leak(Box::new(logger))).is_ok() {
+        log::set_max_level(level);
+    }
 
     let verbosity = match args.verbose {
         0 => Verbosity::Normal,
@@ -36,15 +659,67 @@ fn main() {
         _ => Verbosity::Normal,
     };
 
+    // disassembly output is kept free of anything else
+    if args.disassemble.is_none() {
+        println!("rust-6502-emu");
+    }
+    if verbosity > Verbosity::Normal {
+        log::info!("Being verbose... {:?} [{}]", verbosity, verbosity as u8);
+    }
+
+    let emulator = match build_emulator(&args, verbosity) {
+        Ok(emulator) => emulator,
+        Err(err) => {
+            println!("Application error: {err}");
+            process::exit(1);
+        },
+    };
+
     let config = Config {
+        verbosity,
         cycles_to_execute: args.cycles,
-        load_demo: args.demo,
         load_file: args.file,
         interactive: args.interactive,
-        verbosity,
+        monitor_script: args.monitor_script,
+        checkpoint_interval: args.checkpoints,
+        screen_refresh: args.screen_refresh,
+        screen_redraw: if args.full_redraw { Redraw::Full } else { Redraw::Diff },
+        #[cfg(feature = "window")]
+        framebuffer: args.framebuffer,
+        #[cfg(feature = "window")]
+        framebuffer_bpp: args.bpp,
+        #[cfg(feature = "window")]
+        palette_file: args.palette,
+        #[cfg(feature = "window")]
+        framebuffer_refresh: args.framebuffer_refresh,
+        #[cfg(feature = "window")]
+        window_scale: args.scale,
+        heatmap: args.heatmap,
+        heatmap_csv: args.heatmap_csv,
+        profile: args.profile,
+        stats: args.stats,
+        coverage_file: args.coverage,
+        record_input_file: args.record_input,
+        disassemble: args.disassemble,
+        disassemble_listing: args.listing,
+        disassemble_code_file: args.code_from,
+        disassemble_format: Format {
+            bytes: !args.no_bytes,
+            lowercase: args.lowercase,
+            illegal: match args.illegal {
+                Illegal::Data => IllegalOpcodes::Data,
+                Illegal::Plain => IllegalOpcodes::Plain,
+                Illegal::Marked => IllegalOpcodes::Marked,
+            },
+            radix: match args.radix {
+                Base::Hex => Radix::Hex,
+                Base::Dec => Radix::Decimal,
+            },
+        },
+        output_file: args.output,
     };
 
-    if let Err(err) = rust_6502_emu::run(config) {
+    if let Err(err) = rust_6502_emu::run(emulator, config) {
         println!("Application error: {err}");
         process::exit(1);
     }
@@ -1,18 +1,86 @@
 
+use core::any::Any;
+use core::cell::{Cell, Ref, RefCell};
+use core::ops::RangeInclusive;
+#[cfg(feature = "std")]
 use std::fs::File;
-use std::io::{BufReader, Read, Error};
+#[cfg(feature = "std")]
+use std::io::{self, BufReader, Read, Write, Error};
+
+use sha2::{Digest, Sha256};
 
 use crate::cpu;
+use crate::device::Device;
+use crate::events::{Event, EventBus};
+use crate::heatmap::Heatmap;
 use crate::instruction::Opcode;
+use crate::rom::BankedRom;
+#[cfg(feature = "std")]
+use crate::rom::RomImage;
+#[cfg(feature = "std")]
+use crate::state;
+use crate::symbols::SymbolTable;
+use crate::watch::Watchpoints;
+use crate::prelude::*;
 
 const MEMORY_SIZE: usize = 0x10000;
 
 pub const ADDR_RESET_VECTOR: u16 = 0xE000;
 
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,        // opcode fetch
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum UninitPolicy {
+    Ignore,         // no tracking of uninitialized reads
+    Warn,           // report uninitialized reads and continue
+    Break,          // report uninitialized reads and stop execution
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Region {
+    pub name: String,
+    pub range: RangeInclusive<u16>,
+}
+
+// state overwritten by a write, restored in reverse order to undo writes
+#[derive(Clone, Debug)]
+enum Undo {
+    Ram { addr: u16, value: u8, initialized: bool },
+    #[cfg(feature = "std")]
+    Device { name: String, state: Vec<u8> },
+    RomBank(usize),
+}
+
+// writes recorded while journaling, see `Memory::start_journal`
+#[derive(Clone, Debug, Default)]
+pub struct Journal {
+    entries: Vec<Undo>,
+}
 
 pub struct Memory {
     data: [u8; MEMORY_SIZE],
     current_write_addr: Option<u16>,
+    rom: Option<BankedRom>,
+    devices: Vec<RefCell<Box<dyn Device>>>,     // reads may change device state, like other accesses through &self
+
+    // bytes written (or loaded) since reset; one bit per address
+    initialized: [u64; MEMORY_SIZE / 64],
+    uninit_policy: UninitPolicy,
+    uninit_reads: RefCell<Vec<u16>>,
+
+    regions: Vec<Region>,
+    symbols: SymbolTable,
+
+    heatmap: Option<RefCell<Heatmap>>,
+    watchpoints: Watchpoints,
+    events: EventBus,
+    untracked: Cell<bool>,          // suspends access tracking, e.g. while formatting debug output
+    journal: Option<Journal>,
 }
 
 impl Memory {
@@ -20,18 +88,426 @@ impl Memory {
         Self {
             data: [0; MEMORY_SIZE],
             current_write_addr: None,       // comfort feature for consecutive writes
+            rom: None,
+            devices: Vec::new(),
+            initialized: [0; MEMORY_SIZE / 64],
+            uninit_policy: UninitPolicy::Ignore,
+            uninit_reads: RefCell::new(Vec::new()),
+            regions: vec![
+                Region { name: String::from("ZP"), range: cpu::ZERO_PAGE_BASE..=cpu::ZERO_PAGE_BASE + 0xFF },
+                Region { name: String::from("STACK"), range: cpu::STACK_BASE..=cpu::STACK_BASE + 0xFF },
+                Region { name: String::from("VECTORS"), range: cpu::VECTOR_NMI..=0xFFFF },
+            ],
+            symbols: SymbolTable::default(),
+            heatmap: None,
+            watchpoints: Watchpoints::default(),
+            events: EventBus::default(),
+            untracked: Cell::new(false),
+            journal: None,
         }
     }
 
     pub fn reset(&mut self) {
         // initialize with zero
         self.data = [0; MEMORY_SIZE];
+        self.initialized = [0; MEMORY_SIZE / 64];
+        self.uninit_reads.borrow_mut().clear();
 
         self.write_u16(cpu::VECTOR_RES, ADDR_RESET_VECTOR);
 
         self.current_write_addr = None;
+
+        self.reset_devices();
+    }
+
+    // attached devices return to their power-on state; memory contents are kept
+    pub fn reset_devices(&mut self) {
+        if let Some(rom) = &mut self.rom {
+            rom.select_bank(0);
+        }
+        for device in &mut self.devices {
+            device.get_mut().reset();
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub fn map_rom(&mut self, filename: &str, base: u16, bank_size: u16, bank_select: u16) -> Result<(), Error> {
+        let image = RomImage::open(filename)?;
+        self.map_banked_rom(BankedRom::create(image, base, bank_size, bank_select));
+        Ok(())
+    }
+
+    pub fn map_banked_rom(&mut self, rom: BankedRom) {
+        self.add_region("ROM", rom.base..=rom.base.saturating_add(rom.bank_size - 1));
+        self.add_region("BANKSEL", rom.bank_select..=rom.bank_select);
+        self.rom = Some(rom);
+    }
+
+    // maps the device's registers; an attached device with the same name is replaced
+    pub fn attach_device(&mut self, device: Box<dyn Device>) {
+        let name = String::from(device.name());
+        self.detach_device(&name);
+        self.add_region(&name, device.range());
+        self.devices.push(RefCell::new(device));
+    }
+
+    pub fn detach_device(&mut self, name: &str) -> Option<Box<dyn Device>> {
+        let index = self.devices.iter_mut().position(|device| device.get_mut().name() == name)?;
+        self.remove_region(name);
+        Some(self.devices.remove(index).into_inner())
+    }
+
+    // in the order attached
+    pub fn devices(&self) -> impl Iterator<Item = Ref<'_, Box<dyn Device>>> {
+        self.devices.iter().map(|device| device.borrow())
+    }
+
+    // the attached device with the name if it is a `T`
+    pub fn device<T: Device>(&self, name: &str) -> Option<Ref<'_, T>> {
+        let device = self.devices.iter().map(|device| device.borrow()).find(|device| device.name() == name)?;
+        Ref::filter_map(device, |device| (device.as_ref() as &dyn Any).downcast_ref::<T>()).ok()
+    }
+
+    pub fn device_mut<T: Device>(&mut self, name: &str) -> Option<&mut T> {
+        let device = self.devices.iter_mut().map(|device| device.get_mut()).find(|device| device.name() == name)?;
+        (device.as_mut() as &mut dyn Any).downcast_mut::<T>()
+    }
+
+    fn device_at(&self, addr: u16) -> Option<&RefCell<Box<dyn Device>>> {
+        self.devices.iter().find(|device| device.borrow().range().contains(&addr))
+    }
+
+    // advances the attached devices by the cycles the CPU spent
+    pub fn tick_devices(&mut self, cycles: u64) {
+        for device in &mut self.devices {
+            device.get_mut().tick(cycles);
+        }
+    }
+
+    // any device asserting its IRQ output
+    pub fn device_irq(&self) -> bool {
+        self.devices.iter().any(|device| device.borrow().irq())
+    }
+
+    pub fn device_nmi(&self) -> bool {
+        self.devices.iter().any(|device| device.borrow().nmi())
+    }
+
+    // cycles until every device releases RDY
+    pub fn device_halt(&mut self) -> u64 {
+        self.devices.iter_mut().map(|device| device.get_mut().halt()).max().unwrap_or(0)
+    }
+
+    // names a region; an existing region with the same name is replaced
+    pub fn add_region(&mut self, name: &str, range: RangeInclusive<u16>) {
+        self.remove_region(name);
+        self.regions.push(Region { name: String::from(name), range });
+    }
+
+    pub fn remove_region(&mut self, name: &str) -> bool {
+        let count = self.regions.len();
+        self.regions.retain(|region| region.name != name);
+        self.regions.len() != count
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    // the most specific (smallest) region containing the address
+    pub fn region_at(&self, addr: u16) -> Option<&Region> {
+        self.regions.iter()
+            .filter(|region| region.range.contains(&addr))
+            .min_by_key(|region| *region.range.end() - *region.range.start())
+    }
+
+    // RAM contents and initialization; the ROM bank and attached devices are separate sections of the
+    // state, see `state`
+    #[cfg(feature = "std")]
+    pub fn save_state(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(&self.data)?;
+        for bits in self.initialized {
+            state::write_u64(writer, bits)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "std")]
+    pub fn load_state(&mut self, reader: &mut impl Read) -> io::Result<()> {
+        reader.read_exact(&mut self.data)?;
+        for bits in self.initialized.iter_mut() {
+            *bits = state::read_u64(reader)?;
+        }
+        self.uninit_reads.borrow_mut().clear();
+        self.current_write_addr = None;
+        Ok(())
+    }
+
+    // restores the state of the attached device with the name; false if there is none
+    #[cfg(feature = "std")]
+    pub(crate) fn restore_device(&mut self, name: &str, reader: &mut impl Read) -> io::Result<bool> {
+        match self.devices.iter_mut().map(|device| device.get_mut()).find(|device| device.name() == name) {
+            Some(device) => device.load_state(reader).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn restore_rom_bank(&mut self, bank: usize) -> io::Result<()> {
+        match &mut self.rom {
+            Some(rom) => {
+                rom.select_bank(bank);
+                Ok(())
+            },
+            None => Err(state::invalid_data("State requires a mapped ROM image")),
+        }
+    }
+
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    pub fn symbols_mut(&mut self) -> &mut SymbolTable {
+        &mut self.symbols
+    }
+
+    pub fn rom(&self) -> Option<&BankedRom> {
+        self.rom.as_ref()
+    }
+
+    // performs the DMA transfers devices requested and returns the number of cycles the CPU has to be stalled
+    pub fn service_dma(&mut self) -> u64 {
+        let mut cycles = 0;
+        for index in 0..self.devices.len() {
+            let Some(transfer) = self.devices[index].get_mut().pending_transfer() else {
+                continue;
+            };
+            if self.journal.is_some() {
+                self.journal_device(index);
+            }
+            self.devices[index].get_mut().transfer_done();
+
+            for i in 0..transfer.len {
+                let value = self.peek(transfer.src.wrapping_add(i));
+                self.store(transfer.dst.wrapping_add(i), value);
+            }
+            cycles += transfer.cycles();
+        }
+        cycles
+    }
+
+    pub fn uninit_policy(&self) -> UninitPolicy {
+        self.uninit_policy
+    }
+
+    pub fn set_uninit_policy(&mut self, policy: UninitPolicy) {
+        self.uninit_policy = policy;
+        self.uninit_reads.borrow_mut().clear();
+    }
+
+    pub fn is_initialized(&self, addr: u16) -> bool {
+        self.initialized[addr as usize / 64] & (1 << (addr % 64)) != 0
+    }
+
+    // addresses of uninitialized bytes read since the last call
+    pub fn take_uninit_reads(&self) -> Vec<u16> {
+        self.uninit_reads.take()
+    }
+
+    pub fn enable_heatmap(&mut self) {
+        if self.heatmap.is_none() {
+            self.heatmap = Some(RefCell::new(Heatmap::create()));
+        }
+    }
+
+    pub fn disable_heatmap(&mut self) {
+        self.heatmap = None;
+    }
+
+    pub fn heatmap(&self) -> Option<Ref<'_, Heatmap>> {
+        self.heatmap.as_ref().map(|heatmap| heatmap.borrow())
+    }
+
+    pub fn clear_heatmap(&mut self) {
+        if let Some(heatmap) = &self.heatmap {
+            heatmap.borrow_mut().clear();
+        }
+    }
+
+    // runs `f` without recording any accesses (heatmap, uninitialized reads)
+    pub fn untracked<T>(&self, f: impl FnOnce() -> T) -> T {
+        let untracked = self.untracked.replace(true);
+        let result = f();
+        self.untracked.set(untracked);
+        result
+    }
+
+    pub fn watchpoints(&self) -> &Watchpoints {
+        &self.watchpoints
+    }
+
+    pub fn watchpoints_mut(&mut self) -> &mut Watchpoints {
+        &mut self.watchpoints
+    }
+
+    // listeners following execution, see `Event`
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    pub fn events_mut(&mut self) -> &mut EventBus {
+        &mut self.events
+    }
+
+    // records the previous contents of everything written until `take_journal`, so the writes can be undone
+    pub fn start_journal(&mut self) {
+        self.journal = Some(Journal::default());
+    }
+
+    pub fn take_journal(&mut self) -> Journal {
+        self.journal.take().unwrap_or_default()
     }
 
+    pub fn undo(&mut self, journal: Journal) {
+        for entry in journal.entries.into_iter().rev() {
+            match entry {
+                Undo::Ram { addr, value, initialized } => {
+                    self.data[addr as usize] = value;
+                    match initialized {
+                        true => self.initialized[addr as usize / 64] |= 1 << (addr % 64),
+                        false => self.initialized[addr as usize / 64] &= !(1 << (addr % 64)),
+                    }
+                },
+                #[cfg(feature = "std")]
+                Undo::Device { name, state } => if let Err(err) = self.restore_device(&name, &mut state.as_slice()) {
+                    log::warn!("State of device {name} not restored: {err}");
+                },
+                Undo::RomBank(bank) => if let Some(rom) = &mut self.rom {
+                    rom.select_bank(bank);
+                },
+            }
+        }
+    }
+
+    fn journal_write(&mut self, addr: u16) {
+        let entry = if let Some(rom) = self.rom.as_ref().filter(|rom| addr == rom.bank_select) {
+            Undo::RomBank(rom.bank())
+        } else if let Some(index) = self.devices.iter().position(|device| device.borrow().range().contains(&addr)) {
+            self.journal_device(index);
+            return;
+        } else {
+            Undo::Ram { addr, value: self.data[addr as usize], initialized: self.is_initialized(addr) }
+        };
+        if let Some(journal) = &mut self.journal {
+            journal.entries.push(entry);
+        }
+    }
+
+    // records the state of the device as saved in machine states (see `Device::save_state`)
+    #[cfg(feature = "std")]
+    fn journal_device(&mut self, index: usize) {
+        let device = self.devices[index].get_mut();
+        let mut state = Vec::new();
+        if let Err(err) = device.save_state(&mut state) {
+            log::warn!("State of device {} not journaled: {err}", device.name());
+            return;
+        }
+        let entry = Undo::Device { name: String::from(device.name()), state };
+        if let Some(journal) = &mut self.journal {
+            journal.entries.push(entry);
+        }
+    }
+
+    // device state is not journaled without std
+    #[cfg(not(feature = "std"))]
+    fn journal_device(&mut self, _index: usize) {}
+
+    fn track(&self, addr: u16, access: Access, old: u8, new: u8) {
+        if self.untracked.get() {
+            return;
+        }
+        if let Some(heatmap) = &self.heatmap {
+            heatmap.borrow_mut().record(addr, access);
+        }
+        if !self.watchpoints.is_empty() {
+            self.watchpoints.check(addr, access, old, new);
+        }
+        if access == Access::Write && !self.events.is_empty() {
+            self.events.emit(&Event::MemoryWrite { addr, old, new });
+        }
+    }
+
+    // I/O registers and ROM take precedence over RAM; devices only see reads with side effects if `accessed`
+    // (untracked reads, e.g. by the monitor, are peeks)
+    fn read_mapped(&self, addr: u16, accessed: bool) -> Option<u8> {
+        if let Some(rom) = self.rom.as_ref().filter(|rom| rom.maps(addr)) {
+            return Some(rom.read(addr));
+        }
+        if let Some(device) = self.device_at(addr) {
+            return Some(match accessed {
+                true => device.borrow_mut().read(addr),
+                false => device.borrow().peek(addr),
+            });
+        }
+        None
+    }
+
+    fn write_mapped(&mut self, addr: u16, value: u8) -> bool {
+        if let Some(rom) = self.rom.as_mut() {
+            if addr == rom.bank_select {
+                rom.select_bank(value as usize);
+                return true;
+            }
+            if rom.maps(addr) {
+                return true;        // writes to ROM are ignored
+            }
+        }
+        if let Some(device) = self.devices.iter_mut().map(|device| device.get_mut()).find(|device| device.range().contains(&addr)) {
+            device.write(addr, value);
+            return true;
+        }
+        false
+    }
+
+    // reads without any side effects such as uninitialized read tracking
+    pub fn peek(&self, addr: u16) -> u8 {
+        self.read_mapped(addr, false).unwrap_or(self.data[addr as usize])
+    }
+
+    fn load(&self, addr: u16, access: Access) -> u8 {
+        let value = match self.read_mapped(addr, !self.untracked.get()) {
+            Some(value) => value,
+            None => {
+                if self.uninit_policy != UninitPolicy::Ignore && !self.untracked.get() && !self.is_initialized(addr) {
+                    self.uninit_reads.borrow_mut().push(addr);
+                }
+                self.data[addr as usize]
+            },
+        };
+        self.track(addr, access, value, value);
+        value
+    }
+
+    fn store(&mut self, addr: u16, value: u8) {
+        self.track(addr, Access::Write, self.peek(addr), value);
+        if self.journal.is_some() {
+            self.journal_write(addr);
+        }
+        if self.write_mapped(addr, value) {
+            return;
+        }
+        self.data[addr as usize] = value;
+        self.initialized[addr as usize / 64] |= 1 << (addr % 64);
+    }
+
+    // writes like the CPU would, but without heatmap and watchpoint tracking (e.g. edits from the monitor)
+    pub fn poke(&mut self, addr: u16, value: u8) {
+        let untracked = self.untracked.replace(true);
+        self.store(addr, value);
+        self.untracked.set(untracked);
+    }
+
+    #[cfg(feature = "std")]
     pub fn load_from_file(&mut self, addr: u16, filename: &str) -> Result<(), Error>{
         let file = File::open(filename)?;
         let mut reader = BufReader::new(file);
@@ -44,8 +520,8 @@ impl Memory {
                 Ok(0) => break, // EOF
                 Ok(bytes_read) => {
                     for item in buffer.iter().take(bytes_read) {
-                        self.write_u8(addr + pos, *item);
-                        pos += 1;
+                        self.write_u8(addr.wrapping_add(pos), *item);
+                        pos = pos.wrapping_add(1);
                     }
                 }
                 Err(e) => return Err(e),
@@ -78,16 +554,21 @@ impl Memory {
 
     }
 
+    // opcode fetch
+    pub fn fetch(&self, addr: u16) -> u8 {
+        self.load(addr, Access::Execute)
+    }
+
     pub fn read_u8(&self, addr: u16) -> u8 {
-        self.data[addr as usize]
+        self.load(addr, Access::Read)
     }
 
     pub fn read_i8(&self, addr: u16) -> i8 {
-        self.data[addr as usize] as i8
+        self.load(addr, Access::Read) as i8
     }
 
     pub fn read_u16(&self, addr: u16) -> u16 {
-        (self.data[addr as usize] as u16) /* LB */ | ((self.data[(addr + 1) as usize] as u16) << 8) /* HB */
+        (self.load(addr, Access::Read) as u16) /* LB */ | ((self.load(addr.wrapping_add(1), Access::Read) as u16) << 8) /* HB */
     }
 
     pub fn write_u8<T: Into<Option<u16>>>(&mut self, addr: T, value: u8) {
@@ -101,7 +582,7 @@ impl Memory {
                 }
             }
         }
-        self.data[write_addr as usize] = value;
+        self.store(write_addr, value);
         self.current_write_addr = Some(write_addr.wrapping_add(1));
     }
 
@@ -120,17 +601,37 @@ impl Memory {
                 }
             }
         }
-        self.data[write_addr as usize] = (value & 0x00FF) as u8;                // LB
-        self.data[write_addr.wrapping_add(1) as usize] = ((value & 0xFF00) >> 8) as u8;   // HB
+        self.store(write_addr, (value & 0x00FF) as u8);                         // LB
+        self.store(write_addr.wrapping_add(1), ((value & 0xFF00) >> 8) as u8);  // HB
         self.current_write_addr = Some(write_addr.wrapping_add(2));
     }
 
-    pub fn dump(&self, addr: u16, bytes: u16) {
-        print!("mem @ 0x{:04X}:", addr);
+    pub fn crc32(&self, range: RangeInclusive<u16>) -> u32 {
+        let mut hasher = crc32fast::Hasher::new();
+        for addr in range {
+            hasher.update(&[self.peek(addr)]);
+        }
+        hasher.finalize()
+    }
+
+    pub fn sha256(&self, range: RangeInclusive<u16>) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        for addr in range {
+            hasher.update([self.peek(addr)]);
+        }
+        hasher.finalize().into()
+    }
+
+    pub fn dump(&self, addr: u16, bytes: u16) -> String {
+        let mut dump = format!("mem @ 0x{:04X}", addr);
+        if let Some(region) = self.region_at(addr) {
+            dump += &format!(" [{}]", region.name);
+        }
+        dump += ":";
         for i in 0..bytes {
-            print!(" {:02X}", self.read_u8(addr + i));
+            dump += &format!(" {:02X}", self.peek(addr.wrapping_add(i)));
         }
-        println!()
+        dump
     }
 }
 
@@ -238,4 +739,193 @@ mod tests {
         mem.write_u16(None, value2);
         assert_eq!(mem.read_u16(addr + 2), value2);
     }
+
+    #[test]
+    fn uninit_reads() {
+        let mut mem = setup();
+
+        // not tracked by default
+        mem.read_u8(0x0200);
+        assert!(mem.take_uninit_reads().is_empty());
+
+        mem.set_uninit_policy(UninitPolicy::Warn);
+        assert!(mem.is_initialized(cpu::VECTOR_RES));
+        assert!(!mem.is_initialized(0x0200));
+
+        mem.read_u8(0x0200);
+        mem.peek(0x0201);                       // peek is never tracked
+        assert_eq!(mem.take_uninit_reads(), vec![0x0200]);
+        assert!(mem.take_uninit_reads().is_empty());
+
+        mem.write_u16(0x0200, 0xBEEF);
+        mem.read_u16(0x0200);
+        assert!(mem.take_uninit_reads().is_empty());
+
+        // reset forgets about initialized bytes
+        mem.reset();
+        mem.read_u8(0x0200);
+        assert_eq!(mem.take_uninit_reads(), vec![0x0200]);
+    }
+
+    #[test]
+    fn regions() {
+        let mut mem = setup();
+
+        assert_eq!(mem.region_at(0x0010).unwrap().name, "ZP");
+        assert_eq!(mem.region_at(0x01FF).unwrap().name, "STACK");
+        assert_eq!(mem.region_at(0xFFFC).unwrap().name, "VECTORS");
+        assert_eq!(mem.region_at(0x0200), None);
+
+        // most specific region wins
+        mem.add_region("SCREEN", 0x0400..=0x07E7);
+        mem.add_region("PTR", 0x00FB..=0x00FC);
+        assert_eq!(mem.region_at(0x0500).unwrap().name, "SCREEN");
+        assert_eq!(mem.region_at(0x00FB).unwrap().name, "PTR");
+        assert_eq!(mem.region_at(0x00FA).unwrap().name, "ZP");
+
+        // replacing and removing
+        mem.add_region("SCREEN", 0x0800..=0x0BE7);
+        assert_eq!(mem.region_at(0x0500), None);
+        assert!(mem.remove_region("SCREEN"));
+        assert!(!mem.remove_region("SCREEN"));
+        assert_eq!(mem.region_at(0x0800), None);
+    }
+
+    #[test]
+    fn poke() {
+        let mut mem = setup();
+        mem.enable_heatmap();
+        mem.watchpoints_mut().add(0x0200..=0x0200, true, true);
+
+        mem.poke(0x0200, 0x42);
+        assert_eq!(mem.peek(0x0200), 0x42);
+        assert!(mem.is_initialized(0x0200));
+        assert!(mem.watchpoints().take_hits().is_empty());
+        assert_eq!(mem.heatmap().unwrap().entry(0x0200).total(), 0);
+    }
+
+    #[test]
+    fn heatmap() {
+        let mut mem = setup();
+        assert!(mem.heatmap().is_none());
+
+        mem.enable_heatmap();
+        mem.write_u8(0x0200, 0xAA);
+        mem.read_u8(0x0200);
+        mem.read_u16(0x0200);
+        mem.fetch(0xE000);
+        mem.peek(0x0200);                       // not tracked
+        mem.untracked(|| mem.read_u8(0x0200));  // not tracked
+
+        let entry = mem.heatmap().unwrap().entry(0x0200);
+        assert_eq!((entry.reads, entry.writes, entry.executes), (2, 1, 0));
+        assert_eq!(mem.heatmap().unwrap().entry(0x0201).reads, 1);
+        assert_eq!(mem.heatmap().unwrap().entry(0xE000).executes, 1);
+
+        mem.clear_heatmap();
+        assert_eq!(mem.heatmap().unwrap().entry(0x0200).total(), 0);
+    }
+
+    #[test]
+    fn checksums() {
+        let mut mem = setup();
+        for (i, value) in b"123456789".iter().enumerate() {
+            mem.write_u8(0x0200 + i as u16, *value);
+        }
+
+        // well-known check values for "123456789"
+        assert_eq!(mem.crc32(0x0200..=0x0208), 0xCBF43926);
+        assert_eq!(mem.sha256(0x0200..=0x0208)[..4], [0x15, 0xE2, 0xB0, 0xD3]);
+
+        // full address space does not overflow
+        assert_eq!(mem.crc32(0x0000..=0xFFFF), mem.crc32(0x0000..=0xFFFF));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn load_from_file_wraps() {
+        let mut mem = setup();
+
+        let filename = std::env::temp_dir().join(format!("rust-6502-emu-load-{}.bin", std::process::id()));
+        std::fs::write(&filename, [0xEA, 0xEA, 0x00]).unwrap();
+        mem.load_from_file(0xFFFE, filename.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&filename).unwrap();
+
+        assert_eq!((mem.read_u8(0xFFFE), mem.read_u8(0xFFFF), mem.read_u8(0x0000)), (0xEA, 0xEA, 0x00));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn banked_rom() {
+        let mut mem = setup();
+
+        // 3 banks of 16 bytes; the last one only partially filled
+        let filename = std::env::temp_dir().join(format!("rust-6502-emu-rom-{}.bin", std::process::id()));
+        let image: Vec<u8> = (0..40).collect();
+        std::fs::write(&filename, &image).unwrap();
+        mem.map_rom(filename.to_str().unwrap(), 0x8000, 0x10, 0xDFFE).unwrap();
+        std::fs::remove_file(&filename).unwrap();
+
+        assert_eq!(mem.rom().unwrap().banks(), 3);
+        assert_eq!(mem.read_u8(0x8000), 0);
+        assert_eq!(mem.read_u8(0x800F), 15);
+        assert_eq!(mem.read_u8(0x8010), 0);     // outside window is RAM
+
+        mem.write_u8(0x8000, 0xAA);              // ROM is not writable
+        assert_eq!(mem.read_u8(0x8000), 0);
+
+        mem.write_u8(0xDFFE, 2);
+        assert_eq!(mem.rom().unwrap().bank(), 2);
+        assert_eq!(mem.read_u8(0x8000), 32);
+        assert_eq!(mem.read_u8(0x8008), 0xFF);  // beyond end of image
+
+        mem.reset();
+        assert_eq!(mem.read_u8(0x8001), 1);
+    }
+
+    #[test]
+    fn dma_transfer() {
+        use crate::dma::{Dma, CTRL_START, DMA_BASE_DEFAULT, DMA_CYCLES_PER_BYTE, REG_CTRL, REG_DST_LB, STATUS_DONE};
+
+        let mut mem = setup();
+        let base = DMA_BASE_DEFAULT;
+        mem.attach_device(Box::new(Dma::create("dma", base).unwrap()));
+
+        for i in 0..0x10 {
+            mem.write_u8(0x0200 + i, i as u8 + 1);
+        }
+
+        // nothing pending
+        assert_eq!(mem.service_dma(), 0);
+
+        mem.write_u16(base, 0x0200);            // source
+        mem.write_u16(None, 0x0300);            // destination
+        mem.write_u16(None, 0x0010);            // length
+        mem.write_u8(None, CTRL_START);
+        assert_eq!(mem.read_u8(0x0300), 0);     // not yet transferred
+
+        assert_eq!(mem.service_dma(), 0x10 * DMA_CYCLES_PER_BYTE);
+        for i in 0..0x10 {
+            assert_eq!(mem.read_u8(0x0300 + i), i as u8 + 1);
+        }
+        assert_eq!(mem.read_u8(base + REG_CTRL), STATUS_DONE);
+        assert_eq!(mem.read_u16(base), 0x0200);
+
+        // transfer is performed only once
+        assert_eq!(mem.service_dma(), 0);
+
+        // undoing a transfer restores the destination and the controller
+        mem.write_u16(base + REG_DST_LB, 0x0400);
+        mem.start_journal();
+        mem.write_u8(base + REG_CTRL, CTRL_START);
+        assert_eq!(mem.service_dma(), 0x10 * DMA_CYCLES_PER_BYTE);
+        let journal = mem.take_journal();
+        mem.undo(journal);
+        assert_eq!(mem.read_u8(0x0400), 0);
+        assert!(!mem.is_initialized(0x0400));
+        assert_eq!(mem.read_u8(base + REG_CTRL), STATUS_DONE);
+        assert_eq!(mem.service_dma(), 0);
+
+        assert!(Dma::create("dma", 0xFFFA).is_err());
+    }
 }
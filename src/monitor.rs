@@ -0,0 +1,2224 @@
+use std::cmp;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use colored::Colorize;
+use num_traits::FromPrimitive;
+
+use crate::asm;
+use crate::bus;
+use crate::cpu::{Cpu, Interrupt, StackFrame, StatusFlags};
+use crate::debugger::{Debugger, InstructionBreak};
+use crate::disasm::Disassembler;
+use crate::expr::{self, Env, Radix};
+use crate::gpio::Gpio;
+use crate::instruction::{Mnemonic, Opcode};
+use crate::lcd::Lcd;
+use crate::mem::{Memory, UninitPolicy, ADDR_RESET_VECTOR};
+use crate::rewind::{Checkpoints, Rewind};
+use crate::screen::{Charset, TextScreen};
+use crate::segments::SevenSegment;
+use crate::state;
+use crate::trace::{TraceComparison, TraceFormat, TraceWriter};
+
+pub const HEATMAP_REPORT_ENTRIES: usize = 16;
+pub const PROFILE_REPORT_ENTRIES: usize = 16;
+pub const STATS_BAR_WIDTH: usize = 40;         // histogram bar for 100%
+pub const MEMORY_DUMP_BYTES: u16 = 0x40;
+pub const MEMORY_DUMP_BYTES_PER_LINE: u16 = 16;
+pub const DISASSEMBLY_LINES: usize = 16;
+pub const BACKTRACE_MAX_SYMBOL_OFFSET: u16 = 0x400;     // farther from a symbol an address is shown without name
+pub const SOURCE_MAX_DEPTH: usize = 16;                // guard against scripts sourcing themselves
+pub const STEP_OVER_MAX_CYCLES: u64 = 10_000_000;   // guard against subroutines which never return
+pub const REWIND_MAX_STEPS: usize = 10_000;
+pub const CHECKPOINTS_MAX: usize = 100;       // machine states kept for "rewind", about 73K each
+pub const REPEATABLE_COMMANDS: [&str; 8] = ["s", "c", "n", "finish", "back", "m", "d", "history"];  // repeated by an empty line
+
+// expression printed after every step
+#[derive(Clone, PartialEq, Debug)]
+pub struct DisplayExpr {
+    pub id: usize,
+    pub expr: String,
+}
+
+impl FromStr for InstructionBreak {
+    type Err = String;
+
+    // mnemonic like "STA" or opcode like "$8D"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with('$') || s.starts_with("0x") {
+            let byte = u8::try_from(parse_value(s, Radix::Hex)?).map_err(|_| format!("Invalid opcode '{s}'"))?;
+            Opcode::from_u8(byte).map(Self::Opcode).ok_or_else(|| format!("Invalid opcode '{s}'"))
+        } else {
+            s.parse::<Mnemonic>().map(Self::Mnemonic)
+        }
+    }
+}
+
+pub struct Monitor {
+    debugger: Debugger,             // breakpoints and other break conditions
+    next_dump_addr: u16,            // "m" without address continues here
+    next_disasm_addr: Option<u16>,  // "d" without address continues here; initially at PC
+    assemble_addr: Option<u16>,     // assembly-input mode ("a") is active, next instruction goes here
+    source_depth: usize,
+    rewind: Rewind,                 // executed instructions which can be stepped back ("back")
+    checkpoints: Option<Checkpoints>,   // periodic machine states to go back further ("rewind")
+    displays: Vec<DisplayExpr>,
+    next_display_id: usize,
+    repeat_command: Option<String>,     // executed again on an empty line
+    radix: Radix,                       // base of numbers without prefix in addresses and values
+    program_file: Option<String>,       // reloaded by "reset hard"
+    screen: Option<TextScreen>,         // shown by "screen"
+}
+
+pub fn print_screen(screen: &TextScreen, mem: &Memory) {
+    for line in screen.lines(mem) {
+        println!("{line}");
+    }
+}
+
+pub fn print_heatmap(mem: &Memory, count: usize) {
+    let heatmap = match mem.heatmap() {
+        Some(heatmap) => heatmap,
+        None => {
+            println!("Heatmap collection is disabled");
+            return;
+        },
+    };
+
+    println!("{}", format!("{:<6} {:>10} {:>10} {:>10} {:>10}  Region", "Addr", "Total", "Reads", "Writes", "Executes").bold());
+    for entry in heatmap.hottest(count) {
+        let region = mem.region_at(entry.addr).map(|region| region.name.as_str()).unwrap_or("");
+        println!("${:04X}  {:>10} {:>10} {:>10} {:>10}  {}", entry.addr, entry.total(), entry.reads, entry.writes, entry.executes, region);
+    }
+}
+
+pub fn print_profile(cpu: &Cpu, mem: &Memory, count: usize) {
+    let profile = match cpu.profile() {
+        Some(profile) => profile,
+        None => {
+            println!("Profiling is disabled");
+            return;
+        },
+    };
+
+    let total = profile.total_cycles().max(1);
+    let percent = |cycles: u64| cycles as f64 * 100.0 / total as f64;
+
+    println!("{}", format!("{:<6} {:>12} {:>7} {:>10}  Location", "Addr", "Cycles", "%", "Executed").bold());
+    for entry in profile.hottest(count) {
+        let location = mem.symbols().describe(entry.addr, BACKTRACE_MAX_SYMBOL_OFFSET).unwrap_or_default();
+        println!("${:04X}  {:>12} {:>6.2}% {:>10}  {}", entry.addr, entry.cycles, percent(entry.cycles), entry.executions, location);
+    }
+
+    let routines = profile.hottest_routines(mem.symbols(), count);
+    if !routines.is_empty() {
+        println!();
+        println!("{}", format!("{:<6} {:>12} {:>7} {:>10}  Routine", "Addr", "Cycles", "%", "Executed").bold());
+        for routine in routines {
+            println!("${:04X}  {:>12} {:>6.2}% {:>10}  {}", routine.addr, routine.cycles, percent(routine.cycles), routine.executions, routine.name);
+        }
+    }
+}
+
+pub fn print_stats(cpu: &Cpu) {
+    let stats = match cpu.stats() {
+        Some(stats) => stats,
+        None => {
+            println!("Statistics collection is disabled");
+            return;
+        },
+    };
+
+    let instructions = stats.instructions();
+    let percent = |count: u64| count as f64 * 100.0 / instructions.max(1) as f64;
+    println!("Instructions: {}, cycles: {} ({:.2} per instruction)", instructions, stats.cycles(), stats.cycles() as f64 / instructions.max(1) as f64);
+    println!("Interrupts: {}", [Interrupt::Brk, Interrupt::Irq, Interrupt::Nmi].map(|interrupt| format!("{} {}", interrupt, stats.interrupts(interrupt))).join(", "));
+
+    let histogram = |entries: Vec<(String, u64)>, heading: &str| {
+        println!();
+        println!("{}", format!("{:<8} {:>12} {:>7}", heading, "Executed", "%").bold());
+        for (name, count) in entries {
+            let bar = "#".repeat((percent(count) * STATS_BAR_WIDTH as f64 / 100.0).round() as usize);
+            println!("{:<8} {:>12} {:>6.2}%  {}", name, count, percent(count), bar);
+        }
+    };
+    histogram(stats.by_mnemonic().into_iter().map(|(mnemonic, count)| (mnemonic.to_string(), count)).collect(), "Mnemonic");
+    histogram(stats.by_addressing_mode().into_iter().map(|(mode, count)| (mode.to_string(), count)).collect(), "Mode");
+}
+
+// summary and unreached symbols, or for a range the covered bytes and uncovered gaps
+pub fn print_coverage(cpu: &Cpu, mem: &Memory, range: Option<RangeInclusive<u16>>) {
+    let coverage = match cpu.coverage() {
+        Some(coverage) => coverage,
+        None => {
+            println!("Coverage tracking is disabled");
+            return;
+        },
+    };
+
+    match range {
+        None => {
+            println!("{} instructions executed, {} bytes covered", coverage.executed().count(), coverage.covered_count(0x0000..=0xFFFF));
+            let unreached = coverage.unreached_symbols(mem.symbols());
+            if !unreached.is_empty() {
+                println!("Symbols never reached:");
+                for (addr, name) in unreached {
+                    println!("${addr:04X}  {name}");
+                }
+            }
+        },
+        Some(range) => {
+            let len = *range.end() as usize - *range.start() as usize + 1;
+            let covered = coverage.covered_count(range.clone());
+            println!("${:04X}-${:04X}: {} of {} bytes covered ({:.2}%)", range.start(), range.end(), covered, len, covered as f64 * 100.0 / len as f64);
+            for gap in coverage.uncovered(range) {
+                let location = mem.symbols().describe(*gap.start(), BACKTRACE_MAX_SYMBOL_OFFSET).unwrap_or_default();
+                println!("${:04X}-${:04X}  not executed  {}", gap.start(), gap.end(), location);
+            }
+        },
+    }
+}
+
+// hexdump lines of `len` bytes starting at `addr`, with printable ASCII alongside
+pub fn hexdump(mem: &Memory, addr: u16, len: u16) -> Vec<String> {
+    let mut lines = Vec::new();
+    for offset in (0..len).step_by(MEMORY_DUMP_BYTES_PER_LINE as usize) {
+        let line_addr = addr.wrapping_add(offset);
+        let bytes: Vec<u8> = (0..cmp::min(MEMORY_DUMP_BYTES_PER_LINE, len - offset)).map(|i| mem.peek(line_addr.wrapping_add(i))).collect();
+        let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        let ascii: String = bytes.iter().map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' }).collect();
+        lines.push(format!("${:04X}  {:<width$}  |{}|", line_addr, hex.join(" "), ascii, width = MEMORY_DUMP_BYTES_PER_LINE as usize * 3 - 1));
+    }
+    lines
+}
+
+pub fn print_stack(cpu: &Cpu, mem: &Memory) {
+    let stack = cpu.stack(mem);
+    if stack.is_empty() {
+        println!("Stack is empty (SP=${:02X})", cpu.sp);
+        return;
+    }
+
+    println!("{}", format!("{:<6} {:<5} SP=${:02X}", "Addr", "Value", cpu.sp).bold());
+    for entry in stack {
+        let annotation = match entry.frame {
+            Some(StackFrame::Subroutine { call, ret }) => format!("return to ${ret:04X} (JSR at ${call:04X})"),
+            Some(StackFrame::Interrupt { brk, ret }) => format!("return to ${ret:04X} (BRK at ${brk:04X})"),
+            Some(StackFrame::Hardware { interrupt, ret }) => format!("return to ${ret:04X} ({interrupt})"),
+            Some(StackFrame::Injected { ret }) => format!("return to ${ret:04X} (call)"),
+            None => String::new(),
+        };
+        println!("${:04X}  {:02X}    {}", entry.addr, entry.value, annotation.bright_black());
+    }
+}
+
+pub fn print_backtrace(cpu: &Cpu, mem: &Memory) {
+    // "$E012 <loop+2>" when the address is within a routine known by symbol
+    let describe = |addr: u16| match mem.symbols().describe(addr, BACKTRACE_MAX_SYMBOL_OFFSET) {
+        Some(symbol) => format!("${addr:04X} <{symbol}>"),
+        None => format!("${addr:04X}"),
+    };
+
+    println!("#0   {}", describe(cpu.pc));
+    for (depth, call) in cpu.call_stack().iter().rev().enumerate() {
+        let caller = match call.frame {
+            StackFrame::Subroutine { call, ret } => format!("JSR at {}, returns to ${ret:04X}", describe(call)),
+            StackFrame::Interrupt { brk, ret } => format!("BRK at {}, returns to ${ret:04X}", describe(brk)),
+            StackFrame::Hardware { interrupt, ret } => format!("{interrupt}, returns to {}", describe(ret)),
+            StackFrame::Injected { ret } => format!("call from monitor, returns to {}", describe(ret)),
+        };
+        println!("#{:<3} {}  {}", depth + 1, describe(call.target), caller.bright_black());
+    }
+}
+
+pub fn get_user_input(monitor: &Monitor) -> Result<String, Box<dyn Error>> {
+    let mut user_input = String::new();
+    let stdin = io::stdin();
+    monitor.print_prompt();
+    _ = std::io::stdout().flush();
+    stdin.read_line(&mut user_input)?;
+    Ok(user_input)
+}
+
+
+// pairs of addresses whose contents differ between `range` and the same-sized block at `dest`
+fn compare(mem: &Memory, range: RangeInclusive<u16>, dest: u16) -> Vec<(u16, u16)> {
+    let start = *range.start();
+    range.map(|addr| (addr, dest.wrapping_add(addr - start)))
+        .filter(|(addr, other)| mem.peek(*addr) != mem.peek(*other))
+        .collect()
+}
+
+// register values and memory contents: numbers in the default base or with prefix ($, 0x, %, +), and arithmetic
+fn parse_value(arg: &str, radix: Radix) -> Result<u16, String> {
+    expr::eval(arg, &Env { radix, ..Env::default() })
+}
+
+// counts and numbers of breakpoints etc. are decimal unless prefixed
+fn parse_count(arg: &str) -> Result<u64, String> {
+    expr::parse_number(arg, Radix::Decimal).map_err(|_| format!("Invalid count '{arg}'"))
+}
+
+// flag pattern in NV-BDIZC order; a flag letter or 1 sets, '-', '.' or 0 clears; the reserved bit is always set
+fn parse_flags(pattern: &str) -> Result<StatusFlags, String> {
+    const FLAGS: [(char, StatusFlags); 8] = [
+        ('N', StatusFlags::N), ('V', StatusFlags::V), ('-', StatusFlags::RESERVED), ('B', StatusFlags::B),
+        ('D', StatusFlags::D), ('I', StatusFlags::I), ('Z', StatusFlags::Z), ('C', StatusFlags::C),
+    ];
+
+    if pattern.chars().count() != FLAGS.len() {
+        return Err(format!("Invalid flags '{pattern}', expected 8 characters in NV-BDIZC order"));
+    }
+
+    let mut sr = StatusFlags::RESERVED;
+    for (c, (name, flag)) in pattern.chars().zip(FLAGS) {
+        match c.to_ascii_uppercase() {
+            '1' => sr.insert(flag),
+            '0' | '-' | '.' => {},
+            c if c == name && flag != StatusFlags::RESERVED => sr.insert(flag),
+            _ => return Err(format!("Invalid flag '{c}' at position of {name} in '{pattern}'")),
+        }
+    }
+    Ok(sr)
+}
+
+// sets a register (A, X, Y, SP, PC, P) or a single flag (N, V, B, D, I, Z, C) from an assignment like "A=FF"
+fn set_register(cpu: &mut Cpu, radix: Radix, assignment: &str) -> Result<(), String> {
+    let (name, value) = assignment.split_once('=').ok_or_else(|| format!("Invalid assignment '{assignment}'"))?;
+    let name = name.to_ascii_uppercase();
+
+    let flag = match name.as_str() {
+        "N" => Some(StatusFlags::N),
+        "V" => Some(StatusFlags::V),
+        "B" => Some(StatusFlags::B),
+        "D" => Some(StatusFlags::D),
+        "I" => Some(StatusFlags::I),
+        "Z" => Some(StatusFlags::Z),
+        "C" => Some(StatusFlags::C),
+        _ => None,
+    };
+    if let Some(flag) = flag {
+        match value {
+            "0" => cpu.sr.remove(flag),
+            "1" => cpu.sr.insert(flag),
+            _ => return Err(format!("Invalid flag value '{value}', expected 0 or 1")),
+        }
+        return Ok(());
+    }
+
+    if name == "P" || name == "SR" {
+        cpu.sr = match parse_flags(value) {
+            Ok(sr) => sr,
+            Err(error) if value.len() == 8 => return Err(error),
+            Err(_) => {
+                let sr = u8::try_from(parse_value(value, radix)?).map_err(|_| format!("Value '{value}' out of range for {name}"))?;
+                StatusFlags::from_bits_truncate(sr) | StatusFlags::RESERVED
+            },
+        };
+        return Ok(());
+    }
+
+    let value16 = parse_value(value, radix)?;
+    let value8 = || u8::try_from(value16).map_err(|_| format!("Value '{value}' out of range for {name}"));
+    match name.as_str() {
+        "A" | "AC" => cpu.ac = value8()?,
+        "X" => cpu.x = value8()?,
+        "Y" => cpu.y = value8()?,
+        "S" | "SP" => cpu.sp = value8()?,
+        "PC" => cpu.pc = value16,
+        _ => return Err(format!("Unknown register '{name}'")),
+    }
+    Ok(())
+}
+
+impl Monitor {
+    pub fn create() -> Self {
+        Self {
+            debugger: Debugger::default(),
+            next_dump_addr: 0,
+            next_disasm_addr: None,
+            assemble_addr: None,
+            source_depth: 0,
+            rewind: Rewind::create(REWIND_MAX_STEPS),
+            checkpoints: None,
+            displays: Vec::new(),
+            next_display_id: 1,
+            repeat_command: None,
+            radix: Radix::Hex,
+            program_file: None,
+            screen: None,
+        }
+    }
+
+    // address expression with symbols, registers and memory references, e.g. "start+5", "E000+X", "[FFFC]"
+    fn eval_addr(&self, cpu: &Cpu, mem: &Memory, arg: &str) -> Result<u16, String> {
+        expr::eval(arg, &Env { radix: self.radix, ..Env::create(cpu, mem) })
+    }
+
+    // inclusive address range
+    fn eval_range(&self, cpu: &Cpu, mem: &Memory, from: &str, to: &str) -> Result<RangeInclusive<u16>, String> {
+        let (from, to) = (self.eval_addr(cpu, mem, from)?, self.eval_addr(cpu, mem, to)?);
+        match from <= to {
+            true => Ok(from..=to),
+            false => Err(format!("Invalid range ${from:04X}-${to:04X}")),
+        }
+    }
+
+    pub fn print_prompt(&self) {
+        match self.assemble_addr {
+            Some(addr) => print!("{} ", format!("${addr:04X}").on_blue().white().bold()),
+            None => print!("{} ", "?".on_blue().white().bold()),
+        }
+    }
+
+    // executes monitor commands from a file, one per line; lines starting with '#' are comments.
+    // Returns false if a command quit the monitor.
+    pub fn source(&mut self, cpu: &mut Cpu, mem: &mut Memory, filename: &str) -> Result<bool, String> {
+        if self.source_depth >= SOURCE_MAX_DEPTH {
+            return Err(format!("Scripts nested too deeply (max. {SOURCE_MAX_DEPTH})"));
+        }
+        let script = std::fs::read_to_string(filename).map_err(|error| format!("Error reading script '{filename}': {error}"))?;
+
+        self.source_depth += 1;
+        let mut running = true;
+        for line in script.lines().map(str::trim) {
+            // empty lines only matter for ending assembly input, they do not repeat commands in scripts
+            if line.starts_with('#') || (line.is_empty() && self.assemble_addr.is_none()) {
+                continue;
+            }
+            self.print_prompt();
+            println!("{line}");
+            if !self.process_user_input(cpu, mem, line) {
+                running = false;
+                break;
+            }
+        }
+        self.source_depth -= 1;
+
+        Ok(running)
+    }
+
+    pub fn set_screen(&mut self, screen: TextScreen) {
+        self.screen = Some(screen);
+    }
+
+    // program image loaded at the reset address on startup
+    pub fn set_program_file(&mut self, filename: &str) {
+        self.program_file = Some(String::from(filename));
+    }
+
+    // a hard reset clears memory and reloads the program image like on startup
+    pub fn reset(&mut self, cpu: &mut Cpu, mem: &mut Memory, hard: bool) -> Result<(), String> {
+        self.rewind.clear();
+        if let Some(checkpoints) = &mut self.checkpoints {
+            checkpoints.clear();
+        }
+        if !hard {
+            cpu.warm_reset(mem);
+            return Ok(());
+        }
+
+        cpu.reset(mem);
+        match &self.program_file {
+            Some(filename) => mem.load_from_file(ADDR_RESET_VECTOR, filename)
+                .map_err(|error| format!("Error reading {filename}: {error}")),
+            None => Ok(()),
+        }
+    }
+
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    pub fn debugger_mut(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
+    pub fn add_display(&mut self, expr: &str) -> usize {
+        let id = self.next_display_id;
+        self.next_display_id += 1;
+        self.displays.push(DisplayExpr { id, expr: String::from(expr) });
+        id
+    }
+
+    pub fn delete_display(&mut self, id: usize) -> bool {
+        let count = self.displays.len();
+        self.displays.retain(|display| display.id != id);
+        self.displays.len() != count
+    }
+
+    // current value of a display expression and the byte it points to
+    fn format_display(&self, cpu: &Cpu, mem: &Memory, display: &DisplayExpr) -> String {
+        match self.eval_addr(cpu, mem, &display.expr) {
+            Ok(value) => format!("#{:<3} {} = ${:04X} ({})  [${:04X}] = ${:02X}", display.id, display.expr, value, value, value, mem.peek(value)),
+            Err(error) => format!("#{:<3} {} = <{}>", display.id, display.expr, error),
+        }
+    }
+
+    fn print_displays(&self, cpu: &Cpu, mem: &Memory) {
+        for display in &self.displays {
+            println!("{}", self.format_display(cpu, mem, display));
+        }
+    }
+
+    // executes a single instruction; returns true if execution should stop
+    pub fn step(&mut self, cpu: &mut Cpu, mem: &mut Memory) -> bool {
+        let pc = cpu.pc;
+        if let Some(checkpoints) = &mut self.checkpoints {
+            if let Err(error) = checkpoints.capture(cpu, mem) {
+                println!("{} Error saving checkpoint: {error}", "!!!".white().on_red().bold());
+            }
+        }
+        let stopped = self.rewind.record(cpu, mem, |cpu, mem| cpu.exec(mem, 1)).is_some();
+
+        self.print_displays(cpu, mem);
+
+        let watch_hits = self.debugger.watch_hits(pc, mem);
+        for hit in &watch_hits {
+            println!("{} {}", "***".black().on_yellow().bold(), hit);
+        }
+
+        stopped || !watch_hits.is_empty()
+    }
+
+    // runs until a breakpoint or watchpoint is hit or the CPU stops; the instruction at the current PC is always executed
+    pub fn run(&mut self, cpu: &mut Cpu, mem: &mut Memory) {
+        self.run_until(cpu, mem, |_, _| false, None);
+    }
+
+    // like `run`, but additionally stops once `done` returns true or after `max_cycles`;
+    // `done` is called with the opcode of the instruction just executed, None if undefined (which the CPU stops at);
+    // returns true if stopped because of `done`
+    pub fn run_until(&mut self, cpu: &mut Cpu, mem: &mut Memory, mut done: impl FnMut(&Cpu, Option<Opcode>) -> bool, max_cycles: Option<u64>) -> bool {
+        let cycles_start = cpu.cycles;
+
+        loop {
+            let opcode = cpu.decode(mem.peek(cpu.pc));
+            if self.step(cpu, mem) {
+                break;
+            }
+            if done(cpu, opcode) {
+                return true;
+            }
+            if let Some(reason) = self.debugger.check(cpu, mem) {
+                println!("{} {}", "***".black().on_yellow().bold(), reason);
+                break;
+            }
+            if max_cycles.is_some_and(|max_cycles| cpu.cycles - cycles_start >= max_cycles) {
+                println!("{} Stopped after {} cycles", "***".black().on_yellow().bold(), cpu.cycles - cycles_start);
+                break;
+            }
+        }
+
+        false
+    }
+
+    // undoes the last `count` executed instructions; memory edits made in the monitor are not undone
+    pub fn back(&mut self, cpu: &mut Cpu, mem: &mut Memory, count: usize) {
+        let undone = self.rewind.back(cpu, mem, count);
+        if undone < count {
+            println!("{} Stepped back {} of {} instructions, no earlier history", "***".black().on_yellow().bold(), undone, count);
+        }
+        if undone > 0 {
+            cpu.dump_state(mem);
+        }
+    }
+
+    // keeps the machine state every `interval` cycles while executing, up to CHECKPOINTS_MAX
+    pub fn enable_checkpoints(&mut self, interval: u64) {
+        self.checkpoints = Some(Checkpoints::create(interval, CHECKPOINTS_MAX));
+    }
+
+    pub fn disable_checkpoints(&mut self) {
+        self.checkpoints = None;
+    }
+
+    pub fn checkpoints(&self) -> Option<&Checkpoints> {
+        self.checkpoints.as_ref()
+    }
+
+    // restores the latest checkpoint at least `cycles` cycles before the current cycle
+    pub fn rewind_cycles(&mut self, cpu: &mut Cpu, mem: &mut Memory, cycles: u64) -> Result<(), String> {
+        let Some(checkpoints) = &mut self.checkpoints else {
+            return Err(String::from("Checkpoints are off; enable with 'checkpoints <cycles>'"));
+        };
+        let (from, target) = (cpu.cycles, cpu.cycles.saturating_sub(cycles));
+        match checkpoints.restore(cpu, mem, target).map_err(|error| format!("Error restoring checkpoint: {error}"))? {
+            Some(cycle) => {
+                // the instruction history refers to the state before rewinding
+                self.rewind.clear();
+                println!("{} Rewound {} cycles to checkpoint at cycle {}", "***".black().on_yellow().bold(), from - cycle, cycle);
+                cpu.dump_state(mem);
+                Ok(())
+            },
+            None => Err(format!("No checkpoint at or before cycle {target}")),
+        }
+    }
+
+    // executes `count` instructions unless stopped earlier by a breakpoint or watchpoint
+    pub fn step_count(&mut self, cpu: &mut Cpu, mem: &mut Memory, count: u64) {
+        let mut executed = 0;
+        if count > 0 {
+            self.run_until(cpu, mem, |_, _| {
+                executed += 1;
+                executed == count
+            }, None);
+        }
+    }
+
+    // runs for at least `cycles` cycles unless stopped earlier by a breakpoint or watchpoint
+    pub fn run_cycles(&mut self, cpu: &mut Cpu, mem: &mut Memory, cycles: u64) {
+        if cycles > 0 {
+            self.run_until(cpu, mem, |_, _| false, Some(cycles));
+        }
+    }
+
+    // runs until PC reaches `addr` (temporary breakpoint)
+    pub fn run_to(&mut self, cpu: &mut Cpu, mem: &mut Memory, addr: u16) {
+        self.run_until(cpu, mem, |cpu, _| cpu.pc == addr, None);
+    }
+
+    // executes a subroutine call as a single step
+    pub fn step_over(&mut self, cpu: &mut Cpu, mem: &mut Memory) {
+        if mem.peek(cpu.pc) != u8::from(Opcode::JSR_ABS) {
+            self.step(cpu, mem);
+            return;
+        }
+
+        // the matching RTS returns to the instruction following JSR with the stack pointer restored
+        let return_addr = cpu.pc.wrapping_add(3);
+        let sp = cpu.sp;
+        self.run_until(cpu, mem, |cpu, _| cpu.pc == return_addr && cpu.sp >= sp, Some(STEP_OVER_MAX_CYCLES));
+    }
+
+    // calls a subroutine and runs until it returns to the current PC; true if it returned
+    pub fn call(&mut self, cpu: &mut Cpu, mem: &mut Memory, addr: u16) -> bool {
+        let (return_addr, sp) = (cpu.pc, cpu.sp);
+        cpu.call(mem, addr);
+        self.run_until(cpu, mem, |cpu, _| cpu.pc == return_addr && cpu.sp >= sp, Some(STEP_OVER_MAX_CYCLES))
+    }
+
+    // runs until the current subroutine (or interrupt handler) returns to its caller
+    pub fn step_out(&mut self, cpu: &mut Cpu, mem: &mut Memory) {
+        // the current frame is left by the RTS or RTI that restores SP to where it was before its return address was
+        // pushed; without a tracked frame the return address is assumed on top of the stack. Returns of nested calls
+        // and interrupts leave SP below that, an RTS to a pushed address leaves it where it was
+        let frame_sp = cpu.call_stack().last().map(|frame| frame.sp);
+        let sp = cpu.sp;
+        self.run_until(cpu, mem, |cpu, opcode| {
+            cpu.entered_interrupt().is_none()
+                && matches!(opcode, Some(Opcode::RTS | Opcode::RTI))
+                && frame_sp.map_or(cpu.sp > sp, |frame_sp| cpu.sp >= frame_sp)
+        }, Some(STEP_OVER_MAX_CYCLES));
+    }
+
+    fn process_watchpoint_command(&mut self, cpu: &Cpu, mem: &mut Memory, command: &str, args: &[&str]) {
+        let result = match command {
+            "watch" => {
+                let (range, access) = match args {
+                    [addr] => (self.eval_addr(cpu, mem, addr).map(|addr| addr..=addr), "rw"),
+                    [addr, access @ ("r" | "w" | "rw")] => (self.eval_addr(cpu, mem, addr).map(|addr| addr..=addr), *access),
+                    [from, to] => (self.eval_addr(cpu, mem, from).and_then(|from| Ok(from..=self.eval_addr(cpu, mem, to)?)), "rw"),
+                    [from, to, access] => (self.eval_addr(cpu, mem, from).and_then(|from| Ok(from..=self.eval_addr(cpu, mem, to)?)), *access),
+                    _ => (Err(String::from("Usage: watch <addr> [<to>] [r|w|rw]")), ""),
+                };
+                let (read, write) = match access {
+                    "r" => (true, false),
+                    "w" => (false, true),
+                    _ => (true, true),
+                };
+                range.and_then(|range| match range.is_empty() {
+                    true => Err(String::from("Invalid watchpoint range")),
+                    false => {
+                        let id = mem.watchpoints_mut().add(range, read, write);
+                        println!("{}", mem.watchpoints().list().iter().find(|wp| wp.id == id).unwrap());
+                        Ok(())
+                    },
+                })
+            },
+            "wl" => {
+                if mem.watchpoints().is_empty() {
+                    println!("No watchpoints");
+                }
+                for wp in mem.watchpoints().list() {
+                    println!("{wp}");
+                }
+                Ok(())
+            },
+            "wc" => match args {
+                [id] => match parse_count(id).map(|n| n as usize) {
+                    Ok(id) if mem.watchpoints_mut().remove(id) => Ok(()),
+                    _ => Err(format!("No watchpoint #{id}")),
+                },
+                _ => Err(String::from("Usage: wc <n>")),
+            },
+            _ => Err(format!("Unhandled watchpoint command '{command}'")),
+        };
+
+        if let Err(error) = result {
+            println!("{error}");
+        }
+    }
+
+    fn process_memory_command(&mut self, cpu: &Cpu, mem: &mut Memory, command: &str, args: &[&str]) {
+        let result = match command {
+            "m" => {
+                let range = match args {
+                    [] => Ok((self.next_dump_addr, MEMORY_DUMP_BYTES)),
+                    [addr] => self.eval_addr(cpu, mem, addr).map(|addr| (addr, MEMORY_DUMP_BYTES)),
+                    [addr, len] => self.eval_addr(cpu, mem, addr).and_then(|addr| Ok((addr, self.eval_addr(cpu, mem, len)?))),
+                    _ => Err(String::from("Usage: m [<addr> [<len>]]")),
+                };
+                range.map(|(addr, len)| {
+                    for line in hexdump(mem, addr, len) {
+                        println!("{line}");
+                    }
+                    self.next_dump_addr = addr.wrapping_add(len);
+                })
+            },
+            "d" => {
+                let range = match args {
+                    [] => Ok((self.next_disasm_addr.unwrap_or(cpu.pc), DISASSEMBLY_LINES)),
+                    [addr] => self.eval_addr(cpu, mem, addr).map(|addr| (addr, DISASSEMBLY_LINES)),
+                    [addr, count] => self.eval_addr(cpu, mem, addr).and_then(|addr| Ok((addr, parse_count(count)? as usize))),
+                    _ => Err(String::from("Usage: d [<addr> [<n>]]")),
+                };
+                range.map(|(addr, count)| {
+                    let mut disassembler = Disassembler::create();
+                    disassembler.set_symbols(Some(mem.symbols()));
+                    disassembler.set_variant(cpu.variant());
+                    let lines = disassembler.disassemble(mem, addr, count);
+                    for line in &lines {
+                        println!("{line}");
+                    }
+                    self.next_disasm_addr = lines.last().map(|line| line.next_addr());
+                })
+            },
+            "w" => match args {
+                [addr, values @ ..] if !values.is_empty() => self.eval_addr(cpu, mem, addr).and_then(|addr| {
+                    let values = values.iter()
+                        .map(|value| parse_value(value, self.radix).and_then(|value| u8::try_from(value).map_err(|_| format!("Value '{value:X}' out of range for a byte"))))
+                        .collect::<Result<Vec<u8>, String>>()?;
+                    for (i, value) in values.into_iter().enumerate() {
+                        mem.poke(addr.wrapping_add(i as u16), value);
+                    }
+                    Ok(())
+                }),
+                _ => Err(String::from("Usage: w <addr> <byte> [<byte> ...]")),
+            },
+            "w16" => match args {
+                [addr, value] => self.eval_addr(cpu, mem, addr).and_then(|addr| {
+                    let value = parse_value(value, self.radix)?;
+                    mem.poke(addr, (value & 0x00FF) as u8);                         // LB
+                    mem.poke(addr.wrapping_add(1), ((value & 0xFF00) >> 8) as u8);  // HB
+                    Ok(())
+                }),
+                _ => Err(String::from("Usage: w16 <addr> <word>")),
+            },
+            "f" => match args {
+                [from, to, value] => self.eval_range(cpu, mem, from, to).and_then(|range| {
+                    let value = parse_value(value, self.radix).and_then(|value| u8::try_from(value).map_err(|_| format!("Value '{value:X}' out of range for a byte")))?;
+                    for addr in range {
+                        mem.poke(addr, value);
+                    }
+                    Ok(())
+                }),
+                _ => Err(String::from("Usage: f <from> <to> <byte>")),
+            },
+            // source is read completely before writing, so overlapping ranges copy correctly
+            "t" => match args {
+                [from, to, dest] => self.eval_range(cpu, mem, from, to).and_then(|range| {
+                    let dest = self.eval_addr(cpu, mem, dest)?;
+                    let bytes: Vec<u8> = range.map(|addr| mem.peek(addr)).collect();
+                    for (i, byte) in bytes.into_iter().enumerate() {
+                        mem.poke(dest.wrapping_add(i as u16), byte);
+                    }
+                    Ok(())
+                }),
+                _ => Err(String::from("Usage: t <from> <to> <dest>")),
+            },
+            "cmp" => match args {
+                [from, to, dest] => self.eval_range(cpu, mem, from, to).and_then(|range| {
+                    let dest = self.eval_addr(cpu, mem, dest)?;
+                    let differences = compare(mem, range, dest);
+                    for (addr, other) in &differences {
+                        println!("${:04X}: {:02X}  ${:04X}: {:02X}", addr, mem.peek(*addr), other, mem.peek(*other));
+                    }
+                    println!("{} difference(s)", differences.len());
+                    Ok(())
+                }),
+                _ => Err(String::from("Usage: cmp <from> <to> <dest>")),
+            },
+            _ => Err(format!("Unhandled memory command '{command}'")),
+        };
+
+        if let Err(error) = result {
+            println!("{error}");
+        }
+    }
+
+    // assembles a line at `addr` into memory and returns the address following the instruction
+    fn assemble(&mut self, cpu: &Cpu, mem: &mut Memory, addr: u16, line: &str) -> Result<u16, String> {
+        let bytes = asm::assemble_line(addr, line, &Env::create(cpu, mem))?;
+        for (i, byte) in bytes.iter().enumerate() {
+            mem.poke(addr.wrapping_add(i as u16), *byte);
+        }
+        let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        println!("${:04X}  {:<8}  {}", addr, hex.join(" "), line.trim());
+        Ok(addr.wrapping_add(bytes.len() as u16))
+    }
+
+    fn process_breakpoint_command(&mut self, cpu: &Cpu, mem: &Memory, command: &str, args: &[&str]) {
+        let cpu_cycles = cpu.cycles;
+        let id = || -> Result<usize, String> {
+            match args {
+                [id] => parse_count(id).map(|n| n as usize).map_err(|_| format!("Invalid breakpoint number '{id}'")),
+                _ => Err(format!("Usage: {command} <n>")),
+            }
+        };
+
+        let result = match command {
+            "b" | "break" => match args {
+                [addr] => self.eval_addr(cpu, mem, addr).map(|addr| {
+                    let id = self.debugger.add_breakpoint(addr);
+                    println!("Breakpoint #{id} at ${addr:04X}");
+                }),
+                _ => Err(String::from("Usage: b <addr>")),
+            },
+            "bl" => {
+                if self.debugger.breakpoints().is_empty() {
+                    println!("No breakpoints");
+                }
+                for bp in self.debugger.breakpoints() {
+                    println!("#{:<3} ${:04X}  {}", bp.id, bp.addr, if bp.enabled { "enabled" } else { "disabled" });
+                }
+                Ok(())
+            },
+            "be" | "bd" => id().and_then(|id| match self.debugger.enable_breakpoint(id, command == "be") {
+                true => Ok(()),
+                false => Err(format!("No breakpoint #{id}")),
+            }),
+            "bc" => id().and_then(|id| match self.debugger.delete_breakpoint(id) {
+                true => Ok(()),
+                false => Err(format!("No breakpoint #{id}")),
+            }),
+            "break-on" => match args {
+                [] => {
+                    if self.debugger.instruction_breaks().is_empty() {
+                        println!("No instruction breaks");
+                    }
+                    for ib in self.debugger.instruction_breaks() {
+                        println!("{ib}");
+                    }
+                    Ok(())
+                },
+                // only instructions the CPU has, e.g. no PHX ($DA) on the NMOS 6502
+                _ => args.iter()
+                    .map(|arg| arg.parse::<InstructionBreak>().and_then(|ib| match (0..=0xFF).filter_map(|byte| cpu.decode(byte)).any(|opcode| ib.matches(opcode)) {
+                        true => Ok(ib),
+                        false => Err(format!("No instruction {ib} on the {:?}", cpu.variant())),
+                    }))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map(|ibs| ibs.into_iter().for_each(|ib| self.debugger.add_instruction_break(ib))),
+            },
+            "break-off" => match args {
+                [] => {
+                    self.debugger.clear_instruction_breaks();
+                    Ok(())
+                },
+                _ => args.iter().try_for_each(|arg| {
+                    let ib = arg.parse::<InstructionBreak>()?;
+                    match self.debugger.remove_instruction_break(ib) {
+                        true => Ok(()),
+                        false => Err(format!("No instruction break on {ib}")),
+                    }
+                }),
+            },
+            "break-int" => match args {
+                [] => {
+                    let interrupts: Vec<String> = self.debugger.interrupt_breaks().iter().map(Interrupt::to_string).collect();
+                    println!("Break on interrupt entry: {}", if interrupts.is_empty() { String::from("off") } else { interrupts.join(" ") });
+                    Ok(())
+                },
+                ["off"] => {
+                    self.debugger.clear_interrupt_breaks();
+                    Ok(())
+                },
+                _ => args.iter().try_for_each(|arg| {
+                    let interrupt = match arg.to_ascii_lowercase().as_str() {
+                        "brk" => Interrupt::Brk,
+                        "irq" => Interrupt::Irq,
+                        "nmi" => Interrupt::Nmi,
+                        _ => return Err(format!("Unknown interrupt '{arg}', expected brk, irq or nmi")),
+                    };
+                    self.debugger.set_interrupt_break(interrupt, true);
+                    Ok(())
+                }),
+            },
+            "break-cycle" => match args {
+                [] => {
+                    match self.debugger.break_cycle() {
+                        Some(cycle) => println!("Cycle break at {cycle} (now at cycle {})", cpu_cycles),
+                        None => println!("No cycle break"),
+                    }
+                    Ok(())
+                },
+                ["-"] => {
+                    self.debugger.set_break_cycle(None);
+                    Ok(())
+                },
+                [cycle] => match parse_count(cycle) {
+                    Ok(cycle) if cycle <= cpu_cycles => Err(format!("Cycle {cycle} already passed (now at cycle {cpu_cycles})")),
+                    Ok(cycle) => {
+                        self.debugger.set_break_cycle(Some(cycle));
+                        Ok(())
+                    },
+                    Err(_) => Err(format!("Invalid cycle '{cycle}'")),
+                },
+                _ => Err(String::from("Usage: break-cycle [<n>|-]")),
+            },
+            _ => Err(format!("Unhandled breakpoint command '{command}'")),
+        };
+
+        if let Err(error) = result {
+            println!("{error}");
+        }
+    }
+
+    pub fn process_user_input(&mut self, cpu: &mut Cpu, mem: &mut Memory, user_input: &str) -> bool {
+        // assembly-input mode: every line is an instruction until an empty line or "."
+        if let Some(addr) = self.assemble_addr {
+            match user_input.trim() {
+                "" | "." => self.assemble_addr = None,
+                line => match self.assemble(cpu, mem, addr, line) {
+                    Ok(next_addr) => self.assemble_addr = Some(next_addr),
+                    Err(error) => println!("{error}"),
+                },
+            }
+            return true;
+        }
+
+        // an empty line repeats the last stepping or dumping command
+        let line = match user_input.trim() {
+            "" => match &self.repeat_command {
+                Some(line) => line.clone(),
+                None => return true,
+            },
+            line => String::from(line),
+        };
+
+        // "10 s" executes "s" ten times
+        let (count, line) = match line.split_once(' ') {
+            Some((count, command)) if !command.trim().is_empty() && parse_count(count).is_ok() => (parse_count(count).unwrap() as usize, command.trim()),
+            _ => (1, line.as_str()),
+        };
+
+        let command = line.split_once(' ').map_or(line, |(command, _)| command);
+        self.repeat_command = match command {
+            "m" | "d" if count == 1 => Some(String::from(command)),        // continues after the last dump
+            "m" | "d" => Some(format!("{count} {command}")),
+            _ if REPEATABLE_COMMANDS.contains(&command) && count == 1 => Some(String::from(line)),
+            _ if REPEATABLE_COMMANDS.contains(&command) => Some(format!("{count} {line}")),
+            _ => None,
+        };
+
+        for _ in 0..count {
+            if !self.process_command(cpu, mem, line) {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn process_command(&mut self, cpu: &mut Cpu, mem: &mut Memory, user_input: &str) -> bool {
+        let (command, args) = user_input.split_once(' ').unwrap_or((user_input, ""));
+        let args: Vec<&str> = args.split_whitespace().collect();
+
+        match command {
+            "" => {},
+            "h" | "?" => {
+                println!("{}", "Help".bold());
+                println!("Addresses and values are expressions: numbers in the default base (see radix), $hex, 0xhex, %bin, +decimal, symbols, registers (A X Y SP PC P),");
+                println!("+ - * / & | ^ << >>, <lo >hi byte, [addr] / word(addr) / byte(addr) memory; no spaces (display takes whole line, e.g. 'word at $FB')");
+                println!("Counts are decimal unless prefixed; a count prefix repeats a command, e.g. '10 s'; an empty line repeats the last {}", REPEATABLE_COMMANDS.join("/"));
+                println!("{} - Default base of numbers without prefix in addresses and values (initially hex)", "radix [hex|dec]".yellow().bold());
+                println!("{} - Quit", "q".yellow().bold());
+                println!("{} - Execute monitor commands from file", "source <file>".yellow().bold());
+                println!("{} - Single step, or execute n instructions", "s [n]".yellow().bold());
+                println!("{} - Execute n cycles", "c <n>".yellow().bold());
+                println!("{} - Step back n instructions (default 1); up to {} instructions are kept", "back [n]".yellow().bold(), REWIND_MAX_STEPS);
+                println!("{} - Keep the machine state every n cycles while executing (up to {}); without argument show", "checkpoints [<n>|off]".yellow().bold(), CHECKPOINTS_MAX);
+                println!("{} - Go back at least n cycles to the latest checkpoint before", "rewind <n>".yellow().bold());
+                println!("{} - Step over subroutine call", "n".yellow().bold());
+                println!("{} - Run until current subroutine returns", "finish".yellow().bold());
+                println!("{} - Run until PC reaches address", "g <addr>".yellow().bold());
+                println!("{} - Run until breakpoint", "r".yellow().bold());
+                println!("{} - Set program counter", "pc <addr>".yellow().bold());
+                println!("{} - Call subroutine and return to the monitor on its RTS; registers are kept for inspection", "call <addr>".yellow().bold());
+                println!("{} - Add breakpoint", "b <addr>".yellow().bold());
+                println!("{} - List breakpoints", "bl".yellow().bold());
+                println!("{} - Enable/disable breakpoint", "be <n> / bd <n>".yellow().bold());
+                println!("{} - Delete breakpoint", "bc <n>".yellow().bold());
+                println!("{} - Break before executing mnemonic or opcode; without argument list", "break-on [<mnemonic>|$<opcode> ...]".yellow().bold());
+                println!("{} - Remove instruction breaks; without argument all", "break-off [<mnemonic>|$<opcode> ...]".yellow().bold());
+                println!("{} - Break on entry into the BRK/IRQ/NMI handler, before its first instruction; without argument show", "break-int [brk|irq|nmi ...|off]".yellow().bold());
+                println!("{} - Assert/release the IRQ line, or trigger an NMI", "irq [on|off] / nmi".yellow().bold());
+                println!("{} - Break when the cycle counter reaches n; without argument show, '-' removes", "break-cycle [<n>|-]".yellow().bold());
+                println!("{} - Add watchpoint for reads and/or writes", "watch <addr> [<to>] [r|w|rw]".yellow().bold());
+                println!("{} - List watchpoints", "wl".yellow().bold());
+                println!("{} - Delete watchpoint", "wc <n>".yellow().bold());
+                println!("{} - Additionally list changed registers/flags as old→new after each step (changes are always highlighted)", "changes [on|off]".yellow().bold());
+                println!("{} - Show or set registers/flags, e.g. reg A=FF X=00 PC=E000 P=NV-BDIZC C=1", "reg [<reg>=<value> ...]".yellow().bold());
+                println!("{} - Load VICE label file (al C:e000 .start); labels can be used as addresses", "ll <file>".yellow().bold());
+                println!("{} - List symbols, look up or define a symbol", "sym [<name> [<addr>]]".yellow().bold());
+                println!("{} - Stream instruction trace to file in the given format (default plain)", "trace [on <file> [plain|vice|nestest|json|calls]|off]".yellow().bold());
+                println!("{} - Trace only instructions in the address ranges; without ranges all", "trace range [<from> <to> ...]".yellow().bold());
+                println!("{} - Show the bus cycles of the next instruction; on prints every cycle while executing", "bus [on|off]".yellow().bold());
+                println!("{} - Compare each executed instruction with a reference trace (e.g. nestest.log), stop at the first difference; without argument show status", "compare [<file>|off]".yellow().bold());
+                println!("{} - Show call chain (backtrace)", "bt".yellow().bold());
+                println!("{} - Print expression after every step, e.g. 'display word at $FB' or 'display A+X'; without argument show all", "display [<expr>]".yellow().bold());
+                println!("{} - Remove display expression", "undisplay <n>".yellow().bold());
+                println!("{} - Show the last n executed instructions (recorded even when not tracing)", "history [n]".yellow().bold());
+                println!("{} - Show stack from SP+1 to $01FF with return addresses", "stack".yellow().bold());
+                println!("{} - Reset CPU and devices keeping memory; 'hard' also clears memory and reloads the program file", "reset [hard]".yellow().bold());
+                println!("{} - Save machine state (CPU, memory, devices) to file", "save <file>".yellow().bold());
+                println!("{} - Restore machine state from file", "load <file>".yellow().bold());
+                println!("{} - Examine memory (continues after last dump without address)", "m [<addr> [<len>]]".yellow().bold());
+                println!("{} - Disassemble n instructions (continues after last disassembly without address, initially at PC)", "d [<addr> [<n>]]".yellow().bold());
+                println!("{} - Write bytes to memory", "w <addr> <byte> [<byte> ...]".yellow().bold());
+                println!("{} - Write 16-bit word to memory (little-endian)", "w16 <addr> <word>".yellow().bold());
+                println!("{} - Fill memory range with byte", "f <from> <to> <byte>".yellow().bold());
+                println!("{} - Copy (transfer) memory range to destination", "t <from> <to> <dest>".yellow().bold());
+                println!("{} - Compare memory range with destination and list differences", "cmp <from> <to> <dest>".yellow().bold());
+                println!("{} - Assemble instructions to memory, one per line; empty line or '.' ends", "a <addr> [<instruction>]".yellow().bold());
+                println!("{} - CRC32 and SHA-256 of memory region", "crc <from> <to>".yellow().bold());
+                println!("{} - Check that the disassembly of a range assembles to the same bytes", "verify <from> <to>".yellow().bold());
+                println!("{} - List named memory regions", "region".yellow().bold());
+                println!("{} - Name memory region", "region <name> <from> <to>".yellow().bold());
+                println!("{} - Remove named memory region", "region <name> -".yellow().bold());
+                println!("{} - List attached devices with their interrupt outputs", "devices".yellow().bold());
+                println!("{} - Show memory as text screen, e.g. screen 0400:40x25 petscii; without argument the configured one", "screen [<addr>:<cols>x<rows> [ascii|petscii|apple2]|off]".yellow().bold());
+                println!("{} - Show the contents of the character LCD", "lcd".yellow().bold());
+                println!("{} - Show the seven-segment display as of the end of the last refresh window", "segments".yellow().bold());
+                println!("{} - Show the levels and directions of the GPIO pins, or drive the input pins", "gpio [<levels>]".yellow().bold());
+                println!("{} - Cycles per address and routine (by symbol): top-N report, collection on/off/clear", "profile [n|on|off|clear]".yellow().bold());
+                println!("{} - Instructions by mnemonic and addressing mode, cycles and interrupts: report, collection on/off/clear", "stats [on|off|clear]".yellow().bold());
+                println!("{} - Code coverage: summary with unreached symbols, gaps in range, tracking on/off/clear, export executed addresses", "cov [on|off|clear|<from> <to>|export <file>]".yellow().bold());
+                println!("{} - Memory access heatmap: top-N report, collection on/off/clear, CSV export", "heat [n|on|off|clear|csv <file>]".yellow().bold());
+                println!("{} - Stop when SP wraps around or a push overwrites a return address in use", "stackcheck [on|off]".yellow().bold());
+                println!("{} - Uninitialized memory read detection", "uninit [ignore|warn|break]".yellow().bold());
+            },
+            "q" => return false,
+            "source" => match args[..] {
+                [filename] => match self.source(cpu, mem, filename) {
+                    Ok(running) => return running,
+                    Err(error) => println!("{error}"),
+                },
+                _ => println!("Usage: source <file>"),
+            },
+            "s" => match args[..] {
+                [] => _ = self.step(cpu, mem),
+                [count] => match parse_count(count) {
+                    Ok(count) => self.step_count(cpu, mem, count),
+                    Err(_) => println!("Invalid count '{count}'"),
+                },
+                _ => println!("Usage: s [n]"),
+            },
+            "c" => match args[..] {
+                [cycles] => match parse_count(cycles) {
+                    Ok(cycles) => self.run_cycles(cpu, mem, cycles),
+                    Err(_) => println!("Invalid count '{cycles}'"),
+                },
+                _ => println!("Usage: c <n>"),
+            },
+            "back" => match args[..] {
+                [] => self.back(cpu, mem, 1),
+                [count] => match parse_count(count).map(|n| n as usize) {
+                    Ok(count) => self.back(cpu, mem, count),
+                    Err(_) => println!("Invalid count '{count}'"),
+                },
+                _ => println!("Usage: back [n]"),
+            },
+            "checkpoints" => match args[..] {
+                [] => match &self.checkpoints {
+                    Some(checkpoints) => match checkpoints.oldest() {
+                        Some(oldest) => println!("Checkpoint every {} cycles, {} kept back to cycle {}", checkpoints.interval(), checkpoints.len(), oldest),
+                        None => println!("Checkpoint every {} cycles, none taken yet", checkpoints.interval()),
+                    },
+                    None => println!("Checkpoints are off"),
+                },
+                ["off"] => self.disable_checkpoints(),
+                [interval] => match parse_count(interval) {
+                    Ok(interval) if interval > 0 => self.enable_checkpoints(interval),
+                    _ => println!("Invalid interval '{interval}'"),
+                },
+                _ => println!("Usage: checkpoints [<cycles>|off]"),
+            },
+            "rewind" => match args[..] {
+                [cycles] => match parse_count(cycles) {
+                    Ok(cycles) => if let Err(error) = self.rewind_cycles(cpu, mem, cycles) {
+                        println!("{error}");
+                    },
+                    Err(_) => println!("Invalid count '{cycles}'"),
+                },
+                _ => println!("Usage: rewind <cycles>"),
+            },
+            "n" => self.step_over(cpu, mem),
+            "finish" => self.step_out(cpu, mem),
+            "g" => match args[..] {
+                [addr] => match self.eval_addr(cpu, mem, addr) {
+                    Ok(addr) => self.run_to(cpu, mem, addr),
+                    Err(error) => println!("{error}"),
+                },
+                _ => println!("Usage: g <addr>"),
+            },
+            "r" => self.run(cpu, mem),
+            "pc" => match args[..] {
+                [addr] => match self.eval_addr(cpu, mem, addr) {
+                    Ok(addr) => {
+                        cpu.pc = addr;
+                        cpu.dump_state(mem);
+                    },
+                    Err(error) => println!("{error}"),
+                },
+                _ => println!("Usage: pc <addr>"),
+            },
+            "call" => match args[..] {
+                [addr] => match self.eval_addr(cpu, mem, addr) {
+                    Ok(addr) => {
+                        let cycles = cpu.cycles;
+                        if self.call(cpu, mem, addr) {
+                            println!("{} Returned from ${:04X} after {} cycles", "***".black().on_yellow().bold(), addr, cpu.cycles - cycles);
+                        }
+                        cpu.dump_state(mem);
+                    },
+                    Err(error) => println!("{error}"),
+                },
+                _ => println!("Usage: call <addr>"),
+            },
+            "b" | "break" | "bl" | "be" | "bd" | "bc" | "break-on" | "break-off" | "break-int" | "break-cycle" => self.process_breakpoint_command(cpu, mem, command, &args),
+            "watch" | "wl" | "wc" => self.process_watchpoint_command(cpu, mem, command, &args),
+            "reg" => {
+                for assignment in &args {
+                    if let Err(error) = set_register(cpu, self.radix, assignment) {
+                        println!("{error}");
+                        break;
+                    }
+                }
+                cpu.dump_state(mem);
+            },
+            "bt" => print_backtrace(cpu, mem),
+            "stack" => print_stack(cpu, mem),
+            "display" if args.is_empty() => {
+                if self.displays.is_empty() {
+                    println!("No display expressions");
+                }
+                self.print_displays(cpu, mem);
+            },
+            "display" => {
+                let expr = args.join(" ");
+                match self.eval_addr(cpu, mem, &expr) {
+                    Ok(_) => {
+                        let id = self.add_display(&expr);
+                        println!("{}", self.format_display(cpu, mem, self.displays.iter().find(|display| display.id == id).unwrap()));
+                    },
+                    Err(error) => println!("{error}"),
+                }
+            },
+            "undisplay" => match args[..] {
+                [id] => match parse_count(id).map(|n| n as usize) {
+                    Ok(id) => if !self.delete_display(id) {
+                        println!("No display expression #{id}");
+                    },
+                    Err(_) => println!("Invalid display number '{id}'"),
+                },
+                _ => println!("Usage: undisplay <n>"),
+            },
+            "history" => match args[..] {
+                [] => cpu.print_history(),
+                [count] => match parse_count(count).map(|n| n as usize) {
+                    Ok(count) => {
+                        let skip = cpu.history().count().saturating_sub(count);
+                        for entry in cpu.history().skip(skip) {
+                            println!("{entry}");
+                        }
+                    },
+                    Err(_) => println!("Invalid count '{count}'"),
+                },
+                _ => println!("Usage: history [n]"),
+            },
+            "save" => match args[..] {
+                [filename] => if let Err(error) = state::save_to_file(cpu, mem, filename) {
+                    println!("Error saving state: {error}");
+                },
+                _ => println!("Usage: save <file>"),
+            },
+            "reset" => match args[..] {
+                [] | ["hard"] => match self.reset(cpu, mem, !args.is_empty()) {
+                    Ok(()) => cpu.dump_state(mem),
+                    Err(error) => println!("{error}"),
+                },
+                _ => println!("Usage: reset [hard]"),
+            },
+            "load" => match args[..] {
+                [filename] => match state::load_from_file(cpu, mem, filename) {
+                    Ok(()) => {
+                        self.rewind.clear();
+                        if let Some(checkpoints) = &mut self.checkpoints {
+                            checkpoints.clear();
+                        }
+                        cpu.dump_state(mem);
+                    },
+                    Err(error) => println!("Error loading state: {error}"),
+                },
+                _ => println!("Usage: load <file>"),
+            },
+            "ll" => match args[..] {
+                [filename] => match mem.symbols_mut().load_vice(filename) {
+                    Ok(count) => println!("Loaded {count} labels"),
+                    Err(error) => println!("{error}"),
+                },
+                _ => println!("Usage: ll <file>"),
+            },
+            "sym" => match args[..] {
+                [] => {
+                    let mut symbols: Vec<(u16, &str)> = mem.symbols().iter().collect();
+                    symbols.sort();
+                    for (addr, name) in symbols {
+                        println!("${addr:04X}  {name}");
+                    }
+                },
+                [name] => match mem.symbols().lookup(name) {
+                    Some(addr) => println!("${addr:04X}  {name}"),
+                    None => println!("No symbol '{name}'"),
+                },
+                [name, addr] => match self.eval_addr(cpu, mem, addr) {
+                    Ok(addr) => mem.symbols_mut().add(name, addr),
+                    Err(error) => println!("{error}"),
+                },
+                _ => println!("Usage: sym [<name> [<addr>]]"),
+            },
+            "trace" => match args[..] {
+                [] => match cpu.trace() {
+                    Some(trace) if trace.ranges().is_empty() => println!("Tracing to file is on ({})", trace.format()),
+                    Some(trace) => {
+                        let ranges: Vec<String> = trace.ranges().iter().map(|range| format!("${:04X}-${:04X}", range.start(), range.end())).collect();
+                        println!("Tracing to file is on ({}) for {}", trace.format(), ranges.join(", "));
+                    },
+                    None => println!("Tracing to file is off"),
+                },
+                ["range", ref bounds @ ..] if bounds.len() % 2 == 0 => {
+                    let ranges = bounds.chunks(2).map(|bounds| self.eval_range(cpu, mem, bounds[0], bounds[1])).collect::<Result<Vec<_>, String>>();
+                    match (ranges, cpu.trace_mut()) {
+                        (Ok(ranges), Some(trace)) => trace.set_ranges(ranges),
+                        (Ok(_), None) => println!("Tracing to file is off"),
+                        (Err(error), _) => println!("{error}"),
+                    }
+                },
+                ["on", filename, ref format @ ..] if format.len() <= 1 => {
+                    let format = format.first().map_or(Ok(TraceFormat::Plain), |format| format.parse());
+                    match format.and_then(|format| File::create(filename).map(|file| (file, format)).map_err(|error| format!("Error creating trace file: {error}"))) {
+                        Ok((file, format)) => cpu.start_trace(TraceWriter::create(Box::new(BufWriter::new(file)), format)),
+                        Err(error) => println!("{error}"),
+                    }
+                },
+                ["off"] => cpu.stop_trace(),
+                _ => println!("Usage: trace [on <file> [plain|vice|nestest|json|calls]|off|range [<from> <to> ...]]"),
+            },
+            "compare" => match args[..] {
+                [] => match cpu.trace_comparison() {
+                    Some(comparison) if comparison.is_active() => println!("Comparing with reference trace, {} instructions match so far", comparison.matched()),
+                    Some(comparison) => match comparison.divergence() {
+                        Some(divergence) => println!("{divergence}"),
+                        None => println!("End of reference trace reached, {} instructions match", comparison.matched()),
+                    },
+                    None => println!("No reference trace"),
+                },
+                ["off"] => {
+                    cpu.stop_trace_comparison();
+                },
+                [filename] => match File::open(filename) {
+                    Ok(file) => cpu.start_trace_comparison(TraceComparison::create(Box::new(io::BufReader::new(file)))),
+                    Err(error) => println!("Error opening reference trace: {error}"),
+                },
+                _ => println!("Usage: compare [<file>|off]"),
+            },
+            "m" | "d" | "w" | "w16" | "f" | "t" | "cmp" => self.process_memory_command(cpu, mem, command, &args),
+            "a" => match args.split_first() {
+                Some((addr, instruction)) => match self.eval_addr(cpu, mem, addr) {
+                    Ok(addr) if instruction.is_empty() => self.assemble_addr = Some(addr),
+                    Ok(addr) => if let Err(error) = self.assemble(cpu, mem, addr, &instruction.join(" ")) {
+                        println!("{error}");
+                    },
+                    Err(error) => println!("{error}"),
+                },
+                None => println!("Usage: a <addr> [<instruction>]"),
+            },
+            "crc" => {
+                let range = match (args.first(), args.get(1)) {
+                    (Some(from), Some(to)) => self.eval_addr(cpu, mem, from).and_then(|from| Ok(from..=self.eval_addr(cpu, mem, to)?)),
+                    _ => Err(String::from("Usage: crc <from> <to>")),
+                };
+                match range {
+                    Ok(range) => {
+                        let sha256: String = mem.sha256(range.clone()).iter().map(|byte| format!("{:02x}", byte)).collect();
+                        println!("CRC32:   {:08X}", mem.crc32(range));
+                        println!("SHA-256: {sha256}");
+                    },
+                    Err(error) => println!("{error}"),
+                }
+            },
+            "verify" => {
+                let range = match args[..] {
+                    [from, to] => self.eval_range(cpu, mem, from, to),
+                    _ => Err(String::from("Usage: verify <from> <to>")),
+                };
+                match range.and_then(|range| asm::verify_round_trip(mem, range)) {
+                    Ok(count) => println!("{count} lines disassemble and reassemble to identical bytes"),
+                    Err(error) => println!("{} {error}", "!!!".white().on_red().bold()),
+                }
+            },
+            "region" => {
+                match args[..] {
+                    [] => {
+                        for region in mem.regions() {
+                            println!("{:04X}-{:04X}  {}", region.range.start(), region.range.end(), region.name);
+                        }
+                    },
+                    [name, "-"] => if !mem.remove_region(name) {
+                        println!("No region named '{name}'");
+                    },
+                    [name, from, to] => {
+                        match self.eval_addr(cpu, mem, from).and_then(|from| Ok(from..=self.eval_addr(cpu, mem, to)?)) {
+                            Ok(range) if range.is_empty() => println!("Invalid region range"),
+                            Ok(range) => mem.add_region(name, range),
+                            Err(error) => println!("{error}"),
+                        }
+                    },
+                    _ => println!("Usage: region [<name> <from> <to> | <name> -]"),
+                }
+            },
+            "screen" => match args[..] {
+                [] => match &self.screen {
+                    Some(screen) => print_screen(screen, mem),
+                    None => println!("No screen configured"),
+                },
+                ["off"] => self.screen = None,
+                [geometry] | [geometry, _] => {
+                    let charset = match args.get(1) {
+                        None | Some(&"ascii") => Ok(Charset::Ascii),
+                        Some(&"petscii") => Ok(Charset::Petscii),
+                        Some(&"apple2") => Ok(Charset::AppleII),
+                        Some(charset) => Err(format!("Unknown character set '{charset}'")),
+                    };
+                    match charset.and_then(|charset| {
+                        let (base, columns, rows) = TextScreen::parse_geometry(geometry)?;
+                        TextScreen::create(base, columns, rows, charset)
+                    }) {
+                        Ok(screen) => {
+                            print_screen(&screen, mem);
+                            self.screen = Some(screen);
+                        },
+                        Err(error) => println!("{error}"),
+                    }
+                },
+                _ => println!("Usage: screen [<addr>:<cols>x<rows> [ascii|petscii|apple2]|off]"),
+            },
+            "lcd" => match mem.device::<Lcd>("lcd") {
+                Some(lcd) => {
+                    for line in lcd.lines() {
+                        println!("{line}");
+                    }
+                },
+                None => println!("No LCD attached"),
+            },
+            "segments" => match mem.device::<SevenSegment>("segments") {
+                Some(segments) => {
+                    for line in segments.lines() {
+                        println!("{line}");
+                    }
+                },
+                None => println!("No seven-segment display attached"),
+            },
+            "gpio" => match (mem.device_mut::<Gpio>("gpio"), &args[..]) {
+                (None, _) => println!("No GPIO port attached"),
+                (Some(gpio), []) => println!("Pins: {:08b}, directions: {:08b} (1 = output)", gpio.port(), gpio.ddr()),
+                (Some(gpio), [levels]) => match parse_value(levels, self.radix).and_then(|value| u8::try_from(value).map_err(|_| format!("Value '{value:X}' out of range for a byte"))) {
+                    Ok(levels) => gpio.set_pins(levels),
+                    Err(error) => println!("{error}"),
+                },
+                _ => println!("Usage: gpio [<levels>]"),
+            },
+            "bus" => match args[..] {
+                [] => {
+                    println!("{}", bus::HEADER);
+                    for cycle in bus::instruction_cycles(cpu, mem) {
+                        println!("{cycle}");
+                    }
+                },
+                ["on"] => {
+                    println!("{}", bus::HEADER);
+                    cpu.on_bus_cycle(|cycle| println!("{cycle}"));
+                },
+                ["off"] => cpu.stop_bus_trace(),
+                _ => println!("Usage: bus [on|off]"),
+            },
+            "devices" => {
+                let mut attached = 0;
+                for device in mem.devices() {
+                    let range = device.range();
+                    let outputs = [("IRQ", device.irq()), ("NMI", device.nmi())].iter()
+                        .filter(|(_, asserted)| *asserted)
+                        .map(|(line, _)| *line)
+                        .collect::<Vec<_>>();
+                    println!("{:04X}-{:04X}  {}  {}", range.start(), range.end(), device.name(), outputs.join(" ").red().bold());
+                    attached += 1;
+                }
+                if attached == 0 {
+                    println!("No devices attached");
+                }
+            },
+            "profile" => match args[..] {
+                [] => print_profile(cpu, mem, PROFILE_REPORT_ENTRIES),
+                ["on"] => cpu.enable_profile(),
+                ["off"] => cpu.disable_profile(),
+                ["clear"] => cpu.clear_profile(),
+                [count] => match parse_count(count).map(|n| n as usize) {
+                    Ok(count) => print_profile(cpu, mem, count),
+                    Err(_) => println!("Usage: profile [n|on|off|clear]"),
+                },
+                _ => println!("Usage: profile [n|on|off|clear]"),
+            },
+            "stats" => match args[..] {
+                [] => print_stats(cpu),
+                ["on"] => cpu.enable_stats(),
+                ["off"] => cpu.disable_stats(),
+                ["clear"] => cpu.clear_stats(),
+                _ => println!("Usage: stats [on|off|clear]"),
+            },
+            "cov" => match args[..] {
+                [] => print_coverage(cpu, mem, None),
+                ["on"] => cpu.enable_coverage(),
+                ["off"] => cpu.disable_coverage(),
+                ["clear"] => cpu.clear_coverage(),
+                ["export", filename] => match cpu.coverage() {
+                    Some(coverage) => if let Err(error) = File::create(filename).and_then(|file| coverage.write_addresses(BufWriter::new(file))) {
+                        println!("Error writing coverage: {error}");
+                    },
+                    None => println!("Coverage tracking is disabled"),
+                },
+                [from, to] => match self.eval_range(cpu, mem, from, to) {
+                    Ok(range) => print_coverage(cpu, mem, Some(range)),
+                    Err(error) => println!("{error}"),
+                },
+                _ => println!("Usage: cov [on|off|clear|<from> <to>|export <file>]"),
+            },
+            "heat" => {
+                match args[..] {
+                    [] => print_heatmap(mem, HEATMAP_REPORT_ENTRIES),
+                    ["on"] => mem.enable_heatmap(),
+                    ["off"] => mem.disable_heatmap(),
+                    ["clear"] => mem.clear_heatmap(),
+                    ["csv", filename] => {
+                        match mem.heatmap() {
+                            Some(heatmap) => {
+                                if let Err(error) = File::create(filename).and_then(|file| heatmap.write_csv(BufWriter::new(file))) {
+                                    println!("Error writing heatmap: {error}");
+                                }
+                            },
+                            None => println!("Heatmap collection is disabled"),
+                        }
+                    },
+                    [count] => match parse_count(count).map(|n| n as usize) {
+                        Ok(count) => print_heatmap(mem, count),
+                        Err(_) => println!("Invalid count '{count}'"),
+                    },
+                    _ => println!("Usage: heat [n|on|off|clear|csv <file>]"),
+                }
+            },
+            "radix" => {
+                match args.first() {
+                    None => {},
+                    Some(&"hex") => self.radix = Radix::Hex,
+                    Some(&"dec") => self.radix = Radix::Decimal,
+                    Some(arg) => println!("Unknown argument '{arg}'"),
+                }
+                println!("Default base: {}", if self.radix == Radix::Hex { "hex" } else { "dec" });
+            },
+            "changes" => {
+                match args.first() {
+                    None => {},
+                    Some(&"on") => cpu.set_show_changes(true),
+                    Some(&"off") => cpu.set_show_changes(false),
+                    Some(arg) => println!("Unknown argument '{arg}'"),
+                }
+                println!("List register changes: {}", if cpu.show_changes() { "on" } else { "off" });
+            },
+            "irq" => {
+                match args.first() {
+                    None => {},
+                    Some(&"on") => cpu.set_irq(true),
+                    Some(&"off") => cpu.set_irq(false),
+                    Some(arg) => println!("Unknown argument '{arg}'"),
+                }
+                println!("IRQ line: {}", if cpu.irq() { "asserted" } else { "released" });
+            },
+            "nmi" => cpu.nmi(),
+            "stackcheck" => {
+                match args.first() {
+                    None => {},
+                    Some(&"on") => cpu.set_stack_check(true),
+                    Some(&"off") => cpu.set_stack_check(false),
+                    Some(arg) => println!("Unknown argument '{arg}'"),
+                }
+                println!("Stack check: {}", if cpu.stack_check() { "on" } else { "off" });
+            },
+            "uninit" => {
+                match args.first() {
+                    None => {},
+                    Some(&"ignore") => mem.set_uninit_policy(UninitPolicy::Ignore),
+                    Some(&"warn") => mem.set_uninit_policy(UninitPolicy::Warn),
+                    Some(&"break") => mem.set_uninit_policy(UninitPolicy::Break),
+                    Some(policy) => println!("Unknown policy '{policy}'"),
+                }
+                println!("Uninitialized read detection: {:?}", mem.uninit_policy());
+            },
+            _ => println!("Unknown command '{command}'. Try 'h' or '?'  for help."),
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::debugger::Breakpoint;
+    use crate::instruction::Opcode::*;
+    use super::*;
+
+    fn setup() -> (Monitor, Cpu, Memory) {
+        let (cpu, mem) = crate::cpu::tests::setup();
+        (Monitor::create(), cpu, mem)
+    }
+
+    #[test]
+    fn eval_addr() {
+        let (mut monitor, mut cpu, mut mem) = setup();
+        assert_eq!(monitor.eval_addr(&cpu, &mem, "$E000"), Ok(0xE000));
+        assert_eq!(monitor.eval_addr(&cpu, &mem, "0xe000"), Ok(0xE000));
+        assert_eq!(monitor.eval_addr(&cpu, &mem, "E000"), Ok(0xE000));
+        assert_eq!(monitor.eval_addr(&cpu, &mem, "512"), Ok(0x0512));
+        assert_eq!(monitor.eval_addr(&cpu, &mem, "+512"), Ok(0x0200));
+        assert_eq!(monitor.eval_addr(&cpu, &mem, "%10"), Ok(0x0002));
+        assert!(monitor.eval_addr(&cpu, &mem, "$10000").is_err());
+        assert!(monitor.eval_addr(&cpu, &mem, "xyz").is_err());
+
+        monitor.process_user_input(&mut cpu, &mut mem, "radix dec");
+        assert_eq!(monitor.eval_addr(&cpu, &mem, "512"), Ok(0x0200));
+        assert_eq!(monitor.eval_addr(&cpu, &mem, "$512"), Ok(0x0512));
+        assert!(monitor.eval_addr(&cpu, &mem, "E000").is_err());
+
+        // values follow the default base, counts are always decimal
+        monitor.process_user_input(&mut cpu, &mut mem, "w $0200 10");
+        assert_eq!(mem.peek(0x0200), 10);
+        monitor.process_user_input(&mut cpu, &mut mem, "radix hex");
+        monitor.process_user_input(&mut cpu, &mut mem, "w $0200 10");
+        monitor.process_user_input(&mut cpu, &mut mem, "reg X=%101");
+        assert_eq!((mem.peek(0x0200), cpu.x), (0x10, 5));
+        assert_eq!(super::parse_count("10"), Ok(10));
+        assert_eq!(super::parse_count("$10"), Ok(16));
+    }
+
+    #[test]
+    fn source() {
+        let (mut monitor, mut cpu, mut mem) = setup();
+
+        let dir = std::env::temp_dir();
+        let script = dir.join(format!("rust-6502-emu-test-{}.mon", std::process::id()));
+        let nested = dir.join(format!("rust-6502-emu-test-{}-nested.mon", std::process::id()));
+        std::fs::write(&nested, "# nested script\nb $E002\nsource nested-does-not-exist.mon\n").unwrap();
+        std::fs::write(&script, format!("a $E000\nINX\nINX\nJMP $E000\n.\nsource {}\nr\nq\nreg X=FF\n", nested.display())).unwrap();
+
+        let running = monitor.process_user_input(&mut cpu, &mut mem, &format!("source {}", script.display()));
+        std::fs::remove_file(&script).unwrap();
+        std::fs::remove_file(&nested).unwrap();
+
+        // "q" stops the script and the monitor
+        assert!(!running);
+        assert_eq!(monitor.debugger().breakpoints().len(), 1);
+        assert_eq!((cpu.pc, cpu.x), (0xE002, 2));
+
+        // scripts sourcing themselves are stopped
+        let script = dir.join(format!("rust-6502-emu-test-{}-self.mon", std::process::id()));
+        std::fs::write(&script, format!("s\nsource {}\n", script.display())).unwrap();
+        assert!(monitor.source(&mut cpu, &mut mem, script.to_str().unwrap()).unwrap());
+        std::fs::remove_file(&script).unwrap();
+        assert_eq!(monitor.source_depth, 0);
+    }
+
+    #[test]
+    fn symbols() {
+        let (mut monitor, mut cpu, mut mem) = setup();
+
+        // E000 INX, E001 JMP $E000
+        mem.write_u8(ADDR_RESET_VECTOR, INX.into());
+        mem.write_u8(None, JMP_ABS.into());
+        mem.write_u16(None, ADDR_RESET_VECTOR);
+        mem.symbols_mut().parse_vice("al C:e000 .start\nal C:e001 .jump\n").unwrap();
+
+        monitor.process_user_input(&mut cpu, &mut mem, "b jump");
+        assert_eq!(monitor.debugger().breakpoints()[0].addr, 0xE001);
+        monitor.process_user_input(&mut cpu, &mut mem, "b nowhere");
+        assert_eq!(monitor.debugger().breakpoints().len(), 1);
+
+        monitor.process_user_input(&mut cpu, &mut mem, "sym data $0200");
+        monitor.process_user_input(&mut cpu, &mut mem, "w data 42");
+        assert_eq!(mem.peek(0x0200), 0x42);
+
+        monitor.process_user_input(&mut cpu, &mut mem, "g jump");
+        assert_eq!(cpu.pc, 0xE001);
+    }
+
+    #[test]
+    fn address_expressions() {
+        let (mut monitor, mut cpu, mut mem) = setup();
+        mem.symbols_mut().add("start", 0xE000);
+        cpu.x = 2;
+
+        monitor.process_user_input(&mut cpu, &mut mem, "b start+X*2");
+        monitor.process_user_input(&mut cpu, &mut mem, "b [$FFFC]+1");
+        assert_eq!(monitor.debugger().breakpoints().iter().map(|bp| bp.addr).collect::<Vec<u16>>(), vec![0xE004, 0xE001]);
+
+        monitor.process_user_input(&mut cpu, &mut mem, "w16 $0200 E000");
+        monitor.process_user_input(&mut cpu, &mut mem, "w [$0200]+1 $42");
+        assert_eq!(mem.peek(0xE001), 0x42);
+    }
+
+    #[test]
+    fn parse_flags() {
+        assert_eq!(super::parse_flags("NV-BDIZC"), Ok(StatusFlags::ALL | StatusFlags::RESERVED));
+        assert_eq!(super::parse_flags("n.-....c"), Ok(StatusFlags::N | StatusFlags::C | StatusFlags::RESERVED));
+        assert_eq!(super::parse_flags("00100011"), Ok(StatusFlags::Z | StatusFlags::C | StatusFlags::RESERVED));
+        assert!(super::parse_flags("NV-BDIZ").is_err());
+        assert!(super::parse_flags("VN-BDIZC").is_err());
+    }
+
+    #[test]
+    fn set_registers() {
+        let (mut monitor, mut cpu, mut mem) = setup();
+
+        monitor.process_user_input(&mut cpu, &mut mem, "reg A=FF x=$10 Y=0x20 SP=80 PC=E123 P=N.-....C");
+        assert_eq!((cpu.ac, cpu.x, cpu.y, cpu.sp, cpu.pc), (0xFF, 0x10, 0x20, 0x80, 0xE123));
+        assert_eq!(cpu.sr, StatusFlags::N | StatusFlags::C | StatusFlags::RESERVED);
+
+        monitor.process_user_input(&mut cpu, &mut mem, "reg N=0 Z=1");
+        assert_eq!(cpu.sr, StatusFlags::Z | StatusFlags::C | StatusFlags::RESERVED);
+
+        monitor.process_user_input(&mut cpu, &mut mem, "reg P=03");
+        assert_eq!(cpu.sr, StatusFlags::Z | StatusFlags::C | StatusFlags::RESERVED);
+
+        // invalid assignments leave the register untouched and stop processing
+        monitor.process_user_input(&mut cpu, &mut mem, "reg A=100 X=00");
+        assert_eq!((cpu.ac, cpu.x), (0xFF, 0x10));
+        monitor.process_user_input(&mut cpu, &mut mem, "reg Q=1 C=2");
+        assert!(cpu.sr.contains(StatusFlags::C));
+    }
+
+    #[test]
+    fn examine_and_edit_memory() {
+        let (mut monitor, mut cpu, mut mem) = setup();
+
+        monitor.process_user_input(&mut cpu, &mut mem, "w $0200 48 69 $21 0x00");
+        monitor.process_user_input(&mut cpu, &mut mem, "w16 $0210 E123");
+        assert_eq!((mem.peek(0x0200), mem.peek(0x0201), mem.peek(0x0202), mem.peek(0x0203)), (0x48, 0x69, 0x21, 0x00));
+        assert_eq!((mem.peek(0x0210), mem.peek(0x0211)), (0x23, 0xE1));
+
+        // nothing is written if any value is invalid
+        monitor.process_user_input(&mut cpu, &mut mem, "w $0200 01 100");
+        assert_eq!(mem.peek(0x0200), 0x48);
+
+        assert_eq!(hexdump(&mem, 0x0200, 20), vec![
+            "$0200  48 69 21 00 00 00 00 00 00 00 00 00 00 00 00 00  |Hi!.............|",
+            "$0210  23 E1 00 00                                      |#...|",
+        ]);
+
+        monitor.process_user_input(&mut cpu, &mut mem, "m $0200 +20");
+        assert_eq!(monitor.next_dump_addr, 0x0214);
+        monitor.process_user_input(&mut cpu, &mut mem, "m");
+        assert_eq!(monitor.next_dump_addr, 0x0214 + MEMORY_DUMP_BYTES);
+
+        // $0200 PHA, $0201 ADC $21 (0x69 0x21), $0203 BRK, $0204 BRK
+        monitor.process_user_input(&mut cpu, &mut mem, "d $0200 3");
+        assert_eq!(monitor.next_disasm_addr, Some(0x0204));
+        // continues with 12 BRKs, $0210 (unknown), $0211 SBC ($00,X), $0213 BRK, $0214 BRK
+        monitor.process_user_input(&mut cpu, &mut mem, "");
+        assert_eq!(monitor.next_disasm_addr, Some(0x0215));
+    }
+
+    #[test]
+    fn fill_copy_compare() {
+        let (mut monitor, mut cpu, mut mem) = setup();
+
+        monitor.process_user_input(&mut cpu, &mut mem, "f $0200 $02FF AA");
+        assert!((0x0200..=0x02FF).all(|addr| mem.peek(addr) == 0xAA));
+        assert_eq!(mem.peek(0x0300), 0x00);
+
+        monitor.process_user_input(&mut cpu, &mut mem, "w $0210 01 02 03");
+        monitor.process_user_input(&mut cpu, &mut mem, "t $0200 $02FF $0400");
+        assert_eq!((mem.peek(0x0410), mem.peek(0x0412), mem.peek(0x04FF)), (0x01, 0x03, 0xAA));
+        assert!(compare(&mem, 0x0200..=0x02FF, 0x0400).is_empty());
+
+        mem.poke(0x0411, 0x00);
+        assert_eq!(compare(&mem, 0x0200..=0x02FF, 0x0400), vec![(0x0211, 0x0411)]);
+
+        // overlapping copy
+        monitor.process_user_input(&mut cpu, &mut mem, "t $0210 $0212 $0211");
+        assert_eq!((mem.peek(0x0211), mem.peek(0x0212), mem.peek(0x0213)), (0x01, 0x02, 0x03));
+
+        // compares without running, and is not repeated by an empty line
+        let cycles = cpu.cycles;
+        monitor.process_user_input(&mut cpu, &mut mem, "cmp $0200 $0300 $0400");
+        monitor.process_user_input(&mut cpu, &mut mem, "");
+        assert_eq!(cpu.cycles, cycles);
+
+        monitor.process_user_input(&mut cpu, &mut mem, "f $0300 $0200 00");
+        assert_eq!(mem.peek(0x0200), 0xAA);
+    }
+
+    #[test]
+    fn repeat_and_count_prefix() {
+        let (mut monitor, mut cpu, mut mem) = setup();
+        monitor.process_user_input(&mut cpu, &mut mem, "a $E000 INX");
+        monitor.process_user_input(&mut cpu, &mut mem, "a $E001 JMP $E000");
+
+        // empty line without a previous command does nothing
+        assert!(monitor.process_user_input(&mut cpu, &mut mem, ""));
+        assert_eq!(cpu.pc, 0xE000);
+
+        monitor.process_user_input(&mut cpu, &mut mem, "s");
+        monitor.process_user_input(&mut cpu, &mut mem, "");
+        monitor.process_user_input(&mut cpu, &mut mem, "  ");
+        assert_eq!((cpu.x, cpu.pc), (2, 0xE001));
+
+        monitor.process_user_input(&mut cpu, &mut mem, "10 s");
+        assert_eq!(cpu.x, 7);
+        monitor.process_user_input(&mut cpu, &mut mem, "");
+        assert_eq!(cpu.x, 12);
+
+        // not repeatable
+        monitor.process_user_input(&mut cpu, &mut mem, "reg X=00");
+        monitor.process_user_input(&mut cpu, &mut mem, "");
+        assert_eq!(cpu.x, 0);
+
+        monitor.process_user_input(&mut cpu, &mut mem, "m $0200 $10");
+        assert_eq!(monitor.next_dump_addr, 0x0210);
+        monitor.process_user_input(&mut cpu, &mut mem, "");
+        assert_eq!(monitor.next_dump_addr, 0x0210 + MEMORY_DUMP_BYTES);
+
+        monitor.process_user_input(&mut cpu, &mut mem, "3 reg X=01");
+        assert_eq!(cpu.x, 1);
+        assert!(!monitor.process_user_input(&mut cpu, &mut mem, "2 q"));
+    }
+
+    #[test]
+    fn displays() {
+        let (mut monitor, mut cpu, mut mem) = setup();
+        monitor.process_user_input(&mut cpu, &mut mem, "w $00FB 34 12");
+        monitor.process_user_input(&mut cpu, &mut mem, "w $1234 AA");
+        monitor.process_user_input(&mut cpu, &mut mem, "display word at $FB");
+        monitor.process_user_input(&mut cpu, &mut mem, "display A+X");
+        monitor.process_user_input(&mut cpu, &mut mem, "display unknown");
+        assert_eq!(monitor.displays.len(), 2);
+
+        assert_eq!(monitor.format_display(&cpu, &mem, &monitor.displays[0]), "#1   word at $FB = $1234 (4660)  [$1234] = $AA");
+        cpu.ac = 0x10;
+        cpu.x = 0x02;
+        assert_eq!(monitor.format_display(&cpu, &mem, &monitor.displays[1]), "#2   A+X = $0012 (18)  [$0012] = $00");
+
+        monitor.process_user_input(&mut cpu, &mut mem, "undisplay 1");
+        assert_eq!(monitor.displays, vec![DisplayExpr { id: 2, expr: String::from("A+X") }]);
+        assert!(!monitor.delete_display(1));
+    }
+
+    #[test]
+    fn back() {
+        let (mut monitor, mut cpu, mut mem) = setup();
+        monitor.process_user_input(&mut cpu, &mut mem, "a $E000 LDX #$00");
+        monitor.process_user_input(&mut cpu, &mut mem, "a $E002 INX");
+        monitor.process_user_input(&mut cpu, &mut mem, "a $E003 STX $0200");
+        monitor.process_user_input(&mut cpu, &mut mem, "a $E006 JMP $E002");
+
+        monitor.process_user_input(&mut cpu, &mut mem, "s 7");
+        assert_eq!((cpu.x, mem.peek(0x0200), cpu.pc), (2, 2, 0xE002));
+
+        monitor.process_user_input(&mut cpu, &mut mem, "back");
+        assert_eq!((cpu.x, mem.peek(0x0200), cpu.pc), (2, 2, 0xE006));
+        monitor.process_user_input(&mut cpu, &mut mem, "back 2");
+        assert_eq!((cpu.x, mem.peek(0x0200), cpu.pc), (1, 1, 0xE002));
+        monitor.process_user_input(&mut cpu, &mut mem, "back 10");
+        assert_eq!((cpu.x, mem.peek(0x0200), cpu.pc), (0, 0, 0xE000));
+
+        // execution continues from the rewound state
+        monitor.process_user_input(&mut cpu, &mut mem, "s 3");
+        assert_eq!((cpu.x, mem.peek(0x0200), cpu.pc), (1, 1, 0xE006));
+    }
+
+    #[test]
+    fn save_load_state() {
+        let (mut monitor, mut cpu, mut mem) = setup();
+        let filename = std::env::temp_dir().join(format!("rust-6502-emu-test-{}.state", std::process::id()));
+        let filename = filename.display();
+
+        monitor.process_user_input(&mut cpu, &mut mem, "w $0200 AA");
+        monitor.process_user_input(&mut cpu, &mut mem, "reg X=42");
+        monitor.process_user_input(&mut cpu, &mut mem, &format!("save {filename}"));
+
+        monitor.process_user_input(&mut cpu, &mut mem, "w $0200 55");
+        monitor.process_user_input(&mut cpu, &mut mem, "reg X=00");
+        monitor.process_user_input(&mut cpu, &mut mem, &format!("load {filename}"));
+        assert_eq!((mem.peek(0x0200), cpu.x), (0xAA, 0x42));
+
+        std::fs::remove_file(filename.to_string()).unwrap();
+        monitor.process_user_input(&mut cpu, &mut mem, &format!("load {filename}"));
+        assert_eq!(cpu.x, 0x42);
+    }
+
+    #[test]
+    fn rewind_cycles() {
+        let (mut monitor, mut cpu, mut mem) = setup();
+        // E000 INX, E001 JMP $E000; 5 cycles per loop
+        monitor.process_user_input(&mut cpu, &mut mem, "w $E000 E8 4C 00 E0");
+
+        monitor.process_user_input(&mut cpu, &mut mem, "rewind 10");
+        monitor.process_user_input(&mut cpu, &mut mem, "checkpoints 100");
+        monitor.process_user_input(&mut cpu, &mut mem, "c 1000");
+        assert_eq!((cpu.cycles, cpu.x), (1007, 200));
+        assert_eq!(monitor.checkpoints().unwrap().len(), 10);
+
+        // the latest checkpoint at least 250 cycles back
+        monitor.process_user_input(&mut cpu, &mut mem, "rewind 250");
+        assert_eq!((cpu.cycles, cpu.pc, cpu.x), (707, 0xE000, 140));
+        monitor.process_user_input(&mut cpu, &mut mem, "back");
+        assert_eq!(cpu.cycles, 707);
+
+        monitor.process_user_input(&mut cpu, &mut mem, "rewind 5000");
+        assert_eq!(cpu.cycles, 707);
+        monitor.process_user_input(&mut cpu, &mut mem, "checkpoints off");
+        assert!(monitor.checkpoints().is_none());
+    }
+
+    #[test]
+    fn assemble() {
+        let (mut monitor, mut cpu, mut mem) = setup();
+
+        monitor.process_user_input(&mut cpu, &mut mem, "a $E000");
+        assert_eq!(monitor.assemble_addr, Some(0xE000));
+        monitor.process_user_input(&mut cpu, &mut mem, "LDA #$10");
+        monitor.process_user_input(&mut cpu, &mut mem, "STA $0200,X");
+        monitor.process_user_input(&mut cpu, &mut mem, "bogus");
+        monitor.process_user_input(&mut cpu, &mut mem, "BNE $E000");
+        assert_eq!(monitor.assemble_addr, Some(0xE007));
+        monitor.process_user_input(&mut cpu, &mut mem, "");
+        assert_eq!(monitor.assemble_addr, None);
+
+        // single instruction form stays in command mode
+        monitor.process_user_input(&mut cpu, &mut mem, "a $E007 JMP ($FFFC)");
+        assert_eq!(monitor.assemble_addr, None);
+
+        let code: Vec<u8> = (0xE000..0xE00A).map(|addr| mem.peek(addr)).collect();
+        assert_eq!(code, vec![LDA_IMM.into(), 0x10, STA_ABX.into(), 0x00, 0x02, BNE_REL.into(), 0xF9, JMP_IND.into(), 0xFC, 0xFF]);
+    }
+
+    #[test]
+    fn breakpoints() {
+        let (mut monitor, mut cpu, mut mem) = setup();
+
+        // endless loop of NOPs: E000 NOP, E001 NOP, E002 JMP $E000
+        mem.write_u8(ADDR_RESET_VECTOR, NOP.into());
+        mem.write_u8(None, NOP.into());
+        mem.write_u8(None, JMP_ABS.into());
+        mem.write_u16(None, ADDR_RESET_VECTOR);
+
+        monitor.process_user_input(&mut cpu, &mut mem, "b $E002");
+        monitor.process_user_input(&mut cpu, &mut mem, "b $E001");
+        assert_eq!(monitor.debugger().breakpoints().len(), 2);
+
+        monitor.process_user_input(&mut cpu, &mut mem, "r");
+        assert_eq!(cpu.pc, 0xE001);
+
+        // continuing from a breakpoint executes the instruction at PC first
+        monitor.process_user_input(&mut cpu, &mut mem, "r");
+        assert_eq!(cpu.pc, 0xE002);
+
+        monitor.process_user_input(&mut cpu, &mut mem, "bd 1");
+        monitor.process_user_input(&mut cpu, &mut mem, "r");
+        assert_eq!(cpu.pc, 0xE001);
+
+        monitor.process_user_input(&mut cpu, &mut mem, "bc 2");
+        monitor.process_user_input(&mut cpu, &mut mem, "be 1");
+        assert_eq!(monitor.debugger().breakpoints(), &[Breakpoint { id: 1, addr: 0xE002, enabled: true }]);
+        monitor.process_user_input(&mut cpu, &mut mem, "r");
+        assert_eq!(cpu.pc, 0xE002);
+    }
+
+    #[test]
+    fn instruction_breaks() {
+        let (mut monitor, mut cpu, mut mem) = setup();
+
+        // E000 INX, E001 STA $0200, E004 BRK, E005 (break mark), E006 JMP $E000
+        mem.write_u8(ADDR_RESET_VECTOR, INX.into());
+        mem.write_u8(None, STA_ABS.into());
+        mem.write_u16(None, 0x0200);
+        mem.write_u8(None, BRK.into());
+        mem.write_u8(None, 0xAA);
+        mem.write_u8(None, JMP_ABS.into());
+        mem.write_u16(None, ADDR_RESET_VECTOR);
+
+        monitor.process_user_input(&mut cpu, &mut mem, "break-on sta $00");
+        assert_eq!(monitor.debugger().instruction_breaks(), &[InstructionBreak::Mnemonic(Mnemonic::STA), InstructionBreak::Opcode(BRK)]);
+
+        monitor.process_user_input(&mut cpu, &mut mem, "r");
+        assert_eq!(cpu.pc, 0xE001);
+        monitor.process_user_input(&mut cpu, &mut mem, "r");
+        assert_eq!(cpu.pc, 0xE004);
+
+        // invalid arguments add nothing, nor do instructions the CPU does not have
+        monitor.process_user_input(&mut cpu, &mut mem, "break-on LDA XYZ $02");
+        monitor.process_user_input(&mut cpu, &mut mem, "break-on $DA");
+        assert_eq!(monitor.debugger().instruction_breaks().len(), 2);
+        cpu.set_variant(crate::cpu::Variant::Cmos65C02);
+        monitor.process_user_input(&mut cpu, &mut mem, "break-on $DA");
+        assert_eq!(monitor.debugger().instruction_breaks().len(), 3);
+        monitor.process_user_input(&mut cpu, &mut mem, "break-off $DA");
+
+        monitor.process_user_input(&mut cpu, &mut mem, "break-off STA");
+        assert_eq!(monitor.debugger().instruction_breaks(), &[InstructionBreak::Opcode(BRK)]);
+        monitor.process_user_input(&mut cpu, &mut mem, "break-off");
+        assert!(monitor.debugger().instruction_breaks().is_empty());
+    }
+
+    #[test]
+    fn interrupt_breaks() {
+        let (mut monitor, mut cpu, mut mem) = setup();
+        mem.write_u16(crate::cpu::VECTOR_IRQ, 0xE010);
+        mem.write_u16(crate::cpu::VECTOR_NMI, 0xE020);
+        monitor.process_user_input(&mut cpu, &mut mem, "a $E000 CLI");
+        monitor.process_user_input(&mut cpu, &mut mem, "a $E001 JMP $E001");
+        monitor.process_user_input(&mut cpu, &mut mem, "a $E003 BRK");
+        monitor.process_user_input(&mut cpu, &mut mem, "a $E010 RTI");
+        monitor.process_user_input(&mut cpu, &mut mem, "a $E020 JMP $E003");
+
+        monitor.process_user_input(&mut cpu, &mut mem, "break-int brk nmi");
+        monitor.process_user_input(&mut cpu, &mut mem, "break-int xyz");
+        assert_eq!(monitor.debugger().interrupt_breaks(), vec![Interrupt::Brk, Interrupt::Nmi]);
+
+        // IRQs are not caught; the NMI stops before its handler runs
+        monitor.process_user_input(&mut cpu, &mut mem, "irq on");
+        monitor.process_user_input(&mut cpu, &mut mem, "s 5");
+        monitor.process_user_input(&mut cpu, &mut mem, "irq off");
+        monitor.process_user_input(&mut cpu, &mut mem, "nmi");
+        monitor.process_user_input(&mut cpu, &mut mem, "r");
+        assert_eq!(cpu.pc, 0xE020);
+
+        monitor.process_user_input(&mut cpu, &mut mem, "r");
+        assert_eq!((cpu.pc, cpu.entered_interrupt()), (0xE010, Some(Interrupt::Brk)));
+
+        monitor.process_user_input(&mut cpu, &mut mem, "break-int off");
+        assert!(monitor.debugger().interrupt_breaks().is_empty());
+    }
+
+    #[test]
+    fn break_cycle() {
+        let (mut monitor, mut cpu, mut mem) = setup();
+
+        // E000 INX, E001 JMP $E000 (5 cycles per loop)
+        mem.write_u8(ADDR_RESET_VECTOR, INX.into());
+        mem.write_u8(None, JMP_ABS.into());
+        mem.write_u16(None, ADDR_RESET_VECTOR);
+
+        monitor.process_user_input(&mut cpu, &mut mem, "break-cycle 5");
+        assert_eq!(monitor.debugger().break_cycle(), None);
+
+        let cycle = cpu.cycles + 21;
+        monitor.process_user_input(&mut cpu, &mut mem, &format!("break-cycle {cycle}"));
+        assert_eq!(monitor.debugger().break_cycle(), Some(cycle));
+        monitor.process_user_input(&mut cpu, &mut mem, "r");
+        assert_eq!((cpu.cycles, cpu.x), (cycle + 1, 5));
+        assert_eq!(monitor.debugger().break_cycle(), None);
+
+        monitor.process_user_input(&mut cpu, &mut mem, "break-cycle 1000");
+        monitor.process_user_input(&mut cpu, &mut mem, "break-cycle -");
+        assert_eq!(monitor.debugger().break_cycle(), None);
+    }
+
+    #[test]
+    fn step_over() {
+        let (mut monitor, mut cpu, mut mem) = setup();
+
+        // E000 JSR $E010, E003 NOP; E010 INX, E011 JSR $E020, E014 RTS; E020 INY, E021 RTS
+        mem.write_u8(ADDR_RESET_VECTOR, JSR_ABS.into());
+        mem.write_u16(None, 0xE010);
+        mem.write_u8(None, NOP.into());
+        mem.write_u8(0xE010, INX.into());
+        mem.write_u8(None, JSR_ABS.into());
+        mem.write_u16(None, 0xE020);
+        mem.write_u8(None, RTS.into());
+        mem.write_u8(0xE020, INY.into());
+        mem.write_u8(None, RTS.into());
+
+        monitor.process_user_input(&mut cpu, &mut mem, "n");
+        assert_eq!((cpu.pc, cpu.x, cpu.y), (0xE003, 1, 1));
+
+        // non-JSR instructions are single-stepped
+        monitor.process_user_input(&mut cpu, &mut mem, "n");
+        assert_eq!(cpu.pc, 0xE004);
+
+        // breakpoints inside the subroutine still stop execution
+        cpu.reset(&mut mem);
+        mem.write_u8(ADDR_RESET_VECTOR, JSR_ABS.into());
+        mem.write_u16(None, 0xE010);
+        mem.write_u8(0xE010, INX.into());
+        mem.write_u8(None, JSR_ABS.into());
+        mem.write_u16(None, 0xE020);
+        mem.write_u8(None, RTS.into());
+        mem.write_u8(0xE020, INY.into());
+        mem.write_u8(None, RTS.into());
+        monitor.process_user_input(&mut cpu, &mut mem, "b $E020");
+        monitor.process_user_input(&mut cpu, &mut mem, "n");
+        assert_eq!(cpu.pc, 0xE020);
+    }
+
+    #[test]
+    fn step_out() {
+        let (mut monitor, mut cpu, mut mem) = setup();
+
+        // E000 JSR $E010, E003 NOP; E010 PHA, E011 PLA, E012 JSR $E020, E015 INX, E016 RTS; E020 INY, E021 RTS
+        mem.write_u8(ADDR_RESET_VECTOR, JSR_ABS.into());
+        mem.write_u16(None, 0xE010);
+        mem.write_u8(None, NOP.into());
+        mem.write_u8(0xE010, PHA.into());
+        mem.write_u8(None, PLA.into());
+        mem.write_u8(None, JSR_ABS.into());
+        mem.write_u16(None, 0xE020);
+        mem.write_u8(None, INX.into());
+        mem.write_u8(None, RTS.into());
+        mem.write_u8(0xE020, INY.into());
+        mem.write_u8(None, RTS.into());
+
+        monitor.process_user_input(&mut cpu, &mut mem, "s");
+        monitor.process_user_input(&mut cpu, &mut mem, "s");
+        assert_eq!(cpu.pc, 0xE011);
+
+        // PLA does not count as leaving the subroutine; nested calls are run through
+        monitor.process_user_input(&mut cpu, &mut mem, "finish");
+        assert_eq!((cpu.pc, cpu.x, cpu.y), (0xE003, 1, 1));
+    }
+
+    #[test]
+    fn step_out_interrupted() {
+        let (mut monitor, mut cpu, mut mem) = setup();
+
+        // E000 JSR $E010, E003 JSR $E020, E006 NOP; E010 CLI, E011 INX, E012 RTS;
+        // E020 LDA #$E0, E022 PHA, E023 LDA #$2F, E025 PHA, E026 RTS; E030 INY, E031 RTS
+        // IRQ handler E040 PLA, E041 ORA #$04, E043 PHA, E044 RTI returns with I set
+        mem.write_u8(ADDR_RESET_VECTOR, JSR_ABS.into());
+        mem.write_u16(None, 0xE010);
+        mem.write_u8(None, JSR_ABS.into());
+        mem.write_u16(None, 0xE020);
+        mem.write_u8(None, NOP.into());
+        mem.write_u8(0xE010, CLI.into());
+        mem.write_u8(None, INX.into());
+        mem.write_u8(None, RTS.into());
+        mem.write_u8(0xE020, LDA_IMM.into());
+        mem.write_u8(None, 0xE0);
+        mem.write_u8(None, PHA.into());
+        mem.write_u8(None, LDA_IMM.into());
+        mem.write_u8(None, 0x2F);
+        mem.write_u8(None, PHA.into());
+        mem.write_u8(None, RTS.into());
+        mem.write_u8(0xE030, INY.into());
+        mem.write_u8(None, RTS.into());
+        mem.write_u8(0xE040, PLA.into());
+        mem.write_u8(None, ORA_IMM.into());
+        mem.write_u8(None, 0x04);
+        mem.write_u8(None, PHA.into());
+        mem.write_u8(None, RTI.into());
+        mem.write_u16(crate::cpu::VECTOR_IRQ, 0xE040);
+
+        // the RTI of an IRQ taken inside the subroutine does not leave it
+        monitor.process_user_input(&mut cpu, &mut mem, "s");
+        cpu.set_irq(true);
+        monitor.process_user_input(&mut cpu, &mut mem, "finish");
+        assert_eq!((cpu.pc, cpu.x), (0xE003, 1));
+        assert!(cpu.sr.contains(StatusFlags::I));
+
+        // nor does an RTS to a pushed address
+        monitor.process_user_input(&mut cpu, &mut mem, "s");
+        monitor.process_user_input(&mut cpu, &mut mem, "finish");
+        assert_eq!((cpu.pc, cpu.y), (0xE006, 1));
+    }
+
+    #[test]
+    fn undefined_opcode() {
+        let (mut monitor, mut cpu, mut mem) = setup();
+
+        // E000 INX, E001 undefined $02
+        mem.write_u8(ADDR_RESET_VECTOR, INX.into());
+        mem.write_u8(None, 0x02);
+
+        monitor.process_user_input(&mut cpu, &mut mem, "c 100");
+        assert_eq!((cpu.pc, cpu.x), (0xE001, 1));
+
+        // executing again stops again, whichever command runs
+        for command in ["c 100", "s 5", "n", "finish", "g $E002", "r"] {
+            monitor.process_user_input(&mut cpu, &mut mem, command);
+            assert_eq!(cpu.pc, 0xE001, "{command}");
+        }
+    }
+
+    #[test]
+    fn run_to() {
+        let (mut monitor, mut cpu, mut mem) = setup();
+
+        // E000 INX, E001 JMP $E000
+        mem.write_u8(ADDR_RESET_VECTOR, INX.into());
+        mem.write_u8(None, JMP_ABS.into());
+        mem.write_u16(None, ADDR_RESET_VECTOR);
+
+        monitor.process_user_input(&mut cpu, &mut mem, "g $E001");
+        assert_eq!((cpu.pc, cpu.x), (0xE001, 1));
+
+        // PC already at target: runs a full loop
+        monitor.process_user_input(&mut cpu, &mut mem, "g $E001");
+        assert_eq!((cpu.pc, cpu.x), (0xE001, 2));
+
+        // permanent breakpoints still apply
+        monitor.process_user_input(&mut cpu, &mut mem, "b $E000");
+        monitor.process_user_input(&mut cpu, &mut mem, "g $E001");
+        assert_eq!((cpu.pc, cpu.x), (0xE000, 2));
+    }
+
+    #[test]
+    fn run_for_count() {
+        let (mut monitor, mut cpu, mut mem) = setup();
+
+        // E000 INX, E001 JMP $E000
+        mem.write_u8(ADDR_RESET_VECTOR, INX.into());
+        mem.write_u8(None, JMP_ABS.into());
+        mem.write_u16(None, ADDR_RESET_VECTOR);
+
+        monitor.process_user_input(&mut cpu, &mut mem, "s 5");
+        assert_eq!((cpu.pc, cpu.x), (0xE001, 3));
+
+        monitor.process_user_input(&mut cpu, &mut mem, "s 0");
+        assert_eq!((cpu.pc, cpu.x), (0xE001, 3));
+
+        // INX takes 2 cycles, JMP 3
+        let cycles = cpu.cycles;
+        monitor.process_user_input(&mut cpu, &mut mem, "c 10");
+        assert_eq!((cpu.pc, cpu.x, cpu.cycles - cycles), (0xE001, 5, 10));
+
+        // breakpoints stop counted execution early
+        monitor.process_user_input(&mut cpu, &mut mem, "b $E000");
+        monitor.process_user_input(&mut cpu, &mut mem, "s 100");
+        assert_eq!((cpu.pc, cpu.x), (0xE000, 5));
+        monitor.process_user_input(&mut cpu, &mut mem, "c 1000");
+        assert_eq!((cpu.pc, cpu.x), (0xE000, 6));
+    }
+
+    #[test]
+    fn watchpoints() {
+        let (mut monitor, mut cpu, mut mem) = setup();
+
+        // E000 LDA $0200, E003 STA $0201, E006 JMP $E000
+        mem.write_u8(ADDR_RESET_VECTOR, LDA_ABS.into());
+        mem.write_u16(None, 0x0200);
+        mem.write_u8(None, STA_ABS.into());
+        mem.write_u16(None, 0x0201);
+        mem.write_u8(None, JMP_ABS.into());
+        mem.write_u16(None, ADDR_RESET_VECTOR);
+
+        monitor.process_user_input(&mut cpu, &mut mem, "watch $0201 w");
+        monitor.process_user_input(&mut cpu, &mut mem, "r");
+        assert_eq!(cpu.pc, 0xE006);
+
+        monitor.process_user_input(&mut cpu, &mut mem, "wc 1");
+        monitor.process_user_input(&mut cpu, &mut mem, "watch $0200 $0201 r");
+        monitor.process_user_input(&mut cpu, &mut mem, "r");
+        assert_eq!(cpu.pc, 0xE003);
+        assert_eq!(mem.watchpoints().list().len(), 1);
+    }
+
+    #[test]
+    fn reset() {
+        let (mut monitor, mut cpu, mut mem) = setup();
+
+        // E000 LDX #$05, E002 INX, E003 STX $0200, E006 JMP $E002
+        let program = [LDX_IMM.into(), 0x05, INX.into(), STX_ABS.into(), 0x00, 0x02, JMP_ABS.into(), 0x02, 0xE0];
+        let filename = std::env::temp_dir().join(format!("rust-6502-emu-test-{}.bin", std::process::id()));
+        std::fs::write(&filename, program).unwrap();
+        mem.load_from_file(ADDR_RESET_VECTOR, filename.to_str().unwrap()).unwrap();
+        monitor.set_program_file(filename.to_str().unwrap());
+
+        monitor.process_user_input(&mut cpu, &mut mem, "s 10");
+        monitor.process_user_input(&mut cpu, &mut mem, "w $E001 07");
+        assert_ne!(cpu.pc, ADDR_RESET_VECTOR);
+
+        // memory and the modified program are kept
+        monitor.process_user_input(&mut cpu, &mut mem, "reset");
+        assert_eq!((cpu.pc, cpu.x, cpu.sp), (ADDR_RESET_VECTOR, 0, 0xFD));
+        assert_ne!(mem.peek(0x0200), 0x00);
+        monitor.process_user_input(&mut cpu, &mut mem, "s");
+        assert_eq!(cpu.x, 7);
+        monitor.process_user_input(&mut cpu, &mut mem, "back");
+        assert_eq!(cpu.pc, ADDR_RESET_VECTOR);
+        monitor.process_user_input(&mut cpu, &mut mem, "back");
+        assert_eq!(cpu.pc, ADDR_RESET_VECTOR);
+
+        // memory is cleared and the original program reloaded
+        monitor.process_user_input(&mut cpu, &mut mem, "reset hard");
+        assert_eq!(cpu.pc, ADDR_RESET_VECTOR);
+        assert_eq!(mem.peek(0x0200), 0x00);
+        monitor.process_user_input(&mut cpu, &mut mem, "s");
+        assert_eq!(cpu.x, 5);
+
+        std::fs::remove_file(&filename).unwrap();
+        assert!(monitor.reset(&mut cpu, &mut mem, true).is_err());
+    }
+
+    #[test]
+    fn pc_and_call() {
+        let (mut monitor, mut cpu, mut mem) = setup();
+
+        // E000 NOP; E010 INX, E011 JSR $E020, E014 RTS; E020 INY, E021 RTS
+        mem.write_u8(ADDR_RESET_VECTOR, NOP.into());
+        mem.write_u8(0xE010, INX.into());
+        mem.write_u8(None, JSR_ABS.into());
+        mem.write_u16(None, 0xE020);
+        mem.write_u8(None, RTS.into());
+        mem.write_u8(0xE020, INY.into());
+        mem.write_u8(None, RTS.into());
+
+        monitor.process_user_input(&mut cpu, &mut mem, "pc $E020");
+        assert_eq!(cpu.pc, 0xE020);
+        monitor.process_user_input(&mut cpu, &mut mem, "pc $E000");
+
+        let sp = cpu.sp;
+        monitor.process_user_input(&mut cpu, &mut mem, "call $E010");
+        assert_eq!((cpu.pc, cpu.sp, cpu.x, cpu.y), (0xE000, sp, 1, 1));
+        assert!(cpu.call_stack().is_empty());
+
+        // a breakpoint inside the routine stops with the injected frame still active
+        monitor.process_user_input(&mut cpu, &mut mem, "b $E020");
+        assert!(!monitor.call(&mut cpu, &mut mem, 0xE010));
+        assert_eq!(cpu.pc, 0xE020);
+        assert_eq!(cpu.call_stack()[0].frame, StackFrame::Injected { ret: 0xE000 });
+        monitor.process_user_input(&mut cpu, &mut mem, "finish");
+        monitor.process_user_input(&mut cpu, &mut mem, "finish");
+        assert_eq!((cpu.pc, cpu.sp), (0xE000, sp));
+    }
+}
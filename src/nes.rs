@@ -0,0 +1,399 @@
+// NES (NTSC) with an NROM cartridge, enough for CPU test ROMs using the documented opcodes only, e.g. the first part of
+// nestest; undocumented opcodes stop execution. The 2A03 CPU without decimal mode, and
+//
+//  $0000-$07FF  2K RAM, mirrored up to $1FFF
+//  $2000-$2007  PPU registers, mirrored up to $3FFF; a stub setting the vblank flag ($2002 bit 7) at line 241 and
+//               clearing it at line 261 of 262 lines of 341 dots (3 per cycle), raising NMI during vblank if enabled
+//               ($2000 bit 7). VRAM is accessed through $2006/$2007, but nothing is rendered
+//  $4000-$401F  APU and I/O registers, writes are ignored; OAM DMA ($4014) is not emulated and the controllers ($4016,
+//               $4017) have no buttons pressed
+//  $6000-$7FFF  PRG RAM, where blargg's tests write their result
+//  $8000-$FFFF  PRG ROM of the iNES image given as ROM 'cart' (mapper 0), 16K mirrored or 32K
+
+use std::ops::RangeInclusive;
+
+use crate::cpu::Variant;
+use crate::device::Device;
+use crate::ines::{Ines, Mirroring, PRG_UNIT};
+use crate::machine::{Machine, Profile, RomSlot, Wiring};
+use crate::rom::{Rom, RomImage};
+
+pub const PPU_BASE: u16 = 0x2000;
+pub const APU_BASE: u16 = 0x4000;
+pub const PRG_BASE: u16 = 0x8000;
+pub const TRAINER_ADDR: u16 = 0x7000;
+
+pub const REG_PPUCTRL: u16 = 0;
+pub const REG_PPUMASK: u16 = 1;
+pub const REG_PPUSTATUS: u16 = 2;
+pub const REG_OAMADDR: u16 = 3;
+pub const REG_OAMDATA: u16 = 4;
+pub const REG_PPUSCROLL: u16 = 5;
+pub const REG_PPUADDR: u16 = 6;
+pub const REG_PPUDATA: u16 = 7;
+
+pub const PPUCTRL_NMI: u8 = 0x80;
+const PPUCTRL_INCREMENT_32: u8 = 0x04;
+pub const PPUSTATUS_VBLANK: u8 = 0x80;
+
+const LINE_DOTS: u64 = 341;
+const FRAME_DOTS: u64 = 262 * LINE_DOTS;
+const VBLANK_START: u64 = 241 * LINE_DOTS + 1;
+const VBLANK_END: u64 = 261 * LINE_DOTS + 1;
+const DOTS_PER_CYCLE: u64 = 3;
+
+pub const NES: Profile = Profile {
+    name: "nes",
+    description: "NES (NTSC, NROM): 2A03 CPU, 2K RAM mirrored to $1FFF, PPU stub with vblank and NMI at $2000, iNES image as ROM 'cart' with its PRG ROM at $8000",
+    clock_hz: 1_789_773,
+    frame_cycles: FRAME_DOTS / DOTS_PER_CYCLE,      // 60 Hz
+    roms: &[RomSlot { name: "cart", addr: PRG_BASE, size: 0 }],
+    keyboard: false,
+    wire,
+};
+
+fn wire(machine: &mut Machine, wiring: &mut Wiring) -> Result<(), String> {
+    machine.cpu.set_variant(Variant::Ricoh2A03);
+    machine.attach(Box::new(Ram::create()));
+    machine.attach(Box::new(ApuIo));
+    let Some(image) = wiring.take_rom("cart") else {
+        machine.attach(Box::new(Ppu::create(Vec::new(), Mirroring::Horizontal)));
+        return Ok(());
+    };
+    let ines = Ines::parse(image.bytes()).map_err(|error| format!("cart: {error}"))?;
+    if ines.mapper != 0 {
+        return Err(format!("cart: mapper {} is not supported, only NROM (0)", ines.mapper));
+    }
+    if ines.prg.len() > 2 * PRG_UNIT {
+        return Err(format!("cart: NROM has at most 32K PRG ROM instead of {}K", ines.prg.len() / 1024));
+    }
+    if let Some(trainer) = &ines.trainer {
+        for (addr, byte) in (TRAINER_ADDR..).zip(trainer) {
+            machine.mem.write_u8(addr, *byte);
+        }
+    }
+    let prg = ines.prg.repeat(2 * PRG_UNIT / ines.prg.len());
+    machine.attach(Box::new(Rom::create("cart", PRG_BASE, RomImage::from_bytes(prg))?));
+    machine.attach(Box::new(Ppu::create(ines.chr, ines.mirroring)));
+    Ok(())
+}
+
+// the 2K of internal RAM, mirrored
+struct Ram {
+    data: [u8; 0x800],
+}
+
+impl Ram {
+    fn create() -> Self {
+        Self { data: [0; 0x800] }
+    }
+}
+
+impl Device for Ram {
+    fn name(&self) -> &str {
+        "nes-ram"
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        0x0000..=0x1FFF
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        self.peek(addr)
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.data[addr as usize % 0x800]
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        self.data[addr as usize % 0x800] = value;
+    }
+}
+
+struct ApuIo;
+
+impl Device for ApuIo {
+    fn name(&self) -> &str {
+        "nes-apu-io"
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        APU_BASE..=APU_BASE + 0x1F
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        self.peek(addr)
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x4016 | 0x4017 => 0x40,    // open bus in the upper bits, no button pressed
+            _ => 0x00,
+        }
+    }
+
+    fn write(&mut self, _addr: u16, _value: u8) {}
+}
+
+pub struct Ppu {
+    ctrl: u8,
+    mask: u8,
+    vblank: bool,
+    oam_addr: u8,
+    oam: [u8; 256],
+    scroll: [u8; 2],
+    addr: u16,              // VRAM address
+    second_write: bool,     // to $2005/$2006
+    buffer: u8,             // of $2007 reads
+    latch: u8,              // last value written, read back from write-only registers
+    vram: Vec<u8>,          // pattern tables (CHR ROM, or RAM if none), 4 nametables, palette
+    chr_rom: bool,
+    mirroring: Mirroring,
+    dot: u64,               // into the frame
+    frames: u64,
+}
+
+impl Ppu {
+    pub fn create(chr: Vec<u8>, mirroring: Mirroring) -> Self {
+        let mut vram = vec![0; 0x4000];
+        vram[..chr.len().min(0x2000)].copy_from_slice(&chr[..chr.len().min(0x2000)]);
+        Self {
+            ctrl: 0,
+            mask: 0,
+            vblank: false,
+            oam_addr: 0,
+            oam: [0; 256],
+            scroll: [0; 2],
+            addr: 0,
+            second_write: false,
+            buffer: 0,
+            latch: 0,
+            vram,
+            chr_rom: !chr.is_empty(),
+            mirroring,
+            dot: 0,
+            frames: 0,
+        }
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn scroll(&self) -> (u8, u8) {
+        (self.scroll[0], self.scroll[1])
+    }
+
+    // nametables mirrored as wired on the cartridge, palette entries 0 of the sprite palettes shared with the background
+    fn vram_index(&self, addr: u16) -> usize {
+        let addr = (addr & 0x3FFF) as usize;
+        match addr {
+            0x0000..=0x1FFF => addr,
+            0x2000..=0x3EFF => {
+                let table = (addr - 0x2000) / 0x400 % 4;
+                let table = match self.mirroring {
+                    Mirroring::Horizontal => table / 2,
+                    Mirroring::Vertical => table % 2,
+                    Mirroring::FourScreen => table,
+                };
+                0x2000 + table * 0x400 + addr % 0x400
+            },
+            _ => match addr % 0x20 {
+                index if index % 4 == 0 => 0x3F00 + index % 0x10,
+                index => 0x3F00 + index,
+            },
+        }
+    }
+
+    fn increment(&mut self) {
+        let step = if self.ctrl & PPUCTRL_INCREMENT_32 != 0 { 32 } else { 1 };
+        self.addr = self.addr.wrapping_add(step) & 0x3FFF;
+    }
+}
+
+impl Device for Ppu {
+    fn name(&self) -> &str {
+        "nes-ppu"
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        PPU_BASE..=0x3FFF
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        let value = self.peek(addr);
+        match addr % 8 {
+            REG_PPUSTATUS => {
+                self.vblank = false;
+                self.second_write = false;
+            },
+            REG_PPUDATA => {
+                self.buffer = self.vram[self.vram_index(self.addr)];
+                self.increment();
+            },
+            _ => {},
+        }
+        value
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match addr % 8 {
+            REG_PPUSTATUS => (if self.vblank { PPUSTATUS_VBLANK } else { 0 }) | (self.latch & 0x1F),
+            REG_OAMDATA => self.oam[self.oam_addr as usize],
+            REG_PPUDATA if self.addr >= 0x3F00 => self.vram[self.vram_index(self.addr)],      // palette without delay
+            REG_PPUDATA => self.buffer,
+            _ => self.latch,
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        self.latch = value;
+        match addr % 8 {
+            REG_PPUCTRL => self.ctrl = value,
+            REG_PPUMASK => self.mask = value,
+            REG_OAMADDR => self.oam_addr = value,
+            REG_OAMDATA => {
+                self.oam[self.oam_addr as usize] = value;
+                self.oam_addr = self.oam_addr.wrapping_add(1);
+            },
+            REG_PPUSCROLL => {
+                self.scroll[self.second_write as usize] = value;
+                self.second_write = !self.second_write;
+            },
+            REG_PPUADDR => {
+                self.addr = match self.second_write {
+                    false => (self.addr & 0x00FF) | ((value as u16 & 0x3F) << 8),
+                    true => (self.addr & 0xFF00) | value as u16,
+                };
+                self.second_write = !self.second_write;
+            },
+            REG_PPUDATA => {
+                if self.addr >= 0x2000 || !self.chr_rom {
+                    let index = self.vram_index(self.addr);
+                    self.vram[index] = value;
+                }
+                self.increment();
+            },
+            _ => {},
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        let mut dots = cycles * DOTS_PER_CYCLE;
+        while dots > 0 {
+            let next = match self.dot {
+                dot if dot < VBLANK_START => VBLANK_START,
+                dot if dot < VBLANK_END => VBLANK_END,
+                _ => FRAME_DOTS,
+            };
+            let step = dots.min(next - self.dot);
+            self.dot += step;
+            dots -= step;
+            match self.dot {
+                VBLANK_START => self.vblank = true,
+                VBLANK_END => self.vblank = false,
+                FRAME_DOTS => {
+                    self.dot = 0;
+                    self.frames += 1;
+                },
+                _ => {},
+            }
+        }
+    }
+
+    fn nmi(&self) -> bool {
+        self.vblank && self.ctrl & PPUCTRL_NMI != 0
+    }
+
+    fn reset(&mut self) {
+        self.ctrl = 0;
+        self.mask = 0;
+        self.second_write = false;
+        self.buffer = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ines::CHR_UNIT;
+
+    use super::*;
+
+    // NROM image with 16K PRG ROM, its reset vector pointing to $C000, and 8K CHR ROM
+    fn image(mapper: u8) -> Vec<u8> {
+        let mut bytes = b"NES\x1A".to_vec();
+        bytes.extend([1, 1, 0x01 | mapper << 4, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let mut prg = vec![0xEA; PRG_UNIT];
+        prg[0x3FFC..].copy_from_slice(&[0x00, 0xC0, 0x00, 0x00]);
+        bytes.extend(prg);
+        bytes.extend((0..CHR_UNIT).map(|index| index as u8));
+        bytes
+    }
+
+    fn cart(mapper: u8) -> Result<Machine, String> {
+        let filename = std::env::temp_dir().join(format!("rust-6502-emu-nes-{mapper}-{}.nes", std::process::id()));
+        std::fs::write(&filename, image(mapper)).unwrap();
+        let machine = Machine::create(&NES, Wiring::with_roms(vec![(String::from("cart"), String::from(filename.to_str().unwrap()))]));
+        std::fs::remove_file(&filename).unwrap();
+        machine
+    }
+
+    #[test]
+    fn memory() {
+        let mut machine = cart(0).unwrap();
+        assert_eq!((machine.cpu.pc, machine.cpu.variant()), (0xC000, Variant::Ricoh2A03));
+
+        // RAM mirrored every 2K, PRG ROM every 16K
+        let mem = &mut machine.mem;
+        mem.write_u8(0x0805, 0x42);
+        mem.write_u8(0x8000, 0x00);
+        assert_eq!((mem.peek(0x0005), mem.peek(0x1805), mem.peek(0x8000)), (0x42, 0x42, 0xEA));
+        assert_eq!((mem.peek(0xBFFD), mem.peek(0xFFFD), mem.peek(0x4016)), (0xC0, 0xC0, 0x40));
+
+        assert_eq!(cart(1).err().unwrap(), "cart: mapper 1 is not supported, only NROM (0)");
+        assert!(Machine::create(&NES, Wiring::default()).is_ok());
+    }
+
+    #[test]
+    fn ppu() {
+        let mut ppu = Ppu::create((0..CHR_UNIT).map(|index| index as u8).collect(), Mirroring::Vertical);
+
+        // vblank from line 241 to 261, NMI only if enabled, the flag cleared by reading it
+        ppu.tick((VBLANK_START - 1) / DOTS_PER_CYCLE);
+        assert_eq!(ppu.peek(0x2002), 0x00);
+        ppu.tick(1);
+        assert_eq!((ppu.peek(0x2002), ppu.nmi()), (PPUSTATUS_VBLANK, false));
+        ppu.write(0x2000, PPUCTRL_NMI);
+        assert!(ppu.nmi());
+        assert_eq!(ppu.read(0x3FFA), PPUSTATUS_VBLANK);
+        assert!(!ppu.nmi());
+        ppu.tick(FRAME_DOTS / DOTS_PER_CYCLE + 1);
+        assert_eq!((ppu.frames(), ppu.nmi()), (1, true));
+        ppu.tick((VBLANK_END - VBLANK_START) / DOTS_PER_CYCLE + 1);
+        assert_eq!(ppu.peek(0x2002) & PPUSTATUS_VBLANK, 0);
+
+        // buffered reads from CHR ROM, which ignores writes
+        ppu.write(0x2006, 0x00);
+        ppu.write(0x2006, 0x10);
+        ppu.write(0x2007, 0xFF);
+        assert_eq!((ppu.read(0x2007), ppu.read(0x2007), ppu.read(0x2007)), (0x00, 0x11, 0x12));
+
+        // nametable $2400 is mirrored at $2C00, the palette at $3F20, with a step of 32
+        ppu.write(0x2000, PPUCTRL_INCREMENT_32);
+        ppu.write(0x2006, 0x24);
+        ppu.write(0x2006, 0x00);
+        ppu.write(0x2007, 0x5A);
+        ppu.write(0x2007, 0x5B);
+        ppu.write(0x2006, 0x3F);
+        ppu.write(0x2006, 0x10);
+        ppu.write(0x2007, 0x0F);
+        ppu.write(0x2006, 0x2C);
+        ppu.write(0x2006, 0x20);
+        ppu.read(0x2007);
+        assert_eq!(ppu.read(0x2007), 0x5B);
+        ppu.write(0x2006, 0x3F);
+        ppu.write(0x2006, 0x20);
+        assert_eq!(ppu.read(0x2007), 0x0F);
+    }
+}
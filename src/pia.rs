@@ -0,0 +1,391 @@
+// MOS 6520 / Motorola 6821 Peripheral Interface Adapter
+//
+// Register layout (offset from base address):
+//   +0  PA/DDRA       port A if CRA bit 2 is set (reads clear the CA1/CA2 flags), data direction otherwise
+//   +1  CRA           control register A
+//   +2  PB/DDRB       port B if CRB bit 2 is set (reads clear the CB1/CB2 flags), data direction otherwise
+//   +3  CRB           control register B
+//
+// Control register bits:
+//   7    C1 flag (read-only)
+//   6    C2 flag (read-only), only set while C2 is an input
+//   5-3  C2 as input (bit 5 = 0): bit 4 active edge (1 = positive), bit 3 IRQ enabled;
+//        C2 as output (bit 5 = 1): bit 4 = 1 sets C2 to bit 3; bit 4 = 0 is the handshake (bit 3 = 0) or pulse
+//        (bit 3 = 1) mode, C2 going low on reading PA (writing PB) until the active C1 edge or for a cycle
+//   2    port/DDR select
+//   1    C1 active edge (1 = positive)
+//   0    C1 IRQ enabled
+//
+// IRQA and IRQB are combined into the device's IRQ output.
+
+use std::io::{self, Read, Write};
+use std::ops::RangeInclusive;
+
+use crate::device::Device;
+use crate::state;
+
+pub const PIA_BASE_DEFAULT: u16 = 0xD010;       // as in the Apple-1
+pub const PIA_REGISTERS: u16 = 4;
+
+pub const REG_PA: u16 = 0;
+pub const REG_CRA: u16 = 1;
+pub const REG_PB: u16 = 2;
+pub const REG_CRB: u16 = 3;
+
+pub const CR_C1_IRQ: u8 = 0b00000001;
+pub const CR_C1_POSITIVE: u8 = 0b00000010;
+pub const CR_PORT: u8 = 0b00000100;
+pub const CR_C2_IRQ: u8 = 0b00001000;          // C2 as input
+pub const CR_C2_POSITIVE: u8 = 0b00010000;
+pub const CR_C2_OUTPUT: u8 = 0b00100000;
+pub const CR_C2_FLAG: u8 = 0b01000000;
+pub const CR_C1_FLAG: u8 = 0b10000000;
+
+// one of the two identical halves
+#[derive(Clone, Debug)]
+struct Side {
+    or: u8,
+    ddr: u8,
+    cr: u8,         // flags in bits 7-6
+    pins: u8,       // levels applied from outside
+    c1: bool,
+    c2_in: bool,
+    c2_out: bool,
+    c2_pulse: bool, // low for the current cycle
+}
+
+impl Side {
+    fn create() -> Self {
+        Self {
+            or: 0,
+            ddr: 0,
+            cr: 0,
+            pins: 0xFF,
+            c1: false,
+            c2_in: false,
+            c2_out: true,
+            c2_pulse: false,
+        }
+    }
+
+    fn port(&self) -> u8 {
+        (self.or & self.ddr) | (self.pins & !self.ddr)
+    }
+
+    fn c2_mode(&self) -> u8 {
+        (self.cr >> 3) & 0b111
+    }
+
+    fn read_data(&self) -> u8 {
+        match self.cr & CR_PORT != 0 {
+            true => self.port(),
+            false => self.ddr,
+        }
+    }
+
+    // on reading port A or writing port B
+    fn start_handshake(&mut self) {
+        match self.c2_mode() {
+            0b100 => self.c2_out = false,
+            0b101 => {
+                self.c2_out = false;
+                self.c2_pulse = true;
+            },
+            _ => {},
+        }
+    }
+
+    fn write_cr(&mut self, value: u8) {
+        self.cr = (self.cr & (CR_C1_FLAG | CR_C2_FLAG)) | (value & 0b00111111);
+        if self.cr & CR_C2_OUTPUT != 0 {
+            self.cr &= !CR_C2_FLAG;
+        }
+        match self.c2_mode() {
+            0b110 => self.c2_out = false,
+            0b111 => self.c2_out = true,
+            _ => {},
+        }
+    }
+
+    fn set_c1(&mut self, level: bool) {
+        if level != self.c1 && level == (self.cr & CR_C1_POSITIVE != 0) {
+            self.cr |= CR_C1_FLAG;
+            if self.c2_mode() == 0b100 {
+                self.c2_out = true;     // handshake completed
+            }
+        }
+        self.c1 = level;
+    }
+
+    fn set_c2(&mut self, level: bool) {
+        if self.cr & CR_C2_OUTPUT == 0 && level != self.c2_in && level == (self.cr & CR_C2_POSITIVE != 0) {
+            self.cr |= CR_C2_FLAG;
+        }
+        self.c2_in = level;
+    }
+
+    fn tick(&mut self) {
+        if self.c2_pulse {
+            self.c2_pulse = false;
+            self.c2_out = true;
+        }
+    }
+
+    fn irq(&self) -> bool {
+        (self.cr & CR_C1_FLAG != 0 && self.cr & CR_C1_IRQ != 0)
+            || (self.cr & CR_C2_FLAG != 0 && self.cr & CR_C2_IRQ != 0 && self.cr & CR_C2_OUTPUT == 0)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Pia {
+    name: String,
+    pub base: u16,
+    a: Side,
+    b: Side,
+}
+
+impl Pia {
+    pub fn create(name: &str, base: u16) -> Self {
+        Self {
+            name: String::from(name),
+            base,
+            a: Side::create(),
+            b: Side::create(),
+        }
+    }
+
+    // levels on the port pins
+    pub fn port_a(&self) -> u8 {
+        self.a.port()
+    }
+
+    pub fn port_b(&self) -> u8 {
+        self.b.port()
+    }
+
+    // levels applied to the input pins
+    pub fn set_port_a(&mut self, pins: u8) {
+        self.a.pins = pins;
+    }
+
+    pub fn set_port_b(&mut self, pins: u8) {
+        self.b.pins = pins;
+    }
+
+    // control line inputs, setting their flag on the edge selected in the control register
+    pub fn set_ca1(&mut self, level: bool) {
+        self.a.set_c1(level);
+    }
+
+    pub fn set_ca2(&mut self, level: bool) {
+        self.a.set_c2(level);
+    }
+
+    pub fn set_cb1(&mut self, level: bool) {
+        self.b.set_c1(level);
+    }
+
+    pub fn set_cb2(&mut self, level: bool) {
+        self.b.set_c2(level);
+    }
+
+    // control line outputs; high while C2 is an input
+    pub fn ca2(&self) -> bool {
+        self.a.cr & CR_C2_OUTPUT == 0 || self.a.c2_out
+    }
+
+    pub fn cb2(&self) -> bool {
+        self.b.cr & CR_C2_OUTPUT == 0 || self.b.c2_out
+    }
+
+    pub fn irq_a(&self) -> bool {
+        self.a.irq()
+    }
+
+    pub fn irq_b(&self) -> bool {
+        self.b.irq()
+    }
+
+    fn reg(&self, addr: u16) -> u16 {
+        addr.wrapping_sub(self.base) % PIA_REGISTERS
+    }
+}
+
+impl Device for Pia {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        self.base..=self.base + (PIA_REGISTERS - 1)
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        let value = self.peek(addr);
+        match self.reg(addr) {
+            REG_PA if self.a.cr & CR_PORT != 0 => {
+                self.a.cr &= !(CR_C1_FLAG | CR_C2_FLAG);
+                self.a.start_handshake();
+            },
+            REG_PB if self.b.cr & CR_PORT != 0 => self.b.cr &= !(CR_C1_FLAG | CR_C2_FLAG),
+            _ => {},
+        }
+        value
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match self.reg(addr) {
+            REG_PA => self.a.read_data(),
+            REG_CRA => self.a.cr,
+            REG_PB => self.b.read_data(),
+            _ => self.b.cr,
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match self.reg(addr) {
+            REG_PA if self.a.cr & CR_PORT != 0 => self.a.or = value,
+            REG_PA => self.a.ddr = value,
+            REG_CRA => self.a.write_cr(value),
+            REG_PB if self.b.cr & CR_PORT != 0 => {
+                self.b.or = value;
+                self.b.start_handshake();
+            },
+            REG_PB => self.b.ddr = value,
+            _ => self.b.write_cr(value),
+        }
+    }
+
+    fn tick(&mut self, _cycles: u64) {
+        self.a.tick();
+        self.b.tick();
+    }
+
+    fn irq(&self) -> bool {
+        self.a.irq() || self.b.irq()
+    }
+
+    // registers are cleared; pins keep their levels
+    fn reset(&mut self) {
+        for side in [&mut self.a, &mut self.b] {
+            *side = Side { pins: side.pins, c1: side.c1, c2_in: side.c2_in, ..Side::create() };
+        }
+    }
+
+    fn save_state(&self, writer: &mut dyn Write) -> io::Result<()> {
+        for side in [&self.a, &self.b] {
+            for value in [side.or, side.ddr, side.cr, side.pins] {
+                state::write_u8(writer, value)?;
+            }
+            for value in [side.c1, side.c2_in, side.c2_out, side.c2_pulse] {
+                state::write_bool(writer, value)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn load_state(&mut self, reader: &mut dyn Read) -> io::Result<()> {
+        for side in [&mut self.a, &mut self.b] {
+            for value in [&mut side.or, &mut side.ddr, &mut side.cr, &mut side.pins] {
+                *value = state::read_u8(reader)?;
+            }
+            for value in [&mut side.c1, &mut side.c2_in, &mut side.c2_out, &mut side.c2_pulse] {
+                *value = state::read_bool(reader)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: u16 = PIA_BASE_DEFAULT;
+
+    #[test]
+    fn ports() {
+        let mut pia = Pia::create("pia", BASE);
+
+        // DDR selected after reset
+        pia.write(BASE + REG_PB, 0x7F);
+        pia.write(BASE + REG_CRB, CR_PORT);
+        pia.write(BASE + REG_PB, 0x81);
+        pia.set_port_b(0x00);
+        assert_eq!((pia.port_b(), pia.read(BASE + REG_PB)), (0x01, 0x01));
+        pia.write(BASE + REG_CRB, 0);
+        assert_eq!(pia.peek(BASE + REG_PB), 0x7F);
+
+        pia.write(BASE + REG_CRA, CR_PORT);
+        pia.set_port_a(0xC1);
+        assert_eq!(pia.read(BASE + REG_PA), 0xC1);
+    }
+
+    #[test]
+    fn keyboard_strobe() {
+        // the Apple-1 polls bit 7 of KBDCR, set by the keyboard strobe on CA1, and reads the key from KBD
+        let mut pia = Pia::create("pia", BASE);
+        pia.write(BASE + REG_CRA, CR_PORT | CR_C1_POSITIVE);
+        pia.set_port_a(0x80 | b'A');
+        pia.set_ca1(true);
+        assert_eq!((pia.peek(BASE + REG_CRA) & CR_C1_FLAG, pia.irq()), (CR_C1_FLAG, false));
+        assert_eq!(pia.read(BASE + REG_PA), 0xC1);
+        assert_eq!(pia.peek(BASE + REG_CRA) & CR_C1_FLAG, 0);
+
+        // with interrupts enabled; the negative edge is not active
+        pia.write(BASE + REG_CRA, CR_PORT | CR_C1_POSITIVE | CR_C1_IRQ);
+        pia.set_ca1(false);
+        assert!(!pia.irq());
+        pia.set_ca1(true);
+        assert!(pia.irq() && pia.irq_a() && !pia.irq_b());
+        pia.read(BASE + REG_PA);
+        assert!(!pia.irq());
+    }
+
+    #[test]
+    fn control_line_2() {
+        let mut pia = Pia::create("pia", BASE);
+
+        // input with IRQ on the negative edge
+        pia.write(BASE + REG_CRB, CR_PORT | CR_C2_IRQ);
+        pia.set_cb2(true);
+        assert!(!pia.irq());
+        pia.set_cb2(false);
+        assert_eq!((pia.irq(), pia.peek(BASE + REG_CRB) & CR_C2_FLAG), (true, CR_C2_FLAG));
+        pia.read(BASE + REG_PB);
+        assert!(!pia.irq());
+
+        // manual output
+        pia.write(BASE + REG_CRA, CR_C2_OUTPUT | CR_C2_POSITIVE);
+        assert!(!pia.ca2());
+        pia.write(BASE + REG_CRA, CR_C2_OUTPUT | CR_C2_POSITIVE | CR_C2_IRQ);
+        assert!(pia.ca2());
+
+        // handshake on reading port A: low until the active CA1 edge
+        pia.write(BASE + REG_CRA, CR_PORT | CR_C2_OUTPUT | CR_C1_POSITIVE);
+        pia.read(BASE + REG_PA);
+        assert!(!pia.ca2());
+        pia.tick(1);
+        assert!(!pia.ca2());
+        pia.set_ca1(true);
+        assert!(pia.ca2());
+
+        // pulse on writing port B: low for a cycle
+        pia.write(BASE + REG_CRB, CR_PORT | CR_C2_OUTPUT | CR_C2_IRQ);
+        pia.write(BASE + REG_PB, 0x00);
+        assert!(!pia.cb2());
+        pia.tick(1);
+        assert!(pia.cb2());
+    }
+
+    #[test]
+    fn reset() {
+        let mut pia = Pia::create("pia", BASE);
+        pia.write(BASE + REG_CRA, CR_PORT | CR_C1_IRQ);
+        pia.set_ca1(false);
+        pia.set_port_a(0x12);
+        pia.reset();
+        assert_eq!((pia.peek(BASE + REG_CRA), pia.peek(BASE + REG_PA), pia.port_a()), (0, 0, 0x12));
+    }
+}
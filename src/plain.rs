@@ -0,0 +1,33 @@
+// Stand-in for the styling methods of `colored`, which needs std: text is left unstyled
+
+use core::fmt::Display;
+
+use crate::prelude::*;
+
+pub trait Colorize: Display + Sized {
+    fn bold(self) -> String {
+        self.to_string()
+    }
+
+    fn black(self) -> String {
+        self.to_string()
+    }
+
+    fn yellow(self) -> String {
+        self.to_string()
+    }
+
+    fn bright_black(self) -> String {
+        self.to_string()
+    }
+
+    fn bright_blue(self) -> String {
+        self.to_string()
+    }
+
+    fn on_yellow(self) -> String {
+        self.to_string()
+    }
+}
+
+impl<T: Display> Colorize for T {}
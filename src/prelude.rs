@@ -0,0 +1,6 @@
+// what the std prelude provides beyond core's, for the modules also built without std
+pub use alloc::borrow::ToOwned;
+pub use alloc::boxed::Box;
+pub use alloc::string::{String, ToString};
+pub use alloc::vec::Vec;
+pub use alloc::{format, vec};
@@ -0,0 +1,117 @@
+use alloc::collections::BTreeMap;
+
+use crate::symbols::SymbolTable;
+use crate::prelude::*;
+
+const ADDRESSES: usize = 0x10000;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ProfileEntry {
+    pub addr: u16,
+    pub cycles: u64,
+    pub executions: u64,
+}
+
+// cycles spent per routine, i.e. all addresses from a symbol up to the next one
+#[derive(Clone, PartialEq, Debug)]
+pub struct RoutineEntry {
+    pub name: String,
+    pub addr: u16,
+    pub cycles: u64,
+    pub executions: u64,
+}
+
+// per-address cycle counters, attributed to the address of the executed instruction
+pub struct Profile {
+    cycles: Vec<u64>,
+    executions: Vec<u64>,
+}
+
+impl Profile {
+    pub fn create() -> Self {
+        Self {
+            cycles: vec![0; ADDRESSES],
+            executions: vec![0; ADDRESSES],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.cycles.fill(0);
+        self.executions.fill(0);
+    }
+
+    pub fn record(&mut self, pc: u16, cycles: u64) {
+        self.cycles[pc as usize] = self.cycles[pc as usize].saturating_add(cycles);
+        self.executions[pc as usize] = self.executions[pc as usize].saturating_add(1);
+    }
+
+    pub fn total_cycles(&self) -> u64 {
+        self.cycles.iter().fold(0u64, |total, cycles| total.saturating_add(*cycles))
+    }
+
+    // all executed addresses in ascending order
+    pub fn entries(&self) -> impl Iterator<Item = ProfileEntry> + '_ {
+        (0..=0xFFFF)
+            .map(|addr| ProfileEntry { addr, cycles: self.cycles[addr as usize], executions: self.executions[addr as usize] })
+            .filter(|entry| entry.executions > 0)
+    }
+
+    // the `count` addresses with the most cycles, hottest first
+    pub fn hottest(&self, count: usize) -> Vec<ProfileEntry> {
+        let mut entries: Vec<ProfileEntry> = self.entries().collect();
+        entries.sort_by(|a, b| b.cycles.cmp(&a.cycles).then(a.addr.cmp(&b.addr)));
+        entries.truncate(count);
+        entries
+    }
+
+    // the `count` routines with the most cycles; addresses below the first symbol are not included
+    pub fn hottest_routines(&self, symbols: &SymbolTable, count: usize) -> Vec<RoutineEntry> {
+        let mut routines: BTreeMap<u16, RoutineEntry> = BTreeMap::new();
+        for entry in self.entries() {
+            let Some((name, offset)) = symbols.nearest(entry.addr) else {
+                continue;
+            };
+            let addr = entry.addr - offset;
+            let routine = routines.entry(addr).or_insert_with(|| RoutineEntry { name: String::from(name), addr, cycles: 0, executions: 0 });
+            routine.cycles = routine.cycles.saturating_add(entry.cycles);
+            routine.executions = routine.executions.saturating_add(entry.executions);
+        }
+
+        let mut routines: Vec<RoutineEntry> = routines.into_values().collect();
+        routines.sort_by(|a, b| b.cycles.cmp(&a.cycles).then(a.addr.cmp(&b.addr)));
+        routines.truncate(count);
+        routines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hottest() {
+        let mut profile = Profile::create();
+        profile.record(0xE000, 2);
+        profile.record(0xE002, 6);
+        profile.record(0xE002, 6);
+        profile.record(0xE100, 4);
+        profile.record(0xE101, 3);
+
+        assert_eq!(profile.total_cycles(), 21);
+        assert_eq!(profile.hottest(2), vec![
+            ProfileEntry { addr: 0xE002, cycles: 12, executions: 2 },
+            ProfileEntry { addr: 0xE100, cycles: 4, executions: 1 },
+        ]);
+
+        let mut symbols = SymbolTable::default();
+        symbols.add("main", 0xE000);
+        symbols.add("print", 0xE100);
+        let routines = profile.hottest_routines(&symbols, 10);
+        assert_eq!(routines.len(), 2);
+        assert_eq!((routines[0].name.as_str(), routines[0].cycles, routines[0].executions), ("main", 14, 3));
+        assert_eq!((routines[1].name.as_str(), routines[1].cycles, routines[1].executions), ("print", 7, 2));
+
+        profile.clear();
+        assert!(profile.hottest(2).is_empty());
+    }
+}
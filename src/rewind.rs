@@ -0,0 +1,210 @@
+use std::collections::VecDeque;
+use std::io;
+
+use crate::cpu::{Cpu, Snapshot};
+use crate::mem::{Journal, Memory};
+use crate::state;
+
+// recently executed instructions, each as the CPU state before execution and the memory writes it made
+pub struct Rewind {
+    capacity: usize,
+    steps: VecDeque<(Snapshot, Journal)>,
+}
+
+impl Rewind {
+    pub fn create(capacity: usize) -> Self {
+        Self { capacity, steps: VecDeque::new() }
+    }
+
+    // executes `f` (usually a single instruction) so that it can be undone with `back`
+    pub fn record<T>(&mut self, cpu: &mut Cpu, mem: &mut Memory, f: impl FnOnce(&mut Cpu, &mut Memory) -> T) -> T {
+        let snapshot = cpu.snapshot();
+        mem.start_journal();
+        let result = f(cpu, mem);
+        let journal = mem.take_journal();
+
+        if self.capacity > 0 {
+            if self.steps.len() == self.capacity {
+                self.steps.pop_front();
+            }
+            self.steps.push_back((snapshot, journal));
+        }
+        result
+    }
+
+    // undoes up to `count` recorded steps, most recent first; returns the number of steps undone
+    pub fn back(&mut self, cpu: &mut Cpu, mem: &mut Memory, count: usize) -> usize {
+        let mut undone = 0;
+        while undone < count {
+            let Some((snapshot, journal)) = self.steps.pop_back() else {
+                break;
+            };
+            mem.undo(journal);
+            cpu.restore(snapshot);
+            undone += 1;
+        }
+        undone
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.steps.clear();
+    }
+}
+
+// complete machine states saved every `interval` cycles, reaching back further than the instruction history
+pub struct Checkpoints {
+    interval: u64,
+    capacity: usize,
+    states: VecDeque<(u64, Vec<u8>)>,  // cycle counter and saved state, oldest first
+}
+
+impl Checkpoints {
+    pub fn create(interval: u64, capacity: usize) -> Self {
+        Self { interval: interval.max(1), capacity, states: VecDeque::new() }
+    }
+
+    // saves the machine if `interval` cycles have passed since the last checkpoint; returns true if saved.
+    // Checkpoints ahead of the cycle counter (e.g. after stepping back) no longer belong to this run and are dropped.
+    pub fn capture(&mut self, cpu: &Cpu, mem: &Memory) -> io::Result<bool> {
+        while self.states.back().is_some_and(|(cycles, _)| *cycles > cpu.cycles) {
+            self.states.pop_back();
+        }
+        if self.capacity == 0 || self.states.back().is_some_and(|(cycles, _)| cpu.cycles < cycles + self.interval) {
+            return Ok(false);
+        }
+
+        let mut state = Vec::new();
+        state::save(cpu, mem, &mut state)?;
+        if self.states.len() == self.capacity {
+            self.states.pop_front();
+        }
+        self.states.push_back((cpu.cycles, state));
+        Ok(true)
+    }
+
+    // restores the latest checkpoint at or before `cycle` and drops later ones; returns the cycle of the checkpoint
+    pub fn restore(&mut self, cpu: &mut Cpu, mem: &mut Memory, cycle: u64) -> io::Result<Option<u64>> {
+        while self.states.back().is_some_and(|(cycles, _)| *cycles > cycle) {
+            self.states.pop_back();
+        }
+        match self.states.back() {
+            Some((cycles, state)) => {
+                state::load(cpu, mem, &mut state.as_slice())?;
+                Ok(Some(*cycles))
+            },
+            None => Ok(None),
+        }
+    }
+
+    pub fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    // cycle of the oldest checkpoint
+    pub fn oldest(&self) -> Option<u64> {
+        self.states.front().map(|(cycles, _)| *cycles)
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.states.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instruction::Opcode::*;
+    use crate::mem::ADDR_RESET_VECTOR;
+
+    use super::*;
+
+    #[test]
+    fn back() {
+        let mut mem = Memory::create();
+        let mut cpu = Cpu::create();
+        cpu.reset(&mut mem);
+
+        // E000 LDA #$42, E002 STA $0200, E005 JSR $E00A, E00A INC $0200
+        mem.write_u8(ADDR_RESET_VECTOR, LDA_IMM.into());
+        mem.write_u8(None, 0x42);
+        mem.write_u8(None, STA_ABS.into());
+        mem.write_u16(None, 0x0200);
+        mem.write_u8(None, JSR_ABS.into());
+        mem.write_u16(None, 0xE00A);
+        mem.write_u8(0xE00A, INC_ABS.into());
+        mem.write_u16(None, 0x0200);
+
+        let mut rewind = Rewind::create(3);
+        let (cycles, sp) = (cpu.cycles, cpu.sp);
+        for _ in 0..4 {
+            rewind.record(&mut cpu, &mut mem, |cpu, mem| cpu.exec(mem, 1));
+        }
+        assert_eq!((cpu.pc, mem.peek(0x0200), cpu.call_stack().len()), (0xE00D, 0x43, 1));
+        assert_eq!(rewind.len(), 3);
+
+        assert_eq!(rewind.back(&mut cpu, &mut mem, 1), 1);
+        assert_eq!((cpu.pc, mem.peek(0x0200), cpu.call_stack().len()), (0xE00A, 0x42, 1));
+
+        // undoing the JSR also restores the stack contents
+        assert_eq!(rewind.back(&mut cpu, &mut mem, 2), 2);
+        assert_eq!((cpu.pc, cpu.ac, cpu.sp, mem.peek(0x0200)), (0xE002, 0x42, sp, 0x00));
+        assert!(!mem.is_initialized(0x0200));
+        assert!(!mem.is_initialized(0x01FF));
+        assert!(cpu.call_stack().is_empty());
+
+        // the first instruction dropped out of the buffer
+        assert_eq!(rewind.back(&mut cpu, &mut mem, 5), 0);
+        assert!(cpu.cycles > cycles);
+    }
+
+    #[test]
+    fn checkpoints() {
+        let mut mem = Memory::create();
+        let mut cpu = Cpu::create();
+        cpu.reset(&mut mem);
+
+        // E000 INC $0200, E003 JMP $E000; 9 cycles per loop
+        mem.write_u8(ADDR_RESET_VECTOR, INC_ABS.into());
+        mem.write_u16(None, 0x0200);
+        mem.write_u8(None, JMP_ABS.into());
+        mem.write_u16(None, ADDR_RESET_VECTOR);
+
+        let mut checkpoints = Checkpoints::create(90, 3);
+        while cpu.cycles < 1000 {
+            checkpoints.capture(&cpu, &mem).unwrap();
+            cpu.exec(&mut mem, 1);
+        }
+        assert_eq!(checkpoints.len(), 3);
+        assert_eq!(checkpoints.oldest(), Some(817));
+
+        // back to the checkpoint at cycle 907, i.e. after 100 loops
+        assert_eq!(checkpoints.restore(&mut cpu, &mut mem, 950).unwrap(), Some(907));
+        assert_eq!((cpu.cycles, cpu.pc, mem.peek(0x0200)), (907, ADDR_RESET_VECTOR, 100));
+        assert_eq!(checkpoints.restore(&mut cpu, &mut mem, 850).unwrap(), Some(817));
+        assert_eq!(checkpoints.len(), 1);
+        assert_eq!(checkpoints.restore(&mut cpu, &mut mem, 800).unwrap(), None);
+        assert!(checkpoints.is_empty());
+
+        // continuing from an earlier cycle replaces the checkpoints ahead of it
+        let mut checkpoints = Checkpoints::create(90, 3);
+        checkpoints.capture(&cpu, &mem).unwrap();
+        cpu.cycles = 0;
+        assert!(checkpoints.capture(&cpu, &mem).unwrap());
+        assert_eq!(checkpoints.oldest(), Some(0));
+    }
+}
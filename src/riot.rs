@@ -0,0 +1,256 @@
+// MOS 6532 RAM-I/O-Timer (RIOT): two 8-bit ports and an interval timer. Its 128 bytes of RAM are selected by a
+// separate pin (RS) and mapped by the machine.
+//
+// Register layout (offset from base address, A4-A0):
+//   +00  ORA           port A; reads output register bits where DDRA is 1 and the input pins where it is 0
+//   +01  DDRA          data direction of port A (1 = output)
+//   +02  ORB           port B
+//   +03  DDRB
+//   +04  TIMER         read: the timer; bit 3 of the address enables the timer interrupt, reading clears its flag
+//   +05  FLAGS         read: bit 7 timer expired, bit 6 PA7 edge (not emulated)
+//   +04  EDGE CONTROL  write with A4 = 0: PA7 edge detection, stored without effect
+//   +14  TIM1T         write: starts the timer with an interval of 1, 8, 64 or 1024 cycles; bit 3 of the address
+//   +15  TIM8T                enables the timer interrupt
+//   +16  TIM64T
+//   +17  T1024T
+//
+// The timer is decremented one cycle after being written and then once per interval. When it passes zero its flag is
+// set and it keeps counting down from $FF once per cycle until written again.
+
+use std::io::{self, Read, Write};
+use std::ops::RangeInclusive;
+
+use crate::device::Device;
+use crate::state;
+
+pub const RIOT_REGISTERS: u16 = 32;
+
+pub const REG_ORA: u16 = 0x00;
+pub const REG_DDRA: u16 = 0x01;
+pub const REG_ORB: u16 = 0x02;
+pub const REG_DDRB: u16 = 0x03;
+pub const REG_TIMER: u16 = 0x04;
+pub const REG_FLAGS: u16 = 0x05;
+pub const REG_TIM1T: u16 = 0x14;
+pub const REG_TIM8T: u16 = 0x15;
+pub const REG_TIM64T: u16 = 0x16;
+pub const REG_T1024T: u16 = 0x17;
+
+pub const TIMER_IRQ: u16 = 0x08;        // address bit enabling the timer interrupt
+
+pub const FLAG_TIMER: u8 = 0b10000000;
+
+const INTERVALS: [u64; 4] = [1, 8, 64, 1024];
+
+#[derive(Clone, Debug)]
+pub struct Riot {
+    name: String,
+    pub base: u16,
+    ora: u8,
+    ddra: u8,
+    orb: u8,
+    ddrb: u8,
+    pins_a: u8,             // levels applied to the port pins from outside
+    pins_b: u8,
+    edge: u8,
+    timer: u8,
+    interval: u64,
+    countdown: u64,         // cycles until the next decrement
+    expired: bool,          // passed zero, counting once per cycle
+    flag: bool,
+    irq_enabled: bool,
+}
+
+impl Riot {
+    pub fn create(name: &str, base: u16) -> Self {
+        Self {
+            name: String::from(name),
+            base,
+            ora: 0,
+            ddra: 0,
+            orb: 0,
+            ddrb: 0,
+            pins_a: 0xFF,       // inputs are pulled up
+            pins_b: 0xFF,
+            edge: 0,
+            timer: 0,
+            interval: INTERVALS[3],
+            countdown: INTERVALS[3],
+            expired: false,
+            flag: false,
+            irq_enabled: false,
+        }
+    }
+
+    // levels on the port pins: driven by the output register where configured as output, from outside otherwise
+    pub fn port_a(&self) -> u8 {
+        (self.ora & self.ddra) | (self.pins_a & !self.ddra)
+    }
+
+    pub fn port_b(&self) -> u8 {
+        (self.orb & self.ddrb) | (self.pins_b & !self.ddrb)
+    }
+
+    // levels applied to the input pins
+    pub fn set_port_a(&mut self, pins: u8) {
+        self.pins_a = pins;
+    }
+
+    pub fn set_port_b(&mut self, pins: u8) {
+        self.pins_b = pins;
+    }
+
+    fn reg(&self, addr: u16) -> u16 {
+        addr.wrapping_sub(self.base) % RIOT_REGISTERS
+    }
+}
+
+impl Device for Riot {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        self.base..=self.base + (RIOT_REGISTERS - 1)
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        let reg = self.reg(addr);
+        if reg & 0x05 == REG_TIMER {
+            self.flag = false;
+            self.irq_enabled = reg & TIMER_IRQ != 0;
+        }
+        self.peek(addr)
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match self.reg(addr) {
+            reg if reg & 0x04 == 0 => match reg & 0x03 {
+                REG_ORA => self.port_a(),
+                REG_DDRA => self.ddra,
+                REG_ORB => self.port_b(),
+                _ => self.ddrb,
+            },
+            reg if reg & 0x01 == 0 => self.timer,
+            _ => if self.flag { FLAG_TIMER } else { 0 },
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match self.reg(addr) {
+            reg if reg & 0x04 == 0 => match reg & 0x03 {
+                REG_ORA => self.ora = value,
+                REG_DDRA => self.ddra = value,
+                REG_ORB => self.orb = value,
+                _ => self.ddrb = value,
+            },
+            reg if reg & 0x10 != 0 => {
+                self.timer = value;
+                self.interval = INTERVALS[(reg & 0x03) as usize];
+                self.countdown = 1;
+                self.expired = false;
+                self.flag = false;
+                self.irq_enabled = reg & TIMER_IRQ != 0;
+            },
+            _ => self.edge = value,
+        }
+    }
+
+    fn tick(&mut self, mut cycles: u64) {
+        while cycles > 0 {
+            if self.expired {
+                self.timer = self.timer.wrapping_sub(cycles as u8);
+                return;
+            }
+            let step = cycles.min(self.countdown);
+            self.countdown -= step;
+            cycles -= step;
+            if self.countdown == 0 {
+                if self.timer == 0 {
+                    self.expired = true;
+                    self.flag = true;
+                }
+                self.timer = self.timer.wrapping_sub(1);
+                self.countdown = self.interval;
+            }
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.flag && self.irq_enabled
+    }
+
+    // ports and interrupt enable are cleared; the timer keeps running and the pins keep their levels
+    fn reset(&mut self) {
+        (self.ora, self.ddra, self.orb, self.ddrb) = (0, 0, 0, 0);
+        self.edge = 0;
+        self.irq_enabled = false;
+    }
+
+    fn save_state(&self, writer: &mut dyn Write) -> io::Result<()> {
+        for value in [self.ora, self.ddra, self.orb, self.ddrb, self.pins_a, self.pins_b, self.edge, self.timer] {
+            state::write_u8(writer, value)?;
+        }
+        state::write_u64(writer, self.interval)?;
+        state::write_u64(writer, self.countdown)?;
+        for value in [self.expired, self.flag, self.irq_enabled] {
+            state::write_bool(writer, value)?;
+        }
+        Ok(())
+    }
+
+    fn load_state(&mut self, reader: &mut dyn Read) -> io::Result<()> {
+        for value in [&mut self.ora, &mut self.ddra, &mut self.orb, &mut self.ddrb, &mut self.pins_a, &mut self.pins_b, &mut self.edge, &mut self.timer] {
+            *value = state::read_u8(reader)?;
+        }
+        self.interval = state::read_u64(reader)?;
+        self.countdown = state::read_u64(reader)?;
+        for value in [&mut self.expired, &mut self.flag, &mut self.irq_enabled] {
+            *value = state::read_bool(reader)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: u16 = 0x0280;
+
+    #[test]
+    fn ports() {
+        let mut riot = Riot::create("riot", BASE);
+        riot.set_port_a(0x7F);
+        riot.write(BASE + REG_DDRB, 0xF0);
+        riot.write(BASE + REG_ORB, 0x5A);
+        riot.set_port_b(0x0B);
+        assert_eq!((riot.read(BASE + REG_ORA), riot.port_b(), riot.peek(BASE + REG_DDRB)), (0x7F, 0x5B, 0xF0));
+        riot.reset();
+        assert_eq!((riot.peek(BASE + REG_ORB), riot.peek(BASE + REG_ORA + RIOT_REGISTERS)), (0x0B, 0x7F));
+    }
+
+    #[test]
+    fn timer() {
+        let mut riot = Riot::create("riot", BASE);
+
+        // decremented one cycle after being written, then once per 8 cycles
+        riot.write(BASE + (REG_TIM8T | TIMER_IRQ), 3);
+        riot.tick(1);
+        assert_eq!(riot.peek(BASE + REG_TIMER), 2);
+        riot.tick(16);
+        assert_eq!((riot.peek(BASE + REG_TIMER), riot.peek(BASE + REG_FLAGS), riot.irq()), (0, 0, false));
+
+        // passing zero sets the flag, then the timer counts once per cycle
+        riot.tick(8);
+        assert_eq!((riot.peek(BASE + REG_TIMER), riot.peek(BASE + REG_FLAGS), riot.irq()), (0xFF, FLAG_TIMER, true));
+        riot.tick(5);
+        assert_eq!(riot.read(BASE + REG_TIMER), 0xFA);
+        assert_eq!((riot.peek(BASE + REG_FLAGS), riot.irq()), (0, false));
+
+        // T1024T without interrupt
+        riot.write(BASE + REG_T1024T, 1);
+        riot.tick(1 + 2 * 1024);
+        assert_eq!((riot.peek(BASE + REG_TIMER), riot.peek(BASE + REG_FLAGS), riot.irq()), (0xFF, FLAG_TIMER, false));
+    }
+}
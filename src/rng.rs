@@ -0,0 +1,106 @@
+// Random number generator: each read returns a new pseudo-random byte, e.g. at $FE as in easy6502. The sequence is
+// determined by the seed (SplitMix64); writing a byte reseeds the generator with it.
+
+use std::ops::RangeInclusive;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::device::Device;
+
+pub const RNG_ADDR_DEFAULT: u16 = 0x00FE;
+
+#[derive(Clone, Debug)]
+pub struct Rng {
+    name: String,
+    pub addr: u16,
+    seed: u64,
+    state: u64,
+    value: u8,      // returned by the next read
+}
+
+impl Rng {
+    pub fn create(name: &str, addr: u16, seed: u64) -> Self {
+        let mut rng = Self {
+            name: String::from(name),
+            addr,
+            seed,
+            state: seed,
+            value: 0,
+        };
+        rng.advance();
+        rng
+    }
+
+    // a seed differing from run to run
+    pub fn seed_from_time() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64)
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    fn advance(&mut self) {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        self.value = ((z ^ (z >> 31)) >> 56) as u8;
+    }
+}
+
+impl Device for Rng {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        self.addr..=self.addr
+    }
+
+    fn read(&mut self, _addr: u16) -> u8 {
+        let value = self.value;
+        self.advance();
+        value
+    }
+
+    fn peek(&self, _addr: u16) -> u8 {
+        self.value
+    }
+
+    fn write(&mut self, _addr: u16, value: u8) {
+        *self = Self::create(&self.name, self.addr, value as u64);
+    }
+
+    // starts the sequence again
+    fn reset(&mut self) {
+        *self = Self::create(&self.name, self.addr, self.seed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(rng: &mut Rng, count: usize) -> Vec<u8> {
+        (0..count).map(|_| rng.read(RNG_ADDR_DEFAULT)).collect()
+    }
+
+    #[test]
+    fn sequence() {
+        let mut rng = Rng::create("rng", RNG_ADDR_DEFAULT, 42);
+        let first = bytes(&mut rng, 256);
+        assert!(first.iter().collect::<std::collections::HashSet<_>>().len() > 128, "not spread");
+
+        // the same seed gives the same sequence, peeking doesn't advance it
+        let mut other = Rng::create("rng", RNG_ADDR_DEFAULT, 42);
+        assert_eq!(other.peek(RNG_ADDR_DEFAULT), first[0]);
+        assert_eq!(bytes(&mut other, 256), first);
+        rng.reset();
+        assert_eq!(bytes(&mut rng, 256), first);
+        assert_ne!(bytes(&mut Rng::create("rng", RNG_ADDR_DEFAULT, 43), 256), first);
+
+        // reseeding by a write
+        rng.write(RNG_ADDR_DEFAULT, 7);
+        assert_eq!((rng.seed(), bytes(&mut rng, 16)), (7, bytes(&mut Rng::create("rng", RNG_ADDR_DEFAULT, 7), 16)));
+    }
+}
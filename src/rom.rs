@@ -0,0 +1,138 @@
+use core::ops::RangeInclusive;
+#[cfg(feature = "std")]
+use std::fs::File;
+#[cfg(feature = "std")]
+use std::io::Error;
+
+#[cfg(feature = "std")]
+use memmap2::Mmap;
+
+use crate::device::Device;
+use crate::prelude::*;
+
+pub const ROM_BASE_DEFAULT: u16 = 0x8000;               // 0x8000 to 0xBFFF bank window
+pub const ROM_BANK_SIZE_DEFAULT: u16 = 0x4000;          // 16K banks
+pub const ROM_BANK_SELECT_DEFAULT: u16 = 0xDFFE;        // writing selects the bank shown in the window
+
+pub(crate) const OPEN_BUS: u8 = 0xFF;                   // read from a bank beyond the end of the image
+
+// ROM image memory-mapped from disk, or built in memory (e.g. from cartridge chips); banks are served directly from it
+pub struct RomImage {
+    data: RomData,
+}
+
+enum RomData {
+    #[cfg(feature = "std")]
+    Mapped(Mmap),
+    Owned(Vec<u8>),
+}
+
+impl RomImage {
+    #[cfg(feature = "std")]
+    pub fn open(filename: &str) -> Result<Self, Error> {
+        let file = File::open(filename)?;
+
+        // SAFETY: the mapping is read-only; the image file is not expected to be modified while running
+        let mmap = unsafe { Mmap::map(&file)? };
+
+        Ok(Self { data: RomData::Mapped(mmap) })
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self { data: RomData::Owned(bytes) }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        match &self.data {
+            #[cfg(feature = "std")]
+            RomData::Mapped(mmap) => mmap,
+            RomData::Owned(bytes) => bytes,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes().is_empty()
+    }
+
+    pub fn read(&self, offset: usize) -> u8 {
+        self.bytes().get(offset).copied().unwrap_or(OPEN_BUS)
+    }
+}
+
+// bank-switched window onto a ROM image
+pub struct BankedRom {
+    image: RomImage,
+    pub base: u16,
+    pub bank_size: u16,
+    pub bank_select: u16,
+    bank: usize,
+}
+
+impl BankedRom {
+    pub fn create(image: RomImage, base: u16, bank_size: u16, bank_select: u16) -> Self {
+        if bank_size == 0 {
+            panic!("Bank size must not be zero");
+        }
+        Self { image, base, bank_size, bank_select, bank: 0 }
+    }
+
+    pub fn banks(&self) -> usize {
+        self.image.len().div_ceil(self.bank_size as usize)
+    }
+
+    pub fn bank(&self) -> usize {
+        self.bank
+    }
+
+    pub fn select_bank(&mut self, bank: usize) {
+        self.bank = bank;
+    }
+
+    pub fn maps(&self, addr: u16) -> bool {
+        addr >= self.base && addr - self.base < self.bank_size
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        self.image.read(self.bank * self.bank_size as usize + (addr - self.base) as usize)
+    }
+}
+
+// ROM image at a fixed address, e.g. a machine's system ROM; writes are ignored
+pub struct Rom {
+    name: String,
+    pub base: u16,
+    image: RomImage,
+}
+
+impl Rom {
+    pub fn create(name: &str, base: u16, image: RomImage) -> Result<Self, String> {
+        if image.is_empty() || base as usize + image.len() > 0x10000 {
+            return Err(format!("ROM '{name}' of {} bytes does not fit at ${base:04X}", image.len()));
+        }
+        Ok(Self { name: String::from(name), base, image })
+    }
+}
+
+impl Device for Rom {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        self.base..=(self.base as usize + self.image.len() - 1) as u16
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        self.peek(addr)
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.image.read(addr.wrapping_sub(self.base) as usize)
+    }
+
+    fn write(&mut self, _addr: u16, _value: u8) {}
+}
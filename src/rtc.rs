@@ -0,0 +1,228 @@
+// Real-time clock with the date and time (UTC) in binary registers
+//
+// Register layout (offset from base address):
+//   +0  seconds       0-59; reading it latches the time into all registers, so they don't change while being read
+//   +1  minutes       0-59
+//   +2  hours         0-23
+//   +3  day           1-31
+//   +4  month         1-12
+//   +5  year LB
+//   +6  year HB
+//   +7  weekday       0-6 from Sunday (read-only)
+//
+// Writing a register sets that part of the time, keeping the others as latched. The clock either follows the host's
+// clock or advances with the emulated cycles from 2000-01-01 00:00:00, which makes runs reproducible.
+
+use std::ops::RangeInclusive;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::device::Device;
+
+pub const RTC_BASE_DEFAULT: u16 = 0xDF10;
+pub const RTC_REGISTERS: u16 = 8;
+pub const RTC_CLOCK_HZ_DEFAULT: u64 = 1_000_000;
+
+pub const REG_SECONDS: u16 = 0;
+pub const REG_MINUTES: u16 = 1;
+pub const REG_HOURS: u16 = 2;
+pub const REG_DAY: u16 = 3;
+pub const REG_MONTH: u16 = 4;
+pub const REG_YEAR_LB: u16 = 5;
+pub const REG_YEAR_HB: u16 = 6;
+pub const REG_WEEKDAY: u16 = 7;
+
+const EMULATED_EPOCH: i64 = 946684800;         // 2000-01-01 00:00:00 in seconds since 1970
+const SECONDS_PER_DAY: i64 = 86400;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RtcClock {
+    Host,
+    Cycles { hz: u64 },     // emulated, at the given CPU clock
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u8,
+    pub day: u8,
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub weekday: u8,
+}
+
+impl DateTime {
+    // from seconds since 1970-01-01 00:00:00, with the civil calendar algorithms by Howard Hinnant
+    pub fn from_timestamp(timestamp: i64) -> Self {
+        let (days, time) = (timestamp.div_euclid(SECONDS_PER_DAY), timestamp.rem_euclid(SECONDS_PER_DAY));
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        Self {
+            year: yoe + era * 400 + (month <= 2) as i64,
+            month: month as u8,
+            day: (doy - (153 * mp + 2) / 5 + 1) as u8,
+            hours: (time / 3600) as u8,
+            minutes: (time / 60 % 60) as u8,
+            seconds: (time % 60) as u8,
+            weekday: (days + 4).rem_euclid(7) as u8,     // 1970-01-01 was a Thursday
+        }
+    }
+
+    // out-of-range values carry over, e.g. minute 60 is the next hour
+    pub fn timestamp(&self) -> i64 {
+        let month = self.month.clamp(1, 12) as i64;
+        let year = self.year - (month <= 2) as i64;
+        let era = year.div_euclid(400);
+        let yoe = year.rem_euclid(400);
+        let doy = (153 * if month > 2 { month - 3 } else { month + 9 } + 2) / 5 + self.day as i64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146097 + doe - 719468;
+        days * SECONDS_PER_DAY + self.hours as i64 * 3600 + self.minutes as i64 * 60 + self.seconds as i64
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Rtc {
+    name: String,
+    pub base: u16,
+    clock: RtcClock,
+    cycles: u64,
+    offset: i64,            // set by the program, in seconds
+    latched: DateTime,
+}
+
+impl Rtc {
+    pub fn create(name: &str, base: u16, clock: RtcClock) -> Self {
+        let mut rtc = Self {
+            name: String::from(name),
+            base,
+            clock,
+            cycles: 0,
+            offset: 0,
+            latched: DateTime::from_timestamp(0),
+        };
+        rtc.latch();
+        rtc
+    }
+
+    // seconds since 1970-01-01 00:00:00
+    pub fn timestamp(&self) -> i64 {
+        let clock = match self.clock {
+            RtcClock::Host => SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs() as i64),
+            RtcClock::Cycles { hz } => EMULATED_EPOCH + (self.cycles / hz.max(1)) as i64,
+        };
+        clock + self.offset
+    }
+
+    pub fn now(&self) -> DateTime {
+        DateTime::from_timestamp(self.timestamp())
+    }
+
+    fn latch(&mut self) {
+        self.latched = self.now();
+    }
+}
+
+impl Device for Rtc {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        self.base..=self.base + (RTC_REGISTERS - 1)
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        if addr.wrapping_sub(self.base) == REG_SECONDS {
+            self.latch();
+        }
+        self.peek(addr)
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        let time = &self.latched;
+        match addr.wrapping_sub(self.base) {
+            REG_SECONDS => time.seconds,
+            REG_MINUTES => time.minutes,
+            REG_HOURS => time.hours,
+            REG_DAY => time.day,
+            REG_MONTH => time.month,
+            REG_YEAR_LB => time.year as u8,
+            REG_YEAR_HB => (time.year >> 8) as u8,
+            _ => time.weekday,
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        let time = &mut self.latched;
+        match addr.wrapping_sub(self.base) {
+            REG_SECONDS => time.seconds = value,
+            REG_MINUTES => time.minutes = value,
+            REG_HOURS => time.hours = value,
+            REG_DAY => time.day = value,
+            REG_MONTH => time.month = value,
+            REG_YEAR_LB => time.year = (time.year & !0xFF) | value as i64,
+            REG_YEAR_HB => time.year = (time.year & 0xFF) | (value as i64) << 8,
+            _ => return,
+        }
+        self.offset += self.latched.timestamp() - self.timestamp();
+        self.latched = DateTime::from_timestamp(self.latched.timestamp());
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.cycles = self.cycles.saturating_add(cycles);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: u16 = RTC_BASE_DEFAULT;
+
+    #[test]
+    fn calendar() {
+        let leap_day = DateTime { year: 2024, month: 2, day: 29, hours: 13, minutes: 37, seconds: 42, weekday: 4 };
+        assert_eq!(DateTime::from_timestamp(1709213862), leap_day);
+        assert_eq!(leap_day.timestamp(), 1709213862);
+        assert_eq!(DateTime::from_timestamp(EMULATED_EPOCH).weekday, 6);
+        assert_eq!(DateTime::from_timestamp(-1).year, 1969);
+        for timestamp in (-SECONDS_PER_DAY * 800..SECONDS_PER_DAY * 800).step_by(SECONDS_PER_DAY as usize / 3 + 7) {
+            assert_eq!(DateTime::from_timestamp(timestamp).timestamp(), timestamp);
+        }
+    }
+
+    #[test]
+    fn emulated() {
+        let mut rtc = Rtc::create("rtc", BASE, RtcClock::Cycles { hz: 1000 });
+        let read = |rtc: &mut Rtc| (BASE..BASE + RTC_REGISTERS).map(|addr| rtc.read(addr)).collect::<Vec<u8>>();
+        assert_eq!(read(&mut rtc), vec![0, 0, 0, 1, 1, 0xD0, 0x07, 6]);
+
+        // latched until seconds are read again
+        rtc.tick(3_661_000);
+        assert_eq!(rtc.peek(BASE + REG_HOURS), 0);
+        assert_eq!(read(&mut rtc), vec![1, 1, 1, 1, 1, 0xD0, 0x07, 6]);
+
+        // setting the date keeps the clock running from there
+        rtc.write(BASE + REG_YEAR_LB, 0xEA);
+        rtc.write(BASE + REG_MONTH, 12);
+        rtc.write(BASE + REG_DAY, 31);
+        rtc.write(BASE + REG_HOURS, 23);
+        rtc.write(BASE + REG_MINUTES, 59);
+        rtc.write(BASE + REG_SECONDS, 59);
+        assert_eq!(rtc.now(), DateTime { year: 2026, month: 12, day: 31, hours: 23, minutes: 59, seconds: 59, weekday: 4 });
+        rtc.tick(1000);
+        assert_eq!(read(&mut rtc), vec![0, 0, 0, 1, 1, 0xEB, 0x07, 5]);
+    }
+
+    #[test]
+    fn host() {
+        let rtc = Rtc::create("rtc", BASE, RtcClock::Host);
+        assert!(rtc.now().year >= 2024);
+    }
+}
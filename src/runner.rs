@@ -0,0 +1,215 @@
+// runs the emulator on a background thread controlled over channels, for front-ends (e.g. a GUI) that cannot block on
+// `Cpu::exec`:
+//
+//   let handle = EmulatorHandle::spawn(|| { let mut mem = Memory::create(); ...; (cpu, mem) });
+//   handle.send(Command::Resume)?;
+//   while let Some(event) = handle.try_event() { ... }
+//
+// The CPU and memory are set up on the thread, as their hooks and devices need not be `Send`. The thread starts paused
+// and runs at full speed while resumed; an undefined opcode stops execution like any other stop reason.
+
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+
+use crate::cpu::{Cpu, Registers, StopReason};
+use crate::mem::Memory;
+
+// cycles executed between checks for commands while running
+const SLICE_CYCLES: u64 = 10_000;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Command {
+    Pause,
+    Resume,
+    Step,                               // one instruction (or interrupt entry) while paused
+    Irq(bool),                          // IRQ line asserted or released
+    Nmi,
+    ReadMemory { addr: u16, len: u16 },
+    State,                              // asks for the registers
+    Quit,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Event {
+    Resumed,
+    Paused(Registers),                  // by `Command::Pause` or after `Command::Step`
+    Stopped(StopReason, Registers),     // by the CPU, e.g. a hook or an undefined opcode; paused
+    Memory { addr: u16, bytes: Vec<u8> },
+    State(Registers),
+}
+
+pub struct EmulatorHandle {
+    commands: Sender<Command>,
+    events: Receiver<Event>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl EmulatorHandle {
+    pub fn spawn(setup: impl FnOnce() -> (Cpu, Memory) + Send + 'static) -> Self {
+        let (commands, command_receiver) = mpsc::channel();
+        let (event_sender, events) = mpsc::channel();
+        let thread = thread::spawn(move || {
+            let (cpu, mem) = setup();
+            Runner { cpu, mem, commands: command_receiver, events: event_sender, running: false }.run();
+        });
+        Self { commands, events, thread: Some(thread) }
+    }
+
+    pub fn send(&self, command: Command) -> Result<(), String> {
+        self.commands.send(command).map_err(|_| String::from("Emulator thread has ended"))
+    }
+
+    // next event if there is one, without blocking
+    pub fn try_event(&self) -> Option<Event> {
+        self.events.try_recv().ok()
+    }
+
+    // next event, blocking; None once the thread has ended
+    pub fn wait_event(&self) -> Option<Event> {
+        self.events.recv().ok()
+    }
+}
+
+impl Drop for EmulatorHandle {
+    fn drop(&mut self) {
+        let _ = self.commands.send(Command::Quit);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+struct Runner {
+    cpu: Cpu,
+    mem: Memory,
+    commands: Receiver<Command>,
+    events: Sender<Event>,
+    running: bool,
+}
+
+impl Runner {
+    // until quit or the handle is dropped
+    fn run(mut self) {
+        loop {
+            // waits for commands while paused, otherwise takes those sent meanwhile
+            let command = match self.running {
+                true => match self.commands.try_recv() {
+                    Ok(command) => Some(command),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => return,
+                },
+                false => match self.commands.recv() {
+                    Ok(command) => Some(command),
+                    Err(_) => return,
+                },
+            };
+
+            let event = match command {
+                Some(Command::Quit) => return,
+                Some(command) => self.handle(command),
+                None => self.run_slice(),
+            };
+            if let Some(event) = event {
+                if self.events.send(event).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    fn handle(&mut self, command: Command) -> Option<Event> {
+        match command {
+            Command::Pause => {
+                self.running = false;
+                Some(Event::Paused(self.cpu.registers()))
+            },
+            Command::Resume => {
+                self.running = true;
+                Some(Event::Resumed)
+            },
+            Command::Step if !self.running => Some(match self.step() {
+                Some(reason) => Event::Stopped(reason, self.cpu.registers()),
+                None => Event::Paused(self.cpu.registers()),
+            }),
+            Command::Step => None,
+            Command::Irq(asserted) => {
+                self.cpu.set_irq(asserted);
+                None
+            },
+            Command::Nmi => {
+                self.cpu.nmi();
+                None
+            },
+            Command::ReadMemory { addr, len } => {
+                let bytes = (0..len).map(|offset| self.mem.peek(addr.wrapping_add(offset))).collect();
+                Some(Event::Memory { addr, bytes })
+            },
+            Command::State => Some(Event::State(self.cpu.registers())),
+            Command::Quit => None,
+        }
+    }
+
+    fn run_slice(&mut self) -> Option<Event> {
+        let end = self.cpu.cycles.saturating_add(SLICE_CYCLES);
+        while self.cpu.cycles < end {
+            if let Some(reason) = self.step() {
+                self.running = false;
+                return Some(Event::Stopped(reason, self.cpu.registers()));
+            }
+        }
+        None
+    }
+
+    fn step(&mut self) -> Option<StopReason> {
+        self.cpu.exec(&mut self.mem, 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::tests::setup;
+    use crate::cpu::VECTOR_NMI;
+    use crate::instruction::Opcode::*;
+    use crate::mem::ADDR_RESET_VECTOR;
+
+    use super::*;
+
+    #[test]
+    fn commands() {
+        // E000 LDX #$03, E002 DEX, E003 BNE $E002, E005 STX $10, E007 .byte $02; NMI handler at E100
+        let handle = EmulatorHandle::spawn(|| {
+            let (cpu, mut mem) = setup();
+            mem.write_u8(ADDR_RESET_VECTOR, LDX_IMM.into());
+            mem.write_u8(None, 0x03);
+            mem.write_u8(None, DEX.into());
+            mem.write_u8(None, BNE_REL.into());
+            mem.write_u8(None, 0xFD);
+            mem.write_u8(None, STX_ZPG.into());
+            mem.write_u8(None, 0x10);
+            mem.write_u8(None, 0x02);
+            mem.write_u16(VECTOR_NMI, 0xE100);
+            (cpu, mem)
+        });
+
+        handle.send(Command::Step).unwrap();
+        let Some(Event::Paused(registers)) = handle.wait_event() else { panic!("not paused") };
+        assert_eq!((registers.pc, registers.x), (0xE002, 0x03));
+
+        handle.send(Command::Resume).unwrap();
+        assert_eq!(handle.wait_event(), Some(Event::Resumed));
+        let Some(Event::Stopped(reason, registers)) = handle.wait_event() else { panic!("not stopped") };
+        assert_eq!((reason, registers.pc, registers.x), (StopReason::UndefinedOpcode { pc: 0xE007, opcode: 0x02 }, 0xE007, 0x00));
+
+        handle.send(Command::ReadMemory { addr: 0xE004, len: 3 }).unwrap();
+        assert_eq!(handle.wait_event(), Some(Event::Memory { addr: 0xE004, bytes: vec![0xFD, 0x86, 0x10] }));
+
+        handle.send(Command::State).unwrap();
+        assert_eq!(handle.wait_event(), Some(Event::State(registers)));
+        assert_eq!(handle.try_event(), None);
+
+        handle.send(Command::Nmi).unwrap();
+        handle.send(Command::Step).unwrap();
+        let Some(Event::Paused(entered)) = handle.wait_event() else { panic!("not paused") };
+        assert_eq!((entered.pc, entered.sp, entered.cycles), (0xE100, registers.sp.wrapping_sub(3), registers.cycles + 7));
+    }
+}
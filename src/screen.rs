@@ -0,0 +1,227 @@
+// Text-mode screen: a memory region holding one byte per character cell, row by row, rendered to the terminal with
+// ANSI escape sequences. Bytes are mapped to characters as ASCII, as C64 screen codes ("PETSCII", upper case and
+// graphics set, with the graphics approximated by Unicode block and box drawing characters; bit 7 is reverse video) or
+// as Apple II characters (bit 7 clear is inverse or flashing, both shown in reverse video). Rows are consecutive in
+// memory unless their addresses are given, as for the interleaved Apple II text page.
+
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+use crate::mem::Memory;
+
+pub const SCREEN_REFRESH_CYCLES_DEFAULT: u64 = 20000;  // 50 times per second at 1 MHz
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Charset {
+    Ascii,
+    Petscii,
+    AppleII,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Redraw {
+    Full,       // clears the terminal and draws all cells
+    Diff,       // draws only the cells changed since the last rendering
+}
+
+// screen codes $40-$7F
+const PETSCII_GRAPHICS: [char; 64] = [
+    '─', '♠', '│', '─', '─', '─', '─', '│', '│', '╮', '╰', '╯', '└', '╲', '╱', '┌',
+    '┐', '●', '─', '♥', '│', '╭', '╳', '○', '♣', '│', '♦', '┼', '▒', '│', 'π', '◥',
+    ' ', '▌', '▄', '▔', '▁', '▏', '▒', '▕', '▒', '◤', '▕', '├', '▗', '└', '┐', '▂',
+    '┌', '┴', '┬', '┤', '▎', '▍', '▐', '▀', '▃', '▃', '▟', '▖', '▝', '┘', '▘', '▚',
+];
+
+impl Charset {
+    // the character and whether it is shown in reverse video
+    pub fn char(&self, byte: u8) -> (char, bool) {
+        match self {
+            Charset::Ascii => match byte {
+                0x20..=0x7E => (byte as char, false),
+                _ => (' ', false),
+            },
+            Charset::Petscii => {
+                let code = byte & 0x7F;
+                let char = match code {
+                    0x00 => '@',
+                    0x01..=0x1A => (b'A' + code - 1) as char,
+                    0x1B => '[',
+                    0x1C => '£',
+                    0x1D => ']',
+                    0x1E => '↑',
+                    0x1F => '←',
+                    0x20..=0x3F => code as char,
+                    _ => PETSCII_GRAPHICS[(code - 0x40) as usize],
+                };
+                (char, byte & 0x80 != 0)
+            },
+            Charset::AppleII => {
+                let code = byte & 0x7F;
+                let char = match code {
+                    0x00..=0x1F => (code + 0x40) as char,
+                    0x20..=0x3F => code as char,
+                    // $40-$7F flash with the characters of $00-$3F
+                    _ if byte & 0x80 == 0 => Charset::AppleII.char(code & 0x3F).0,
+                    0x7F => ' ',
+                    _ => code as char,
+                };
+                (char, byte & 0x80 == 0)
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TextScreen {
+    pub base: u16,
+    pub columns: u16,
+    pub rows: u16,
+    pub charset: Charset,
+    row_addrs: Vec<u16>,
+    drawn: Option<Vec<u8>>,     // cells as of the last rendering
+}
+
+impl TextScreen {
+    pub fn create(base: u16, columns: u16, rows: u16, charset: Charset) -> Result<Self, String> {
+        if columns == 0 || rows == 0 {
+            return Err(String::from("Screen must have at least one row and column"));
+        }
+        if base as usize + columns as usize * rows as usize > 0x10000 {
+            return Err(format!("Screen of {columns}x{rows} at ${base:04X} extends beyond $FFFF"));
+        }
+        let row_addrs = (0..rows).map(|row| base + row * columns).collect();
+        Ok(Self { base, columns, rows, charset, row_addrs, drawn: None })
+    }
+
+    // rows starting at the given addresses
+    pub fn with_row_addrs(row_addrs: Vec<u16>, columns: u16, charset: Charset) -> Result<Self, String> {
+        if columns == 0 || row_addrs.is_empty() {
+            return Err(String::from("Screen must have at least one row and column"));
+        }
+        if let Some(addr) = row_addrs.iter().find(|addr| **addr as usize + columns as usize > 0x10000) {
+            return Err(format!("Screen row of {columns} columns at ${addr:04X} extends beyond $FFFF"));
+        }
+        let base = *row_addrs.iter().min().unwrap_or(&0);
+        Ok(Self { base, columns, rows: row_addrs.len() as u16, charset, row_addrs, drawn: None })
+    }
+
+    // "ADDR:COLUMNSxROWS" with a hex address, e.g. "0400:40x25"
+    pub fn parse_geometry(text: &str) -> Result<(u16, u16, u16), String> {
+        let error = || format!("Expected ADDR:COLUMNSxROWS, e.g. 0400:40x25, instead of '{text}'");
+        let (addr, size) = text.split_once(':').ok_or_else(error)?;
+        let (columns, rows) = size.split_once('x').ok_or_else(error)?;
+        let addr = u16::from_str_radix(addr.trim_start_matches('$'), 16).map_err(|_| error())?;
+        Ok((addr, columns.parse().map_err(|_| error())?, rows.parse().map_err(|_| error())?))
+    }
+
+    // from the first to the last cell in memory
+    pub fn range(&self) -> RangeInclusive<u16> {
+        let end = self.row_addrs.iter().max().map_or(0, |addr| *addr as usize + self.columns as usize - 1);
+        self.base..=end as u16
+    }
+
+    fn cells(&self, mem: &Memory) -> Vec<u8> {
+        self.row_addrs.iter()
+            .flat_map(|addr| (0..self.columns).map(move |column| mem.peek(addr + column)))
+            .collect()
+    }
+
+    // the rows as plain text, without reverse video
+    pub fn lines(&self, mem: &Memory) -> Vec<String> {
+        self.cells(mem)
+            .chunks(self.columns as usize)
+            .map(|row| row.iter().map(|byte| self.charset.char(*byte).0).collect())
+            .collect()
+    }
+
+    // draws at the top left of the terminal and leaves the cursor below; returns whether anything was drawn
+    pub fn render(&mut self, mem: &Memory, out: &mut impl Write, redraw: Redraw) -> io::Result<bool> {
+        let cells = self.cells(mem);
+        let drawn = match (redraw, self.drawn.take()) {
+            (Redraw::Diff, Some(drawn)) => drawn,
+            _ => {
+                write!(out, "\x1b[H\x1b[2J")?;
+                Vec::new()
+            },
+        };
+
+        let mut changed = false;
+        for (index, byte) in cells.iter().enumerate() {
+            if drawn.get(index) == Some(byte) {
+                continue;
+            }
+            let (row, column) = (index / self.columns as usize, index % self.columns as usize);
+            let (char, reverse) = self.charset.char(*byte);
+            match reverse {
+                true => write!(out, "\x1b[{};{}H\x1b[7m{char}\x1b[27m", row + 1, column + 1)?,
+                false => write!(out, "\x1b[{};{}H{char}", row + 1, column + 1)?,
+            }
+            changed = true;
+        }
+        if changed {
+            write!(out, "\x1b[{};1H", self.rows + 1)?;
+            out.flush()?;
+        }
+        self.drawn = Some(cells);
+        Ok(changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let mut mem = Memory::create();
+        let mut screen = TextScreen::create(0x0400, 4, 2, Charset::Petscii).unwrap();
+        assert_eq!(screen.range(), 0x0400..=0x0407);
+        for (addr, byte) in (0x0400..).zip([0x08, 0x09, 0x21, 0x20, 0x20, 0x31, 0xB2, 0x51]) {
+            mem.write_u8(addr, byte);
+        }
+        assert_eq!(screen.lines(&mem), vec!["HI! ", " 12●"]);
+
+        let mut out = Vec::new();
+        assert!(screen.render(&mem, &mut out, Redraw::Diff).unwrap());
+        let full = String::from_utf8(out).unwrap();
+        assert!(full.starts_with("\x1b[H\x1b[2J\x1b[1;1HH\x1b[1;2HI"));
+        assert!(full.ends_with("\x1b[2;3H\x1b[7m2\x1b[27m\x1b[2;4H●\x1b[3;1H"));
+
+        // only changes are drawn
+        let mut out = Vec::new();
+        assert!(!screen.render(&mem, &mut out, Redraw::Diff).unwrap());
+        mem.write_u8(0x0401, 0x0F);
+        assert!(screen.render(&mem, &mut out, Redraw::Diff).unwrap());
+        assert_eq!(String::from_utf8(out).unwrap(), "\x1b[1;2HO\x1b[3;1H");
+
+        let mut out = Vec::new();
+        screen.render(&mem, &mut out, Redraw::Full).unwrap();
+        assert!(String::from_utf8(out).unwrap().starts_with("\x1b[H\x1b[2J"));
+    }
+
+    #[test]
+    fn row_addrs() {
+        let mut mem = Memory::create();
+        let screen = TextScreen::with_row_addrs(vec![0x0480, 0x0400], 2, Charset::AppleII).unwrap();
+        assert_eq!((screen.base, screen.rows, screen.range()), (0x0400, 2, 0x0400..=0x0481));
+        for (addr, byte) in [(0x0400, 0xC2), (0x0401, 0xD9), (0x0480, 0xC8), (0x0481, 0xC9)] {
+            mem.write_u8(addr, byte);
+        }
+        assert_eq!(screen.lines(&mem), vec!["HI", "BY"]);
+        assert!(TextScreen::with_row_addrs(vec![0xFFFF], 2, Charset::Ascii).is_err());
+    }
+
+    #[test]
+    fn geometry() {
+        assert_eq!(TextScreen::parse_geometry("0400:40x25"), Ok((0x0400, 40, 25)));
+        assert_eq!(TextScreen::parse_geometry("$F000:80x24"), Ok((0xF000, 80, 24)));
+        assert!(TextScreen::parse_geometry("0400:40").is_err());
+        assert!(TextScreen::create(0xFE00, 40, 25, Charset::Ascii).is_err());
+        assert_eq!(Charset::Ascii.char(0x41), ('A', false));
+        assert_eq!(Charset::Ascii.char(0x0D), (' ', false));
+        assert_eq!(Charset::AppleII.char(0xC1), ('A', false));
+        assert_eq!(Charset::AppleII.char(0x01), ('A', true));
+        assert_eq!(Charset::AppleII.char(0x61), ('!', true));
+        assert_eq!(Charset::AppleII.char(0xA0), (' ', false));
+    }
+}
@@ -0,0 +1,206 @@
+// Multiplexed seven-segment LED display, as on the KIM-1 and many trainer boards: one port drives the segments of all
+// digits, another selects the digit lit, and the program scans the digits faster than the eye can follow.
+//
+// Register layout (offset from base address):
+//   +0  SEGMENTS      bit 0 segment a (top), clockwise to bit 5 f (top left), bit 6 g (middle), bit 7 decimal point
+//   +1  DIGIT         number of the digit lit, 0 being the leftmost; others light none
+//
+// The time each segment is lit is summed over a window of emulated time (20000 cycles, 50 Hz at 1 MHz). At its end
+// the display shows the segments lit for at least a quarter of the time of the brightest one, hiding the glimpses of
+// a pattern on the wrong digit while the program switches between them. It is drawn as ASCII art, 3 rows per digit.
+
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+use crate::device::Device;
+use crate::screen::Redraw;
+
+pub const SEGMENTS_BASE_DEFAULT: u16 = 0xDF70;
+pub const SEGMENTS_REGISTERS: u16 = 2;
+pub const SEGMENTS_WINDOW_CYCLES: u64 = 20000;
+
+pub const REG_SEGMENTS: u16 = 0x0;
+pub const REG_DIGIT: u16 = 0x1;
+
+pub const SEGMENT_DP: u8 = 0b10000000;
+
+// segments of the hex digits 0-F
+pub const HEX_DIGITS: [u8; 16] = [
+    0x3F, 0x06, 0x5B, 0x4F, 0x66, 0x6D, 0x7D, 0x07, 0x7F, 0x6F, 0x77, 0x7C, 0x39, 0x5E, 0x79, 0x71,
+];
+
+pub struct SevenSegment {
+    name: String,
+    pub base: u16,
+    segments: u8,
+    digit: u8,
+    lit: Vec<[u64; 8]>,     // cycles each segment of each digit has been lit in the current window
+    elapsed: u64,           // into the window
+    shown: Vec<u8>,
+    drawn: Option<Vec<String>>,
+}
+
+impl SevenSegment {
+    pub fn create(name: &str, base: u16, digits: usize) -> Self {
+        Self {
+            name: String::from(name),
+            base,
+            segments: 0,
+            digit: 0,
+            lit: vec![[0; 8]; digits],
+            elapsed: 0,
+            shown: vec![0; digits],
+            drawn: None,
+        }
+    }
+
+    // segments shown per digit, as of the end of the last window
+    pub fn shown(&self) -> &[u8] {
+        &self.shown
+    }
+
+    // the digits drawn next to each other, 4 characters wide including the decimal point
+    pub fn lines(&self) -> Vec<String> {
+        let on = |pattern: u8, segment: usize, char: char| if pattern & (1 << segment) != 0 { char } else { ' ' };
+        let mut lines = vec![String::new(); 3];
+        for &pattern in &self.shown {
+            lines[0].extend([' ', on(pattern, 0, '_'), ' ', ' ']);
+            lines[1].extend([on(pattern, 5, '|'), on(pattern, 6, '_'), on(pattern, 1, '|'), ' ']);
+            lines[2].extend([on(pattern, 4, '|'), on(pattern, 3, '_'), on(pattern, 2, '|'), on(pattern, 7, '.')]);
+        }
+        lines
+    }
+
+    // draws the digits from a row of the terminal on (1 is the top, then cleared first) if they changed or always for
+    // a full redraw, and leaves the cursor below; returns whether anything was drawn
+    pub fn render(&mut self, out: &mut impl Write, top: u16, redraw: Redraw) -> io::Result<bool> {
+        let lines = self.lines();
+        if redraw == Redraw::Diff && self.drawn.as_ref() == Some(&lines) {
+            return Ok(false);
+        }
+        if top == 1 && self.drawn.is_none() {
+            write!(out, "\x1b[H\x1b[2J")?;
+        }
+        for (row, line) in (top..).zip(&lines) {
+            write!(out, "\x1b[{row};1H{line}")?;
+        }
+        write!(out, "\x1b[{};1H", top + 3)?;
+        out.flush()?;
+        self.drawn = Some(lines);
+        Ok(true)
+    }
+
+    fn latch(&mut self) {
+        let brightest = self.lit.iter().flatten().copied().max().unwrap_or(0);
+        for (shown, lit) in self.shown.iter_mut().zip(&mut self.lit) {
+            *shown = (0..8).filter(|&segment| lit[segment] > 0 && lit[segment] * 4 >= brightest).fold(0, |pattern, segment| pattern | 1 << segment);
+            *lit = [0; 8];
+        }
+        self.elapsed = 0;
+    }
+}
+
+impl Device for SevenSegment {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        self.base..=self.base + (SEGMENTS_REGISTERS - 1)
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        self.peek(addr)
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match addr.wrapping_sub(self.base) % SEGMENTS_REGISTERS {
+            REG_SEGMENTS => self.segments,
+            _ => self.digit,
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr.wrapping_sub(self.base) % SEGMENTS_REGISTERS {
+            REG_SEGMENTS => self.segments = value,
+            _ => self.digit = value,
+        }
+    }
+
+    fn tick(&mut self, mut cycles: u64) {
+        while cycles > 0 {
+            let step = cycles.min(SEGMENTS_WINDOW_CYCLES - self.elapsed);
+            if let Some(lit) = self.lit.get_mut(self.digit as usize) {
+                for (segment, time) in lit.iter_mut().enumerate() {
+                    if self.segments & (1 << segment) != 0 {
+                        *time += step;
+                    }
+                }
+            }
+            self.elapsed += step;
+            cycles -= step;
+            if self.elapsed == SEGMENTS_WINDOW_CYCLES {
+                self.latch();
+            }
+        }
+    }
+
+    // the ports are cleared, turning the display off
+    fn reset(&mut self) {
+        (self.segments, self.digit) = (0, 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: u16 = SEGMENTS_BASE_DEFAULT;
+
+    // scans the digits for at least a window, each lit for 100 cycles after showing the previous pattern for 4
+    fn scan(display: &mut SevenSegment, patterns: &[u8]) {
+        let mut elapsed = 0;
+        while elapsed < SEGMENTS_WINDOW_CYCLES {
+            for (digit, pattern) in patterns.iter().enumerate() {
+                display.write(BASE + REG_DIGIT, digit as u8);
+                display.tick(4);
+                display.write(BASE + REG_SEGMENTS, *pattern);
+                display.tick(100);
+                elapsed += 104;
+            }
+        }
+    }
+
+    #[test]
+    fn multiplexing() {
+        let mut display = SevenSegment::create("segments", BASE, 4);
+        scan(&mut display, &[HEX_DIGITS[1], HEX_DIGITS[2], HEX_DIGITS[0xA] | SEGMENT_DP]);
+        assert_eq!(display.shown(), [0x06, 0x5B, 0xF7, 0x00]);
+        assert_eq!(display.lines(), vec![
+            "     _   _      ",
+            "  |  _| |_|     ",
+            "  | |_  | |.    ",
+        ]);
+        assert_eq!((display.peek(BASE + REG_SEGMENTS), display.peek(BASE + REG_DIGIT)), (0xF7, 2));
+
+        // nothing lit in a whole window
+        display.reset();
+        display.write(BASE + REG_DIGIT, 0xFF);
+        display.write(BASE + REG_SEGMENTS, 0xFF);
+        display.tick(2 * SEGMENTS_WINDOW_CYCLES);
+        assert_eq!(display.shown(), [0; 4]);
+    }
+
+    #[test]
+    fn render() {
+        let mut display = SevenSegment::create("segments", BASE, 1);
+        display.write(BASE + REG_SEGMENTS, HEX_DIGITS[8]);
+        display.tick(SEGMENTS_WINDOW_CYCLES);
+        let mut out = Vec::new();
+        assert!(display.render(&mut out, 1, Redraw::Diff).unwrap());
+        assert_eq!(String::from_utf8(out).unwrap(), "\x1b[H\x1b[2J\x1b[1;1H _  \x1b[2;1H|_| \x1b[3;1H|_| \x1b[4;1H");
+        let mut out = Vec::new();
+        assert!(!display.render(&mut out, 1, Redraw::Diff).unwrap());
+        assert!(display.render(&mut out, 7, Redraw::Full).unwrap());
+    }
+}
@@ -0,0 +1,342 @@
+// Machine state snapshots (CPU, memory and attached devices)
+//
+// File layout, all values little-endian:
+//
+//   magic     8 bytes  "R6502STA"
+//   version   u16      format version, see FORMAT_VERSION
+//   sections  until the END section, each:
+//     tag     4 bytes  e.g. "CPU "
+//     version u16      version of the section contents
+//     length  u32      length of the contents
+//     contents
+//
+// Sections:
+//
+//   "CPU "  PC (u16), A, X, Y, P, SP (u8 each), cycles (u64), IRQ line, NMI pending (bool as u8)
+//   "MEM "  64K RAM, then 1024 u64 bitmaps of initialized addresses
+//   "ROM "  selected bank (u64); only if a banked ROM is mapped
+//   "DEV "  one per attached device: name length (u8), name, then the device's own state (see `Device::save_state`)
+//   "END "  empty
+//
+// Earlier files may have a "DMA " section instead of a "DEV " section for the DMA controller, with the same contents
+// as its device state; it is restored into the attached device named "dma".
+//
+// Compatibility: sections of unknown tags are skipped and fields appended to the contents of a section are ignored,
+// so additions need no new version. Changing existing fields increments the section version; older versions remain
+// readable. The format version only changes if the layout above does. Files with a newer format or section version
+// than supported are rejected before anything is restored.
+//
+// Device states are restored into the attached device with the same name. Saved devices that are not attached are
+// skipped with a warning; attached devices without a saved state keep theirs.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+use crate::cpu::Cpu;
+use crate::mem::Memory;
+
+pub const STATE_MAGIC: &[u8; 8] = b"R6502STA";
+pub const FORMAT_VERSION: u16 = 2;     // 1 was the unversioned layout without sections
+
+const SECTION_CPU: &[u8; 4] = b"CPU ";
+const SECTION_MEMORY: &[u8; 4] = b"MEM ";
+const SECTION_DMA: &[u8; 4] = b"DMA ";
+const SECTION_ROM: &[u8; 4] = b"ROM ";
+const SECTION_DEVICE: &[u8; 4] = b"DEV ";
+const SECTION_END: &[u8; 4] = b"END ";
+
+// supported version of each section
+const SECTION_VERSIONS: [(&[u8; 4], u16); 6] = [(SECTION_CPU, 1), (SECTION_MEMORY, 1), (SECTION_DMA, 1), (SECTION_ROM, 1), (SECTION_DEVICE, 1), (SECTION_END, 1)];
+
+pub(crate) fn write_u8(writer: &mut (impl Write + ?Sized), value: u8) -> io::Result<()> {
+    writer.write_all(&[value])
+}
+
+pub(crate) fn write_u16(writer: &mut (impl Write + ?Sized), value: u16) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+pub(crate) fn write_u64(writer: &mut (impl Write + ?Sized), value: u64) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+pub(crate) fn write_bool(writer: &mut (impl Write + ?Sized), value: bool) -> io::Result<()> {
+    write_u8(writer, value as u8)
+}
+
+pub(crate) fn write_u32(writer: &mut (impl Write + ?Sized), value: u32) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+pub(crate) fn read_u8(reader: &mut (impl Read + ?Sized)) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+pub(crate) fn read_u16(reader: &mut (impl Read + ?Sized)) -> io::Result<u16> {
+    let mut buf = [0; 2];
+    reader.read_exact(&mut buf)?;
+    Ok(u16::from_le_bytes(buf))
+}
+
+pub(crate) fn read_u32(reader: &mut (impl Read + ?Sized)) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+pub(crate) fn read_u64(reader: &mut (impl Read + ?Sized)) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+pub(crate) fn read_bool(reader: &mut (impl Read + ?Sized)) -> io::Result<bool> {
+    match read_u8(reader)? {
+        0 => Ok(false),
+        1 => Ok(true),
+        value => Err(invalid_data(&format!("Invalid boolean {value}"))),
+    }
+}
+
+pub(crate) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn section_name(tag: &[u8; 4]) -> String {
+    String::from_utf8_lossy(tag).trim_end().to_owned()
+}
+
+fn write_section(writer: &mut impl Write, tag: &[u8; 4], contents: impl FnOnce(&mut Vec<u8>) -> io::Result<()>) -> io::Result<()> {
+    let mut buf = Vec::new();
+    contents(&mut buf)?;
+    let version = SECTION_VERSIONS.iter().find(|(known, _)| *known == tag).map_or(1, |(_, version)| *version);
+    let len = u32::try_from(buf.len()).map_err(|_| invalid_data(&format!("Section {} too large", section_name(tag))))?;
+    writer.write_all(tag)?;
+    write_u16(writer, version)?;
+    write_u32(writer, len)?;
+    writer.write_all(&buf)
+}
+
+pub fn save(cpu: &Cpu, mem: &Memory, writer: &mut impl Write) -> io::Result<()> {
+    writer.write_all(STATE_MAGIC)?;
+    write_u16(writer, FORMAT_VERSION)?;
+    write_section(writer, SECTION_CPU, |buf| cpu.save_state(buf))?;
+    write_section(writer, SECTION_MEMORY, |buf| mem.save_state(buf))?;
+    if let Some(rom) = mem.rom() {
+        write_section(writer, SECTION_ROM, |buf| write_u64(buf, rom.bank() as u64))?;
+    }
+    for device in mem.devices() {
+        write_section(writer, SECTION_DEVICE, |buf| {
+            let name = device.name().as_bytes();
+            write_u8(buf, u8::try_from(name.len()).map_err(|_| invalid_data(&format!("Device name {} too long", device.name())))?)?;
+            buf.write_all(name)?;
+            device.save_state(buf)
+        })?;
+    }
+    write_section(writer, SECTION_END, |_| Ok(()))?;
+    writer.flush()
+}
+
+// sections by tag with their contents; all versions are checked to be supported
+fn read_sections(reader: &mut impl Read) -> io::Result<Vec<([u8; 4], Vec<u8>)>> {
+    let mut magic = [0; STATE_MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+    if &magic != STATE_MAGIC {
+        return Err(invalid_data("Not a machine state file"));
+    }
+    let version = read_u16(reader)?;
+    if version != FORMAT_VERSION {
+        return Err(invalid_data(&format!("Unsupported state format version {version} (supported: {FORMAT_VERSION})")));
+    }
+
+    let mut sections = Vec::new();
+    loop {
+        let mut tag = [0; 4];
+        reader.read_exact(&mut tag)?;
+        let version = read_u16(reader)?;
+        let mut contents = vec![0; read_u32(reader)? as usize];
+        reader.read_exact(&mut contents)?;
+
+        if let Some((_, supported)) = SECTION_VERSIONS.iter().find(|(known, _)| **known == tag) {
+            if version > *supported {
+                return Err(invalid_data(&format!("Section {} has version {version}, newer than supported ({supported})", section_name(&tag))));
+            }
+        }
+        if &tag == SECTION_END {
+            return Ok(sections);
+        }
+        sections.push((tag, contents));
+    }
+}
+
+// the file is read and checked completely before anything is restored; on an error in the contents of a section
+// the machine may be partially restored
+pub fn load(cpu: &mut Cpu, mem: &mut Memory, reader: &mut impl Read) -> io::Result<()> {
+    let sections = read_sections(reader)?;
+    let section = |tag: &[u8; 4]| sections.iter().find(|(known, _)| known == tag).map(|(_, contents)| contents.as_slice());
+
+    let (Some(mut cpu_state), Some(mut mem_state)) = (section(SECTION_CPU), section(SECTION_MEMORY)) else {
+        return Err(invalid_data("State lacks CPU or memory section"));
+    };
+    cpu.load_state(&mut cpu_state)?;
+    mem.load_state(&mut mem_state)?;
+    if let Some(mut contents) = section(SECTION_DMA) {
+        if !mem.restore_device("dma", &mut contents)? {
+            log::warn!("State of the DMA controller not restored: no such device attached");
+        }
+    }
+    if let Some(mut contents) = section(SECTION_ROM) {
+        mem.restore_rom_bank(read_u64(&mut contents)? as usize)?;
+    }
+    for (_, contents) in sections.iter().filter(|(tag, _)| tag == SECTION_DEVICE) {
+        let mut contents = contents.as_slice();
+        let mut name = vec![0; read_u8(&mut contents)? as usize];
+        contents.read_exact(&mut name)?;
+        let name = String::from_utf8(name).map_err(|_| invalid_data("Invalid device name"))?;
+        if !mem.restore_device(&name, &mut contents)? {
+            log::warn!("State of device {name} not restored: no such device attached");
+        }
+    }
+    Ok(())
+}
+
+pub fn save_to_file(cpu: &Cpu, mem: &Memory, filename: &str) -> io::Result<()> {
+    save(cpu, mem, &mut BufWriter::new(File::create(filename)?))
+}
+
+pub fn load_from_file(cpu: &mut Cpu, mem: &mut Memory, filename: &str) -> io::Result<()> {
+    load(cpu, mem, &mut BufReader::new(File::open(filename)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instruction::Opcode::*;
+    use crate::mem::ADDR_RESET_VECTOR;
+
+    use super::*;
+
+    #[test]
+    fn save_load() {
+        use crate::dma::{Dma, DMA_BASE_DEFAULT};
+
+        let mut mem = Memory::create();
+        let mut cpu = Cpu::create();
+        mem.attach_device(Box::new(Dma::create("dma", DMA_BASE_DEFAULT).unwrap()));
+        cpu.reset(&mut mem);
+
+        // E000 LDX #$05, E002 INX, E003 JMP $E002
+        mem.write_u8(ADDR_RESET_VECTOR, LDX_IMM.into());
+        mem.write_u8(None, 0x05);
+        mem.write_u8(None, INX.into());
+        mem.write_u8(None, JMP_ABS.into());
+        mem.write_u16(None, 0xE002);
+        mem.write_u16(DMA_BASE_DEFAULT, 0x1234);
+        cpu.exec(&mut mem, 1);
+
+        let mut state = Vec::new();
+        save(&cpu, &mem, &mut state).unwrap();
+        let (pc, x, cycles) = (cpu.pc, cpu.x, cpu.cycles);
+
+        cpu.exec(&mut mem, 20);
+        mem.write_u8(0x0200, 0xAA);
+        assert_ne!(cpu.x, x);
+
+        load(&mut cpu, &mut mem, &mut state.as_slice()).unwrap();
+        assert_eq!((cpu.pc, cpu.x, cpu.cycles), (pc, x, cycles));
+        assert_eq!(mem.peek(0x0200), 0x00);
+        assert!(!mem.is_initialized(0x0200));
+        assert_eq!(mem.peek(DMA_BASE_DEFAULT), 0x34);
+
+        // restored machine continues identically
+        cpu.exec(&mut mem, 1);
+        assert_eq!(cpu.x, x + 1);
+
+        // a fresh machine with the same devices resumes without reset
+        let (mut resumed_cpu, mut resumed_mem) = (Cpu::create(), Memory::create());
+        resumed_mem.attach_device(Box::new(Dma::create("dma", DMA_BASE_DEFAULT).unwrap()));
+        load(&mut resumed_cpu, &mut resumed_mem, &mut state.as_slice()).unwrap();
+        assert_eq!(resumed_mem.peek(DMA_BASE_DEFAULT), 0x34);
+        resumed_cpu.exec(&mut resumed_mem, 1);
+        assert_eq!((resumed_cpu.pc, resumed_cpu.x, resumed_cpu.cycles), (cpu.pc, cpu.x, cpu.cycles));
+
+        assert!(load(&mut cpu, &mut mem, &mut &b"garbage"[..]).is_err());
+        assert!(load(&mut cpu, &mut mem, &mut &state[..100]).is_err());
+    }
+
+    #[test]
+    fn devices() {
+        use crate::via::{Via, INT_ANY, INT_T1, REG_IER, REG_IFR, REG_T1C_H, REG_T1C_L, VIA_BASE_DEFAULT as BASE};
+
+        let mut mem = Memory::create();
+        let mut cpu = Cpu::create();
+        mem.attach_device(Box::new(Via::create("via", BASE)));
+        cpu.reset(&mut mem);
+
+        // T1 started with $0010 and its interrupt enabled, 5 cycles in
+        mem.write_u8(BASE + REG_IER, INT_ANY | INT_T1);
+        mem.write_u8(BASE + REG_T1C_L, 0x10);
+        mem.write_u8(BASE + REG_T1C_H, 0x00);
+        mem.tick_devices(5);
+        let mut state = Vec::new();
+        save(&cpu, &mem, &mut state).unwrap();
+
+        mem.tick_devices(100);
+        assert!(mem.device_irq());
+        load(&mut cpu, &mut mem, &mut state.as_slice()).unwrap();
+        assert_eq!((mem.peek(BASE + REG_T1C_L), mem.peek(BASE + REG_IER), mem.device_irq()), (0x0B, INT_ANY | INT_T1, false));
+        mem.tick_devices(12);
+        assert_eq!(mem.peek(BASE + REG_IFR), INT_ANY | INT_T1);
+
+        // devices not attached are skipped, attached ones without a saved state keep theirs
+        let (mut other_cpu, mut other_mem) = (Cpu::create(), Memory::create());
+        load(&mut other_cpu, &mut other_mem, &mut state.as_slice()).unwrap();
+        assert_eq!(other_mem.devices().count(), 0);
+        let mut empty = Vec::new();
+        save(&other_cpu, &other_mem, &mut empty).unwrap();
+        load(&mut cpu, &mut mem, &mut empty.as_slice()).unwrap();
+        assert_eq!(mem.peek(BASE + REG_IFR), INT_ANY | INT_T1);
+    }
+
+    #[test]
+    fn versions() {
+        let mut mem = Memory::create();
+        let mut cpu = Cpu::create();
+        cpu.reset(&mut mem);
+        cpu.x = 0x42;
+
+        let mut state = Vec::new();
+        save(&cpu, &mem, &mut state).unwrap();
+        assert_eq!(&state[8..16], [2, 0, b'C', b'P', b'U', b' ', 1, 0]);
+
+        // unknown sections and fields appended to a section are skipped
+        let mut extended = state[..10].to_vec();
+        write_section(&mut extended, b"NEW ", |buf| buf.write_all(b"future")).unwrap();
+        extended.extend(b"CPU \x01\x00");
+        let cpu_len = u32::from_le_bytes(state[16..20].try_into().unwrap()) as usize;
+        extended.extend(((cpu_len + 1) as u32).to_le_bytes());
+        extended.extend(&state[20..20 + cpu_len]);
+        extended.push(0xFF);
+        extended.extend(&state[20 + cpu_len..]);
+        cpu.x = 0;
+        load(&mut cpu, &mut mem, &mut extended.as_slice()).unwrap();
+        assert_eq!(cpu.x, 0x42);
+
+        // newer versions are rejected before anything is restored
+        let mut newer = state.clone();
+        newer[8] = 3;
+        let error = load(&mut cpu, &mut mem, &mut newer.as_slice()).unwrap_err();
+        assert_eq!(error.to_string(), "Unsupported state format version 3 (supported: 2)");
+        let mut newer = state.clone();
+        newer[14] = 2;
+        cpu.x = 0;
+        let error = load(&mut cpu, &mut mem, &mut newer.as_slice()).unwrap_err();
+        assert_eq!(error.to_string(), "Section CPU has version 2, newer than supported (1)");
+        assert_eq!(cpu.x, 0);
+
+        let mut missing = state[..10].to_vec();
+        write_section(&mut missing, SECTION_END, |_| Ok(())).unwrap();
+        assert!(load(&mut cpu, &mut mem, &mut missing.as_slice()).is_err());
+    }
+}
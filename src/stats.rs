@@ -0,0 +1,117 @@
+use num_traits::FromPrimitive;
+
+use crate::cpu::Interrupt;
+use crate::instruction::{AddressingMode, Instruction, Mnemonic, Opcode};
+use crate::prelude::*;
+
+const OPCODES: usize = 0x100;
+
+// executed instructions by opcode and interrupts taken, with the cycles spent on them
+pub struct Stats {
+    executions: Vec<u64>,
+    cycles: u64,
+    irqs: u64,
+    nmis: u64,
+}
+
+impl Stats {
+    pub fn create() -> Self {
+        Self {
+            executions: vec![0; OPCODES],
+            cycles: 0,
+            irqs: 0,
+            nmis: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.executions.fill(0);
+        self.cycles = 0;
+        self.irqs = 0;
+        self.nmis = 0;
+    }
+
+    pub fn record(&mut self, opcode: Opcode, cycles: u64) {
+        self.executions[opcode as usize] = self.executions[opcode as usize].saturating_add(1);
+        self.cycles = self.cycles.saturating_add(cycles);
+    }
+
+    pub fn record_interrupt(&mut self, interrupt: Interrupt, cycles: u64) {
+        match interrupt {
+            Interrupt::Irq => self.irqs = self.irqs.saturating_add(1),
+            Interrupt::Nmi => self.nmis = self.nmis.saturating_add(1),
+            Interrupt::Brk => {},   // counted as instruction
+        }
+        self.cycles = self.cycles.saturating_add(cycles);
+    }
+
+    pub fn instructions(&self) -> u64 {
+        self.executions.iter().fold(0u64, |total, count| total.saturating_add(*count))
+    }
+
+    // including interrupt entry sequences and DMA stalls caused by instructions
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn interrupts(&self, interrupt: Interrupt) -> u64 {
+        match interrupt {
+            Interrupt::Brk => self.executions[Opcode::BRK as usize],
+            Interrupt::Irq => self.irqs,
+            Interrupt::Nmi => self.nmis,
+        }
+    }
+
+    // executed instructions by mnemonic, most frequent first; equally frequent ones in opcode order
+    pub fn by_mnemonic(&self) -> Vec<(Mnemonic, u64)> {
+        self.histogram(|ins| ins.mnemonic)
+    }
+
+    // executed instructions by addressing mode, most frequent first
+    pub fn by_addressing_mode(&self) -> Vec<(AddressingMode, u64)> {
+        self.histogram(|ins| ins.addr_mode)
+    }
+
+    fn histogram<T: PartialEq>(&self, key: impl Fn(&Instruction) -> T) -> Vec<(T, u64)> {
+        let mut counts: Vec<(T, u64)> = Vec::new();
+        for (byte, count) in self.executions.iter().enumerate().filter(|(_, count)| **count > 0) {
+            let Some(ins) = Opcode::from_u8(byte as u8).and_then(|opcode| Instruction::from_opcode(opcode).ok()) else {
+                continue;
+            };
+            let key = key(&ins);
+            match counts.iter_mut().find(|(known, _)| *known == key) {
+                Some((_, total)) => *total = total.saturating_add(*count),
+                None => counts.push((key, *count)),
+            }
+        }
+        counts.sort_by_key(|(_, count)| core::cmp::Reverse(*count));
+        counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instruction::Opcode::*;
+
+    use super::*;
+
+    #[test]
+    fn histogram() {
+        let mut stats = Stats::create();
+        stats.record(LDA_IMM, 2);
+        stats.record(LDA_ABS, 4);
+        stats.record(STA_ABS, 4);
+        stats.record(LDA_IMM, 2);
+        stats.record(BRK, 7);
+        stats.record_interrupt(Interrupt::Irq, 7);
+
+        assert_eq!((stats.instructions(), stats.cycles()), (5, 26));
+        assert_eq!((stats.interrupts(Interrupt::Brk), stats.interrupts(Interrupt::Irq), stats.interrupts(Interrupt::Nmi)), (1, 1, 0));
+        assert_eq!(stats.by_mnemonic(), vec![(Mnemonic::LDA, 3), (Mnemonic::BRK, 1), (Mnemonic::STA, 1)]);
+        assert_eq!(stats.by_addressing_mode(), vec![(AddressingMode::ABS, 2), (AddressingMode::IMM, 2), (AddressingMode::IMP, 1)]);
+
+        stats.clear();
+        assert_eq!(stats.instructions(), 0);
+        assert!(stats.by_mnemonic().is_empty());
+    }
+}
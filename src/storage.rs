@@ -0,0 +1,210 @@
+// Block storage: a host image file accessed in sectors of 512 bytes through a sector buffer. A command transfers
+// between the buffer and the sector at the LBA; the data register steps through the buffer.
+//
+//  +0  command/status  write: command; read: bit 0 set if the last command failed, bit 1 if the image is read-only
+//  +1  LBA LB          sector number
+//  +2  LBA MB
+//  +3  LBA HB
+//  +4  data            reads or writes the buffer byte at the index and advances it (wrapping at the end)
+//  +5  size LB         image size in sectors (read-only)
+//  +6  size MB
+//  +7  size HB
+//
+// Commands: $00 resets the index, $01 reads the sector into the buffer, $02 writes the buffer to the sector; the
+// latter two reset the index as well. A partial last sector reads as padded with zeros; writes beyond the image fail.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::RangeInclusive;
+
+use crate::device::Device;
+
+pub const STORAGE_BASE_DEFAULT: u16 = 0xDF40;
+pub const SECTOR_SIZE: usize = 512;
+
+pub const REG_COMMAND: u16 = 0;
+pub const REG_LBA_LB: u16 = 1;
+pub const REG_LBA_MB: u16 = 2;
+pub const REG_LBA_HB: u16 = 3;
+pub const REG_DATA: u16 = 4;
+pub const REG_SIZE_LB: u16 = 5;
+pub const REG_SIZE_MB: u16 = 6;
+pub const REG_SIZE_HB: u16 = 7;
+
+pub const COMMAND_RESET_INDEX: u8 = 0x00;
+pub const COMMAND_READ: u8 = 0x01;
+pub const COMMAND_WRITE: u8 = 0x02;
+
+const STATUS_ERROR: u8 = 0x01;
+const STATUS_READ_ONLY: u8 = 0x02;
+
+pub struct Storage {
+    name: String,
+    pub base: u16,
+    file: File,
+    writable: bool,
+    size: u64,                      // in bytes
+    lba: u32,
+    buffer: [u8; SECTOR_SIZE],
+    index: usize,
+    error: bool,
+}
+
+impl Storage {
+    // opened read-only if the image file cannot be written
+    pub fn open(name: &str, base: u16, filename: &str) -> Result<Self, String> {
+        let error = |error: io::Error| format!("Cannot open storage image '{filename}': {error}");
+        let (file, writable) = match OpenOptions::new().read(true).write(true).open(filename) {
+            Ok(file) => (file, true),
+            Err(_) => (File::open(filename).map_err(error)?, false),
+        };
+        let size = file.metadata().map_err(error)?.len();
+        Ok(Self {
+            name: String::from(name),
+            base,
+            file,
+            writable,
+            size,
+            lba: 0,
+            buffer: [0; SECTOR_SIZE],
+            index: 0,
+            error: false,
+        })
+    }
+
+    // including a partial last one
+    pub fn sectors(&self) -> u32 {
+        self.size.div_ceil(SECTOR_SIZE as u64).min(0xFFFFFF) as u32
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.writable
+    }
+
+    fn read_sector(&mut self) -> io::Result<()> {
+        if self.lba >= self.sectors() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "sector beyond image"));
+        }
+        let offset = self.lba as u64 * SECTOR_SIZE as u64;
+        let length = (self.size - offset).min(SECTOR_SIZE as u64) as usize;
+        self.buffer = [0; SECTOR_SIZE];
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(&mut self.buffer[..length])
+    }
+
+    fn write_sector(&mut self) -> io::Result<()> {
+        let offset = self.lba as u64 * SECTOR_SIZE as u64;
+        if !self.writable || offset + SECTOR_SIZE as u64 > self.size {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "read-only image or sector beyond image"));
+        }
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(&self.buffer)
+    }
+
+    fn status(&self) -> u8 {
+        (if self.error { STATUS_ERROR } else { 0 }) | (if self.writable { 0 } else { STATUS_READ_ONLY })
+    }
+}
+
+impl Device for Storage {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        self.base..=self.base + REG_SIZE_HB
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        let value = self.peek(addr);
+        if addr.wrapping_sub(self.base) == REG_DATA {
+            self.index = (self.index + 1) % SECTOR_SIZE;
+        }
+        value
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match addr.wrapping_sub(self.base) {
+            REG_COMMAND => self.status(),
+            REG_LBA_LB => self.lba as u8,
+            REG_LBA_MB => (self.lba >> 8) as u8,
+            REG_LBA_HB => (self.lba >> 16) as u8,
+            REG_DATA => self.buffer[self.index],
+            REG_SIZE_LB => self.sectors() as u8,
+            REG_SIZE_MB => (self.sectors() >> 8) as u8,
+            _ => (self.sectors() >> 16) as u8,
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr.wrapping_sub(self.base) {
+            REG_COMMAND => {
+                self.error = match value {
+                    COMMAND_RESET_INDEX => false,
+                    COMMAND_READ => self.read_sector().is_err(),
+                    COMMAND_WRITE => self.write_sector().is_err(),
+                    _ => true,
+                };
+                self.index = 0;
+            },
+            REG_LBA_LB => self.lba = (self.lba & 0xFFFF00) | value as u32,
+            REG_LBA_MB => self.lba = (self.lba & 0xFF00FF) | (value as u32) << 8,
+            REG_LBA_HB => self.lba = (self.lba & 0x00FFFF) | (value as u32) << 16,
+            REG_DATA => {
+                self.buffer[self.index] = value;
+                self.index = (self.index + 1) % SECTOR_SIZE;
+            },
+            _ => {},
+        }
+    }
+
+    fn reset(&mut self) {
+        self.lba = 0;
+        self.index = 0;
+        self.error = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sectors() {
+        // 2 sectors and a partial one
+        let filename = std::env::temp_dir().join(format!("rust-6502-emu-storage-{}.img", std::process::id()));
+        let image: Vec<u8> = (0..SECTOR_SIZE * 2 + 16).map(|index| (index / SECTOR_SIZE) as u8 + 1).collect();
+        std::fs::write(&filename, &image).unwrap();
+        let mut storage = Storage::open("storage", STORAGE_BASE_DEFAULT, filename.to_str().unwrap()).unwrap();
+        assert_eq!((storage.peek(0xDF45), storage.peek(0xDF46), storage.peek(0xDF40)), (3, 0, 0));
+
+        storage.write(0xDF41, 1);
+        storage.write(0xDF40, COMMAND_READ);
+        assert_eq!((storage.read(0xDF44), storage.read(0xDF44), storage.peek(0xDF40)), (2, 2, 0));
+
+        // write the buffer to the first sector, changing its second byte
+        storage.write(0xDF40, COMMAND_RESET_INDEX);
+        storage.read(0xDF44);
+        storage.write(0xDF44, 0xAA);
+        storage.write(0xDF41, 0);
+        storage.write(0xDF40, COMMAND_WRITE);
+        assert_eq!(storage.peek(0xDF40), 0);
+
+        // partial sector is padded, but cannot be written
+        storage.write(0xDF41, 2);
+        storage.write(0xDF40, COMMAND_READ);
+        assert_eq!((storage.peek(0xDF40), storage.buffer[15], storage.buffer[16]), (0, 3, 0));
+        storage.write(0xDF40, COMMAND_WRITE);
+        assert_eq!(storage.peek(0xDF40), STATUS_ERROR);
+        storage.write(0xDF41, 3);
+        storage.write(0xDF40, COMMAND_READ);
+        assert_eq!(storage.peek(0xDF40), STATUS_ERROR);
+
+        drop(storage);
+        let image = std::fs::read(&filename).unwrap();
+        std::fs::remove_file(&filename).unwrap();
+        assert_eq!(image.len(), SECTOR_SIZE * 2 + 16);
+        assert_eq!(image[..3], [2, 0xAA, 2]);
+        assert_eq!(image[SECTOR_SIZE..SECTOR_SIZE + 2], [2, 2]);
+    }
+}
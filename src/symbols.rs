@@ -0,0 +1,125 @@
+use alloc::collections::BTreeMap;
+#[cfg(feature = "std")]
+use std::fs;
+
+use crate::prelude::*;
+
+// labels for addresses, e.g. loaded from a VICE label file
+#[derive(Default)]
+pub struct SymbolTable {
+    by_addr: BTreeMap<u16, String>,         // first name defined for an address
+    by_name: BTreeMap<String, u16>,
+}
+
+impl SymbolTable {
+    pub fn add(&mut self, name: &str, addr: u16) {
+        if let Some(old_addr) = self.by_name.insert(String::from(name), addr) {
+            if self.by_addr.get(&old_addr).is_some_and(|old_name| old_name == name) {
+                self.by_addr.remove(&old_addr);
+            }
+        }
+        self.by_addr.entry(addr).or_insert_with(|| String::from(name));
+    }
+
+    pub fn clear(&mut self) {
+        self.by_addr.clear();
+        self.by_name.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_name.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<u16> {
+        self.by_name.get(name).copied()
+    }
+
+    pub fn name_at(&self, addr: u16) -> Option<&str> {
+        self.by_addr.get(&addr).map(String::as_str)
+    }
+
+    // closest symbol at or below `addr` with the offset from it, e.g. for naming the routine containing an address
+    pub fn nearest(&self, addr: u16) -> Option<(&str, u16)> {
+        self.by_addr.range(..=addr).next_back().map(|(symbol_addr, name)| (name.as_str(), addr - symbol_addr))
+    }
+
+    // "name" or "name+offset" for addresses within `max_offset` bytes after a symbol
+    pub fn describe(&self, addr: u16, max_offset: u16) -> Option<String> {
+        match self.nearest(addr) {
+            Some((name, 0)) => Some(String::from(name)),
+            Some((name, offset)) if offset <= max_offset => Some(format!("{name}+{offset}")),
+            _ => None,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &str)> + '_ {
+        self.by_name.iter().map(|(name, addr)| (*addr, name.as_str()))
+    }
+
+    // VICE label file lines like "al C:e000 .start"; returns the number of labels added
+    pub fn parse_vice(&mut self, text: &str) -> Result<usize, String> {
+        let mut count = 0;
+        for (number, line) in text.lines().enumerate().map(|(i, line)| (i + 1, line.trim())) {
+            if line.is_empty() {
+                continue;
+            }
+            let (addr, name) = match line.split_whitespace().collect::<Vec<&str>>()[..] {
+                ["al", addr, name] => (addr, name),
+                _ => return Err(format!("Line {number}: expected 'al <addr> .<label>', got '{line}'")),
+            };
+            let addr = addr.strip_prefix("C:").unwrap_or(addr);
+            let addr = u16::from_str_radix(addr, 16).map_err(|_| format!("Line {number}: invalid address '{addr}'"))?;
+            let name = name.strip_prefix('.').unwrap_or(name);
+            if name.is_empty() {
+                return Err(format!("Line {number}: missing label name"));
+            }
+            self.add(name, addr);
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    #[cfg(feature = "std")]
+    pub fn load_vice(&mut self, filename: &str) -> Result<usize, String> {
+        let text = fs::read_to_string(filename).map_err(|error| format!("Error reading label file '{filename}': {error}"))?;
+        self.parse_vice(&text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_vice() {
+        let mut symbols = SymbolTable::default();
+        assert_eq!(symbols.parse_vice("al C:e000 .start\n\nal C:e010 .loop\nal e020 .print\nal C:e000 .reset\n"), Ok(4));
+        assert_eq!(symbols.len(), 4);
+
+        assert_eq!(symbols.lookup("loop"), Some(0xE010));
+        assert_eq!(symbols.lookup("reset"), Some(0xE000));
+        assert_eq!(symbols.name_at(0xE000), Some("start"));
+        assert_eq!(symbols.name_at(0xE011), None);
+        assert_eq!(symbols.nearest(0xE015), Some(("loop", 5)));
+        assert_eq!(symbols.nearest(0x0200), None);
+        assert_eq!(symbols.describe(0xE012, 0xFF), Some(String::from("loop+2")));
+        assert_eq!(symbols.describe(0xE012, 1), None);
+
+        assert!(symbols.parse_vice("al C:zzzz .bad").is_err());
+        assert!(symbols.parse_vice("break e000").is_err());
+    }
+
+    #[test]
+    fn redefine() {
+        let mut symbols = SymbolTable::default();
+        symbols.add("start", 0xE000);
+        symbols.add("start", 0xE100);
+        assert_eq!(symbols.lookup("start"), Some(0xE100));
+        assert_eq!(symbols.name_at(0xE000), None);
+        assert_eq!(symbols.name_at(0xE100), Some("start"));
+    }
+}
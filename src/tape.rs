@@ -0,0 +1,245 @@
+// Tape: a byte stream from a host file (cassette or paper tape) read through a data/status pair while the motor runs,
+// a byte arriving at a fixed rate or, instantly, as soon as the previous one was taken. A byte not taken before the
+// next one arrives is lost.
+//
+//  +0  data            read: the byte under the head, taking it
+//  +1  status/control  read: bit 7 byte ready, bit 6 end of tape, bit 1 byte lost (cleared by taking a byte),
+//                      bit 0 motor on; write: bit 0 motor on, bit 1 rewinds
+
+use std::fs;
+use std::ops::RangeInclusive;
+
+use crate::device::Device;
+
+pub const TAPE_BASE_DEFAULT: u16 = 0xDF50;
+pub const TAPE_CLOCK_HZ_DEFAULT: u64 = 1_000_000;
+
+pub const REG_DATA: u16 = 0;
+pub const REG_STATUS: u16 = 1;
+
+const STATUS_READY: u8 = 0x80;
+const STATUS_END: u8 = 0x40;
+const STATUS_LOST: u8 = 0x02;
+const STATUS_MOTOR: u8 = 0x01;
+
+const CONTROL_MOTOR: u8 = 0x01;
+const CONTROL_REWIND: u8 = 0x02;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TapeRate {
+    Instant,
+    BytesPerSecond { rate: u32, hz: u64 },     // at an emulated clock rate
+}
+
+pub struct Tape {
+    name: String,
+    pub base: u16,
+    data: Vec<u8>,
+    rate: TapeRate,
+    position: usize,            // of the next byte to arrive
+    byte: Option<u8>,           // ready to be taken
+    last: u8,                   // last byte arrived
+    lost: bool,
+    motor: bool,
+    countdown: u64,             // cycles until the next byte arrives
+}
+
+impl Tape {
+    pub fn create(name: &str, base: u16, data: Vec<u8>, rate: TapeRate) -> Self {
+        Self {
+            name: String::from(name),
+            base,
+            data,
+            rate,
+            position: 0,
+            byte: None,
+            last: 0,
+            lost: false,
+            motor: false,
+            countdown: 0,
+        }
+    }
+
+    pub fn load(name: &str, base: u16, filename: &str, rate: TapeRate) -> Result<Self, String> {
+        let data = fs::read(filename).map_err(|error| format!("Cannot read tape file '{filename}': {error}"))?;
+        Ok(Self::create(name, base, data, rate))
+    }
+
+    // "instant" or bytes per second
+    pub fn parse_rate(text: &str) -> Result<TapeRate, String> {
+        match text {
+            "instant" => Ok(TapeRate::Instant),
+            _ => match text.parse() {
+                Ok(rate) if rate > 0 => Ok(TapeRate::BytesPerSecond { rate, hz: TAPE_CLOCK_HZ_DEFAULT }),
+                _ => Err(format!("Expected 'instant' or bytes per second instead of '{text}'")),
+            },
+        }
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn rewind(&mut self) {
+        self.position = 0;
+        self.byte = None;
+        self.lost = false;
+        self.countdown = self.cycles_per_byte();
+    }
+
+    fn cycles_per_byte(&self) -> u64 {
+        match self.rate {
+            TapeRate::Instant => 0,
+            TapeRate::BytesPerSecond { rate, hz } => (hz / rate as u64).max(1),
+        }
+    }
+
+    fn arrive(&mut self) {
+        if let Some(byte) = self.data.get(self.position).copied() {
+            self.lost |= self.byte.is_some();
+            self.byte = Some(byte);
+            self.last = byte;
+            self.position += 1;
+        }
+    }
+
+    fn status(&self) -> u8 {
+        let mut status = 0;
+        if self.byte.is_some() {
+            status |= STATUS_READY;
+        } else if self.position == self.data.len() {
+            status |= STATUS_END;
+        }
+        if self.lost {
+            status |= STATUS_LOST;
+        }
+        if self.motor {
+            status |= STATUS_MOTOR;
+        }
+        status
+    }
+}
+
+impl Device for Tape {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        self.base..=self.base + REG_STATUS
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        let value = self.peek(addr);
+        if addr.wrapping_sub(self.base) == REG_DATA && self.byte.take().is_some() {
+            self.lost = false;
+        }
+        value
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match addr.wrapping_sub(self.base) {
+            REG_DATA => self.byte.unwrap_or(self.last),
+            _ => self.status(),
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        if addr.wrapping_sub(self.base) == REG_STATUS {
+            if value & CONTROL_REWIND != 0 {
+                self.rewind();
+            }
+            if value & CONTROL_MOTOR != 0 && !self.motor {
+                self.countdown = self.cycles_per_byte();
+            }
+            self.motor = value & CONTROL_MOTOR != 0;
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        if !self.motor {
+            return;
+        }
+        match self.rate {
+            TapeRate::Instant => {
+                if self.byte.is_none() {
+                    self.arrive();
+                }
+            },
+            TapeRate::BytesPerSecond { .. } => {
+                let mut remaining = cycles;
+                while remaining >= self.countdown {
+                    remaining -= self.countdown;
+                    self.arrive();
+                    self.countdown = self.cycles_per_byte();
+                }
+                self.countdown -= remaining;
+            },
+        }
+    }
+
+    fn reset(&mut self) {
+        self.motor = false;
+        self.byte = None;
+        self.lost = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instant() {
+        let mut tape = Tape::create("tape", TAPE_BASE_DEFAULT, vec![0x12, 0x34], TapeRate::Instant);
+        tape.tick(10);
+        assert_eq!(tape.peek(0xDF51), 0x00);
+
+        tape.write(0xDF51, CONTROL_MOTOR);
+        tape.tick(1);
+        assert_eq!((tape.peek(0xDF51), tape.read(0xDF50)), (0x81, 0x12));
+        assert_eq!(tape.peek(0xDF51), 0x01);
+        tape.tick(1);
+        assert_eq!((tape.read(0xDF50), tape.position()), (0x34, 2));
+        tape.tick(1);
+        assert_eq!((tape.peek(0xDF51), tape.peek(0xDF50)), (0x41, 0x34));
+
+        tape.write(0xDF51, CONTROL_MOTOR | CONTROL_REWIND);
+        tape.tick(1);
+        assert_eq!(tape.read(0xDF50), 0x12);
+    }
+
+    #[test]
+    fn rate() {
+        assert_eq!(Tape::parse_rate("instant"), Ok(TapeRate::Instant));
+        assert!(Tape::parse_rate("0").is_err());
+        let rate = Tape::parse_rate("10").unwrap();
+        assert_eq!(rate, TapeRate::BytesPerSecond { rate: 10, hz: TAPE_CLOCK_HZ_DEFAULT });
+
+        // a byte every 100000 cycles
+        let mut tape = Tape::create("tape", TAPE_BASE_DEFAULT, vec![1, 2, 3], rate);
+        tape.write(0xDF51, CONTROL_MOTOR);
+        tape.tick(99999);
+        assert_eq!(tape.peek(0xDF51), 0x01);
+        tape.tick(1);
+        assert_eq!(tape.read(0xDF50), 1);
+
+        // the second byte is lost when the third arrives
+        tape.tick(200000);
+        assert_eq!((tape.peek(0xDF51), tape.read(0xDF50)), (0x83, 3));
+        assert_eq!(tape.peek(0xDF51), 0x41);
+
+        tape.write(0xDF51, 0);
+        tape.write(0xDF51, CONTROL_REWIND);
+        tape.tick(100000);
+        assert_eq!((tape.peek(0xDF51), tape.position()), (0x00, 0));
+    }
+}
@@ -0,0 +1,364 @@
+// Instruction traces in the layouts expected by different tools; one line per instruction with the registers
+// before its execution
+
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use crate::cpu::StatusFlags;
+
+const CALL_DEPTH_INDENT_MAX: usize = 32;       // deeper calls (e.g. runaway recursion) are not indented further
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum TraceFormat {
+    #[default]
+    Plain,      // E000  A9 42     LDA #$42        A:00 X:00 Y:00 P:20 SP:FD CYC:7
+    Vice,       // .C:e000  a9 42     LDA #$42        - A:00 X:00 Y:00 SP:fd ..-.....          7
+    Nestest,    // E000  A9 42     LDA #$42                        A:00 X:00 Y:00 P:20 SP:FD CYC:7
+    Json,       // {"pc":57344,"bytes":[169,66],"mnemonic":"LDA","operands":"#$42","a":0,...,"cycles":7}
+    Calls,      //         12    E005  JSR $E00A       -> E00A   (only jumps, calls, returns and interrupts)
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(Self::Plain),
+            "vice" => Ok(Self::Vice),
+            "nestest" => Ok(Self::Nestest),
+            "json" => Ok(Self::Json),
+            "calls" => Ok(Self::Calls),
+            _ => Err(format!("Unknown trace format '{s}', expected plain, vice, nestest, json or calls")),
+        }
+    }
+}
+
+impl fmt::Display for TraceFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Plain => write!(f, "plain"),
+            Self::Vice => write!(f, "vice"),
+            Self::Nestest => write!(f, "nestest"),
+            Self::Json => write!(f, "json"),
+            Self::Calls => write!(f, "calls"),
+        }
+    }
+}
+
+// an instruction about to be executed with the registers at that point
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TraceEntry {
+    pub pc: u16,
+    pub bytes: Vec<u8>,             // opcode and operands
+    pub mnemonic: String,
+    pub operands: String,           // assembler syntax
+    pub ac: u8,
+    pub x: u8,
+    pub y: u8,
+    pub sr: u8,
+    pub sp: u8,
+    pub cycles: u64,
+}
+
+impl TraceFormat {
+    pub fn line(&self, entry: &TraceEntry) -> String {
+        let hex = |bytes: &[u8]| bytes.iter().map(|byte| format!("{byte:02X}")).collect::<Vec<String>>().join(" ");
+        let text = format!("{} {}", entry.mnemonic, entry.operands).trim_end().to_owned();
+        match self {
+            Self::Plain => format!("{:04X}  {:<8}  {} {:<10}  A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+                entry.pc, hex(&entry.bytes), entry.mnemonic, entry.operands,
+                entry.ac, entry.x, entry.y, entry.sr, entry.sp, entry.cycles),
+            Self::Vice => format!(".C:{:04x}  {:<8}  {:<16}- A:{:02X} X:{:02X} Y:{:02X} SP:{:02x} {}  {:>9}",
+                entry.pc, hex(&entry.bytes).to_lowercase(), text,
+                entry.ac, entry.x, entry.y, entry.sp, StatusFlags::from_bits_retain(entry.sr), entry.cycles),
+            Self::Nestest => format!("{:04X}  {:<8}  {:<32}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+                entry.pc, hex(&entry.bytes), text,
+                entry.ac, entry.x, entry.y, entry.sr, entry.sp, entry.cycles),
+            Self::Json => {
+                let bytes = entry.bytes.iter().map(|byte| byte.to_string()).collect::<Vec<String>>().join(",");
+                format!(r#"{{"pc":{},"bytes":[{bytes}],"mnemonic":{},"operands":{},"a":{},"x":{},"y":{},"p":{},"sp":{},"cycles":{}}}"#,
+                    entry.pc, json_string(&entry.mnemonic), json_string(&entry.operands),
+                    entry.ac, entry.x, entry.y, entry.sr, entry.sp, entry.cycles)
+            },
+            Self::Calls => format!("{:>10}  {:04X}  {}", entry.cycles, entry.pc, text),
+        }
+    }
+
+    // whether only changes of control flow are traced, see `call_line`
+    pub fn is_call_trace(&self) -> bool {
+        *self == Self::Calls
+    }
+
+    // a jump, call, return or interrupt (as mnemonic, e.g. "IRQ") continuing at `target`, indented by the call depth
+    // at which it occurred
+    pub fn call_line(entry: &TraceEntry, target: u16, depth: usize) -> String {
+        let text = format!("{} {}", entry.mnemonic, entry.operands);
+        format!("{:>10}  {:indent$}{:04X}  {:<14}  -> {:04X}", entry.cycles, "", entry.pc, text.trim_end(), target, indent = 2 * depth.min(CALL_DEPTH_INDENT_MAX))
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// trace lines in one format to a writer
+pub struct TraceWriter {
+    writer: Box<dyn Write>,
+    format: TraceFormat,
+    ranges: Vec<RangeInclusive<u16>>,   // instruction addresses to trace; all if empty
+}
+
+impl TraceWriter {
+    pub fn create(writer: Box<dyn Write>, format: TraceFormat) -> Self {
+        Self { writer, format, ranges: Vec::new() }
+    }
+
+    pub fn format(&self) -> TraceFormat {
+        self.format
+    }
+
+    // restricts the trace to instructions in any of the ranges; none traces all
+    pub fn set_ranges(&mut self, ranges: Vec<RangeInclusive<u16>>) {
+        self.ranges = ranges;
+    }
+
+    pub fn ranges(&self) -> &[RangeInclusive<u16>] {
+        &self.ranges
+    }
+
+    // whether the instruction at `pc` is traced; `write` does not check this
+    pub fn includes(&self, pc: u16) -> bool {
+        self.ranges.is_empty() || self.ranges.iter().any(|range| range.contains(&pc))
+    }
+
+    pub fn write(&mut self, entry: &TraceEntry) -> io::Result<()> {
+        writeln!(self.writer, "{}", self.format.line(entry))
+    }
+
+    pub fn write_call(&mut self, entry: &TraceEntry, target: u16, depth: usize) -> io::Result<()> {
+        writeln!(self.writer, "{}", TraceFormat::call_line(entry, target, depth))
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+// registers of a line of a reference trace; fields missing in its layout are not compared
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct ReferenceLine {
+    pub pc: u16,
+    pub ac: Option<u8>,
+    pub x: Option<u8>,
+    pub y: Option<u8>,
+    pub sr: Option<u8>,
+    pub sp: Option<u8>,
+    pub cycles: Option<u64>,
+}
+
+impl ReferenceLine {
+    // any of the formats written by `TraceFormat`, and logs like nestest.log with additional fields
+    pub fn parse(line: &str) -> Result<Self, String> {
+        if line.trim_start().starts_with('{') {
+            let field = |key: &str| -> Option<u64> {
+                let value = line.split(&format!("\"{key}\":")).nth(1)?;
+                value.split(|c: char| !c.is_ascii_digit()).next()?.parse().ok()
+            };
+            let byte = |key: &str| field(key).and_then(|value| u8::try_from(value).ok());
+            let pc = field("pc").and_then(|pc| u16::try_from(pc).ok()).ok_or_else(|| String::from("Missing \"pc\""))?;
+            return Ok(Self { pc, ac: byte("a"), x: byte("x"), y: byte("y"), sr: byte("p"), sp: byte("sp"), cycles: field("cycles") });
+        }
+
+        let mut tokens = line.split_whitespace();
+        let pc = tokens.next().map(|pc| pc.strip_prefix(".C:").unwrap_or(pc)).ok_or_else(|| String::from("Empty line"))?;
+        let pc = u16::from_str_radix(pc, 16).map_err(|_| format!("Invalid address '{pc}'"))?;
+        let mut reference = Self { pc, ..Self::default() };
+        for token in tokens {
+            let Some((key, value)) = token.split_once(':') else {
+                continue;
+            };
+            let byte = || u8::from_str_radix(value, 16).ok();
+            match key {
+                "A" => reference.ac = byte(),
+                "X" => reference.x = byte(),
+                "Y" => reference.y = byte(),
+                "P" => reference.sr = byte(),
+                "SP" => reference.sp = byte(),
+                "CYC" => reference.cycles = value.parse().ok(),
+                _ => {},
+            }
+        }
+        Ok(reference)
+    }
+
+    // fields differing from `entry` as (name, expected, actual)
+    pub fn differences(&self, entry: &TraceEntry) -> Vec<(&'static str, String, String)> {
+        let mut differences = Vec::new();
+        if self.pc != entry.pc {
+            differences.push(("PC", format!("${:04X}", self.pc), format!("${:04X}", entry.pc)));
+        }
+        let registers = [("A", self.ac, entry.ac), ("X", self.x, entry.x), ("Y", self.y, entry.y), ("P", self.sr, entry.sr), ("SP", self.sp, entry.sp)];
+        for (name, expected, actual) in registers {
+            if let Some(expected) = expected.filter(|expected| *expected != actual) {
+                differences.push((name, format!("${expected:02X}"), format!("${actual:02X}")));
+            }
+        }
+        if let Some(expected) = self.cycles.filter(|expected| *expected != entry.cycles) {
+            differences.push(("CYC", expected.to_string(), entry.cycles.to_string()));
+        }
+        differences
+    }
+}
+
+// first line of a reference trace that does not match the execution
+#[derive(Clone, PartialEq, Debug)]
+pub struct Divergence {
+    pub line: usize,                                    // line number in the reference trace
+    pub reference: String,
+    pub actual: TraceEntry,
+    pub differences: Vec<(&'static str, String, String)>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Line {} of the reference trace differs", self.line)?;
+        writeln!(f, "  expected: {}", self.reference.trim_end())?;
+        write!(f, "  actual:   {}", TraceFormat::Plain.line(&self.actual))?;
+        for (name, expected, actual) in &self.differences {
+            match name.is_empty() {
+                true => write!(f, "\n  {expected}")?,                   // reference line not readable
+                false => write!(f, "\n  {name:<3} expected {expected}, actual {actual}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// compares each executed instruction with the next line of a reference trace, e.g. nestest.log
+pub struct TraceComparison {
+    reader: Box<dyn BufRead>,
+    line: usize,                    // number of the last line read
+    matched: usize,                 // instructions matching so far
+    finished: bool,                 // end of the reference reached
+    divergence: Option<Divergence>,
+}
+
+impl TraceComparison {
+    pub fn create(reader: Box<dyn BufRead>) -> Self {
+        Self { reader, line: 0, matched: 0, finished: false, divergence: None }
+    }
+
+    // comparing stops at the first divergence and at the end of the reference
+    pub fn is_active(&self) -> bool {
+        !self.finished && self.divergence.is_none()
+    }
+
+    pub fn matched(&self) -> usize {
+        self.matched
+    }
+
+    pub fn divergence(&self) -> Option<&Divergence> {
+        self.divergence.as_ref()
+    }
+
+    // Err with the divergence if `entry` does not match the next non-empty reference line
+    pub fn check(&mut self, entry: &TraceEntry) -> Result<(), &Divergence> {
+        if !self.is_active() {
+            return Ok(());
+        }
+        let mut text = String::new();
+        loop {
+            text.clear();
+            self.line += 1;
+            match self.reader.read_line(&mut text) {
+                Ok(0) => {
+                    self.finished = true;
+                    return Ok(());
+                },
+                Ok(_) if text.trim().is_empty() => continue,
+                Ok(_) => break,
+                Err(error) => text = format!("(unreadable: {error})"),
+            }
+            break;
+        }
+
+        let differences = match ReferenceLine::parse(&text) {
+            Ok(reference) => reference.differences(entry),
+            Err(error) => vec![("", error, String::new())],
+        };
+        if differences.is_empty() {
+            self.matched += 1;
+            return Ok(());
+        }
+        Err(self.divergence.insert(Divergence { line: self.line, reference: text, actual: entry.clone(), differences }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats() {
+        let entry = TraceEntry {
+            pc: 0xC000, bytes: vec![0x4C, 0xF5, 0xC5], mnemonic: String::from("JMP"), operands: String::from("$C5F5"),
+            ac: 0, x: 0x01, y: 0, sr: 0x24, sp: 0xFD, cycles: 7,
+        };
+        assert_eq!(TraceFormat::Plain.line(&entry), "C000  4C F5 C5  JMP $C5F5       A:00 X:01 Y:00 P:24 SP:FD CYC:7");
+        assert_eq!(TraceFormat::Nestest.line(&entry), "C000  4C F5 C5  JMP $C5F5                       A:00 X:01 Y:00 P:24 SP:FD CYC:7");
+        assert_eq!(TraceFormat::Vice.line(&entry), ".C:c000  4c f5 c5  JMP $C5F5       - A:00 X:01 Y:00 SP:fd ..-..I..          7");
+        assert_eq!(TraceFormat::Json.line(&entry),
+            r#"{"pc":49152,"bytes":[76,245,197],"mnemonic":"JMP","operands":"$C5F5","a":0,"x":1,"y":0,"p":36,"sp":253,"cycles":7}"#);
+        assert_eq!(json_string("a\"b\\"), r#""a\"b\\""#);
+        assert_eq!(TraceFormat::call_line(&entry, 0xC5F5, 2), "         7      C000  JMP $C5F5       -> C5F5");
+
+        assert_eq!("nestest".parse(), Ok(TraceFormat::Nestest));
+        assert!("csv".parse::<TraceFormat>().is_err());
+    }
+
+    #[test]
+    fn reference() {
+        let nestest = "C5F5  A2 00     LDX #$00                        A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7";
+        assert_eq!(ReferenceLine::parse(nestest), Ok(ReferenceLine {
+            pc: 0xC5F5, ac: Some(0), x: Some(0), y: Some(0), sr: Some(0x24), sp: Some(0xFD), cycles: Some(7),
+        }));
+        let vice = ReferenceLine::parse(".C:c5f5  a2 00     LDX #$00        - A:01 X:00 Y:00 SP:fd ..-..I..          7").unwrap();
+        assert_eq!((vice.pc, vice.ac, vice.sr, vice.sp, vice.cycles), (0xC5F5, Some(1), None, Some(0xFD), None));
+        let json = ReferenceLine::parse(r##"{"pc":50677,"bytes":[162,0],"mnemonic":"LDX","operands":"#$00","a":0,"x":0,"y":0,"p":36,"sp":253,"cycles":7}""##);
+        assert_eq!(json, ReferenceLine::parse(nestest));
+        assert!(ReferenceLine::parse("xyz A:00").is_err());
+
+        let entry = |pc, x, cycles| TraceEntry {
+            pc, bytes: vec![0xE8], mnemonic: String::from("INX"), operands: String::new(),
+            ac: 0, x, y: 0, sr: 0x24, sp: 0xFD, cycles,
+        };
+        let log = "C000  E8  INX  A:00 X:00 Y:00 P:24 SP:FD CYC:7\n\nC001  E8  INX  A:00 X:01 Y:00 P:24 SP:FD CYC:9\nC002  E8  INX  A:00 X:02 Y:00 P:24 SP:FD CYC:11\n";
+        let mut comparison = TraceComparison::create(Box::new(log.as_bytes()));
+        assert!(comparison.check(&entry(0xC000, 0, 7)).is_ok());
+        assert!(comparison.check(&entry(0xC001, 1, 9)).is_ok());
+        let divergence = comparison.check(&entry(0xC002, 3, 12)).unwrap_err();
+        assert_eq!(divergence.line, 4);
+        assert_eq!(divergence.differences, vec![("X", String::from("$02"), String::from("$03")), ("CYC", String::from("11"), String::from("12"))]);
+        assert_eq!(divergence.to_string().lines().last(), Some("  CYC expected 11, actual 12"));
+        assert!(!comparison.is_active());
+        assert_eq!(comparison.matched(), 2);
+
+        let mut comparison = TraceComparison::create(Box::new("C000 A:00\n".as_bytes()));
+        assert!(comparison.check(&entry(0xC000, 0, 7)).is_ok());
+        assert!(comparison.check(&entry(0xC001, 0, 9)).is_ok());
+        assert!(!comparison.is_active() && comparison.divergence().is_none());
+    }
+}
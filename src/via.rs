@@ -0,0 +1,617 @@
+// MOS 6522 Versatile Interface Adapter
+//
+// Register layout (offset from base address):
+//   +0  ORB/IRB       port B; reads input pins where DDRB is 0 and the output register where it is 1
+//   +1  ORA/IRA       port A; accesses clear the CA1/CA2 interrupt flags
+//   +2  DDRB          data direction of port B (1 = output)
+//   +3  DDRA
+//   +4  T1C-L         write: T1 latch LB; read: counter LB, clears the T1 flag
+//   +5  T1C-H         write: latch HB, loads the counter from the latch and starts T1; read: counter HB
+//   +6  T1L-L         T1 latch LB
+//   +7  T1L-H         T1 latch HB; writes clear the T1 flag
+//   +8  T2C-L         write: T2 latch LB; read: counter LB, clears the T2 flag
+//   +9  T2C-H         write: counter HB, loads the counter LB from the latch and starts T2; read: counter HB
+//   +A  SR            shift register; accesses clear the SR flag and start shifting 8 bits
+//   +B  ACR           auxiliary control: T1 mode (bits 7-6), T2 mode (bit 5), shift register mode (bits 4-2)
+//   +C  PCR           peripheral control: active edges of CA1 (bit 0) and CB1 (bit 4)
+//   +D  IFR           interrupt flags; writing 1 clears a flag, bit 7 is set while any enabled flag is
+//   +E  IER           interrupt enable; writes set (bit 7 = 1) or clear the given bits, reads return bit 7 set
+//   +F  ORA/IRA       port A without affecting the interrupt flags
+//
+// Timers count down once per cycle. T1 sets its flag when it passes zero and, in free-run mode, reloads from the latch
+// a cycle later (period N+2); in one-shot mode only the first time-out after starting it sets the flag. T2 is
+// one-shot or counts negative pulses on PB6. The handshake modes of CA2/CB2 are not emulated.
+
+use std::io::{self, Read, Write};
+use std::ops::RangeInclusive;
+
+use crate::device::Device;
+use crate::state;
+
+pub const VIA_BASE_DEFAULT: u16 = 0x6000;
+pub const VIA_REGISTERS: u16 = 16;
+
+pub const REG_ORB: u16 = 0x0;
+pub const REG_ORA: u16 = 0x1;
+pub const REG_DDRB: u16 = 0x2;
+pub const REG_DDRA: u16 = 0x3;
+pub const REG_T1C_L: u16 = 0x4;
+pub const REG_T1C_H: u16 = 0x5;
+pub const REG_T1L_L: u16 = 0x6;
+pub const REG_T1L_H: u16 = 0x7;
+pub const REG_T2C_L: u16 = 0x8;
+pub const REG_T2C_H: u16 = 0x9;
+pub const REG_SR: u16 = 0xA;
+pub const REG_ACR: u16 = 0xB;
+pub const REG_PCR: u16 = 0xC;
+pub const REG_IFR: u16 = 0xD;
+pub const REG_IER: u16 = 0xE;
+pub const REG_ORA_NH: u16 = 0xF;
+
+// interrupt flags (IFR/IER)
+pub const INT_CA2: u8 = 0b00000001;
+pub const INT_CA1: u8 = 0b00000010;
+pub const INT_SR: u8 = 0b00000100;
+pub const INT_CB2: u8 = 0b00001000;
+pub const INT_CB1: u8 = 0b00010000;
+pub const INT_T2: u8 = 0b00100000;
+pub const INT_T1: u8 = 0b01000000;
+pub const INT_ANY: u8 = 0b10000000;
+
+pub const ACR_PB7_OUTPUT: u8 = 0b10000000;
+pub const ACR_T1_FREE_RUN: u8 = 0b01000000;
+pub const ACR_T2_PULSE_COUNT: u8 = 0b00100000;
+pub const ACR_SR_MODE: u8 = 0b00011100;
+
+pub const PCR_CA1_POSITIVE: u8 = 0b00000001;
+pub const PCR_CB1_POSITIVE: u8 = 0b00010000;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum ShiftClock {
+    T2,         // a bit every second T2 time-out of the LB latch, i.e. every 2*(N+2) cycles
+    Phi2,       // a bit per cycle
+    Cb1,        // a bit per positive edge on CB1
+}
+
+#[derive(Clone, Debug)]
+pub struct Via {
+    name: String,
+    pub base: u16,
+    ora: u8,
+    orb: u8,
+    ddra: u8,
+    ddrb: u8,
+    pins_a: u8,             // levels applied to the port pins from outside
+    pins_b: u8,
+    ca1: bool,
+    cb1: bool,
+    cb2_in: bool,
+    cb2_out: bool,          // last bit shifted out
+    t1_counter: u16,
+    t1_latch: u16,
+    t1_armed: bool,         // flag is set at the next time-out
+    t1_reload: bool,        // counter passed zero in free-run mode and is reloaded with the next cycle
+    pb7: bool,
+    t2_counter: u16,
+    t2_latch_lb: u8,
+    t2_armed: bool,
+    sr: u8,
+    sr_bits: u8,            // bits left to shift
+    sr_cycles: u64,         // towards the next bit
+    acr: u8,
+    pcr: u8,
+    ifr: u8,
+    ier: u8,
+}
+
+impl Via {
+    pub fn create(name: &str, base: u16) -> Self {
+        Self {
+            name: String::from(name),
+            base,
+            ora: 0,
+            orb: 0,
+            ddra: 0,
+            ddrb: 0,
+            pins_a: 0xFF,       // inputs are pulled up
+            pins_b: 0xFF,
+            ca1: false,
+            cb1: false,
+            cb2_in: false,
+            cb2_out: false,
+            t1_counter: 0,
+            t1_latch: 0,
+            t1_armed: false,
+            t1_reload: false,
+            pb7: true,
+            t2_counter: 0,
+            t2_latch_lb: 0,
+            t2_armed: false,
+            sr: 0,
+            sr_bits: 0,
+            sr_cycles: 0,
+            acr: 0,
+            pcr: 0,
+            ifr: 0,
+            ier: 0,
+        }
+    }
+
+    // levels on the port pins: driven by the output register where configured as output, from outside otherwise
+    pub fn port_a(&self) -> u8 {
+        (self.ora & self.ddra) | (self.pins_a & !self.ddra)
+    }
+
+    pub fn port_b(&self) -> u8 {
+        let port = (self.orb & self.ddrb) | (self.pins_b & !self.ddrb);
+        match self.acr & ACR_PB7_OUTPUT != 0 {
+            true => (port & 0x7F) | ((self.pb7 as u8) << 7),
+            false => port,
+        }
+    }
+
+    // levels applied to the input pins
+    pub fn set_port_a(&mut self, pins: u8) {
+        self.pins_a = pins;
+    }
+
+    pub fn set_port_b(&mut self, pins: u8) {
+        let falling = self.pins_b & !pins;
+        self.pins_b = pins;
+        if self.acr & ACR_T2_PULSE_COUNT != 0 && falling & 0x40 != 0 && self.ddrb & 0x40 == 0 {
+            self.t2_counter = self.t2_counter.wrapping_sub(1);
+            if self.t2_counter == 0 && self.t2_armed {
+                self.t2_armed = false;
+                self.ifr |= INT_T2;
+            }
+        }
+    }
+
+    // control inputs; CA1 and CB1 set their flag on the edge selected in PCR
+    pub fn set_ca1(&mut self, level: bool) {
+        if level != self.ca1 && level == (self.pcr & PCR_CA1_POSITIVE != 0) {
+            self.ifr |= INT_CA1;
+        }
+        self.ca1 = level;
+    }
+
+    pub fn set_cb1(&mut self, level: bool) {
+        if level != self.cb1 {
+            if level == (self.pcr & PCR_CB1_POSITIVE != 0) {
+                self.ifr |= INT_CB1;
+            }
+            if level && self.shift_clock() == Some(ShiftClock::Cb1) {
+                self.shift_bit();
+            }
+        }
+        self.cb1 = level;
+    }
+
+    // data shifted in
+    pub fn set_cb2(&mut self, level: bool) {
+        self.cb2_in = level;
+    }
+
+    // data shifted out
+    pub fn cb2(&self) -> bool {
+        self.cb2_out
+    }
+
+    fn reg(&self, addr: u16) -> u16 {
+        addr.wrapping_sub(self.base) % VIA_REGISTERS
+    }
+
+    fn ifr(&self) -> u8 {
+        match self.ifr & self.ier & !INT_ANY != 0 {
+            true => self.ifr | INT_ANY,
+            false => self.ifr,
+        }
+    }
+
+    fn start_t1(&mut self) {
+        self.t1_counter = self.t1_latch;
+        self.t1_armed = true;
+        self.t1_reload = false;
+        self.ifr &= !INT_T1;
+        if self.acr & ACR_T1_FREE_RUN == 0 {
+            self.pb7 = false;       // low until the time-out in one-shot mode
+        }
+    }
+
+    fn tick_t1(&mut self, mut cycles: u64) {
+        while cycles > 0 {
+            if self.t1_reload {
+                self.t1_reload = false;
+                self.t1_counter = self.t1_latch;
+                cycles -= 1;
+                continue;
+            }
+
+            // count down to the next time-out at most
+            let steps = cycles.min(self.t1_counter as u64 + 1);
+            self.t1_counter = self.t1_counter.wrapping_sub(steps as u16);
+            cycles -= steps;
+            if self.t1_counter != 0xFFFF {
+                continue;
+            }
+
+            let free_run = self.acr & ACR_T1_FREE_RUN != 0;
+            if self.t1_armed {
+                self.ifr |= INT_T1;
+                self.pb7 = if free_run { !self.pb7 } else { true };
+                self.t1_armed = free_run;
+            }
+            self.t1_reload = free_run;
+        }
+    }
+
+    fn tick_t2(&mut self, cycles: u64) {
+        if self.acr & ACR_T2_PULSE_COUNT != 0 {
+            return;
+        }
+        let remaining = self.t2_counter as u64 + 1;     // cycles until passing zero
+        self.t2_counter = self.t2_counter.wrapping_sub(cycles as u16);
+        if cycles >= remaining && self.t2_armed {
+            self.t2_armed = false;
+            self.ifr |= INT_T2;
+        }
+    }
+
+    fn shift_clock(&self) -> Option<ShiftClock> {
+        match (self.acr & ACR_SR_MODE) >> 2 {
+            0b001 | 0b100 | 0b101 => Some(ShiftClock::T2),
+            0b010 | 0b110 => Some(ShiftClock::Phi2),
+            0b011 | 0b111 => Some(ShiftClock::Cb1),
+            _ => None,
+        }
+    }
+
+    fn start_shift(&mut self) {
+        self.ifr &= !INT_SR;
+        if self.shift_clock().is_some() {
+            self.sr_bits = 8;
+            self.sr_cycles = 0;
+        }
+    }
+
+    // shifts out MSB first (modes 1xx) or in at the LSB (modes 0xx)
+    fn shift_bit(&mut self) {
+        let mode = (self.acr & ACR_SR_MODE) >> 2;
+        let free_run = mode == 0b100;
+        if self.sr_bits == 0 && !free_run {
+            return;
+        }
+        if mode & 0b100 != 0 {
+            self.cb2_out = self.sr & 0x80 != 0;
+            self.sr = self.sr.rotate_left(1);
+        } else {
+            self.sr = (self.sr << 1) | self.cb2_in as u8;
+        }
+        if free_run {
+            return;
+        }
+        self.sr_bits -= 1;
+        if self.sr_bits == 0 {
+            self.ifr |= INT_SR;
+        }
+    }
+
+    fn tick_sr(&mut self, cycles: u64) {
+        let period = match self.shift_clock() {
+            Some(ShiftClock::Phi2) => 1,
+            Some(ShiftClock::T2) => 2 * (self.t2_latch_lb as u64 + 2),
+            _ => return,
+        };
+        if self.sr_bits == 0 && (self.acr & ACR_SR_MODE) >> 2 != 0b100 {
+            return;
+        }
+        self.sr_cycles += cycles;
+        while self.sr_cycles >= period {
+            self.sr_cycles -= period;
+            self.shift_bit();
+        }
+    }
+}
+
+impl Device for Via {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        self.base..=self.base + (VIA_REGISTERS - 1)
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        let value = self.peek(addr);
+        match self.reg(addr) {
+            REG_ORB => self.ifr &= !(INT_CB1 | INT_CB2),
+            REG_ORA => self.ifr &= !(INT_CA1 | INT_CA2),
+            REG_T1C_L => self.ifr &= !INT_T1,
+            REG_T2C_L => self.ifr &= !INT_T2,
+            REG_SR => self.start_shift(),
+            _ => {},
+        }
+        value
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match self.reg(addr) {
+            REG_ORB => self.port_b(),
+            REG_ORA | REG_ORA_NH => self.port_a(),
+            REG_DDRB => self.ddrb,
+            REG_DDRA => self.ddra,
+            REG_T1C_L => self.t1_counter as u8,
+            REG_T1C_H => (self.t1_counter >> 8) as u8,
+            REG_T1L_L => self.t1_latch as u8,
+            REG_T1L_H => (self.t1_latch >> 8) as u8,
+            REG_T2C_L => self.t2_counter as u8,
+            REG_T2C_H => (self.t2_counter >> 8) as u8,
+            REG_SR => self.sr,
+            REG_ACR => self.acr,
+            REG_PCR => self.pcr,
+            REG_IFR => self.ifr(),
+            _ => self.ier | INT_ANY,
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match self.reg(addr) {
+            REG_ORB => {
+                self.orb = value;
+                self.ifr &= !(INT_CB1 | INT_CB2);
+            },
+            REG_ORA => {
+                self.ora = value;
+                self.ifr &= !(INT_CA1 | INT_CA2);
+            },
+            REG_ORA_NH => self.ora = value,
+            REG_DDRB => self.ddrb = value,
+            REG_DDRA => self.ddra = value,
+            REG_T1C_L | REG_T1L_L => self.t1_latch = (self.t1_latch & 0xFF00) | value as u16,
+            REG_T1C_H => {
+                self.t1_latch = (self.t1_latch & 0x00FF) | (value as u16) << 8;
+                self.start_t1();
+            },
+            REG_T1L_H => {
+                self.t1_latch = (self.t1_latch & 0x00FF) | (value as u16) << 8;
+                self.ifr &= !INT_T1;
+            },
+            REG_T2C_L => self.t2_latch_lb = value,
+            REG_T2C_H => {
+                self.t2_counter = (value as u16) << 8 | self.t2_latch_lb as u16;
+                self.t2_armed = true;
+                self.ifr &= !INT_T2;
+            },
+            REG_SR => {
+                self.sr = value;
+                self.start_shift();
+            },
+            REG_ACR => self.acr = value,
+            REG_PCR => self.pcr = value,
+            REG_IFR => self.ifr &= !(value & !INT_ANY),
+            _ => match value & INT_ANY != 0 {
+                true => self.ier |= value & !INT_ANY,
+                false => self.ier &= !value,
+            },
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.tick_t1(cycles);
+        self.tick_t2(cycles);
+        self.tick_sr(cycles);
+    }
+
+    fn irq(&self) -> bool {
+        self.ifr() & INT_ANY != 0
+    }
+
+    // registers are cleared, the timers and the shift register are stopped; pins keep their levels
+    fn reset(&mut self) {
+        *self = Self {
+            pins_a: self.pins_a,
+            pins_b: self.pins_b,
+            ca1: self.ca1,
+            cb1: self.cb1,
+            cb2_in: self.cb2_in,
+            t1_counter: self.t1_counter,
+            t1_latch: self.t1_latch,
+            t2_counter: self.t2_counter,
+            ..Self::create(&self.name, self.base)
+        };
+    }
+
+    fn save_state(&self, writer: &mut dyn Write) -> io::Result<()> {
+        for value in [self.ora, self.orb, self.ddra, self.ddrb, self.pins_a, self.pins_b] {
+            state::write_u8(writer, value)?;
+        }
+        for value in [self.ca1, self.cb1, self.cb2_in, self.cb2_out] {
+            state::write_bool(writer, value)?;
+        }
+        state::write_u16(writer, self.t1_counter)?;
+        state::write_u16(writer, self.t1_latch)?;
+        for value in [self.t1_armed, self.t1_reload, self.pb7] {
+            state::write_bool(writer, value)?;
+        }
+        state::write_u16(writer, self.t2_counter)?;
+        state::write_u8(writer, self.t2_latch_lb)?;
+        state::write_bool(writer, self.t2_armed)?;
+        state::write_u8(writer, self.sr)?;
+        state::write_u8(writer, self.sr_bits)?;
+        state::write_u64(writer, self.sr_cycles)?;
+        for value in [self.acr, self.pcr, self.ifr, self.ier] {
+            state::write_u8(writer, value)?;
+        }
+        Ok(())
+    }
+
+    fn load_state(&mut self, reader: &mut dyn Read) -> io::Result<()> {
+        for value in [&mut self.ora, &mut self.orb, &mut self.ddra, &mut self.ddrb, &mut self.pins_a, &mut self.pins_b] {
+            *value = state::read_u8(reader)?;
+        }
+        for value in [&mut self.ca1, &mut self.cb1, &mut self.cb2_in, &mut self.cb2_out] {
+            *value = state::read_bool(reader)?;
+        }
+        self.t1_counter = state::read_u16(reader)?;
+        self.t1_latch = state::read_u16(reader)?;
+        for value in [&mut self.t1_armed, &mut self.t1_reload, &mut self.pb7] {
+            *value = state::read_bool(reader)?;
+        }
+        self.t2_counter = state::read_u16(reader)?;
+        self.t2_latch_lb = state::read_u8(reader)?;
+        self.t2_armed = state::read_bool(reader)?;
+        self.sr = state::read_u8(reader)?;
+        self.sr_bits = state::read_u8(reader)?;
+        self.sr_cycles = state::read_u64(reader)?;
+        for value in [&mut self.acr, &mut self.pcr, &mut self.ifr, &mut self.ier] {
+            *value = state::read_u8(reader)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: u16 = VIA_BASE_DEFAULT;
+
+    fn setup() -> Via {
+        Via::create("via", BASE)
+    }
+
+    #[test]
+    fn ports() {
+        let mut via = setup();
+        via.write(BASE + REG_DDRA, 0xF0);
+        via.write(BASE + REG_ORA, 0xA5);
+        via.set_port_a(0x3C);
+        assert_eq!((via.port_a(), via.read(BASE + REG_ORA)), (0xAC, 0xAC));
+
+        // CA1 on the selected edge, cleared by accessing ORA but not ORA without handshake
+        via.write(BASE + REG_PCR, PCR_CA1_POSITIVE);
+        via.write(BASE + REG_IER, INT_ANY | INT_CA1);
+        via.set_ca1(true);
+        assert!(via.irq());
+        assert_eq!((via.peek(BASE + REG_IFR), via.peek(BASE + REG_IER)), (INT_ANY | INT_CA1, INT_ANY | INT_CA1));
+        via.read(BASE + REG_ORA_NH);
+        assert!(via.irq());
+        via.read(BASE + REG_ORA);
+        assert!(!via.irq());
+        via.set_ca1(false);
+        assert!(!via.irq());
+
+        // disabled flags are set without an interrupt
+        via.write(BASE + REG_IER, INT_CA1);
+        via.set_ca1(true);
+        assert_eq!((via.peek(BASE + REG_IFR), via.irq()), (INT_CA1, false));
+        via.write(BASE + REG_IFR, INT_CA1);
+        assert_eq!(via.peek(BASE + REG_IFR), 0);
+    }
+
+    #[test]
+    fn timer1() {
+        let mut via = setup();
+        via.write(BASE + REG_IER, INT_ANY | INT_T1);
+        via.write(BASE + REG_T1C_L, 10);
+        via.write(BASE + REG_T1C_H, 0);
+        via.tick(10);
+        assert_eq!((via.peek(BASE + REG_T1C_L), via.irq()), (0, false));
+        via.tick(1);
+        assert!(via.irq());
+        via.read(BASE + REG_T1C_L);
+        assert!(!via.irq());
+
+        // one-shot: no further interrupts until restarted
+        via.tick(0x20000);
+        assert!(!via.irq());
+
+        // free-run: every N+2 cycles, toggling PB7
+        via.write(BASE + REG_ACR, ACR_T1_FREE_RUN | ACR_PB7_OUTPUT);
+        via.write(BASE + REG_T1C_H, 0);
+        via.tick(11);
+        assert_eq!((via.irq(), via.port_b() & 0x80), (true, 0x00));
+        via.write(BASE + REG_IFR, INT_T1);
+        via.tick(11);
+        assert!(!via.irq());
+        via.tick(1);
+        assert_eq!((via.irq(), via.port_b() & 0x80, via.peek(BASE + REG_T1C_L)), (true, 0x80, 0xFF));
+        via.tick(1);
+        assert_eq!((via.peek(BASE + REG_T1C_L), via.peek(BASE + REG_T1L_L)), (10, 10));
+    }
+
+    #[test]
+    fn timer2() {
+        let mut via = setup();
+        via.write(BASE + REG_IER, INT_ANY | INT_T2);
+        via.write(BASE + REG_T2C_L, 0x00);
+        via.write(BASE + REG_T2C_H, 0x01);
+        via.tick(0x100);
+        assert!(!via.irq());
+        via.tick(1);
+        assert_eq!((via.irq(), via.peek(BASE + REG_T2C_H)), (true, 0xFF));
+        via.read(BASE + REG_T2C_L);
+        via.tick(0x10000);
+        assert!(!via.irq());
+
+        // counting pulses on PB6
+        via.write(BASE + REG_ACR, ACR_T2_PULSE_COUNT);
+        via.write(BASE + REG_T2C_L, 2);
+        via.write(BASE + REG_T2C_H, 0);
+        for _ in 0..2 {
+            assert!(!via.irq());
+            via.set_port_b(0xBF);
+            via.set_port_b(0xFF);
+        }
+        assert!(via.irq());
+    }
+
+    #[test]
+    fn shift_register() {
+        let mut via = setup();
+
+        // shift out under φ2, MSB first
+        via.write(BASE + REG_IER, INT_ANY | INT_SR);
+        via.write(BASE + REG_ACR, 0b110 << 2);
+        via.write(BASE + REG_SR, 0b10100000);
+        via.tick(1);
+        assert!(via.cb2());
+        via.tick(1);
+        assert!(!via.cb2());
+        via.tick(5);
+        assert!(!via.irq());
+        via.tick(1);
+        assert_eq!((via.irq(), via.peek(BASE + REG_SR)), (true, 0b10100000));
+
+        // shift in on CB1 edges
+        via.write(BASE + REG_ACR, 0b011 << 2);
+        via.read(BASE + REG_SR);
+        assert!(!via.irq());
+        for bit in [true, false, false, true, true, false, true, true] {
+            via.set_cb2(bit);
+            via.set_cb1(true);
+            via.set_cb1(false);
+        }
+        assert_eq!((via.irq(), via.peek(BASE + REG_SR)), (true, 0b10011011));
+    }
+
+    #[test]
+    fn on_bus() {
+        let mut mem = crate::mem::Memory::create();
+        mem.attach_device(Box::new(setup()));
+        mem.write_u8(BASE + REG_DDRB, 0xFF);
+        mem.write_u8(BASE + REG_ORB, 0x42);
+        mem.device_mut::<Via>("via").unwrap().set_port_a(0x17);
+        assert_eq!((mem.read_u8(BASE + REG_ORA), mem.device::<Via>("via").unwrap().port_b()), (0x17, 0x42));
+
+        mem.write_u8(BASE + REG_IER, INT_ANY | INT_T1);
+        mem.write_u8(BASE + REG_T1C_L, 1);
+        mem.write_u8(BASE + REG_T1C_H, 0);
+        mem.tick_devices(2);
+        assert!(mem.device_irq());
+        mem.reset_devices();
+        assert!(!mem.device_irq());
+        assert_eq!(mem.peek(BASE + REG_DDRB), 0);
+    }
+}
@@ -0,0 +1,280 @@
+// Commodore VIC-20 (PAL, unexpanded): 5K RAM, the ROMs at fixed addresses and the I/O chips at $9000:
+//
+//  $0000-$03FF  RAM
+//  $0400-$0FFF  3K expansion, open
+//  $1000-$1FFF  RAM, the screen at $1E00
+//  $2000-$7FFF  expansion blocks 1-3, open
+//  $8000-$8FFF  character ROM ('char', 4K)
+//  $9000-$900F  VIC (6561) registers; only the raster counter ($9003 bit 7, $9004) works, 312 lines of 71 cycles
+//  $9110-$911F  VIA 1: NMI
+//  $9120-$912F  VIA 2: keyboard matrix (columns selected on port B, rows read on port A), IRQ
+//  $9400-$97FF  color RAM (4 bits)
+//  $A000-$BFFF  expansion block 5 (cartridges), open
+//  $C000-$DFFF  BASIC ROM ('basic', 8K)
+//  $E000-$FFFF  KERNAL ROM ('kernal', 8K)
+//
+// Open addresses read the last byte on the bus, taken as the high byte of the address; writes to them are ignored.
+// The screen RAM is shown in the terminal. Host keys are typed on the keyboard matrix as PETSCII.
+
+use std::ops::RangeInclusive;
+
+use crate::acia::SerialBackend;
+use crate::commodore::{self, Matrix, MatrixKeyboard};
+use crate::device::Device;
+use crate::keyboard::Keymap;
+use crate::machine::{Machine, Profile, RomSlot, Wiring};
+use crate::screen::{Charset, TextScreen};
+use crate::via::Via;
+
+pub const SCREEN_RAM: u16 = 0x1E00;
+pub const IO_BASE: u16 = 0x9000;
+pub const VIA1_BASE: u16 = 0x9110;
+pub const VIA2_BASE: u16 = 0x9120;
+
+const RASTER_LINES: u16 = 312;
+const LINE_CYCLES: u64 = 71;
+
+// columns on port B, rows on port A
+const MATRIX: Matrix = [
+    [b'1', 0x5F, 0, 0x03, b' ', 0, b'Q', b'2'],             // ... LEFT-ARROW CTRL RUN/STOP ... C= ...
+    [b'3', b'W', b'A', 0, b'Z', b'S', b'E', b'4'],          // ... LEFT-SHIFT ...
+    [b'5', b'R', b'D', b'X', b'C', b'F', b'T', b'6'],
+    [b'7', b'Y', b'G', b'V', b'B', b'H', b'U', b'8'],
+    [b'9', b'I', b'J', b'N', b'M', b'K', b'O', b'0'],
+    [b'+', b'P', b'L', b',', b'.', b':', b'@', b'-'],
+    [0x5C, b'*', b';', b'/', 0, b'=', 0x5E, 0x13],          // POUND ... RIGHT-SHIFT ... UP-ARROW HOME
+    [0x14, 0x0D, 0x1D, 0x11, 0x85, 0x86, 0x87, 0x88],     // DEL RETURN CRSR-RIGHT CRSR-DOWN F1 F3 F5 F7
+];
+const LEFT_SHIFT: (u8, u8) = (1, 3);
+
+// expansion areas without RAM or cartridge
+const OPEN: [(&str, RangeInclusive<u16>); 3] = [
+    ("expansion-3k", 0x0400..=0x0FFF),
+    ("expansion-blocks-1-3", 0x2000..=0x7FFF),
+    ("expansion-block-5", 0xA000..=0xBFFF),
+];
+
+pub const VIC20: Profile = Profile {
+    name: "vic20",
+    description: "Commodore VIC-20 (PAL, unexpanded): 5K RAM, ROMs 'char' (4K) at $8000, 'basic' (8K) at $C000 and 'kernal' (8K) at $E000, VIAs at $9110/$9120, screen at $1E00",
+    clock_hz: 1_108_405,
+    frame_cycles: RASTER_LINES as u64 * LINE_CYCLES,     // 50 Hz
+    roms: &[
+        RomSlot { name: "basic", addr: 0xC000, size: 0x2000 },
+        RomSlot { name: "kernal", addr: 0xE000, size: 0x2000 },
+        RomSlot { name: "char", addr: 0x8000, size: 0x1000 },
+    ],
+    keyboard: true,
+    wire,
+};
+
+fn wire(machine: &mut Machine, wiring: &mut Wiring) -> Result<(), String> {
+    let keymap = wiring.keymap.take().unwrap_or_else(commodore::keymap);
+    machine.attach(Box::new(Vic20Io::create(wiring.keyboard.take(), keymap)));
+    for (name, range) in OPEN {
+        machine.attach(Box::new(OpenBus { name, range }));
+    }
+    machine.screen = Some(TextScreen::create(SCREEN_RAM, 22, 23, Charset::Petscii)?);
+    Ok(())
+}
+
+struct OpenBus {
+    name: &'static str,
+    range: RangeInclusive<u16>,
+}
+
+impl Device for OpenBus {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        self.range.clone()
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        self.peek(addr)
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        (addr >> 8) as u8
+    }
+
+    fn write(&mut self, _addr: u16, _value: u8) {}
+}
+
+// the I/O area: VIC registers, VIAs and color RAM
+pub struct Vic20Io {
+    vic: [u8; 16],
+    raster: u16,
+    cycles: u64,            // into the current line
+    color: [u8; 0x400],
+    via1: Via,
+    via2: Via,
+    keyboard: MatrixKeyboard,
+}
+
+impl Vic20Io {
+    pub fn create(backend: Option<Box<dyn SerialBackend>>, keymap: Keymap) -> Self {
+        Self {
+            vic: [0; 16],
+            raster: 0,
+            cycles: 0,
+            color: [0; 0x400],
+            via1: Via::create("via1", VIA1_BASE),
+            via2: Via::create("via2", VIA2_BASE),
+            keyboard: MatrixKeyboard::create(backend, keymap, &MATRIX, LEFT_SHIFT),
+        }
+    }
+
+    pub fn press(&mut self, host: u8) {
+        self.keyboard.press(host);
+    }
+
+    pub fn via1(&self) -> &Via {
+        &self.via1
+    }
+
+    pub fn via2(&self) -> &Via {
+        &self.via2
+    }
+
+    fn scan(&mut self) {
+        let rows = self.keyboard.rows(self.via2.port_b());
+        self.via2.set_port_a(rows);
+    }
+
+    fn peek_vic(&self, reg: usize) -> u8 {
+        match reg {
+            0x3 => (self.vic[0x3] & 0x7F) | ((self.raster & 1) << 7) as u8,
+            0x4 => (self.raster >> 1) as u8,
+            0x8 | 0x9 => 0xFF,      // paddles
+            _ => self.vic[reg],
+        }
+    }
+}
+
+impl Device for Vic20Io {
+    fn name(&self) -> &str {
+        "vic20-io"
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        IO_BASE..=0x9FFF
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x9110..=0x911F => self.via1.read(addr),
+            0x9120..=0x912F => self.via2.read(addr),
+            _ => self.peek(addr),
+        }
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x9000..=0x900F => self.peek_vic((addr % 16) as usize),
+            0x9110..=0x911F => self.via1.peek(addr),
+            0x9120..=0x912F => self.via2.peek(addr),
+            0x9400..=0x97FF => self.color[(addr - 0x9400) as usize],
+            _ => (addr >> 8) as u8,
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x9000..=0x900F => self.vic[(addr % 16) as usize] = value,
+            0x9110..=0x911F => self.via1.write(addr, value),
+            0x9120..=0x912F => {
+                self.via2.write(addr, value);
+                self.scan();
+            },
+            0x9400..=0x97FF => self.color[(addr - 0x9400) as usize] = value & 0x0F,
+            _ => {},
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.cycles += cycles;
+        self.raster = ((self.raster as u64 + self.cycles / LINE_CYCLES) % RASTER_LINES as u64) as u16;
+        self.cycles %= LINE_CYCLES;
+        self.via1.tick(cycles);
+        self.via2.tick(cycles);
+        self.keyboard.tick(cycles);
+        self.scan();
+    }
+
+    fn irq(&self) -> bool {
+        self.via2.irq()
+    }
+
+    fn nmi(&self) -> bool {
+        self.via1.irq()
+    }
+
+    // the VIAs are reset, the VIC and color RAM are kept
+    fn reset(&mut self) {
+        self.via1.reset();
+        self.via2.reset();
+        self.keyboard.reset();
+        self.scan();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::via::{ACR_T1_FREE_RUN, INT_ANY, INT_T1};
+
+    use super::*;
+
+    #[test]
+    fn memory() {
+        // the character ROM is mapped as given
+        let filename = std::env::temp_dir().join(format!("rust-6502-emu-vic20-char-{}.rom", std::process::id()));
+        std::fs::write(&filename, vec![0x3C; 0x1000]).unwrap();
+        let roms = vec![(String::from("char"), String::from(filename.to_str().unwrap()))];
+        let mut machine = Machine::create(&VIC20, Wiring::with_roms(roms)).unwrap();
+        std::fs::remove_file(&filename).unwrap();
+        let screen = machine.screen.as_ref().unwrap();
+        assert_eq!((screen.range(), screen.columns, screen.rows), (0x1E00..=0x1FF9, 22, 23));
+
+        let mem = &mut machine.mem;
+        for addr in [0x0300, 0x0400, 0x1000, 0x2000, 0x8000, 0x9400, 0xA000] {
+            mem.write_u8(addr, 0xA5);
+        }
+        assert_eq!((mem.peek(0x0300), mem.peek(0x0400), mem.peek(0x1000)), (0xA5, 0x04, 0xA5));
+        assert_eq!((mem.peek(0x2000), mem.peek(0x8000), mem.peek(0x9400), mem.peek(0xA000)), (0x20, 0x3C, 0x05, 0xA0));
+
+        // raster line 3 after 3 lines and some cycles
+        mem.tick_devices(3 * LINE_CYCLES + 10);
+        assert_eq!((mem.peek(0x9003) & 0x80, mem.peek(0x9004)), (0x80, 1));
+    }
+
+    #[test]
+    fn keyboard() {
+        let mut machine = Machine::create(&VIC20, Wiring::default()).unwrap();
+        let mem = &mut machine.mem;
+
+        // column 1 selected, the row of A
+        mem.write_u8(0x9122, 0xFF);
+        mem.write_u8(0x9120, 0xFD);
+        mem.device_mut::<Vic20Io>("vic20-io").unwrap().press(b'a');
+        mem.tick_devices(1);
+        assert_eq!(mem.peek(0x9121), 0xFB);
+        mem.write_u8(0x9120, 0xFE);
+        assert_eq!(mem.peek(0x9121), 0xFF);
+
+        // VIA 2 interrupts the CPU, VIA 1 raises NMI
+        mem.write_u8(0x912B, ACR_T1_FREE_RUN);
+        mem.write_u8(0x912E, INT_ANY | INT_T1);
+        mem.write_u8(0x9124, 10);
+        mem.write_u8(0x9125, 0);
+        mem.write_u8(0x911E, INT_ANY | INT_T1);
+        mem.write_u8(0x9114, 20);
+        mem.write_u8(0x9115, 0);
+        mem.tick_devices(12);
+        assert!(mem.device_irq() && !mem.device_nmi());
+        mem.tick_devices(10);
+        assert!(mem.device_nmi());
+    }
+}
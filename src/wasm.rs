@@ -0,0 +1,119 @@
+// JavaScript interface for running the emulator in a browser (feature "wasm"), built as a cdylib for wasm32-unknown-unknown, e.g.
+//
+//   const emulator = new Emulator();
+//   emulator.load(0x0600, program);
+//   emulator.set_pc(0x0600);
+//   const stop = emulator.run(10000);       // undefined unless stopped, e.g. at a BRK without handler
+//   const { pc, ac, x, y } = emulator.registers();
+//
+// Nothing is printed and nothing panics on the program's behalf: an undefined opcode stops execution with an error
+// message like any other `StopReason` instead of aborting the WebAssembly instance.
+
+use wasm_bindgen::prelude::*;
+
+use crate::cpu::{Cpu, Registers};
+use crate::mem::Memory;
+use crate::prelude::*;
+
+#[wasm_bindgen(js_name = Emulator)]
+pub struct WasmEmulator {
+    cpu: Cpu,
+    mem: Memory,
+}
+
+#[wasm_bindgen(js_class = Emulator)]
+impl WasmEmulator {
+    // cleared memory, PC at the reset vector
+    #[wasm_bindgen(constructor)]
+    pub fn create() -> Self {
+        let mut mem = Memory::create();
+        let mut cpu = Cpu::create();
+        cpu.reset(&mut mem);
+        Self { cpu, mem }
+    }
+
+    // restarts at the reset vector keeping the memory, e.g. the loaded program
+    pub fn reset(&mut self) {
+        self.cpu.warm_reset(&mut self.mem);
+    }
+
+    pub fn load(&mut self, addr: u16, bytes: &[u8]) {
+        for (offset, &byte) in bytes.iter().enumerate() {
+            self.mem.write_u8(addr.wrapping_add(offset as u16), byte);
+        }
+    }
+
+    pub fn set_pc(&mut self, addr: u16) {
+        self.cpu.pc = addr;
+    }
+
+    // executes an instruction or enters an interrupt; returns why execution stopped, if it did
+    pub fn step(&mut self) -> Option<String> {
+        self.cpu.exec(&mut self.mem, 1).map(|reason| reason.to_string())
+    }
+
+    // executes at least the given number of cycles unless stopped before
+    pub fn run(&mut self, cycles: u64) -> Option<String> {
+        let end = self.cpu.cycles.saturating_add(cycles);
+        while self.cpu.cycles < end {
+            if let Some(reason) = self.step() {
+                return Some(reason);
+            }
+        }
+        None
+    }
+
+    pub fn registers(&self) -> Registers {
+        self.cpu.registers()
+    }
+
+    // without side effects on devices
+    pub fn read(&self, addr: u16) -> u8 {
+        self.mem.peek(addr)
+    }
+
+    pub fn read_memory(&self, addr: u16, len: u16) -> Vec<u8> {
+        (0..len).map(|offset| self.mem.peek(addr.wrapping_add(offset))).collect()
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        self.mem.write_u8(addr, value);
+    }
+}
+
+impl Default for WasmEmulator {
+    fn default() -> Self {
+        Self::create()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run() {
+        // 0600 LDX #$03, 0602 DEX, 0603 BNE $0602, 0605 STX $10, 0607 .byte $02
+        let mut emulator = WasmEmulator::create();
+        emulator.load(0x0600, &[0xA2, 0x03, 0xCA, 0xD0, 0xFD, 0x86, 0x10, 0x02]);
+        emulator.set_pc(0x0600);
+        assert_eq!(emulator.step(), None);
+        assert_eq!(emulator.registers().x, 0x03);
+
+        assert_eq!(emulator.run(1000), Some(String::from("Undefined opcode $02 at $0607")));
+        let registers = emulator.registers();
+        assert_eq!((registers.pc, registers.x, registers.cycles), (0x0607, 0x00, 7 + 2 + 3 * 2 + 2 * 3 + 2 + 3));
+        assert_eq!(emulator.read_memory(0x0604, 3), vec![0xFD, 0x86, 0x10]);
+
+        emulator.write(0x10, 0x42);
+        assert_eq!(emulator.read(0x10), 0x42);
+        emulator.reset();
+        assert_eq!((emulator.registers().pc, emulator.read(0x0600)), (emulator.read(0xFFFC) as u16 | (emulator.read(0xFFFD) as u16) << 8, 0xA2));
+
+        // 0700 SED, 0701 LDA #$19, 0703 ADC #$03 in decimal mode
+        emulator.load(0x0700, &[0xF8, 0xA9, 0x19, 0x69, 0x03]);
+        emulator.set_pc(0x0700);
+        assert_eq!((emulator.step(), emulator.step(), emulator.step()), (None, None, None));
+        assert_eq!(emulator.registers().ac, 0x22);
+    }
+}
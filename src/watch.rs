@@ -0,0 +1,128 @@
+use core::cell::RefCell;
+use core::fmt;
+use core::ops::RangeInclusive;
+
+use crate::mem::Access;
+use crate::prelude::*;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Watchpoint {
+    pub id: usize,
+    pub range: RangeInclusive<u16>,
+    pub read: bool,
+    pub write: bool,
+}
+
+impl Watchpoint {
+    fn matches(&self, addr: u16, access: Access) -> bool {
+        let watched = match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+            Access::Execute => false,
+        };
+        watched && self.range.contains(&addr)
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let access = match (self.read, self.write) {
+            (true, true) => "rw",
+            (true, false) => "r",
+            (false, true) => "w",
+            (false, false) => "-",
+        };
+        if self.range.start() == self.range.end() {
+            write!(f, "#{:<3} ${:04X}        {}", self.id, self.range.start(), access)
+        } else {
+            write!(f, "#{:<3} ${:04X}-${:04X}  {}", self.id, self.range.start(), self.range.end(), access)
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct WatchHit {
+    pub id: usize,
+    pub addr: u16,
+    pub access: Access,
+    pub old: u8,            // value before the access
+    pub new: u8,            // value after the access; same as `old` for reads
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.access {
+            Access::Write => write!(f, "Watchpoint #{}: write ${:04X}: ${:02X} -> ${:02X}", self.id, self.addr, self.old, self.new),
+            _ => write!(f, "Watchpoint #{}: read ${:04X}: ${:02X}", self.id, self.addr, self.old),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Watchpoints {
+    watchpoints: Vec<Watchpoint>,
+    next_id: usize,
+    hits: RefCell<Vec<WatchHit>>,
+}
+
+impl Watchpoints {
+    pub fn add(&mut self, range: RangeInclusive<u16>, read: bool, write: bool) -> usize {
+        self.next_id += 1;
+        self.watchpoints.push(Watchpoint { id: self.next_id, range, read, write });
+        self.next_id
+    }
+
+    pub fn remove(&mut self, id: usize) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|wp| wp.id != id);
+        self.watchpoints.len() != count
+    }
+
+    pub fn list(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.watchpoints.is_empty()
+    }
+
+    pub fn check(&self, addr: u16, access: Access, old: u8, new: u8) {
+        for wp in self.watchpoints.iter().filter(|wp| wp.matches(addr, access)) {
+            self.hits.borrow_mut().push(WatchHit { id: wp.id, addr, access, old, new });
+        }
+    }
+
+    // hits recorded since the last call
+    pub fn take_hits(&self) -> Vec<WatchHit> {
+        self.hits.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check() {
+        let mut watchpoints = Watchpoints::default();
+        let id_rw = watchpoints.add(0x0200..=0x02FF, true, true);
+        let id_w = watchpoints.add(0x0210..=0x0210, false, true);
+
+        watchpoints.check(0x0300, Access::Write, 0, 1);
+        watchpoints.check(0x0200, Access::Execute, 0, 0);
+        assert!(watchpoints.take_hits().is_empty());
+
+        watchpoints.check(0x0210, Access::Read, 5, 5);
+        watchpoints.check(0x0210, Access::Write, 5, 6);
+        assert_eq!(watchpoints.take_hits(), vec![
+            WatchHit { id: id_rw, addr: 0x0210, access: Access::Read, old: 5, new: 5 },
+            WatchHit { id: id_rw, addr: 0x0210, access: Access::Write, old: 5, new: 6 },
+            WatchHit { id: id_w, addr: 0x0210, access: Access::Write, old: 5, new: 6 },
+        ]);
+        assert!(watchpoints.take_hits().is_empty());
+
+        assert!(watchpoints.remove(id_rw));
+        assert!(!watchpoints.remove(id_rw));
+        assert_eq!(watchpoints.list().len(), 1);
+    }
+}
@@ -0,0 +1,47 @@
+// Graphical window showing a framebuffer (feature "window"), scaled up by an integer factor. Closing the window or
+// pressing Escape ends the run.
+
+use minifb::{Key, Scale, WindowOptions};
+
+use crate::framebuffer::Framebuffer;
+use crate::mem::Memory;
+
+pub const WINDOW_SCALE_DEFAULT: u8 = 4;
+
+pub struct Window {
+    window: minifb::Window,
+    framebuffer: Framebuffer,
+}
+
+impl Window {
+    pub fn create(title: &str, framebuffer: Framebuffer, scale: u8) -> Result<Self, String> {
+        let scale = match scale {
+            1 => Scale::X1,
+            2 => Scale::X2,
+            4 => Scale::X4,
+            8 => Scale::X8,
+            16 => Scale::X16,
+            32 => Scale::X32,
+            _ => return Err(format!("Unsupported window scale {scale}; expected 1, 2, 4, 8, 16 or 32")),
+        };
+        let options = WindowOptions { scale, ..WindowOptions::default() };
+        let window = minifb::Window::new(title, framebuffer.width as usize, framebuffer.height as usize, options)
+            .map_err(|error| format!("Cannot open window: {error}"))?;
+        Ok(Self { window, framebuffer })
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.window.is_open() && !self.window.is_key_down(Key::Escape)
+    }
+
+    // also processes the window's events
+    pub fn render(&mut self, mem: &Memory) -> Result<(), String> {
+        let pixels = self.framebuffer.pixels(mem);
+        self.window.update_with_buffer(&pixels, self.framebuffer.width as usize, self.framebuffer.height as usize)
+            .map_err(|error| format!("Cannot update window: {error}"))
+    }
+}
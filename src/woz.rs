@@ -0,0 +1,122 @@
+// Memory contents as text in the format of the Apple-1 Woz monitor, e.g.
+//
+//   0300: A9 01 8D 00 02
+//   : 60                   continues after the last byte of the previous line
+//   300R                   run address
+//
+// Addresses and bytes are hex with up to 4 and 2 digits; '#' and ';' start a comment.
+
+use std::fs;
+
+use crate::asm::Segment;
+use crate::mem::Memory;
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct WozImage {
+    pub segments: Vec<Segment>,     // in file order; lines continuing at the next address are merged
+    pub run: Option<u16>,           // address of the last run ("R") command
+}
+
+impl WozImage {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut image = Self::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split(['#', ';']).next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: &str| format!("Line {}: {message} '{line}'", number + 1);
+            let hex = |digits: &str, max_len: usize| match digits.len() <= max_len {
+                true => u16::from_str_radix(digits, 16).ok(),
+                false => None,
+            };
+
+            if let Some(addr) = line.strip_suffix(['R', 'r']).filter(|_| !line.contains(':')) {
+                image.run = Some(hex(addr.trim(), 4).ok_or_else(|| error("Invalid run address in"))?);
+                continue;
+            }
+
+            let Some((addr, bytes)) = line.split_once(':') else {
+                return Err(error("Expected address and bytes in"));
+            };
+            let bytes = bytes.split_whitespace()
+                .map(|byte| hex(byte, 2).map(|byte| byte as u8).ok_or_else(|| error(&format!("Invalid byte '{byte}' in"))))
+                .collect::<Result<Vec<u8>, String>>()?;
+            let addr = match addr.trim() {
+                "" => match image.segments.last() {
+                    Some(segment) => segment.addr as usize + segment.bytes.len(),
+                    None => return Err(error("Continuation without preceding address in")),
+                },
+                addr => hex(addr, 4).ok_or_else(|| error("Invalid address in"))? as usize,
+            };
+            if addr > 0xFFFF || addr + bytes.len() > 0x10000 {
+                return Err(error("Bytes beyond $FFFF in"));
+            }
+
+            match image.segments.last_mut() {
+                Some(segment) if segment.addr as usize + segment.bytes.len() == addr => segment.bytes.extend(bytes),
+                _ => image.segments.push(Segment { addr: addr as u16, bytes }),
+            }
+        }
+        Ok(image)
+    }
+
+    pub fn load_from_file(filename: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(filename).map_err(|error| format!("{filename}: {error}"))?;
+        Self::parse(&text).map_err(|error| format!("{filename}: {error}"))
+    }
+
+    // where execution starts: the run address, otherwise the first byte
+    pub fn origin(&self) -> Option<u16> {
+        self.run.or_else(|| self.segments.first().map(|segment| segment.addr))
+    }
+
+    pub fn contains(&self, addr: u16) -> bool {
+        self.segments.iter().any(|segment| (segment.addr as usize..segment.addr as usize + segment.bytes.len()).contains(&(addr as usize)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.segments.iter().map(|segment| segment.bytes.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn write_to(&self, mem: &mut Memory) {
+        for segment in &self.segments {
+            for (addr, byte) in (segment.addr..=0xFFFF).zip(&segment.bytes) {
+                mem.write_u8(addr, *byte);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let image = WozImage::parse("; hello\n0300: A9 01 8D 00 02\n: 60\n\n0280: ff # data\nfffc:00 03\n300R\n").unwrap();
+        assert_eq!(image.segments, vec![
+            Segment { addr: 0x0300, bytes: vec![0xA9, 0x01, 0x8D, 0x00, 0x02, 0x60] },
+            Segment { addr: 0x0280, bytes: vec![0xFF] },
+            Segment { addr: 0xFFFC, bytes: vec![0x00, 0x03] },
+        ]);
+        assert_eq!((image.run, image.origin(), image.len()), (Some(0x0300), Some(0x0300), 9));
+        assert!(image.contains(0x0305) && !image.contains(0x0306));
+
+        let mut mem = Memory::create();
+        image.write_to(&mut mem);
+        assert_eq!((mem.peek(0x0305), mem.read_u16(0xFFFC)), (0x60, 0x0300));
+        WozImage::parse("FFFC: 00 03 01 03").unwrap().write_to(&mut mem);
+        assert_eq!((mem.read_u16(0xFFFC), mem.read_u16(0xFFFE)), (0x0300, 0x0301));
+
+        assert_eq!(WozImage::parse("E000 A9 01\n").unwrap_err(), "Line 1: Expected address and bytes in 'E000 A9 01'");
+        assert_eq!(WozImage::parse(": 01\n").unwrap_err(), "Line 1: Continuation without preceding address in ': 01'");
+        assert_eq!(WozImage::parse("0300: A9 1FF\n").unwrap_err(), "Line 1: Invalid byte '1FF' in '0300: A9 1FF'");
+        assert!(WozImage::parse("FFFF: 01 02\n").is_err());
+        assert!(WozImage::parse("10000: 01\n").is_err());
+    }
+}